@@ -0,0 +1,144 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use engineioxide::{
+    channel::{PacketChannel, RingBuffer},
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    service::{tungstenite::protocol::Role, EngineIoService, ProtocolVersion, WsHandshake},
+    socket::{DisconnectReason, Socket},
+};
+use futures::StreamExt;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_tungstenite::WebSocketStream;
+
+const MESSAGES: usize = 100_000;
+const EMITTERS: usize = 4;
+
+#[derive(Debug, Clone)]
+struct MyHandler(mpsc::UnboundedSender<Arc<Socket<()>>>);
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        self.0.send(socket).unwrap();
+    }
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+    fn on_message(&self, _msg: String, _socket: Arc<Socket<()>>) {}
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+/// Emits `MESSAGES` messages of 50 bytes from `EMITTERS` concurrent tasks, in bursts filling the buffer of the socket,
+/// to a client connected through a loopback tcp connection, and waits for the client to receive all of them
+async fn emit_messages(config: EngineIoConfig) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let svc = EngineIoService::with_config(MyHandler(tx), config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap());
+    let (client, server) = tokio::join!(client, listener.accept());
+    let (client, server) = (client.unwrap(), server.unwrap().0);
+    let (parts, _) = http::Request::new(()).into_parts();
+    let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    tokio::spawn(async move {
+        svc.on_ws_connection(server, WsHandshake::new(ProtocolVersion::V4, parts))
+            .await;
+    });
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    client.next().await.unwrap().unwrap(); // Open packet
+
+    let socket = rx.recv().await.unwrap();
+    let msg = "a".repeat(50);
+    let emitters: Vec<_> = (0..EMITTERS)
+        .map(|_| {
+            let (socket, msg) = (socket.clone(), msg.clone());
+            tokio::spawn(async move {
+                let mut sent = 0;
+                while sent < MESSAGES / EMITTERS {
+                    // The buffer is filled before yielding to the writer
+                    while sent < MESSAGES / EMITTERS && socket.emit(msg.clone()).is_ok() {
+                        sent += 1;
+                    }
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+    for _ in 0..MESSAGES {
+        client.next().await.unwrap().unwrap();
+    }
+    for emitter in emitters {
+        emitter.await.unwrap();
+    }
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    // The emitters and the writer run in parallel so that they contend on the queue
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(EMITTERS + 1)
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("Emit 100k messages from 4 tasks over websocket");
+    group.sample_size(10);
+    group.bench_function("Mutex<VecDeque> (default)", |b| {
+        b.iter(|| {
+            let config = EngineIoConfig::builder().max_buffer_size(1024).build();
+            rt.block_on(emit_messages(config))
+        })
+    });
+    group.bench_function("lock-free RingBuffer", |b| {
+        b.iter(|| {
+            let config = EngineIoConfig::builder()
+                .max_buffer_size(1024)
+                .packet_channel(RingBuffer::with_capacity)
+                .build();
+            rt.block_on(emit_messages(config))
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("Send 100k values from 4 threads through the queue");
+    group.sample_size(10);
+    group.bench_function("Mutex<VecDeque> (default)", |b| {
+        b.iter(|| contend(Mutex::new(VecDeque::with_capacity(1024))))
+    });
+    group.bench_function("lock-free RingBuffer", |b| {
+        b.iter(|| contend(RingBuffer::with_capacity(1024)))
+    });
+    group.finish();
+}
+
+/// Sends `MESSAGES` values from `EMITTERS` threads while another thread receives them,
+/// to measure the contention on the queue without the cost of the transport
+fn contend<Q: PacketChannel<usize>>(queue: Q) {
+    std::thread::scope(|s| {
+        for _ in 0..EMITTERS {
+            s.spawn(|| {
+                for i in 0..MESSAGES / EMITTERS {
+                    let mut value = i;
+                    while let Err(v) = queue.try_send(value) {
+                        value = v;
+                        std::thread::yield_now();
+                    }
+                }
+            });
+        }
+        let mut received = 0;
+        while received < MESSAGES {
+            match queue.recv() {
+                Some(_) => received += 1,
+                None => std::thread::yield_now(),
+            }
+        }
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,29 +1,34 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use engineioxide::Packet;
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("Decode packet ping/pong", |b| {
-        let packet: String = Packet::Ping.try_into().unwrap();
+        let packet = Packet::Ping.encode();
         b.iter(|| Packet::try_from(packet.as_str()).unwrap())
     });
     c.bench_function("Decode packet ping/pong upgrade", |b| {
-        let packet: String = Packet::PingUpgrade.try_into().unwrap();
+        let packet = Packet::PingUpgrade.encode();
         b.iter(|| Packet::try_from(packet.as_str()).unwrap())
     });
     c.bench_function("Decode packet message", |b| {
-        let packet: String = Packet::Message(black_box("Hello").to_string())
-            .try_into()
-            .unwrap();
+        let packet = Packet::Message(black_box("Hello").to_string()).encode();
         b.iter(|| Packet::try_from(packet.as_str()).unwrap())
     });
+    c.bench_function("Decode packet message 100kb", |b| {
+        let data = format!("[\"event\",\"{}\"]", "a".repeat(100_000));
+        let packet = Packet::Message(data).encode();
+        b.iter_batched(
+            || packet.clone(),
+            |packet| Packet::try_from(packet).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
     c.bench_function("Decode packet noop", |b| {
-        let packet: String = Packet::Noop.try_into().unwrap();
+        let packet = Packet::Noop.encode();
         b.iter(|| Packet::try_from(packet.as_str()).unwrap())
     });
     c.bench_function("Decode packet binary b64", |b| {
-        let packet: String = Packet::Binary(black_box(vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05]))
-            .try_into()
-            .unwrap();
+        let packet = Packet::Binary(black_box(vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05])).encode();
         b.iter(|| Packet::try_from(packet.clone()).unwrap())
     });
 }
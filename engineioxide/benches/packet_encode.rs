@@ -1,34 +1,37 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use engineioxide::{config::EngineIoConfig, sid::Sid, OpenPacket, Packet, TransportType};
+use engineioxide::{
+    config::EngineIoConfig, sid::Sid, OpenPacket, Packet, ProtocolVersion, TransportType,
+};
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("Encode packet open", |b| {
         let packet = Packet::Open(OpenPacket::new(
             black_box(TransportType::Polling),
             black_box(Sid::ZERO),
+            ProtocolVersion::V4,
             &EngineIoConfig::default(),
         ));
-        b.iter(|| TryInto::<String>::try_into(packet.clone()))
+        b.iter(|| packet.clone().encode())
     });
     c.bench_function("Encode packet ping/pong", |b| {
         let packet = Packet::Ping;
-        b.iter(|| TryInto::<String>::try_into(packet.clone()))
+        b.iter(|| packet.clone().encode())
     });
     c.bench_function("Encode packet ping/pong upgrade", |b| {
         let packet = Packet::PingUpgrade;
-        b.iter(|| TryInto::<String>::try_into(packet.clone()))
+        b.iter(|| packet.clone().encode())
     });
     c.bench_function("Encode packet message", |b| {
         let packet = Packet::Message(black_box("Hello").to_string());
-        b.iter(|| TryInto::<String>::try_into(packet.clone()))
+        b.iter(|| packet.clone().encode())
     });
     c.bench_function("Encode packet noop", |b| {
         let packet = Packet::Noop;
-        b.iter(|| TryInto::<String>::try_into(packet.clone()))
+        b.iter(|| packet.clone().encode())
     });
     c.bench_function("Encode packet binary b64", |b| {
         let packet = Packet::Binary(black_box(vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05]));
-        b.iter(|| TryInto::<String>::try_into(packet.clone()))
+        b.iter(|| packet.clone().encode())
     });
 }
 
@@ -0,0 +1,94 @@
+use std::{sync::Arc, time::Duration};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    service::{tungstenite::protocol::Role, EngineIoService, ProtocolVersion, WsHandshake},
+    socket::{DisconnectReason, Socket},
+};
+use futures::StreamExt;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_tungstenite::WebSocketStream;
+
+const MESSAGES: usize = 100_000;
+
+#[derive(Debug, Clone)]
+struct MyHandler(mpsc::UnboundedSender<Arc<Socket<()>>>);
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        self.0.send(socket).unwrap();
+    }
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+    fn on_message(&self, _msg: String, _socket: Arc<Socket<()>>) {}
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+/// Emits `MESSAGES` messages of 50 bytes to a client connected through a loopback tcp connection
+/// and waits for the client to receive all of them
+async fn emit_messages(config: EngineIoConfig) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let svc = EngineIoService::with_config(MyHandler(tx), config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap());
+    let (client, server) = tokio::join!(client, listener.accept());
+    let (client, server) = (client.unwrap(), server.unwrap().0);
+    let (parts, _) = http::Request::new(()).into_parts();
+    let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    tokio::spawn(async move {
+        svc.on_ws_connection(server, WsHandshake::new(ProtocolVersion::V4, parts))
+            .await;
+    });
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    client.next().await.unwrap().unwrap(); // Open packet
+
+    let socket = rx.recv().await.unwrap();
+    let msg = "a".repeat(50);
+    // Each message is emitted separately, as by independent tasks
+    let emitter = tokio::spawn(async move {
+        for _ in 0..MESSAGES {
+            while socket.emit(msg.clone()).is_err() {
+                tokio::task::yield_now().await;
+            }
+            tokio::task::yield_now().await;
+        }
+    });
+    for _ in 0..MESSAGES {
+        client.next().await.unwrap().unwrap();
+    }
+    emitter.await.unwrap();
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("Emit 100k messages over websocket");
+    group.sample_size(10);
+    group.bench_function("flush per message", |b| {
+        b.iter(|| {
+            let config = EngineIoConfig::builder().max_buffer_size(1024).build();
+            rt.block_on(emit_messages(config))
+        })
+    });
+    group.bench_function("coalesce window 2ms", |b| {
+        b.iter(|| {
+            let config = EngineIoConfig::builder()
+                .max_buffer_size(1024)
+                .write_coalesce_window(Duration::from_millis(2))
+                .build();
+            rt.block_on(emit_messages(config))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -5,7 +5,8 @@ use http_body::{Body, Frame, SizeHint};
 use http_body_util::Full;
 use pin_project_lite::pin_project;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::task::{ready, Context, Poll};
+use tokio::sync::oneshot;
 
 pin_project! {
     #[project = BodyProj]
@@ -14,6 +15,8 @@ pin_project! {
         CustomBody {
             #[pin]
             body: Full<Bytes>,
+            // Notified once the body is handed to the connection
+            flushed: Vec<oneshot::Sender<()>>,
         },
         Body {
             #[pin]
@@ -32,7 +35,21 @@ impl<B> ResponseBody<B> {
     }
 
     pub fn custom_response(body: Full<Bytes>) -> Self {
-        ResponseBody::CustomBody { body }
+        ResponseBody::CustomBody {
+            body,
+            flushed: Vec::new(),
+        }
+    }
+
+    /// Set the channels to notify once a custom body is handed to the connection
+    pub fn with_flushed(self, notifiers: Vec<oneshot::Sender<()>>) -> Self {
+        match self {
+            ResponseBody::CustomBody { body, mut flushed } => {
+                flushed.extend(notifiers);
+                ResponseBody::CustomBody { body, flushed }
+            }
+            body => body,
+        }
     }
 
     pub fn new(body: B) -> Self {
@@ -53,7 +70,7 @@ where
         match &self {
             ResponseBody::EmptyResponse => true,
             ResponseBody::Body { body } => body.is_end_stream(),
-            ResponseBody::CustomBody { body } => body.is_end_stream(),
+            ResponseBody::CustomBody { body, .. } => body.is_end_stream(),
         }
     }
 
@@ -65,7 +82,7 @@ where
                 hint
             }
             ResponseBody::Body { body } => body.size_hint(),
-            ResponseBody::CustomBody { body } => body.size_hint(),
+            ResponseBody::CustomBody { body, .. } => body.size_hint(),
         }
     }
 
@@ -76,7 +93,14 @@ where
         match self.project() {
             BodyProj::EmptyResponse => Poll::Ready(None),
             BodyProj::Body { body } => body.poll_frame(cx),
-            BodyProj::CustomBody { body } => body.poll_frame(cx).map_err(|err| match err {}),
+            BodyProj::CustomBody { body, flushed } => {
+                let frame = ready!(body.poll_frame(cx)).map(|f| f.map_err(|err| match err {}));
+                // The full body is yielded in one frame
+                for tx in flushed.drain(..) {
+                    tx.send(()).ok();
+                }
+                Poll::Ready(frame)
+            }
         }
     }
 }
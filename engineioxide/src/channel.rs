@@ -0,0 +1,673 @@
+//! A bounded multi-producer single-consumer channel used to buffer the packets of a socket.
+//!
+//! Unlike a [`tokio::sync::mpsc`] channel, the queue is shared between both halves
+//! so that the sender can discard the oldest packets when it is full, depending on the [`OverflowPolicy`].
+//!
+//! The queue storing the packets is a [`PacketChannel`]. It is a [`Mutex`]-guarded [`VecDeque`] by default,
+//! another implementation can be set with [`EngineIoConfigBuilder::packet_channel`],
+//! for example the lock-free [`RingBuffer`].
+//! The channel itself only relies on atomics, it never takes a lock when sending or receiving a value.
+//!
+//! [`EngineIoConfigBuilder::packet_channel`]: crate::config::EngineIoConfigBuilder::packet_channel
+use std::{
+    cell::UnsafeCell,
+    collections::VecDeque,
+    fmt::Debug,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::sync::{
+    mpsc::error::{TryRecvError, TrySendError},
+    Notify,
+};
+
+use crate::config::OverflowPolicy;
+
+/// The queue storing the packets buffered in the outbound channel of a socket.
+///
+/// The channel wrapping the queue handles the waiting, the capacity and the [`OverflowPolicy`],
+/// but not the synchronization: the queue is shared without lock between the emitting tasks
+/// and the task writing to the transport.
+/// [`try_send`](Self::try_send) is called concurrently by the emitting tasks, and [`recv`](Self::recv)
+/// by the writing task and, with [`OverflowPolicy::DropOldest`], by the emitting tasks.
+pub trait PacketChannel<T>: Debug + Send + Sync {
+    /// Appends a value at the back of the queue.
+    /// The value is given back if the queue has no room for it.
+    ///
+    /// It must not fail while the queue holds less values than its [`capacity`](Self::capacity).
+    fn try_send(&self, value: T) -> Result<(), T>;
+
+    /// Removes the value at the front of the queue, if any.
+    fn recv(&self) -> Option<T>;
+
+    /// Returns the maximum number of values the queue can hold, if it is bounded.
+    /// The capacity of the channel is lowered to it, so that a reserved slot always has room in the queue.
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// The default [`PacketChannel`], growing as needed up to the capacity of the channel.
+impl<T: Debug + Send> PacketChannel<T> for Mutex<VecDeque<T>> {
+    fn try_send(&self, value: T) -> Result<(), T> {
+        self.lock().unwrap().push_back(value);
+        Ok(())
+    }
+    fn recv(&self) -> Option<T> {
+        self.lock().unwrap().pop_front()
+    }
+}
+
+/// A lock-free [`PacketChannel`] with a fixed number of slots allocated once.
+/// It never reallocates nor blocks, which suits the sockets with bursty emissions from several tasks.
+///
+/// Each slot carries a sequence number telling whether it is ready to be written or read for the current lap,
+/// so that the producers and the consumers only contend on the indexes of the buffer.
+pub struct RingBuffer<T> {
+    slots: Box<[Slot<T>]>,
+    /// The position of the next value to receive
+    head: AtomicUsize,
+    /// The position of the next value to send
+    tail: AtomicUsize,
+}
+
+struct Slot<T> {
+    /// `pos` when the slot is free for the value at `pos`, `pos + 1` once the value is written
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// SAFETY: a value is only accessed by the task which claimed its slot by moving the head or the tail,
+// and the sequence number of the slot publishes it to the other side.
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl<T> RingBuffer<T> {
+    /// Creates a ring buffer with the given number of slots
+    ///
+    /// # Panics
+    /// If the capacity is 0
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "ring buffer capacity must be greater than 0");
+        Self {
+            slots: (0..capacity)
+                .map(|i| Slot {
+                    seq: AtomicUsize::new(i),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T: Send> PacketChannel<T> for RingBuffer<T> {
+    fn try_send(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq.wrapping_sub(pos) as isize {
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the slot is free and claimed by moving the tail past it
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(tail) => pos = tail,
+                },
+                // The slot still holds the value of the previous lap
+                diff if diff < 0 => return Err(value),
+                _ => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+    fn recv(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq.wrapping_sub(pos.wrapping_add(1)) as isize {
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the value was written and the slot is claimed by moving the head past it
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.seq
+                            .store(pos.wrapping_add(self.slots.len()), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(head) => pos = head,
+                },
+                // The value of this lap is not written yet
+                diff if diff < 0 => return None,
+                _ => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+    fn capacity(&self) -> Option<usize> {
+        Some(self.slots.len())
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.head.get_mut(), *self.tail.get_mut());
+        let mut pos = head;
+        while pos != tail {
+            let slot = &mut self.slots[pos % self.slots.len()];
+            // SAFETY: the values between the head and the tail are written and never read
+            unsafe { slot.value.get_mut().assume_init_drop() };
+            pos = pos.wrapping_add(1);
+        }
+    }
+}
+
+impl<T> Debug for RingBuffer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RingBuffer")
+            .field("capacity", &self.slots.len())
+            .field("head", &self.head)
+            .field("tail", &self.tail)
+            .finish()
+    }
+}
+
+/// Creates a bounded channel with the given `capacity` and [`OverflowPolicy`], backed by a [`VecDeque`]
+///
+/// # Panics
+/// If the capacity is 0
+pub(crate) fn channel<T: Debug + Send + 'static>(
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (Sender<T>, Receiver<T>) {
+    channel_with(
+        Box::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        capacity,
+        policy,
+    )
+}
+
+/// Creates a bounded channel with the given `capacity` and [`OverflowPolicy`], backed by the given queue.
+/// The capacity is lowered to the one of the queue if it is smaller.
+///
+/// # Panics
+/// If the capacity is 0
+pub(crate) fn channel_with<T>(
+    queue: Box<dyn PacketChannel<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (Sender<T>, Receiver<T>) {
+    let capacity = queue.capacity().map_or(capacity, |max| max.min(capacity));
+    assert!(capacity > 0, "channel capacity must be greater than 0");
+    let shared = Arc::new(Shared {
+        queue,
+        capacity,
+        policy,
+        slots: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        dropped: AtomicU64::new(0),
+        rx_notify: Notify::new(),
+        tx_notify: Notify::new(),
+        tx_waiters: AtomicUsize::new(0),
+        closed_notify: Notify::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    queue: Box<dyn PacketChannel<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// Number of slots taken by the buffered values and the [`Permit`]s
+    slots: AtomicUsize,
+    /// Set once one of the halves is closed or dropped
+    closed: AtomicBool,
+    /// Number of values discarded because of the [`OverflowPolicy`]
+    dropped: AtomicU64,
+    rx_notify: Notify,
+    /// Notified when a value is received or when the channel is closed, to wake up [`Sender::reserve`]
+    tx_notify: Notify,
+    /// Number of [`Sender::reserve`] calls waiting for a free slot, so that receiving a value
+    /// only goes through the [`Notify`] lock when someone is waiting
+    tx_waiters: AtomicUsize,
+    closed_notify: Notify,
+}
+
+impl<T> Shared<T> {
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.rx_notify.notify_one();
+        self.tx_notify.notify_waiters();
+        self.closed_notify.notify_waiters();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Takes a free slot, if any
+    fn take_slot(&self) -> bool {
+        self.slots
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |slots| {
+                (slots < self.capacity).then_some(slots + 1)
+            })
+            .is_ok()
+    }
+
+    fn release_slot(&self) {
+        self.slots.fetch_sub(1, Ordering::SeqCst);
+        if self.tx_waiters.load(Ordering::SeqCst) > 0 {
+            self.tx_notify.notify_waiters();
+        }
+    }
+
+    /// Sends a value in a slot already taken
+    fn send_in_slot(&self, value: T) {
+        match self.queue.try_send(value) {
+            Ok(()) => self.rx_notify.notify_one(),
+            // Only a queue refusing values below its capacity can get there, the value is accounted as dropped
+            Err(_) => {
+                self.release_slot();
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Sends a value applying the [`OverflowPolicy`] if the channel is full.
+    fn push(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.take_slot() {
+            self.send_in_slot(value);
+            return Ok(());
+        }
+        match self.policy {
+            OverflowPolicy::Reject => return Err(TrySendError::Full(value)),
+            OverflowPolicy::DropNewest => {}
+            // The slot of the discarded value is handed over to the new one.
+            // If all the slots are held by permits, there is no value to discard and the new one is dropped.
+            OverflowPolicy::DropOldest => {
+                if self.queue.recv().is_some() {
+                    self.send_in_slot(value);
+                }
+            }
+        }
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// The sending half of the [`channel`]
+#[derive(Debug)]
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Tries to send a value. If the channel is full, the [`OverflowPolicy`] is applied.
+    /// With [`OverflowPolicy::Reject`], a [`TrySendError::Full`] error is returned with the value.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.shared.is_closed() {
+            return Err(TrySendError::Closed(value));
+        }
+        self.shared.push(value)
+    }
+
+    /// Tries to reserve a slot in the channel.
+    ///
+    /// With [`OverflowPolicy::Reject`], it fails if the channel is full.
+    /// Otherwise, the policy is applied when the value is sent through the [`Permit`].
+    pub fn try_reserve(&self) -> Result<Permit<'_, T>, TrySendError<()>> {
+        if self.shared.is_closed() {
+            return Err(TrySendError::Closed(()));
+        }
+        let slot = self.shared.policy == OverflowPolicy::Reject;
+        if slot && !self.shared.take_slot() {
+            return Err(TrySendError::Full(()));
+        }
+        Ok(Permit {
+            shared: &self.shared,
+            slot,
+        })
+    }
+
+    /// Waits for a free slot in the channel and reserves it.
+    ///
+    /// Unlike [`Sender::try_reserve`], it waits for the channel to have room whatever the [`OverflowPolicy`],
+    /// so that the value sent through the [`Permit`] never discards another one.
+    pub async fn reserve(&self) -> Result<Permit<'_, T>, TrySendError<()>> {
+        let notified = self.shared.tx_notify.notified();
+        tokio::pin!(notified);
+        self.shared.tx_waiters.fetch_add(1, Ordering::SeqCst);
+        let res = loop {
+            // Register the waiter before checking the state to not miss a notification
+            notified.as_mut().enable();
+            if self.shared.is_closed() {
+                break Err(TrySendError::Closed(()));
+            }
+            if self.shared.take_slot() {
+                break Ok(Permit {
+                    shared: &self.shared,
+                    slot: true,
+                });
+            }
+            notified.as_mut().await;
+            notified.set(self.shared.tx_notify.notified());
+        };
+        self.shared.tx_waiters.fetch_sub(1, Ordering::SeqCst);
+        res
+    }
+
+    /// Returns true if the channel is closed
+    pub fn is_closed(&self) -> bool {
+        self.shared.is_closed()
+    }
+
+    /// Waits for the channel to be closed
+    pub async fn closed(&self) {
+        let notified = self.shared.closed_notify.notified();
+        tokio::pin!(notified);
+        loop {
+            // Register the waiter before checking the state to not miss a notification
+            notified.as_mut().enable();
+            if self.is_closed() {
+                return;
+            }
+            notified.as_mut().await;
+            notified.set(self.shared.closed_notify.notified());
+        }
+    }
+
+    /// Returns the number of values discarded because of the [`OverflowPolicy`]
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+/// A reserved slot in the [`channel`]
+#[derive(Debug)]
+pub(crate) struct Permit<'a, T> {
+    shared: &'a Shared<T>,
+    /// Whether a slot is held, otherwise the [`OverflowPolicy`] is applied when sending
+    slot: bool,
+}
+
+impl<T> Permit<'_, T> {
+    /// Sends a value using the reserved slot.
+    /// The value is silently dropped if the channel was closed in the meantime.
+    pub fn send(self, value: T) {
+        let permit = std::mem::ManuallyDrop::new(self);
+        let shared = permit.shared;
+        match (permit.slot, shared.is_closed()) {
+            (true, true) => shared.release_slot(),
+            // The capacity of the channel is bounded by the one of the queue so the value always fits
+            (true, false) => shared.send_in_slot(value),
+            (false, true) => {}
+            (false, false) => {
+                shared.push(value).ok();
+            }
+        }
+    }
+}
+
+impl<T> Drop for Permit<'_, T> {
+    fn drop(&mut self) {
+        if self.slot {
+            self.shared.release_slot();
+        }
+    }
+}
+
+/// The receiving half of the [`channel`]
+#[derive(Debug)]
+pub(crate) struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value, waiting for one to be sent.
+    /// Returns `None` once the channel is closed and empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            match self.try_recv() {
+                Ok(value) => return Some(value),
+                Err(TryRecvError::Disconnected) => return None,
+                // A permit is stored by `notify_one` if a value is sent before we start waiting
+                Err(TryRecvError::Empty) => self.shared.rx_notify.notified().await,
+            }
+        }
+    }
+
+    /// Tries to receive the next value without waiting.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let value = match self.shared.queue.recv() {
+            Some(value) => value,
+            None if !self.shared.is_closed() => return Err(TryRecvError::Empty),
+            // A value may have been sent right before the channel was closed
+            None => self.shared.queue.recv().ok_or(TryRecvError::Disconnected)?,
+        };
+        self.shared.release_slot();
+        Ok(value)
+    }
+
+    /// Closes the channel. Buffered values can still be received.
+    pub fn close(&mut self) {
+        self.shared.close();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_when_full() {
+        let (tx, mut rx) = channel(2, OverflowPolicy::Reject);
+        tx.try_send(1).unwrap();
+        let permit = tx.try_reserve().unwrap();
+        assert!(matches!(tx.try_send(2), Err(TrySendError::Full(2))));
+        assert!(matches!(tx.try_reserve(), Err(TrySendError::Full(()))));
+        permit.send(3);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(tx.dropped(), 0);
+    }
+
+    #[test]
+    fn drop_oldest_when_full() {
+        let (tx, mut rx) = channel(2, OverflowPolicy::DropOldest);
+        for i in 0..5 {
+            tx.try_send(i).unwrap();
+        }
+        tx.try_reserve().unwrap().send(5);
+        assert_eq!(rx.try_recv(), Ok(4));
+        assert_eq!(rx.try_recv(), Ok(5));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(tx.dropped(), 4);
+    }
+
+    #[test]
+    fn drop_newest_when_full() {
+        let (tx, mut rx) = channel(2, OverflowPolicy::DropNewest);
+        for i in 0..5 {
+            tx.try_send(i).unwrap();
+        }
+        tx.try_reserve().unwrap().send(5);
+        assert_eq!(rx.try_recv(), Ok(0));
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(tx.dropped(), 4);
+    }
+
+    #[test]
+    fn released_permit() {
+        let (tx, _rx) = channel::<()>(1, OverflowPolicy::Reject);
+        drop(tx.try_reserve().unwrap());
+        assert!(tx.try_reserve().is_ok());
+    }
+
+    #[tokio::test]
+    async fn close() {
+        let (tx, mut rx) = channel(2, OverflowPolicy::Reject);
+        tx.try_send(1).unwrap();
+        let closed = tokio::spawn(async move {
+            tx.closed().await;
+            tx
+        });
+        rx.close();
+        let tx = closed.await.unwrap();
+        assert!(tx.is_closed());
+        assert!(matches!(tx.try_send(2), Err(TrySendError::Closed(2))));
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn reserve_waits_for_free_slot() {
+        let (tx, mut rx) = channel(1, OverflowPolicy::DropOldest);
+        tx.try_send(1).unwrap();
+        let tx = Arc::new(tx);
+        let reserve = tokio::spawn({
+            let tx = tx.clone();
+            async move { tx.reserve().await.map(|permit| permit.send(2)).is_ok() }
+        });
+        tokio::task::yield_now().await;
+        assert!(!reserve.is_finished());
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(reserve.await.unwrap());
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(tx.dropped(), 0);
+
+        tx.try_send(3).unwrap();
+        let reserve = tokio::spawn(async move { tx.reserve().await.is_err() });
+        tokio::task::yield_now().await;
+        rx.close();
+        assert!(reserve.await.unwrap());
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let (tx, mut rx) = channel_with(
+            Box::new(RingBuffer::with_capacity(2)),
+            2,
+            OverflowPolicy::DropOldest,
+        );
+        tx.try_send(0).unwrap();
+        tx.try_send(1).unwrap();
+        assert_eq!(rx.try_recv(), Ok(0));
+        // The next slots wrap around the end of the buffer
+        tx.try_send(2).unwrap();
+        tx.try_send(3).unwrap();
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(tx.dropped(), 1);
+
+        // The capacity of the channel is lowered to the one of a smaller queue,
+        // so a reserved slot always has room for its value
+        let (tx, mut rx) = channel_with(
+            Box::new(RingBuffer::with_capacity(1)),
+            2,
+            OverflowPolicy::Reject,
+        );
+        let permit = tx.try_reserve().unwrap();
+        assert!(matches!(tx.try_reserve(), Err(TrySendError::Full(()))));
+        assert!(matches!(tx.try_send(2), Err(TrySendError::Full(2))));
+        permit.send(1);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(tx.dropped(), 0);
+    }
+
+    #[test]
+    fn ring_buffer_concurrent_senders() {
+        const SENDERS: usize = 4;
+        const VALUES: usize = 10_000;
+        let (tx, mut rx) = channel_with(
+            Box::new(RingBuffer::with_capacity(8)),
+            8,
+            OverflowPolicy::Reject,
+        );
+        let tx = Arc::new(tx);
+        let senders: Vec<_> = (0..SENDERS)
+            .map(|sender| {
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    for i in 0..VALUES {
+                        let mut value = (sender, i);
+                        while let Err(TrySendError::Full(v)) = tx.try_send(value) {
+                            value = v;
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // The values of each sender are received in order
+        let mut next = [0; SENDERS];
+        while next.iter().any(|&i| i < VALUES) {
+            match rx.try_recv() {
+                Ok((sender, i)) => {
+                    assert_eq!(next[sender], i);
+                    next[sender] += 1;
+                }
+                Err(_) => std::thread::yield_now(),
+            }
+        }
+        senders.into_iter().for_each(|s| s.join().unwrap());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn ring_buffer_drops_remaining_values() {
+        let value = Arc::new(());
+        let queue = RingBuffer::with_capacity(2);
+        queue.try_send(value.clone()).unwrap();
+        queue.try_send(value.clone()).unwrap();
+        assert!(queue.try_send(value.clone()).is_err());
+        assert!(queue.recv().is_some());
+        drop(queue);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[tokio::test]
+    async fn recv_waits_for_value() {
+        let (tx, mut rx) = channel(2, OverflowPolicy::Reject);
+        let recv = tokio::spawn(async move { rx.recv().await });
+        tokio::task::yield_now().await;
+        tx.try_send(1).unwrap();
+        assert_eq!(recv.await.unwrap(), Some(1));
+    }
+}
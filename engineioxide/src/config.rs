@@ -29,9 +29,24 @@
 //! let svc = EngineIoService::with_config(MyHandler, config);
 //! ```
 
-use std::{borrow::Cow, time::Duration};
+use std::{borrow::Cow, future::Future, net::SocketAddr, sync::Arc, time::Duration};
 
-use crate::service::TransportType;
+use http::{header::SET_COOKIE, request::Parts, HeaderMap, HeaderName, HeaderValue};
+use tokio::{
+    runtime::{Handle, TryCurrentError},
+    task::JoinHandle,
+};
+
+use crate::{
+    channel::PacketChannel,
+    errors::Error,
+    rate_limit::{HandshakePermit, HandshakeRateLimit},
+    recorder::SessionRecording,
+    service::{ProtocolVersion, TransportType},
+    session::{MemorySessionStore, SessionStore},
+    sid::Sid,
+    socket::PacketBuf,
+};
 
 /// Configuration for the engine.io engine & transports
 #[derive(Debug, Clone)]
@@ -48,20 +63,309 @@ pub struct EngineIoConfig {
     /// Defaults to 20 seconds.
     pub ping_timeout: Duration,
 
+    /// If false, no heartbeat task is spawned for the sockets: the server never sends pings
+    /// and only relies on the transport to detect closed connections (e.g. with TCP keepalive).
+    /// It is meant for internal links over a trusted network, where the heartbeat traffic is pure overhead.
+    ///
+    /// For the compatibility with the clients, which still expect pings, the handshake advertises
+    /// the longest interval they can wait for. The v3 pings of the clients are still answered.
+    ///
+    /// Defaults to true.
+    pub heartbeat: bool,
+
     /// The maximum number of packets that can be buffered per connection before being emitted to the client.
     ///
-    /// If the buffer if full the `emit()` method will return an error
+    /// If the buffer if full the `emit()` method will return an error,
+    /// unless another [`overflow_policy`](Self::overflow_policy) is set.
     ///
     /// Defaults to 128 packets
     pub max_buffer_size: usize,
 
+    /// What to do when a packet is emitted while the buffer of a socket is full.
+    ///
+    /// Defaults to [`OverflowPolicy::Reject`].
+    pub overflow_policy: OverflowPolicy,
+
+    /// Creates the queue buffering the packets emitted to each socket, with the
+    /// [`max_buffer_size`](Self::max_buffer_size) as capacity. See [`PacketChannel`].
+    ///
+    /// Defaults to `None` (the packets are buffered in a [`Mutex`](std::sync::Mutex)-guarded [`VecDeque`](std::collections::VecDeque)).
+    pub packet_channel: Option<PacketChannelFactory>,
+
     /// The maximum number of bytes that can be received per http request.
     /// Defaults to 100kb.
     pub max_payload: u64,
 
+    /// The number of inbound packets dispatched to the handler before yielding to the runtime,
+    /// when reading a polling payload or the websocket frames already received.
+    /// It prevents a client sending many packets at once from starving the other sockets of its worker thread.
+    /// 0 disables the yields.
+    ///
+    /// Defaults to 64 packets.
+    pub packets_per_yield: usize,
+
+    /// The minimum size in bytes of a polling response payload to be compressed,
+    /// if the client accepts a supported encoding (gzip or brotli) in its `Accept-Encoding` header.
+    ///
+    /// Defaults to 1kb.
+    #[cfg(feature = "compression")]
+    pub compression_threshold: usize,
+
+    /// How invalid UTF-8 in the text packets of polling requests is handled.
+    ///
+    /// Defaults to [`Utf8Validation::Strict`].
+    pub utf8_validation: Utf8Validation,
+
+    /// The maximum size in bytes of a text message received from the client, measured on the decoded UTF-8 string.
+    ///
+    /// If a bigger message is received, the session is closed. With websocket,
+    /// the connection is closed with a policy violation (1008) close code.
+    ///
+    /// Defaults to `None` (no limit other than the [`max_payload`](Self::max_payload) of polling requests).
+    pub max_message_size: Option<usize>,
+
+    /// The maximum amount of time a polling request is held open while waiting for data.
+    /// After this duration the request is released with a noop packet so that the client can re-poll.
+    /// Defaults to 25 seconds.
+    pub polling_duration: Duration,
+
+    /// The amount of time the server will wait for the upgrade probe exchange to complete
+    /// when a polling session is upgraded to websocket.
+    /// If it times out, the websocket attempt is abandoned and the session stays on polling.
+    /// Defaults to 10 seconds.
+    pub upgrade_timeout: Duration,
+
+    /// If set, a polling session can only be upgraded to websocket by a request
+    /// with the same [`UpgradeOrigin`] as the handshake request of the session.
+    /// It prevents another client knowing the sid from taking over the session.
+    /// The upgrades failing the check are rejected with a `400 Bad Request` response,
+    /// the session is left untouched. It is checked along with the [`allowed_origins`](Self::allowed_origins).
+    ///
+    /// Defaults to `None` (only the sid is checked).
+    pub strict_upgrade_origin: Option<UpgradeOrigin>,
+
+    /// The interval at which the server will send websocket protocol-level ping frames to the client.
+    /// It is independent of the engine.io heartbeat and can be used to keep idle connections
+    /// open through proxies. Received pong frames update [`Socket::last_transport_activity`].
+    ///
+    /// Defaults to `None` (no ping frames are sent).
+    ///
+    /// [`Socket::last_transport_activity`]: crate::socket::Socket::last_transport_activity
+    pub ws_ping_interval: Option<Duration>,
+
+    /// If true, transport activity (websocket pong frames) received since the last engine.io ping
+    /// extends the engine.io heartbeat deadline when the client does not answer in time.
+    ///
+    /// Defaults to false, to keep the behavior of the engine.io protocol.
+    pub transport_liveness: bool,
+
+    /// If set, the websocket writer waits for this amount of time after a packet before flushing,
+    /// so that the packets emitted in bursts are written with fewer flushes and syscalls.
+    /// Heartbeat and close packets are always flushed right away.
+    /// It trades a bit of latency for throughput, a window of a few milliseconds is usually enough.
+    ///
+    /// Defaults to `None` (the packets are flushed as soon as the emission buffer is drained).
+    pub write_coalesce_window: Option<Duration>,
+
+    /// If set, a parked polling request waits for this amount of time after the first packet
+    /// before responding, so that the packets emitted in bursts are sent in a single payload.
+    /// The response is never delayed beyond this bound, and it is sent right away
+    /// if a heartbeat or close packet is emitted or if the payload is full.
+    /// It trades a bit of latency for fewer round-trips, a delay of a few milliseconds is usually enough.
+    ///
+    /// Defaults to `None` (the packets are sent as soon as they are emitted).
+    pub polling_coalesce_delay: Option<Duration>,
+
+    /// If set, the interval between the heartbeat pings of the server adapts to the round-trip times
+    /// observed for each socket, within the bounds of the [`AdaptiveHeartbeat`].
+    /// It only applies to the v4 protocol, where the pings are sent by the server.
+    ///
+    /// Defaults to `None` (pings are sent every [`ping_interval`](Self::ping_interval)).
+    pub adaptive_heartbeat: Option<AdaptiveHeartbeat>,
+
+    /// What to do when a client sends a ping with the v4 protocol, where only the server should send pings.
+    /// See [`ClientPingPolicy`] for the available policies.
+    ///
+    /// Defaults to [`ClientPingPolicy::Pong`].
+    pub client_ping_policy: ClientPingPolicy,
+
+    /// The amount of time a client can stay connected without sending any message.
+    /// Heartbeats are not taken into account, so it closes connections that are alive but idle at the application level.
+    /// Idle sockets are closed with the [`DisconnectReason::IdleTimeout`](crate::DisconnectReason::IdleTimeout) reason.
+    ///
+    /// Defaults to `None` (idle connections are kept).
+    pub idle_timeout: Option<Duration>,
+
+    /// The maximum amount of time a socket can stay connected. Once it is reached, the socket is closed
+    /// with the [`DisconnectReason::LifetimeExpired`](crate::DisconnectReason::LifetimeExpired) reason
+    /// and the websocket close code `1012` (service restart), so that the client reconnects
+    /// and gets rebalanced by the load balancer.
+    ///
+    /// Defaults to `None` (connections are never recycled).
+    pub max_connection_lifetime: Option<Duration>,
+
+    /// If set, a background task scans the sockets at this interval and closes the ones that were not seen alive
+    /// (see [`Socket::last_alive_at`](crate::socket::Socket::last_alive_at)) for longer than the heartbeat
+    /// allows (`ping_interval + ping_timeout`, plus one sweep interval), with the
+    /// [`DisconnectReason::HeartbeatTimeout`](crate::DisconnectReason::HeartbeatTimeout) reason.
+    ///
+    /// Each socket already closes itself through its own heartbeat task, the sweep is a safety net
+    /// against the sessions leaked by a stuck task. The task is stopped when the server shuts down.
+    /// There is no sweep if the [`heartbeat`](Self::heartbeat) is disabled.
+    ///
+    /// Defaults to `None` (no sweep).
+    pub heartbeat_sweep_interval: Option<Duration>,
+
+    /// If false, polling responses have a `X-Accel-Buffering: no` header
+    /// so that reverse proxies like nginx don't buffer the long-polling responses.
+    ///
+    /// Defaults to true.
+    pub proxy_buffering: bool,
+
+    /// The amount of time given to the websocket writer to flush the final packets
+    /// and the close frame when a connection is closed, before it is forcibly dropped.
+    /// Defaults to 1 second.
+    pub close_grace: Duration,
+
+    /// The number of times a websocket write failing with a transient error (e.g. `WouldBlock`)
+    /// is retried before the connection is closed with [`DisconnectReason::TransportError`].
+    /// Fatal errors, like a connection reset, are never retried.
+    ///
+    /// Defaults to 3.
+    ///
+    /// [`DisconnectReason::TransportError`]: crate::DisconnectReason::TransportError
+    pub ws_write_retries: u32,
+
+    /// The delay before the first retry of a failed websocket write, doubled for each following retry.
+    ///
+    /// Defaults to 10ms.
+    pub ws_write_retry_backoff: Duration,
+
+    /// If true, a socket upgraded from polling to websocket is downgraded back to polling
+    /// when its websocket writes still fail after the [`ws_write_retries`](Self::ws_write_retries),
+    /// instead of being closed with a [`TransportError`](crate::socket::DisconnectReason::TransportError).
+    ///
+    /// The websocket connection is dropped and the session is kept, the pending packets are sent
+    /// to the next polling request. The packets that were not flushed to the websocket when the failure
+    /// occurred are sent again first, whole: the packets sent atomically are never split.
+    ///
+    /// It is only a best effort: the session survives only if the client polls again with the same sid
+    /// before the heartbeat times out, which the official clients do not do once upgraded.
+    /// It is only safe with clients that keep the polling transport available after the upgrade
+    /// and that tolerate receiving twice the packets that were partially written before the failure.
+    /// Sockets that were opened directly with a websocket are never downgraded.
+    ///
+    /// It requires the `polling` feature. Defaults to false.
+    pub ws_polling_fallback: bool,
+
+    /// The websocket subprotocols accepted by the server.
+    /// During the upgrade handshake, the first subprotocol of the client `Sec-WebSocket-Protocol` header
+    /// that is accepted is selected and echoed in the response.
+    ///
+    /// Defaults to an empty list.
+    pub ws_subprotocols: Vec<Cow<'static, str>>,
+
+    /// If true, a websocket upgrade offering only subprotocols that are not accepted
+    /// is rejected with a `400 Bad Request` response. Otherwise it is accepted without subprotocol.
+    /// Upgrades without subprotocol are always accepted.
+    ///
+    /// Defaults to false.
+    pub ws_subprotocol_strict: bool,
+
+    /// The origins allowed to connect, e.g. `https://example.com`, compared with the `Origin` header
+    /// of the websocket upgrade requests. The same list should be given to the CORS configuration
+    /// of the polling transport, [`is_origin_allowed`](Self::is_origin_allowed) can be used for this.
+    ///
+    /// Like the [`strict_upgrade_origin`](Self::strict_upgrade_origin) check, the upgrade requests
+    /// with a disallowed origin are rejected with a `400 Bad Request` response. Requests without `Origin` header
+    /// are accepted: browsers always send it, and other clients can set any value.
+    ///
+    /// Defaults to an empty list (all origins are allowed).
+    pub allowed_origins: Vec<Cow<'static, str>>,
+
+    /// The [`SessionStore`] used to validate session ids across processes.
+    /// Defaults to a [`MemorySessionStore`] which only knows about the sessions of the current process.
+    pub session_store: Arc<dyn SessionStore>,
+
+    /// The [`HandshakeRateLimit`] limiting the handshakes per remote IP.
+    /// Over-limit handshakes are rejected with a `429 Too Many Requests` response.
+    /// The handshakes over its concurrency bound are rejected with a `503 Service Unavailable` response.
+    ///
+    /// Defaults to `None`.
+    pub handshake_rate_limit: Option<Arc<HandshakeRateLimit>>,
+
+    /// The [`SessionRecording`] of a random sample of the new sessions.
+    /// See the [`recorder`](crate::recorder) module for more details.
+    ///
+    /// Defaults to `None`, the sessions are only recorded with [`Socket::start_recording`](crate::Socket::start_recording).
+    pub session_recording: Option<SessionRecording>,
+
+    /// The delay advertised in the `Retry-After` header of the `503 Service Unavailable` responses
+    /// sent to new sessions while the server is paused, see [`EngineIoHandle::pause_accepting`].
+    /// It is rounded down to the second, with a minimum of one second.
+    ///
+    /// Defaults to 5 seconds.
+    ///
+    /// [`EngineIoHandle::pause_accepting`]: crate::handler::EngineIoHandle::pause_accepting
+    pub retry_after: Duration,
+
+    /// The number of events buffered for each [`EventStream`](crate::events::EventStream) of the server.
+    /// When a stream lags behind, the oldest events are dropped for it.
+    ///
+    /// Defaults to 1024 events.
+    pub event_stream_capacity: usize,
+
+    /// The number of shards of the socket map, see [`ShardedMap`](crate::shard::ShardedMap).
+    /// More shards lower the lock contention between the handshakes, disconnections and broadcasts.
+    ///
+    /// Defaults to four times the number of available CPUs.
+    pub socket_shards: usize,
+
+    /// A hook called with the headers of the handshake responses, just before they are sent:
+    /// the polling handshake response and the `101 Switching Protocols` response of websocket connections.
+    /// It can be used to add a sticky session cookie or security headers.
+    ///
+    /// The following headers are reserved, they are restored after the hook is called:
+    /// * For polling: `Content-Type`, `Content-Length`, `Cache-Control`, `Pragma`, `Expires` and `X-Accel-Buffering`.
+    /// * For websocket: `Upgrade`, `Connection`, `Sec-WebSocket-Accept` and `Sec-WebSocket-Protocol`.
+    ///
+    /// Defaults to `None`.
+    pub handshake_response_hook: Option<HandshakeResponseHook>,
+
+    /// If set, a cookie containing the sid is set on the handshake responses: the polling handshake response,
+    /// the `101 Switching Protocols` response of direct websocket connections and of upgrades.
+    /// It lets the load balancers route all the requests of a session to the same server (sticky sessions).
+    /// The server itself never reads it. See [`HandshakeCookie`].
+    ///
+    /// Defaults to `None` (no cookie is set).
+    pub cookie: Option<HandshakeCookie>,
+
+    /// The runtime on which the tasks of the server are spawned (heartbeats, websocket connections, ...).
+    /// Its time driver must be enabled.
+    /// Defaults to `None`, the tasks are spawned on the runtime of the current context.
+    pub runtime: Option<Handle>,
+
+    /// How the payloads of the packets are written in the tracing events, see [`PayloadLogging`].
+    /// Defaults to [`PayloadLogging::Full`] in debug builds and to [`PayloadLogging::Off`] in release builds.
+    pub payload_logging: PayloadLogging,
+
+    /// The request header from which the correlation id of a connection is captured at handshake,
+    /// e.g. `x-request-id` set by a reverse proxy.
+    /// If it is not set or if the header is missing, a random correlation id is generated.
+    ///
+    /// The correlation id is available with [`Socket::correlation_id`](crate::Socket::correlation_id)
+    /// and is included in the tracing events of the socket.
+    ///
+    /// Defaults to `None`.
+    pub correlation_id_header: Option<HeaderName>,
+
     /// Allowed transports on this server
     /// It is represented as a bitfield to allow to combine any number of transports easily
     pub transports: u8,
+
+    /// Allowed protocol versions on this server, the bit `1 << version` is set for each allowed [`ProtocolVersion`].
+    /// The protocol v3 is only supported with the `v3` feature.
+    pub allowed_versions: u8,
 }
 
 impl Default for EngineIoConfig {
@@ -70,9 +374,53 @@ impl Default for EngineIoConfig {
             req_path: "/engine.io".into(),
             ping_interval: Duration::from_millis(25000),
             ping_timeout: Duration::from_millis(20000),
+            heartbeat: true,
             max_buffer_size: 128,
+            overflow_policy: OverflowPolicy::default(),
+            packet_channel: None,
             max_payload: 1e5 as u64, // 100kb
+            packets_per_yield: 64,
+            #[cfg(feature = "compression")]
+            compression_threshold: 1024,
+            utf8_validation: Utf8Validation::default(),
+            max_message_size: None,
+            polling_duration: Duration::from_millis(25000),
+            upgrade_timeout: Duration::from_millis(10000),
+            strict_upgrade_origin: None,
+            ws_ping_interval: None,
+            transport_liveness: false,
+            write_coalesce_window: None,
+            polling_coalesce_delay: None,
+            adaptive_heartbeat: None,
+            client_ping_policy: ClientPingPolicy::default(),
+            idle_timeout: None,
+            max_connection_lifetime: None,
+            heartbeat_sweep_interval: None,
+            proxy_buffering: true,
+            close_grace: Duration::from_millis(1000),
+            ws_write_retries: 3,
+            ws_write_retry_backoff: Duration::from_millis(10),
+            ws_polling_fallback: false,
+            ws_subprotocols: Vec::new(),
+            ws_subprotocol_strict: false,
+            allowed_origins: Vec::new(),
+            session_store: Arc::new(MemorySessionStore::default()),
+            handshake_rate_limit: None,
+            session_recording: None,
+            retry_after: Duration::from_secs(5),
+            event_stream_capacity: 1024,
+            socket_shards: crate::shard::default_shard_count(),
+            handshake_response_hook: None,
+            cookie: None,
+            runtime: None,
+            payload_logging: PayloadLogging::default(),
+            correlation_id_header: None,
             transports: TransportType::Polling as u8 | TransportType::Websocket as u8,
+            allowed_versions: if cfg!(feature = "v3") {
+                1 << ProtocolVersion::V3 as u8 | 1 << ProtocolVersion::V4 as u8
+            } else {
+                1 << ProtocolVersion::V4 as u8
+            },
         }
     }
 }
@@ -83,11 +431,524 @@ impl EngineIoConfig {
         EngineIoConfigBuilder::new()
     }
 
+    /// Check if a request path should be handled by the engine.io server.
+    ///
+    /// The path matches if it is the configured [`req_path`](Self::req_path) or one of its sub paths
+    /// (e.g. "/engine.io/" for "/engine.io" but not "/engine.iofoo").
+    pub fn matches_path(&self, path: &str) -> bool {
+        let req_path = self.req_path.trim_end_matches('/');
+        path.strip_prefix(req_path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// The ping interval sent to the clients in the handshake.
+    ///
+    /// With an [`adaptive_heartbeat`](Self::adaptive_heartbeat), it is the longest interval
+    /// so that the clients don't consider the server as dead when the interval grows.
+    ///
+    /// Without [`heartbeat`](Self::heartbeat), it is the longest interval a javascript client can wait for
+    /// (its timers are limited to `i32::MAX` milliseconds), minus the ping timeout.
+    pub(crate) fn advertised_ping_interval(&self) -> Duration {
+        if !self.heartbeat {
+            return Duration::from_millis(i32::MAX as u64).saturating_sub(self.ping_timeout);
+        }
+        match self.adaptive_heartbeat {
+            Some(adaptive) => adaptive.max_interval,
+            None => self.ping_interval,
+        }
+    }
+
+    /// Yields to the runtime once every [`packets_per_yield`](Self::packets_per_yield) dispatched packets.
+    pub(crate) async fn yield_after(&self, dispatched: usize) {
+        if self.packets_per_yield > 0 && dispatched % self.packets_per_yield == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Check if a received text message exceeds the [`max_message_size`](Self::max_message_size).
+    pub(crate) fn is_message_too_large(&self, msg: &str) -> bool {
+        self.max_message_size.is_some_and(|max| msg.len() > max)
+    }
+
+    /// Returns the configured [`runtime`](Self::runtime), or the runtime of the current context.
+    ///
+    /// It returns an error if there is no configured runtime and it is called outside of a runtime context.
+    pub fn runtime_handle(&self) -> Result<Handle, TryCurrentError> {
+        match &self.runtime {
+            Some(handle) => Ok(handle.clone()),
+            None => Handle::try_current(),
+        }
+    }
+
+    /// Spawns a task on the configured [`runtime`](Self::runtime), or on the runtime of the current context.
+    ///
+    /// # Panics
+    /// If there is no configured runtime and it is called outside of a runtime context.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        spawn_on(self.runtime.as_ref(), future)
+    }
+
+    /// Returns true if the origin is one of the [`allowed_origins`](Self::allowed_origins)
+    /// or if there is no allowed origin. The scheme and the host are compared case-insensitively.
+    ///
+    /// It can be used to share the allowlist with the CORS configuration of the polling transport.
+    pub fn is_origin_allowed(&self, origin: &HeaderValue) -> bool {
+        let Ok(origin) = origin.to_str() else {
+            return self.allowed_origins.is_empty();
+        };
+        let origin = origin.trim_end_matches('/');
+        self.allowed_origins.is_empty()
+            || self
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    }
+
+    /// Checks the origin of a websocket upgrade request: its `Origin` header against the
+    /// [`allowed_origins`](Self::allowed_origins) and, when it upgrades a polling session,
+    /// the [`strict_upgrade_origin`](Self::strict_upgrade_origin) against the `handshake` request of the session.
+    /// Requests failing the check get a `400 Bad Request` error.
+    pub(crate) fn check_upgrade_origin(
+        &self,
+        req: &Parts,
+        handshake: Option<&Parts>,
+    ) -> Result<(), Error> {
+        if let Some(origin) = req.headers.get(http::header::ORIGIN) {
+            if !self.is_origin_allowed(origin) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    "websocket upgrade rejected, origin not allowed: {:?}",
+                    origin
+                );
+                return Err(Error::HttpErrorResponse(http::StatusCode::BAD_REQUEST));
+            }
+        }
+        let strict = self.strict_upgrade_origin.as_ref().zip(handshake);
+        if strict.is_some_and(|(origin, handshake)| !origin.matches(handshake, req)) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                "websocket upgrade rejected, the request origin doesn't match the handshake"
+            );
+            return Err(Error::HttpErrorResponse(http::StatusCode::BAD_REQUEST));
+        }
+        Ok(())
+    }
+
+    /// Checks the [`handshake_rate_limit`](Self::handshake_rate_limit) if there is one.
+    /// Over-limit handshakes get a `429 Too Many Requests` error, and handshakes over the concurrency
+    /// bound get a `503 Service Unavailable` error.
+    ///
+    /// The returned permit must be kept until the open packet is sent.
+    pub(crate) fn check_handshake_rate(
+        &self,
+        req: &Parts,
+    ) -> Result<Option<HandshakePermit>, Error> {
+        let Some(limit) = &self.handshake_rate_limit else {
+            return Ok(None);
+        };
+        limit.check(req).map_err(Error::TooManyRequests)?;
+        match limit.acquire() {
+            Some(permit) => Ok(Some(permit)),
+            None => Err(Error::Unavailable(Duration::from_secs(1))),
+        }
+    }
+
+    /// Calls the [`handshake_response_hook`](Self::handshake_response_hook) if there is one.
+    /// The `reserved` headers are restored once the hook is called.
+    pub(crate) fn call_handshake_hook(
+        &self,
+        handshake: &Handshake<'_>,
+        headers: &mut HeaderMap,
+        reserved: &[HeaderName],
+    ) {
+        let Some(hook) = &self.handshake_response_hook else {
+            return;
+        };
+        let saved: Vec<(&HeaderName, Vec<_>)> = reserved
+            .iter()
+            .map(|name| (name, headers.get_all(name).iter().cloned().collect()))
+            .collect();
+        (hook.0)(handshake, headers);
+        for (name, values) in saved {
+            headers.remove(name);
+            for value in values {
+                headers.append(name.clone(), value);
+            }
+        }
+    }
+
+    /// Appends the [`cookie`](Self::cookie) of the session to the headers of a handshake response, if it is set
+    pub(crate) fn set_cookie(&self, sid: Sid, headers: &mut HeaderMap) {
+        if let Some(cookie) = &self.cookie {
+            headers.append(SET_COOKIE, cookie.header_value(sid));
+        }
+    }
+
     /// Check if a [`TransportType`] is enabled in the [`EngineIoConfig`]
     #[inline(always)]
     pub fn allowed_transport(&self, transport: TransportType) -> bool {
         self.transports & transport as u8 == transport as u8
     }
+
+    /// Check if a [`ProtocolVersion`] is enabled in the [`EngineIoConfig`]
+    #[inline(always)]
+    pub fn allowed_version(&self, version: ProtocolVersion) -> bool {
+        self.allowed_versions & 1 << version as u8 != 0
+    }
+}
+
+/// The behavior of a socket when a packet is emitted while its buffer is full,
+/// typically because the client is too slow to receive the packets.
+///
+/// Packets discarded by a policy are counted by [`Socket::dropped_packets`](crate::Socket::dropped_packets).
+/// Adjacent packets emitted together (e.g. a message with its binary attachments) are always discarded together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The packet is rejected and the `emit()` method returns an error with the original data.
+    #[default]
+    Reject,
+    /// The oldest buffered packet is discarded to make room for the new one.
+    /// It is useful when only the most recent data matters, e.g. for live updates.
+    DropOldest,
+    /// The new packet is silently discarded and the buffered packets are kept.
+    DropNewest,
+}
+
+/// The behavior of the server when a client sends a ping with the v4 protocol,
+/// see [`EngineIoConfig::client_ping_policy`].
+///
+/// With the v4 protocol the pings are sent by the server and the clients only answer with pongs,
+/// but some clients send pings anyway. With the v3 protocol the pings are always answered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClientPingPolicy {
+    /// The ping is discarded.
+    Ignore,
+    /// The ping is answered with a pong, for the interoperability with lenient clients.
+    #[default]
+    Pong,
+    /// The connection is closed with the [`DisconnectReason::PacketParsingError`](crate::DisconnectReason::PacketParsingError) reason.
+    Close,
+}
+
+/// The bounds of an adaptive heartbeat, see [`EngineIoConfig::adaptive_heartbeat`].
+///
+/// The first ping of a socket is sent after the [`ping_interval`](EngineIoConfig::ping_interval),
+/// clamped within the bounds. Then, after each pong, the round-trip time is compared to its smoothed average:
+/// * If it is stable, the interval is lengthened by 25%, to spare the battery and bandwidth of the clients.
+/// * If it doubles or exceeds half the [`ping_timeout`](EngineIoConfig::ping_timeout), the interval is halved,
+///   to detect a failing connection sooner.
+/// * If a pong is missed while the transport is kept alive, the interval drops to `min_interval`.
+///
+/// The `max_interval` is advertised as the ping interval in the handshake,
+/// so that the clients wait long enough for the pings before considering the server as dead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveHeartbeat {
+    /// The shortest interval between two pings
+    pub min_interval: Duration,
+    /// The longest interval between two pings
+    pub max_interval: Duration,
+}
+
+impl AdaptiveHeartbeat {
+    /// Round-trip time variations under this tolerance are always considered stable
+    const RTT_TOLERANCE: Duration = Duration::from_millis(5);
+
+    /// Clamps an interval within the bounds
+    pub(crate) fn clamp(&self, interval: Duration) -> Duration {
+        interval.clamp(self.min_interval, self.max_interval)
+    }
+
+    /// Computes the next interval from the last round-trip time and the smoothed average of the previous ones
+    pub(crate) fn next_interval(
+        &self,
+        interval: Duration,
+        rtt: Duration,
+        smoothed_rtt: Duration,
+        timeout: Duration,
+    ) -> Duration {
+        let next = if rtt > timeout / 2 || rtt > smoothed_rtt * 2 + Self::RTT_TOLERANCE {
+            interval / 2
+        } else if rtt <= smoothed_rtt + smoothed_rtt / 4 + Self::RTT_TOLERANCE {
+            interval + interval / 4
+        } else {
+            interval
+        };
+        self.clamp(next)
+    }
+}
+
+/// How invalid UTF-8 in the text packets received with the polling transport is handled.
+///
+/// Text packets are validated once, when they are decoded, and their data is then handed over without being copied.
+/// Websocket text frames are always validated by the websocket protocol,
+/// as well as engine.io v3 string payloads whose packet lengths are counted in characters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Utf8Validation {
+    /// A packet with invalid UTF-8 is rejected and the request fails, as required by the protocol.
+    #[default]
+    Strict,
+    /// Invalid sequences are replaced with the replacement character `U+FFFD`,
+    /// to tolerate clients sending slightly malformed text.
+    Lossy,
+}
+
+/// How the payloads of the packets are written in the tracing events,
+/// e.g. to comply with a policy forbidding to log message contents.
+///
+/// The default policy is [`Full`](PayloadLogging::Full) in debug builds and [`Off`](PayloadLogging::Off)
+/// in release builds, so that auth tokens or personal data are not dumped in production logs by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadLogging {
+    /// The payloads are never logged, only their type and their length.
+    Off,
+    /// The payloads are replaced by their length and a hash of their content.
+    /// Identical payloads can be correlated across the logs without being readable.
+    Hashed,
+    /// The payloads are truncated to the given number of bytes.
+    Truncated(usize),
+    /// The payloads are fully logged.
+    Full,
+}
+
+impl Default for PayloadLogging {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            PayloadLogging::Full
+        } else {
+            PayloadLogging::Off
+        }
+    }
+}
+
+impl PayloadLogging {
+    /// Formats a payload for a tracing event according to the policy
+    pub fn format(self, payload: &(impl std::fmt::Debug + ?Sized)) -> String {
+        match self {
+            PayloadLogging::Off => "<redacted>".to_string(),
+            PayloadLogging::Hashed => {
+                let payload = format!("{payload:?}");
+                format!(
+                    "<{} bytes, hash {:016x}>",
+                    payload.len(),
+                    hash_payload(&payload)
+                )
+            }
+            PayloadLogging::Truncated(max) => {
+                let mut payload = format!("{payload:?}");
+                if truncate_on_char_boundary(&mut payload, max) {
+                    payload.push_str("...");
+                }
+                payload
+            }
+            PayloadLogging::Full => format!("{payload:?}"),
+        }
+    }
+}
+
+/// Truncates a string to at most `max` bytes, on a char boundary so that a multi-byte char is never split.
+/// Returns true if the string was truncated.
+pub(crate) fn truncate_on_char_boundary(s: &mut String, max: usize) -> bool {
+    if s.len() <= max {
+        return false;
+    }
+    let end = s
+        .char_indices()
+        .map(|(i, _)| i)
+        .take_while(|&i| i <= max)
+        .last()
+        .unwrap_or(0);
+    s.truncate(end);
+    true
+}
+
+/// Hashes a payload for the [`PayloadLogging::Hashed`] policy.
+/// The hasher has fixed keys so that the hashes are stable between the connections.
+pub(crate) fn hash_payload(payload: impl AsRef<[u8]>) -> u64 {
+    use std::hash::Hasher;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    hasher.write(payload.as_ref());
+    hasher.finish()
+}
+
+/// The handshake information given to the [`handshake_response_hook`](EngineIoConfig::handshake_response_hook)
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Handshake<'a> {
+    /// The id of the session.
+    /// It is `None` for a direct websocket connection because its session is created once the connection is upgraded.
+    pub sid: Option<Sid>,
+    /// The transport of the handshake request
+    pub transport: TransportType,
+    /// The parts of the handshake http request
+    pub req: &'a Parts,
+}
+
+/// A hook to modify the headers of the handshake responses,
+/// see [`EngineIoConfig::handshake_response_hook`].
+#[derive(Clone)]
+pub struct HandshakeResponseHook(Arc<HandshakeHookFn>);
+type HandshakeHookFn = dyn Fn(&Handshake<'_>, &mut HeaderMap) + Send + Sync;
+
+impl HandshakeResponseHook {
+    /// Create a new hook from a function
+    pub fn new(hook: impl Fn(&Handshake<'_>, &mut HeaderMap) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+}
+
+impl std::fmt::Debug for HandshakeResponseHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("HandshakeResponseHook").finish()
+    }
+}
+
+/// Creates the [`PacketChannel`] of each socket from its capacity,
+/// see [`EngineIoConfig::packet_channel`].
+#[derive(Clone)]
+pub struct PacketChannelFactory(Arc<PacketChannelFn>);
+type PacketChannelFn = dyn Fn(usize) -> Box<dyn PacketChannel<PacketBuf>> + Send + Sync;
+
+impl PacketChannelFactory {
+    /// Create a new factory from a function
+    pub fn new<Q: PacketChannel<PacketBuf> + 'static>(
+        factory: impl Fn(usize) -> Q + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(move |capacity| Box::new(factory(capacity))))
+    }
+
+    /// Creates a queue with the given capacity
+    pub(crate) fn create(&self, capacity: usize) -> Box<dyn PacketChannel<PacketBuf>> {
+        (self.0)(capacity)
+    }
+}
+
+impl std::fmt::Debug for PacketChannelFactory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PacketChannelFactory").finish()
+    }
+}
+
+/// The cookie set on the handshake responses with the sid as value, see [`EngineIoConfig::cookie`].
+///
+/// It is serialized as `{name}={sid}; Path={path}; HttpOnly; SameSite={same_site}; Secure`,
+/// the attributes being omitted when they are not set.
+///
+/// The default cookie is the one of the reference implementation: `io={sid}; Path=/; HttpOnly; SameSite=Lax`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeCookie {
+    /// The name of the cookie
+    pub name: Cow<'static, str>,
+    /// The `Path` attribute of the cookie
+    pub path: Option<Cow<'static, str>>,
+    /// The `SameSite` attribute of the cookie
+    pub same_site: Option<SameSite>,
+    /// If true, the cookie has the `Secure` attribute, it is only sent over https.
+    /// The browsers require it with [`SameSite::None`].
+    pub secure: bool,
+    /// If true, the cookie has the `HttpOnly` attribute, it can't be read by javascript
+    pub http_only: bool,
+}
+
+impl Default for HandshakeCookie {
+    fn default() -> Self {
+        Self {
+            name: "io".into(),
+            path: Some("/".into()),
+            same_site: Some(SameSite::Lax),
+            secure: false,
+            http_only: true,
+        }
+    }
+}
+
+impl HandshakeCookie {
+    /// Serializes the cookie with the given sid as value
+    pub(crate) fn header_value(&self, sid: Sid) -> HeaderValue {
+        let mut cookie = format!("{}={}", self.name, sid);
+        if let Some(path) = &self.path {
+            cookie.push_str("; Path=");
+            cookie.push_str(path);
+        }
+        if self.http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        if let Some(same_site) = self.same_site {
+            cookie.push_str("; SameSite=");
+            cookie.push_str(same_site.as_str());
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        // The name and the path are checked when the cookie is set on the config
+        HeaderValue::try_from(cookie).expect("invalid handshake cookie")
+    }
+}
+
+/// The `SameSite` attribute of the [`HandshakeCookie`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// The cookie is only sent with the requests from the same site
+    Strict,
+    /// The cookie is also sent when navigating to the site from another one
+    Lax,
+    /// The cookie is sent with all the requests, it must be [`secure`](HandshakeCookie::secure)
+    None,
+}
+
+impl SameSite {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// What is compared between the handshake request of a polling session and the request upgrading it to websocket,
+/// see [`EngineIoConfig::strict_upgrade_origin`].
+///
+/// A value missing from either request fails the check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpgradeOrigin {
+    /// The remote IP, read from a [`SocketAddr`](std::net::SocketAddr) inserted in the extensions of the requests,
+    /// for example by a middleware copying it from the connection info of the server.
+    RemoteIp,
+    /// The value of a header, e.g. `X-Forwarded-For` behind a reverse proxy
+    /// or a client certificate header set by a TLS terminating proxy.
+    Header(HeaderName),
+}
+
+impl UpgradeOrigin {
+    /// Returns true if the upgrade request has the same origin as the handshake request
+    pub(crate) fn matches(&self, handshake: &Parts, upgrade: &Parts) -> bool {
+        match self {
+            UpgradeOrigin::RemoteIp => {
+                let remote_ip = |req: &Parts| req.extensions.get::<SocketAddr>().map(|a| a.ip());
+                remote_ip(handshake).is_some_and(|ip| remote_ip(upgrade) == Some(ip))
+            }
+            UpgradeOrigin::Header(name) => handshake
+                .headers
+                .get(name)
+                .is_some_and(|value| upgrade.headers.get(name) == Some(value)),
+        }
+    }
+}
+
+/// Spawns a task on the given runtime, or on the runtime of the current context
+pub(crate) fn spawn_on<F>(runtime: Option<&Handle>, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match runtime {
+        Some(handle) => handle.spawn(future),
+        None => tokio::spawn(future),
+    }
 }
 
 /// Builder for [`EngineIoConfig`]
@@ -103,10 +964,13 @@ impl EngineIoConfigBuilder {
         }
     }
 
-    /// The path to listen for engine.io requests on.
+    /// The path to listen for engine.io requests on, for example "/myapp/engine.io".
+    /// It is normalized with a leading slash and without trailing slash.
+    ///
+    /// Requests that don't match this path are passed to the inner service.
     /// Defaults to "/engine.io".
     pub fn req_path(mut self, req_path: impl Into<Cow<'static, str>>) -> Self {
-        self.config.req_path = req_path.into();
+        self.config.req_path = normalize_path(req_path.into());
         self
     }
 
@@ -124,6 +988,15 @@ impl EngineIoConfigBuilder {
         self
     }
 
+    /// If false, the server never sends pings and only relies on the transport to detect closed connections.
+    /// It is meant for internal links over a trusted network, see [`EngineIoConfig::heartbeat`].
+    ///
+    /// Defaults to true.
+    pub fn heartbeat(mut self, heartbeat: bool) -> Self {
+        self.config.heartbeat = heartbeat;
+        self
+    }
+
     /// The maximum number of packets that can be buffered per connection before being emitted to the client.
     ///
     /// If the buffer if full the `emit()` method will return an error
@@ -163,6 +1036,34 @@ impl EngineIoConfigBuilder {
         self
     }
 
+    /// What to do when a packet is emitted while the buffer of a socket is full.
+    /// See [`OverflowPolicy`] for the available policies.
+    ///
+    /// Defaults to [`OverflowPolicy::Reject`].
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.config.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Creates the queue buffering the packets emitted to each socket, from the
+    /// [`max_buffer_size`](EngineIoConfig::max_buffer_size) of the sockets. See [`PacketChannel`].
+    ///
+    /// ```
+    /// # use engineioxide::{config::EngineIoConfig, channel::RingBuffer};
+    /// let config = EngineIoConfig::builder()
+    ///     .packet_channel(RingBuffer::with_capacity)
+    ///     .build();
+    /// ```
+    ///
+    /// Defaults to a [`Mutex`](std::sync::Mutex)-guarded [`VecDeque`](std::collections::VecDeque).
+    pub fn packet_channel<Q: PacketChannel<PacketBuf> + 'static>(
+        mut self,
+        factory: impl Fn(usize) -> Q + Send + Sync + 'static,
+    ) -> Self {
+        self.config.packet_channel = Some(PacketChannelFactory::new(factory));
+        self
+    }
+
     /// The maximum number of bytes that can be received per http request.
     /// Defaults to 100kb.
     pub fn max_payload(mut self, max_payload: u64) -> Self {
@@ -170,10 +1071,430 @@ impl EngineIoConfigBuilder {
         self
     }
 
+    /// The number of inbound packets dispatched to the handler before yielding to the runtime,
+    /// when reading a polling payload or the websocket frames already received.
+    /// It prevents a client sending many packets at once from starving the other sockets of its worker thread.
+    /// 0 disables the yields.
+    ///
+    /// Defaults to 64 packets.
+    pub fn packets_per_yield(mut self, packets_per_yield: usize) -> Self {
+        self.config.packets_per_yield = packets_per_yield;
+        self
+    }
+
+    /// The minimum size in bytes of a polling response payload to be compressed,
+    /// if the client accepts a supported encoding (gzip or brotli) in its `Accept-Encoding` header.
+    /// Small responses, like heartbeats, are never worth compressing.
+    ///
+    /// Encoded polling requests (with a `Content-Encoding` header) are always decompressed,
+    /// the decompressed body must not exceed the [`max_payload`](Self::max_payload) either.
+    ///
+    /// Defaults to 1kb.
+    #[cfg(feature = "compression")]
+    pub fn compression_threshold(mut self, compression_threshold: usize) -> Self {
+        self.config.compression_threshold = compression_threshold;
+        self
+    }
+
+    /// How invalid UTF-8 in the text packets of polling requests is handled.
+    /// See [`Utf8Validation`] for the available modes.
+    ///
+    /// Defaults to [`Utf8Validation::Strict`].
+    pub fn utf8_validation(mut self, utf8_validation: Utf8Validation) -> Self {
+        self.config.utf8_validation = utf8_validation;
+        self
+    }
+
+    /// The maximum size in bytes of a text message received from the client, measured on the decoded UTF-8 string.
+    ///
+    /// If a bigger message is received, the session is closed. With websocket,
+    /// the connection is closed with a policy violation (1008) close code.
+    ///
+    /// Defaults to no limit.
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.config.max_message_size = Some(max_message_size);
+        self
+    }
+
+    /// The maximum amount of time a polling request is held open while waiting for data.
+    /// After this duration the request is released with a noop packet so that the client can re-poll.
+    /// Defaults to 25 seconds.
+    pub fn polling_duration(mut self, polling_duration: Duration) -> Self {
+        self.config.polling_duration = polling_duration;
+        self
+    }
+
+    /// The amount of time the server will wait for the upgrade probe exchange to complete
+    /// when a polling session is upgraded to websocket.
+    /// If it times out, the websocket attempt is abandoned and the session stays on polling.
+    /// Defaults to 10 seconds.
+    pub fn upgrade_timeout(mut self, upgrade_timeout: Duration) -> Self {
+        self.config.upgrade_timeout = upgrade_timeout;
+        self
+    }
+
+    /// Only accepts the upgrades of a polling session from a request
+    /// with the same [`UpgradeOrigin`] as the handshake request of the session.
+    /// The upgrades failing the check are rejected with a `400 Bad Request` response.
+    ///
+    /// By default only the sid is checked.
+    pub fn strict_upgrade_origin(mut self, origin: UpgradeOrigin) -> Self {
+        self.config.strict_upgrade_origin = Some(origin);
+        self
+    }
+
+    /// The interval at which the server will send websocket protocol-level ping frames to the client.
+    /// It is independent of the engine.io heartbeat and can be used to keep idle connections
+    /// open through proxies.
+    ///
+    /// Defaults to `None` (no ping frames are sent).
+    pub fn ws_ping_interval(mut self, ws_ping_interval: Duration) -> Self {
+        self.config.ws_ping_interval = Some(ws_ping_interval);
+        self
+    }
+
+    /// If true, transport activity (websocket pong frames) received since the last engine.io ping
+    /// extends the engine.io heartbeat deadline when the client does not answer in time.
+    ///
+    /// Defaults to false, to keep the behavior of the engine.io protocol.
+    pub fn transport_liveness(mut self, transport_liveness: bool) -> Self {
+        self.config.transport_liveness = transport_liveness;
+        self
+    }
+
+    /// Waits for this amount of time after a packet before flushing the websocket,
+    /// so that the packets emitted in bursts are written with fewer flushes and syscalls.
+    /// Heartbeat and close packets are always flushed right away.
+    ///
+    /// Defaults to `None` (the packets are flushed as soon as the emission buffer is drained).
+    pub fn write_coalesce_window(mut self, write_coalesce_window: Duration) -> Self {
+        self.config.write_coalesce_window = Some(write_coalesce_window);
+        self
+    }
+
+    /// Waits for this amount of time after the first packet before responding to a parked polling request,
+    /// so that the packets emitted in bursts are sent in a single payload.
+    /// Heartbeat and close packets and full payloads are always sent right away.
+    ///
+    /// Defaults to `None` (the packets are sent as soon as they are emitted).
+    pub fn polling_coalesce_delay(mut self, polling_coalesce_delay: Duration) -> Self {
+        self.config.polling_coalesce_delay = Some(polling_coalesce_delay);
+        self
+    }
+
+    /// What to do when a client sends a ping with the v4 protocol, where only the server should send pings.
+    /// See [`ClientPingPolicy`] for the available policies.
+    ///
+    /// Defaults to [`ClientPingPolicy::Pong`].
+    pub fn client_ping_policy(mut self, client_ping_policy: ClientPingPolicy) -> Self {
+        self.config.client_ping_policy = client_ping_policy;
+        self
+    }
+
+    /// Adapts the interval between the heartbeat pings of each socket to its round-trip times,
+    /// within `min_interval` and `max_interval`. See [`AdaptiveHeartbeat`] for more details.
+    ///
+    /// Defaults to `None` (pings are sent every [`ping_interval`](EngineIoConfig::ping_interval)).
+    ///
+    /// # Panics
+    /// If `min_interval` is zero or greater than `max_interval`.
+    pub fn adaptive_heartbeat(mut self, min_interval: Duration, max_interval: Duration) -> Self {
+        assert!(
+            !min_interval.is_zero() && min_interval <= max_interval,
+            "the adaptive heartbeat bounds should satisfy 0 < min_interval <= max_interval"
+        );
+        self.config.adaptive_heartbeat = Some(AdaptiveHeartbeat {
+            min_interval,
+            max_interval,
+        });
+        self
+    }
+
+    /// The amount of time a client can stay connected without sending any message.
+    /// Heartbeats are not taken into account, so it closes connections that are alive but idle at the application level.
+    /// Idle sockets are closed with the [`DisconnectReason::IdleTimeout`](crate::DisconnectReason::IdleTimeout) reason.
+    ///
+    /// Defaults to `None` (idle connections are kept).
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.config.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// The maximum amount of time a socket can stay connected before it is closed, so that the client
+    /// reconnects and gets rebalanced, e.g. after a scaling event.
+    /// Expired sockets are closed with the [`DisconnectReason::LifetimeExpired`](crate::DisconnectReason::LifetimeExpired) reason.
+    ///
+    /// Defaults to `None` (connections are never recycled).
+    pub fn max_connection_lifetime(mut self, max_connection_lifetime: Duration) -> Self {
+        self.config.max_connection_lifetime = Some(max_connection_lifetime);
+        self
+    }
+
+    /// The interval at which a background task closes the sockets that outlived their heartbeat deadline,
+    /// as a safety net against stuck heartbeat tasks.
+    /// Dead sockets are closed with the [`DisconnectReason::HeartbeatTimeout`](crate::DisconnectReason::HeartbeatTimeout) reason.
+    ///
+    /// Defaults to `None` (no sweep).
+    pub fn heartbeat_sweep_interval(mut self, heartbeat_sweep_interval: Duration) -> Self {
+        self.config.heartbeat_sweep_interval = Some(heartbeat_sweep_interval);
+        self
+    }
+
+    /// If false, polling responses have a `X-Accel-Buffering: no` header
+    /// so that reverse proxies like nginx don't buffer the long-polling responses.
+    ///
+    /// Defaults to true.
+    pub fn proxy_buffering(mut self, proxy_buffering: bool) -> Self {
+        self.config.proxy_buffering = proxy_buffering;
+        self
+    }
+
+    /// The amount of time given to the websocket writer to flush the final packets
+    /// and the close frame when a connection is closed, before it is forcibly dropped.
+    /// Defaults to 1 second.
+    pub fn close_grace(mut self, close_grace: Duration) -> Self {
+        self.config.close_grace = close_grace;
+        self
+    }
+
+    /// The number of times a websocket write failing with a transient error (e.g. `WouldBlock`)
+    /// is retried before the connection is closed. Fatal errors are never retried.
+    ///
+    /// Defaults to 3.
+    pub fn ws_write_retries(mut self, ws_write_retries: u32) -> Self {
+        self.config.ws_write_retries = ws_write_retries;
+        self
+    }
+
+    /// The delay before the first retry of a failed websocket write, doubled for each following retry.
+    ///
+    /// Defaults to 10ms.
+    pub fn ws_write_retry_backoff(mut self, ws_write_retry_backoff: Duration) -> Self {
+        self.config.ws_write_retry_backoff = ws_write_retry_backoff;
+        self
+    }
+
+    /// If true, a socket upgraded from polling to websocket is downgraded back to polling
+    /// when its websocket writes still fail after the write retries, instead of being closed.
+    ///
+    /// It is a best effort that only works with clients still able to poll the session,
+    /// see [`EngineIoConfig::ws_polling_fallback`] for the conditions under which it is safe.
+    ///
+    /// Defaults to false.
+    pub fn ws_polling_fallback(mut self, ws_polling_fallback: bool) -> Self {
+        self.config.ws_polling_fallback = ws_polling_fallback;
+        self
+    }
+
+    /// The websocket subprotocols accepted by the server.
+    /// The first subprotocol offered by the client that is accepted is echoed in the upgrade response.
+    ///
+    /// Defaults to an empty list.
+    ///
+    /// ```
+    /// # use engineioxide::config::EngineIoConfig;
+    /// let config = EngineIoConfig::builder()
+    ///     .ws_subprotocols(["v2.chat", "v1.chat"])
+    ///     .build();
+    /// ```
+    pub fn ws_subprotocols<I>(mut self, subprotocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'static, str>>,
+    {
+        self.config.ws_subprotocols = subprotocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// If true, a websocket upgrade offering only subprotocols that are not accepted
+    /// is rejected with a `400 Bad Request` response.
+    ///
+    /// Defaults to false, the upgrade is accepted without subprotocol.
+    pub fn ws_subprotocol_strict(mut self, ws_subprotocol_strict: bool) -> Self {
+        self.config.ws_subprotocol_strict = ws_subprotocol_strict;
+        self
+    }
+
+    /// The origins allowed to connect, e.g. `https://example.com`.
+    /// Websocket upgrade requests with a disallowed `Origin` header are rejected
+    /// with a `400 Bad Request` response, like the [`strict_upgrade_origin`](Self::strict_upgrade_origin) check.
+    /// The same list should be given to the CORS configuration of the polling transport.
+    ///
+    /// Defaults to an empty list (all origins are allowed).
+    ///
+    /// ```
+    /// # use engineioxide::config::EngineIoConfig;
+    /// let config = EngineIoConfig::builder()
+    ///     .allowed_origins(["https://example.com", "https://admin.example.com"])
+    ///     .build();
+    /// ```
+    pub fn allowed_origins<I>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'static, str>>,
+    {
+        self.config.allowed_origins = origins.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The [`SessionStore`] used to validate session ids across processes,
+    /// for example when running behind a load balancer without sticky sessions.
+    /// See the [`session`](crate::session) module for more details.
+    /// Defaults to a [`MemorySessionStore`].
+    pub fn session_store(mut self, session_store: impl SessionStore) -> Self {
+        self.config.session_store = Arc::new(session_store);
+        self
+    }
+
+    /// Limits the handshakes per remote IP with a [`HandshakeRateLimit`].
+    /// Over-limit handshakes are rejected with a `429 Too Many Requests` response,
+    /// for polling and websocket handshakes alike.
+    /// See the [`rate_limit`](crate::rate_limit) module for more details.
+    ///
+    /// Defaults to `None`, the handshakes are not limited.
+    pub fn handshake_rate_limit(mut self, limit: Arc<HandshakeRateLimit>) -> Self {
+        self.config.handshake_rate_limit = Some(limit);
+        self
+    }
+
+    /// Records the packets of a random sample of the new sessions with a [`SessionRecording`].
+    /// See the [`recorder`](crate::recorder) module for more details.
+    ///
+    /// Defaults to `None`, the sessions are only recorded with [`Socket::start_recording`](crate::Socket::start_recording).
+    pub fn session_recording(mut self, recording: SessionRecording) -> Self {
+        self.config.session_recording = Some(recording);
+        self
+    }
+
+    /// The delay advertised in the `Retry-After` header of the `503 Service Unavailable` responses
+    /// sent to new sessions while the server is paused, see [`EngineIoHandle::pause_accepting`].
+    ///
+    /// Defaults to 5 seconds.
+    ///
+    /// [`EngineIoHandle::pause_accepting`]: crate::handler::EngineIoHandle::pause_accepting
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.config.retry_after = retry_after;
+        self
+    }
+
+    /// The number of events buffered for each [`EventStream`](crate::events::EventStream) of the server.
+    /// When a stream lags behind, the oldest events are dropped for it.
+    ///
+    /// Defaults to 1024 events.
+    ///
+    /// # Panics
+    /// If the capacity is 0.
+    pub fn event_stream_capacity(mut self, event_stream_capacity: usize) -> Self {
+        assert!(
+            event_stream_capacity > 0,
+            "event_stream_capacity must be > 0"
+        );
+        self.config.event_stream_capacity = event_stream_capacity;
+        self
+    }
+
+    /// The number of shards of the socket map, see [`ShardedMap`](crate::shard::ShardedMap).
+    /// More shards lower the lock contention between the handshakes, disconnections and broadcasts.
+    ///
+    /// Defaults to four times the number of available CPUs.
+    ///
+    /// # Panics
+    /// If the number of shards is 0.
+    pub fn socket_shards(mut self, socket_shards: usize) -> Self {
+        assert!(socket_shards > 0, "socket_shards must be > 0");
+        self.config.socket_shards = socket_shards;
+        self
+    }
+
+    /// A hook called with the headers of the handshake responses, just before they are sent:
+    /// the polling handshake response and the `101 Switching Protocols` response of websocket connections.
+    /// It can be used to add a sticky session cookie or security headers.
+    ///
+    /// Some headers are reserved and can't be modified, see [`EngineIoConfig::handshake_response_hook`].
+    ///
+    /// ```
+    /// # use engineioxide::config::EngineIoConfig;
+    /// # use http::{header::SET_COOKIE, HeaderValue};
+    /// let config = EngineIoConfig::builder()
+    ///     .handshake_response_hook(|_, headers| {
+    ///         headers.append(SET_COOKIE, HeaderValue::from_static("node=1; Path=/; HttpOnly"));
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn handshake_response_hook(
+        mut self,
+        hook: impl Fn(&Handshake<'_>, &mut HeaderMap) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.handshake_response_hook = Some(HandshakeResponseHook::new(hook));
+        self
+    }
+
+    /// Sets a cookie containing the sid on the handshake responses, for cookie-based sticky sessions.
+    /// See [`EngineIoConfig::cookie`] and [`HandshakeCookie`].
+    ///
+    /// Defaults to `None` (no cookie is set).
+    ///
+    /// ```
+    /// # use engineioxide::config::{EngineIoConfig, HandshakeCookie, SameSite};
+    /// let config = EngineIoConfig::builder()
+    ///     .cookie(HandshakeCookie {
+    ///         same_site: Some(SameSite::None),
+    ///         secure: true,
+    ///         ..Default::default()
+    ///     })
+    ///     .build();
+    /// ```
+    ///
+    /// # Panics
+    /// If the name or the path of the cookie contain characters that are not allowed in a header value.
+    pub fn cookie(mut self, cookie: HandshakeCookie) -> Self {
+        assert!(
+            HeaderValue::try_from(format!(
+                "{}{}",
+                cookie.name,
+                cookie.path.as_deref().unwrap_or_default()
+            ))
+            .is_ok(),
+            "the name and the path of the cookie should be valid header values"
+        );
+        self.config.cookie = Some(cookie);
+        self
+    }
+
+    /// The runtime on which the tasks of the server are spawned (heartbeats, websocket connections, ...).
+    /// It is useful when the server is embedded in an application managing its own runtimes.
+    /// Its time driver must be enabled.
+    /// Defaults to the runtime of the current context.
+    pub fn runtime(mut self, runtime: Handle) -> Self {
+        self.config.runtime = Some(runtime);
+        self
+    }
+
+    /// How the payloads of the packets are written in the tracing events.
+    /// See [`PayloadLogging`] for the available policies.
+    ///
+    /// Defaults to [`PayloadLogging::Full`] in debug builds and to [`PayloadLogging::Off`] in release builds.
+    pub fn payload_logging(mut self, payload_logging: PayloadLogging) -> Self {
+        self.config.payload_logging = payload_logging;
+        self
+    }
+
+    /// The request header from which the correlation id of a connection is captured at handshake,
+    /// e.g. `x-request-id`. A random correlation id is generated if the header is missing.
+    ///
+    /// Defaults to `None`, the correlation ids are always random.
+    pub fn correlation_id_header(mut self, header: HeaderName) -> Self {
+        self.config.correlation_id_header = Some(header);
+        self
+    }
+
     /// Allowed transports on this server
     ///
     /// The `transports` array should have a size of 1 or 2
     ///
+    /// The requests for a disabled transport are rejected with a `400 Bad Request` response.
+    /// With the websocket transport disabled, the upgrade to websocket is not advertised to the polling clients.
+    ///
     /// Defaults to :
     /// `[TransportType::Polling, TransportType::Websocket]`
     pub fn transports<const N: usize>(mut self, transports: [TransportType; N]) -> Self {
@@ -185,11 +1506,50 @@ impl EngineIoConfigBuilder {
         self
     }
 
+    /// Allowed engine.io protocol versions on this server, e.g. `[ProtocolVersion::V4]` to reject the v3 clients
+    /// of a server built with the `v3` feature.
+    ///
+    /// The requests with another version are rejected with the `Unsupported protocol version` error response.
+    ///
+    /// Defaults to all the versions supported by the enabled features:
+    /// `[ProtocolVersion::V3, ProtocolVersion::V4]` with the `v3` feature, `[ProtocolVersion::V4]` otherwise.
+    ///
+    /// ```
+    /// # use engineioxide::{config::EngineIoConfig, service::ProtocolVersion};
+    /// let config = EngineIoConfig::builder()
+    ///     .allowed_versions([ProtocolVersion::V4])
+    ///     .build();
+    /// assert!(!config.allowed_version(ProtocolVersion::V3));
+    /// ```
+    pub fn allowed_versions<const N: usize>(mut self, versions: [ProtocolVersion; N]) -> Self {
+        assert!(N > 0 && N <= 2);
+        self.config.allowed_versions = 0;
+        for version in versions {
+            self.config.allowed_versions |= 1 << version as u8;
+        }
+        self
+    }
+
     /// Build the config
     pub fn build(self) -> EngineIoConfig {
         self.config
     }
 }
+
+/// Add a leading slash and remove the trailing slashes of a request path
+fn normalize_path(path: Cow<'static, str>) -> Cow<'static, str> {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.starts_with('/') {
+        if trimmed.len() == path.len() {
+            path
+        } else {
+            trimmed.to_string().into()
+        }
+    } else {
+        format!("/{trimmed}").into()
+    }
+}
+
 impl Default for EngineIoConfigBuilder {
     fn default() -> Self {
         Self::new()
@@ -200,6 +1560,30 @@ impl Default for EngineIoConfigBuilder {
 mod tests {
     use super::*;
 
+    #[test]
+    pub fn config_req_path() {
+        let path = |p: &'static str| EngineIoConfig::builder().req_path(p).build().req_path;
+        assert_eq!(path("/engine.io"), "/engine.io");
+        assert_eq!(path("/myapp/socket.io/"), "/myapp/socket.io");
+        assert_eq!(path("myapp/socket.io"), "/myapp/socket.io");
+        assert_eq!(path("socket.io//"), "/socket.io");
+        assert_eq!(path("/"), "/");
+        assert_eq!(path(""), "/");
+
+        let conf = EngineIoConfig::builder()
+            .req_path("/myapp/socket.io/")
+            .build();
+        assert!(conf.matches_path("/myapp/socket.io"));
+        assert!(conf.matches_path("/myapp/socket.io/"));
+        assert!(!conf.matches_path("/myapp/socket.iofoo"));
+        assert!(!conf.matches_path("/socket.io/"));
+        assert!(!conf.matches_path("/myapp/"));
+
+        let conf = EngineIoConfig::builder().req_path("/").build();
+        assert!(conf.matches_path("/"));
+        assert!(conf.matches_path("/anything"));
+    }
+
     #[test]
     pub fn config_transports() {
         let conf = EngineIoConfig::builder()
@@ -219,4 +1603,189 @@ mod tests {
         assert!(conf.allowed_transport(TransportType::Polling));
         assert!(conf.allowed_transport(TransportType::Websocket));
     }
+
+    #[test]
+    pub fn adaptive_heartbeat_interval() {
+        let adaptive = AdaptiveHeartbeat {
+            min_interval: Duration::from_secs(10),
+            max_interval: Duration::from_secs(60),
+        };
+        let ms = Duration::from_millis;
+        let timeout = Duration::from_secs(20);
+        // Stable rtt
+        assert_eq!(
+            adaptive.next_interval(ms(20_000), ms(50), ms(48), timeout),
+            ms(25_000)
+        );
+        assert_eq!(
+            adaptive.next_interval(ms(55_000), ms(50), ms(48), timeout),
+            ms(60_000)
+        );
+        // Rising rtt
+        assert_eq!(
+            adaptive.next_interval(ms(20_000), ms(80), ms(50), timeout),
+            ms(20_000)
+        );
+        // Spiking rtt or slow pong
+        assert_eq!(
+            adaptive.next_interval(ms(40_000), ms(200), ms(50), timeout),
+            ms(20_000)
+        );
+        assert_eq!(
+            adaptive.next_interval(ms(40_000), ms(11_000), ms(10_000), timeout),
+            ms(20_000)
+        );
+        assert_eq!(
+            adaptive.next_interval(ms(15_000), ms(200), ms(50), timeout),
+            ms(10_000)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "0 < min_interval <= max_interval")]
+    pub fn adaptive_heartbeat_invalid_bounds() {
+        EngineIoConfig::builder()
+            .adaptive_heartbeat(Duration::from_secs(2), Duration::from_secs(1));
+    }
+
+    #[test]
+    pub fn payload_logging_format() {
+        let payload = "héllo world";
+        assert_eq!(PayloadLogging::Off.format(payload), "<redacted>");
+        let hashed = PayloadLogging::Hashed.format(payload);
+        assert!(hashed.starts_with("<14 bytes, hash "));
+        assert!(!hashed.contains("llo"));
+        assert_eq!(hashed, PayloadLogging::Hashed.format(payload));
+        assert_ne!(hashed, PayloadLogging::Hashed.format("hello world"));
+        assert_eq!(PayloadLogging::Full.format(payload), "\"héllo world\"");
+        assert_eq!(PayloadLogging::Truncated(6).format(payload), "\"héll...");
+        // The payload is truncated on a char boundary
+        assert_eq!(PayloadLogging::Truncated(3).format(payload), "\"h...");
+        assert_eq!(
+            PayloadLogging::Truncated(20).format(payload),
+            "\"héllo world\""
+        );
+    }
+
+    #[test]
+    pub fn truncate_multi_byte_chars() {
+        // 1, 4 (emoji), 3 (CJK) and 1 bytes chars
+        let text = "a😀中b";
+        let expected = [
+            (0, ""),
+            (1, "a"),
+            (2, "a"),
+            (4, "a"),
+            (5, "a😀"),
+            (7, "a😀"),
+            (8, "a😀中"),
+        ];
+        for (max, prefix) in expected {
+            let mut s = text.to_string();
+            assert!(truncate_on_char_boundary(&mut s, max));
+            assert_eq!(s, prefix, "truncated to {max} bytes");
+        }
+        let mut s = text.to_string();
+        assert!(!truncate_on_char_boundary(&mut s, text.len()));
+        assert_eq!(s, text);
+
+        // Every split offset gives a valid prefix
+        let text = "日本語のテキスト🎉🎉".repeat(3);
+        for max in 0..text.len() {
+            let mut s = text.clone();
+            truncate_on_char_boundary(&mut s, max);
+            assert!(s.len() <= max && text.starts_with(&s));
+            let next = text[s.len()..].chars().next().unwrap();
+            assert!(s.len() + next.len_utf8() > max);
+        }
+        assert_eq!(PayloadLogging::Truncated(6).format("中文"), "\"中...");
+    }
+
+    #[test]
+    pub fn upgrade_origin_matches() {
+        let req = |ip: Option<&str>, header: Option<&str>| {
+            let mut req = http::Request::builder();
+            if let Some(header) = header {
+                req = req.header("x-client", header);
+            }
+            if let Some(ip) = ip {
+                req = req.extension(SocketAddr::new(ip.parse().unwrap(), 1234));
+            }
+            req.body(()).unwrap().into_parts().0
+        };
+        let ip = UpgradeOrigin::RemoteIp;
+        assert!(ip.matches(&req(Some("1.2.3.4"), None), &req(Some("1.2.3.4"), None)));
+        assert!(!ip.matches(&req(Some("1.2.3.4"), None), &req(Some("4.3.2.1"), None)));
+        assert!(!ip.matches(&req(None, None), &req(None, None)));
+
+        let header = UpgradeOrigin::Header(HeaderName::from_static("x-client"));
+        assert!(header.matches(&req(None, Some("a")), &req(Some("1.2.3.4"), Some("a"))));
+        assert!(!header.matches(&req(None, Some("a")), &req(None, Some("b"))));
+        assert!(!header.matches(&req(None, Some("a")), &req(None, None)));
+        assert!(!header.matches(&req(None, None), &req(None, None)));
+    }
+
+    #[test]
+    pub fn origin_allowed() {
+        let origin = HeaderValue::from_static;
+        let config = EngineIoConfig::default();
+        assert!(config.is_origin_allowed(&origin("https://evil.com")));
+
+        let config = EngineIoConfig::builder()
+            .allowed_origins(["https://example.com/", "http://localhost:3000"])
+            .build();
+        assert!(config.is_origin_allowed(&origin("https://example.com")));
+        assert!(config.is_origin_allowed(&origin("HTTPS://Example.com")));
+        assert!(config.is_origin_allowed(&origin("http://localhost:3000")));
+        assert!(!config.is_origin_allowed(&origin("http://example.com")));
+        assert!(!config.is_origin_allowed(&origin("https://example.com.evil.com")));
+        assert!(!config.is_origin_allowed(&origin("http://localhost:3001")));
+        assert!(!config.is_origin_allowed(&origin("null")));
+    }
+
+    #[test]
+    pub fn upgrade_origin_policy() {
+        let req = |origin: Option<&str>, client: Option<&str>| {
+            let mut req = http::Request::builder();
+            if let Some(origin) = origin {
+                req = req.header(http::header::ORIGIN, origin);
+            }
+            if let Some(client) = client {
+                req = req.header("x-client", client);
+            }
+            req.body(()).unwrap().into_parts().0
+        };
+        let config = EngineIoConfig::builder()
+            .allowed_origins(["https://example.com"])
+            .strict_upgrade_origin(UpgradeOrigin::Header(HeaderName::from_static("x-client")))
+            .build();
+        let handshake = req(None, Some("a"));
+        let accepted = |origin, client, handshake| match config
+            .check_upgrade_origin(&req(origin, client), handshake)
+        {
+            Ok(()) => true,
+            Err(Error::HttpErrorResponse(http::StatusCode::BAD_REQUEST)) => false,
+            Err(e) => panic!("unexpected error {e:?}"),
+        };
+
+        assert!(accepted(Some("https://example.com"), None, None));
+        assert!(accepted(None, None, None));
+        assert!(!accepted(Some("https://evil.com"), None, None));
+
+        assert!(accepted(
+            Some("https://example.com"),
+            Some("a"),
+            Some(&handshake)
+        ));
+        assert!(!accepted(
+            Some("https://example.com"),
+            Some("b"),
+            Some(&handshake)
+        ));
+        assert!(!accepted(
+            Some("https://evil.com"),
+            Some("a"),
+            Some(&handshake)
+        ));
+    }
 }
@@ -1,39 +1,123 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
+    collections::HashSet,
+    sync::{atomic::Ordering, Arc, OnceLock, Weak},
+    time::Duration,
 };
 
+use futures::future::BoxFuture;
 use http::request::Parts;
+use tokio::task::AbortHandle;
 
 use crate::{
     config::EngineIoConfig,
-    handler::EngineIoHandler,
+    errors::Error,
+    events::{EventSender, ServerEvent},
+    handler::{EngineIoHandle, EngineIoHandler, ServerState, SharedState},
     service::TransportType,
+    session::SessionMetadata,
+    shard::ShardedMap,
     socket::{DisconnectReason, Socket},
 };
 use crate::{service::ProtocolVersion, sid::Sid};
 
-type SocketMap<T> = RwLock<HashMap<Sid, Arc<T>>>;
+#[cfg(feature = "polling")]
+use crate::events::UpgradeFailure;
+
+pub(crate) type SocketMap<T> = ShardedMap<Arc<T>>;
 
 /// The [`EngineIo`] struct holds the state of the engine.io server as well as utility methods to manage the state
 pub struct EngineIo<H: EngineIoHandler> {
     /// A map of all the sockets connected to the server
-    sockets: SocketMap<Socket<H::Data>>,
+    sockets: Arc<SocketMap<Socket<H::Data>>>,
 
     /// The handler for the engine.io server that will be called when events are received
     pub handler: H,
 
     /// The config for the engine.io server
     pub config: EngineIoConfig,
+
+    /// Set once the idle reaper task is spawned
+    idle_reaper: OnceLock<()>,
+
+    /// Set once the task closing the sockets exceeding their maximum lifetime is spawned
+    lifetime_reaper: OnceLock<()>,
+
+    /// The task closing the sockets that outlived their heartbeat deadline, aborted on shutdown
+    heartbeat_sweeper: OnceLock<AbortHandle>,
+
+    /// Whether new sessions are accepted, shared with the [`EngineIoHandle`]s
+    state: Arc<SharedState>,
+
+    /// The sending half of the [`ServerEvent`] streams
+    events: Arc<EventSender>,
 }
 
 impl<H: EngineIoHandler> EngineIo<H> {
     /// Create a new Engine.IO server with a [`EngineIoHandler`] and a [`EngineIoConfig`]
     pub fn new(handler: H, config: EngineIoConfig) -> Self {
         Self {
-            sockets: RwLock::new(HashMap::new()),
+            events: Arc::new(EventSender::new(config.event_stream_capacity)),
+            sockets: Arc::new(ShardedMap::new(config.socket_shards)),
             config,
             handler,
+            idle_reaper: OnceLock::new(),
+            lifetime_reaper: OnceLock::new(),
+            heartbeat_sweeper: OnceLock::new(),
+            state: Arc::new(SharedState::new()),
+        }
+    }
+
+    /// Create a new Engine.IO server and call the [`EngineIoHandler::on_start`] hook
+    pub(crate) fn start(handler: H, config: EngineIoConfig) -> Arc<Self> {
+        let engine = Arc::new(Self::new(handler, config));
+        engine.handler.on_start(engine.handle());
+        engine
+    }
+
+    /// Get a [`EngineIoHandle`] to this server
+    pub(crate) fn handle(&self) -> EngineIoHandle<H::Data> {
+        EngineIoHandle::new(
+            Arc::downgrade(&self.sockets),
+            self.state.clone(),
+            Arc::downgrade(&self.events),
+        )
+    }
+
+    /// Counts a session as upgrading to websocket until the returned guard is dropped
+    #[cfg(feature = "polling")]
+    pub(crate) fn upgrading(&self) -> UpgradingGuard {
+        self.state.upgrading.fetch_add(1, Ordering::Relaxed);
+        UpgradingGuard(self.state.clone())
+    }
+
+    /// Rejects the handshakes of new sessions with a `503 Service Unavailable` error
+    /// if the server is paused or shutting down
+    pub(crate) fn check_accepting(&self) -> Result<(), Error> {
+        match self.state.get() {
+            ServerState::Accepting => Ok(()),
+            _state => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(state = ?_state, "rejecting handshake");
+                Err(Error::Unavailable(self.config.retry_after))
+            }
+        }
+    }
+
+    /// Gracefully shutdown the server:
+    /// * The [`EngineIoHandler::on_shutdown`] hook is awaited while the sockets are still open
+    /// * All the sockets are then closed with the [`DisconnectReason::ClosingServer`] reason,
+    ///   once the packets emitted by the hook are sent
+    pub(crate) async fn shutdown(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("shutting down engine.io server");
+        self.state.set(ServerState::ShuttingDown);
+        if let Some(sweeper) = self.heartbeat_sweeper.get() {
+            sweeper.abort();
+        }
+        self.handler.on_shutdown().await;
+
+        for socket in self.sockets.values() {
+            socket.close_after_flush(DisconnectReason::ClosingServer);
         }
     }
 }
@@ -42,53 +126,313 @@ impl<H: EngineIoHandler> EngineIo<H> {
     /// Create a new engine.io session and a new socket and add it to the socket map
     pub(crate) fn create_session(
         self: &Arc<Self>,
+        sid: Sid,
         protocol: ProtocolVersion,
         transport: TransportType,
         req: Parts,
-        #[cfg(feature = "v3")] supports_binary: bool,
+        #[cfg(feature = "v3")] force_base64: bool,
     ) -> Arc<Socket<H::Data>> {
-        let engine = self.clone();
-        let close_fn = Box::new(move |sid, reason| engine.close_session(sid, reason));
+        // The socket only keeps a weak reference to the engine so that the engine is dropped with its last service
+        let engine = Arc::downgrade(self);
+        let close_fn = Box::new(move |sid, reason| {
+            if let Some(engine) = engine.upgrade() {
+                engine.close_session(sid, reason);
+            }
+        });
 
         let socket = Socket::new(
+            sid,
             protocol,
             transport,
             &self.config,
             req,
             close_fn,
             #[cfg(feature = "v3")]
-            supports_binary,
+            force_base64,
         );
         let socket = Arc::new(socket);
-        self.sockets
-            .write()
-            .unwrap()
-            .insert(socket.id, socket.clone());
+        self.sockets.insert(socket.id, socket.clone());
+        self.state.connections.fetch_add(1, Ordering::Relaxed);
+        if let Some(idle_timeout) = self.config.idle_timeout {
+            // The reaper is spawned with the first session because a runtime is not always available when the engine is created
+            self.idle_reaper.get_or_init(|| {
+                self.config
+                    .spawn(reap_idle_sockets(Arc::downgrade(self), idle_timeout));
+            });
+        }
+        if let Some(lifetime) = self.config.max_connection_lifetime {
+            self.lifetime_reaper.get_or_init(|| {
+                self.config.spawn(reap_expired_sockets(
+                    Arc::downgrade(&self.sockets),
+                    lifetime,
+                ));
+            });
+        }
+        if let Some(period) = self
+            .config
+            .heartbeat_sweep_interval
+            .filter(|_| self.config.heartbeat)
+        {
+            self.heartbeat_sweeper.get_or_init(|| {
+                let interval = self
+                    .config
+                    .adaptive_heartbeat
+                    .map_or(self.config.ping_interval, |a| a.max_interval);
+                let deadline = interval + self.config.ping_timeout + period;
+                self.config
+                    .spawn(sweep_dead_sockets(
+                        Arc::downgrade(&self.sockets),
+                        period,
+                        deadline,
+                    ))
+                    .abort_handle()
+            });
+        }
+        self.events
+            .send(|| ServerEvent::Connected { sid: socket.id });
         self.handler.on_connect(socket.clone());
         socket
     }
 
+    /// Notifies the handler and the event streams that a socket was upgraded to websocket
+    #[cfg(feature = "polling")]
+    pub(crate) fn on_upgrade(&self, socket: Arc<Socket<H::Data>>) {
+        self.events
+            .send(|| ServerEvent::Upgraded { sid: socket.id });
+        self.handler.on_upgrade(socket);
+    }
+
+    /// Notifies the event streams that the upgrade of a socket to websocket failed
+    #[cfg(feature = "polling")]
+    pub(crate) fn on_upgrade_failed(&self, sid: Sid, reason: UpgradeFailure) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={sid}] upgrade failed: {reason:?}");
+        self.events
+            .send(|| ServerEvent::UpgradeFailed { sid, reason });
+    }
+
+    /// Reports a transport error of a session to the event streams
+    pub(crate) fn report_error(&self, sid: Sid, err: &Error) {
+        self.events.send(|| ServerEvent::Error {
+            sid,
+            err: err.to_string(),
+        });
+    }
+
     /// Get a socket by its sid
     /// Clones the socket ref to avoid holding the lock
+    #[cfg(any(feature = "polling", test))]
     pub fn get_socket(&self, sid: Sid) -> Option<Arc<Socket<H::Data>>> {
-        self.sockets.read().unwrap().get(&sid).cloned()
+        self.sockets.get(&sid)
+    }
+
+    /// Get a socket by its sid, if it is not in this process the session store is queried to know
+    /// if the session is owned by another process ([`Error::ForeignSessionID`]) or is unknown ([`Error::UnknownSessionID`])
+    #[cfg(feature = "polling")]
+    pub(crate) async fn get_socket_or_err(&self, sid: Sid) -> Result<Arc<Socket<H::Data>>, Error> {
+        if let Some(socket) = self.get_socket(sid) {
+            return Ok(socket);
+        }
+        if self.config.session_store.exists(sid).await {
+            Err(Error::ForeignSessionID(sid))
+        } else {
+            Err(Error::UnknownSessionID(sid))
+        }
+    }
+
+    /// Refresh a session in the session store if it is still open
+    /// The store is called under the lock of the socket shard so that it can't be reordered with the removal of the session
+    pub(crate) fn touch_session(&self, socket: &Socket<H::Data>) -> Option<BoxFuture<'static, ()>> {
+        self.sockets.read_shard(&socket.id, |sockets| {
+            sockets.contains_key(&socket.id).then(|| {
+                let metadata = SessionMetadata {
+                    transport: socket.transport_type(),
+                    protocol: socket.protocol,
+                };
+                self.config.session_store.touch(socket.id, metadata)
+            })
+        })
     }
 
     /// Close an engine.io session by removing the socket from the socket map and closing the socket
     /// It should be the only way to close a session and to remove a socket from the socket map
     pub fn close_session(&self, sid: Sid, reason: DisconnectReason) {
-        let socket = self.sockets.write().unwrap().remove(&sid);
-        if let Some(socket) = socket {
+        let socket = self.sockets.write_shard(&sid, |sockets| {
+            sockets
+                .remove(&sid)
+                .map(|socket| (socket, self.config.session_store.remove(sid)))
+        });
+        if let Some((socket, remove_session)) = socket {
+            self.state.connections.fetch_sub(1, Ordering::Relaxed);
+            socket.mark_session_closed();
+            socket.end_binary_stream(&reason);
+            self.config.spawn(remove_session);
             // Try to close the internal channel if it is available
             // E.g. with polling transport the channel is not always locked so it is necessary to close it here
-            socket.internal_rx.try_lock().map(|mut rx| rx.close()).ok();
+            // The buffered packets are dropped so that the emitters waiting for a flush are notified
+            if let Ok(mut rx) = socket.internal_rx.try_lock() {
+                rx.close();
+                while rx.try_recv().is_ok() {}
+            }
             socket.abort_heartbeat();
+            self.events.send(|| ServerEvent::Disconnected {
+                sid,
+                reason: reason.clone(),
+            });
             self.handler.on_disconnect(socket, reason);
+            #[cfg(feature = "tracing")]
+            tracing::debug!("remaining sockets: {:?}", self.sockets.len());
+        }
+    }
+}
+
+impl<H: EngineIoHandler> Drop for EngineIo<H> {
+    /// Tears down the server once the last service, layer or transport task referencing it is dropped:
+    /// * The tasks of the sockets are stopped first, so that none of them observes a closed channel
+    /// * The internal channels of the sockets are then closed,
+    ///   the tasks waiting for [`Socket::closed`] exit and the buffered packets are dropped
+    /// * The socket map is finally cleared
+    ///
+    /// The [`EngineIoHandler`] is not notified, the sockets are silently discarded.
+    fn drop(&mut self) {
+        if let Some(sweeper) = self.heartbeat_sweeper.get() {
+            sweeper.abort();
+        }
+        let sockets = self.sockets.values();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            "tearing down engine.io server with {} sockets",
+            sockets.len()
+        );
+        for socket in &sockets {
+            socket.abort_heartbeat();
+        }
+        for socket in &sockets {
+            socket.mark_session_closed();
+            if let Ok(mut rx) = socket.internal_rx.try_lock() {
+                rx.close();
+                while rx.try_recv().is_ok() {}
+            }
+        }
+        self.sockets.clear();
+        self.state.connections.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Decrements the number of upgrading sessions of the [`Health`](crate::handler::Health) when dropped
+#[cfg(feature = "polling")]
+pub(crate) struct UpgradingGuard(Arc<SharedState>);
+
+#[cfg(feature = "polling")]
+impl Drop for UpgradingGuard {
+    fn drop(&mut self) {
+        self.0.upgrading.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Periodically close the sockets that did not receive any message during the `idle_timeout`.
+/// The task stops once the engine is dropped.
+///
+/// The [`EngineIoHandler::on_close_warning`] hook is called first, the socket is then closed once the grace period
+/// it returns elapses, unless a message is received in the meantime.
+async fn reap_idle_sockets<H: EngineIoHandler>(engine: Weak<EngineIo<H>>, idle_timeout: Duration) {
+    let period = (idle_timeout / 10).max(Duration::from_millis(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The idle sockets waiting for the end of their grace period
+    let mut warned = HashSet::new();
+    loop {
+        interval.tick().await;
+        let Some(engine) = engine.upgrade() else {
+            break;
+        };
+        let idle = engine
+            .sockets
+            .filter_values(|socket| socket.last_message_at().elapsed() >= idle_timeout);
+        // A socket that was active during its grace period is warned again the next time it is idle
+        warned.retain(|sid| idle.iter().any(|socket| socket.id == *sid));
+        for socket in idle {
+            if warned.contains(&socket.id) {
+                continue;
+            }
+            let grace = engine
+                .handler
+                .on_close_warning(socket.clone(), &DisconnectReason::IdleTimeout);
+            if grace.is_zero() {
+                close_idle_socket(&socket);
+                continue;
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={}] closing idle socket in {grace:?}", socket.id);
+            warned.insert(socket.id);
+            engine.config.spawn(async move {
+                tokio::time::sleep(grace).await;
+                if socket.last_message_at().elapsed() >= idle_timeout {
+                    close_idle_socket(&socket);
+                }
+            });
+        }
+    }
+}
+
+fn close_idle_socket<D: Default + Send + Sync + 'static>(socket: &Socket<D>) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!("[sid={}] closing idle socket", socket.id);
+    socket.close_with_reason(DisconnectReason::IdleTimeout, "idle timeout");
+}
+
+/// Periodically close the sockets that have been connected for more than `lifetime`.
+/// The task stops once the engine is dropped.
+async fn reap_expired_sockets<D>(sockets: Weak<SocketMap<Socket<D>>>, lifetime: Duration)
+where
+    D: Default + Send + Sync + 'static,
+{
+    let period = (lifetime / 10).max(Duration::from_millis(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(sockets) = sockets.upgrade() else {
+            break;
+        };
+        let expired = sockets.filter_values(|socket| socket.connected_at().elapsed() >= lifetime);
+        drop(sockets);
+        for socket in expired {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={}] closing socket exceeding its lifetime", socket.id);
+            socket.close_with_reason(
+                DisconnectReason::LifetimeExpired,
+                "connection lifetime expired",
+            );
+        }
+    }
+}
+
+/// Periodically close the sockets that were not seen alive during the `deadline`,
+/// in case their heartbeat task is stuck. The task stops once the engine is dropped.
+async fn sweep_dead_sockets<D>(
+    sockets: Weak<SocketMap<Socket<D>>>,
+    period: Duration,
+    deadline: Duration,
+) where
+    D: Default + Send + Sync + 'static,
+{
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(sockets) = sockets.upgrade() else {
+            break;
+        };
+        let dead = sockets.filter_values(|socket| socket.last_alive_at().elapsed() >= deadline);
+        drop(sockets);
+        for socket in dead {
             #[cfg(feature = "tracing")]
             tracing::debug!(
-                "remaining sockets: {:?}",
-                self.sockets.read().unwrap().len()
+                "[sid={}] closing socket missed by its heartbeat task",
+                socket.id
             );
+            socket.close(DisconnectReason::HeartbeatTimeout);
         }
     }
 }
@@ -102,6 +446,18 @@ mod tests {
     #[derive(Debug, Clone)]
     struct MockHandler;
 
+    #[derive(Debug, Default)]
+    struct ReasonHandler(std::sync::Mutex<Vec<(Sid, DisconnectReason)>>);
+    impl EngineIoHandler for ReasonHandler {
+        type Data = ();
+        fn on_connect(&self, _: Arc<Socket<()>>) {}
+        fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) {
+            self.0.lock().unwrap().push((socket.id, reason));
+        }
+        fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+        fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+    }
+
     impl EngineIoHandler for MockHandler {
         type Data = ();
 
@@ -124,20 +480,212 @@ mod tests {
         }
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn last_alive_at_follows_pongs() {
+        let engine = Arc::new(EngineIo::new(MockHandler, EngineIoConfig::default()));
+        let socket = engine.create_session(
+            Sid::new(),
+            ProtocolVersion::V4,
+            TransportType::Polling,
+            Request::<()>::default().into_parts().0,
+            #[cfg(feature = "v3")]
+            true,
+        );
+        let created = socket.last_alive_at();
+        socket
+            .clone()
+            .spawn_heartbeat(Duration::from_secs(10), Duration::from_secs(60));
+
+        // Pings are sent but not answered
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        assert_eq!(socket.last_alive_at(), created);
+
+        socket.recv_heartbeat(crate::packet::Packet::Pong).unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(socket.last_alive_at(), tokio::time::Instant::now());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_reaper() {
+        let config = EngineIoConfig::builder()
+            .idle_timeout(Duration::from_secs(60))
+            .build();
+        let engine = Arc::new(EngineIo::new(ReasonHandler::default(), config));
+        let create = || {
+            engine.create_session(
+                Sid::new(),
+                ProtocolVersion::V4,
+                TransportType::Polling,
+                Request::<()>::default().into_parts().0,
+                #[cfg(feature = "v3")]
+                true,
+            )
+        };
+        let heartbeats_only = create();
+        let chatting = create();
+
+        for _ in 0..4 {
+            tokio::time::sleep(Duration::from_secs(25)).await;
+            chatting.touch_message();
+            // Heartbeats are not considered as activity
+            heartbeats_only.heartbeat.notify_one();
+        }
+
+        assert!(engine.get_socket(heartbeats_only.id).is_none());
+        assert!(engine.get_socket(chatting.id).is_some());
+        assert_eq!(
+            *engine.handler.0.lock().unwrap(),
+            [(heartbeats_only.id, DisconnectReason::IdleTimeout)]
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct WarningHandler {
+        warned: std::sync::Mutex<Vec<Sid>>,
+        closed: std::sync::Mutex<Vec<Sid>>,
+    }
+    impl EngineIoHandler for WarningHandler {
+        type Data = ();
+        fn on_connect(&self, _: Arc<Socket<()>>) {}
+        fn on_disconnect(&self, socket: Arc<Socket<()>>, _: DisconnectReason) {
+            self.closed.lock().unwrap().push(socket.id);
+        }
+        fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+        fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+        fn on_close_warning(&self, socket: Arc<Socket<()>>, reason: &DisconnectReason) -> Duration {
+            assert_eq!(reason, &DisconnectReason::IdleTimeout);
+            self.warned.lock().unwrap().push(socket.id);
+            Duration::from_secs(20)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_reaper_grace_period() {
+        let config = EngineIoConfig::builder()
+            .idle_timeout(Duration::from_secs(60))
+            .build();
+        let engine = Arc::new(EngineIo::new(WarningHandler::default(), config));
+        let create = || {
+            engine.create_session(
+                Sid::new(),
+                ProtocolVersion::V4,
+                TransportType::Polling,
+                Request::<()>::default().into_parts().0,
+                #[cfg(feature = "v3")]
+                true,
+            )
+        };
+        let idle = create();
+        let resumed = create();
+
+        // Both sockets are warned once when they become idle
+        tokio::time::sleep(Duration::from_secs(66)).await;
+        let mut warned = engine.handler.warned.lock().unwrap().clone();
+        warned.sort();
+        let mut expected = vec![idle.id, resumed.id];
+        expected.sort();
+        assert_eq!(warned, expected);
+        resumed.touch_message();
+
+        tokio::time::sleep(Duration::from_secs(13)).await;
+        assert!(engine.get_socket(idle.id).is_some());
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(engine.get_socket(idle.id).is_none());
+        // The socket active during its grace period is not closed
+        assert!(engine.get_socket(resumed.id).is_some());
+        assert_eq!(*engine.handler.closed.lock().unwrap(), [idle.id]);
+
+        // It is warned again once it is idle again
+        tokio::time::sleep(Duration::from_secs(50)).await;
+        assert_eq!(engine.handler.warned.lock().unwrap().len(), 3);
+        assert!(engine.get_socket(resumed.id).is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lifetime_reaper() {
+        let config = EngineIoConfig::builder()
+            .max_connection_lifetime(Duration::from_secs(60))
+            .build();
+        let engine = Arc::new(EngineIo::new(ReasonHandler::default(), config));
+        let create = || {
+            engine.create_session(
+                Sid::new(),
+                ProtocolVersion::V4,
+                TransportType::Polling,
+                Request::<()>::default().into_parts().0,
+                #[cfg(feature = "v3")]
+                true,
+            )
+        };
+        let old = create();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        let young = create();
+        // Activity doesn't extend the lifetime
+        old.touch_message();
+
+        tokio::time::sleep(Duration::from_secs(40)).await;
+        assert!(engine.get_socket(old.id).is_none());
+        assert!(engine.get_socket(young.id).is_some());
+        assert_eq!(
+            *engine.handler.0.lock().unwrap(),
+            [(old.id, DisconnectReason::LifetimeExpired)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_sweeper() {
+        let config = EngineIoConfig::builder()
+            .ping_interval(Duration::from_secs(10))
+            .ping_timeout(Duration::from_secs(5))
+            .heartbeat_sweep_interval(Duration::from_secs(5))
+            .build();
+        let engine = Arc::new(EngineIo::new(ReasonHandler::default(), config));
+        let create = || {
+            engine.create_session(
+                Sid::new(),
+                ProtocolVersion::V4,
+                TransportType::Polling,
+                Request::<()>::default().into_parts().0,
+                #[cfg(feature = "v3")]
+                true,
+            )
+        };
+        // No heartbeat task is spawned, as if it was stuck
+        let stuck = create();
+        let alive = create();
+
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_secs(9)).await;
+            alive.touch_transport();
+        }
+
+        assert!(engine.get_socket(stuck.id).is_none());
+        assert!(engine.get_socket(alive.id).is_some());
+        assert_eq!(
+            *engine.handler.0.lock().unwrap(),
+            [(stuck.id, DisconnectReason::HeartbeatTimeout)]
+        );
+
+        engine.shutdown().await;
+        tokio::task::yield_now().await;
+        assert!(engine.heartbeat_sweeper.get().unwrap().is_finished());
+    }
+
     #[tokio::test]
     async fn create_session() {
         let config = EngineIoConfig::default();
         let engine = Arc::new(EngineIo::new(MockHandler, config));
         let socket = engine.create_session(
+            Sid::new(),
             ProtocolVersion::V4,
             TransportType::Polling,
             Request::<()>::default().into_parts().0,
             #[cfg(feature = "v3")]
             true,
         );
-        assert_eq!(engine.sockets.read().unwrap().len(), 1);
+        assert_eq!(engine.sockets.len(), 1);
         assert_eq!(socket.protocol, ProtocolVersion::V4);
-        assert!(socket.is_http());
+        assert_eq!(socket.transport_type(), TransportType::Polling);
     }
 
     #[tokio::test]
@@ -145,15 +693,16 @@ mod tests {
         let config = EngineIoConfig::default();
         let engine = Arc::new(EngineIo::new(MockHandler, config));
         let socket = engine.create_session(
+            Sid::new(),
             ProtocolVersion::V4,
             TransportType::Polling,
             Request::<()>::default().into_parts().0,
             #[cfg(feature = "v3")]
             true,
         );
-        assert_eq!(engine.sockets.read().unwrap().len(), 1);
+        assert_eq!(engine.sockets.len(), 1);
         engine.close_session(socket.id, DisconnectReason::TransportClose);
-        assert_eq!(engine.sockets.read().unwrap().len(), 0);
+        assert_eq!(engine.sockets.len(), 0);
     }
 
     #[tokio::test]
@@ -161,15 +710,16 @@ mod tests {
         let config = EngineIoConfig::default();
         let engine = Arc::new(EngineIo::new(MockHandler, config));
         let socket = engine.create_session(
+            Sid::new(),
             ProtocolVersion::V4,
             TransportType::Polling,
             Request::<()>::default().into_parts().0,
             #[cfg(feature = "v3")]
             true,
         );
-        assert_eq!(engine.sockets.read().unwrap().len(), 1);
+        assert_eq!(engine.sockets.len(), 1);
         let socket = engine.get_socket(socket.id).unwrap();
         assert_eq!(socket.protocol, ProtocolVersion::V4);
-        assert!(socket.is_http());
+        assert_eq!(socket.transport_type(), TransportType::Polling);
     }
 }
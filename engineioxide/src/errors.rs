@@ -1,10 +1,11 @@
 use http::{Response, StatusCode};
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, error::TrySendError};
 use tokio_tungstenite::tungstenite;
 
 use crate::body::ResponseBody;
-use crate::packet::Packet;
+use crate::packet::{Packet, PacketError};
 use crate::sid::Sid;
+use crate::socket::PacketBuf;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -23,21 +24,33 @@ pub enum Error {
     #[error("http error: {0:?}")]
     Http(#[from] http::Error),
     #[error("internal channel error: {0:?}")]
-    SendChannel(#[from] mpsc::error::TrySendError<Packet>),
+    SendChannel(#[from] TrySendError<Packet>),
     #[error("internal channel error: {0:?}")]
     RecvChannel(#[from] mpsc::error::TryRecvError),
     #[error("heartbeat timeout")]
     HeartbeatTimeout,
     #[error("upgrade error")]
     Upgrade,
+    #[error("upgrade timeout")]
+    UpgradeTimeout,
+    #[error("upgrade aborted by the client")]
+    UpgradeAborted,
+    #[error("duplicate upgrade")]
+    DuplicateUpgrade,
     #[error("aborted connection")]
     Aborted,
 
     #[error("http error response: {0:?}")]
     HttpErrorResponse(StatusCode),
+    #[error("server not accepting new sessions, retry after {0:?}")]
+    Unavailable(std::time::Duration),
+    #[error("handshake rate limit exceeded, retry after {0:?}")]
+    TooManyRequests(std::time::Duration),
 
     #[error("unknown session id")]
     UnknownSessionID(Sid),
+    #[error("session owned by another server")]
+    ForeignSessionID(Sid),
     #[error("transport mismatch")]
     TransportMismatch,
     #[error("payload too large")]
@@ -49,6 +62,28 @@ pub enum Error {
     InvalidPacketType(Option<char>),
 }
 
+impl From<PacketError> for Error {
+    fn from(err: PacketError) -> Self {
+        match err {
+            PacketError::InvalidPacketType(c) => Error::InvalidPacketType(c),
+            PacketError::Base64(e) => Error::Base64(e),
+            PacketError::Open(e) => Error::Serialize(e),
+        }
+    }
+}
+
+/// Keeps the channel error of a control packet sent through the priority lane
+impl From<TrySendError<PacketBuf>> for Error {
+    fn from(err: TrySendError<PacketBuf>) -> Self {
+        // The control packets are sent alone
+        let err = match err {
+            TrySendError::Full(mut p) => TrySendError::Full(p.pop().unwrap_or(Packet::Noop)),
+            TrySendError::Closed(mut p) => TrySendError::Closed(p.pop().unwrap_or(Packet::Noop)),
+        };
+        Error::SendChannel(err)
+    }
+}
+
 /// Convert an error into an http response
 /// If it is a known error, return the appropriate http status code
 /// Otherwise, return a 500
@@ -66,6 +101,19 @@ impl<B> From<Error> for Response<ResponseBody<B>> {
                 .status(code)
                 .body(ResponseBody::empty_response())
                 .unwrap(),
+            Error::Unavailable(retry_after) => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header("Retry-After", retry_after.as_secs().max(1))
+                .body(ResponseBody::empty_response())
+                .unwrap(),
+            Error::TooManyRequests(retry_after) => Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header(
+                    "Retry-After",
+                    retry_after.as_secs_f64().ceil().max(1.0) as u64,
+                )
+                .body(ResponseBody::empty_response())
+                .unwrap(),
             Error::BadPacket(_) | Error::InvalidPacketLength | Error::InvalidPacketType(_) => {
                 Response::builder()
                     .status(400)
@@ -77,7 +125,7 @@ impl<B> From<Error> for Response<ResponseBody<B>> {
                 .body(ResponseBody::empty_response())
                 .unwrap(),
 
-            Error::UnknownSessionID(_) => {
+            Error::UnknownSessionID(_) | Error::ForeignSessionID(_) => {
                 conn_err_resp("{\"code\":\"1\",\"message\":\"Session ID unknown\"}")
             }
 
@@ -96,3 +144,34 @@ impl<B> From<Error> for Response<ResponseBody<B>> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use smallvec::smallvec;
+
+    use super::*;
+
+    #[test]
+    fn source_chaining() {
+        let err = Error::from(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "pipe"));
+        let source = err.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(source.unwrap().kind(), std::io::ErrorKind::BrokenPipe);
+
+        let err = Error::from(tungstenite::Error::ConnectionClosed);
+        let source = err.source().unwrap().downcast_ref::<tungstenite::Error>();
+        assert!(matches!(source, Some(tungstenite::Error::ConnectionClosed)));
+
+        let err = Error::from(serde_json::from_str::<u32>("{").unwrap_err());
+        assert!(err.source().unwrap().is::<serde_json::Error>());
+
+        // The failure to send a control packet keeps the packet
+        let buf: PacketBuf = smallvec![Packet::Ping].into();
+        let err = Error::from(TrySendError::Full(buf));
+        let source = err.source().unwrap().downcast_ref::<TrySendError<Packet>>();
+        assert!(matches!(source, Some(TrySendError::Full(Packet::Ping))));
+
+        assert!(Error::HeartbeatTimeout.source().is_none());
+    }
+}
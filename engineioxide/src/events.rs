@@ -0,0 +1,212 @@
+//! ## A stream of the lifecycle events of all the sockets of the server
+//!
+//! The [`EventStream`] returned by [`EngineIoHandle::events`](crate::handler::EngineIoHandle::events)
+//! yields a [`ServerEvent`] for each connection, disconnection, upgrade, failed upgrade and transport error,
+//! for example to log or audit the connections without implementing the [`EngineIoHandler`](crate::handler::EngineIoHandler) hooks.
+//!
+//! It is backed by a broadcast channel: each stream buffers up to
+//! [`event_stream_capacity`](crate::config::EngineIoConfig::event_stream_capacity) events.
+//! A stream that lags behind misses the oldest events, they are counted by [`EventStream::lagged`].
+//! Slow consumers never slow down the server. The stream ends once the server is dropped.
+//!
+//! #### Example :
+//! ```rust
+//! # use engineioxide::service::EngineIoService;
+//! # use engineioxide::handler::EngineIoHandler;
+//! # use engineioxide::events::ServerEvent;
+//! # use engineioxide::{Socket, DisconnectReason};
+//! # use futures::StreamExt;
+//! # use std::sync::Arc;
+//! # #[derive(Debug)]
+//! # struct MyHandler;
+//! # impl EngineIoHandler for MyHandler {
+//! #     type Data = ();
+//! #     fn on_connect(&self, socket: Arc<Socket<()>>) { }
+//! #     fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) { }
+//! #     fn on_message(&self, msg: String, socket: Arc<Socket<()>>) { }
+//! #     fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) { }
+//! # }
+//! # async fn doc() {
+//! let svc = EngineIoService::new(MyHandler);
+//! let mut events = svc.handle().events();
+//! tokio::spawn(async move {
+//!     while let Some(event) = events.next().await {
+//!         match event {
+//!             ServerEvent::Connected { sid } => println!("{sid} connected"),
+//!             ServerEvent::Disconnected { sid, reason } => println!("{sid} disconnected: {reason:?}"),
+//!             _ => {}
+//!         }
+//!     }
+//! });
+//! # }
+//! ```
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures::{stream::BoxStream, Stream, StreamExt};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite;
+
+use crate::{errors::Error, sid::Sid, socket::DisconnectReason};
+
+/// A lifecycle event of a socket, received through an [`EventStream`].
+///
+/// Each event is sent once the corresponding state change is visible,
+/// e.g. a socket is already available with [`EngineIoHandle::get_socket`](crate::handler::EngineIoHandle::get_socket)
+/// when its [`Connected`](ServerEvent::Connected) event is received.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    /// A new session was opened
+    Connected {
+        /// The id of the socket
+        sid: Sid,
+    },
+    /// A session was closed
+    Disconnected {
+        /// The id of the socket
+        sid: Sid,
+        /// The reason of the disconnection
+        reason: DisconnectReason,
+    },
+    /// A polling session was upgraded to websocket
+    Upgraded {
+        /// The id of the socket
+        sid: Sid,
+    },
+    /// An upgrade of a polling session to websocket failed, the websocket connection was dropped
+    /// and the session stays on polling. It follows the [`Error`](ServerEvent::Error) event of the failure.
+    UpgradeFailed {
+        /// The id of the socket
+        sid: Sid,
+        /// The reason of the failure
+        reason: UpgradeFailure,
+    },
+    /// The transport of a session failed, e.g. with an invalid packet or a failed upgrade.
+    /// It is followed by a [`Disconnected`](ServerEvent::Disconnected) event if the session is closed because of it.
+    Error {
+        /// The id of the socket
+        sid: Sid,
+        /// A description of the error
+        err: String,
+    },
+}
+
+/// The reason why an upgrade from polling to websocket failed, see [`ServerEvent::UpgradeFailed`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpgradeFailure {
+    /// The probe exchange and the upgrade packet were not completed
+    /// within the [`upgrade_timeout`](crate::config::EngineIoConfig::upgrade_timeout)
+    ProbeTimeout,
+    /// The websocket connection failed during the upgrade
+    TransportError,
+    /// The client closed the websocket connection before completing the upgrade
+    ClientAbort,
+    /// The client sent an unexpected packet during the upgrade
+    BadPacket,
+}
+
+/// Convert an [`Error`] returned by an upgrade to an [`UpgradeFailure`] if possible
+/// If the error cannot be converted, the upgrade was rejected without being attempted (e.g. a duplicate upgrade)
+impl From<&Error> for Option<UpgradeFailure> {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            UpgradeTimeout => Some(UpgradeFailure::ProbeTimeout),
+            UpgradeAborted | WsTransport(tungstenite::Error::ConnectionClosed) => {
+                Some(UpgradeFailure::ClientAbort)
+            }
+            WsTransport(_) | Io(_) => Some(UpgradeFailure::TransportError),
+            Upgrade | BadPacket(_) | Serialize(_) | StrUtf8(_) | InvalidPacketLength
+            | InvalidPacketType(_) => Some(UpgradeFailure::BadPacket),
+            _ => None,
+        }
+    }
+}
+
+/// The sending half of the event streams, shared by the server and its handles.
+#[derive(Debug)]
+pub(crate) struct EventSender(broadcast::Sender<ServerEvent>);
+
+impl EventSender {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self(broadcast::channel(capacity).0)
+    }
+
+    pub(crate) fn subscribe(&self) -> EventStream {
+        EventStream::new(self.0.subscribe())
+    }
+
+    /// Sends the event built by `event` if there is at least one subscriber.
+    /// Nothing is built otherwise.
+    pub(crate) fn send(&self, event: impl FnOnce() -> ServerEvent) {
+        if self.0.receiver_count() > 0 {
+            // The only error is when every subscriber was dropped in the meantime
+            self.0.send(event()).ok();
+        }
+    }
+}
+
+/// A stream of [`ServerEvent`]s, created with [`EngineIoHandle::events`](crate::handler::EngineIoHandle::events).
+/// See the [module level documentation](self) for more details.
+pub struct EventStream {
+    inner: BoxStream<'static, ServerEvent>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl EventStream {
+    fn new(rx: broadcast::Receiver<ServerEvent>) -> Self {
+        let lagged = Arc::new(AtomicU64::new(0));
+        let counter = lagged.clone();
+        let inner = futures::stream::unfold(rx, move |mut rx| {
+            let counter = counter.clone();
+            async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => return Some((event, rx)),
+                        Err(RecvError::Lagged(n)) => {
+                            counter.fetch_add(n, Ordering::Relaxed);
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })
+        .boxed();
+        Self { inner, lagged }
+    }
+
+    /// An empty stream, for the handles of a server that is already dropped
+    pub(crate) fn closed() -> Self {
+        Self {
+            inner: futures::stream::empty().boxed(),
+            lagged: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Returns the total number of events missed by this stream because it lagged behind
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for EventStream {
+    type Item = ServerEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl std::fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventStream")
+            .field("lagged", &self.lagged())
+            .finish()
+    }
+}
@@ -38,9 +38,26 @@
 //! // Create an engine io service with the given handler
 //! let svc = EngineIoService::new(MyHandler::default());
 //! ```
-use std::sync::Arc;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
 
-use crate::socket::{DisconnectReason, Socket};
+use tokio::sync::mpsc::error::TrySendError;
+
+use crate::{
+    engine::SocketMap,
+    events::{EventSender, EventStream},
+    sid::Sid,
+    socket::{DisconnectReason, Socket},
+};
+
+#[cfg(feature = "polling")]
+use crate::service::TransportType;
 
 /// The [`EngineIoHandler`] trait can be implemented on any struct to handle socket events
 ///
@@ -53,13 +70,59 @@ pub trait EngineIoHandler: std::fmt::Debug + Send + Sync + 'static {
     fn on_connect(&self, socket: Arc<Socket<Self::Data>>);
 
     /// Called when a socket is disconnected with a [`DisconnectReason`]
+    ///
+    /// When the client sends a close packet, it is called as soon as the packet is received
+    /// with [`DisconnectReason::TransportClose`], so upper layers can tear down their own state from it.
     fn on_disconnect(&self, socket: Arc<Socket<Self::Data>>, reason: DisconnectReason);
 
     /// Called when a message is received from the client.
+    ///
+    /// An empty message (a `4` packet without payload) is valid and dispatched with an empty string,
+    /// it is up to the handler to give it a meaning.
     fn on_message(&self, msg: String, socket: Arc<Socket<Self::Data>>);
 
     /// Called when a binary message is received from the client.
+    ///
+    /// It is not called while a [`Socket::binary_stream`] of the socket is alive,
+    /// the binary messages are yielded by the stream instead.
     fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<Self::Data>>);
+
+    /// Called once when a socket is upgraded from polling to websocket.
+    ///
+    /// Duplicate upgrade attempts (e.g. from a retrying client) are rejected and don't trigger it.
+    fn on_upgrade(&self, socket: Arc<Socket<Self::Data>>) {
+        let _ = socket;
+    }
+
+    /// Called once when the engine.io service (or layer) is created, with an [`EngineIoHandle`] to the server.
+    /// It can be used to keep a handle to the server and to spawn background tasks.
+    fn on_start(&self, io: EngineIoHandle<Self::Data>) {
+        let _ = io;
+    }
+
+    /// Called during the graceful shutdown of the server, before the sockets are closed.
+    /// It can be used to flush the state of the handler or to notify the clients.
+    ///
+    /// The graceful shutdown is started with [`EngineIoService::shutdown`] or [`EngineIoLayer::shutdown`].
+    ///
+    /// [`EngineIoService::shutdown`]: crate::service::EngineIoService::shutdown
+    /// [`EngineIoLayer::shutdown`]: crate::layer::EngineIoLayer::shutdown
+    fn on_shutdown(&self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Called before the server closes a socket for an operational reason, such as an [idle timeout](crate::config::EngineIoConfig::idle_timeout).
+    /// It can be used to warn the client, the socket is closed once the returned grace period elapses.
+    ///
+    /// Defaults to closing the socket right away.
+    fn on_close_warning(
+        &self,
+        socket: Arc<Socket<Self::Data>>,
+        reason: &DisconnectReason,
+    ) -> Duration {
+        let _ = (socket, reason);
+        Duration::ZERO
+    }
 }
 
 impl<T: EngineIoHandler> EngineIoHandler for Arc<T> {
@@ -80,4 +143,349 @@ impl<T: EngineIoHandler> EngineIoHandler for Arc<T> {
     fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<Self::Data>>) {
         (**self).on_binary(data, socket)
     }
+
+    fn on_upgrade(&self, socket: Arc<Socket<Self::Data>>) {
+        (**self).on_upgrade(socket)
+    }
+
+    fn on_start(&self, io: EngineIoHandle<Self::Data>) {
+        (**self).on_start(io)
+    }
+
+    fn on_shutdown(&self) -> impl Future<Output = ()> + Send {
+        (**self).on_shutdown()
+    }
+
+    fn on_close_warning(
+        &self,
+        socket: Arc<Socket<Self::Data>>,
+        reason: &DisconnectReason,
+    ) -> Duration {
+        (**self).on_close_warning(socket, reason)
+    }
+}
+
+/// The state of an engine.io server regarding new sessions, see [`EngineIoHandle::state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServerState {
+    /// New sessions are accepted
+    Accepting,
+    /// New sessions are rejected with a `503 Service Unavailable` response,
+    /// the existing sockets keep working
+    Paused,
+    /// The graceful shutdown of the server is started
+    ShuttingDown,
+}
+
+/// A snapshot of the health of a server, e.g. to answer the readiness probes of an orchestrator.
+///
+/// It is read from atomic counters, so it can be polled frequently without contending with the connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Health {
+    /// The number of open sessions
+    pub connections: usize,
+    /// The number of sessions in the middle of an upgrade from polling to websocket,
+    /// a number that keeps growing is the sign of stuck upgrades
+    pub upgrading: usize,
+    /// If the server is draining, see [`EngineIoHandle::set_draining`]
+    pub draining: bool,
+    /// If new sessions are accepted, false while the server is draining or shutting down
+    pub accepting: bool,
+}
+
+/// The [`ServerState`] of a server and its connection counters, shared between the server and its handles
+#[derive(Debug)]
+pub(crate) struct SharedState {
+    state: AtomicU8,
+    /// The number of open sessions
+    pub(crate) connections: AtomicUsize,
+    /// The number of sessions upgrading to websocket
+    pub(crate) upgrading: AtomicUsize,
+}
+
+impl SharedState {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: AtomicU8::new(ServerState::Accepting as u8),
+            connections: AtomicUsize::new(0),
+            upgrading: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn health(&self) -> Health {
+        let state = self.get();
+        Health {
+            connections: self.connections.load(Ordering::Relaxed),
+            upgrading: self.upgrading.load(Ordering::Relaxed),
+            draining: state == ServerState::Paused,
+            accepting: state == ServerState::Accepting,
+        }
+    }
+
+    pub(crate) fn get(&self) -> ServerState {
+        match self.state.load(Ordering::Acquire) {
+            0 => ServerState::Accepting,
+            1 => ServerState::Paused,
+            _ => ServerState::ShuttingDown,
+        }
+    }
+
+    pub(crate) fn set(&self, state: ServerState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    /// Switches from `from` to `to`, the shutdown state is never left
+    fn transition(&self, from: ServerState, to: ServerState) {
+        let _ =
+            self.state
+                .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+/// A handle to an engine.io server, given to the [`EngineIoHandler::on_start`] hook.
+///
+/// It doesn't keep the server alive: once the server is dropped, it behaves as if there was no socket.
+pub struct EngineIoHandle<D: Default + Send + Sync + 'static> {
+    sockets: Weak<SocketMap<Socket<D>>>,
+    state: Arc<SharedState>,
+    events: Weak<EventSender>,
+}
+
+impl<D: Default + Send + Sync + 'static> EngineIoHandle<D> {
+    pub(crate) fn new(
+        sockets: Weak<SocketMap<Socket<D>>>,
+        state: Arc<SharedState>,
+        events: Weak<EventSender>,
+    ) -> Self {
+        Self {
+            sockets,
+            state,
+            events,
+        }
+    }
+
+    /// Subscribes to the lifecycle [`ServerEvent`](crate::events::ServerEvent)s of all the sockets:
+    /// connections, disconnections, upgrades and transport errors.
+    /// Only the events happening after the subscription are received.
+    ///
+    /// A stream that lags behind misses the oldest events, see [`EventStream::lagged`].
+    /// The stream ends once the server is dropped.
+    pub fn events(&self) -> EventStream {
+        match self.events.upgrade() {
+            Some(events) => events.subscribe(),
+            None => EventStream::closed(),
+        }
+    }
+
+    /// Get the current [`ServerState`] of the server
+    pub fn state(&self) -> ServerState {
+        self.state.get()
+    }
+
+    /// Returns the [`Health`] of the server: its number of sessions and upgrades in progress,
+    /// and whether it accepts new sessions. It only reads atomic counters.
+    pub fn health(&self) -> Health {
+        self.state.health()
+    }
+
+    /// Stop accepting new sessions: polling and websocket handshakes are rejected with a
+    /// `503 Service Unavailable` response and a `Retry-After` header
+    /// (see [`EngineIoConfig::retry_after`](crate::config::EngineIoConfig::retry_after)).
+    ///
+    /// The existing sockets are not affected, polling sessions can still be upgraded to websocket.
+    /// It has no effect once the shutdown of the server is started.
+    pub fn pause_accepting(&self) {
+        self.state
+            .transition(ServerState::Accepting, ServerState::Paused);
+    }
+
+    /// Accept new sessions again after a call to [`pause_accepting`](Self::pause_accepting).
+    /// It has no effect once the shutdown of the server is started.
+    pub fn resume_accepting(&self) {
+        self.state
+            .transition(ServerState::Paused, ServerState::Accepting);
+    }
+
+    /// Puts the server in draining mode, e.g. to remove this node from a load balancer without downtime.
+    /// It is the same as [`pause_accepting`](Self::pause_accepting): the handshakes of new sessions are rejected
+    /// with a `503 Service Unavailable` response so that the load balancer routes them elsewhere,
+    /// while the existing sockets keep working until they disconnect.
+    /// The remaining sockets can then be closed with [`close_all`](Self::close_all) to finish the drain.
+    ///
+    /// `set_draining(false)` accepts new sessions again.
+    /// It has no effect once the shutdown of the server is started.
+    pub fn set_draining(&self, draining: bool) {
+        if draining {
+            self.pause_accepting();
+        } else {
+            self.resume_accepting();
+        }
+    }
+
+    /// Returns true if the server is draining, see [`set_draining`](Self::set_draining).
+    pub fn is_draining(&self) -> bool {
+        self.state() == ServerState::Paused
+    }
+
+    /// Closes all the sockets with the [`DisconnectReason::ClosingServer`] reason,
+    /// once the packets already buffered for them are sent. The [`ServerState`] is not changed,
+    /// a draining server keeps rejecting new sessions.
+    ///
+    /// Returns the number of closed sockets.
+    pub fn close_all(&self) -> usize {
+        let sockets = self.sockets();
+        for socket in &sockets {
+            socket.close_after_flush(DisconnectReason::ClosingServer);
+        }
+        sockets.len()
+    }
+
+    /// Pushes the clients still on the polling transport to upgrade to websocket, e.g. to migrate
+    /// them off polling during an incident.
+    ///
+    /// A noop packet is sent to each polling socket to release its pending polling request,
+    /// so that the client polls again and gets a chance to upgrade. The sockets still on polling
+    /// after the `grace` are closed with the [`DisconnectReason::UpgradeRequired`] reason,
+    /// which the clients handle as a closed transport: they reconnect.
+    ///
+    /// Returns the number of sockets upgraded during the grace and the number of closed ones.
+    /// The sockets that disconnected by themselves in the meantime are not counted.
+    #[cfg(feature = "polling")]
+    pub async fn force_upgrade_or_close(&self, grace: Duration) -> UpgradeReport {
+        let polling: Vec<_> = self
+            .sockets()
+            .into_iter()
+            .filter(|socket| socket.transport_type() == TransportType::Polling)
+            .collect();
+        for socket in &polling {
+            socket.send_priority(crate::packet::Packet::Noop).ok();
+        }
+        tokio::time::sleep(grace).await;
+
+        let mut report = UpgradeReport::default();
+        for socket in polling {
+            if socket.is_closed() {
+                continue;
+            }
+            if socket.transport_type() == TransportType::Websocket {
+                report.upgraded += 1;
+            } else {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("[sid={}] closing socket still on polling", socket.id);
+                socket.close(DisconnectReason::UpgradeRequired);
+                report.closed += 1;
+            }
+        }
+        report
+    }
+
+    /// Get a socket by its sid
+    pub fn get_socket(&self, sid: Sid) -> Option<Arc<Socket<D>>> {
+        self.sockets.upgrade()?.get(&sid)
+    }
+
+    /// Get all the sockets connected to the server
+    pub fn sockets(&self) -> Vec<Arc<Socket<D>>> {
+        match self.sockets.upgrade() {
+            Some(sockets) => sockets.values(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Emit a message to all the sockets connected to the server.
+    ///
+    /// Returns the ids of the sockets to which the message could not be sent (closed or full buffer).
+    pub fn broadcast(&self, msg: String) -> Result<(), Vec<Sid>> {
+        let errors: Vec<_> = self
+            .sockets()
+            .into_iter()
+            .filter(|socket| socket.emit(msg.clone()).is_err())
+            .map(|socket| socket.id)
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Emit a message to each of the given sockets, e.g. to notify a list of users.
+    ///
+    /// The sockets are resolved at once, locking each shard of the socket map at most once,
+    /// instead of once per sid with [`get_socket`](Self::get_socket).
+    ///
+    /// Returns the result of the emit for each sid, in the same order.
+    /// The sids not connected to this server get an [`EmitToError::UnknownSid`] error.
+    pub fn emit_to_many(&self, sids: &[Sid], msg: String) -> Vec<(Sid, Result<(), EmitToError>)> {
+        let sockets = match self.sockets.upgrade() {
+            Some(sockets) => sockets.get_many(sids),
+            None => vec![None; sids.len()],
+        };
+        sids.iter()
+            .zip(sockets)
+            .map(|(sid, socket)| {
+                let res = match socket {
+                    Some(socket) => socket.emit(msg.clone()).map_err(EmitToError::from),
+                    None => Err(EmitToError::UnknownSid),
+                };
+                (*sid, res)
+            })
+            .collect()
+    }
+
+    /// Emit a binary message to all the sockets connected to the server.
+    ///
+    /// Returns the ids of the sockets to which the message could not be sent (closed or full buffer).
+    pub fn broadcast_binary(&self, data: Vec<u8>) -> Result<(), Vec<Sid>> {
+        let errors: Vec<_> = self
+            .sockets()
+            .into_iter()
+            .filter(|socket| socket.emit_binary(data.clone()).is_err())
+            .map(|socket| socket.id)
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The outcome of [`EngineIoHandle::force_upgrade_or_close`] for the sockets that were on polling
+#[cfg(feature = "polling")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpgradeReport {
+    /// The number of sockets upgraded to websocket during the grace
+    pub upgraded: usize,
+    /// The number of sockets closed because they were still on polling
+    pub closed: usize,
+}
+
+/// Error returned for each socket by [`EngineIoHandle::emit_to_many`]
+#[derive(thiserror::Error, Debug)]
+pub enum EmitToError {
+    /// No socket with this id is connected to the server
+    #[error("unknown session id")]
+    UnknownSid,
+    /// The message could not be buffered (closed socket or full buffer), the original message is returned
+    #[error("error buffering the message: {0}")]
+    Send(#[from] TrySendError<String>),
+}
+
+impl<D: Default + Send + Sync + 'static> Clone for EngineIoHandle<D> {
+    fn clone(&self) -> Self {
+        Self {
+            sockets: self.sockets.clone(),
+            state: self.state.clone(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+impl<D: Default + Send + Sync + 'static> std::fmt::Debug for EngineIoHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EngineIoHandle")
+            .field("state", &self.state())
+            .finish()
+    }
 }
@@ -25,38 +25,70 @@
 //!     .layer(layer);
 //! // Spawn the axum server
 //! ```
+use std::sync::Arc;
+
 use tower::Layer;
 
-use crate::{config::EngineIoConfig, handler::EngineIoHandler, service::EngineIoService};
+use crate::{
+    config::EngineIoConfig,
+    engine::EngineIo,
+    handler::{EngineIoHandle, EngineIoHandler},
+    service::EngineIoService,
+};
 
 /// A tower [`Layer`] for engine.io so it can be used as a middleware
-#[derive(Debug, Clone)]
+///
+/// The engine.io server is created with the layer, all the services created from it share the same server.
+/// The handler doesn't need to be [`Clone`], an [`Arc<H>`] can also be used to keep a reference to it.
 pub struct EngineIoLayer<H: EngineIoHandler> {
-    config: EngineIoConfig,
-    handler: H,
+    engine: Arc<EngineIo<H>>,
 }
 
 impl<H: EngineIoHandler> EngineIoLayer<H> {
     /// Create a new [`EngineIoLayer`] with a given [`Handler`](crate::handler::EngineIoHandler)
     /// and a default [`EngineIoConfig`]
     pub fn new(handler: H) -> Self {
-        Self {
-            config: EngineIoConfig::default(),
-            handler,
-        }
+        Self::from_config(handler, EngineIoConfig::default())
     }
 
     /// Create a new [`EngineIoLayer`] with a given [`Handler`](crate::handler::EngineIoHandler)
     /// and a custom [`EngineIoConfig`]
     pub fn from_config(handler: H, config: EngineIoConfig) -> Self {
-        Self { config, handler }
+        Self {
+            engine: EngineIo::start(handler, config),
+        }
+    }
+
+    /// Get a [`EngineIoHandle`] to the server of this layer.
+    pub fn handle(&self) -> EngineIoHandle<H::Data> {
+        self.engine.handle()
+    }
+
+    /// Gracefully shutdown the server of this layer.
+    /// See [`EngineIoService::shutdown`] for more details.
+    pub async fn shutdown(&self) {
+        self.engine.shutdown().await
+    }
+}
+
+impl<H: EngineIoHandler> Clone for EngineIoLayer<H> {
+    fn clone(&self) -> Self {
+        Self {
+            engine: self.engine.clone(),
+        }
+    }
+}
+
+impl<H: EngineIoHandler> std::fmt::Debug for EngineIoLayer<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EngineIoLayer").finish()
     }
 }
 
-impl<S: Clone, H: EngineIoHandler + Clone> Layer<S> for EngineIoLayer<H> {
+impl<S: Clone, H: EngineIoHandler> Layer<S> for EngineIoLayer<H> {
     type Service = EngineIoService<H, S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        EngineIoService::with_config_inner(inner, self.handler.clone(), self.config.clone())
+        EngineIoService::from_engine(inner, self.engine.clone())
     }
 }
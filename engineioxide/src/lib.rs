@@ -38,16 +38,24 @@ pub use socket::{DisconnectReason, Socket};
 #[cfg(feature = "test-utils")]
 pub use packet::*;
 
+pub mod channel;
 pub mod config;
+pub mod events;
 pub mod handler;
 pub mod layer;
+pub mod packet;
+pub mod rate_limit;
+pub mod recorder;
+pub mod reliable;
 pub mod service;
+pub mod session;
+pub mod shard;
 pub mod sid;
 pub mod socket;
+pub mod stream;
 
 mod body;
 mod engine;
 mod errors;
-mod packet;
 mod peekable;
 mod transport;
@@ -1,10 +1,43 @@
+//! ## The engine.io packet codec
+//!
+//! [`Packet::encode`] and [`Packet::decode`] convert packets to and from their text representation,
+//! as sent in websocket text frames and in polling payloads. The packets of a v4 polling payload are
+//! joined with the [`PAYLOAD_SEPARATOR`], see [`encode_payload`] and [`decode_payload`].
+//!
+//! The codec is independent of the transports (it doesn't depend on tokio or hyper),
+//! so it can be used to implement a custom transport. The built-in transports use the same functions.
+//!
+//! #### Example :
+//! ```rust
+//! # use engineioxide::{packet::Packet, ProtocolVersion};
+//! let packet = Packet::Message("hello".into());
+//! let text = packet.clone().encode();
+//! assert_eq!(text, "4hello");
+//! assert_eq!(Packet::decode(text, ProtocolVersion::V4).unwrap(), packet);
+//! ```
 use base64::{engine::general_purpose, Engine};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::config::EngineIoConfig;
-use crate::errors::Error;
 use crate::sid::Sid;
-use crate::TransportType;
+use crate::{ProtocolVersion, TransportType};
+
+/// The separator of the packets of a v4 polling payload
+pub const PAYLOAD_SEPARATOR: u8 = b'\x1e';
+
+/// Error returned when decoding a [`Packet`]
+#[derive(thiserror::Error, Debug)]
+pub enum PacketError {
+    /// The packet is empty or its type is unknown
+    #[error("invalid packet type: {0:?}")]
+    InvalidPacketType(Option<char>),
+    /// The base64 data of a binary packet is invalid
+    #[error("invalid base64 binary packet: {0}")]
+    Base64(#[from] base64::DecodeError),
+    /// The data of an open packet is invalid
+    #[error("invalid open packet: {0}")]
+    Open(#[from] serde_json::Error),
+}
 
 /// A Packet type to use when receiving and sending data from the client
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -51,6 +84,38 @@ pub enum Packet {
 }
 
 impl Packet {
+    /// Formats the packet for a tracing event according to the [`PayloadLogging`](crate::config::PayloadLogging) policy.
+    /// When the payloads are not logged, only the type and the length of the payload are kept,
+    /// along with a hash of the payload for the [`Hashed`](crate::config::PayloadLogging::Hashed) policy.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn log(&self, policy: crate::config::PayloadLogging) -> String {
+        use crate::config::{hash_payload, PayloadLogging};
+        match (policy, self) {
+            (PayloadLogging::Hashed, Packet::Message(msg)) => {
+                format!(
+                    "Message(<{} bytes, hash {:016x}>)",
+                    msg.len(),
+                    hash_payload(msg)
+                )
+            }
+            (PayloadLogging::Hashed, Packet::Binary(data) | Packet::BinaryV3(data)) => {
+                format!(
+                    "Binary(<{} bytes, hash {:016x}>)",
+                    data.len(),
+                    hash_payload(data)
+                )
+            }
+            (PayloadLogging::Off, Packet::Message(msg)) => {
+                format!("Message(<{} bytes>)", msg.len())
+            }
+            (PayloadLogging::Off, Packet::Binary(data) | Packet::BinaryV3(data)) => {
+                format!("Binary(<{} bytes>)", data.len())
+            }
+            (PayloadLogging::Off | PayloadLogging::Hashed, packet) => format!("{packet:?}"),
+            (policy, packet) => policy.format(packet),
+        }
+    }
+
     /// Check if the packet is a binary packet
     pub fn is_binary(&self) -> bool {
         matches!(self, Packet::Binary(_) | Packet::BinaryV3(_))
@@ -73,6 +138,61 @@ impl Packet {
         }
     }
 
+    /// Serializes the packet to its text representation.
+    ///
+    /// Binary packets are encoded in base64, prefixed with `b` ([`Binary`](Packet::Binary))
+    /// or `b4` ([`BinaryV3`](Packet::BinaryV3)).
+    /// A websocket transport should send them as binary frames instead.
+    pub fn encode(self) -> String {
+        let len = self.get_size_hint(true);
+        let mut buffer = String::with_capacity(len);
+        match self {
+            Packet::Open(open) => {
+                buffer.push('0');
+                // The fields of an open packet are always serializable
+                buffer.push_str(&serde_json::to_string(&open).unwrap());
+            }
+            Packet::Close => buffer.push('1'),
+            Packet::Ping => buffer.push('2'),
+            Packet::Pong => buffer.push('3'),
+            Packet::PingUpgrade => buffer.push_str("2probe"),
+            Packet::PongUpgrade => buffer.push_str("3probe"),
+            Packet::Message(msg) => {
+                buffer.push('4');
+                buffer.push_str(&msg);
+            }
+            Packet::Upgrade => buffer.push('5'),
+            Packet::Noop => buffer.push('6'),
+            Packet::Binary(data) => {
+                buffer.push('b');
+                general_purpose::STANDARD.encode_string(data, &mut buffer);
+            }
+            Packet::BinaryV3(data) => {
+                buffer.push_str("b4");
+                general_purpose::STANDARD.encode_string(data, &mut buffer);
+            }
+        };
+        buffer
+    }
+
+    /// Deserializes a packet from its text representation.
+    ///
+    /// The protocol version is needed to decode base64 binary packets:
+    /// with the v3 protocol they are prefixed with `b4`, with the v4 protocol only with `b`.
+    /// The allocation of a message packet is reused rather than copying its data.
+    pub fn decode(mut value: String, protocol: ProtocolVersion) -> Result<Packet, PacketError> {
+        match value.as_bytes() {
+            [b'4', ..] => {
+                value.remove(0);
+                Ok(Packet::Message(value))
+            }
+            [b'b', rest @ ..] if protocol == ProtocolVersion::V4 => {
+                Ok(Packet::Binary(general_purpose::STANDARD.decode(rest)?))
+            }
+            _ => Packet::try_from(value.as_str()),
+        }
+    }
+
     /// Get the max size the packet could have when serialized
     ///
     ///  If b64 is true, it returns the max size when serialized to base64
@@ -107,50 +227,26 @@ impl Packet {
     }
 }
 
-/// Serialize a [Packet] to a [String] according to the Engine.IO protocol
-impl TryInto<String> for Packet {
-    type Error = Error;
-    fn try_into(self) -> Result<String, Self::Error> {
-        let len = self.get_size_hint(true);
-        let mut buffer = String::with_capacity(len);
-        match self {
-            Packet::Open(open) => {
-                buffer.push('0');
-                buffer.push_str(&serde_json::to_string(&open)?);
-            }
-            Packet::Close => buffer.push('1'),
-            Packet::Ping => buffer.push('2'),
-            Packet::Pong => buffer.push('3'),
-            Packet::PingUpgrade => buffer.push_str("2probe"),
-            Packet::PongUpgrade => buffer.push_str("3probe"),
-            Packet::Message(msg) => {
-                buffer.push('4');
-                buffer.push_str(&msg);
-            }
-            Packet::Upgrade => buffer.push('5'),
-            Packet::Noop => buffer.push('6'),
-            Packet::Binary(data) => {
-                buffer.push('b');
-                general_purpose::STANDARD.encode_string(data, &mut buffer);
-            }
-            Packet::BinaryV3(data) => {
-                buffer.push_str("b4");
-                general_purpose::STANDARD.encode_string(data, &mut buffer);
-            }
-        };
-        Ok(buffer)
+/// Serialize a [Packet] to a [String] according to the Engine.IO protocol, see [`Packet::encode`]
+impl From<Packet> for String {
+    fn from(packet: Packet) -> String {
+        packet.encode()
     }
 }
-/// Deserialize a [Packet] from a [String] according to the Engine.IO protocol
+/// Deserialize a [Packet] from a [String] according to the Engine.IO protocol.
+///
+/// A base64 binary packet prefixed with `b4` is always decoded as a [`BinaryV3`](Packet::BinaryV3) packet,
+/// use [`Packet::decode`] when the protocol version is known.
 impl TryFrom<&str> for Packet {
-    type Error = Error;
+    type Error = PacketError;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let packet_type = value
             .as_bytes()
             .first()
-            .ok_or(Error::InvalidPacketType(None))?;
-        let is_upgrade = value.len() == 6 && &value[1..6] == "probe";
+            .ok_or(PacketError::InvalidPacketType(None))?;
+        let is_upgrade = value.len() == 6 && &value.as_bytes()[1..] == b"probe";
         let res = match packet_type {
+            b'0' => Packet::Open(serde_json::from_str(&value[1..])?),
             b'1' => Packet::Close,
             b'2' if is_upgrade => Packet::PingUpgrade,
             b'2' => Packet::Ping,
@@ -163,35 +259,51 @@ impl TryFrom<&str> for Packet {
                 Packet::BinaryV3(general_purpose::STANDARD.decode(value[2..].as_bytes())?)
             }
             b'b' => Packet::Binary(general_purpose::STANDARD.decode(value[1..].as_bytes())?),
-            c => Err(Error::InvalidPacketType(Some(*c as char)))?,
+            _ => Err(PacketError::InvalidPacketType(value.chars().next()))?,
         };
         Ok(res)
     }
 }
 
 impl TryFrom<String> for Packet {
-    type Error = Error;
-    fn try_from(value: String) -> Result<Self, Self::Error> {
+    type Error = PacketError;
+    fn try_from(mut value: String) -> Result<Self, Self::Error> {
+        // The allocation of a message packet is reused rather than copying its data
+        if value.as_bytes().first() == Some(&b'4') {
+            value.remove(0);
+            return Ok(Packet::Message(value));
+        }
         Packet::try_from(value.as_str())
     }
 }
 
 /// An OpenPacket is used to initiate a connection
-#[derive(Debug, Clone, Serialize, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
 #[serde(rename_all = "camelCase")]
 pub struct OpenPacket {
     sid: Sid,
     upgrades: Vec<String>,
     ping_interval: u64,
     ping_timeout: u64,
-    max_payload: u64,
+    /// Not sent to the v3 clients, which don't know it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_payload: Option<u64>,
 }
 
 impl OpenPacket {
     /// Create a new [OpenPacket]
-    /// If the current transport is polling, the server will always allow the client to upgrade to websocket
-    pub fn new(transport: TransportType, sid: Sid, config: &EngineIoConfig) -> Self {
-        let upgrades = if transport == TransportType::Polling {
+    /// If the current transport is polling, the server allows the client to upgrade to websocket
+    /// unless the websocket transport is disabled with [`EngineIoConfigBuilder::transports`](crate::config::EngineIoConfigBuilder::transports).
+    /// The `maxPayload` field is omitted for the [`ProtocolVersion::V3`] clients.
+    pub fn new(
+        transport: TransportType,
+        sid: Sid,
+        protocol: ProtocolVersion,
+        config: &EngineIoConfig,
+    ) -> Self {
+        let upgrades = if transport == TransportType::Polling
+            && config.allowed_transport(TransportType::Websocket)
+        {
             vec!["websocket".to_string()]
         } else {
             vec![]
@@ -199,11 +311,56 @@ impl OpenPacket {
         OpenPacket {
             sid,
             upgrades,
-            ping_interval: config.ping_interval.as_millis() as u64,
+            ping_interval: config.advertised_ping_interval().as_millis() as u64,
             ping_timeout: config.ping_timeout.as_millis() as u64,
-            max_payload: config.max_payload,
+            max_payload: (protocol != ProtocolVersion::V3).then_some(config.max_payload),
+        }
+    }
+
+    /// The id of the session
+    pub fn sid(&self) -> Sid {
+        self.sid
+    }
+
+    /// The transports the client can upgrade to
+    pub fn upgrades(&self) -> &[String] {
+        &self.upgrades
+    }
+
+    /// The interval between the pings of the server, in milliseconds
+    pub fn ping_interval(&self) -> u64 {
+        self.ping_interval
+    }
+
+    /// The delay the server waits for a pong, in milliseconds
+    pub fn ping_timeout(&self) -> u64 {
+        self.ping_timeout
+    }
+
+    /// The max size of a payload accepted by the server, in bytes.
+    /// It is not sent with the protocol v3.
+    pub fn max_payload(&self) -> Option<u64> {
+        self.max_payload
+    }
+}
+
+/// Encodes packets into a v4 polling payload, joined with the [`PAYLOAD_SEPARATOR`]
+pub fn encode_payload(packets: impl IntoIterator<Item = Packet>) -> String {
+    let mut payload = String::new();
+    for packet in packets {
+        if !payload.is_empty() {
+            payload.push(PAYLOAD_SEPARATOR as char);
         }
+        payload.push_str(&packet.encode());
     }
+    payload
+}
+
+/// Decodes the packets of a v4 polling payload, split on the [`PAYLOAD_SEPARATOR`]
+pub fn decode_payload(payload: &str) -> impl Iterator<Item = Result<Packet, PacketError>> + '_ {
+    payload
+        .split(PAYLOAD_SEPARATOR as char)
+        .map(|packet| Packet::decode(packet.to_string(), ProtocolVersion::V4))
 }
 
 #[cfg(test)]
@@ -211,6 +368,7 @@ mod tests {
     use crate::config::EngineIoConfig;
 
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use std::{convert::TryInto, time::Duration};
 
     #[test]
@@ -219,16 +377,27 @@ mod tests {
         let packet = Packet::Open(OpenPacket::new(
             TransportType::Polling,
             sid,
+            ProtocolVersion::V4,
             &EngineIoConfig::default(),
         ));
-        let packet_str: String = packet.try_into().unwrap();
+        let packet_str: String = packet.into();
         assert_eq!(packet_str, format!("0{{\"sid\":\"{sid}\",\"upgrades\":[\"websocket\"],\"pingInterval\":25000,\"pingTimeout\":20000,\"maxPayload\":100000}}"));
+
+        // The v3 handshake has no maxPayload field
+        let packet = Packet::Open(OpenPacket::new(
+            TransportType::Polling,
+            sid,
+            ProtocolVersion::V3,
+            &EngineIoConfig::default(),
+        ));
+        let packet_str: String = packet.into();
+        assert_eq!(packet_str, format!("0{{\"sid\":\"{sid}\",\"upgrades\":[\"websocket\"],\"pingInterval\":25000,\"pingTimeout\":20000}}"));
     }
 
     #[test]
     fn test_message_packet() {
         let packet = Packet::Message("hello".into());
-        let packet_str: String = packet.try_into().unwrap();
+        let packet_str: String = packet.into();
         assert_eq!(packet_str, "4hello");
     }
 
@@ -242,7 +411,7 @@ mod tests {
     #[test]
     fn test_binary_packet() {
         let packet = Packet::Binary(vec![1, 2, 3]);
-        let packet_str: String = packet.try_into().unwrap();
+        let packet_str: String = packet.into();
         assert_eq!(packet_str, "bAQID");
     }
 
@@ -256,7 +425,7 @@ mod tests {
     #[test]
     fn test_binary_packet_v3() {
         let packet = Packet::BinaryV3(vec![1, 2, 3]);
-        let packet_str: String = packet.try_into().unwrap();
+        let packet_str: String = packet.into();
         assert_eq!(packet_str, "b4AQID");
     }
 
@@ -267,12 +436,31 @@ mod tests {
         assert_eq!(packet, Packet::BinaryV3(vec![1, 2, 3]));
     }
 
+    #[test]
+    fn test_open_packet_upgrades() {
+        let sid = Sid::new();
+        let open = |transport, transports: &[TransportType]| {
+            let config = EngineIoConfig {
+                transports: transports.iter().fold(0, |acc, t| acc | *t as u8),
+                ..Default::default()
+            };
+            OpenPacket::new(transport, sid, ProtocolVersion::V4, &config).upgrades
+        };
+        let both = [TransportType::Polling, TransportType::Websocket];
+        assert_eq!(open(TransportType::Polling, &both), ["websocket"]);
+        assert!(open(TransportType::Websocket, &both).is_empty());
+        assert!(open(TransportType::Websocket, &[TransportType::Websocket]).is_empty());
+        // The client can't upgrade to a disabled transport
+        assert!(open(TransportType::Polling, &[TransportType::Polling]).is_empty());
+    }
+
     #[test]
     fn test_packet_get_size_hint() {
         // Max serialized packet
         let open = OpenPacket::new(
             TransportType::Polling,
             Sid::new(),
+            ProtocolVersion::V4,
             &EngineIoConfig {
                 max_buffer_size: usize::MAX,
                 max_payload: u64::MAX,
@@ -318,4 +506,115 @@ mod tests {
         assert_eq!(packet.get_size_hint(false), 4);
         assert_eq!(packet.get_size_hint(true), 6);
     }
+
+    /// Generates a string mixing ascii, multi-byte and random chars
+    fn random_string(rng: &mut StdRng) -> String {
+        let len = rng.gen_range(0..20);
+        (0..len)
+            .map(|_| match rng.gen_range(0..3) {
+                0 => rng.gen_range(' '..='~'),
+                1 => ['é', '€', '中', '😀', '4', 'b'][rng.gen_range(0..6)],
+                _ => rng.gen::<char>(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        use ProtocolVersion::*;
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..2000 {
+            let len = rng.gen_range(0..20);
+            let bin: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let packets = [
+                (Packet::Message(random_string(&mut rng)), V4),
+                (Packet::Message(random_string(&mut rng)), V3),
+                (Packet::Binary(bin.clone()), V4),
+                (Packet::BinaryV3(bin), V3),
+            ];
+            for (packet, protocol) in packets {
+                let decoded = Packet::decode(packet.clone().encode(), protocol).unwrap();
+                assert_eq!(decoded, packet);
+            }
+        }
+
+        let open = OpenPacket::new(
+            TransportType::Polling,
+            Sid::new(),
+            ProtocolVersion::V4,
+            &Default::default(),
+        );
+        let packets = [
+            Packet::Open(open),
+            Packet::Close,
+            Packet::Ping,
+            Packet::Pong,
+            Packet::PingUpgrade,
+            Packet::PongUpgrade,
+            Packet::Upgrade,
+            Packet::Noop,
+        ];
+        for packet in packets {
+            for protocol in [V3, V4] {
+                let decoded = Packet::decode(packet.clone().encode(), protocol).unwrap();
+                assert_eq!(decoded, packet);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_v4_binary_starting_with_4() {
+        // The base64 data starts with a `4`, it is not a v3 binary packet
+        let packet = Packet::Binary(vec![0xE0, 1, 2]);
+        let encoded = packet.clone().encode();
+        assert_eq!(encoded, "b4AEC");
+        assert_eq!(
+            Packet::decode(encoded.clone(), ProtocolVersion::V4).unwrap(),
+            packet
+        );
+        assert!(Packet::decode(encoded, ProtocolVersion::V3).is_err());
+    }
+
+    #[test]
+    fn decode_invalid_packets() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10000 {
+            let packet = random_string(&mut rng);
+            for protocol in [ProtocolVersion::V3, ProtocolVersion::V4] {
+                // It must never panic
+                let _ = Packet::decode(packet.clone(), protocol);
+            }
+        }
+        // A multi-byte packet type in a 6 bytes packet
+        let err = Packet::decode("é1234".into(), ProtocolVersion::V4).unwrap_err();
+        assert!(matches!(err, PacketError::InvalidPacketType(Some('é'))));
+        let err = Packet::decode("".into(), ProtocolVersion::V4).unwrap_err();
+        assert!(matches!(err, PacketError::InvalidPacketType(None)));
+        let err = Packet::decode("b!".into(), ProtocolVersion::V4).unwrap_err();
+        assert!(matches!(err, PacketError::Base64(_)));
+        let err = Packet::decode("0{}".into(), ProtocolVersion::V4).unwrap_err();
+        assert!(matches!(err, PacketError::Open(_)));
+    }
+
+    #[test]
+    fn payload_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..500 {
+            let packets: Vec<_> = (0..rng.gen_range(1..10))
+                .map(|_| match rng.gen_range(0..3) {
+                    0 => Packet::Ping,
+                    1 => Packet::Binary(vec![rng.gen(); rng.gen_range(0..10)]),
+                    // The separator can't be sent in a message of a polling payload
+                    _ => Packet::Message(random_string(&mut rng).replace('\x1e', "")),
+                })
+                .collect();
+            let payload = encode_payload(packets.clone());
+            let decoded: Vec<_> = decode_payload(&payload).map(Result::unwrap).collect();
+            assert_eq!(decoded, packets);
+        }
+        assert_eq!(
+            encode_payload([Packet::Message("hello".into()), Packet::Ping]),
+            "4hello\x1e2"
+        );
+    }
 }
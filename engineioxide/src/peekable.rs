@@ -1,79 +1,169 @@
-use tokio::sync::mpsc::{error::TryRecvError, Receiver};
+use std::collections::VecDeque;
+
+use tokio::sync::mpsc::error::TryRecvError;
+
+use crate::channel::Receiver;
 
 /// Peekable receiver for polling transport
-/// It is a thin wrapper around a [`Receiver`](tokio::sync::mpsc::Receiver) that allows to peek the next packet without consuming it
+/// It is a thin wrapper around a [`Receiver`] that allows to peek the next packet without consuming it
 ///
 /// Its main goal is to be able to peek the next packet without consuming it to calculate the
 /// packet length when using polling transport to check if it fits according to the max_payload setting
+///
+/// The values received ahead of time with [`PeekableReceiver::buffer_next`] are kept in order
+/// and returned before the values of the channels.
+///
+/// It also merges a priority lane with the main channel: the values of the priority lane are always
+/// received first, so that the control packets are never stuck behind a backlog of messages.
 #[derive(Debug)]
 pub struct PeekableReceiver<T> {
     rx: Receiver<T>,
-    next: Option<T>,
+    priority: Receiver<T>,
+    buffered: VecDeque<T>,
 }
 impl<T> PeekableReceiver<T> {
-    pub fn new(rx: Receiver<T>) -> Self {
-        Self { rx, next: None }
+    pub fn new(rx: Receiver<T>, priority: Receiver<T>) -> Self {
+        Self {
+            rx,
+            priority,
+            buffered: VecDeque::new(),
+        }
     }
+    #[cfg(feature = "polling")]
     pub fn peek(&mut self) -> Option<&T> {
-        if self.next.is_none() {
-            self.next = self.rx.try_recv().ok();
+        if self.buffered.is_empty() {
+            let value = self.try_recv().ok()?;
+            self.buffered.push_back(value);
         }
-        self.next.as_ref()
+        self.buffered.front()
+    }
+    /// Waits for the next value of the channels and keeps it in the buffer,
+    /// it is then returned by [`peek`](Self::peek) and [`try_recv`](Self::try_recv) after the already buffered values.
+    ///
+    /// It is cancel safe: no value is lost if the future is dropped before completion.
+    #[cfg(feature = "polling")]
+    pub async fn buffer_next(&mut self) -> Option<&T> {
+        let value = self.recv_channels().await?;
+        self.buffered.push_back(value);
+        self.buffered.back()
     }
     pub async fn recv(&mut self) -> Option<T> {
-        if self.next.is_none() {
-            self.rx.recv().await
-        } else {
-            self.next.take()
+        if let Ok(value) = self.try_recv() {
+            return Some(value);
+        }
+        self.recv_channels().await
+    }
+    async fn recv_channels(&mut self) -> Option<T> {
+        tokio::select! {
+            biased;
+            // Once the priority lane is closed, only the main channel is awaited
+            Some(value) = self.priority.recv() => Some(value),
+            value = self.rx.recv() => value,
         }
     }
+    /// Puts back a value at the front of the buffer, it is the next value returned.
+    pub fn push_front(&mut self, value: T) {
+        self.buffered.push_front(value);
+    }
     pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
-        if self.next.is_none() {
-            self.rx.try_recv()
-        } else {
-            Ok(self.next.take().unwrap())
+        if let Some(value) = self.buffered.pop_front() {
+            return Ok(value);
         }
+        self.priority.try_recv().or_else(|_| self.rx.try_recv())
     }
 
     pub fn close(&mut self) {
+        self.priority.close();
         self.rx.close()
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "polling"))]
 mod tests {
     use tokio::sync::Mutex;
 
     #[tokio::test]
     async fn peek() {
         use super::PeekableReceiver;
-        use crate::packet::Packet;
-        use tokio::sync::mpsc::channel;
+        use crate::{channel::channel, config::OverflowPolicy, packet::Packet};
 
-        let (tx, rx) = channel(1);
-        let rx = Mutex::new(PeekableReceiver::new(rx));
+        let (tx, rx) = channel(1, OverflowPolicy::Reject);
+        let (_priority_tx, priority_rx) = channel(1, OverflowPolicy::Reject);
+        let rx = Mutex::new(PeekableReceiver::new(rx, priority_rx));
         let mut rx = rx.lock().await;
 
         assert!(rx.peek().is_none());
 
-        tx.send(Packet::Ping).await.unwrap();
+        tx.try_send(Packet::Ping).unwrap();
         assert_eq!(rx.peek(), Some(&Packet::Ping));
         assert_eq!(rx.recv().await, Some(Packet::Ping));
         assert!(rx.peek().is_none());
 
-        tx.send(Packet::Pong).await.unwrap();
+        tx.try_send(Packet::Pong).unwrap();
         assert_eq!(rx.peek(), Some(&Packet::Pong));
         assert_eq!(rx.recv().await, Some(Packet::Pong));
         assert!(rx.peek().is_none());
 
-        tx.send(Packet::Close).await.unwrap();
+        tx.try_send(Packet::Close).unwrap();
         assert_eq!(rx.peek(), Some(&Packet::Close));
         assert_eq!(rx.recv().await, Some(Packet::Close));
         assert!(rx.peek().is_none());
 
-        tx.send(Packet::Close).await.unwrap();
+        tx.try_send(Packet::Close).unwrap();
         assert_eq!(rx.peek(), Some(&Packet::Close));
         assert_eq!(rx.recv().await, Some(Packet::Close));
         assert!(rx.peek().is_none());
     }
+
+    #[tokio::test]
+    async fn priority_lane() {
+        use super::PeekableReceiver;
+        use crate::{channel::channel, config::OverflowPolicy, packet::Packet};
+
+        let (tx, rx) = channel(10, OverflowPolicy::Reject);
+        let (priority_tx, priority_rx) = channel(10, OverflowPolicy::Reject);
+        let mut rx = PeekableReceiver::new(rx, priority_rx);
+
+        for i in 0..5 {
+            tx.try_send(Packet::Message(i.to_string())).unwrap();
+        }
+        priority_tx.try_send(Packet::Ping).unwrap();
+        assert_eq!(rx.peek(), Some(&Packet::Ping));
+        assert_eq!(rx.recv().await, Some(Packet::Ping));
+        assert_eq!(rx.try_recv(), Ok(Packet::Message("0".into())));
+
+        priority_tx.try_send(Packet::Close).unwrap();
+        assert_eq!(rx.try_recv(), Ok(Packet::Close));
+        assert_eq!(rx.recv().await, Some(Packet::Message("1".into())));
+
+        // The main channel is still received once the priority lane is closed
+        drop(priority_tx);
+        assert_eq!(rx.recv().await, Some(Packet::Message("2".into())));
+        rx.close();
+        assert_eq!(rx.recv().await, Some(Packet::Message("3".into())));
+        assert_eq!(rx.recv().await, Some(Packet::Message("4".into())));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn buffer_next() {
+        use super::PeekableReceiver;
+        use crate::{channel::channel, config::OverflowPolicy, packet::Packet};
+
+        let (tx, rx) = channel(10, OverflowPolicy::Reject);
+        let (priority_tx, priority_rx) = channel(10, OverflowPolicy::Reject);
+        let mut rx = PeekableReceiver::new(rx, priority_rx);
+
+        tx.try_send(Packet::Message("0".into())).unwrap();
+        tx.try_send(Packet::Message("1".into())).unwrap();
+        assert_eq!(rx.buffer_next().await, Some(&Packet::Message("0".into())));
+        priority_tx.try_send(Packet::Close).unwrap();
+        assert_eq!(rx.buffer_next().await, Some(&Packet::Close));
+
+        // The buffered values are returned first, in the order they were received
+        assert_eq!(rx.peek(), Some(&Packet::Message("0".into())));
+        assert_eq!(rx.try_recv(), Ok(Packet::Message("0".into())));
+        assert_eq!(rx.try_recv(), Ok(Packet::Close));
+        assert_eq!(rx.try_recv(), Ok(Packet::Message("1".into())));
+    }
 }
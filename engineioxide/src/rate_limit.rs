@@ -0,0 +1,464 @@
+//! ## Handshake rate limit per remote IP
+//!
+//! A [`HandshakeRateLimit`] limits the number of sessions that a single IP can open, to mitigate connection floods
+//! and reconnection storms.
+//! Each IP has a token bucket: a handshake consumes a token and the tokens are refilled at a constant rate.
+//! Over-limit handshakes are rejected with a `429 Too Many Requests` response and a `Retry-After` header,
+//! for polling and websocket handshakes alike, before any socket is created.
+//!
+//! The state of at most [`max_ips`](HandshakeRateLimit::max_ips) IPs is kept,
+//! the least recently seen IPs are evicted first.
+//!
+//! The client is identified with:
+//! * The value of the [`identity_header`](HandshakeRateLimit::identity_header), if it is set and present
+//!   and if [`trust_proxy`](HandshakeRateLimit::trust_proxy) is enabled.
+//! * The address of the `X-Forwarded-For` header, if [`trust_proxy`](HandshakeRateLimit::trust_proxy) is enabled.
+//!   It is the first address, or the address set by the outermost trusted proxy
+//!   with [`forwarded_depth`](HandshakeRateLimit::forwarded_depth).
+//! * Otherwise a [`SocketAddr`] inserted in the extensions of the request, for example by a middleware
+//!   copying it from the connection info of the server.
+//!
+//! Handshakes without a resolvable identity are not limited per IP.
+//!
+//! The number of handshakes in progress across all the clients can also be bounded with
+//! [`max_concurrent_handshakes`](HandshakeRateLimit::max_concurrent_handshakes),
+//! the handshakes over this bound are rejected with a `503 Service Unavailable` response.
+//!
+//! #### Example :
+//! ```rust
+//! # use std::{sync::Arc, time::Duration};
+//! # use engineioxide::config::EngineIoConfig;
+//! # use engineioxide::rate_limit::HandshakeRateLimit;
+//! // Bursts of 20 handshakes per IP, then 10 handshakes per second
+//! let limit = HandshakeRateLimit::per_second(10, 20)
+//!     .max_ips(10_000)
+//!     .max_concurrent_handshakes(500)
+//!     // Behind a single reverse proxy appending the client address to the `X-Forwarded-For` header
+//!     .forwarded_depth(1);
+//! let limit = Arc::new(limit);
+//! let config = EngineIoConfig::builder()
+//!     .handshake_rate_limit(limit.clone())
+//!     .build();
+//!
+//! // The limit can be kept to measure the rejected handshakes
+//! assert_eq!(limit.rejected(), 0);
+//! assert_eq!(limit.rejected_concurrent(), 0);
+//! ```
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use http::{request::Parts, HeaderName, HeaderValue};
+
+/// A token bucket rate limit of the handshakes, keyed by remote IP.
+/// See the [module level documentation](self) for more details.
+#[derive(Debug)]
+pub struct HandshakeRateLimit {
+    burst: u32,
+    refill_interval: Duration,
+    max_ips: usize,
+    trust_proxy: bool,
+    forwarded_depth: usize,
+    identity_header: Option<HeaderName>,
+    max_concurrent: usize,
+    in_flight: Arc<AtomicUsize>,
+    state: Mutex<BucketMap>,
+    rejected: AtomicU64,
+    rejected_concurrent: AtomicU64,
+}
+
+impl HandshakeRateLimit {
+    /// Creates a rate limit allowing bursts of `burst` handshakes per IP,
+    /// with a token refilled every `refill_interval`.
+    ///
+    /// It tracks up to 10 000 IPs by default, and doesn't trust the `X-Forwarded-For` header.
+    pub fn new(burst: u32, refill_interval: Duration) -> Self {
+        Self {
+            burst,
+            refill_interval,
+            max_ips: 10_000,
+            trust_proxy: false,
+            forwarded_depth: 0,
+            identity_header: None,
+            max_concurrent: usize::MAX,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            state: Mutex::new(BucketMap::default()),
+            rejected: AtomicU64::new(0),
+            rejected_concurrent: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates a rate limit allowing `handshakes_per_sec` handshakes per second and per IP,
+    /// with bursts of up to `burst` handshakes.
+    pub fn per_second(handshakes_per_sec: u32, burst: u32) -> Self {
+        let refill_interval = Duration::from_secs(1) / handshakes_per_sec.max(1);
+        Self::new(burst, refill_interval)
+    }
+
+    /// The maximum number of IPs whose state is kept. It caps the memory used by the rate limit.
+    /// When it is reached, the least recently seen IP is evicted.
+    ///
+    /// Defaults to 10 000 IPs.
+    pub fn max_ips(mut self, max_ips: usize) -> Self {
+        self.max_ips = max_ips.max(1);
+        self
+    }
+
+    /// If true, the remote IP is taken from the `X-Forwarded-For` header.
+    /// It should only be enabled behind a reverse proxy setting this header,
+    /// otherwise clients can spoof their IP.
+    ///
+    /// Defaults to false.
+    pub fn trust_proxy(mut self, trust_proxy: bool) -> Self {
+        self.trust_proxy = trust_proxy;
+        self
+    }
+
+    /// The number of trusted reverse proxies appending the address of their peer to the `X-Forwarded-For` header.
+    /// The remote IP is the `depth`-th address from the end of the header, the addresses before it
+    /// are set by the client and can be spoofed. If the header has fewer addresses, the request
+    /// didn't go through all the proxies and the [`SocketAddr`] of the request is used instead.
+    ///
+    /// It enables [`trust_proxy`](Self::trust_proxy) if `depth` is not 0.
+    ///
+    /// Defaults to 0, the first address of the header is used.
+    pub fn forwarded_depth(mut self, depth: usize) -> Self {
+        self.forwarded_depth = depth;
+        self.trust_proxy |= depth > 0;
+        self
+    }
+
+    /// A header identifying the clients instead of their IP, for example a header set by an API gateway.
+    /// Requests without this header are identified by their IP.
+    ///
+    /// The header is only used if [`trust_proxy`](Self::trust_proxy) is enabled: it is set by the clients
+    /// otherwise, and a client sending a new value on each handshake would bypass the limit per IP.
+    ///
+    /// Defaults to `None`.
+    pub fn identity_header(mut self, header: HeaderName) -> Self {
+        self.identity_header = Some(header);
+        self
+    }
+
+    /// The maximum number of handshakes in progress at the same time, across all the clients.
+    /// It bounds the work of allocating the new sessions when many clients reconnect at once.
+    /// A handshake is in progress until its open packet is sent.
+    ///
+    /// Defaults to unlimited.
+    pub fn max_concurrent_handshakes(mut self, max: usize) -> Self {
+        self.max_concurrent = max.max(1);
+        self
+    }
+
+    /// Returns the total number of handshakes rejected by the rate limit per IP
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total number of handshakes rejected because of the
+    /// [`max_concurrent_handshakes`](Self::max_concurrent_handshakes) bound
+    pub fn rejected_concurrent(&self) -> u64 {
+        self.rejected_concurrent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of handshakes currently in progress
+    pub fn handshakes_in_progress(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of IPs whose state is currently kept
+    pub fn tracked_ips(&self) -> usize {
+        self.state.lock().unwrap().buckets.len()
+    }
+
+    /// Resolves the remote IP of a request, respecting the [`trust_proxy`](Self::trust_proxy)
+    /// and [`forwarded_depth`](Self::forwarded_depth) settings
+    pub fn remote_ip(&self, req: &Parts) -> Option<IpAddr> {
+        if self.trust_proxy {
+            let forwarded = req
+                .headers
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| match self.forwarded_depth {
+                    0 => v.split(',').next(),
+                    depth => v.rsplit(',').nth(depth - 1),
+                })
+                .and_then(|ip| ip.trim().parse().ok());
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+        req.extensions.get::<SocketAddr>().map(|addr| addr.ip())
+    }
+
+    /// Resolves the identity of the client, respecting the [`identity_header`](Self::identity_header)
+    /// and [`trust_proxy`](Self::trust_proxy) settings
+    fn identity(&self, req: &Parts) -> Option<Identity> {
+        self.identity_header
+            .as_ref()
+            .filter(|_| self.trust_proxy)
+            .and_then(|header| req.headers.get(header))
+            .map(|value| Identity::Header(value.clone()))
+            .or_else(|| self.remote_ip(req).map(Identity::Ip))
+    }
+
+    /// Consumes a token for the client of the request.
+    /// Returns the delay before the next token if the handshake is over the limit.
+    pub(crate) fn check(&self, req: &Parts) -> Result<(), Duration> {
+        let Some(identity) = self.identity(req) else {
+            return Ok(());
+        };
+        let res = self
+            .state
+            .lock()
+            .unwrap()
+            .take(&identity, Instant::now(), self);
+        if res.is_err() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(?identity, "handshake rate limit exceeded");
+        }
+        res
+    }
+
+    /// Reserves a slot for a handshake, returns `None` if
+    /// [`max_concurrent_handshakes`](Self::max_concurrent_handshakes) are already in progress.
+    pub(crate) fn acquire(&self) -> Option<HandshakePermit> {
+        let max = self.max_concurrent;
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .map_err(|_| {
+                self.rejected_concurrent.fetch_add(1, Ordering::Relaxed);
+                #[cfg(feature = "tracing")]
+                tracing::debug!("too many handshakes in progress");
+            })
+            .ok()
+            .map(|_| HandshakePermit(self.in_flight.clone()))
+    }
+}
+
+/// A handshake in progress, counted until it is dropped
+#[derive(Debug)]
+pub(crate) struct HandshakePermit(Arc<AtomicUsize>);
+
+impl Drop for HandshakePermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// The key of the token buckets
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Identity {
+    Ip(IpAddr),
+    Header(HeaderValue),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// The position of the client in the recency order
+    seq: u64,
+}
+
+/// The token buckets of the clients, with their recency order to evict the least recently seen clients
+#[derive(Debug, Default)]
+struct BucketMap {
+    buckets: HashMap<Identity, Bucket>,
+    recency: BTreeMap<u64, Identity>,
+    seq: u64,
+}
+
+impl BucketMap {
+    fn take(
+        &mut self,
+        identity: &Identity,
+        now: Instant,
+        limit: &HandshakeRateLimit,
+    ) -> Result<(), Duration> {
+        self.seq += 1;
+        let seq = self.seq;
+        if !self.buckets.contains_key(identity) && self.buckets.len() >= limit.max_ips {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.buckets.remove(&oldest);
+            }
+        }
+        let bucket = self.buckets.entry(identity.clone()).or_insert(Bucket {
+            tokens: limit.burst as f64,
+            last_refill: now,
+            seq,
+        });
+        self.recency.remove(&bucket.seq);
+        self.recency.insert(seq, identity.clone());
+        bucket.seq = seq;
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        let refilled = if limit.refill_interval.is_zero() {
+            limit.burst as f64
+        } else {
+            elapsed.as_secs_f64() / limit.refill_interval.as_secs_f64()
+        };
+        bucket.tokens = (bucket.tokens + refilled).min(limit.burst as f64);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(limit.refill_interval.mul_f64(1.0 - bucket.tokens))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(ip: &str) -> Parts {
+        let mut req = http::Request::new(()).into_parts().0;
+        req.extensions
+            .insert(SocketAddr::new(ip.parse().unwrap(), 1234));
+        req
+    }
+
+    #[test]
+    fn token_bucket() {
+        let limit = HandshakeRateLimit::new(2, Duration::from_secs(1));
+        let mut state = BucketMap::default();
+        let ip = &Identity::Ip("127.0.0.1".parse().unwrap());
+        let now = Instant::now();
+        assert!(state.take(ip, now, &limit).is_ok());
+        assert!(state.take(ip, now, &limit).is_ok());
+        assert_eq!(state.take(ip, now, &limit), Err(Duration::from_secs(1)));
+        // Half a token is not enough
+        let half = now + Duration::from_millis(500);
+        assert_eq!(
+            state.take(ip, half, &limit),
+            Err(Duration::from_millis(500))
+        );
+        assert!(state
+            .take(ip, now + Duration::from_millis(1000), &limit)
+            .is_ok());
+        // The bucket never holds more than the burst
+        let later = now + Duration::from_secs(60);
+        assert!(state.take(ip, later, &limit).is_ok());
+        assert!(state.take(ip, later, &limit).is_ok());
+        assert!(state.take(ip, later, &limit).is_err());
+    }
+
+    #[test]
+    fn per_second() {
+        let limit = HandshakeRateLimit::per_second(10, 1);
+        let mut state = BucketMap::default();
+        let ip = &Identity::Ip("127.0.0.1".parse().unwrap());
+        let now = Instant::now();
+        assert!(state.take(ip, now, &limit).is_ok());
+        assert_eq!(state.take(ip, now, &limit), Err(Duration::from_millis(100)));
+        assert!(state
+            .take(ip, now + Duration::from_millis(100), &limit)
+            .is_ok());
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let limit = HandshakeRateLimit::new(1, Duration::from_secs(60)).max_ips(2);
+        assert!(limit.check(&req("10.0.0.1")).is_ok());
+        assert!(limit.check(&req("10.0.0.2")).is_ok());
+        // Refreshes 10.0.0.1, 10.0.0.2 is now the least recently seen IP
+        assert!(limit.check(&req("10.0.0.1")).is_err());
+        assert!(limit.check(&req("10.0.0.3")).is_ok());
+        assert_eq!(limit.tracked_ips(), 2);
+        // 10.0.0.2 was evicted so it gets a new bucket
+        assert!(limit.check(&req("10.0.0.2")).is_ok());
+        assert!(limit.check(&req("10.0.0.2")).is_err());
+        assert_eq!(limit.rejected(), 2);
+    }
+
+    #[test]
+    fn remote_ip() {
+        let mut req = req("10.0.0.1");
+        req.headers
+            .insert("x-forwarded-for", "1.2.3.4, 10.0.0.1".parse().unwrap());
+        let limit = HandshakeRateLimit::new(1, Duration::from_secs(1));
+        assert_eq!(limit.remote_ip(&req), Some("10.0.0.1".parse().unwrap()));
+        let limit = limit.trust_proxy(true);
+        assert_eq!(limit.remote_ip(&req), Some("1.2.3.4".parse().unwrap()));
+
+        let no_ip = http::Request::new(()).into_parts().0;
+        assert_eq!(limit.remote_ip(&no_ip), None);
+        assert!(limit.check(&no_ip).is_ok());
+        assert!(limit.check(&no_ip).is_ok());
+    }
+
+    #[test]
+    fn forwarded_depth() {
+        let mut req = req("10.0.0.1");
+        req.headers.insert(
+            "x-forwarded-for",
+            "6.6.6.6, 1.2.3.4, 10.0.0.2".parse().unwrap(),
+        );
+        let limit = HandshakeRateLimit::new(1, Duration::from_secs(1)).forwarded_depth(1);
+        assert_eq!(limit.remote_ip(&req), Some("10.0.0.2".parse().unwrap()));
+        let limit = limit.forwarded_depth(2);
+        assert_eq!(limit.remote_ip(&req), Some("1.2.3.4".parse().unwrap()));
+        // Not enough proxies in the header, the socket address is used
+        let limit = limit.forwarded_depth(4);
+        assert_eq!(limit.remote_ip(&req), Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn identity_header() {
+        let limit = HandshakeRateLimit::new(1, Duration::from_secs(60))
+            .identity_header(HeaderName::from_static("x-client-id"))
+            .trust_proxy(true);
+        let mut a = req("10.0.0.1");
+        a.headers.insert("x-client-id", "a".parse().unwrap());
+        let mut b = req("10.0.0.1");
+        b.headers.insert("x-client-id", "b".parse().unwrap());
+        assert!(limit.check(&a).is_ok());
+        assert!(limit.check(&b).is_ok());
+        assert!(limit.check(&a).is_err());
+        // Without the header the client is identified by its IP
+        assert!(limit.check(&req("10.0.0.1")).is_ok());
+        assert!(limit.check(&req("10.0.0.1")).is_err());
+    }
+
+    #[test]
+    fn identity_header_not_trusted() {
+        let limit = HandshakeRateLimit::new(2, Duration::from_secs(60))
+            .identity_header(HeaderName::from_static("x-client-id"))
+            .max_ips(2);
+        // A single IP rotating the header on each handshake is still limited per IP
+        for i in 0..10 {
+            let mut req = req("10.0.0.1");
+            req.headers.insert("x-client-id", i.into());
+            assert_eq!(limit.check(&req).is_ok(), i < 2);
+        }
+        assert_eq!(limit.rejected(), 8);
+        assert_eq!(limit.tracked_ips(), 1);
+        // The other clients are not evicted
+        assert!(limit.check(&req("10.0.0.2")).is_ok());
+        assert_eq!(limit.tracked_ips(), 2);
+    }
+
+    #[test]
+    fn max_concurrent_handshakes() {
+        let limit = HandshakeRateLimit::new(1, Duration::from_secs(1)).max_concurrent_handshakes(2);
+        let a = limit.acquire().unwrap();
+        let _b = limit.acquire().unwrap();
+        assert!(limit.acquire().is_none());
+        assert_eq!(limit.handshakes_in_progress(), 2);
+        drop(a);
+        assert!(limit.acquire().is_some());
+        assert_eq!(limit.rejected_concurrent(), 1);
+    }
+}
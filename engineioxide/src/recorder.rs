@@ -0,0 +1,144 @@
+//! ## Session capture: recording of the packets of selected sockets
+//!
+//! A [`SessionRecorder`] receives every inbound and outbound engine.io packet of the recorded sockets,
+//! with the time at which it was received or emitted. It is meant to capture full sessions
+//! to debug protocol issues in production.
+//!
+//! The recording is enabled:
+//! * For a single socket at runtime, with [`Socket::start_recording`](crate::Socket::start_recording).
+//! * For a random sample of the new sessions, with [`EngineIoConfigBuilder::session_recording`](crate::config::EngineIoConfigBuilder::session_recording).
+//!
+//! When a socket is not recorded, the only cost is an atomic load per packet, nothing is cloned.
+//!
+//! Inbound packets are recorded once they are decoded, before being handled.
+//! Outbound packets are recorded when they are emitted, before being buffered,
+//! so the packets rejected because the buffer of the socket is full are recorded too.
+//!
+//! With the `test-utils` feature, a recorded inbound stream can be fed back to a handler
+//! with [`TestSocketHandle::replay`](crate::socket::TestSocketHandle::replay) to reproduce an issue.
+//!
+//! #### Example :
+//! ```rust
+//! # use std::sync::Arc;
+//! # use engineioxide::config::EngineIoConfig;
+//! # use engineioxide::recorder::{MemoryRecorder, SessionRecording};
+//! // Record one session out of a thousand
+//! let recorder = Arc::new(MemoryRecorder::new());
+//! let config = EngineIoConfig::builder()
+//!     .session_recording(SessionRecording::new(recorder.clone()).sample_rate(0.001))
+//!     .build();
+//!
+//! for record in recorder.take() {
+//!     println!("{} {:?} {:?}", record.sid, record.direction, record.packet);
+//! }
+//! ```
+use std::{
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::{packet::Packet, sid::Sid};
+
+/// The direction of a recorded packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The packet was received from the client
+    Inbound,
+    /// The packet was emitted to the client
+    Outbound,
+}
+
+/// A sink for the packets of the recorded sockets, see the [module level documentation](self).
+pub trait SessionRecorder: Send + Sync + 'static {
+    /// Records a packet of a recorded socket.
+    ///
+    /// It is called synchronously when the packet is received or emitted,
+    /// so it should return quickly, e.g. by sending the packet to a channel.
+    fn record(&self, sid: Sid, direction: Direction, packet: &Packet, at: SystemTime);
+}
+
+/// A packet recorded by a [`MemoryRecorder`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedPacket {
+    /// The socket of the packet
+    pub sid: Sid,
+    /// Whether the packet was received or emitted
+    pub direction: Direction,
+    /// The recorded packet
+    pub packet: Packet,
+    /// The time at which the packet was received or emitted
+    pub at: SystemTime,
+}
+
+/// A [`SessionRecorder`] keeping the recorded packets in memory, in the order they were recorded.
+#[derive(Debug, Default)]
+pub struct MemoryRecorder {
+    packets: Mutex<Vec<RecordedPacket>>,
+}
+
+impl MemoryRecorder {
+    /// Creates an empty recorder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a clone of the recorded packets
+    pub fn packets(&self) -> Vec<RecordedPacket> {
+        self.packets.lock().unwrap().clone()
+    }
+
+    /// Takes the recorded packets, leaving the recorder empty
+    pub fn take(&self) -> Vec<RecordedPacket> {
+        std::mem::take(&mut self.packets.lock().unwrap())
+    }
+}
+
+impl SessionRecorder for MemoryRecorder {
+    fn record(&self, sid: Sid, direction: Direction, packet: &Packet, at: SystemTime) {
+        self.packets.lock().unwrap().push(RecordedPacket {
+            sid,
+            direction,
+            packet: packet.clone(),
+            at,
+        });
+    }
+}
+
+/// The recording of a random sample of the new sessions,
+/// see [`EngineIoConfig::session_recording`](crate::config::EngineIoConfig::session_recording).
+#[derive(Clone)]
+pub struct SessionRecording {
+    recorder: Arc<dyn SessionRecorder>,
+    sample_rate: f64,
+}
+
+impl SessionRecording {
+    /// Records all the new sessions with the given recorder
+    pub fn new(recorder: Arc<dyn SessionRecorder>) -> Self {
+        Self {
+            recorder,
+            sample_rate: 1.0,
+        }
+    }
+
+    /// The fraction of the new sessions that are recorded, between 0 and 1.
+    ///
+    /// Defaults to 1 (all the sessions are recorded).
+    pub fn sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns the recorder if a new session is sampled
+    pub(crate) fn sample(&self) -> Option<Arc<dyn SessionRecorder>> {
+        (rand::random::<f64>() < self.sample_rate).then(|| self.recorder.clone())
+    }
+}
+
+impl std::fmt::Debug for SessionRecording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionRecording")
+            .field("sample_rate", &self.sample_rate)
+            .finish()
+    }
+}
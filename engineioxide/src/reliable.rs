@@ -0,0 +1,170 @@
+//! Acknowledged binary packets, to detect the packets lost by a flaky transport, e.g. during an upgrade.
+//!
+//! A binary packet sent with [`Socket::emit_binary_reliable`](crate::Socket::emit_binary_reliable)
+//! starts with a [`HEADER_LEN`] bytes header, and the client is expected to answer it with an ack packet
+//! carrying the same sequence number:
+//!
+//! | magic (1 byte) | kind (1 byte) | sequence number (u32 BE) |
+//! |----------------|---------------|------------------------- |
+//! | `0xF6`         | `0` data, `1` ack | unique per socket |
+//!
+//! ⚠️ It requires the cooperation of the client: it must strip the header of the data packets
+//! and send back a binary ack packet, e.g. with [`decode_data`] and [`encode_ack`].
+//! Without it, every reliable packet is sent twice and then fails with a [`ReliableError::Timeout`] error.
+//!
+//! The ack packets of the reliable packets waiting for their ack are consumed by the socket,
+//! they are never passed to the [`EngineIoHandler::on_binary`](crate::handler::EngineIoHandler::on_binary) handler.
+//! The late and duplicated acks are regular binary packets, they are passed to the handler.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use tokio::sync::oneshot;
+
+/// The first byte of every reliable packet
+pub const MAGIC: u8 = 0xF6;
+/// The length of the header of every reliable packet
+pub const HEADER_LEN: usize = 6;
+
+const KIND_DATA: u8 = 0;
+const KIND_ACK: u8 = 1;
+
+/// Error returned by [`Socket::emit_binary_reliable`](crate::Socket::emit_binary_reliable)
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReliableError {
+    /// The internal buffer of the socket is full
+    #[error("the internal buffer of the socket is full")]
+    Full,
+    /// The socket is closed
+    #[error("the socket is closed")]
+    Closed,
+    /// The packet was not acknowledged by the client, even after being sent again
+    #[error("the binary packet was not acknowledged")]
+    Timeout,
+}
+
+/// Encodes a data packet
+pub(crate) fn encode_data(seq: u32, data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + data.len());
+    buf.push(MAGIC);
+    buf.push(KIND_DATA);
+    buf.extend_from_slice(&seq.to_be_bytes());
+    buf.extend_from_slice(data);
+    buf
+}
+
+/// Encodes the ack packet of the given sequence number, to be sent by the client
+pub fn encode_ack(seq: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    buf.push(MAGIC);
+    buf.push(KIND_ACK);
+    buf.extend_from_slice(&seq.to_be_bytes());
+    buf
+}
+
+/// Decodes a data packet into its sequence number and its data, `None` is returned if it is not one
+pub fn decode_data(data: &[u8]) -> Option<(u32, &[u8])> {
+    if data.len() < HEADER_LEN || data[0] != MAGIC || data[1] != KIND_DATA {
+        return None;
+    }
+    let seq = u32::from_be_bytes(data[2..HEADER_LEN].try_into().unwrap());
+    Some((seq, &data[HEADER_LEN..]))
+}
+
+/// Decodes an ack packet into its sequence number, `None` is returned if it is not one
+fn decode_ack(data: &[u8]) -> Option<u32> {
+    if data.len() != HEADER_LEN || data[0] != MAGIC || data[1] != KIND_ACK {
+        return None;
+    }
+    Some(u32::from_be_bytes(data[2..].try_into().unwrap()))
+}
+
+/// The reliable packets of a socket waiting for their ack
+#[derive(Debug, Default)]
+pub(crate) struct PendingAcks {
+    next_seq: AtomicU32,
+    pending: Mutex<HashMap<u32, oneshot::Sender<()>>>,
+}
+
+impl PendingAcks {
+    /// Issues a new sequence number and returns a receiver resolved when it is acknowledged.
+    /// The sequence number is forgotten when the returned [`PendingAck`] is dropped.
+    pub fn register(&self) -> (PendingAck<'_>, oneshot::Receiver<()>) {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(seq, tx);
+        (PendingAck { acks: self, seq }, rx)
+    }
+
+    /// Consumes the binary packet if it is the ack of a sequence number waiting for its ack.
+    pub fn ack(&self, data: &[u8]) -> bool {
+        let Some(seq) = decode_ack(data) else {
+            return false;
+        };
+        match self.pending.lock().unwrap().remove(&seq) {
+            Some(tx) => {
+                tx.send(()).ok();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A sequence number waiting for its ack, it is forgotten when dropped,
+/// even if the future waiting for the ack is cancelled.
+#[derive(Debug)]
+pub(crate) struct PendingAck<'a> {
+    acks: &'a PendingAcks,
+    pub seq: u32,
+}
+
+impl Drop for PendingAck<'_> {
+    fn drop(&mut self) {
+        self.acks.pending.lock().unwrap().remove(&self.seq);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_roundtrip() {
+        let packet = encode_data(42, b"hello");
+        assert_eq!(packet[..2], [MAGIC, KIND_DATA]);
+        assert_eq!(decode_data(&packet), Some((42, &b"hello"[..])));
+        assert_eq!(decode_data(&encode_ack(42)), None);
+        assert_eq!(decode_data(b"hello"), None);
+    }
+
+    #[test]
+    fn only_pending_acks_are_consumed() {
+        let acks = PendingAcks::default();
+        let (pending, mut rx) = acks.register();
+        let seq = pending.seq;
+
+        assert!(!acks.ack(&encode_ack(seq + 1)));
+        assert!(!acks.ack(b"hello!"));
+        assert!(rx.try_recv().is_err());
+
+        assert!(acks.ack(&encode_ack(seq)));
+        assert_eq!(rx.try_recv(), Ok(()));
+        // A duplicated ack is application data
+        assert!(!acks.ack(&encode_ack(seq)));
+    }
+
+    #[test]
+    fn dropped_pending_ack_is_forgotten() {
+        let acks = PendingAcks::default();
+        let (pending, _rx) = acks.register();
+        let seq = pending.seq;
+        drop(pending);
+        assert!(acks.pending.lock().unwrap().is_empty());
+        assert!(!acks.ack(&encode_ack(seq)));
+    }
+}
@@ -13,9 +13,6 @@ pub(crate) type BoxFuture<B> =
 pin_project! {
     #[project = ResFutProj]
     pub enum ResponseFuture<F, B> {
-        EmptyResponse {
-            code: u16,
-        },
         ReadyResponse {
             res: Option<Result<Response<ResponseBody<B>>, Error>>,
         },
@@ -30,9 +27,6 @@ pin_project! {
 }
 
 impl<F, B> ResponseFuture<F, B> {
-    pub fn empty_response(code: u16) -> Self {
-        ResponseFuture::EmptyResponse { code }
-    }
     pub fn ready(res: Result<Response<ResponseBody<B>>, Error>) -> Self {
         ResponseFuture::ReadyResponse { res: Some(res) }
     }
@@ -54,10 +48,6 @@ where
         let res = match self.project() {
             ResFutProj::Future { future } => ready!(future.poll(cx))?.map(ResponseBody::new),
 
-            ResFutProj::EmptyResponse { code } => Response::builder()
-                .status(*code)
-                .body(ResponseBody::empty_response())
-                .unwrap(),
             ResFutProj::AsyncResponse { future } => ready!(future
                 .as_mut()
                 .poll(cx)
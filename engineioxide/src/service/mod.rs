@@ -31,16 +31,23 @@ use std::{
     task::{Context, Poll},
 };
 
-use ::futures::future::{self, Ready};
+use ::futures::{
+    future::{self, Ready},
+    Sink, Stream,
+};
 use bytes::Bytes;
-use http::{Request, Response};
+use http::{request::Parts, Request, Response};
 use http_body::Body;
 use http_body_util::Empty;
 use hyper::service::Service as HyperSvc;
 use tower::Service as TowerSvc;
 
 use crate::{
-    body::ResponseBody, config::EngineIoConfig, engine::EngineIo, handler::EngineIoHandler,
+    body::ResponseBody,
+    config::EngineIoConfig,
+    engine::EngineIo,
+    handler::{EngineIoHandle, EngineIoHandler},
+    transport::ws,
 };
 
 mod futures;
@@ -49,6 +56,28 @@ mod parser;
 pub use self::parser::{ProtocolVersion, TransportType};
 use self::{futures::ResponseFuture, parser::dispatch_req};
 
+/// The websocket implementation used by [`EngineIoService::on_ws_connection`]
+pub use tokio_tungstenite::tungstenite;
+use tungstenite::{Error as WsError, Message};
+
+/// The handshake of a websocket connection upgraded outside of the engine,
+/// given to [`EngineIoService::on_ws_connection`].
+#[derive(Debug)]
+pub struct WsHandshake {
+    /// The engine.io protocol version spoken by the client
+    pub protocol: ProtocolVersion,
+    /// The parts of the http upgrade request,
+    /// they are available on the socket as for the connections upgraded by the engine
+    pub req: Parts,
+}
+
+impl WsHandshake {
+    /// Create a new handshake from the protocol version and the parts of the upgrade request
+    pub fn new(protocol: ProtocolVersion, req: Parts) -> Self {
+        Self { protocol, req }
+    }
+}
+
 /// A `Service` that handles engine.io requests as a middleware.
 /// If the request is not an engine.io request, it forwards it to the inner service.
 /// If it is an engine.io request it will forward it to the appropriate `transport`.
@@ -79,9 +108,72 @@ impl<S: Clone, H: EngineIoHandler> EngineIoService<H, S> {
 
     /// Create a new [`EngineIoService`] with a custom inner service and a custom config.
     pub fn with_config_inner(inner: S, handler: H, config: EngineIoConfig) -> Self {
-        EngineIoService {
-            inner,
-            engine: Arc::new(EngineIo::new(handler, config)),
+        EngineIoService::from_engine(inner, EngineIo::start(handler, config))
+    }
+
+    /// Create a new [`EngineIoService`] with a custom inner service from an existing engine.
+    pub(crate) fn from_engine(inner: S, engine: Arc<EngineIo<H>>) -> Self {
+        EngineIoService { inner, engine }
+    }
+
+    /// Get a [`EngineIoHandle`] to the server of this service.
+    pub fn handle(&self) -> EngineIoHandle<H::Data> {
+        self.engine.handle()
+    }
+
+    /// Gracefully shutdown the server of this service.
+    ///
+    /// The [`EngineIoHandler::on_shutdown`] hook is awaited first, while the sockets are still open.
+    /// All the sockets are then closed with the [`DisconnectReason::ClosingServer`](crate::DisconnectReason::ClosingServer) reason.
+    pub async fn shutdown(&self) {
+        self.engine.shutdown().await
+    }
+
+    /// Drive the engine.io protocol over a websocket connection that was already upgraded,
+    /// e.g. by a custom acceptor, without going through the http service.
+    ///
+    /// A new session is created for the connection and the open packet is sent.
+    /// The messages are then handled as for the connections upgraded by the engine,
+    /// with the same heartbeat, until the connection is closed, when the returned future resolves.
+    /// Upgrades of polling sessions are not supported through this method.
+    ///
+    /// If the server doesn't accept new sessions (paused, shutting down or rate limited),
+    /// the connection is closed right away with a `1013 Try Again Later` close frame.
+    ///
+    /// #### Example
+    /// ```no_run
+    /// # use engineioxide::{handler::EngineIoHandler, service::*, Socket, DisconnectReason};
+    /// # use engineioxide::service::tungstenite::protocol::Role;
+    /// # use std::sync::Arc;
+    /// # #[derive(Debug)]
+    /// # struct MyHandler;
+    /// # impl EngineIoHandler for MyHandler {
+    /// #     type Data = ();
+    /// #     fn on_connect(&self, socket: Arc<Socket<()>>) { }
+    /// #     fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) { }
+    /// #     fn on_message(&self, msg: String, socket: Arc<Socket<()>>) { }
+    /// #     fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) { }
+    /// # }
+    /// # async fn doc(stream: tokio::net::TcpStream, req: http::Request<()>) {
+    /// let svc = EngineIoService::new(MyHandler);
+    /// // The http upgrade was handled by a custom acceptor
+    /// let ws = tokio_tungstenite::WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+    /// let (parts, _) = req.into_parts();
+    /// svc.on_ws_connection(ws, WsHandshake::new(ProtocolVersion::V4, parts)).await;
+    /// # }
+    /// ```
+    pub async fn on_ws_connection<W>(&self, ws: W, handshake: WsHandshake)
+    where
+        W: Stream<Item = Result<Message, WsError>>
+            + Sink<Message, Error = WsError>
+            + Unpin
+            + Send
+            + 'static,
+    {
+        let engine = self.engine.clone();
+        if let Err(_e) = ws::on_external(engine, ws, handshake.protocol, handshake.req).await {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("external ws closed with error: {:?}", _e);
         }
     }
 
@@ -125,8 +217,7 @@ where
     }
 
     fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
-        let path = self.engine.config.req_path.as_ref();
-        if req.uri().path().starts_with(path) {
+        if self.engine.config.matches_path(req.uri().path()) {
             dispatch_req(req, self.engine.clone())
         } else {
             ResponseFuture::new(self.inner.call(req))
@@ -149,8 +240,7 @@ where
     type Future = ResponseFuture<S::Future, ResBody>;
 
     fn call(&self, req: Request<ReqBody>) -> Self::Future {
-        let path = self.engine.config.req_path.as_ref();
-        if req.uri().path().starts_with(path) {
+        if self.engine.config.matches_path(req.uri().path()) {
             dispatch_req(req, self.engine.clone())
         } else {
             ResponseFuture::new(self.inner.call(req))
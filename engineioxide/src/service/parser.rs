@@ -3,18 +3,16 @@
 use std::{str::FromStr, sync::Arc};
 
 use futures::Future;
-use http::{Method, Request, Response};
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
 
 use crate::{
-    body::ResponseBody,
-    config::EngineIoConfig,
-    engine::EngineIo,
-    handler::EngineIoHandler,
-    service::futures::ResponseFuture,
-    sid::Sid,
-    transport::{polling, ws},
+    body::ResponseBody, config::EngineIoConfig, engine::EngineIo, errors::Error,
+    handler::EngineIoHandler, service::futures::ResponseFuture, sid::Sid, transport::ws,
 };
 
+#[cfg(feature = "polling")]
+use crate::transport::polling;
+
 /// Dispatch a request according to the [`RequestInfo`] to the appropriate [`transport`](crate::transport).
 pub fn dispatch_req<F, H, ReqBody, ResBody>(
     req: Request<ReqBody>,
@@ -28,7 +26,13 @@ where
     H: EngineIoHandler,
     F: Future,
 {
+    let proxy_buffering = engine.config.proxy_buffering;
+    let no_cache = move |mut res: Response<ResponseBody<ResBody>>| {
+        set_no_cache_headers(res.headers_mut(), proxy_buffering);
+        res
+    };
     match RequestInfo::parse(&req, &engine.config) {
+        #[cfg(feature = "polling")]
         Ok(RequestInfo {
             protocol,
             sid: None,
@@ -36,49 +40,91 @@ where
             method: Method::GET,
             #[cfg(feature = "v3")]
             b64,
-        }) => ResponseFuture::ready(polling::open_req(
-            engine,
-            protocol,
-            req,
-            #[cfg(feature = "v3")]
-            !b64,
-        )),
+        }) => no_cache_response(
+            polling::open_req(
+                engine,
+                protocol,
+                req,
+                #[cfg(feature = "v3")]
+                b64,
+            ),
+            no_cache,
+        ),
+        #[cfg(feature = "polling")]
         Ok(RequestInfo {
             protocol,
             sid: Some(sid),
             transport: TransportType::Polling,
             method: Method::GET,
             ..
-        }) => ResponseFuture::async_response(Box::pin(polling::polling_req(engine, protocol, sid))),
+        }) => no_cache_response(
+            polling::polling_req(engine, protocol, sid, req.into_parts().0.headers),
+            no_cache,
+        ),
+        #[cfg(feature = "polling")]
         Ok(RequestInfo {
             protocol,
             sid: Some(sid),
             transport: TransportType::Polling,
             method: Method::POST,
             ..
-        }) => {
-            ResponseFuture::async_response(Box::pin(polling::post_req(engine, protocol, sid, req)))
-        }
+        }) => no_cache_response(polling::post_req(engine, protocol, sid, req), no_cache),
         Ok(RequestInfo {
             protocol,
             sid,
             transport: TransportType::Websocket,
             method: Method::GET,
-            ..
-        }) => ResponseFuture::ready(ws::new_req(engine, protocol, sid, req)),
+            #[cfg(feature = "v3")]
+            b64,
+        }) => ResponseFuture::ready(ws::new_req(
+            engine,
+            protocol,
+            sid,
+            req,
+            #[cfg(feature = "v3")]
+            b64,
+        )),
         Err(e) => {
             #[cfg(feature = "tracing")]
             tracing::debug!("error parsing request: {:?}", e);
-            ResponseFuture::ready(Ok(e.into()))
+            ResponseFuture::ready(Ok(no_cache(e.into())))
         }
         _req => {
             #[cfg(feature = "tracing")]
             tracing::debug!("invalid request: {:?}", _req);
-            ResponseFuture::empty_response(400)
+            let err = Error::HttpErrorResponse(StatusCode::BAD_REQUEST);
+            ResponseFuture::ready(Ok(no_cache(err.into())))
         }
     }
 }
 
+/// Resolves a polling request and sets the no-cache headers on its response, including error responses.
+#[cfg(feature = "polling")]
+fn no_cache_response<F, ResBody>(
+    res: impl Future<Output = Result<Response<ResponseBody<ResBody>>, Error>> + Send + 'static,
+    no_cache: impl FnOnce(Response<ResponseBody<ResBody>>) -> Response<ResponseBody<ResBody>>
+        + Send
+        + 'static,
+) -> ResponseFuture<F, ResBody> {
+    ResponseFuture::async_response(Box::pin(async move {
+        Ok(no_cache(res.await.unwrap_or_else(Response::from)))
+    }))
+}
+
+/// Sets the headers preventing intermediaries from caching or transforming polling responses.
+/// The client also appends a `t` cache-busting param to its requests, it is accepted and ignored.
+fn set_no_cache_headers(headers: &mut HeaderMap, proxy_buffering: bool) {
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("no-store, no-transform"),
+    );
+    headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+    headers.insert(header::EXPIRES, HeaderValue::from_static("0"));
+    if !proxy_buffering {
+        headers.insert("x-accel-buffering", HeaderValue::from_static("no"));
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ParseError {
     #[error("transport unknown")]
@@ -172,6 +218,7 @@ impl FromStr for TransportType {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "websocket" => Ok(TransportType::Websocket),
+            #[cfg(feature = "polling")]
             "polling" => Ok(TransportType::Polling),
             _ => Err(ParseError::UnknownTransport),
         }
@@ -223,6 +270,10 @@ impl RequestInfo {
             .ok_or(UnsupportedProtocolVersion)
             .and_then(|t| t.parse())?;
 
+        if !config.allowed_version(protocol) {
+            return Err(UnsupportedProtocolVersion);
+        }
+
         let sid = query
             .split('&')
             .find(|s| s.starts_with("sid="))
@@ -272,6 +323,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "polling")]
     fn request_info_polling() {
         let req = build_request("http://localhost:3000/socket.io/?EIO=4&transport=polling");
         let info = RequestInfo::parse(&req, &EngineIoConfig::default()).unwrap();
@@ -341,12 +393,31 @@ mod tests {
         assert!(matches!(err, ParseError::UnknownTransport));
     }
     #[test]
+    #[cfg(not(feature = "polling"))]
+    fn polling_transport_unknown_err() {
+        let req = build_request("http://localhost:3000/socket.io/?EIO=4&transport=polling");
+        let err = RequestInfo::parse(&req, &EngineIoConfig::default()).unwrap_err();
+        assert!(matches!(err, ParseError::UnknownTransport));
+    }
+    #[test]
     fn unsupported_protocol_version() {
         let req = build_request("http://localhost:3000/socket.io/?EIO=2&transport=polling");
         let err = RequestInfo::parse(&req, &EngineIoConfig::default()).unwrap_err();
         assert!(matches!(err, ParseError::UnsupportedProtocolVersion));
     }
     #[test]
+    #[cfg(all(feature = "v3", feature = "polling"))]
+    fn disallowed_protocol_version() {
+        let req = build_request("http://localhost:3000/socket.io/?EIO=3&transport=polling");
+        assert!(RequestInfo::parse(&req, &EngineIoConfig::default()).is_ok());
+        let config = EngineIoConfig::builder()
+            .allowed_versions([ProtocolVersion::V4])
+            .build();
+        let err = RequestInfo::parse(&req, &config).unwrap_err();
+        assert!(matches!(err, ParseError::UnsupportedProtocolVersion));
+    }
+    #[test]
+    #[cfg(feature = "polling")]
     fn bad_handshake_method() {
         let req = Request::post("http://localhost:3000/socket.io/?EIO=4&transport=polling")
             .body(())
@@ -356,6 +427,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "polling")]
     fn unsupported_transport() {
         let req = build_request("http://localhost:3000/socket.io/?EIO=4&transport=polling");
         let err = RequestInfo::parse(
@@ -0,0 +1,147 @@
+//! ## Session store used to validate session ids across processes
+//!
+//! Engine.io sockets are always stored in the process that created them. However, behind a load balancer
+//! without sticky sessions, a polling request for an existing session may land on another process.
+//!
+//! A [`SessionStore`] holds the existence and metadata of every session so that such a process
+//! can tell apart an unknown session from a session owned by another process. In the latter case, a polling
+//! request gets a close packet so that the client can cleanly reconnect, instead of a "Session ID unknown" error.
+//!
+//! By default, the [`MemorySessionStore`] is used, it only knows about the sessions of the current process.
+//!
+//! #### Example :
+//! ```rust
+//! # use engineioxide::config::EngineIoConfig;
+//! # use engineioxide::session::{SessionMetadata, SessionStore};
+//! # use engineioxide::sid::Sid;
+//! # use futures::future::BoxFuture;
+//! /// A store sharing the sessions between all the processes, for example with redis
+//! #[derive(Debug)]
+//! struct SharedStore;
+//!
+//! impl SessionStore for SharedStore {
+//!     fn exists(&self, sid: Sid) -> BoxFuture<'static, bool> {
+//!         Box::pin(async move { false /* query the shared store */ })
+//!     }
+//!     fn touch(&self, sid: Sid, metadata: SessionMetadata) -> BoxFuture<'static, ()> {
+//!         Box::pin(async move { /* insert the session or refresh its expiry */ })
+//!     }
+//!     fn remove(&self, sid: Sid) -> BoxFuture<'static, ()> {
+//!         Box::pin(async move { /* remove the session */ })
+//!     }
+//!     fn metadata(&self, sid: Sid) -> BoxFuture<'static, Option<SessionMetadata>> {
+//!         Box::pin(async move { None /* query the shared store */ })
+//!     }
+//! }
+//!
+//! let config = EngineIoConfig::builder()
+//!     .session_store(SharedStore)
+//!     .build();
+//! ```
+
+use std::{collections::HashMap, sync::RwLock};
+
+use futures::future::BoxFuture;
+
+use crate::{service::ProtocolVersion, sid::Sid, TransportType};
+
+/// Metadata of an engine.io session stored in a [`SessionStore`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionMetadata {
+    /// The transport currently used by the session
+    pub transport: TransportType,
+    /// The engine.io protocol version of the session
+    pub protocol: ProtocolVersion,
+}
+
+/// A store holding the existence and metadata of the engine.io sessions.
+///
+/// Only the session validation goes through the store, the sockets themselves stay in the process that created them.
+///
+/// The store is:
+/// * touched when a session is created, at each polling request and when it is upgraded to websocket.
+/// * queried with [`exists`](SessionStore::exists) when a request is received for a session that is not
+///   in the current process.
+/// * cleared when a session is closed.
+///
+/// A store expiring its entries should use an expiry greater than `ping_interval + ping_timeout`.
+///
+/// The methods may be called while the engine holds its socket map lock, so that a touch can't
+/// be reordered with the removal of the same session. They should return quickly and do the I/O in the returned future.
+///
+/// Errors of the underlying storage should be handled by the implementation,
+/// for example by considering the session as unknown.
+pub trait SessionStore: std::fmt::Debug + Send + Sync + 'static {
+    /// Returns true if the session exists, in any process.
+    fn exists(&self, sid: Sid) -> BoxFuture<'static, bool>;
+
+    /// Inserts or refreshes a session with its metadata.
+    fn touch(&self, sid: Sid, metadata: SessionMetadata) -> BoxFuture<'static, ()>;
+
+    /// Removes a session.
+    fn remove(&self, sid: Sid) -> BoxFuture<'static, ()>;
+
+    /// Returns the metadata of a session if it exists.
+    fn metadata(&self, sid: Sid) -> BoxFuture<'static, Option<SessionMetadata>>;
+}
+
+/// The default [`SessionStore`]. It stores the sessions of the current process in memory.
+///
+/// The map is updated when the methods are called, the returned futures are already resolved.
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: RwLock<HashMap<Sid, SessionMetadata>>,
+}
+
+impl SessionStore for MemorySessionStore {
+    fn exists(&self, sid: Sid) -> BoxFuture<'static, bool> {
+        let exists = self.sessions.read().unwrap().contains_key(&sid);
+        Box::pin(async move { exists })
+    }
+
+    fn touch(&self, sid: Sid, metadata: SessionMetadata) -> BoxFuture<'static, ()> {
+        self.sessions.write().unwrap().insert(sid, metadata);
+        Box::pin(async {})
+    }
+
+    fn remove(&self, sid: Sid) -> BoxFuture<'static, ()> {
+        self.sessions.write().unwrap().remove(&sid);
+        Box::pin(async {})
+    }
+
+    fn metadata(&self, sid: Sid) -> BoxFuture<'static, Option<SessionMetadata>> {
+        let metadata = self.sessions.read().unwrap().get(&sid).copied();
+        Box::pin(async move { metadata })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_store() {
+        let store = MemorySessionStore::default();
+        let sid = Sid::new();
+        let metadata = SessionMetadata {
+            transport: TransportType::Polling,
+            protocol: ProtocolVersion::V4,
+        };
+        assert!(!store.exists(sid).await);
+        assert_eq!(store.metadata(sid).await, None);
+
+        store.touch(sid, metadata).await;
+        assert!(store.exists(sid).await);
+        assert_eq!(store.metadata(sid).await, Some(metadata));
+
+        let upgraded = SessionMetadata {
+            transport: TransportType::Websocket,
+            ..metadata
+        };
+        store.touch(sid, upgraded).await;
+        assert_eq!(store.metadata(sid).await, Some(upgraded));
+
+        store.remove(sid).await;
+        assert!(!store.exists(sid).await);
+    }
+}
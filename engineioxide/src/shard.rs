@@ -0,0 +1,226 @@
+//! ## A map of values keyed by [`Sid`], sharded to lower the lock contention
+//!
+//! With a single lock over all the sockets, every handshake, disconnection and broadcast contends on it
+//! once there are tens of thousands of connections. A [`ShardedMap`] splits the map into buckets selected
+//! by a hash of the sid, each with its own lock, so that the operations on different sids rarely contend.
+//!
+//! The number of shards is set with [`EngineIoConfigBuilder::socket_shards`](crate::config::EngineIoConfigBuilder::socket_shards),
+//! it defaults to [`default_shard_count`].
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{BuildHasher, BuildHasherDefault},
+    sync::RwLock,
+};
+
+use crate::sid::Sid;
+
+/// Returns the default number of shards: four times the number of available CPUs
+pub fn default_shard_count() -> usize {
+    std::thread::available_parallelism().map_or(4, |n| n.get() * 4)
+}
+
+/// A map of values keyed by [`Sid`], sharded by a hash of the sid, see the [module level documentation](self).
+///
+/// The operations on a single sid only lock its shard.
+/// The operations on the whole map ([`len`](Self::len), [`values`](Self::values), ...) lock the shards one by one,
+/// so they are not atomic over the whole map.
+pub struct ShardedMap<T> {
+    shards: Box<[RwLock<HashMap<Sid, T>>]>,
+}
+
+impl<T> ShardedMap<T> {
+    /// Creates an empty map with the given number of shards
+    ///
+    /// # Panics
+    /// If `shards` is zero.
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "the number of shards must be greater than zero");
+        Self {
+            shards: (0..shards).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    /// Returns the number of shards of the map
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_index(&self, sid: &Sid) -> usize {
+        let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(sid);
+        (hash % self.shards.len() as u64) as usize
+    }
+
+    fn shard(&self, sid: &Sid) -> &RwLock<HashMap<Sid, T>> {
+        &self.shards[self.shard_index(sid)]
+    }
+
+    /// Inserts a value, returning the previous value of the sid if there was one
+    pub fn insert(&self, sid: Sid, value: T) -> Option<T> {
+        self.shard(&sid).write().unwrap().insert(sid, value)
+    }
+
+    /// Removes a value, returning it if it was in the map
+    pub fn remove(&self, sid: &Sid) -> Option<T> {
+        self.shard(sid).write().unwrap().remove(sid)
+    }
+
+    /// Returns true if the map contains a value for the sid
+    pub fn contains_key(&self, sid: &Sid) -> bool {
+        self.shard(sid).read().unwrap().contains_key(sid)
+    }
+
+    /// Calls `f` with the shard of the sid locked for reading
+    pub fn read_shard<R>(&self, sid: &Sid, f: impl FnOnce(&HashMap<Sid, T>) -> R) -> R {
+        f(&self.shard(sid).read().unwrap())
+    }
+
+    /// Calls `f` with the shard of the sid locked for writing
+    pub fn write_shard<R>(&self, sid: &Sid, f: impl FnOnce(&mut HashMap<Sid, T>) -> R) -> R {
+        f(&mut self.shard(sid).write().unwrap())
+    }
+
+    /// Returns the number of values, summed across the shards
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().unwrap().len()).sum()
+    }
+
+    /// Returns true if the map contains no values
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.read().unwrap().is_empty())
+    }
+
+    /// Removes all the values
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap().clear();
+        }
+    }
+
+    /// Shrinks the capacity of each shard as much as possible
+    pub fn shrink_to_fit(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap().shrink_to_fit();
+        }
+    }
+}
+
+impl<T: Clone> ShardedMap<T> {
+    /// Returns a clone of the value of the sid
+    pub fn get(&self, sid: &Sid) -> Option<T> {
+        self.shard(sid).read().unwrap().get(sid).cloned()
+    }
+
+    /// Returns a clone of the values of the sids, in the same order.
+    ///
+    /// The sids are grouped by shard so that each shard is locked at most once.
+    pub fn get_many(&self, sids: &[Sid]) -> Vec<Option<T>> {
+        let mut by_shard = vec![Vec::new(); self.shards.len()];
+        for (i, sid) in sids.iter().enumerate() {
+            by_shard[self.shard_index(sid)].push(i);
+        }
+        let mut values = vec![None; sids.len()];
+        for (shard, indices) in self.shards.iter().zip(by_shard) {
+            if indices.is_empty() {
+                continue;
+            }
+            let shard = shard.read().unwrap();
+            for i in indices {
+                values[i] = shard.get(&sids[i]).cloned();
+            }
+        }
+        values
+    }
+
+    /// Returns a clone of all the values, iterating the shards one by one
+    pub fn values(&self) -> Vec<T> {
+        self.filter_values(|_| true)
+    }
+
+    /// Returns a clone of the values matching the predicate, iterating the shards one by one
+    pub fn filter_values(&self, mut predicate: impl FnMut(&T) -> bool) -> Vec<T> {
+        let mut values = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.read().unwrap();
+            values.extend(shard.values().filter(|v| predicate(v)).cloned());
+        }
+        values
+    }
+}
+
+impl<T> Default for ShardedMap<T> {
+    fn default() -> Self {
+        Self::new(default_shard_count())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for ShardedMap<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut map = f.debug_map();
+        for shard in self.shards.iter() {
+            map.entries(shard.read().unwrap().iter());
+        }
+        map.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_across_shards() {
+        let map = ShardedMap::new(8);
+        let sids: Vec<_> = (0..100).map(|_| Sid::new()).collect();
+        for (i, sid) in sids.iter().enumerate() {
+            assert_eq!(map.insert(*sid, i), None);
+        }
+        assert_eq!(map.len(), 100);
+        assert!(!map.is_empty());
+        assert_eq!(map.get(&sids[42]), Some(42));
+        assert!(map.contains_key(&sids[7]));
+
+        let mut values = map.values();
+        values.sort_unstable();
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+        assert_eq!(map.filter_values(|v| v % 2 == 0).len(), 50);
+
+        assert_eq!(map.remove(&sids[42]), Some(42));
+        assert_eq!(map.remove(&sids[42]), None);
+        assert_eq!(map.get(&sids[42]), None);
+        assert_eq!(map.len(), 99);
+
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn get_many_keeps_the_order() {
+        let map = ShardedMap::new(8);
+        let sids: Vec<_> = (0..20).map(|_| Sid::new()).collect();
+        for (i, sid) in sids.iter().enumerate().filter(|(i, _)| i % 3 != 0) {
+            map.insert(*sid, i);
+        }
+        let values = map.get_many(&sids);
+        for (i, value) in values.into_iter().enumerate() {
+            assert_eq!(value, (i % 3 != 0).then_some(i));
+        }
+        assert!(map.get_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn values_are_spread_over_the_shards() {
+        let map = ShardedMap::new(4);
+        for _ in 0..1000 {
+            map.insert(Sid::new(), ());
+        }
+        for shard in map.shards.iter() {
+            assert!(shard.read().unwrap().len() > 100);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_shards() {
+        ShardedMap::<()>::new(0);
+    }
+}
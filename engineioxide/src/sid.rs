@@ -16,12 +16,14 @@ pub struct Sid([u8; 16]);
 impl Sid {
     /// A zeroed session id
     pub const ZERO: Self = Self([0u8; 16]);
-    /// Generate a new random session id (base64 10 chars)
+    /// Generate a new random session id (base64 16 chars)
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// Get the session id as a base64 10 chars string
+    /// Get the session id as a base64 16 chars string.
+    ///
+    /// It is the form sent to the clients, it can be parsed back with [`Sid::from_str`].
     pub fn as_str(&self) -> &str {
         // SAFETY: SID is always a base64 chars string
         unsafe { std::str::from_utf8_unchecked(&self.0) }
@@ -130,6 +132,20 @@ mod tests {
         assert_eq!(id.to_string(), "AA9AAA0AAzAAAAHs");
     }
 
+    #[test]
+    fn test_sid_round_trip() {
+        for _ in 0..1000 {
+            let id = Sid::new();
+            let s = id.to_string();
+            assert_eq!(s, id.as_str());
+            assert_eq!(format!("{id:?}"), s);
+            assert_eq!(Sid::from_str(&s).unwrap(), id);
+            let json = serde_json::to_string(&id).unwrap();
+            assert_eq!(json, format!("\"{s}\""));
+            assert_eq!(serde_json::from_str::<Sid>(&json).unwrap(), id);
+        }
+    }
+
     #[test]
     fn test_sid_from_str_invalid() {
         let id = Sid::from_str("*$^ùù!").unwrap_err();
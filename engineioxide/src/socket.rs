@@ -54,31 +54,45 @@
 //! let svc = EngineIoService::new(MyHandler::default());
 //! ```
 use std::{
+    collections::VecDeque,
+    future::Future,
     sync::{
-        atomic::{AtomicU8, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
+use futures::FutureExt;
 use http::request::Parts;
 use smallvec::{smallvec, SmallVec};
 use tokio::{
-    sync::{
-        mpsc::{self},
-        mpsc::{error::TrySendError, Receiver},
-        Mutex,
-    },
+    runtime::Handle,
+    sync::{mpsc::error::TrySendError, oneshot, Mutex, Notify},
     task::JoinHandle,
+    time::Instant,
+};
+use tokio_tungstenite::tungstenite::{
+    self,
+    protocol::{frame::coding::CloseCode, CloseFrame},
 };
-use tokio_tungstenite::tungstenite;
 
 use crate::{
-    config::EngineIoConfig, errors::Error, packet::Packet, peekable::PeekableReceiver,
+    channel::{self, channel, channel_with},
+    config::{spawn_on, AdaptiveHeartbeat, ClientPingPolicy, EngineIoConfig},
+    errors::Error,
+    packet::Packet,
+    peekable::PeekableReceiver,
+    recorder::{Direction, SessionRecorder, SessionRecording},
+    reliable::{self, PendingAcks, ReliableError},
     service::ProtocolVersion,
 };
 use crate::{service::TransportType, sid::Sid};
 
+/// The sender of a [`Socket::binary_stream`]
+type BinaryStreamTx =
+    tokio::sync::mpsc::UnboundedSender<Result<bytes::Bytes, crate::stream::StreamError>>;
+
 /// A [`DisconnectReason`] represents the reason why a [`Socket`] was closed.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DisconnectReason {
@@ -93,10 +107,49 @@ pub enum DisconnectReason {
     TransportError,
     /// The client did not respond to the heartbeat
     HeartbeatTimeout,
+    /// The client did not send any message for the [`EngineIoConfig::idle_timeout`] duration
+    IdleTimeout,
+    /// The socket was connected for the [`EngineIoConfig::max_connection_lifetime`] duration
+    LifetimeExpired,
+    /// The socket was still on the polling transport after the grace of
+    /// [`EngineIoHandle::force_upgrade_or_close`](crate::handler::EngineIoHandle::force_upgrade_or_close)
+    UpgradeRequired,
     /// The server is being closed
     ClosingServer,
 }
 
+impl DisconnectReason {
+    /// The code of the websocket close frame sent to the client when the socket is closed for this reason
+    pub(crate) fn ws_close_code(&self) -> CloseCode {
+        use DisconnectReason::*;
+        match self {
+            TransportClose | HeartbeatTimeout | IdleTimeout => CloseCode::Normal,
+            MultipleHttpPollingError | UpgradeRequired => CloseCode::Policy,
+            PacketParsingError => CloseCode::Protocol,
+            TransportError => CloseCode::Error,
+            LifetimeExpired => CloseCode::Restart,
+            ClosingServer => CloseCode::Away,
+        }
+    }
+}
+
+/// The maximum length in bytes of the reason of a websocket close frame,
+/// the 125 bytes of a control frame payload minus the 2 bytes of the close code
+const MAX_CLOSE_REASON_LEN: usize = 123;
+
+/// Creates a websocket close frame, the reason is truncated to [`MAX_CLOSE_REASON_LEN`] bytes
+/// on a char boundary
+pub(crate) fn close_frame(code: CloseCode, reason: &str) -> CloseFrame<'static> {
+    let mut len = reason.len().min(MAX_CLOSE_REASON_LEN);
+    while !reason.is_char_boundary(len) {
+        len -= 1;
+    }
+    CloseFrame {
+        code,
+        reason: reason[..len].to_string().into(),
+    }
+}
+
 /// Convert an [`Error`] to a [`DisconnectReason`] if possible
 /// This is used to notify the [`Handler`](crate::handler::EngineIoHandler) of the reason why a [`Socket`] was closed
 /// If the error cannot be converted to a [`DisconnectReason`] it means that the error was not fatal and the [`Socket`] can be kept alive
@@ -116,21 +169,34 @@ impl From<&Error> for Option<DisconnectReason> {
     }
 }
 
+/// Error returned by [`Socket::send_and_flush`]
+#[derive(thiserror::Error, Debug)]
+pub enum FlushError {
+    /// The message could not be buffered, the original message is returned
+    #[error("error buffering the message: {0}")]
+    Send(#[from] TrySendError<String>),
+    /// The socket was closed, the transport failed or the message expired before it was flushed
+    #[error("the message was not flushed to the client")]
+    NotFlushed,
+}
+
 /// A permit to emit a message to the client.
 /// A permit holds a place in the internal channel to send one packet to the client.
 pub struct Permit<'a> {
-    inner: mpsc::Permit<'a, PacketBuf>,
+    inner: channel::Permit<'a, PacketBuf>,
+    /// The recorder of the socket, if it was recorded when the permit was reserved
+    recorder: Option<(Sid, Arc<dyn SessionRecorder>)>,
 }
 impl Permit<'_> {
     /// Consume the permit and emit a message to the client.
     #[inline]
     pub fn emit(self, msg: String) {
-        self.inner.send(smallvec![Packet::Message(msg)]);
+        self.send(smallvec![Packet::Message(msg)]);
     }
     /// Consume the permit and emit a binary message to the client.
     #[inline]
     pub fn emit_binary(self, data: Vec<u8>) {
-        self.inner.send(smallvec![Packet::Binary(data)]);
+        self.send(smallvec![Packet::Binary(data)]);
     }
 
     /// Consume the permit and emit a message with multiple binary data to the client.
@@ -142,12 +208,122 @@ impl Permit<'_> {
         for d in data {
             packets.push(Packet::Binary(d));
         }
-        self.inner.send(packets);
+        self.send(packets);
+    }
+
+    fn send(self, packets: SmallVec<[Packet; 10]>) {
+        if let Some((sid, recorder)) = self.recorder {
+            record(sid, &*recorder, Direction::Outbound, &packets);
+        }
+        self.inner.send(packets.into());
+    }
+}
+
+/// Records the packets with the given recorder, they share the same timestamp
+#[cold]
+fn record(sid: Sid, recorder: &dyn SessionRecorder, direction: Direction, packets: &[Packet]) {
+    let at = SystemTime::now();
+    for packet in packets {
+        recorder.record(sid, direction, packet, at);
+    }
+}
+
+/// Buffered packets to send to the client, they are stored in the [`PacketChannel`](crate::channel::PacketChannel) of the socket.
+///
+/// Adjacent packets are sent atomically. An optional deadline can be set so that
+/// the packets are dropped by the transport if they could not be sent in time.
+#[derive(Debug)]
+pub struct PacketBuf {
+    packets: SmallVec<[Packet; 10]>,
+    deadline: Option<Instant>,
+    /// Notified once the packets are flushed to the client
+    flushed: Option<oneshot::Sender<()>>,
+}
+
+impl PacketBuf {
+    /// Create a new [`PacketBuf`] that should be dropped if it is not sent before the `deadline`
+    pub(crate) fn with_deadline(packets: SmallVec<[Packet; 10]>, deadline: Instant) -> Self {
+        Self {
+            packets,
+            deadline: Some(deadline),
+            flushed: None,
+        }
+    }
+
+    /// Create a new [`PacketBuf`] that notifies the `flushed` channel once it is flushed to the client
+    pub(crate) fn with_flush_notifier(
+        packets: SmallVec<[Packet; 10]>,
+        flushed: oneshot::Sender<()>,
+    ) -> Self {
+        Self {
+            packets,
+            deadline: None,
+            flushed: Some(flushed),
+        }
+    }
+
+    /// Take the flush notifier of the packets, the transport should notify it once they are flushed.
+    /// If it is dropped without being notified, the emitter is told that the packets were not flushed.
+    pub(crate) fn take_flush_notifier(&mut self) -> Option<oneshot::Sender<()>> {
+        self.flushed.take()
+    }
+
+    /// Copies the packets and moves the flush notifier to the copy,
+    /// so that the copy can be sent again if the transport fails to flush the packets.
+    pub(crate) fn take_retry_copy(&mut self) -> Self {
+        Self {
+            packets: self.packets.clone(),
+            deadline: self.deadline,
+            flushed: self.flushed.take(),
+        }
+    }
+
+    /// Returns true if the deadline of the packets is passed and they should not be sent anymore
+    pub(crate) fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+    }
+}
+
+impl From<SmallVec<[Packet; 10]>> for PacketBuf {
+    fn from(packets: SmallVec<[Packet; 10]>) -> Self {
+        Self {
+            packets,
+            deadline: None,
+            flushed: None,
+        }
+    }
+}
+
+impl std::ops::Deref for PacketBuf {
+    type Target = SmallVec<[Packet; 10]>;
+    fn deref(&self) -> &Self::Target {
+        &self.packets
+    }
+}
+impl std::ops::DerefMut for PacketBuf {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.packets
+    }
+}
+
+impl IntoIterator for PacketBuf {
+    type Item = Packet;
+    type IntoIter = smallvec::IntoIter<[Packet; 10]>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.packets.into_iter()
     }
 }
 
-/// Buffered packets to send to the client
-pub(crate) type PacketBuf = SmallVec<[Packet; 10]>;
+/// The timestamps of the last heartbeat round-trip of a [`Socket`]
+#[derive(Debug, Default, Clone, Copy)]
+struct HeartbeatStatus {
+    last_ping_at: Option<Instant>,
+    last_pong_at: Option<Instant>,
+    last_rtt: Option<Duration>,
+    interval: Option<Duration>,
+}
+
 /// A [`Socket`] represents a client connection to the server.
 /// It is agnostic to the [`TransportType`].
 ///
@@ -166,11 +342,35 @@ where
     /// The protocol version used by the socket
     pub protocol: ProtocolVersion,
 
+    /// The correlation id of the connection, captured from a request header at handshake or random
+    correlation_id: Box<str>,
+    /// How the payloads of the packets are written in the tracing events
+    #[cfg(feature = "tracing")]
+    payload_logging: crate::config::PayloadLogging,
+    /// The span of the tracing events of the socket, with its id and its correlation id
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+
     /// The transport type represented as a bitfield
     /// It is represented as a bitfield to allow the use of an [`AtomicU8`] so it can be shared between threads
     /// without any mutex
     transport: AtomicU8,
 
+    /// Set while the client is upgrading from polling to websocket, between the probe and the upgrade packet.
+    /// Polling requests are released without data during this window so that the buffered packets
+    /// are sent in order over the websocket once it is active.
+    #[cfg(feature = "polling")]
+    upgrading: AtomicBool,
+
+    /// If the socket was opened with the polling transport, only these sockets can be downgraded back to polling
+    #[cfg(feature = "polling")]
+    polling_capable: bool,
+
+    /// Notified when an upgrade starts, to release the polling request parked on the socket.
+    /// It doesn't go through the packet queue so that the request is released even if the queue is full.
+    #[cfg(feature = "polling")]
+    poll_release: Notify,
+
     /// Channel to send [`PacketBuf`] to the connection
     ///
     /// It is used and managed by the [`EngineIo`](crate::engine) struct depending on the transport type
@@ -189,63 +389,175 @@ where
     pub(crate) internal_rx: Mutex<PeekableReceiver<PacketBuf>>,
 
     /// Channel to send [PacketBuf] to the internal connection
-    internal_tx: mpsc::Sender<PacketBuf>,
-
-    /// Internal channel to receive Pong [`Packets`](Packet) (v4 protocol) or Ping (v3 protocol) in the heartbeat job
-    /// which is running in a separate task
-    heartbeat_rx: Mutex<Receiver<()>>,
-    /// Channel to send Ping [`Packets`](Packet) (v4 protocol) or Ping (v3 protocol) from the connexion to the heartbeat job
-    /// which is running in a separate task
-    pub(crate) heartbeat_tx: mpsc::Sender<()>,
+    internal_tx: channel::Sender<PacketBuf>,
+    /// Priority lane of the internal channel for the control packets, drained before `internal_tx`
+    /// so that the heartbeat and the close packets are not delayed by a backlog of messages
+    priority_tx: channel::Sender<PacketBuf>,
+
+    /// Notifies the heartbeat job, which is running in a separate task, of the Pong [`Packets`](Packet) (v4 protocol)
+    /// or Ping (v3 protocol) received from the connexion.
+    ///
+    /// Notifying never blocks: the heartbeats received while the job is not waiting are coalesced into a single permit,
+    /// so a client sending several of them in a row never stalls the packet handling.
+    pub(crate) heartbeat: Notify,
     /// Handle to the heartbeat job so that it can be aborted when the socket is closed
     heartbeat_handle: Mutex<Option<JoinHandle<()>>>,
 
+    /// Lock held by a transport while it is dispatching received packets to the handler.
+    ///
+    /// When upgrading from polling to websocket, the websocket transport waits for this lock
+    /// so that every packet of an in-flight polling request is dispatched before
+    /// the first packet received on the websocket.
+    #[cfg(feature = "polling")]
+    pub(crate) recv_lock: Mutex<()>,
+
+    /// The close frame sent to a websocket client when the connection is closed.
+    /// If it is not set, an empty close frame is sent.
+    ws_close_frame: std::sync::Mutex<Option<CloseFrame<'static>>>,
+
+    /// Last time a websocket protocol-level pong frame was received
+    last_transport_activity: std::sync::Mutex<Instant>,
+    /// Last time a message (text or binary) was received, heartbeats are not taken into account
+    last_message_at: std::sync::Mutex<Instant>,
+    /// Creation time of the socket, the reference of `last_seen`
+    created_at: Instant,
+    /// Milliseconds between `created_at` and the last time any packet was received from the client
+    last_seen: AtomicU64,
+    /// Status of the last engine.io heartbeat round-trip, updated by the heartbeat job
+    heartbeat_status: std::sync::Mutex<HeartbeatStatus>,
+    /// Number of consecutive pings that were not answered in time
+    missed_pongs: AtomicU32,
+    /// The id of the next stream sent with [`Socket::send_stream`]
+    next_stream_id: AtomicU32,
+    /// The packets sent with [`Socket::emit_binary_reliable`] waiting for their ack
+    binary_acks: PendingAcks,
+    /// The sender of the stream returned by [`Socket::binary_stream`],
+    /// the received binary packets are forwarded to it instead of the handler
+    binary_stream_tx: std::sync::Mutex<Option<BinaryStreamTx>>,
+    /// The runtime on which the tasks of the socket are spawned
+    runtime: Option<Handle>,
+    /// If the heartbeat job is spawned (see [`EngineIoConfig::heartbeat`])
+    heartbeat_enabled: bool,
+    /// If transport activity should extend the engine.io heartbeat deadline
+    /// (see [`EngineIoConfig::transport_liveness`])
+    transport_liveness: bool,
+    /// The bounds of the heartbeat interval if it adapts to the round-trip times
+    /// (see [`EngineIoConfig::adaptive_heartbeat`])
+    adaptive_heartbeat: Option<AdaptiveHeartbeat>,
+    /// What to do when the client sends a ping with the v4 protocol
+    /// (see [`EngineIoConfig::client_ping_policy`])
+    client_ping_policy: ClientPingPolicy,
+
+    /// Set when the session is closed, the packets emitted afterwards are rejected
+    /// even if the transport still holds the internal channel
+    session_closed: AtomicBool,
+
     /// Function to call when the socket is closed
     close_fn: Box<dyn Fn(Sid, DisconnectReason) + Send + Sync>,
+
+    /// Set if the packets of the socket are recorded, it is checked before reading the recorder
+    recording: AtomicBool,
+    recorder: std::sync::RwLock<Option<Arc<dyn SessionRecorder>>>,
     /// User data bound to the socket
     pub data: D,
 
     /// Http Request data used to create a socket
     pub req_parts: Parts,
 
-    /// If the client supports binary packets (via polling XHR2)
+    /// If the client asked for base64 encoded binary packets with the `b64` query param
     #[cfg(feature = "v3")]
-    pub(crate) supports_binary: bool,
+    pub(crate) force_base64: bool,
 }
 
+/// The maximum length of a correlation id captured from a request header, longer ones are replaced by a random id
+const MAX_CORRELATION_ID_LEN: usize = 128;
+/// The capacity of the priority lane, only a few control packets are in flight at the same time
+const PRIORITY_BUFFER_SIZE: usize = 16;
+
 impl<D> Socket<D>
 where
     D: Default + Send + Sync + 'static,
 {
     pub(crate) fn new(
+        id: Sid,
         protocol: ProtocolVersion,
         transport: TransportType,
         config: &EngineIoConfig,
         req_parts: Parts,
         close_fn: Box<dyn Fn(Sid, DisconnectReason) + Send + Sync>,
-        #[cfg(feature = "v3")] supports_binary: bool,
+        #[cfg(feature = "v3")] force_base64: bool,
     ) -> Self {
-        let (internal_tx, internal_rx) = mpsc::channel(config.max_buffer_size);
-        let (heartbeat_tx, heartbeat_rx) = mpsc::channel(1);
+        let queue = match &config.packet_channel {
+            Some(factory) => factory.create(config.max_buffer_size),
+            None => Box::new(std::sync::Mutex::new(VecDeque::with_capacity(
+                config.max_buffer_size,
+            ))),
+        };
+        let (internal_tx, internal_rx) =
+            channel_with(queue, config.max_buffer_size, config.overflow_policy);
+        let (priority_tx, priority_rx) =
+            channel(PRIORITY_BUFFER_SIZE, crate::config::OverflowPolicy::Reject);
+        let correlation_id: Box<str> = config
+            .correlation_id_header
+            .as_ref()
+            .and_then(|header| req_parts.headers.get(header)?.to_str().ok())
+            .filter(|value| !value.is_empty() && value.len() <= MAX_CORRELATION_ID_LEN)
+            .map(Into::into)
+            .unwrap_or_else(|| Sid::new().to_string().into());
+        let recorder = config
+            .session_recording
+            .as_ref()
+            .and_then(SessionRecording::sample);
 
         Self {
-            id: Sid::new(),
+            id,
             protocol,
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!("engineio_socket", sid = %id, correlation_id = %correlation_id),
+            correlation_id,
+            #[cfg(feature = "tracing")]
+            payload_logging: config.payload_logging,
             transport: AtomicU8::new(transport as u8),
+            #[cfg(feature = "polling")]
+            upgrading: AtomicBool::new(false),
+            #[cfg(feature = "polling")]
+            polling_capable: transport == TransportType::Polling,
+            #[cfg(feature = "polling")]
+            poll_release: Notify::new(),
 
-            internal_rx: Mutex::new(PeekableReceiver::new(internal_rx)),
+            internal_rx: Mutex::new(PeekableReceiver::new(internal_rx, priority_rx)),
             internal_tx,
+            priority_tx,
 
-            heartbeat_rx: Mutex::new(heartbeat_rx),
-            heartbeat_tx,
+            heartbeat: Notify::new(),
             heartbeat_handle: Mutex::new(None),
+            #[cfg(feature = "polling")]
+            recv_lock: Mutex::new(()),
+            ws_close_frame: std::sync::Mutex::new(None),
+            last_transport_activity: std::sync::Mutex::new(Instant::now()),
+            last_message_at: std::sync::Mutex::new(Instant::now()),
+            created_at: Instant::now(),
+            last_seen: AtomicU64::new(0),
+            heartbeat_status: std::sync::Mutex::new(HeartbeatStatus::default()),
+            missed_pongs: AtomicU32::new(0),
+            next_stream_id: AtomicU32::new(0),
+            binary_acks: PendingAcks::default(),
+            binary_stream_tx: std::sync::Mutex::new(None),
+            heartbeat_enabled: config.heartbeat,
+            transport_liveness: config.transport_liveness,
+            adaptive_heartbeat: config.adaptive_heartbeat,
+            client_ping_policy: config.client_ping_policy,
+            runtime: config.runtime.clone(),
             close_fn,
+            session_closed: AtomicBool::new(false),
+            recording: AtomicBool::new(recorder.is_some()),
+            recorder: std::sync::RwLock::new(recorder),
 
             data: D::default(),
             req_parts,
 
             #[cfg(feature = "v3")]
-            supports_binary,
+            force_base64,
         }
     }
 
@@ -259,9 +571,52 @@ where
     /// Sends a packet to the connection.
     pub(crate) fn send(&self, packet: Packet) -> Result<(), TrySendError<Packet>> {
         #[cfg(feature = "tracing")]
-        tracing::debug!("[sid={}] sending packet: {:?}", self.id, packet);
+        tracing::debug!(
+            parent: &self.span,
+            "[sid={}] sending packet: {}",
+            self.id,
+            packet.log(self.payload_logging)
+        );
+        if self.is_session_closed() {
+            return Err(TrySendError::Closed(packet));
+        }
+        self.record(Direction::Outbound, std::slice::from_ref(&packet));
         self.internal_tx
-            .try_send(smallvec![packet])
+            .try_send(smallvec![packet].into())
+            .map_err(|p| match p {
+                TrySendError::Full(mut p) => {
+                    self.on_buffer_full();
+                    TrySendError::Full(p.pop().unwrap())
+                }
+                TrySendError::Closed(mut p) => TrySendError::Closed(p.pop().unwrap()),
+            })?;
+        Ok(())
+    }
+
+    /// Called when a packet is rejected because the buffer of the socket is full,
+    /// it usually means that the client doesn't read its packets fast enough.
+    fn on_buffer_full(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            parent: &self.span,
+            "[sid={}] packet rejected, the socket buffer is full",
+            self.id
+        );
+    }
+
+    /// Sends a control packet to the connection through the priority lane,
+    /// it is sent before the packets buffered with [`Socket::send`].
+    pub(crate) fn send_priority(&self, packet: Packet) -> Result<(), TrySendError<Packet>> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            parent: &self.span,
+            "[sid={}] sending priority packet: {}",
+            self.id,
+            packet.log(self.payload_logging)
+        );
+        self.record(Direction::Outbound, std::slice::from_ref(&packet));
+        self.priority_tx
+            .try_send(smallvec![packet].into())
             .map_err(|p| match p {
                 TrySendError::Full(mut p) => TrySendError::Full(p.pop().unwrap()),
                 TrySendError::Closed(mut p) => TrySendError::Closed(p.pop().unwrap()),
@@ -269,19 +624,165 @@ where
         Ok(())
     }
 
+    /// Sets the close frame sent to a websocket client when the connection is closed.
+    pub(crate) fn set_ws_close_frame(&self, frame: CloseFrame<'static>) {
+        self.ws_close_frame.lock().unwrap().replace(frame);
+    }
+
+    /// Takes the close frame to send to a websocket client, if one was set.
+    pub(crate) fn take_ws_close_frame(&self) -> Option<CloseFrame<'static>> {
+        self.ws_close_frame.lock().unwrap().take()
+    }
+
+    /// Returns the last time a websocket protocol-level pong frame was received from the client.
+    ///
+    /// It is only updated when [`EngineIoConfig::ws_ping_interval`] is set
+    /// and the socket is using the websocket transport.
+    /// Otherwise it is the creation time of the socket.
+    pub fn last_transport_activity(&self) -> Instant {
+        *self.last_transport_activity.lock().unwrap()
+    }
+
+    /// Record transport activity (e.g. a websocket pong frame)
+    pub(crate) fn touch_transport(&self) {
+        *self.last_transport_activity.lock().unwrap() = Instant::now();
+    }
+
+    /// Returns the last time a text or binary message was received from the client.
+    ///
+    /// Heartbeat packets are not taken into account. If no message was received, it is the creation time of the socket.
+    pub fn last_message_at(&self) -> Instant {
+        *self.last_message_at.lock().unwrap()
+    }
+
+    /// Record that a message was received from the client
+    pub(crate) fn touch_message(&self) {
+        *self.last_message_at.lock().unwrap() = Instant::now();
+    }
+
+    /// Returns the time at which the session was created
+    pub fn connected_at(&self) -> Instant {
+        self.created_at
+    }
+
+    /// Returns the last time any packet was received from the client: messages, heartbeats or close packets.
+    /// If no packet was received, it is the creation time of the socket.
+    ///
+    /// Unlike [`last_message_at`](Self::last_message_at), it is updated without lock so it is cheap to query for many sockets.
+    pub fn last_seen(&self) -> Instant {
+        self.created_at + Duration::from_millis(self.last_seen.load(Ordering::Relaxed))
+    }
+
+    /// Record that a packet was received from the client
+    pub(crate) fn touch_seen(&self) {
+        let elapsed = self.created_at.elapsed().as_millis() as u64;
+        self.last_seen.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    /// Returns false if the heartbeat is disabled (see [`EngineIoConfig::heartbeat`]).
+    /// The closed connections are then only detected by the transport,
+    /// and the heartbeat introspection methods of the socket always return `None`.
+    pub fn is_heartbeat_enabled(&self) -> bool {
+        self.heartbeat_enabled
+    }
+
+    /// Returns the last time the heartbeat job sent a ping packet to the client,
+    /// or received one from the client with the v3 protocol.
+    ///
+    /// It is always `None` if the heartbeat is disabled.
+    pub fn last_ping_at(&self) -> Option<Instant> {
+        self.heartbeat_status.lock().unwrap().last_ping_at
+    }
+
+    /// Returns the last time the heartbeat job received a pong packet from the client,
+    /// or sent one to the client with the v3 protocol.
+    ///
+    /// It is always `None` if the heartbeat is disabled.
+    pub fn last_pong_at(&self) -> Option<Instant> {
+        self.heartbeat_status.lock().unwrap().last_pong_at
+    }
+
+    /// Returns the last time the client was known to be alive:
+    /// the most recent of [`last_pong_at`](Self::last_pong_at) and [`last_transport_activity`](Self::last_transport_activity).
+    ///
+    /// Until the first pong, it is at least the creation time of the socket.
+    pub fn last_alive_at(&self) -> Instant {
+        let transport = self.last_transport_activity();
+        self.last_pong_at()
+            .map_or(transport, |pong| pong.max(transport))
+    }
+
+    /// Returns the round-trip time of the last acknowledged ping.
+    ///
+    /// It is always `None` with the v3 protocol, because pings are sent by the client,
+    /// or if the heartbeat is disabled.
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.heartbeat_status.lock().unwrap().last_rtt
+    }
+
+    /// Returns the current interval between two pings sent by the heartbeat job.
+    ///
+    /// It is the [`EngineIoConfig::ping_interval`], unless an [`EngineIoConfig::adaptive_heartbeat`] is set.
+    /// It is `None` with the v3 protocol, because pings are sent by the client,
+    /// or if the heartbeat is disabled.
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        self.heartbeat_status.lock().unwrap().interval
+    }
+
+    /// Returns the number of consecutive pings that were not answered within the `ping_timeout`.
+    /// It is reset to 0 when a pong is received (a ping with the v3 protocol).
+    ///
+    /// A missed pong closes the socket, unless the transport is still alive
+    /// (see [`EngineIoConfig::transport_liveness`]). A value greater than 0 flags a connection at risk.
+    pub fn missed_pongs(&self) -> u32 {
+        self.missed_pongs.load(Ordering::Relaxed)
+    }
+
+    /// Record a pong received for a ping sent at `ping_instant`, returns the round-trip time
+    fn record_pong(&self, ping_instant: Option<Instant>) -> Option<Duration> {
+        let now = Instant::now();
+        let mut status = self.heartbeat_status.lock().unwrap();
+        status.last_pong_at = Some(now);
+        status.last_rtt = ping_instant.map(|instant| now - instant);
+        self.missed_pongs.store(0, Ordering::Relaxed);
+        status.last_rtt
+    }
+
+    /// Check if the transport was active since the given instant
+    /// and if transport activity is allowed to extend the heartbeat deadline
+    fn transport_alive_since(&self, instant: Instant) -> bool {
+        self.transport_liveness && self.last_transport_activity() > instant
+    }
+
+    /// Spawns a task on the configured runtime (see [`EngineIoConfig::runtime`])
+    pub(crate) fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        spawn_on(self.runtime.as_ref(), future)
+    }
+
     /// Spawn the heartbeat job
     ///
-    /// Keep a handle to the job so that it can be aborted when the socket is closed
+    /// Keep a handle to the job so that it can be aborted when the socket is closed.
+    /// Nothing is spawned if the heartbeat is disabled.
     pub(crate) fn spawn_heartbeat(self: Arc<Self>, interval: Duration, timeout: Duration) {
+        if !self.heartbeat_enabled {
+            return;
+        }
         let socket = self.clone();
 
-        let handle = tokio::spawn(async move {
+        let job = async move {
             if let Err(_e) = socket.heartbeat_job(interval, timeout).await {
                 socket.close(DisconnectReason::HeartbeatTimeout);
                 #[cfg(feature = "tracing")]
                 tracing::debug!("[sid={}] heartbeat error: {:?}", socket.id, _e);
             }
-        });
+        };
+        #[cfg(feature = "tracing")]
+        let job = tracing::Instrument::instrument(job, self.span.clone());
+        let handle = self.spawn(job);
         self.heartbeat_handle
             .try_lock()
             .expect("heartbeat handle mutex should not be locked twice")
@@ -294,7 +795,11 @@ where
     #[cfg(feature = "v3")]
     async fn heartbeat_job(&self, interval: Duration, timeout: Duration) -> Result<(), Error> {
         match self.protocol {
-            ProtocolVersion::V3 => self.heartbeat_job_v3(interval, timeout).await,
+            // The client pings at the interval advertised in the handshake
+            ProtocolVersion::V3 => {
+                let interval = self.adaptive_heartbeat.map_or(interval, |a| a.max_interval);
+                self.heartbeat_job_v3(interval, timeout).await
+            }
             ProtocolVersion::V4 => self.heartbeat_job_v4(interval, timeout).await,
         }
     }
@@ -310,11 +815,13 @@ where
     /// Heartbeat is sent every `interval` milliseconds and the client is expected to respond within `timeout` milliseconds.
     ///
     /// If the client does not respond within the timeout, the connection is closed.
+    ///
+    /// With an [`AdaptiveHeartbeat`], the interval is adjusted after each round-trip.
     async fn heartbeat_job_v4(&self, interval: Duration, timeout: Duration) -> Result<(), Error> {
-        let mut heartbeat_rx = self
-            .heartbeat_rx
-            .try_lock()
-            .expect("Pong rx should be locked only once");
+        let adaptive = self.adaptive_heartbeat;
+        let mut interval = adaptive.map_or(interval, |a| a.clamp(interval));
+        let mut smoothed_rtt: Option<Duration> = None;
+        self.heartbeat_status.lock().unwrap().interval = Some(interval);
 
         let instant = tokio::time::Instant::now();
         let mut interval_tick = tokio::time::interval(interval);
@@ -330,57 +837,228 @@ where
 
         loop {
             // Some clients send the pong packet in first. If that happens, we should consume it.
-            heartbeat_rx.try_recv().ok();
+            self.heartbeat.notified().now_or_never();
 
-            self.internal_tx
-                .try_send(smallvec![Packet::Ping])
-                .map_err(|_| Error::HeartbeatTimeout)?;
-            tokio::time::timeout(timeout, heartbeat_rx.recv())
-                .await
-                .map_err(|_| Error::HeartbeatTimeout)?
-                .ok_or(Error::HeartbeatTimeout)?;
+            let ping_instant = Instant::now();
+            self.priority_tx.try_send(smallvec![Packet::Ping].into())?;
+            self.heartbeat_status.lock().unwrap().last_ping_at = Some(ping_instant);
+            let next_interval = match tokio::time::timeout(timeout, self.heartbeat.notified()).await
+            {
+                Ok(()) => {
+                    let rtt = self.record_pong(Some(ping_instant));
+                    adaptive.zip(rtt).map(|(adaptive, rtt)| {
+                        // Exponentially weighted moving average, as for the TCP retransmission timeout
+                        let smoothed = smoothed_rtt.unwrap_or(rtt);
+                        smoothed_rtt = Some(smoothed - smoothed / 8 + rtt / 8);
+                        adaptive.next_interval(interval, rtt, smoothed, timeout)
+                    })
+                }
+                Err(_) => {
+                    self.missed_pongs.fetch_add(1, Ordering::Relaxed);
+                    if !self.transport_alive_since(ping_instant) {
+                        return Err(Error::HeartbeatTimeout);
+                    }
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("[sid={}] no pong received but transport is alive", self.id);
+                    adaptive.map(|a| a.min_interval)
+                }
+            };
+            if let Some(next_interval) = next_interval.filter(|next| *next != interval) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    "[sid={}] heartbeat interval adjusted to {:?}",
+                    self.id,
+                    next_interval
+                );
+                interval = next_interval;
+                interval_tick = tokio::time::interval_at(ping_instant + interval, interval);
+                self.heartbeat_status.lock().unwrap().interval = Some(interval);
+            }
             interval_tick.tick().await;
         }
     }
 
     #[cfg(feature = "v3")]
     async fn heartbeat_job_v3(&self, interval: Duration, timeout: Duration) -> Result<(), Error> {
-        let mut heartbeat_rx = self
-            .heartbeat_rx
-            .try_lock()
-            .expect("Pong rx should be locked only once");
-
         #[cfg(feature = "tracing")]
         tracing::debug!("[sid={}] heartbeat receiver routine started", self.id);
 
         loop {
-            tokio::time::timeout(interval + timeout, heartbeat_rx.recv())
-                .await
-                .map_err(|_| Error::HeartbeatTimeout)?
-                .ok_or(Error::HeartbeatTimeout)?;
+            let instant = Instant::now();
+            match tokio::time::timeout(interval + timeout, self.heartbeat.notified()).await {
+                Ok(()) => {}
+                Err(_) => {
+                    self.missed_pongs.fetch_add(1, Ordering::Relaxed);
+                    if !self.transport_alive_since(instant) {
+                        return Err(Error::HeartbeatTimeout);
+                    }
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("[sid={}] no ping received but transport is alive", self.id);
+                    continue;
+                }
+            }
+            self.heartbeat_status.lock().unwrap().last_ping_at = Some(Instant::now());
 
             #[cfg(feature = "tracing")]
             tracing::debug!("[sid={}] ping received, sending pong", self.id);
-            self.internal_tx
-                .try_send(smallvec![Packet::Pong])
-                .map_err(|_| Error::HeartbeatTimeout)?;
+            self.priority_tx.try_send(smallvec![Packet::Pong].into())?;
+            self.record_pong(None);
         }
     }
 
+    /// Handles a [`Ping`](Packet::Ping) or [`Pong`](Packet::Pong) packet received from the client.
+    ///
+    /// The pongs (v4 protocol) and the pings (v3 protocol) are forwarded to the heartbeat job,
+    /// the pings received with the v4 protocol are handled according to the [`ClientPingPolicy`].
+    pub(crate) fn recv_heartbeat(&self, packet: Packet) -> Result<(), Error> {
+        if packet == Packet::Ping && self.protocol == ProtocolVersion::V4 {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                "[sid={}] unexpected ping from the client, policy: {:?}",
+                self.id,
+                self.client_ping_policy
+            );
+            return match self.client_ping_policy {
+                ClientPingPolicy::Ignore => Ok(()),
+                ClientPingPolicy::Pong => {
+                    // A full buffer will be reported by the next emitted packet
+                    self.priority_tx
+                        .try_send(smallvec![Packet::Pong].into())
+                        .ok();
+                    Ok(())
+                }
+                ClientPingPolicy::Close => Err(Error::BadPacket(packet)),
+            };
+        }
+        if !self.heartbeat_enabled {
+            // There is no heartbeat job to answer the pings of the v3 clients
+            if packet == Packet::Ping {
+                self.priority_tx
+                    .try_send(smallvec![Packet::Pong].into())
+                    .ok();
+            }
+            return Ok(());
+        }
+        self.heartbeat.notify_one();
+        Ok(())
+    }
+
     /// Returns true if the [`Socket`] has a websocket [`TransportType`]
+    #[cfg(feature = "polling")]
     pub(crate) fn is_ws(&self) -> bool {
         self.transport.load(Ordering::Relaxed) == TransportType::Websocket as u8
     }
     /// returns true if the [`Socket`] has an HTTP [`TransportType`]
+    #[cfg(feature = "polling")]
     pub(crate) fn is_http(&self) -> bool {
         self.transport.load(Ordering::Relaxed) == TransportType::Polling as u8
     }
 
     /// Sets the [`TransportType`] to WebSocket
     /// Used when the client upgrade the connection from HTTP to WebSocket
-    pub(crate) fn upgrade_to_websocket(&self) {
+    ///
+    /// Returns false if the socket was already upgraded, so that the upgrade side effects only run once.
+    #[cfg(feature = "polling")]
+    pub(crate) fn upgrade_to_websocket(&self) -> bool {
         self.transport
-            .store(TransportType::Websocket as u8, Ordering::Relaxed);
+            .compare_exchange(
+                TransportType::Polling as u8,
+                TransportType::Websocket as u8,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    /// Sets the [`TransportType`] back to Polling
+    /// Used when the websocket writes of a socket upgraded from polling keep failing
+    /// (see [`EngineIoConfig::ws_polling_fallback`])
+    ///
+    /// Returns false if the socket was opened with a websocket or if it is not using the websocket transport.
+    #[cfg(feature = "polling")]
+    pub(crate) fn downgrade_to_polling(&self) -> bool {
+        self.polling_capable
+            && self
+                .transport
+                .compare_exchange(
+                    TransportType::Websocket as u8,
+                    TransportType::Polling as u8,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+    }
+
+    /// Returns true if the socket was opened with the polling transport, so that it can be downgraded back to polling
+    #[cfg(feature = "polling")]
+    pub(crate) fn is_polling_capable(&self) -> bool {
+        self.polling_capable
+    }
+
+    /// Marks the start of an upgrade from polling to websocket.
+    ///
+    /// Returns false if another upgrade is in progress or if the socket was already upgraded.
+    #[cfg(feature = "polling")]
+    pub(crate) fn start_upgrade(&self) -> bool {
+        !self.is_ws()
+            && self
+                .upgrading
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    /// Returns true if the socket is neither upgraded nor upgrading to websocket
+    #[cfg(feature = "polling")]
+    pub(crate) fn can_upgrade(&self) -> bool {
+        !self.is_ws() && !self.upgrading.load(Ordering::Relaxed)
+    }
+
+    /// Marks the end of an upgrade from polling to websocket, whether it succeeded or not
+    #[cfg(feature = "polling")]
+    pub(crate) fn end_upgrade(&self) {
+        self.upgrading.store(false, Ordering::Release);
+    }
+
+    /// Returns true if the client is upgrading from polling to websocket
+    #[cfg(feature = "polling")]
+    pub(crate) fn is_upgrading(&self) -> bool {
+        self.upgrading.load(Ordering::Relaxed)
+    }
+
+    /// Releases the polling request parked on the socket, if any, so that the client can complete the upgrade
+    #[cfg(feature = "polling")]
+    pub(crate) fn release_poll(&self) {
+        self.poll_release.notify_waiters();
+    }
+
+    /// Returns a future resolved when the polling request is released with [`Socket::release_poll`]
+    #[cfg(feature = "polling")]
+    pub(crate) fn poll_released(&self) -> tokio::sync::futures::Notified<'_> {
+        self.poll_release.notified()
+    }
+
+    /// Returns the correlation id of the connection.
+    ///
+    /// It is captured at handshake from the [`EngineIoConfig::correlation_id_header`] request header
+    /// if there is one, otherwise it is random. It is included in the tracing events of the socket
+    /// so that application logs can be joined with them.
+    pub fn correlation_id(&self) -> &str {
+        &self.correlation_id
+    }
+
+    /// Returns the tracing span of the socket, with its id and its correlation id as fields.
+    ///
+    /// The events of the socket are recorded in this span,
+    /// it can be entered to attach application events to the connection.
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Returns the policy for the payloads written in the tracing events
+    #[cfg(all(feature = "tracing", feature = "polling"))]
+    pub(crate) fn payload_logging(&self) -> crate::config::PayloadLogging {
+        self.payload_logging
     }
 
     /// Returns the current [`TransportType`] of the [`Socket`]
@@ -388,6 +1066,21 @@ where
         TransportType::from(self.transport.load(Ordering::Relaxed))
     }
 
+    /// Returns true if the client asked for base64 encoded binary packets with the `b64=1` query param at handshake.
+    /// Binary packets are then sent as base64 text packets, even over websocket.
+    ///
+    /// It is only supported by the engine.io v3 clients, it is always false without the `v3` feature.
+    pub fn force_base64(&self) -> bool {
+        #[cfg(feature = "v3")]
+        {
+            self.force_base64
+        }
+        #[cfg(not(feature = "v3"))]
+        {
+            false
+        }
+    }
+
     /// Reserve `n` permits to emit multiple messages and ensure that there is enough
     /// space in the internal chan.
     ///
@@ -395,8 +1088,14 @@ where
     /// If the socket is closed, the function will return a [`TrySendError::Closed`] error.
     #[inline]
     pub fn reserve(&self) -> Result<Permit<'_>, TrySendError<()>> {
+        if self.is_session_closed() {
+            return Err(TrySendError::Closed(()));
+        }
         let permit = self.internal_tx.try_reserve()?;
-        Ok(Permit { inner: permit })
+        Ok(Permit {
+            inner: permit,
+            recorder: self.recorder().map(|recorder| (self.id, recorder)),
+        })
     }
 
     /// Emits a message to the client.
@@ -413,17 +1112,161 @@ where
         })
     }
 
+    /// Emits a message to the client that will be dropped if it is not sent before the given `ttl`.
+    ///
+    /// It is useful for data that is only valid for a short period of time.
+    /// If the client is slow and the message is still buffered when the `ttl` expires,
+    /// the message will be silently discarded rather than sent stale.
+    ///
+    /// ⚠️ If the buffer is full or the socket is disconnected, an error will be returned with the original data
+    pub fn emit_with_ttl(&self, msg: String, ttl: Duration) -> Result<(), TrySendError<String>> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={}] sending message with ttl {:?}", self.id, ttl);
+        if self.is_session_closed() {
+            return Err(TrySendError::Closed(msg));
+        }
+        let packets =
+            PacketBuf::with_deadline(smallvec![Packet::Message(msg)], Instant::now() + ttl);
+        self.record(Direction::Outbound, &packets);
+        self.internal_tx.try_send(packets).map_err(|e| match e {
+            TrySendError::Full(mut p) => {
+                self.on_buffer_full();
+                TrySendError::Full(p.pop().unwrap().into_message())
+            }
+            TrySendError::Closed(mut p) => TrySendError::Closed(p.pop().unwrap().into_message()),
+        })
+    }
+
+    /// Emits a message to the client and waits for it to be flushed.
+    ///
+    /// If the transport is in websocket mode, it resolves once the frame is written and flushed to the connection.
+    ///
+    /// If the transport is in polling mode, it resolves once the polling response including the message
+    /// is handed to the http connection, not when the message is only buffered.
+    ///
+    /// It is useful for protocols that need a strict ordering between the server and the client over polling.
+    ///
+    /// ⚠️ If the buffer is full or the socket is disconnected, a [`FlushError::Send`] error is returned with the original data.
+    /// If the message could not be flushed, because the socket was closed in the meantime, a [`FlushError::NotFlushed`] error is returned.
+    pub async fn send_and_flush(&self, msg: String) -> Result<(), FlushError> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={}] sending message waiting for flush", self.id);
+        let (tx, rx) = oneshot::channel();
+        if self.is_session_closed() {
+            return Err(TrySendError::Closed(msg).into());
+        }
+        let packets = PacketBuf::with_flush_notifier(smallvec![Packet::Message(msg)], tx);
+        self.record(Direction::Outbound, &packets);
+        self.internal_tx.try_send(packets).map_err(|e| match e {
+            TrySendError::Full(mut p) => {
+                self.on_buffer_full();
+                TrySendError::Full(p.pop().unwrap().into_message())
+            }
+            TrySendError::Closed(mut p) => TrySendError::Closed(p.pop().unwrap().into_message()),
+        })?;
+        rx.await.map_err(|_| FlushError::NotFlushed)
+    }
+
+    /// Starts recording the inbound and outbound packets of the socket with the given recorder,
+    /// replacing the current recorder if there is one.
+    /// See the [`recorder`](crate::recorder) module for more details.
+    pub fn start_recording(&self, recorder: Arc<dyn SessionRecorder>) {
+        *self.recorder.write().unwrap() = Some(recorder);
+        self.recording.store(true, Ordering::Release);
+    }
+
+    /// Stops recording the packets of the socket
+    pub fn stop_recording(&self) {
+        self.recording.store(false, Ordering::Release);
+        self.recorder.write().unwrap().take();
+    }
+
+    /// Returns true if the packets of the socket are recorded
+    #[inline]
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Acquire)
+    }
+
+    /// Returns the recorder of the socket if it is recorded
+    #[inline]
+    fn recorder(&self) -> Option<Arc<dyn SessionRecorder>> {
+        if !self.is_recording() {
+            return None;
+        }
+        self.recorder.read().unwrap().clone()
+    }
+
+    /// Records an inbound packet if the socket is recorded and returns it
+    #[inline]
+    pub(crate) fn record_inbound(&self, packet: Packet) -> Packet {
+        self.record(Direction::Inbound, std::slice::from_ref(&packet));
+        packet
+    }
+
+    /// Records the packets if the socket is recorded
+    #[inline]
+    pub(crate) fn record(&self, direction: Direction, packets: &[Packet]) {
+        if let Some(recorder) = self.recorder() {
+            record(self.id, &*recorder, direction, packets);
+        }
+    }
+
     /// Immediately closes the socket and the underlying connection.
     /// The socket will be removed from the `Engine` and the [`Handler`](crate::handler::EngineIoHandler) will be notified.
+    ///
+    /// The close packet is sent before the packets that are still buffered, which are discarded.
     pub fn close(&self, reason: DisconnectReason) {
         (self.close_fn)(self.id, reason);
-        self.send(Packet::Close).ok();
+        self.send_priority(Packet::Close).ok();
+    }
+
+    /// Closes the socket like [`Socket::close`], with a human-readable `text` sent to websocket clients
+    /// as the reason of the close frame. Browsers expose it as the `reason` of the `CloseEvent`.
+    ///
+    /// The code of the close frame is derived from the [`DisconnectReason`], and the text
+    /// is truncated to the 123 bytes allowed by the websocket protocol.
+    /// Polling clients only receive the close packet.
+    pub fn close_with_reason(&self, reason: DisconnectReason, text: &str) {
+        self.set_ws_close_frame(close_frame(reason.ws_close_code(), text));
+        self.close(reason);
+    }
+
+    /// Closes the socket like [`Socket::close`], but the close packet is sent after the packets that are still buffered
+    pub(crate) fn close_after_flush(&self, reason: DisconnectReason) {
+        (self.close_fn)(self.id, reason);
+        self.send_close();
+    }
+
+    /// Sends the close packet after the buffered packets, even if the session is already closed
+    pub(crate) fn send_close(&self) {
+        self.record(Direction::Outbound, &[Packet::Close]);
+        self.internal_tx
+            .try_send(smallvec![Packet::Close].into())
+            .ok();
+    }
+
+    /// Marks the session as closed, the packets emitted afterwards are rejected with a closed error
+    pub(crate) fn mark_session_closed(&self) {
+        self.session_closed.store(true, Ordering::Release);
+    }
+
+    fn is_session_closed(&self) -> bool {
+        self.session_closed.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of packets discarded because the buffer of the socket was full,
+    /// according to the configured [`OverflowPolicy`](crate::config::OverflowPolicy).
+    ///
+    /// It is always 0 with the default [`OverflowPolicy::Reject`](crate::config::OverflowPolicy::Reject) policy,
+    /// because the packets are returned to the caller instead.
+    pub fn dropped_packets(&self) -> u64 {
+        self.internal_tx.dropped()
     }
 
     /// Returns true if the socket is closed
     /// It means that no more packets can be sent to the client
     pub fn is_closed(&self) -> bool {
-        self.internal_tx.is_closed()
+        self.is_session_closed() || self.internal_tx.is_closed()
     }
 
     /// Wait for the socket to be fully closed
@@ -449,6 +1292,163 @@ where
             TrySendError::Closed(p) => TrySendError::Closed(p.into_binary()),
         })
     }
+
+    /// Emits a binary message tagged with a sequence number and waits for the client to acknowledge it.
+    /// If it is not acknowledged within the `timeout`, it is sent once again with the same sequence number.
+    ///
+    /// ⚠️ It requires the cooperation of the client, which must send back an ack packet,
+    /// see the [`reliable`](crate::reliable) module for the format.
+    ///
+    /// Returns a [`ReliableError::Timeout`] error if the second attempt is not acknowledged either,
+    /// or a [`ReliableError::Full`] or [`ReliableError::Closed`] error if the packet can't be buffered.
+    pub async fn emit_binary_reliable(
+        &self,
+        data: bytes::Bytes,
+        timeout: Duration,
+    ) -> Result<(), ReliableError> {
+        // The sequence number is forgotten when the guard is dropped, even if this future is cancelled
+        let (pending, mut rx) = self.binary_acks.register();
+        let packet = reliable::encode_data(pending.seq, &data);
+        for _attempt in 0..2 {
+            self.emit_binary(packet.clone()).map_err(|e| match e {
+                TrySendError::Full(_) => ReliableError::Full,
+                TrySendError::Closed(_) => ReliableError::Closed,
+            })?;
+            if tokio::time::timeout(timeout, &mut rx).await.is_ok() {
+                return Ok(());
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                "[sid={}] binary packet {} not acknowledged",
+                self.id,
+                pending.seq
+            );
+        }
+        Err(ReliableError::Timeout)
+    }
+
+    /// Returns true if the binary packet is the ack of a packet sent with [`Socket::emit_binary_reliable`],
+    /// in which case it should not be passed to the handler
+    #[inline]
+    fn recv_binary_ack(&self, data: &[u8]) -> bool {
+        data.first() == Some(&reliable::MAGIC) && self.binary_acks.ack(data)
+    }
+
+    /// Handles a binary packet received from the client. It is consumed if it is the ack of a reliable packet
+    /// or if it is forwarded to the [`Socket::binary_stream`], otherwise it is given back for the handler.
+    pub(crate) fn recv_binary(&self, data: Vec<u8>) -> Option<Vec<u8>> {
+        if self.recv_binary_ack(&data) {
+            return None;
+        }
+        let mut stream_tx = self.binary_stream_tx.lock().unwrap();
+        match stream_tx.as_ref() {
+            Some(tx) => match tx.send(Ok(data.into())) {
+                Ok(()) => None,
+                // The stream was dropped, the packets go back to the handler
+                Err(e) => {
+                    *stream_tx = None;
+                    e.0.ok().map(Vec::from)
+                }
+            },
+            None => Some(data),
+        }
+    }
+
+    /// Returns a stream of the binary packets received from the client, yielded as soon as they are read
+    /// by the transport, so that large uploads can be processed chunk by chunk.
+    ///
+    /// While the stream is alive, the binary packets are forwarded to it **instead of**
+    /// the [`EngineIoHandler::on_binary`](crate::handler::EngineIoHandler::on_binary) handler.
+    /// Once it is dropped, the next packets are passed to the handler again,
+    /// the packets buffered in the stream that were not read are discarded.
+    /// Calling this method again ends the previous stream.
+    ///
+    /// The stream ends when the socket is closed. If it is not closed by the client
+    /// (e.g. transport error or heartbeat timeout), it yields a [`StreamError::Closed`](crate::stream::StreamError::Closed)
+    /// error first.
+    ///
+    /// The packets are buffered without limit until they are read from the stream,
+    /// the transport can't apply backpressure to the client.
+    pub fn binary_stream(
+        &self,
+    ) -> impl futures::Stream<Item = Result<bytes::Bytes, crate::stream::StreamError>> + Send + 'static
+    {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        // Checked with the lock held so that a concurrent close can't miss the new sender
+        let mut stream_tx = self.binary_stream_tx.lock().unwrap();
+        if !self.is_session_closed() {
+            stream_tx.replace(tx);
+        }
+        drop(stream_tx);
+        futures::stream::unfold(rx, |mut rx| async move {
+            let item = rx.recv().await?;
+            Some((item, rx))
+        })
+    }
+
+    /// Ends the [`Socket::binary_stream`] when the socket is closed
+    pub(crate) fn end_binary_stream(&self, reason: &DisconnectReason) {
+        if let Some(tx) = self.binary_stream_tx.lock().unwrap().take() {
+            if *reason != DisconnectReason::TransportClose {
+                tx.send(Err(crate::stream::StreamError::Closed)).ok();
+            }
+        }
+    }
+
+    /// Sends a stream of binary data to the client, each chunk as a separate binary packet,
+    /// so that a large payload is never buffered at once.
+    ///
+    /// Each packet starts with a small header with the stream id and the chunk sequence number,
+    /// and the stream is terminated by an end marker, see the [`stream`](crate::stream) module for the format.
+    /// The client reassembles it with a [`StreamAssembler`](crate::stream::StreamAssembler).
+    ///
+    /// When the internal buffer of the socket is full, it waits for the transport to send the buffered packets
+    /// before pulling the next chunk, whatever the [`OverflowPolicy`](crate::config::OverflowPolicy), so that no chunk is dropped.
+    /// Other messages can still be emitted in the meantime, they are interleaved with the chunks.
+    ///
+    /// Returns the id of the stream once the end marker is buffered,
+    /// or a [`StreamError::Closed`](crate::stream::StreamError::Closed) error if the socket is closed mid-stream.
+    pub async fn send_stream<S>(&self, stream: S) -> Result<u32, crate::stream::StreamError>
+    where
+        S: futures::Stream<Item = bytes::Bytes>,
+    {
+        use crate::stream::{Frame, StreamError};
+        use futures::StreamExt;
+
+        let id = self.next_stream_id.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={}] sending binary stream {id}", self.id);
+        let binary = |data| {
+            if self.protocol == ProtocolVersion::V3 {
+                Packet::BinaryV3(data)
+            } else {
+                Packet::Binary(data)
+            }
+        };
+
+        futures::pin_mut!(stream);
+        let mut seq = 0;
+        while let Some(chunk) = stream.next().await {
+            let permit = self
+                .internal_tx
+                .reserve()
+                .await
+                .map_err(|_| StreamError::Closed)?;
+            let packet = binary(Frame::chunk(id, seq, &chunk));
+            self.record(Direction::Outbound, std::slice::from_ref(&packet));
+            permit.send(smallvec![packet].into());
+            seq += 1;
+        }
+        let permit = self
+            .internal_tx
+            .reserve()
+            .await
+            .map_err(|_| StreamError::Closed)?;
+        let packet = binary(Frame::end(id, seq));
+        self.record(Direction::Outbound, std::slice::from_ref(&packet));
+        permit.send(smallvec![packet].into());
+        Ok(id)
+    }
 }
 
 impl<D: Default + Send + Sync + 'static> std::fmt::Debug for Socket<D> {
@@ -459,8 +1459,8 @@ impl<D: Default + Send + Sync + 'static> std::fmt::Debug for Socket<D> {
             .field("conn", &self.transport)
             .field("internal_rx", &self.internal_rx)
             .field("internal_tx", &self.internal_tx)
-            .field("heartbeat_rx", &self.heartbeat_rx)
-            .field("heartbeat_tx", &self.heartbeat_tx)
+            .field("priority_tx", &self.priority_tx)
+            .field("heartbeat", &self.heartbeat)
             .field("heartbeat_handle", &self.heartbeat_handle)
             .field("req_data", &self.req_parts)
             .finish()
@@ -488,27 +1488,177 @@ where
         sid: Sid,
         close_fn: Box<dyn Fn(Sid, DisconnectReason) + Send + Sync>,
     ) -> Socket<D> {
-        let (internal_tx, internal_rx) = mpsc::channel(200);
-        let (heartbeat_tx, heartbeat_rx) = mpsc::channel(1);
+        let (internal_tx, internal_rx) = channel(200, crate::config::OverflowPolicy::Reject);
+        let (priority_tx, priority_rx) =
+            channel(PRIORITY_BUFFER_SIZE, crate::config::OverflowPolicy::Reject);
 
         Self {
             id: sid,
             protocol: ProtocolVersion::V4,
+            correlation_id: sid.to_string().into(),
+            #[cfg(feature = "tracing")]
+            payload_logging: Default::default(),
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::none(),
             transport: AtomicU8::new(TransportType::Websocket as u8),
+            #[cfg(feature = "polling")]
+            upgrading: AtomicBool::new(false),
+            #[cfg(feature = "polling")]
+            polling_capable: false,
+            #[cfg(feature = "polling")]
+            poll_release: Notify::new(),
 
-            internal_rx: Mutex::new(PeekableReceiver::new(internal_rx)),
+            internal_rx: Mutex::new(PeekableReceiver::new(internal_rx, priority_rx)),
             internal_tx,
+            priority_tx,
 
-            heartbeat_rx: Mutex::new(heartbeat_rx),
-            heartbeat_tx,
+            heartbeat: Notify::new(),
             heartbeat_handle: Mutex::new(None),
+            #[cfg(feature = "polling")]
+            recv_lock: Mutex::new(()),
+            ws_close_frame: std::sync::Mutex::new(None),
+            last_transport_activity: std::sync::Mutex::new(Instant::now()),
+            last_message_at: std::sync::Mutex::new(Instant::now()),
+            created_at: Instant::now(),
+            last_seen: AtomicU64::new(0),
+            heartbeat_status: std::sync::Mutex::new(HeartbeatStatus::default()),
+            missed_pongs: AtomicU32::new(0),
+            next_stream_id: AtomicU32::new(0),
+            binary_acks: PendingAcks::default(),
+            binary_stream_tx: std::sync::Mutex::new(None),
+            heartbeat_enabled: true,
+            transport_liveness: false,
+            adaptive_heartbeat: None,
+            client_ping_policy: ClientPingPolicy::default(),
+            runtime: None,
             close_fn,
+            session_closed: AtomicBool::new(false),
+            recording: AtomicBool::new(false),
+            recorder: std::sync::RwLock::new(None),
 
             data: D::default(),
             req_parts: http::Request::<()>::default().into_parts().0,
 
             #[cfg(feature = "v3")]
-            supports_binary: true,
+            force_base64: false,
+        }
+    }
+}
+
+/// A handle to drive a [`Socket`] created with [`test_socket`] without any transport.
+///
+/// Inbound packets are dispatched to the [`EngineIoHandler`](crate::handler::EngineIoHandler)
+/// the same way the transports do it, and outbound packets can be inspected
+/// without any network or timer involved.
+#[cfg(feature = "test-utils")]
+pub struct TestSocketHandle<H: crate::handler::EngineIoHandler> {
+    socket: Arc<Socket<H::Data>>,
+    handler: Arc<H>,
+    outbound: std::sync::Mutex<std::collections::VecDeque<Packet>>,
+}
+
+/// Create a [`Socket`] wired to in-memory channels for testing purpose.
+///
+/// The [`on_connect`](crate::handler::EngineIoHandler::on_connect) callback is called
+/// before returning and the [`on_disconnect`](crate::handler::EngineIoHandler::on_disconnect)
+/// callback is called once when the socket is closed.
+#[cfg(feature = "test-utils")]
+pub fn test_socket<H: crate::handler::EngineIoHandler>(
+    handler: H,
+) -> (Arc<Socket<H::Data>>, TestSocketHandle<H>) {
+    use std::sync::atomic::AtomicBool;
+
+    let handler = Arc::new(handler);
+    let socket = Arc::new_cyclic(|weak: &std::sync::Weak<Socket<H::Data>>| {
+        let weak = weak.clone();
+        let handler = handler.clone();
+        let closed = AtomicBool::new(false);
+        let close_fn = Box::new(move |_, reason| {
+            if closed.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            if let Some(socket) = weak.upgrade() {
+                socket.mark_session_closed();
+                if let Ok(mut rx) = socket.internal_rx.try_lock() {
+                    rx.close();
+                }
+                handler.on_disconnect(socket, reason);
+            }
+        });
+        Socket::new_dummy(Sid::new(), close_fn)
+    });
+    handler.on_connect(socket.clone());
+
+    let handle = TestSocketHandle {
+        socket: socket.clone(),
+        handler,
+        outbound: std::sync::Mutex::new(std::collections::VecDeque::new()),
+    };
+    (socket, handle)
+}
+
+#[cfg(feature = "test-utils")]
+impl<H: crate::handler::EngineIoHandler> TestSocketHandle<H> {
+    /// Push an inbound packet as if it was received from the client.
+    ///
+    /// * [`Message`](Packet::Message) and [`Binary`](Packet::Binary) packets are forwarded to the handler.
+    /// * [`Ping`](Packet::Ping) and [`Pong`](Packet::Pong) packets are handled as by the transports,
+    ///   see [`EngineIoConfig::client_ping_policy`](crate::config::EngineIoConfig::client_ping_policy).
+    /// * A [`Close`](Packet::Close) packet closes the socket with [`DisconnectReason::TransportClose`].
+    ///
+    /// Any other packet is rejected with [`Error::BadPacket`].
+    pub fn push(&self, packet: Packet) -> Result<(), Error> {
+        self.socket.touch_seen();
+        self.socket
+            .record(Direction::Inbound, std::slice::from_ref(&packet));
+        match packet {
+            Packet::Message(msg) => self.handler.on_message(msg, self.socket.clone()),
+            Packet::Binary(bin) | Packet::BinaryV3(bin) => {
+                if let Some(bin) = self.socket.recv_binary(bin) {
+                    self.handler.on_binary(bin, self.socket.clone())
+                }
+            }
+            p @ (Packet::Ping | Packet::Pong) => self.socket.recv_heartbeat(p)?,
+            Packet::Close => {
+                (self.socket.close_fn)(self.socket.id, DisconnectReason::TransportClose)
+            }
+            p => return Err(Error::BadPacket(p)),
         }
+        Ok(())
+    }
+
+    /// Push the inbound packets of a recorded session in order, as if they were received from the client,
+    /// to reproduce an issue against a handler. The outbound packets of the recording are ignored.
+    ///
+    /// It stops at the first packet rejected by [`push`](Self::push).
+    pub fn replay(
+        &self,
+        records: impl IntoIterator<Item = crate::recorder::RecordedPacket>,
+    ) -> Result<(), Error> {
+        records
+            .into_iter()
+            .filter(|record| record.direction == Direction::Inbound)
+            .try_for_each(|record| self.push(record.packet))
+    }
+
+    /// Pop the next outbound packet sent to the client if there is one.
+    pub fn try_recv(&self) -> Option<Packet> {
+        let mut outbound = self.outbound.lock().unwrap();
+        if outbound.is_empty() {
+            let mut rx = self.socket.internal_rx.try_lock().ok()?;
+            let packets = std::iter::from_fn(|| rx.try_recv().ok()).find(|p| !p.is_expired())?;
+            outbound.extend(packets);
+        }
+        outbound.pop_front()
+    }
+
+    /// Drain all the outbound packets currently buffered.
+    pub fn drain(&self) -> Vec<Packet> {
+        std::iter::from_fn(|| self.try_recv()).collect()
+    }
+
+    /// Get the socket driven by this handle.
+    pub fn socket(&self) -> &Arc<Socket<H::Data>> {
+        &self.socket
     }
 }
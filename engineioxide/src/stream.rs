@@ -0,0 +1,320 @@
+//! Chunked binary streams, to transfer large payloads without buffering them in a single binary packet.
+//!
+//! A stream sent with [`Socket::send_stream`](crate::Socket::send_stream) is split into binary packets,
+//! each one starting with a [`HEADER_LEN`] bytes header:
+//!
+//! | magic (1 byte) | kind (1 byte) | stream id (u32 BE) | sequence number (u32 BE) |
+//! |----------------|---------------|--------------------|------------------------- |
+//! | `0xF5`         | `0` chunk, `1` end | unique per socket | starts at 0 for each stream |
+//!
+//! The last packet of a stream is an `end` marker without data, whose sequence number follows the last chunk.
+//!
+//! On the receiving side, the binary packets are fed into a [`StreamAssembler`] that reassembles
+//! them into one [`BinaryStream`] per stream id.
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::Stream;
+use tokio::sync::mpsc;
+
+/// The first byte of every stream packet
+pub const MAGIC: u8 = 0xF5;
+/// The length of the header of every stream packet
+pub const HEADER_LEN: usize = 10;
+
+const KIND_CHUNK: u8 = 0;
+const KIND_END: u8 = 1;
+
+/// Error returned when sending or receiving a [`BinaryStream`]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum StreamError {
+    /// The socket was closed before the stream was fully transferred
+    #[error("the socket was closed before the end of the stream")]
+    Closed,
+    /// A chunk was received with an unexpected sequence number, the stream is aborted
+    #[error("unexpected chunk sequence number, expected {expected} but got {received}")]
+    OutOfOrder {
+        /// The expected sequence number
+        expected: u32,
+        /// The received sequence number
+        received: u32,
+    },
+}
+
+/// A decoded stream packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Frame {
+    pub stream_id: u32,
+    pub seq: u32,
+    /// `None` for the end marker
+    pub data: Option<Bytes>,
+}
+
+impl Frame {
+    /// Encodes a chunk packet
+    pub(crate) fn chunk(stream_id: u32, seq: u32, data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + data.len());
+        Self::push_header(&mut buf, KIND_CHUNK, stream_id, seq);
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    /// Encodes an end marker packet
+    pub(crate) fn end(stream_id: u32, seq: u32) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN);
+        Self::push_header(&mut buf, KIND_END, stream_id, seq);
+        buf
+    }
+
+    fn push_header(buf: &mut Vec<u8>, kind: u8, stream_id: u32, seq: u32) {
+        buf.push(MAGIC);
+        buf.push(kind);
+        buf.extend_from_slice(&stream_id.to_be_bytes());
+        buf.extend_from_slice(&seq.to_be_bytes());
+    }
+
+    /// Decodes a stream packet, `None` is returned if it is not one
+    pub(crate) fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN || data[0] != MAGIC {
+            return None;
+        }
+        let stream_id = u32::from_be_bytes(data[2..6].try_into().unwrap());
+        let seq = u32::from_be_bytes(data[6..10].try_into().unwrap());
+        let data = match data[1] {
+            KIND_CHUNK => Some(Bytes::copy_from_slice(&data[HEADER_LEN..])),
+            KIND_END if data.len() == HEADER_LEN => None,
+            _ => return None,
+        };
+        Some(Self {
+            stream_id,
+            seq,
+            data,
+        })
+    }
+}
+
+/// The result of [`StreamAssembler::push`]
+#[derive(Debug)]
+pub enum Received {
+    /// The binary packet is not part of a stream, it is given back
+    Binary(Vec<u8>),
+    /// The first packet of a new stream was received
+    NewStream(BinaryStream),
+    /// The packet was forwarded to its stream
+    Chunk,
+}
+
+#[derive(Debug)]
+struct Inbound {
+    tx: mpsc::UnboundedSender<Result<Bytes, StreamError>>,
+    next_seq: u32,
+}
+
+/// Reassembles the binary packets received from a socket into [`BinaryStream`]s, by stream id.
+///
+/// There should be one assembler per socket, fed with every binary packet received from it.
+/// When it is dropped, the streams that are not finished end with a [`StreamError::Closed`] error.
+///
+/// The chunks are buffered without limit until they are read from their [`BinaryStream`],
+/// the transport can't apply backpressure to the remote sender.
+#[derive(Debug, Default)]
+pub struct StreamAssembler {
+    streams: Mutex<HashMap<u32, Inbound>>,
+}
+
+impl StreamAssembler {
+    /// Creates a new empty assembler
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a binary packet received from the socket.
+    ///
+    /// Chunks with an unexpected sequence number abort their stream with a [`StreamError::OutOfOrder`] error,
+    /// and the following chunks of this stream are ignored.
+    pub fn push(&self, data: Vec<u8>) -> Received {
+        let Some(frame) = Frame::decode(&data) else {
+            return Received::Binary(data);
+        };
+        let mut streams = self.streams.lock().unwrap();
+        let mut new_stream = None;
+        if frame.seq == 0 && !streams.contains_key(&frame.stream_id) {
+            let (tx, rx) = mpsc::unbounded_channel();
+            streams.insert(frame.stream_id, Inbound { tx, next_seq: 0 });
+            new_stream = Some(BinaryStream {
+                id: frame.stream_id,
+                rx,
+                done: false,
+            });
+        }
+
+        if let Some(inbound) = streams.get_mut(&frame.stream_id) {
+            if inbound.next_seq != frame.seq {
+                let err = StreamError::OutOfOrder {
+                    expected: inbound.next_seq,
+                    received: frame.seq,
+                };
+                inbound.tx.send(Err(err)).ok();
+                streams.remove(&frame.stream_id);
+            } else {
+                inbound.next_seq += 1;
+                match frame.data {
+                    Some(data) => {
+                        inbound.tx.send(Ok(data)).ok();
+                    }
+                    // Dropping the sender ends the stream
+                    None => {
+                        streams.remove(&frame.stream_id);
+                    }
+                }
+            }
+        }
+        match new_stream {
+            Some(stream) => Received::NewStream(stream),
+            None => Received::Chunk,
+        }
+    }
+}
+
+impl Drop for StreamAssembler {
+    fn drop(&mut self) {
+        let streams = self.streams.get_mut().unwrap();
+        for (_, inbound) in streams.drain() {
+            inbound.tx.send(Err(StreamError::Closed)).ok();
+        }
+    }
+}
+
+/// A binary stream reassembled by a [`StreamAssembler`].
+///
+/// It yields the chunks in order and ends once the end marker is received,
+/// or with an error if the stream is aborted.
+#[derive(Debug)]
+pub struct BinaryStream {
+    id: u32,
+    rx: mpsc::UnboundedReceiver<Result<Bytes, StreamError>>,
+    done: bool,
+}
+
+impl BinaryStream {
+    /// The id of the stream, unique per socket
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Stream for BinaryStream {
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let item = std::task::ready!(self.rx.poll_recv(cx));
+        if !matches!(item, Some(Ok(_))) {
+            self.done = true;
+        }
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    fn chunk(stream_id: u32, seq: u32, data: &[u8]) -> Vec<u8> {
+        Frame::chunk(stream_id, seq, data)
+    }
+
+    #[test]
+    fn frame_encode_decode() {
+        let packet = chunk(7, 3, b"hello");
+        assert_eq!(packet[..HEADER_LEN], [MAGIC, 0, 0, 0, 0, 7, 0, 0, 0, 3]);
+        assert_eq!(
+            Frame::decode(&packet),
+            Some(Frame {
+                stream_id: 7,
+                seq: 3,
+                data: Some(Bytes::from_static(b"hello"))
+            })
+        );
+        assert_eq!(
+            Frame::decode(&Frame::end(7, 4)),
+            Some(Frame {
+                stream_id: 7,
+                seq: 4,
+                data: None
+            })
+        );
+        assert_eq!(Frame::decode(&[MAGIC, 0, 0]), None);
+        assert_eq!(Frame::decode(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]), None);
+        assert_eq!(Frame::decode(&[MAGIC, 2, 0, 0, 0, 0, 0, 0, 0, 0]), None);
+    }
+
+    #[tokio::test]
+    async fn reassemble_interleaved_streams() {
+        let assembler = StreamAssembler::new();
+        let Received::NewStream(mut a) = assembler.push(chunk(1, 0, b"a0")) else {
+            panic!("expected a new stream");
+        };
+        let Received::NewStream(mut b) = assembler.push(chunk(2, 0, b"b0")) else {
+            panic!("expected a new stream");
+        };
+        assert!(matches!(
+            assembler.push(chunk(1, 1, b"a1")),
+            Received::Chunk
+        ));
+        assert!(matches!(assembler.push(Frame::end(2, 1)), Received::Chunk));
+        assert!(matches!(
+            assembler.push(vec![1, 2, 3]),
+            Received::Binary(data) if data == [1, 2, 3]
+        ));
+        assert!(matches!(assembler.push(Frame::end(1, 2)), Received::Chunk));
+
+        assert_eq!((a.id(), b.id()), (1, 2));
+        let a: Vec<_> = a.by_ref().collect().await;
+        assert_eq!(a, [Ok("a0".into()), Ok("a1".into())]);
+        assert_eq!(b.next().await, Some(Ok("b0".into())));
+        assert_eq!(b.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn out_of_order_chunk() {
+        let assembler = StreamAssembler::new();
+        let Received::NewStream(stream) = assembler.push(chunk(1, 0, b"0")) else {
+            panic!("expected a new stream");
+        };
+        assembler.push(chunk(1, 2, b"2"));
+        // The following chunks of the aborted stream are ignored
+        assert!(matches!(assembler.push(chunk(1, 3, b"3")), Received::Chunk));
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(
+            items,
+            [
+                Ok("0".into()),
+                Err(StreamError::OutOfOrder {
+                    expected: 1,
+                    received: 2
+                })
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unfinished_stream_on_drop() {
+        let assembler = StreamAssembler::new();
+        let Received::NewStream(stream) = assembler.push(chunk(1, 0, b"0")) else {
+            panic!("expected a new stream");
+        };
+        drop(assembler);
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(items, [Ok("0".into()), Err(StreamError::Closed)]);
+    }
+}
@@ -1,4 +1,5 @@
 //! All transports modules available in engineioxide
 
+#[cfg(feature = "polling")]
 pub mod polling;
 pub mod ws;
@@ -0,0 +1,225 @@
+//! Compression of the polling responses and decompression of the polling requests,
+//! negotiated with the `Accept-Encoding` and `Content-Encoding` http headers.
+use std::io::{Read, Write};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::{
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING},
+    HeaderMap, Request, StatusCode,
+};
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+
+use crate::errors::Error;
+
+/// A content encoding supported for the polling transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            v if v.eq_ignore_ascii_case("gzip") || v.eq_ignore_ascii_case("x-gzip") => {
+                Some(Self::Gzip)
+            }
+            v if v.eq_ignore_ascii_case("br") => Some(Self::Brotli),
+            _ => None,
+        }
+    }
+
+    /// The value of the `Content-Encoding` header for this encoding
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Brotli => "br",
+        }
+    }
+
+    /// Selects the preferred encoding accepted by the client in the `Accept-Encoding` header.
+    /// The encoding with the highest `q` weight is selected (1 if not specified),
+    /// brotli is preferred over gzip for the same weight and encodings with a `q=0` weight are ignored.
+    pub fn from_accept_encoding(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let encoding = Self::parse(parts.next()?)?;
+                let q = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+                (q > 0.0).then_some((encoding, q))
+            })
+            .max_by(|(a, qa), (b, qb)| {
+                qa.total_cmp(qb)
+                    .then_with(|| (*a == Self::Brotli).cmp(&(*b == Self::Brotli)))
+            })
+            .map(|(encoding, _)| encoding)
+    }
+
+    /// Returns the encoding of a request body from its `Content-Encoding` header.
+    /// `None` is returned if the body is not encoded.
+    /// An [`UNSUPPORTED_MEDIA_TYPE`](StatusCode::UNSUPPORTED_MEDIA_TYPE) error is returned for unknown encodings.
+    pub fn from_content_encoding(headers: &HeaderMap) -> Result<Option<Self>, Error> {
+        match headers.get(CONTENT_ENCODING).map(|v| v.to_str()) {
+            None => Ok(None),
+            Some(Ok(v)) if v.trim().eq_ignore_ascii_case("identity") => Ok(None),
+            Some(Ok(v)) => Self::parse(v)
+                .map(Some)
+                .ok_or(Error::HttpErrorResponse(StatusCode::UNSUPPORTED_MEDIA_TYPE)),
+            Some(Err(_)) => Err(Error::HttpErrorResponse(StatusCode::UNSUPPORTED_MEDIA_TYPE)),
+        }
+    }
+
+    /// Compresses the given data
+    pub fn compress(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Self::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+        }
+    }
+
+    /// Decompresses the given data.
+    /// A [`PayloadTooLarge`](Error::PayloadTooLarge) error is returned
+    /// as soon as the decompressed data exceeds `max_payload`.
+    pub fn decompress(self, data: &[u8], max_payload: u64) -> Result<Vec<u8>, Error> {
+        let decoder: Box<dyn Read + '_> = match self {
+            Self::Gzip => Box::new(flate2::read::GzDecoder::new(data)),
+            Self::Brotli => Box::new(brotli::Decompressor::new(data, 4096)),
+        };
+        let mut buf = Vec::new();
+        decoder
+            .take(max_payload + 1)
+            .read_to_end(&mut buf)
+            .map_err(|_| Error::HttpErrorResponse(StatusCode::BAD_REQUEST))?;
+        if buf.len() as u64 > max_payload {
+            Err(Error::PayloadTooLarge)
+        } else {
+            Ok(buf)
+        }
+    }
+}
+
+/// Reads and decompresses the body of an encoded polling request.
+///
+/// Both the compressed and the decompressed body are bounded by `max_payload`
+/// so that a small compressed body can't be expanded without limit.
+pub async fn decompress_req<R>(
+    req: Request<R>,
+    encoding: Encoding,
+    max_payload: u64,
+) -> Result<Request<Full<Bytes>>, Error>
+where
+    R: Body + Unpin,
+    R::Error: std::fmt::Debug,
+{
+    let (parts, mut body) = req.into_parts();
+    let mut data = BytesMut::new();
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|_e| {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("error reading body stream: {:?}", _e);
+            Error::HttpErrorResponse(StatusCode::BAD_REQUEST)
+        })?;
+        if let Ok(chunk) = frame.into_data() {
+            data.put(chunk);
+            if data.len() as u64 > max_payload {
+                return Err(Error::PayloadTooLarge);
+            }
+        }
+    }
+    let data = encoding.decompress(&data, max_payload)?;
+    Ok(Request::from_parts(parts, Full::new(data.into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn accept(value: &'static str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(value));
+        Encoding::from_accept_encoding(&headers)
+    }
+
+    #[test]
+    fn accept_encoding() {
+        assert_eq!(accept("gzip, deflate"), Some(Encoding::Gzip));
+        assert_eq!(accept("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(accept("br;q=0, gzip;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(accept("gzip;q=0"), None);
+        assert_eq!(accept("gzip;q=1, br;q=0.1"), Some(Encoding::Gzip));
+        assert_eq!(accept("gzip;q=0.8, br"), Some(Encoding::Brotli));
+        assert_eq!(accept("br;q=0.5, gzip;q=0.5"), Some(Encoding::Brotli));
+        assert_eq!(accept("gzip, br;q=invalid"), Some(Encoding::Gzip));
+        assert_eq!(accept("deflate, identity"), None);
+        assert_eq!(Encoding::from_accept_encoding(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn content_encoding() {
+        let mut headers = HeaderMap::new();
+        assert!(matches!(
+            Encoding::from_content_encoding(&headers),
+            Ok(None)
+        ));
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("GZIP"));
+        assert!(matches!(
+            Encoding::from_content_encoding(&headers),
+            Ok(Some(Encoding::Gzip))
+        ));
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("zstd"));
+        assert!(matches!(
+            Encoding::from_content_encoding(&headers),
+            Err(Error::HttpErrorResponse(StatusCode::UNSUPPORTED_MEDIA_TYPE))
+        ));
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = "4hello world".repeat(100);
+        for encoding in [Encoding::Gzip, Encoding::Brotli] {
+            let compressed = encoding.compress(data.as_bytes()).unwrap();
+            assert!(compressed.len() < data.len());
+            let decompressed = encoding.decompress(&compressed, 1200).unwrap();
+            assert_eq!(decompressed, data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn decompression_bomb() {
+        let data = vec![b'a'; 1_000_000];
+        for encoding in [Encoding::Gzip, Encoding::Brotli] {
+            let compressed = encoding.compress(&data).unwrap();
+            assert!(compressed.len() < 10_000);
+            assert!(matches!(
+                encoding.decompress(&compressed, 100_000),
+                Err(Error::PayloadTooLarge)
+            ));
+        }
+    }
+
+    #[test]
+    fn corrupted_data() {
+        assert!(matches!(
+            Encoding::Gzip.decompress(b"not gzip", 100),
+            Err(Error::HttpErrorResponse(StatusCode::BAD_REQUEST))
+        ));
+    }
+}
@@ -3,24 +3,38 @@ use std::sync::Arc;
 
 use bytes::Bytes;
 use futures::StreamExt;
-use http::{Request, Response, StatusCode};
+use http::{header, HeaderMap, HeaderName, Request, Response, StatusCode};
 use http_body::Body;
 use http_body_util::Full;
 
 use crate::{
     body::ResponseBody,
+    config::{EngineIoConfig, Handshake},
     engine::EngineIo,
     errors::Error,
     handler::EngineIoHandler,
     packet::{OpenPacket, Packet},
+    recorder::Direction,
     service::{ProtocolVersion, TransportType},
     sid::Sid,
     transport::polling::payload::Payload,
     DisconnectReason,
 };
 
+#[cfg(feature = "compression")]
+mod compression;
 mod payload;
 
+/// Headers of the handshake response that can't be modified by the handshake hook
+const HANDSHAKE_RESERVED_HEADERS: [HeaderName; 6] = [
+    header::CONTENT_TYPE,
+    header::CONTENT_LENGTH,
+    header::CACHE_CONTROL,
+    header::PRAGMA,
+    header::EXPIRES,
+    HeaderName::from_static("x-accel-buffering"),
+];
+
 /// Create a response for http request
 fn http_response<B, D>(
     code: StatusCode,
@@ -43,29 +57,76 @@ where
     .body(ResponseBody::custom_response(Full::new(body)))
 }
 
-pub fn open_req<H, B, R>(
+/// Create a response for a polling request.
+/// The payload is compressed if the client accepts it and if it is bigger than the compression threshold.
+fn payload_response<B>(
+    data: Vec<u8>,
+    is_binary: bool,
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))] req_headers: &HeaderMap,
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))] config: &EngineIoConfig,
+) -> Result<Response<ResponseBody<B>>, http::Error> {
+    #[cfg(feature = "compression")]
+    if data.len() >= config.compression_threshold {
+        if let Some(encoding) = compression::Encoding::from_accept_encoding(req_headers) {
+            match encoding.compress(&data) {
+                Ok(compressed) => {
+                    use http::header::*;
+                    let mut res = http_response(StatusCode::OK, compressed, is_binary)?;
+                    let headers = res.headers_mut();
+                    headers.insert(
+                        CONTENT_ENCODING,
+                        HeaderValue::from_static(encoding.as_str()),
+                    );
+                    headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+                    return Ok(res);
+                }
+                Err(_e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("error compressing polling payload: {:?}", _e);
+                }
+            }
+        }
+    }
+    http_response(StatusCode::OK, data, is_binary)
+}
+
+pub async fn open_req<H, B, R>(
     engine: Arc<EngineIo<H>>,
     protocol: ProtocolVersion,
     req: Request<R>,
-    #[cfg(feature = "v3")] supports_binary: bool,
+    #[cfg(feature = "v3")] force_base64: bool,
 ) -> Result<Response<ResponseBody<B>>, Error>
 where
     H: EngineIoHandler,
     B: Send + 'static,
 {
+    let parts = req.into_parts().0;
+    engine.check_accepting()?;
+    let _permit = engine.config.check_handshake_rate(&parts)?;
     let socket = engine.create_session(
+        Sid::new(),
         protocol,
         TransportType::Polling,
-        req.into_parts().0,
+        parts,
         #[cfg(feature = "v3")]
-        supports_binary,
+        force_base64,
     );
 
-    let packet = OpenPacket::new(TransportType::Polling, socket.id, &engine.config);
+    // The session must be known by the store before the client can send its next requests
+    if let Some(touch) = engine.touch_session(&socket) {
+        touch.await;
+    }
 
-    socket.spawn_heartbeat(engine.config.ping_interval, engine.config.ping_timeout);
+    let packet = OpenPacket::new(
+        TransportType::Polling,
+        socket.id,
+        socket.protocol,
+        &engine.config,
+    );
 
-    let packet: String = Packet::Open(packet).try_into().unwrap();
+    let packet = Packet::Open(packet);
+    socket.record(Direction::Outbound, std::slice::from_ref(&packet));
+    let packet = packet.encode();
     let packet = {
         #[cfg(feature = "v3")]
         {
@@ -80,7 +141,19 @@ where
         #[cfg(not(feature = "v3"))]
         packet
     };
-    http_response(StatusCode::OK, packet, false).map_err(Error::Http)
+    let mut res = http_response(StatusCode::OK, packet, false)?;
+    engine.config.set_cookie(socket.id, res.headers_mut());
+    let handshake = Handshake {
+        sid: Some(socket.id),
+        transport: TransportType::Polling,
+        req: &socket.req_parts,
+    };
+    engine
+        .config
+        .call_handshake_hook(&handshake, res.headers_mut(), &HANDSHAKE_RESERVED_HEADERS);
+
+    socket.spawn_heartbeat(engine.config.ping_interval, engine.config.ping_timeout);
+    Ok(res)
 }
 
 /// Handle http polling request
@@ -91,19 +164,36 @@ pub async fn polling_req<B, H>(
     engine: Arc<EngineIo<H>>,
     protocol: ProtocolVersion,
     sid: Sid,
+    req_headers: HeaderMap,
 ) -> Result<Response<ResponseBody<B>>, Error>
 where
     B: Send + 'static,
     H: EngineIoHandler,
 {
-    let socket = engine.get_socket(sid).ok_or(Error::UnknownSessionID(sid))?;
+    let socket = match engine.get_socket_or_err(sid).await {
+        Ok(socket) => socket,
+        // The session is owned by another server (e.g. behind a load balancer without sticky sessions),
+        // the client is sent a close packet so that it can cleanly reconnect.
+        Err(Error::ForeignSessionID(_)) => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={sid}] session owned by another server, sending close");
+            let Payload {
+                data, has_binary, ..
+            } = packet_payload(Packet::Close, protocol);
+            return Ok(http_response(StatusCode::OK, data, has_binary)?);
+        }
+        Err(e) => return Err(e),
+    };
     if !socket.is_http() {
         return Err(Error::TransportMismatch);
     }
+    if let Some(touch) = engine.touch_session(&socket) {
+        touch.await;
+    }
 
     // If the socket is already locked, it means that the socket is being used by another request
     // In case of multiple http polling, session should be closed
-    let rx = match socket.internal_rx.try_lock() {
+    let mut rx = match socket.internal_rx.try_lock() {
         Ok(s) => s,
         Err(_) => {
             socket.close(DisconnectReason::MultipleHttpPollingError);
@@ -114,17 +204,92 @@ where
     #[cfg(feature = "tracing")]
     tracing::debug!("[sid={sid}] polling request");
 
+    // Registered before checking the upgrade state so that an upgrade starting in the meantime is not missed
+    let released = socket.poll_released();
+    tokio::pin!(released);
+    released.as_mut().enable();
+
+    // While the client is upgrading, the packets are kept for the websocket transport
+    // so that none of them is sent to a polling request that the client is not reading anymore.
+    if socket.is_upgrading() {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={sid}] upgrade in progress, sending noop");
+        let Payload {
+            data, has_binary, ..
+        } = packet_payload(Packet::Noop, protocol);
+        return Ok(payload_response(
+            data,
+            has_binary,
+            &req_headers,
+            &engine.config,
+        )?);
+    }
+
     let max_payload = engine.config.max_payload;
+    let coalesce_delay = engine.config.polling_coalesce_delay;
 
-    #[cfg(feature = "v3")]
-    let Payload { data, has_binary } =
-        payload::encoder(rx, protocol, socket.supports_binary, max_payload).await?;
-    #[cfg(not(feature = "v3"))]
-    let Payload { data, has_binary } = payload::encoder(rx, protocol, max_payload).await?;
+    let payload = async {
+        // The packets emitted in bursts are gathered in the same payload
+        if let Some(delay) = coalesce_delay {
+            payload::coalesce(&mut rx, delay, max_payload).await;
+        }
+        #[cfg(feature = "v3")]
+        let payload = payload::encoder(rx, protocol, !socket.force_base64, max_payload);
+        #[cfg(not(feature = "v3"))]
+        let payload = payload::encoder(rx, protocol, max_payload);
+        payload.await
+    };
+
+    // If an upgrade starts while the request is parked, it is released with a noop packet
+    // so that the client can send the upgrade packet on the websocket.
+    let payload = async {
+        tokio::select! {
+            payload = payload => payload,
+            _ = released => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("[sid={sid}] upgrade started, releasing polling request");
+                Ok(packet_payload(Packet::Noop, protocol))
+            }
+        }
+    };
+
+    // If nothing is sent before the polling duration, the request is released with a noop packet
+    // so that the client can re-poll or detect a dead connection.
+    let Payload {
+        data,
+        has_binary,
+        flushed,
+    } = match tokio::time::timeout(engine.config.polling_duration, payload).await {
+        Ok(payload) => payload?,
+        Err(_) => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={sid}] polling duration elapsed, sending noop");
+            packet_payload(Packet::Noop, protocol)
+        }
+    };
 
     #[cfg(feature = "tracing")]
-    tracing::debug!("[sid={sid}] sending data: {:?}", data);
-    Ok(http_response(StatusCode::OK, data, has_binary)?)
+    tracing::debug!(
+        parent: socket.span(),
+        "[sid={sid}] sending data: {}",
+        engine.config.payload_logging.format(&data)
+    );
+    let res = payload_response(data, has_binary, &req_headers, &engine.config)?;
+    Ok(res.map(|body| body.with_flushed(flushed)))
+}
+
+/// Create a payload containing a single text packet
+fn packet_payload(
+    packet: Packet,
+    #[cfg_attr(not(feature = "v3"), allow(unused_variables))] protocol: ProtocolVersion,
+) -> Payload {
+    let packet = packet.encode();
+    // The V3 protocol requires the packet length to be prepended to the packet.
+    #[cfg(feature = "v3")]
+    if protocol == ProtocolVersion::V3 {
+        return Payload::new(format!("{}:{}", packet.chars().count(), packet), false);
+    }
+    Payload::new(packet, false)
 }
 
 /// Handle http polling post request
@@ -143,47 +308,89 @@ where
     <R as Body>::Data: Send,
     B: Send + 'static,
 {
-    let socket = engine.get_socket(sid).ok_or(Error::UnknownSessionID(sid))?;
+    let socket = engine.get_socket_or_err(sid).await?;
     if !socket.is_http() {
         return Err(Error::TransportMismatch);
     }
 
-    let packets = payload::decoder(body, protocol, engine.config.max_payload);
+    // Hold the recv lock for the whole request so that an upgrade to websocket
+    // waits for all the packets of this request to be dispatched.
+    // The transport is checked again because an upgrade may have happened in the meantime.
+    let _recv_lock = socket.recv_lock.lock().await;
+    if !socket.is_http() {
+        return Err(Error::TransportMismatch);
+    }
+
+    let max_payload = engine.config.max_payload;
+    let utf8 = engine.config.utf8_validation;
+    #[cfg(feature = "compression")]
+    let packets = match compression::Encoding::from_content_encoding(body.headers())? {
+        Some(encoding) => {
+            let body = compression::decompress_req(body, encoding, max_payload).await?;
+            futures::future::Either::Left(payload::decoder(body, protocol, max_payload, utf8))
+        }
+        None => futures::future::Either::Right(payload::decoder(body, protocol, max_payload, utf8)),
+    };
+    #[cfg(not(feature = "compression"))]
+    let packets = payload::decoder(body, protocol, max_payload, utf8);
     futures::pin_mut!(packets);
 
+    let mut dispatched = 0;
     while let Some(packet) = packets.next().await {
-        match packet {
+        socket.touch_seen();
+        let res = match packet.map(|p| socket.record_inbound(p)) {
             Ok(Packet::Close) => {
                 #[cfg(feature = "tracing")]
                 tracing::debug!("[sid={sid}] closing session");
-                socket.send(Packet::Noop)?;
+                socket.send_priority(Packet::Noop)?;
                 engine.close_session(sid, DisconnectReason::TransportClose);
                 break;
             }
-            Ok(Packet::Pong | Packet::Ping) => socket
-                .heartbeat_tx
-                .try_send(())
-                .map_err(|_| Error::HeartbeatTimeout),
+            Ok(p @ (Packet::Pong | Packet::Ping)) => socket.recv_heartbeat(p),
             Ok(Packet::Message(msg)) => {
+                if engine.config.is_message_too_large(&msg) {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("[sid={sid}] message too large: {} bytes", msg.len());
+                    engine.report_error(sid, &Error::PayloadTooLarge);
+                    engine.close_session(sid, DisconnectReason::PacketParsingError);
+                    return Err(Error::PayloadTooLarge);
+                }
+                socket.touch_message();
                 engine.handler.on_message(msg, socket.clone());
                 Ok(())
             }
             Ok(Packet::Binary(bin) | Packet::BinaryV3(bin)) => {
-                engine.handler.on_binary(bin, socket.clone());
+                socket.touch_message();
+                if let Some(bin) = socket.recv_binary(bin) {
+                    engine.handler.on_binary(bin, socket.clone());
+                }
                 Ok(())
             }
+            // Noop packets carry no data, the client may send them to flush its polling buffer
+            Ok(Packet::Noop) => Ok(()),
             Ok(p) => {
                 #[cfg(feature = "tracing")]
-                tracing::debug!("[sid={sid}] bad packet received: {:?}", &p);
+                tracing::debug!(
+                    parent: socket.span(),
+                    "[sid={sid}] bad packet received: {}",
+                    p.log(socket.payload_logging())
+                );
                 Err(Error::BadPacket(p))
             }
             Err(e) => {
                 #[cfg(feature = "tracing")]
                 tracing::debug!("[sid={sid}] error parsing packet: {:?}", e);
+                engine.report_error(sid, &e);
                 engine.close_session(sid, DisconnectReason::PacketParsingError);
                 return Err(e);
             }
-        }?;
+        };
+        if let Err(e) = res {
+            engine.report_error(sid, &e);
+            return Err(e);
+        }
+        dispatched += 1;
+        engine.config.yield_after(dispatched).await;
     }
     Ok(http_response(StatusCode::OK, "ok", false)?)
 }
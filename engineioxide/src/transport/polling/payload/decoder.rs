@@ -8,24 +8,26 @@
 use futures::{Stream, StreamExt};
 use http::StatusCode;
 
-use crate::{errors::Error, packet::Packet};
-use bytes::Buf;
+use crate::{config::Utf8Validation, errors::Error, packet::Packet, ProtocolVersion};
+use bytes::{Buf, BufMut, BytesMut};
 use http_body::Body;
 use http_body_util::BodyStream;
+#[cfg(feature = "v3")]
 use std::io::BufRead;
 
+#[cfg(feature = "v3")]
 use super::buf::BufList;
 
+#[cfg(feature = "v3")]
 struct Payload<B: Body + Unpin> {
     body: BodyStream<B>,
     buffer: BufList<B::Data>,
     end_of_stream: bool,
     current_payload_size: u64,
-
-    #[cfg(feature = "v3")]
     yield_packets: u32,
 }
 
+#[cfg(feature = "v3")]
 impl<B: Body + Unpin> Payload<B> {
     fn new(body: B) -> Self {
         Self {
@@ -33,34 +35,49 @@ impl<B: Body + Unpin> Payload<B> {
             buffer: BufList::new(),
             end_of_stream: false,
             current_payload_size: 0,
-            #[cfg(feature = "v3")]
             yield_packets: 0,
         }
     }
 }
 
-/// Polls the body stream for data and adds it to the chunk list in the state
-/// Returns an error if the packet length exceeds the maximum allowed payload size
-async fn poll_body<B, E>(state: &mut Payload<B>, max_payload: u64) -> Result<(), Error>
+/// Reads the next data frame from the body stream.
+/// Returns `None` at the end of the stream.
+async fn next_data<B, E>(body: &mut BodyStream<B>) -> Result<Option<B::Data>, Error>
 where
     B: Body<Error = E> + Unpin,
     E: std::fmt::Debug,
 {
-    let data = match state.body.next().await.transpose() {
-        Ok(Some(frame)) if frame.is_data() => Ok(frame
-            .into_data()
-            .unwrap_or_else(|_| unreachable!("frame.is_data() is true"))),
-        // None or Trailer frames -> ignore and EOS
-        Ok(_) => {
-            state.end_of_stream = true;
-            return Ok(());
+    match body.next().await.transpose() {
+        Ok(Some(frame)) if frame.is_data() => {
+            Ok(Some(frame.into_data().unwrap_or_else(|_| {
+                unreachable!("frame.is_data() is true")
+            })))
         }
+        // None or Trailer frames -> ignore and EOS
+        Ok(_) => Ok(None),
         Err(_e) => {
             #[cfg(feature = "tracing")]
             tracing::debug!("error reading body stream: {:?}", _e);
             Err(Error::HttpErrorResponse(StatusCode::BAD_REQUEST))
         }
-    }?;
+    }
+}
+
+/// Polls the body stream for data and adds it to the chunk list in the state
+/// Returns an error if the packet length exceeds the maximum allowed payload size
+#[cfg(feature = "v3")]
+async fn poll_body<B, E>(state: &mut Payload<B>, max_payload: u64) -> Result<(), Error>
+where
+    B: Body<Error = E> + Unpin,
+    E: std::fmt::Debug,
+{
+    let data = match next_data(&mut state.body).await? {
+        Some(data) => data,
+        None => {
+            state.end_of_stream = true;
+            return Ok(());
+        }
+    };
     if state.current_payload_size + (data.remaining() as u64) <= max_payload {
         state.current_payload_size += data.remaining() as u64;
         state.buffer.push(data);
@@ -70,51 +87,134 @@ where
     }
 }
 
-pub fn v4_decoder<B, E>(body: B, max_payload: u64) -> impl Stream<Item = Result<Packet, Error>>
+/// An incremental decoder for v4 payloads.
+///
+/// Chunks are pushed as they are received from the body and packets can be read
+/// as soon as their separator is received, without waiting for the full body.
+/// A separator or a base64 binary packet split across chunks is handled transparently.
+pub(crate) struct PayloadDecoder {
+    buffer: BytesMut,
+    /// Position up to which the buffer has already been searched for a separator
+    scanned: usize,
+    current_payload_size: u64,
+    max_payload: u64,
+    utf8_validation: Utf8Validation,
+}
+
+impl PayloadDecoder {
+    pub(crate) fn new(max_payload: u64, utf8_validation: Utf8Validation) -> Self {
+        Self {
+            buffer: BytesMut::new(),
+            scanned: 0,
+            current_payload_size: 0,
+            max_payload,
+            utf8_validation,
+        }
+    }
+
+    /// Push a chunk of data in the decoder.
+    /// Returns an error if the total payload size exceeds the maximum allowed payload size
+    pub(crate) fn push(&mut self, chunk: impl Buf) -> Result<(), Error> {
+        let size = self.current_payload_size + chunk.remaining() as u64;
+        if size > self.max_payload {
+            return Err(Error::PayloadTooLarge);
+        }
+        self.current_payload_size = size;
+        self.buffer.put(chunk);
+        Ok(())
+    }
+
+    /// Returns the next complete packet if its separator has been received
+    pub(crate) fn next_packet(&mut self) -> Option<Result<Packet, Error>> {
+        use super::PACKET_SEPARATOR_V4;
+        match self.buffer[self.scanned..]
+            .iter()
+            .position(|b| *b == PACKET_SEPARATOR_V4)
+        {
+            Some(i) => {
+                let mut packet = self.buffer.split_to(self.scanned + i + 1);
+                packet.truncate(packet.len() - 1); // Remove the separator
+                self.scanned = 0;
+                Some(self.decode(packet))
+            }
+            None => {
+                self.scanned = self.buffer.len();
+                None
+            }
+        }
+    }
+
+    /// Returns the last packet, which is not followed by a separator, once the body is fully received
+    pub(crate) fn finish(&mut self) -> Option<Result<Packet, Error>> {
+        if let Some(packet) = self.next_packet() {
+            return Some(packet);
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let packet = self.buffer.split();
+        self.scanned = 0;
+        Some(self.decode(packet))
+    }
+
+    fn decode(&self, packet: BytesMut) -> Result<Packet, Error> {
+        decode_text(packet.into(), self.utf8_validation)
+    }
+}
+
+/// Decodes a text packet, validating its data according to the [`Utf8Validation`] mode.
+/// The data is validated once and is not copied, invalid UTF-8 is reported as an invalid packet.
+fn decode_text(packet: Vec<u8>, utf8_validation: Utf8Validation) -> Result<Packet, Error> {
+    let packet = match String::from_utf8(packet) {
+        Ok(packet) => packet,
+        Err(e) if utf8_validation == Utf8Validation::Lossy => {
+            String::from_utf8_lossy(e.as_bytes()).into_owned()
+        }
+        Err(_) => return Err(Error::InvalidPacketLength),
+    };
+    Ok(Packet::decode(packet, ProtocolVersion::V4)?)
+}
+
+pub fn v4_decoder<B, E>(
+    body: B,
+    max_payload: u64,
+    utf8_validation: Utf8Validation,
+) -> impl Stream<Item = Result<Packet, Error>>
 where
     B: Body<Error = E> + Unpin,
     E: std::fmt::Debug,
 {
-    use super::PACKET_SEPARATOR_V4;
     #[cfg(feature = "tracing")]
     tracing::debug!("decoding payload with v4 decoder");
 
-    let state = Payload::new(body);
+    // (body stream, decoder, end of stream)
+    let state = (
+        BodyStream::new(body),
+        PayloadDecoder::new(max_payload, utf8_validation),
+        false,
+    );
 
-    futures::stream::unfold(state, move |mut state| async move {
-        let mut packet_buf: Vec<u8> = Vec::new();
+    futures::stream::unfold(state, move |(mut body, mut decoder, mut eos)| async move {
         loop {
-            // Read data from the body stream into the buffer
-            if !state.end_of_stream {
-                if let Err(e) = poll_body(&mut state, max_payload).await {
-                    break Some((Err(e), state));
-                }
+            // Packets already buffered are yielded before reading more data from the body
+            if let Some(packet) = decoder.next_packet() {
+                break Some((packet, (body, decoder, eos)));
             }
-
-            // Read from the buffer until the packet separator is found
-            if let Err(e) = (&mut state.buffer)
-                .reader()
-                .read_until(PACKET_SEPARATOR_V4, &mut packet_buf)
-            {
-                break Some((Err(Error::Io(e)), state));
+            if eos {
+                break decoder.finish().map(|p| (p, (body, decoder, eos)));
             }
-
-            let separator_found = packet_buf.ends_with(&[PACKET_SEPARATOR_V4]);
-
-            if separator_found {
-                packet_buf.pop(); // Remove the separator from the packet buffer
-            }
-
-            // Check if a complete packet is found or reached end of stream with remaining data
-            if separator_found
-                || (state.end_of_stream && state.buffer.remaining() == 0 && !packet_buf.is_empty())
-            {
-                let packet = std::str::from_utf8(&packet_buf)
-                    .map_err(|_| Error::InvalidPacketLength)
-                    .and_then(Packet::try_from); // Convert the packet buffer to a Packet object
-                break Some((packet, state)); // Emit the packet and the updated state
-            } else if state.end_of_stream && state.buffer.remaining() == 0 {
-                break None; // Reached end of stream with no more data, end the stream
+            // In case of error, the buffered data is discarded and the stream ends
+            let res = match next_data(&mut body).await {
+                Ok(Some(data)) => decoder.push(data),
+                Ok(None) => {
+                    eos = true;
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                let decoder = PayloadDecoder::new(max_payload, utf8_validation);
+                break Some((Err(e), (body, decoder, true)));
             }
         }
     })
@@ -124,6 +224,7 @@ where
 pub fn v3_binary_decoder<B, E>(
     body: B,
     max_payload: u64,
+    utf8_validation: Utf8Validation,
 ) -> impl Stream<Item = Result<Packet, Error>>
 where
     B: Body<Error = E> + Unpin,
@@ -175,17 +276,13 @@ where
                         _ => break Some((Err(Error::InvalidPacketLength), state)),
                     }
 
-                    if packet_buf.len() > 9 {
+                    // The length is written with one byte per decimal digit
+                    let digits = &packet_buf[1..];
+                    if digits.is_empty() || digits.len() > 8 || digits.iter().any(|&d| d > 9) {
                         break Some((Err(Error::InvalidPacketLength), state));
                     }
-
-                    let size_str = &packet_buf[1..]
-                        .iter()
-                        .map(|b| (b + 48) as char)
-                        .collect::<String>();
-                    if let Ok(size) = size_str.parse() {
-                        packet_size = size;
-                    } else {
+                    packet_size = digits.iter().fold(0, |size, &d| size * 10 + d as u64);
+                    if packet_size == 0 {
                         break Some((Err(Error::InvalidPacketLength), state));
                     }
                     packet_buf.clear();
@@ -202,16 +299,22 @@ where
                 reader.read_to_end(&mut packet_buf).unwrap();
                 // Read the packet data
                 let packet = match packet_type.unwrap() {
-                    STRING_PACKET_IDENTIFIER_V3 => std::str::from_utf8(&packet_buf)
-                        .map_err(|_| Error::InvalidPacketLength)
-                        .and_then(Packet::try_from), // Convert the packet buffer to a Packet object
+                    STRING_PACKET_IDENTIFIER_V3 => decode_text(packet_buf, utf8_validation),
                     BINARY_PACKET_IDENTIFIER_V3 => Ok(Packet::BinaryV3(packet_buf)),
                     _ => Err(Error::InvalidPacketLength),
                 };
 
                 break Some((packet, state));
-            } else if state.end_of_stream && state.buffer.remaining() == 0 {
+            } else if state.end_of_stream
+                && state.buffer.remaining() == 0
+                && packet_type.is_none()
+                && packet_buf.is_empty()
+            {
                 break None;
+            } else if state.end_of_stream {
+                // The body ended in the middle of a packet
+                state.buffer = BufList::new();
+                break Some((Err(Error::InvalidPacketLength), state));
             }
         }
     })
@@ -334,7 +437,8 @@ pub fn v3_string_decoder(
             // Check if the packet length matches the number of characters
             if let Ok(packet) = std::str::from_utf8(&packet_buf) {
                 if packet.graphemes(true).count() == packet_graphemes_len {
-                    let packet = Packet::try_from(packet).map_err(|_| Error::InvalidPacketLength);
+                    let packet = Packet::decode(packet.to_string(), ProtocolVersion::V3)
+                        .map_err(|_| Error::InvalidPacketLength);
                     state.yield_packets += 1;
                     break Some((packet, state)); // Emit the packet and the updated state
                 }
@@ -352,6 +456,7 @@ mod tests {
     use futures::StreamExt;
     use http_body::Frame;
     use http_body_util::{Full, StreamBody};
+    use std::convert::Infallible;
 
     use crate::packet::Packet;
 
@@ -362,7 +467,7 @@ mod tests {
     #[tokio::test]
     async fn payload_iterator_v4() {
         let data = Full::new(Bytes::from("4foo\x1e4€f\x1e4f"));
-        let payload = v4_decoder(data, MAX_PAYLOAD);
+        let payload = v4_decoder(data, MAX_PAYLOAD, Utf8Validation::Strict);
         futures::pin_mut!(payload);
         assert!(matches!(
             payload.next().await.unwrap().unwrap(),
@@ -389,7 +494,7 @@ mod tests {
                     .map(Frame::data)
                     .map(Ok::<_, std::convert::Infallible>),
             ));
-            let payload = v4_decoder(stream, MAX_PAYLOAD);
+            let payload = v4_decoder(stream, MAX_PAYLOAD, Utf8Validation::Strict);
             futures::pin_mut!(payload);
             assert!(matches!(
                 payload.next().await.unwrap().unwrap(),
@@ -417,7 +522,7 @@ mod tests {
                     .map(Frame::data)
                     .map(Ok::<_, std::convert::Infallible>),
             ));
-            let payload = v4_decoder(stream, MAX_PAYLOAD);
+            let payload = v4_decoder(stream, MAX_PAYLOAD, Utf8Validation::Strict);
             futures::pin_mut!(payload);
             let packet = payload.next().await.unwrap();
             assert!(matches!(packet, Err(Error::PayloadTooLarge)));
@@ -457,7 +562,7 @@ mod tests {
         ];
         const BINARY_PAYLOAD: &[u8] = &[1, 2, 3, 4];
         let data = Full::new(Bytes::from(PAYLOAD));
-        let payload = v3_binary_decoder(data, MAX_PAYLOAD);
+        let payload = v3_binary_decoder(data, MAX_PAYLOAD, Utf8Validation::Strict);
         futures::pin_mut!(payload);
         assert!(matches!(
             payload.next().await.unwrap().unwrap(),
@@ -519,7 +624,7 @@ mod tests {
                     .map(Frame::data)
                     .map(Ok::<_, std::convert::Infallible>),
             ));
-            let payload = v3_binary_decoder(stream, MAX_PAYLOAD);
+            let payload = v3_binary_decoder(stream, MAX_PAYLOAD, Utf8Validation::Strict);
             futures::pin_mut!(payload);
             assert!(matches!(
                 payload.next().await.unwrap().unwrap(),
@@ -545,7 +650,7 @@ mod tests {
                     .map(Frame::data)
                     .map(Ok::<_, std::convert::Infallible>),
             ));
-            let payload = v3_binary_decoder(stream, MAX_PAYLOAD);
+            let payload = v3_binary_decoder(stream, MAX_PAYLOAD, Utf8Validation::Strict);
             futures::pin_mut!(payload);
             let packet = payload.next().await.unwrap();
             assert!(matches!(packet, Err(Error::PayloadTooLarge)));
@@ -562,4 +667,112 @@ mod tests {
             assert!(matches!(packet, Err(Error::PayloadTooLarge)));
         }
     }
+
+    #[test]
+    fn payload_decoder_random_chunks_v4() {
+        const PACKETS: &[&str] = &[
+            "4foo",
+            "bAQIDBA==",
+            "4€f",
+            "2",
+            "3",
+            "b",
+            "4",
+            "4{\"a\":[1,2,3]}",
+            "bAAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+            "6",
+        ];
+        // Simple xorshift generator so that the test is deterministic
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..500 {
+            let count = 1 + next() % 8;
+            let packets: Vec<&str> = (0..count)
+                .map(|_| PACKETS[(next() % PACKETS.len() as u64) as usize])
+                .collect();
+            let data = packets.join("\x1e");
+            let expected: Vec<Packet> = packets
+                .iter()
+                .map(|p| Packet::try_from(*p).unwrap())
+                .collect();
+
+            // Split the payload at random positions
+            let mut decoder = PayloadDecoder::new(MAX_PAYLOAD, Utf8Validation::Strict);
+            let mut decoded = Vec::new();
+            let mut data = data.as_bytes();
+            while !data.is_empty() {
+                let len = 1 + (next() as usize % data.len().min(12));
+                decoder.push(&data[..len]).unwrap();
+                data = &data[len..];
+                while let Some(packet) = decoder.next_packet() {
+                    decoded.push(packet.unwrap());
+                }
+            }
+            if let Some(packet) = decoder.finish() {
+                decoded.push(packet.unwrap());
+            }
+            assert_eq!(decoded, expected);
+        }
+    }
+
+    #[test]
+    fn payload_decoder_max_payload_v4() {
+        let mut decoder = PayloadDecoder::new(8, Utf8Validation::Strict);
+        decoder.push(&b"4foo\x1e"[..]).unwrap();
+        assert!(matches!(
+            decoder.push(&b"4foo"[..]),
+            Err(Error::PayloadTooLarge)
+        ));
+    }
+
+    #[test]
+    fn payload_decoder_invalid_utf8_v4() {
+        const DATA: &[u8] = b"4caf\xc3\x1e4ok";
+        let mut decoder = PayloadDecoder::new(MAX_PAYLOAD, Utf8Validation::Strict);
+        decoder.push(DATA).unwrap();
+        assert!(matches!(
+            decoder.next_packet(),
+            Some(Err(Error::InvalidPacketLength))
+        ));
+
+        let mut decoder = PayloadDecoder::new(MAX_PAYLOAD, Utf8Validation::Lossy);
+        decoder.push(DATA).unwrap();
+        assert_eq!(
+            decoder.next_packet().unwrap().unwrap(),
+            Packet::Message("caf\u{FFFD}".into())
+        );
+        assert_eq!(
+            decoder.finish().unwrap().unwrap(),
+            Packet::Message("ok".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn payload_stream_yields_before_end_v4() {
+        let (tx, rx) = futures::channel::mpsc::unbounded::<Result<Frame<Bytes>, Infallible>>();
+        let payload = v4_decoder(StreamBody::new(rx), MAX_PAYLOAD, Utf8Validation::Strict);
+        futures::pin_mut!(payload);
+
+        // The first packet is complete, the second one is split with the end of the body not received yet
+        tx.unbounded_send(Ok(Frame::data(Bytes::from("4foo\x1ebAQ"))))
+            .unwrap();
+        assert_eq!(
+            payload.next().await.unwrap().unwrap(),
+            Packet::Message("foo".into())
+        );
+        tx.unbounded_send(Ok(Frame::data(Bytes::from("ID"))))
+            .unwrap();
+        drop(tx);
+        assert_eq!(
+            payload.next().await.unwrap().unwrap(),
+            Packet::Binary(vec![1, 2, 3])
+        );
+        assert!(payload.next().await.is_none());
+    }
 }
@@ -7,7 +7,7 @@
 //!    * binary encoder (used when there is binary packets and the client supports binary)
 //!
 
-use tokio::sync::MutexGuard;
+use tokio::sync::{oneshot, MutexGuard};
 
 use crate::{
     errors::Error, packet::Packet, peekable::PeekableReceiver, socket::PacketBuf,
@@ -24,12 +24,21 @@ use crate::{
 /// * `payload_len` - The current payload length
 /// * `max_payload` - The maximum payload length
 /// * `b64` - If binary packets should be encoded in base64
+/// * `flushed` - The flush notifiers of the payload, the notifier of the new packet buf is moved here
 fn try_recv_packet(
     rx: &mut MutexGuard<'_, PeekableReceiver<PacketBuf>>,
     payload_len: usize,
     max_payload: u64,
     b64: bool,
+    flushed: &mut Vec<oneshot::Sender<()>>,
 ) -> Option<PacketBuf> {
+    // Drop the packets that were not sent before their deadline
+    while rx.peek().is_some_and(|p| p.is_expired()) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("dropping expired packets");
+        rx.try_recv().ok();
+    }
+
     if let Some(packets) = rx.peek() {
         let size = packets.iter().map(|p| p.get_size_hint(b64)).sum::<usize>();
         if (payload_len + size) as u64 > max_payload {
@@ -39,7 +48,10 @@ fn try_recv_packet(
         }
     }
 
-    let packets = rx.try_recv().ok();
+    let mut packets = rx.try_recv().ok();
+    if let Some(tx) = packets.as_mut().and_then(PacketBuf::take_flush_notifier) {
+        flushed.push(tx);
+    }
 
     if Some(&Packet::Close) == packets.as_ref().and_then(|p| p.first()) {
         #[cfg(feature = "tracing")]
@@ -49,7 +61,10 @@ fn try_recv_packet(
     }
 
     #[cfg(feature = "tracing")]
-    tracing::debug!("sending packet: {:?}", packets);
+    tracing::debug!(
+        "sending {} packets",
+        packets.as_ref().map_or(0, |p| p.len())
+    );
     packets
 }
 
@@ -57,8 +72,18 @@ fn try_recv_packet(
 /// but wait for a new packet if there is no packet in the buffer
 async fn recv_packet(
     rx: &mut MutexGuard<'_, PeekableReceiver<PacketBuf>>,
+    flushed: &mut Vec<oneshot::Sender<()>>,
 ) -> Result<PacketBuf, Error> {
-    let packet = rx.recv().await.ok_or(Error::Aborted)?;
+    let mut packet = rx.recv().await.ok_or(Error::Aborted)?;
+    // Drop the packets that were not sent before their deadline
+    while packet.is_expired() {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("dropping expired packets");
+        packet = rx.recv().await.ok_or(Error::Aborted)?;
+    }
+    if let Some(tx) = packet.take_flush_notifier() {
+        flushed.push(tx);
+    }
     if Some(&Packet::Close) == packet.first() {
         #[cfg(feature = "tracing")]
         tracing::debug!("Received close packet, closing channel");
@@ -66,7 +91,7 @@ async fn recv_packet(
     }
 
     #[cfg(feature = "tracing")]
-    tracing::debug!("sending packet: {:?}", packet);
+    tracing::debug!("sending {} packets", packet.len());
     Ok(packet)
 }
 
@@ -81,14 +106,19 @@ pub async fn v4_encoder(
     #[cfg(feature = "tracing")]
     tracing::debug!("encoding payload with v4 encoder");
     let mut data: String = String::new();
+    let mut flushed = Vec::new();
 
     // Send all packets in the buffer
     const PUNCTUATION_LEN: usize = 1;
-    while let Some(packets) =
-        try_recv_packet(&mut rx, data.len() + PUNCTUATION_LEN, max_payload, true)
-    {
+    while let Some(packets) = try_recv_packet(
+        &mut rx,
+        data.len() + PUNCTUATION_LEN,
+        max_payload,
+        true,
+        &mut flushed,
+    ) {
         for packet in packets {
-            let packet: String = packet.try_into()?;
+            let packet = packet.encode();
 
             if !data.is_empty() {
                 data.push(std::char::from_u32(PACKET_SEPARATOR_V4 as u32).unwrap());
@@ -99,14 +129,14 @@ pub async fn v4_encoder(
 
     // If there is no packet in the buffer, wait for the next packet
     if data.is_empty() {
-        let packets = recv_packet(&mut rx).await?;
+        let packets = recv_packet(&mut rx, &mut flushed).await?;
         for packet in packets {
-            let packet: String = packet.try_into()?;
+            let packet = packet.encode();
             data.push_str(&packet);
         }
     }
 
-    Ok(Payload::new(data, false))
+    Ok(Payload::new(data, false).with_flushed(flushed))
 }
 
 /// Encode one packet into a *binary* payload according to the
@@ -127,7 +157,7 @@ pub fn v3_bin_packet_encoder(packet: Packet, data: &mut Vec<u8>) -> Result<(), E
             data.extend_from_slice(&bin); // raw data
         }
         packet => {
-            let packet: String = packet.try_into()?;
+            let packet = packet.encode();
             data.push(0x0); // 0 = string
 
             let len = packet.len().to_string();
@@ -146,7 +176,7 @@ pub fn v3_bin_packet_encoder(packet: Packet, data: &mut Vec<u8>) -> Result<(), E
 #[cfg(feature = "v3")]
 pub fn v3_string_packet_encoder(packet: Packet, data: &mut Vec<u8>) -> Result<(), Error> {
     use crate::transport::polling::payload::STRING_PACKET_SEPARATOR_V3;
-    let packet: String = packet.try_into()?;
+    let packet = packet.encode();
     let packet = format!(
         "{}{}{}",
         packet.chars().count(),
@@ -165,6 +195,7 @@ pub async fn v3_binary_encoder(
     max_payload: u64,
 ) -> Result<Payload, Error> {
     let mut data: Vec<u8> = Vec::new();
+    let mut flushed = Vec::new();
     let mut packet_buffer: Vec<Packet> = Vec::new();
 
     // estimated size of the `packet_buffer` in bytes
@@ -177,7 +208,9 @@ pub async fn v3_binary_encoder(
     // buffer all packets to find if there is binary packets
     let mut has_binary = false;
 
-    while let Some(packets) = try_recv_packet(&mut rx, estimated_size, max_payload, false) {
+    while let Some(packets) =
+        try_recv_packet(&mut rx, estimated_size, max_payload, false, &mut flushed)
+    {
         for packet in packets {
             if packet.is_binary() {
                 has_binary = true;
@@ -202,7 +235,7 @@ pub async fn v3_binary_encoder(
 
     // If there is no packet in the buffer, wait for the next packet
     if data.is_empty() {
-        let packets = recv_packet(&mut rx).await?;
+        let packets = recv_packet(&mut rx, &mut flushed).await?;
         for packet in packets {
             match packet {
                 Packet::BinaryV3(_) | Packet::Binary(_) => {
@@ -217,8 +250,8 @@ pub async fn v3_binary_encoder(
     }
 
     #[cfg(feature = "tracing")]
-    tracing::debug!("sending packet: {:?}", &data);
-    Ok(Payload::new(data, has_binary))
+    tracing::debug!("sending payload of {} bytes", data.len());
+    Ok(Payload::new(data, has_binary).with_flushed(flushed))
 }
 
 /// Encode multiple packet packet into a *string* payload according to the
@@ -229,6 +262,7 @@ pub async fn v3_string_encoder(
     max_payload: u64,
 ) -> Result<Payload, Error> {
     let mut data: Vec<u8> = Vec::new();
+    let mut flushed = Vec::new();
 
     #[cfg(feature = "tracing")]
     tracing::debug!("encoding payload with v3 string encoder");
@@ -238,7 +272,9 @@ pub async fn v3_string_encoder(
     let max_packet_size_len = max_payload.checked_ilog10().unwrap_or(0) as usize + 1;
     // Current size of the payload
     let current_size = data.len() + PUNCTUATION_LEN + max_packet_size_len;
-    while let Some(packets) = try_recv_packet(&mut rx, current_size, max_payload, true) {
+    while let Some(packets) =
+        try_recv_packet(&mut rx, current_size, max_payload, true, &mut flushed)
+    {
         for packet in packets {
             v3_string_packet_encoder(packet, &mut data)?;
         }
@@ -246,13 +282,13 @@ pub async fn v3_string_encoder(
 
     // If there is no packet in the buffer, wait for the next packet
     if data.is_empty() {
-        let packets = recv_packet(&mut rx).await?;
+        let packets = recv_packet(&mut rx, &mut flushed).await?;
         for packet in packets {
             v3_string_packet_encoder(packet, &mut data)?;
         }
     }
 
-    Ok(Payload::new(data, false))
+    Ok(Payload::new(data, false).with_flushed(flushed))
 }
 
 #[cfg(test)]
@@ -263,36 +299,63 @@ mod tests {
     use PacketBuf;
 
     use super::*;
+    use crate::{channel::channel, config::OverflowPolicy};
     const MAX_PAYLOAD: u64 = 100_000;
 
     #[tokio::test]
     async fn encode_v4_payload() {
         const PAYLOAD: &str = "4hello€\x1ebAQIDBA==\x1e4hello€";
-        let (tx, rx) = tokio::sync::mpsc::channel::<PacketBuf>(10);
-        let rx = Mutex::new(PeekableReceiver::new(rx));
+        let (tx, rx) = channel::<PacketBuf>(10, OverflowPolicy::Reject);
+        let rx = Mutex::new(PeekableReceiver::new(
+            rx,
+            channel(1, OverflowPolicy::Reject).1,
+        ));
         let rx = rx.lock().await;
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Binary(vec![1, 2, 3, 4])])
+        tx.try_send(smallvec::smallvec![Packet::Binary(vec![1, 2, 3, 4])].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
         let Payload { data, .. } = v4_encoder(rx, MAX_PAYLOAD).await.unwrap();
         assert_eq!(data, PAYLOAD.as_bytes());
     }
 
+    #[tokio::test]
+    async fn expired_packets_v4() {
+        use tokio::time::Instant;
+        let (tx, rx) = channel::<PacketBuf>(10, OverflowPolicy::Reject);
+        let rx = Mutex::new(PeekableReceiver::new(
+            rx,
+            channel(1, OverflowPolicy::Reject).1,
+        ));
+        let rx = rx.lock().await;
+        let expired = PacketBuf::with_deadline(
+            smallvec::smallvec![Packet::Message("stale".into())],
+            Instant::now(),
+        );
+        tx.try_send(expired).unwrap();
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
+            .unwrap();
+        let Payload { data, .. } = v4_encoder(rx, MAX_PAYLOAD).await.unwrap();
+        assert_eq!(data, "4hello€".as_bytes());
+    }
+
     #[tokio::test]
     async fn max_payload_v4() {
         const MAX_PAYLOAD: u64 = 10;
-        let (tx, rx) = tokio::sync::mpsc::channel::<PacketBuf>(10);
-        let mutex = Mutex::new(PeekableReceiver::new(rx));
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        let (tx, rx) = channel::<PacketBuf>(10, OverflowPolicy::Reject);
+        let mutex = Mutex::new(PeekableReceiver::new(
+            rx,
+            channel(1, OverflowPolicy::Reject).1,
+        ));
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Binary(vec![1, 2, 3, 4])])
+        tx.try_send(smallvec::smallvec![Packet::Binary(vec![1, 2, 3, 4])].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
         {
             let rx = mutex.lock().await;
@@ -315,15 +378,18 @@ mod tests {
     #[tokio::test]
     async fn encode_v3b64_payload() {
         const PAYLOAD: &str = "7:4hello€10:b4AQIDBA==7:4hello€";
-        let (tx, rx) = tokio::sync::mpsc::channel::<PacketBuf>(10);
-        let mutex = Mutex::new(PeekableReceiver::new(rx));
+        let (tx, rx) = channel::<PacketBuf>(10, OverflowPolicy::Reject);
+        let mutex = Mutex::new(PeekableReceiver::new(
+            rx,
+            channel(1, OverflowPolicy::Reject).1,
+        ));
         let rx = mutex.lock().await;
 
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::BinaryV3(vec![1, 2, 3, 4])])
+        tx.try_send(smallvec::smallvec![Packet::BinaryV3(vec![1, 2, 3, 4])].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
         let Payload {
             data, has_binary, ..
@@ -337,15 +403,18 @@ mod tests {
     async fn max_payload_v3_b64() {
         const MAX_PAYLOAD: u64 = 10;
 
-        let (tx, rx) = tokio::sync::mpsc::channel::<PacketBuf>(10);
-        let mutex = Mutex::new(PeekableReceiver::new(rx));
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        let (tx, rx) = channel::<PacketBuf>(10, OverflowPolicy::Reject);
+        let mutex = Mutex::new(PeekableReceiver::new(
+            rx,
+            channel(1, OverflowPolicy::Reject).1,
+        ));
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::BinaryV3(vec![1, 2, 3, 4])])
+        tx.try_send(smallvec::smallvec![Packet::BinaryV3(vec![1, 2, 3, 4])].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
         {
             let rx = mutex.lock().await;
@@ -365,13 +434,16 @@ mod tests {
         const PAYLOAD: [u8; 20] = [
             0, 9, 255, 52, 104, 101, 108, 108, 111, 226, 130, 172, 1, 5, 255, 4, 1, 2, 3, 4,
         ];
-        let (tx, rx) = tokio::sync::mpsc::channel::<PacketBuf>(10);
-        let mutex = Mutex::new(PeekableReceiver::new(rx));
+        let (tx, rx) = channel::<PacketBuf>(10, OverflowPolicy::Reject);
+        let mutex = Mutex::new(PeekableReceiver::new(
+            rx,
+            channel(1, OverflowPolicy::Reject).1,
+        ));
         let rx = mutex.lock().await;
 
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::BinaryV3(vec![1, 2, 3, 4])])
+        tx.try_send(smallvec::smallvec![Packet::BinaryV3(vec![1, 2, 3, 4])].into())
             .unwrap();
         let Payload {
             data, has_binary, ..
@@ -389,15 +461,18 @@ mod tests {
             0, 1, 1, 255, 52, 104, 101, 108, 108, 111, 111, 111, 226, 130, 172, 1, 5, 255, 4, 1, 2,
             3, 4,
         ];
-        let (tx, rx) = tokio::sync::mpsc::channel::<PacketBuf>(10);
-        let mutex = Mutex::new(PeekableReceiver::new(rx));
-        tx.try_send(smallvec::smallvec![Packet::Message("hellooo€".into())])
+        let (tx, rx) = channel::<PacketBuf>(10, OverflowPolicy::Reject);
+        let mutex = Mutex::new(PeekableReceiver::new(
+            rx,
+            channel(1, OverflowPolicy::Reject).1,
+        ));
+        tx.try_send(smallvec::smallvec![Packet::Message("hellooo€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::BinaryV3(vec![1, 2, 3, 4])])
+        tx.try_send(smallvec::smallvec![Packet::BinaryV3(vec![1, 2, 3, 4])].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
-        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())])
+        tx.try_send(smallvec::smallvec![Packet::Message("hello€".into())].into())
             .unwrap();
         {
             let rx = mutex.lock().await;
@@ -1,18 +1,19 @@
 //! Payload encoder and decoder for polling transport.
 
 use crate::{
-    errors::Error, packet::Packet, peekable::PeekableReceiver, service::ProtocolVersion,
-    socket::PacketBuf,
+    config::Utf8Validation, errors::Error, packet::Packet, peekable::PeekableReceiver,
+    service::ProtocolVersion, socket::PacketBuf,
 };
 use futures::Stream;
 use http::Request;
-use tokio::sync::MutexGuard;
+use tokio::sync::{oneshot, MutexGuard};
 
+#[cfg(feature = "v3")]
 mod buf;
 mod decoder;
 mod encoder;
 
-const PACKET_SEPARATOR_V4: u8 = b'\x1e';
+const PACKET_SEPARATOR_V4: u8 = crate::packet::PAYLOAD_SEPARATOR;
 #[cfg(feature = "v3")]
 const STRING_PACKET_SEPARATOR_V3: u8 = b':';
 #[cfg(feature = "v3")]
@@ -26,6 +27,7 @@ pub fn decoder(
     body: Request<impl http_body::Body<Error = impl std::fmt::Debug> + Unpin>,
     #[allow(unused_variables)] protocol: ProtocolVersion,
     max_payload: u64,
+    utf8_validation: Utf8Validation,
 ) -> impl Stream<Item = Result<Packet, Error>> {
     #[cfg(feature = "v3")]
     {
@@ -33,13 +35,17 @@ pub fn decoder(
         use http::header::CONTENT_TYPE;
         #[cfg(feature = "tracing")]
         tracing::debug!("decoding payload {:?}", body.headers().get(CONTENT_TYPE));
-        let is_binary =
-            body.headers().get(CONTENT_TYPE) == Some(&"application/octet-stream".parse().unwrap());
+        let is_binary = body
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_some_and(is_octet_stream);
         match protocol {
-            ProtocolVersion::V4 => Either::Left(decoder::v4_decoder(body, max_payload)),
-            ProtocolVersion::V3 if is_binary => {
-                Either::Right(Either::Left(decoder::v3_binary_decoder(body, max_payload)))
+            ProtocolVersion::V4 => {
+                Either::Left(decoder::v4_decoder(body, max_payload, utf8_validation))
             }
+            ProtocolVersion::V3 if is_binary => Either::Right(Either::Left(
+                decoder::v3_binary_decoder(body, max_payload, utf8_validation),
+            )),
             ProtocolVersion::V3 => {
                 Either::Right(Either::Right(decoder::v3_string_decoder(body, max_payload)))
             }
@@ -48,22 +54,41 @@ pub fn decoder(
 
     #[cfg(not(feature = "v3"))]
     {
-        decoder::v4_decoder(body, max_payload)
+        decoder::v4_decoder(body, max_payload, utf8_validation)
     }
 }
 
+/// Returns true if the content type is `application/octet-stream`, regardless of its parameters and case.
+/// Engine.io v3 clients send binary payloads with this content type, and text payloads with `text/plain`.
+#[cfg(feature = "v3")]
+fn is_octet_stream(content_type: &http::HeaderValue) -> bool {
+    content_type
+        .to_str()
+        .ok()
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/octet-stream"))
+}
+
 /// A payload to transmit to the client through http polling
 pub struct Payload {
     pub data: Vec<u8>,
     pub has_binary: bool,
+    /// Notifiers of the packets included in this payload, to notify once the payload is flushed
+    pub flushed: Vec<oneshot::Sender<()>>,
 }
 impl Payload {
     pub fn new(data: impl Into<Vec<u8>>, has_binary: bool) -> Self {
         Self {
             data: data.into(),
             has_binary,
+            flushed: Vec::new(),
         }
     }
+
+    pub fn with_flushed(mut self, flushed: Vec<oneshot::Sender<()>>) -> Self {
+        self.flushed = flushed;
+        self
+    }
 }
 
 pub async fn encoder(
@@ -88,3 +113,165 @@ pub async fn encoder(
         encoder::v4_encoder(rx, max_payload).await
     }
 }
+
+/// Waits up to `delay` after the first packet emitted to a parked polling request,
+/// so that the packets emitted in the meantime are encoded in the same payload.
+///
+/// It returns right away if packets were already waiting, and as soon as a heartbeat or close packet
+/// is emitted or the payload is full. The received packets are kept in the [`PeekableReceiver`] buffer
+/// for the encoder.
+pub async fn coalesce(
+    rx: &mut MutexGuard<'_, PeekableReceiver<PacketBuf>>,
+    delay: std::time::Duration,
+    max_payload: u64,
+) {
+    let is_urgent = |p: &PacketBuf| {
+        p.iter()
+            .any(|p| matches!(p, Packet::Ping | Packet::Pong | Packet::Close))
+    };
+    let mut size = 0;
+    let mut full = |p: &PacketBuf| {
+        size += p.iter().map(|p| p.get_size_hint(true)).sum::<usize>();
+        size as u64 >= max_payload
+    };
+
+    if rx.peek().is_some() {
+        return;
+    }
+    match rx.buffer_next().await {
+        Some(p) if !is_urgent(p) && !full(p) => (),
+        _ => return,
+    }
+
+    let deadline = tokio::time::sleep(delay);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            biased;
+            p = rx.buffer_next() => match p {
+                Some(p) if !is_urgent(p) && !full(p) => (),
+                _ => return,
+            },
+            _ = &mut deadline => return,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "v3"))]
+mod tests {
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use http::HeaderValue;
+    use http_body_util::Full;
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::{channel::channel, config::OverflowPolicy, socket::PacketBuf};
+
+    const MAX_PAYLOAD: u64 = 100_000;
+
+    /// Binary payloads encoded by engine.io-parser v2 with `encodePayloadAsBinary`
+    fn binary_samples() -> Vec<Vec<u8>> {
+        let mut large_binary = vec![0, 1, 1, 255];
+        large_binary.extend_from_slice(b"40123456789");
+        large_binary.extend_from_slice(&[1, 3, 0, 1, 255, 4]);
+        large_binary.extend((0..300).map(|i| i as u8));
+        vec![
+            // [{ type: "message", data: "hello€" }, { type: "message", data: Buffer([1, 2, 3, 4]) }]
+            vec![
+                0, 9, 255, 52, 104, 101, 108, 108, 111, 226, 130, 172, 1, 5, 255, 4, 1, 2, 3, 4,
+            ],
+            // [{ type: "message", data: "héllo 😀" }, { type: "message", data: Buffer([5]) }]
+            [
+                &[0, 1, 2, 255][..],
+                "4héllo 😀".as_bytes(),
+                &[1, 2, 255, 4, 5],
+            ]
+            .concat(),
+            // [{ type: "message", data: "0123456789" }, { type: "message", data: Buffer of 300 bytes }]
+            large_binary,
+        ]
+    }
+
+    /// Decodes a payload and encodes the decoded packets again
+    async fn round_trip(payload: Vec<u8>, binary: bool) -> Vec<u8> {
+        let body = Full::new(Bytes::from(payload));
+        let packets: Vec<_> = if binary {
+            decoder::v3_binary_decoder(body, MAX_PAYLOAD, Utf8Validation::Strict)
+                .collect()
+                .await
+        } else {
+            decoder::v3_string_decoder(body, MAX_PAYLOAD)
+                .collect()
+                .await
+        };
+
+        let (tx, rx) = channel::<PacketBuf>(10, OverflowPolicy::Reject);
+        let rx = Mutex::new(PeekableReceiver::new(
+            rx,
+            channel(1, OverflowPolicy::Reject).1,
+        ));
+        for packet in packets {
+            tx.try_send(smallvec::smallvec![packet.unwrap()].into())
+                .unwrap();
+        }
+        let rx = rx.lock().await;
+        let payload = if binary {
+            encoder::v3_binary_encoder(rx, MAX_PAYLOAD).await
+        } else {
+            encoder::v3_string_encoder(rx, MAX_PAYLOAD).await
+        };
+        payload.unwrap().data
+    }
+
+    #[tokio::test]
+    async fn binary_payload_round_trip_v3() {
+        for sample in binary_samples() {
+            assert_eq!(round_trip(sample.clone(), true).await, sample);
+        }
+        // Like engine.io v3, a payload without binary packets is sent as a string payload
+        let sample = [&[0, 6, 255][..], b"2probe", &[0, 1, 1, 255], b"40123456789"].concat();
+        assert_eq!(round_trip(sample, true).await, b"6:2probe11:40123456789");
+    }
+
+    #[tokio::test]
+    async fn base64_payload_round_trip_v3() {
+        // Encoded by engine.io-parser v2 with `encodePayload` for a client without binary support:
+        // [{ type: "message", data: "hello" }, { type: "message", data: Buffer([1, 2, 3, 4]) }]
+        let sample = b"6:4hello10:b4AQIDBA==".to_vec();
+        assert_eq!(round_trip(sample.clone(), false).await, sample);
+    }
+
+    #[tokio::test]
+    async fn invalid_binary_payload_v3() {
+        let samples: [&[u8]; 4] = [
+            // Truncated packet
+            &[0, 9, 255, 52, 104, 101],
+            // Truncated length
+            &[0, 9],
+            // Invalid length digit
+            &[0, 10, 255, 52, 104, 101, 108, 108, 111, 111, 111, 111, 111],
+            // Empty length
+            &[0, 255, 52],
+        ];
+        for sample in samples {
+            let body = Full::new(Bytes::from(sample));
+            let payload = decoder::v3_binary_decoder(body, MAX_PAYLOAD, Utf8Validation::Strict);
+            futures::pin_mut!(payload);
+            assert!(matches!(
+                payload.next().await,
+                Some(Err(Error::InvalidPacketLength))
+            ));
+        }
+    }
+
+    #[test]
+    fn octet_stream_content_type() {
+        let is_binary = |v| is_octet_stream(&HeaderValue::from_static(v));
+        assert!(is_binary("application/octet-stream"));
+        assert!(is_binary("Application/Octet-Stream"));
+        assert!(is_binary("application/octet-stream; charset=binary"));
+        assert!(!is_binary("text/plain;charset=UTF-8"));
+        assert!(!is_binary("application/octet-stream-foo"));
+    }
+}
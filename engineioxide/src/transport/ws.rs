@@ -4,48 +4,121 @@
 //! Other functions are used internally to handle the websocket connection through tasks and channels
 //! and to handle upgrade from polling to ws
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use futures::{
     stream::{SplitSink, SplitStream},
-    SinkExt, StreamExt, TryStreamExt,
+    Sink, SinkExt, Stream, StreamExt, TryStreamExt,
+};
+use http::{
+    header, request::Parts, HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode,
 };
-use http::{request::Parts, HeaderValue, Request, Response, StatusCode};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     task::JoinHandle,
 };
 use tokio_tungstenite::{
-    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    tungstenite::{
+        error::ProtocolError,
+        handshake::derive_accept_key,
+        protocol::{frame::coding::CloseCode, CloseFrame, Role},
+        Error as WsError, Message,
+    },
     WebSocketStream,
 };
 
 use crate::{
     body::ResponseBody,
-    config::EngineIoConfig,
+    config::{spawn_on, EngineIoConfig, Handshake},
     engine::EngineIo,
     errors::Error,
     handler::EngineIoHandler,
     packet::{OpenPacket, Packet},
+    rate_limit::HandshakePermit,
+    recorder::Direction,
     service::ProtocolVersion,
     service::TransportType,
     sid::Sid,
+    socket::PacketBuf,
     DisconnectReason, Socket,
 };
 
+/// Headers of the upgrade response that can't be modified by the handshake hook
+const UPGRADE_RESERVED_HEADERS: [HeaderName; 4] = [
+    header::UPGRADE,
+    header::CONNECTION,
+    header::SEC_WEBSOCKET_ACCEPT,
+    header::SEC_WEBSOCKET_PROTOCOL,
+];
+
 /// Create a response for websocket upgrade
-fn ws_response<B>(ws_key: &HeaderValue) -> Result<Response<ResponseBody<B>>, http::Error> {
+fn ws_response<B>(
+    ws_key: &HeaderValue,
+    subprotocol: Option<HeaderValue>,
+) -> Result<Response<ResponseBody<B>>, http::Error> {
     let derived = derive_accept_key(ws_key.as_bytes());
     let sec = derived.parse::<HeaderValue>().unwrap();
-    Response::builder()
+    let mut res = Response::builder()
         .status(StatusCode::SWITCHING_PROTOCOLS)
         .header(http::header::UPGRADE, HeaderValue::from_static("websocket"))
         .header(
             http::header::CONNECTION,
             HeaderValue::from_static("Upgrade"),
         )
-        .header(http::header::SEC_WEBSOCKET_ACCEPT, sec)
-        .body(ResponseBody::empty_response())
+        .header(http::header::SEC_WEBSOCKET_ACCEPT, sec);
+    if let Some(subprotocol) = subprotocol {
+        res = res.header(http::header::SEC_WEBSOCKET_PROTOCOL, subprotocol);
+    }
+    res.body(ResponseBody::empty_response())
+}
+
+/// Select the first subprotocol offered by the client that is accepted by the server.
+///
+/// If the client offers subprotocols but none of them is accepted,
+/// the upgrade is rejected in strict mode and accepted without subprotocol otherwise.
+fn negotiate_subprotocol(
+    headers: &HeaderMap,
+    config: &EngineIoConfig,
+) -> Result<Option<HeaderValue>, Error> {
+    let mut offered = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+        .map(str::trim)
+        .filter(|subprotocol| !subprotocol.is_empty())
+        .peekable();
+    if offered.peek().is_none() {
+        return Ok(None);
+    }
+    match offered.find(|offered| config.ws_subprotocols.iter().any(|s| s == offered)) {
+        Some(subprotocol) => Ok(HeaderValue::from_str(subprotocol).ok()),
+        None if config.ws_subprotocol_strict => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("no accepted websocket subprotocol offered by the client");
+            Err(Error::HttpErrorResponse(StatusCode::BAD_REQUEST))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The session of a websocket connection
+#[derive(Debug, Clone, Copy)]
+enum WsSession {
+    /// A new session, its sid is reserved when the request is accepted so that it can be set in the cookie
+    New(Sid),
+    /// The upgrade of an existing polling session
+    #[cfg(feature = "polling")]
+    Upgrade(Sid),
+}
+
+impl WsSession {
+    fn sid(self) -> Sid {
+        match self {
+            WsSession::New(sid) => sid,
+            #[cfg(feature = "polling")]
+            WsSession::Upgrade(sid) => sid,
+        }
+    }
 }
 
 /// Upgrade a websocket request to create a websocket connection.
@@ -57,8 +130,25 @@ pub fn new_req<R: Send + 'static, B, H: EngineIoHandler>(
     protocol: ProtocolVersion,
     sid: Option<Sid>,
     req: Request<R>,
+    #[cfg(feature = "v3")] force_base64: bool,
 ) -> Result<Response<ResponseBody<B>>, Error> {
     let (parts, body) = req.into_parts();
+    // Upgrades of existing polling sessions are not new handshakes
+    let permit = match sid {
+        #[cfg(feature = "polling")]
+        Some(sid) => {
+            check_upgrade(&engine, sid, &parts)?;
+            None
+        }
+        // Without the polling transport there is no session to upgrade
+        #[cfg(not(feature = "polling"))]
+        Some(sid) => return Err(Error::UnknownSessionID(sid)),
+        None => {
+            engine.config.check_upgrade_origin(&parts, None)?;
+            engine.check_accepting()?;
+            engine.config.check_handshake_rate(&parts)?
+        }
+    };
     let req = Request::from_parts(parts.clone(), body);
 
     let ws_key = parts
@@ -67,12 +157,43 @@ pub fn new_req<R: Send + 'static, B, H: EngineIoHandler>(
         .ok_or(Error::HttpErrorResponse(StatusCode::BAD_REQUEST))?
         .clone();
 
-    tokio::spawn(async move {
+    let subprotocol = negotiate_subprotocol(&parts.headers, &engine.config)?;
+    let mut res = ws_response(&ws_key, subprotocol)?;
+    let session = match sid {
+        #[cfg(feature = "polling")]
+        Some(sid) => WsSession::Upgrade(sid),
+        _ => WsSession::New(Sid::new()),
+    };
+    // The cookie is refreshed on upgrades
+    engine.config.set_cookie(session.sid(), res.headers_mut());
+    let handshake = Handshake {
+        sid,
+        transport: TransportType::Websocket,
+        req: &parts,
+    };
+    engine
+        .config
+        .call_handshake_hook(&handshake, res.headers_mut(), &UPGRADE_RESERVED_HEADERS);
+
+    let runtime = engine.config.runtime.clone();
+    spawn_on(runtime.as_ref(), async move {
         let conn = hyper::upgrade::on(req)
             .await
             .map(hyper_util::rt::TokioIo::new);
         let res = match conn {
-            Ok(conn) => on_init(engine, conn, protocol, sid, parts).await,
+            Ok(conn) => {
+                on_init(
+                    engine,
+                    conn,
+                    protocol,
+                    session,
+                    permit,
+                    parts,
+                    #[cfg(feature = "v3")]
+                    force_base64,
+                )
+                .await
+            }
             Err(_e) => {
                 #[cfg(feature = "tracing")]
                 tracing::debug!("ws upgrade error: {}", _e);
@@ -92,130 +213,396 @@ pub fn new_req<R: Send + 'static, B, H: EngineIoHandler>(
         }
     });
 
-    Ok(ws_response(&ws_key)?)
+    Ok(res)
+}
+
+/// Checks that the polling session can be upgraded by this request before the upgrade is accepted,
+/// so that a rejected request doesn't disturb the session
+#[cfg(feature = "polling")]
+fn check_upgrade<H: EngineIoHandler>(
+    engine: &EngineIo<H>,
+    sid: Sid,
+    req: &Parts,
+) -> Result<(), Error> {
+    let socket = engine.get_socket(sid).ok_or(Error::UnknownSessionID(sid))?;
+    if !socket.can_upgrade() {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("[sid={sid}] upgrade rejected, the socket is already upgraded or upgrading");
+        return Err(Error::HttpErrorResponse(StatusCode::BAD_REQUEST));
+    }
+    engine
+        .config
+        .check_upgrade_origin(req, Some(&socket.req_parts))
+}
+
+/// A websocket connection: a stream of received messages and a sink of messages to send.
+pub(crate) trait WsConn:
+    Stream<Item = Result<Message, WsError>> + Sink<Message, Error = WsError> + Unpin + Send + 'static
+{
+}
+impl<W> WsConn for W where
+    W: Stream<Item = Result<Message, WsError>>
+        + Sink<Message, Error = WsError>
+        + Unpin
+        + Send
+        + 'static
+{
 }
 
 /// Handle a websocket connection upgrade
-///
-/// Sends an open packet if it is not an upgrade from a polling request
-///
-/// Read packets from the websocket and handle them, it will block until the connection is closed
 async fn on_init<H: EngineIoHandler, S>(
     engine: Arc<EngineIo<H>>,
     conn: S,
     protocol: ProtocolVersion,
-    sid: Option<Sid>,
+    session: WsSession,
+    permit: Option<HandshakePermit>,
     req_data: Parts,
+    #[cfg(feature = "v3")] force_base64: bool,
 ) -> Result<(), Error>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    let ws_init = move || WebSocketStream::from_raw_socket(conn, Role::Server, None);
-    let (socket, ws) = if let Some(sid) = sid {
-        match engine.get_socket(sid) {
+    let ws = WebSocketStream::from_raw_socket(conn, Role::Server, None).await;
+    on_ws(
+        engine,
+        ws,
+        protocol,
+        session,
+        permit,
+        req_data,
+        #[cfg(feature = "v3")]
+        force_base64,
+    )
+    .await
+}
+
+/// Handle an already upgraded websocket connection that was not upgraded by the engine,
+/// see [`EngineIoService::on_ws_connection`](crate::service::EngineIoService::on_ws_connection).
+///
+/// The connection is rejected with a close frame if the server doesn't accept new sessions.
+pub(crate) async fn on_external<H: EngineIoHandler, W: WsConn>(
+    engine: Arc<EngineIo<H>>,
+    mut ws: W,
+    protocol: ProtocolVersion,
+    req_data: Parts,
+) -> Result<(), Error> {
+    let accepted = match engine.check_accepting() {
+        Ok(()) => engine.config.check_handshake_rate(&req_data),
+        Err(e) => Err(e),
+    };
+    let permit = match accepted {
+        Ok(permit) => permit,
+        Err(e) => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("external websocket connection rejected: {e:?}");
+            let frame = CloseFrame {
+                code: CloseCode::Again,
+                reason: "server unavailable".into(),
+            };
+            ws.send(Message::Close(Some(frame))).await.ok();
+            return Err(e);
+        }
+    };
+    on_ws(
+        engine,
+        ws,
+        protocol,
+        WsSession::New(Sid::new()),
+        permit,
+        req_data,
+        #[cfg(feature = "v3")]
+        false,
+    )
+    .await
+}
+
+/// Drive the engine.io protocol over a websocket connection
+///
+/// Sends an open packet if it is not an upgrade from a polling request,
+/// the handshake `permit` is released once it is sent
+///
+/// Read packets from the websocket and handle them, it will block until the connection is closed
+async fn on_ws<H: EngineIoHandler, W: WsConn>(
+    engine: Arc<EngineIo<H>>,
+    mut ws: W,
+    protocol: ProtocolVersion,
+    session: WsSession,
+    permit: Option<HandshakePermit>,
+    req_data: Parts,
+    #[cfg(feature = "v3")] force_base64: bool,
+) -> Result<(), Error> {
+    let (socket, ws) = match session {
+        #[cfg(feature = "polling")]
+        WsSession::Upgrade(sid) => match engine.get_socket(sid) {
             None => return Err(Error::UnknownSessionID(sid)),
-            Some(socket) if socket.is_ws() => return Err(Error::Upgrade),
+            Some(socket) if socket.is_ws() => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    "[sid={sid}] duplicate upgrade rejected, the socket is already upgraded"
+                );
+                return Err(Error::DuplicateUpgrade);
+            }
             Some(socket) => {
-                let mut ws = ws_init().await;
-                upgrade_handshake::<H, S>(&socket, &mut ws).await?;
+                let upgrade_timeout = engine.config.upgrade_timeout;
+                let upgrading = engine.upgrading();
+                let res = upgrade_handshake::<H, W>(&socket, &mut ws, upgrade_timeout).await;
+                drop(upgrading);
+                if let Err(e) = res {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("[sid={sid}] upgrade failed, staying on polling: {e:?}");
+                    engine.report_error(sid, &e);
+                    if let Some(reason) = (&e).into() {
+                        engine.on_upgrade_failed(sid, reason);
+                    }
+                    return Err(e);
+                }
+                engine.on_upgrade(socket.clone());
+                if let Some(touch) = engine.touch_session(&socket) {
+                    touch.await;
+                }
                 (socket, ws)
             }
+        },
+        WsSession::New(sid) => {
+            let socket = engine.create_session(
+                sid,
+                protocol,
+                TransportType::Websocket,
+                req_data,
+                #[cfg(feature = "v3")]
+                force_base64,
+            );
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                parent: socket.span(),
+                "[sid={}] new websocket connection",
+                socket.id
+            );
+            if let Some(touch) = engine.touch_session(&socket) {
+                touch.await;
+            }
+            init_handshake(&socket, &mut ws, &engine.config).await?;
+            drop(permit);
+            socket
+                .clone()
+                .spawn_heartbeat(engine.config.ping_interval, engine.config.ping_timeout);
+            (socket, ws)
         }
-    } else {
-        let socket = engine.create_session(
-            protocol,
-            TransportType::Websocket,
-            req_data,
-            #[cfg(feature = "v3")]
-            false,
-        );
-        #[cfg(feature = "tracing")]
-        tracing::debug!("[sid={}] new websocket connection", socket.id);
-        let mut ws = ws_init().await;
-        init_handshake(socket.id, &mut ws, &engine.config).await?;
-        socket
-            .clone()
-            .spawn_heartbeat(engine.config.ping_interval, engine.config.ping_timeout);
-        (socket, ws)
     };
     let (tx, rx) = ws.split();
-    let rx_handle = forward_to_socket::<H, S>(socket.clone(), tx);
+    let mut rx_handle = Some(forward_to_socket::<H, W>(
+        socket.clone(),
+        tx,
+        &engine.config,
+    ));
 
-    if let Err(ref e) = forward_to_handler(&engine, rx, &socket).await {
-        #[cfg(feature = "tracing")]
-        tracing::debug!("[sid={}] error when handling packet: {:?}", socket.id, e);
-        if let Some(reason) = e.into() {
-            engine.close_session(socket.id, reason);
+    let forward = forward_to_handler(&engine, rx, &socket);
+    #[cfg(feature = "tracing")]
+    let forward = tracing::Instrument::instrument(forward, socket.span().clone());
+    tokio::pin!(forward);
+    // If the writer task downgraded the socket to polling, the websocket is dropped without closing the session
+    let res = tokio::select! {
+        res = &mut forward => res,
+        downgraded = rx_handle.as_mut().unwrap() => {
+            rx_handle = None;
+            if let Ok(true) = downgraded {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("[sid={}] websocket dropped, downgraded to polling", socket.id);
+                return Ok(());
+            }
+            forward.await
+        }
+    };
+    // Every end of the reader goes through the same cleanup: a clean close (close frame or close packet)
+    // is a `TransportClose`, an abrupt end of the connection (e.g. a reset) is a `TransportError`
+    let reason = match res {
+        Ok(()) => DisconnectReason::TransportClose,
+        Err(ref e) => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={}] error when handling packet: {:?}", socket.id, e);
+            engine.report_error(socket.id, e);
+            Option::from(e).unwrap_or(DisconnectReason::TransportClose)
+        }
+    };
+    engine.close_session(socket.id, reason);
+
+    // Give the writer task a brief window to flush the final packets and the close frame
+    // before it is forcibly dropped. If the close frame was already sent it is a no-op.
+    socket.send_close();
+    if let Some(mut rx_handle) = rx_handle {
+        if tokio::time::timeout(engine.config.close_grace, &mut rx_handle)
+            .await
+            .is_err()
+        {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={}] close grace period elapsed", socket.id);
+            rx_handle.abort();
         }
-    } else {
-        engine.close_session(socket.id, DisconnectReason::TransportClose);
     }
-    rx_handle.abort();
     Ok(())
 }
 
 /// Forwards all packets received from a websocket to a EngineIo [`Socket`]
-async fn forward_to_handler<H: EngineIoHandler, S>(
+///
+/// It returns once the client closed the connection with a close frame or a close packet.
+/// If the stream ends without any of them, the connection was abruptly closed and an error is returned.
+async fn forward_to_handler<H: EngineIoHandler, W: WsConn>(
     engine: &Arc<EngineIo<H>>,
-    mut rx: SplitStream<WebSocketStream<S>>,
+    mut rx: SplitStream<W>,
     socket: &Arc<Socket<H::Data>>,
-) -> Result<(), Error>
-where
-    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
-{
-    while let Some(msg) = rx.try_next().await? {
+) -> Result<(), Error> {
+    let mut dispatched = 0;
+    loop {
+        let Some(msg) = rx.try_next().await? else {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={}] websocket ended without close frame", socket.id);
+            return Err(Error::WsTransport(WsError::Protocol(
+                ProtocolError::ResetWithoutClosingHandshake,
+            )));
+        };
+        socket.touch_seen();
         match msg {
-            Message::Text(msg) => match Packet::try_from(msg)? {
-                Packet::Close => {
-                    #[cfg(feature = "tracing")]
-                    tracing::debug!("[sid={}] closing session", socket.id);
-                    engine.close_session(socket.id, DisconnectReason::TransportClose);
-                    break;
-                }
-                Packet::Pong | Packet::Ping => socket
-                    .heartbeat_tx
-                    .try_send(())
-                    .map_err(|_| Error::HeartbeatTimeout),
-                Packet::Message(msg) => {
-                    engine.handler.on_message(msg, socket.clone());
-                    Ok(())
+            Message::Text(msg) => {
+                match socket.record_inbound(Packet::decode(msg, socket.protocol)?) {
+                    Packet::Close => {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("[sid={}] closing session", socket.id);
+                        engine.close_session(socket.id, DisconnectReason::TransportClose);
+                        break;
+                    }
+                    p @ (Packet::Pong | Packet::Ping) => socket.recv_heartbeat(p),
+                    Packet::Message(msg) => {
+                        if engine.config.is_message_too_large(&msg) {
+                            #[cfg(feature = "tracing")]
+                            tracing::debug!(
+                                "[sid={}] message too large: {} bytes",
+                                socket.id,
+                                msg.len()
+                            );
+                            socket.set_ws_close_frame(CloseFrame {
+                                code: CloseCode::Policy,
+                                reason: "message too large".into(),
+                            });
+                            return Err(Error::PayloadTooLarge);
+                        }
+                        socket.touch_message();
+                        engine.handler.on_message(msg, socket.clone());
+                        Ok(())
+                    }
+                    // Base64 encoded binary packets are sent by clients that can't handle binary frames
+                    Packet::Binary(data) | Packet::BinaryV3(data) => {
+                        socket.touch_message();
+                        if let Some(data) = socket.recv_binary(data) {
+                            engine.handler.on_binary(data, socket.clone());
+                        }
+                        Ok(())
+                    }
+                    p => return Err(Error::BadPacket(p)),
                 }
-                p => return Err(Error::BadPacket(p)),
-            },
+            }
             Message::Binary(mut data) => {
                 if socket.protocol == ProtocolVersion::V3 && !data.is_empty() {
                     // The first byte is the message type, which we don't need.
                     let _ = data.remove(0);
                 }
-                engine.handler.on_binary(data, socket.clone());
+                if socket.is_recording() {
+                    socket.record(Direction::Inbound, &[Packet::Binary(data.clone())]);
+                }
+                socket.touch_message();
+                if let Some(data) = socket.recv_binary(data) {
+                    engine.handler.on_binary(data, socket.clone());
+                }
+                Ok(())
+            }
+            // Pong frames are received in response to the websocket ping frames sent by the server
+            Message::Pong(_) => {
+                socket.touch_transport();
                 Ok(())
             }
+            // Ping frames are automatically answered by the websocket implementation
+            Message::Ping(_) => Ok(()),
             Message::Close(_) => break,
-            _ => panic!("[sid={}] unexpected ws message", socket.id),
-        }?
+            Message::Frame(_) => panic!("[sid={}] unexpected ws message", socket.id),
+        }?;
+        dispatched += 1;
+        engine.config.yield_after(dispatched).await;
     }
     Ok(())
 }
 
 /// Forwards all packets waiting to be sent to the websocket
 ///
-/// The websocket stream is flushed only when the internal channel is drained
-fn forward_to_socket<H: EngineIoHandler, S>(
+/// The websocket stream is flushed only when the internal channel is drained.
+/// If a `write_coalesce_window` is set, the packets received within the window are also written
+/// before flushing, except if a heartbeat or close packet must be sent right away.
+///
+/// If a `ws_ping_interval` is set, websocket ping frames are also sent at this interval
+///
+/// Writes failing with a transient error are retried, the socket is closed with
+/// [`DisconnectReason::TransportError`] if they still fail or if the error is fatal.
+/// If [`EngineIoConfig::ws_polling_fallback`] is set, a socket upgraded from polling is downgraded instead,
+/// the task then returns true and leaves the pending packets in the internal channel for the next polling request,
+/// after the packets that were not flushed to the websocket.
+fn forward_to_socket<H: EngineIoHandler, W: WsConn>(
     socket: Arc<Socket<H::Data>>,
-    mut tx: SplitSink<WebSocketStream<S>, Message>,
-) -> JoinHandle<()>
-where
-    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
-{
+    mut tx: SplitSink<W, Message>,
+    config: &EngineIoConfig,
+) -> JoinHandle<bool> {
+    let ws_ping_interval = config.ws_ping_interval;
+    let coalesce_window = config.write_coalesce_window;
+    let retry = WriteRetry {
+        retries: config.ws_write_retries,
+        backoff: config.ws_write_retry_backoff,
+    };
+    #[cfg(feature = "polling")]
+    let polling_fallback = config.ws_polling_fallback;
+    // The written packets are kept until they are flushed if the socket can be downgraded
+    #[cfg(feature = "polling")]
+    let keep_unflushed = polling_fallback && socket.is_polling_capable();
+    #[cfg(not(feature = "polling"))]
+    let keep_unflushed = false;
     // Pipe between websocket and internal socket channel
-    tokio::spawn(async move {
+    socket.clone().spawn(async move {
         let mut internal_rx = socket.internal_rx.try_lock().unwrap();
+        let mut failed = false;
+        let mut downgraded = false;
+        // Copies of the packets written since the last flush, with their flush notifiers
+        let mut unflushed: Vec<PacketBuf> = Vec::new();
+
+        // Retries a failed write, and closes or downgrades the socket if it can't be recovered
+        macro_rules! handle_res {
+            ($res:expr) => {
+                if let Err(e) = $res {
+                    if let Err(_e) = retry.run(&mut tx, e).await {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("[sid={}] error sending packet: {}", socket.id, _e);
+                        #[cfg(feature = "polling")]
+                        let downgrade = polling_fallback && socket.downgrade_to_polling();
+                        #[cfg(not(feature = "polling"))]
+                        let downgrade = false;
+                        if downgrade {
+                            downgraded = true;
+                        } else {
+                            socket.close(DisconnectReason::TransportError);
+                        }
+                        failed = true;
+                    }
+                }
+            };
+        }
 
         // map a packet to a websocket message
         // It is declared as a macro rather than a closure to avoid ownership issues
         macro_rules! map_fn {
             ($item:ident) => {
                 let res = match $item {
+                    Packet::Binary(bin) | Packet::BinaryV3(bin) if socket.force_base64() => {
+                        let packet = match socket.protocol {
+                            ProtocolVersion::V3 => Packet::BinaryV3(bin),
+                            ProtocolVersion::V4 => Packet::Binary(bin),
+                        };
+                        tx.feed(Message::Text(packet.encode())).await
+                    }
                     Packet::Binary(mut bin) | Packet::BinaryV3(mut bin) => {
                         if socket.protocol == ProtocolVersion::V3 {
                             // v3 protocol requires packet type as the first byte
@@ -224,7 +611,7 @@ where
                         tx.feed(Message::Binary(bin)).await
                     }
                     Packet::Close => {
-                        tx.send(Message::Close(None)).await.ok();
+                        tx.send(Message::Close(socket.take_ws_close_frame())).await.ok();
                         internal_rx.close();
                         break;
                     },
@@ -233,43 +620,197 @@ where
                     // it should be discarded here
                     Packet::Noop => Ok(()),
                     _ => {
-                        let packet: String = $item.try_into().unwrap();
-                        tx.feed(Message::Text(packet)).await
+                        tx.feed(Message::Text($item.encode())).await
                     }
                 };
-                if let Err(_e) = res {
-                    #[cfg(feature = "tracing")]
-                    tracing::debug!("[sid={}] error sending packet: {}", socket.id, _e);
+                handle_res!(res);
+                if failed {
+                    break;
                 }
             };
         }
 
-        while let Some(items) = internal_rx.recv().await {
-            for item in items {
-                map_fn!(item);
+        let mut ping_interval = ws_ping_interval
+            .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+
+        loop {
+            let mut items = tokio::select! {
+                items = internal_rx.recv() => match items {
+                    Some(items) => items,
+                    None => break,
+                },
+                _ = next_tick(&mut ping_interval) => {
+                    handle_res!(tx.send(Message::Ping(Vec::new())).await);
+                    if failed {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            // Emitters waiting for their packets to be flushed
+            let mut flushed = Vec::new();
+            // If a packet that must be flushed right away was written
+            let mut urgent = false;
+
+            // Packets that were not sent before their deadline are dropped
+            macro_rules! write_items {
+                ($items:ident) => {
+                    if !$items.is_expired() {
+                        urgent |= $items
+                            .iter()
+                            .any(|p| matches!(p, Packet::Ping | Packet::Pong | Packet::Close));
+                        if keep_unflushed {
+                            unflushed.push($items.take_retry_copy());
+                        } else {
+                            flushed.extend($items.take_flush_notifier());
+                        }
+                        for item in $items {
+                            map_fn!(item);
+                        }
+                    }
+                };
             }
+
+            write_items!(items);
             // For every available packet we continue to send until the channel is drained
-            while let Ok(items) = internal_rx.try_recv() {
-                for item in items {
-                    map_fn!(item);
+            // It stops before receiving the next packets if a write failed, so that they are not lost
+            while !failed {
+                let Ok(mut items) = internal_rx.try_recv() else {
+                    break;
+                };
+                write_items!(items);
+            }
+
+            // The packets received within the coalescing window are written before flushing
+            if let Some(window) = coalesce_window {
+                let deadline = tokio::time::sleep(window);
+                tokio::pin!(deadline);
+                while !urgent && !failed {
+                    tokio::select! {
+                        biased;
+                        items = internal_rx.recv() => match items {
+                            Some(mut items) => {
+                                write_items!(items);
+                            }
+                            None => break,
+                        },
+                        _ = &mut deadline => break,
+                    }
                 }
             }
 
-            tx.flush().await.ok();
+            if failed {
+                break;
+            }
+            handle_res!(tx.flush().await);
+            if failed {
+                break;
+            }
+            let copies = unflushed
+                .drain(..)
+                .filter_map(|mut p| p.take_flush_notifier());
+            for notifier in flushed.into_iter().chain(copies) {
+                notifier.send(()).ok();
+            }
+        }
+
+        // The remaining packets are kept for the next polling request,
+        // after the packets that were not flushed, in their original order
+        if downgraded {
+            for packets in unflushed.into_iter().rev() {
+                internal_rx.push_front(packets);
+            }
+            return true;
         }
+
+        // Drop the remaining packets so that the emitters waiting for a flush are notified
+        internal_rx.close();
+        while internal_rx.try_recv().is_ok() {}
+        false
     })
 }
+
+/// The retry policy of the failed websocket writes
+#[derive(Debug, Clone, Copy)]
+struct WriteRetry {
+    retries: u32,
+    backoff: Duration,
+}
+
+impl WriteRetry {
+    /// Retries a failed write with an exponential backoff while its error is transient.
+    ///
+    /// A frame that failed to be written is kept in the write buffer of the websocket,
+    /// so it is retried by flushing it, except if the buffer was full and the frame was given back.
+    async fn run<T>(&self, tx: &mut T, mut err: WsError) -> Result<(), WsError>
+    where
+        T: Sink<Message, Error = WsError> + Unpin,
+    {
+        let mut backoff = self.backoff;
+        for _ in 0..self.retries {
+            if !is_retryable(&err) {
+                break;
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!("transient websocket write error, retrying in {backoff:?}: {err}");
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            let res = match err {
+                WsError::WriteBufferFull(msg) => tx.feed(msg).await,
+                _ => tx.flush().await,
+            };
+            match res {
+                Ok(()) => return Ok(()),
+                Err(e) => err = e,
+            }
+        }
+        Err(err)
+    }
+}
+
+/// Returns true if a websocket write error is transient and the write can be retried.
+/// The other errors mean that the connection is dead (reset, closed, ...)
+fn is_retryable(err: &WsError) -> bool {
+    use std::io::ErrorKind;
+    match err {
+        WsError::Io(e) => matches!(
+            e.kind(),
+            ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::TimedOut
+        ),
+        WsError::WriteBufferFull(_) => true,
+        _ => false,
+    }
+}
+
+/// Wait for the next tick of an optional interval, never resolves if there is no interval
+async fn next_tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
 /// Send a Engine.IO [`OpenPacket`] to initiate a websocket connection
-async fn init_handshake<S>(
-    sid: Sid,
-    ws: &mut WebSocketStream<S>,
+async fn init_handshake<D, W>(
+    socket: &Socket<D>,
+    ws: &mut W,
     config: &EngineIoConfig,
 ) -> Result<(), Error>
 where
-    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    D: Default + Send + Sync + 'static,
+    W: WsConn,
 {
-    let packet = Packet::Open(OpenPacket::new(TransportType::Websocket, sid, config));
-    ws.send(Message::Text(packet.try_into()?)).await?;
+    let packet = Packet::Open(OpenPacket::new(
+        TransportType::Websocket,
+        socket.id,
+        socket.protocol,
+        config,
+    ));
+    socket.record(Direction::Outbound, std::slice::from_ref(&packet));
+    ws.send(Message::Text(packet.encode())).await?;
     Ok(())
 }
 
@@ -296,58 +837,390 @@ where
 ///│                                                      │
 ///│            -----  WebSocket frames -----             │
 /// ```
+#[cfg(feature = "polling")]
 #[cfg_attr(feature = "tracing", tracing::instrument(skip(socket, ws), fields(sid = socket.id.to_string())))]
-async fn upgrade_handshake<H: EngineIoHandler, S>(
+async fn upgrade_handshake<H: EngineIoHandler, W: WsConn>(
     socket: &Arc<Socket<H::Data>>,
-    ws: &mut WebSocketStream<S>,
-) -> Result<(), Error>
-where
-    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
-{
+    ws: &mut W,
+    upgrade_timeout: Duration,
+) -> Result<(), Error> {
     #[cfg(feature = "tracing")]
     tracing::debug!("websocket connection upgrade");
 
+    // If the client never completes the probe exchange (e.g. a proxy breaking websocket upgrades)
+    // the websocket attempt is abandoned and the session stays on polling.
+    let res = match tokio::time::timeout(upgrade_timeout, probe_handshake(socket, ws)).await {
+        Ok(res) => res,
+        Err(_) => Err(Error::UpgradeTimeout),
+    };
+    match res {
+        // The upgrade in progress belongs to another connection
+        Err(Error::DuplicateUpgrade) => return res,
+        Err(_) => {
+            socket.end_upgrade();
+            return res;
+        }
+        Ok(()) => (),
+    }
+
+    // wait for any polling connection to finish by waiting for the socket to be unlocked
+    let _ = socket.internal_rx.lock().await;
+    // wait for any polling request to dispatch all its packets so that they are
+    // handled before the first packet received on the websocket
+    let _recv_lock = socket.recv_lock.lock().await;
+    let upgraded = socket.upgrade_to_websocket();
+    // The packets buffered during the upgrade are now flushed in order by the websocket transport
+    socket.end_upgrade();
+    if upgraded {
+        Ok(())
+    } else {
+        Err(Error::DuplicateUpgrade)
+    }
+}
+
+/// Exchange the probe packets and wait for the upgrade packet
+#[cfg(feature = "polling")]
+async fn probe_handshake<D, W>(socket: &Socket<D>, ws: &mut W) -> Result<(), Error>
+where
+    D: Default + Send + Sync + 'static,
+    W: WsConn,
+{
     // Fetch the next packet from the ws stream, it should be a PingUpgrade packet
     let msg = match ws.next().await {
         Some(Ok(Message::Text(d))) => d,
+        None | Some(Ok(Message::Close(_))) => Err(Error::UpgradeAborted)?,
+        Some(Err(e)) => Err(e)?,
         _ => Err(Error::Upgrade)?,
     };
-    match Packet::try_from(msg)? {
+    match Packet::decode(msg, socket.protocol)? {
         Packet::PingUpgrade => {
+            // From now on, the packets are kept in the buffer until the websocket is active
+            if !socket.start_upgrade() {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("duplicate upgrade rejected, the socket is already upgrading");
+                return Err(Error::DuplicateUpgrade);
+            }
             // Respond with a PongUpgrade packet
-            ws.send(Message::Text(Packet::PongUpgrade.try_into()?))
-                .await?;
+            ws.send(Message::Text(Packet::PongUpgrade.encode())).await?;
         }
         p => Err(Error::BadPacket(p))?,
     };
 
-    // send a NOOP packet to any pending polling request so it closes gracefully
-    socket.send(Packet::Noop)?;
+    // release any pending polling request with a NOOP packet so it closes gracefully
+    socket.release_poll();
 
     // Fetch the next packet from the ws stream, it should be an Upgrade packet
     let msg = match ws.next().await {
         Some(Ok(Message::Text(d))) => d,
-        Some(Ok(Message::Close(_))) => {
+        None | Some(Ok(Message::Close(_))) => {
             #[cfg(feature = "tracing")]
             tracing::debug!("ws stream closed before upgrade");
-            Err(Error::Upgrade)?
+            Err(Error::UpgradeAborted)?
         }
+        Some(Err(e)) => Err(e)?,
         _ => {
             #[cfg(feature = "tracing")]
             tracing::debug!("unexpected ws message before upgrade");
             Err(Error::Upgrade)?
         }
     };
-    match Packet::try_from(msg)? {
+    match Packet::decode(msg, socket.protocol)? {
         Packet::Upgrade => {
             #[cfg(feature = "tracing")]
             tracing::debug!("ws upgraded successful")
         }
         p => Err(Error::BadPacket(p))?,
     };
-
-    // wait for any polling connection to finish by waiting for the socket to be unlocked
-    let _ = socket.internal_rx.lock().await;
-    socket.upgrade_to_websocket();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use super::*;
+
+    /// A sink whose flushes fail with the given errors, in order
+    #[derive(Default)]
+    struct FailingSink {
+        failures: VecDeque<WsError>,
+        /// The index of the fed message whose write fails with a connection reset
+        feed_failure: Option<usize>,
+        fed: Vec<Message>,
+        flushes: usize,
+    }
+
+    impl Sink<Message> for FailingSink {
+        type Error = WsError;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            Poll::Ready(Ok(()))
+        }
+        fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), WsError> {
+            if self.feed_failure == Some(self.fed.len()) {
+                self.feed_failure = None;
+                return Err(io_err(io::ErrorKind::ConnectionReset));
+            }
+            self.fed.push(item);
+            Ok(())
+        }
+        fn poll_flush(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            self.flushes += 1;
+            Poll::Ready(self.failures.pop_front().map_or(Ok(()), Err))
+        }
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Nothing is ever received, so that the sink can be used as a websocket connection
+    impl Stream for FailingSink {
+        type Item = Result<Message, WsError>;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Pending
+        }
+    }
+
+    fn io_err(kind: io::ErrorKind) -> WsError {
+        WsError::Io(kind.into())
+    }
+
+    const RETRY: WriteRetry = WriteRetry {
+        retries: 3,
+        backoff: Duration::from_millis(10),
+    };
+
+    #[test]
+    fn subprotocol_negotiation() {
+        let mut config = EngineIoConfig::builder()
+            .ws_subprotocols(["v2.chat", "v1.chat"])
+            .build();
+        let offer = |values: &[&'static str]| {
+            let mut headers = HeaderMap::new();
+            for value in values {
+                headers.append(
+                    header::SEC_WEBSOCKET_PROTOCOL,
+                    HeaderValue::from_static(value),
+                );
+            }
+            headers
+        };
+
+        // The first accepted subprotocol offered by the client is selected
+        let res = negotiate_subprotocol(&offer(&["v3.chat, v1.chat", "v2.chat"]), &config);
+        assert_eq!(res.unwrap().unwrap(), "v1.chat");
+        assert!(negotiate_subprotocol(&offer(&[]), &config)
+            .unwrap()
+            .is_none());
+        assert!(negotiate_subprotocol(&offer(&["v3.chat"]), &config)
+            .unwrap()
+            .is_none());
+
+        config.ws_subprotocol_strict = true;
+        assert!(negotiate_subprotocol(&offer(&[]), &config)
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            negotiate_subprotocol(&offer(&["v3.chat"]), &config),
+            Err(Error::HttpErrorResponse(StatusCode::BAD_REQUEST))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn write_retry_transient_error() {
+        let mut sink = FailingSink {
+            failures: [io_err(io::ErrorKind::WouldBlock)].into(),
+            ..Default::default()
+        };
+        let start = tokio::time::Instant::now();
+        let res = RETRY
+            .run(&mut sink, io_err(io::ErrorKind::WouldBlock))
+            .await;
+        assert!(res.is_ok());
+        assert_eq!(sink.flushes, 2);
+        // 10ms then 20ms of backoff
+        assert_eq!(start.elapsed(), Duration::from_millis(30));
+
+        // The frame given back because the buffer was full is sent again
+        let mut sink = FailingSink::default();
+        let msg = Message::Text("4hello".into());
+        let res = RETRY
+            .run(&mut sink, WsError::WriteBufferFull(msg.clone()))
+            .await;
+        assert!(res.is_ok());
+        assert_eq!(sink.fed, [msg]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn write_retry_exhausted() {
+        let mut sink = FailingSink {
+            failures: (0..5).map(|_| io_err(io::ErrorKind::TimedOut)).collect(),
+            ..Default::default()
+        };
+        let res = RETRY.run(&mut sink, io_err(io::ErrorKind::TimedOut)).await;
+        assert!(matches!(res, Err(WsError::Io(e)) if e.kind() == io::ErrorKind::TimedOut));
+        assert_eq!(sink.flushes, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn write_retry_fatal_error() {
+        let mut sink = FailingSink {
+            failures: [io_err(io::ErrorKind::ConnectionReset)].into(),
+            ..Default::default()
+        };
+        // A fatal error is never retried, even after a transient one
+        let res = RETRY
+            .run(&mut sink, io_err(io::ErrorKind::WouldBlock))
+            .await;
+        assert!(matches!(res, Err(WsError::Io(e)) if e.kind() == io::ErrorKind::ConnectionReset));
+        assert_eq!(sink.flushes, 1);
+
+        let res = RETRY.run(&mut sink, WsError::ConnectionClosed).await;
+        assert!(matches!(res, Err(WsError::ConnectionClosed)));
+        assert_eq!(sink.flushes, 1);
+        assert!(!is_retryable(&io_err(io::ErrorKind::BrokenPipe)));
+    }
+
+    #[cfg(feature = "polling")]
+    #[tokio::test]
+    async fn write_failure_downgrades_to_polling() {
+        use std::sync::Mutex;
+
+        #[derive(Debug)]
+        struct MyHandler;
+        impl EngineIoHandler for MyHandler {
+            type Data = ();
+            fn on_connect(&self, _: Arc<Socket<()>>) {}
+            fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+            fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+            fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+        }
+
+        // (opening transport, fallback enabled, downgraded)
+        let cases = [
+            (TransportType::Polling, true, true),
+            (TransportType::Polling, false, false),
+            (TransportType::Websocket, true, false),
+        ];
+        for (transport, fallback, downgraded) in cases {
+            let config = EngineIoConfig::builder()
+                .ws_polling_fallback(fallback)
+                .build();
+            let closed = Arc::new(Mutex::new(None));
+            let closed_ = closed.clone();
+            let socket = Arc::new(Socket::<()>::new(
+                Sid::new(),
+                ProtocolVersion::V4,
+                transport,
+                &config,
+                http::Request::<()>::default().into_parts().0,
+                Box::new(move |_, reason| *closed_.lock().unwrap() = Some(reason)),
+                #[cfg(feature = "v3")]
+                false,
+            ));
+            socket.upgrade_to_websocket();
+            socket.emit("first".into()).unwrap();
+            // Packets sent atomically, e.g. a socket.io packet with its binary attachments
+            socket
+                .reserve()
+                .unwrap()
+                .emit_many("event".into(), vec![vec![1], vec![2]]);
+
+            let conn = FailingSink {
+                failures: [io_err(io::ErrorKind::ConnectionReset)].into(),
+                ..Default::default()
+            };
+            let (tx, _rx) = conn.split();
+            let res = forward_to_socket::<MyHandler, _>(socket.clone(), tx, &config).await;
+            assert_eq!(res.unwrap(), downgraded);
+            assert_eq!(socket.is_http(), downgraded);
+            let reason = closed.lock().unwrap().take();
+            assert_eq!(
+                reason,
+                (!downgraded).then_some(DisconnectReason::TransportError)
+            );
+
+            // The packets that were not flushed are sent again to the next polling request, whole
+            // and in order, followed by the packets emitted after the failure
+            let res = socket.emit("kept".into());
+            assert_eq!(res.is_ok(), downgraded);
+            if downgraded {
+                let mut rx = socket.internal_rx.try_lock().unwrap();
+                let mut next = || rx.try_recv().unwrap().into_iter().collect::<Vec<_>>();
+                assert_eq!(next(), [Packet::Message("first".into())]);
+                assert_eq!(
+                    next(),
+                    [
+                        Packet::Message("event".into()),
+                        Packet::Binary(vec![1]),
+                        Packet::Binary(vec![2]),
+                    ]
+                );
+                assert_eq!(next(), [Packet::Message("kept".into())]);
+                assert!(rx.try_recv().is_err());
+            }
+        }
+    }
+
+    #[cfg(feature = "polling")]
+    #[tokio::test]
+    async fn feed_failure_keeps_queued_packets() {
+        #[derive(Debug)]
+        struct MyHandler;
+        impl EngineIoHandler for MyHandler {
+            type Data = ();
+            fn on_connect(&self, _: Arc<Socket<()>>) {}
+            fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+            fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+            fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+        }
+
+        let config = EngineIoConfig::builder().ws_polling_fallback(true).build();
+        let socket = Arc::new(Socket::<()>::new(
+            Sid::new(),
+            ProtocolVersion::V4,
+            TransportType::Polling,
+            &config,
+            http::Request::<()>::default().into_parts().0,
+            Box::new(|_, _| ()),
+            #[cfg(feature = "v3")]
+            false,
+        ));
+        socket.upgrade_to_websocket();
+        for msg in ["first", "second", "third", "fourth"] {
+            socket.emit(msg.into()).unwrap();
+        }
+
+        // The write of the second packet fails, it is reported when the third one is fed
+        // because the split sink buffers a message, while the fourth one is still queued
+        let conn = FailingSink {
+            feed_failure: Some(1),
+            ..Default::default()
+        };
+        let (tx, _rx) = conn.split();
+        let res = forward_to_socket::<MyHandler, _>(socket.clone(), tx, &config).await;
+        assert!(res.unwrap());
+        assert!(socket.is_http());
+
+        // Every queued packet is sent to the next polling request, in order
+        let mut rx = socket.internal_rx.try_lock().unwrap();
+        let packets: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok())
+            .flatten()
+            .collect();
+        assert_eq!(
+            packets,
+            [
+                Packet::Message("first".into()),
+                Packet::Message("second".into()),
+                Packet::Message("third".into()),
+                Packet::Message("fourth".into()),
+            ]
+        );
+    }
+}
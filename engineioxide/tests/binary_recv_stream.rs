@@ -0,0 +1,125 @@
+//! Tests for the stream of the binary packets received from a socket
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use engineioxide::{
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+    stream::StreamError,
+};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_server, create_ws_connection};
+
+/// Starts reading the binary stream on a `stream` message, the stream items and the
+/// binary packets passed to the handler are reported to the test
+#[derive(Debug, Clone)]
+struct MyHandler {
+    items: mpsc::UnboundedSender<Option<Result<Bytes, StreamError>>>,
+    on_binary: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        let items = self.items.clone();
+        let stream = socket.binary_stream();
+        socket.emit("ready".into()).unwrap();
+        tokio::spawn(async move {
+            tokio::pin!(stream);
+            // Only the first packets are read when asked, the stream is then dropped
+            let count = msg.parse::<usize>().unwrap_or(usize::MAX);
+            for _ in 0..count {
+                let item = stream.next().await;
+                let end = item.is_none();
+                items.send(item).unwrap();
+                if end {
+                    break;
+                }
+            }
+        });
+    }
+    fn on_binary(&self, data: Vec<u8>, _socket: Arc<Socket<()>>) {
+        self.on_binary.send(data).unwrap();
+    }
+}
+
+async fn recv<T>(rx: &mut mpsc::UnboundedReceiver<T>) -> T {
+    tokio::time::timeout(Duration::from_millis(200), rx.recv())
+        .await
+        .expect("timeout")
+        .unwrap()
+}
+
+#[tokio::test]
+pub async fn binary_recv_stream() {
+    const PORT: u16 = 4029;
+    let (items_tx, mut items) = mpsc::unbounded_channel();
+    let (on_binary_tx, mut on_binary) = mpsc::unbounded_channel();
+    let handler = MyHandler {
+        items: items_tx,
+        on_binary: on_binary_tx,
+    };
+    create_server(handler, PORT).await;
+
+    // Without stream, the packets are passed to the handler
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Binary(vec![0])).await.unwrap();
+    assert_eq!(recv(&mut on_binary).await, vec![0]);
+
+    // The stream yields the packets in order, until it is dropped
+    ws.send(Message::Text("42".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4ready".into())
+    );
+    for i in 1..=2u8 {
+        ws.send(Message::Binary(vec![i; 1000])).await.unwrap();
+    }
+    assert_eq!(recv(&mut items).await, Some(Ok(Bytes::from(vec![1; 1000]))));
+    assert_eq!(recv(&mut items).await, Some(Ok(Bytes::from(vec![2; 1000]))));
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    ws.send(Message::Binary(vec![3; 1000])).await.unwrap();
+    assert_eq!(recv(&mut on_binary).await, vec![3; 1000]);
+
+    // The stream ends when the client closes the socket
+    ws.send(Message::Text("4".into())).await.unwrap();
+    ws.next().await.unwrap().unwrap(); // ready
+    ws.send(Message::Binary(vec![4])).await.unwrap();
+    assert_eq!(recv(&mut items).await, Some(Ok(Bytes::from(vec![4]))));
+    ws.send(Message::Text("1".into())).await.unwrap();
+    assert_eq!(recv(&mut items).await, None);
+    assert!(on_binary.try_recv().is_err());
+}
+
+#[tokio::test]
+pub async fn binary_recv_stream_transport_error() {
+    const PORT: u16 = 4030;
+    let (items_tx, mut items) = mpsc::unbounded_channel();
+    let (on_binary_tx, _on_binary) = mpsc::unbounded_channel();
+    let handler = MyHandler {
+        items: items_tx,
+        on_binary: on_binary_tx,
+    };
+    create_server(handler, PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Text("4".into())).await.unwrap();
+    ws.next().await.unwrap().unwrap(); // ready
+    ws.send(Message::Binary(vec![1])).await.unwrap();
+    assert_eq!(recv(&mut items).await, Some(Ok(Bytes::from(vec![1]))));
+
+    // The connection is dropped without close packet
+    drop(ws);
+    assert_eq!(recv(&mut items).await, Some(Err(StreamError::Closed)));
+    assert_eq!(recv(&mut items).await, None);
+}
@@ -0,0 +1,147 @@
+//! Tests for the chunked binary streams
+#![cfg(feature = "polling")]
+
+use std::{sync::Arc, time::Duration};
+
+use base64::{engine::general_purpose, Engine};
+use bytes::Bytes;
+use engineioxide::{
+    config::{EngineIoConfig, OverflowPolicy},
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+    stream::{Received, StreamAssembler, MAGIC},
+};
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{
+    create_polling_connection, create_server_with_config, create_ws_connection, send_req,
+};
+
+/// Sends a stream of `n` chunks when a message `n` is received,
+/// and sends back the concatenation of the streams it receives
+#[derive(Debug, Clone)]
+struct StreamHandler;
+
+impl EngineIoHandler for StreamHandler {
+    type Data = StreamAssembler;
+
+    fn on_connect(&self, _: Arc<Socket<Self::Data>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<Self::Data>>, _: DisconnectReason) {}
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<Self::Data>>) {
+        let n: u8 = msg.parse().unwrap();
+        tokio::spawn(async move {
+            let chunks = futures::stream::iter((0..n).map(|i| Bytes::from(vec![i; 3])));
+            socket.send_stream(chunks).await.unwrap();
+        });
+    }
+
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<Self::Data>>) {
+        if let Received::NewStream(stream) = socket.data.push(data) {
+            tokio::spawn(async move {
+                let chunks: Vec<_> = stream.map(|chunk| chunk.unwrap()).collect().await;
+                socket.emit_binary(chunks.concat()).unwrap();
+            });
+        }
+    }
+}
+
+fn frame(kind: u8, stream_id: u32, seq: u32, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![MAGIC, kind];
+    frame.extend_from_slice(&stream_id.to_be_bytes());
+    frame.extend_from_slice(&seq.to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+#[tokio::test]
+pub async fn ws_binary_stream() {
+    const PORT: u16 = 3900;
+    create_server_with_config(StreamHandler, EngineIoConfig::default(), PORT).await;
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // open packet
+
+    ws.send(Message::Text("43".into())).await.unwrap();
+    let assembler = StreamAssembler::new();
+    let mut stream = None;
+    for seq in 0..4u32 {
+        let msg = tokio::time::timeout(Duration::from_millis(200), ws.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let Message::Binary(data) = msg else {
+            panic!("expected a binary frame, got {msg:?}");
+        };
+        assert_eq!(data[..2], [MAGIC, (seq == 3) as u8]);
+        if let Received::NewStream(s) = assembler.push(data) {
+            stream = Some(s);
+        }
+    }
+    let chunks: Vec<_> = stream.unwrap().collect().await;
+    assert_eq!(
+        chunks,
+        [
+            Ok(vec![0; 3].into()),
+            Ok(vec![1; 3].into()),
+            Ok(vec![2; 3].into())
+        ]
+    );
+
+    // A stream sent by the client is reassembled by the server
+    for frame in [
+        frame(0, 1, 0, b"hello "),
+        frame(0, 1, 1, b"world"),
+        frame(1, 1, 2, b""),
+    ] {
+        ws.send(Message::Binary(frame)).await.unwrap();
+    }
+    let msg = tokio::time::timeout(Duration::from_millis(200), ws.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(msg, Message::Binary(b"hello world".to_vec()));
+}
+
+#[tokio::test]
+pub async fn polling_binary_stream_backpressure() {
+    const PORT: u16 = 3901;
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .max_buffer_size(2)
+        .overflow_policy(OverflowPolicy::DropNewest)
+        .build();
+    create_server_with_config(StreamHandler, config, PORT).await;
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+    send_req(PORT, params(), http::Method::POST, Some("410".into())).await;
+
+    // The stream waits for the buffer to be drained by the polling requests, no chunk is dropped
+    let assembler = StreamAssembler::new();
+    let mut stream = None;
+    let mut packets = 0;
+    while packets < 11 {
+        // The first char of the payload is stripped by `send_req`
+        let payload = format!(
+            "b{}",
+            send_req(PORT, params(), http::Method::GET, None).await
+        );
+        for packet in payload.split('\x1e') {
+            let data = general_purpose::STANDARD
+                .decode(packet.strip_prefix('b').unwrap())
+                .unwrap();
+            if let Received::NewStream(s) = assembler.push(data) {
+                stream = Some(s);
+            }
+            packets += 1;
+        }
+    }
+    let chunks: Vec<_> = stream.unwrap().collect().await;
+    let expected: Vec<_> = (0..10).map(|i| Ok(Bytes::from(vec![i; 3]))).collect();
+    assert_eq!(chunks, expected);
+}
@@ -0,0 +1,74 @@
+//! Tests for the emission of packets to a client that doesn't read them
+#![cfg(feature = "polling")]
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, send_req};
+
+const BUFFER_SIZE: usize = 4;
+
+/// Fills the buffer of the socket when `fill` is received and reports the result of each emit
+#[derive(Debug, Clone)]
+struct MyHandler {
+    tx: mpsc::UnboundedSender<Result<(), TrySendError<String>>>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, socket: Arc<Socket<()>>) {
+        for i in 0..=BUFFER_SIZE {
+            self.tx.send(socket.emit(i.to_string())).unwrap();
+        }
+        socket.close(DisconnectReason::ClosingServer);
+        self.tx.send(socket.emit("closed".into())).unwrap();
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+#[tokio::test]
+pub async fn emit_to_full_buffer() {
+    const PORT: u16 = 4032;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let config = EngineIoConfig::builder()
+        .max_buffer_size(BUFFER_SIZE)
+        .build();
+    create_server_with_config(MyHandler { tx }, config, PORT).await;
+    let sid = create_polling_connection(PORT).await;
+
+    // No polling request is made, so the buffered packets are never read
+    send_req(
+        PORT,
+        format!("transport=polling&sid={sid}"),
+        http::Method::POST,
+        Some("4fill".into()),
+    )
+    .await;
+
+    let mut results = Vec::new();
+    for _ in 0..BUFFER_SIZE + 2 {
+        let res = tokio::time::timeout(Duration::from_millis(200), rx.recv())
+            .await
+            .expect("timeout waiting for the emit result");
+        results.push(res.unwrap());
+    }
+    assert!(results[..BUFFER_SIZE].iter().all(Result::is_ok));
+    assert_eq!(
+        results[BUFFER_SIZE],
+        Err(TrySendError::Full(BUFFER_SIZE.to_string()))
+    );
+    assert_eq!(
+        results[BUFFER_SIZE + 1],
+        Err(TrySendError::Closed("closed".into()))
+    );
+}
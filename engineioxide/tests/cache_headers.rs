@@ -0,0 +1,104 @@
+//! Tests for the caching headers of the polling responses
+#![cfg(feature = "polling")]
+
+use std::sync::Arc;
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use http::{header, HeaderMap, Method, StatusCode};
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, send_req_headers};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        println!("socket connect {}", socket.id);
+    }
+    fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) {
+        println!("socket disconnect {}: {:?}", socket.id, reason);
+    }
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+fn assert_no_cache(headers: &HeaderMap) {
+    assert_eq!(headers[header::CACHE_CONTROL], "no-store, no-transform");
+    assert_eq!(headers[header::PRAGMA], "no-cache");
+    assert_eq!(headers[header::EXPIRES], "0");
+}
+
+#[tokio::test]
+pub async fn polling_responses_are_not_cached() {
+    const PORT: u16 = 3500;
+    create_server_with_config(MyHandler, EngineIoConfig::default(), PORT).await;
+
+    // Handshake, with the `t` cache-busting param
+    let (status, headers) = send_req_headers(
+        PORT,
+        "transport=polling&t=OqLw1Bz".into(),
+        Method::GET,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_no_cache(&headers);
+    assert!(headers.get("x-accel-buffering").is_none());
+
+    let sid = create_polling_connection(PORT).await;
+    let params = format!("transport=polling&sid={sid}&t=OqLw1C0");
+
+    let (status, headers) =
+        send_req_headers(PORT, params.clone(), Method::POST, Some("4hello".into())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_no_cache(&headers);
+
+    let (status, headers) = send_req_headers(PORT, params.clone(), Method::GET, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_no_cache(&headers);
+
+    let (status, headers) = send_req_headers(PORT, params, Method::OPTIONS, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_no_cache(&headers);
+
+    // Error responses
+    let (status, headers) = send_req_headers(
+        PORT,
+        "transport=polling&sid=AAAAAAAAAAAAAAAA".into(),
+        Method::GET,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_no_cache(&headers);
+
+    let (status, headers) =
+        send_req_headers(PORT, "transport=polling".into(), Method::OPTIONS, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_no_cache(&headers);
+}
+
+#[tokio::test]
+pub async fn proxy_buffering_disabled() {
+    const PORT: u16 = 3501;
+    let config = EngineIoConfig::builder().proxy_buffering(false).build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let (_, headers) = send_req_headers(PORT, "transport=polling".into(), Method::GET, None).await;
+    assert_no_cache(&headers);
+    assert_eq!(headers["x-accel-buffering"], "no");
+}
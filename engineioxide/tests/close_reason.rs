@@ -0,0 +1,66 @@
+//! Tests for the reason text of the websocket close frame set with `Socket::close_with_reason`
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message,
+};
+
+mod fixture;
+
+use fixture::{create_server, create_ws_connection};
+
+/// Closes the socket with the received message as the reason
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.close_with_reason(DisconnectReason::ClosingServer, &msg);
+    }
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+async fn close_with(port: u16, reason: &str) -> CloseFrame<'static> {
+    let mut ws = create_ws_connection(port).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Text(format!("4{reason}"))).await.unwrap();
+    tokio::time::timeout(Duration::from_millis(200), async {
+        loop {
+            match ws.next().await.unwrap().unwrap() {
+                Message::Close(frame) => break frame.unwrap(),
+                _ => continue,
+            }
+        }
+    })
+    .await
+    .expect("timeout waiting for the close frame")
+}
+
+#[tokio::test]
+pub async fn close_reason_text() {
+    const PORT: u16 = 4022;
+    create_server(MyHandler, PORT).await;
+
+    let frame = close_with(PORT, "server restarting").await;
+    assert_eq!(frame.code, CloseCode::Away);
+    assert_eq!(frame.reason, "server restarting");
+
+    // An overlong reason is truncated to 123 bytes, without splitting the 2 bytes chars
+    let frame = close_with(PORT, &"é".repeat(100)).await;
+    assert_eq!(frame.code, CloseCode::Away);
+    assert_eq!(frame.reason.len(), 122);
+    assert_eq!(frame.reason, "é".repeat(61));
+
+    let frame = close_with(PORT, &"a".repeat(200)).await;
+    assert_eq!(frame.reason, "a".repeat(123));
+}
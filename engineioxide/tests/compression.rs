@@ -0,0 +1,152 @@
+//! Tests for the compression of the polling transport
+#![cfg(feature = "compression")]
+
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use http::{header::CONTENT_ENCODING, Method, StatusCode};
+use tokio::sync::mpsc;
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, send_raw_req};
+
+const BIG_MSG_LEN: usize = 2000;
+
+/// Emits a big message on "big", a small one on "small" and forwards the other messages
+#[derive(Debug, Clone)]
+struct MyHandler(mpsc::Sender<String>);
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        match msg.as_str() {
+            "big" => socket.emit("a".repeat(BIG_MSG_LEN)).unwrap(),
+            "small" => socket.emit("a".into()).unwrap(),
+            _ => self.0.try_send(msg).unwrap(),
+        }
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+async fn create_server(port: u16) -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel(10);
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .max_payload(10_000)
+        .compression_threshold(1024)
+        .build();
+    create_server_with_config(MyHandler(tx), config, port).await;
+    rx
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Sends a message, then polls the response with the given `Accept-Encoding` header
+async fn poll_after(port: u16, sid: &str, msg: &str, accept: &str) -> (http::HeaderMap, Vec<u8>) {
+    let params = || format!("transport=polling&sid={sid}");
+    let body = format!("4{msg}").into_bytes();
+    send_raw_req(port, params(), Method::POST, &[], body).await;
+    let (status, headers, body) = send_raw_req(
+        port,
+        params(),
+        Method::GET,
+        &[("Accept-Encoding", accept)],
+        vec![],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    (headers, body)
+}
+
+#[tokio::test]
+pub async fn compress_big_responses() {
+    const PORT: u16 = 3700;
+    let _rx = create_server(PORT).await;
+    let sid = create_polling_connection(PORT).await;
+    let expected = format!("4{}", "a".repeat(BIG_MSG_LEN));
+
+    let (headers, body) = poll_after(PORT, &sid, "big", "gzip, deflate").await;
+    assert_eq!(headers.get(CONTENT_ENCODING).unwrap(), "gzip");
+    assert!(body.len() < BIG_MSG_LEN);
+    let mut decompressed = String::new();
+    flate2::read::GzDecoder::new(&body[..])
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, expected);
+
+    let (headers, body) = poll_after(PORT, &sid, "big", "gzip, br").await;
+    assert_eq!(headers.get(CONTENT_ENCODING).unwrap(), "br");
+    let mut decompressed = String::new();
+    brotli::Decompressor::new(&body[..], 4096)
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, expected);
+}
+
+#[tokio::test]
+pub async fn no_compression_under_threshold_or_without_accept_encoding() {
+    const PORT: u16 = 3701;
+    let _rx = create_server(PORT).await;
+    let sid = create_polling_connection(PORT).await;
+
+    let (headers, body) = poll_after(PORT, &sid, "small", "gzip, br").await;
+    assert!(headers.get(CONTENT_ENCODING).is_none());
+    assert_eq!(body, b"4a");
+
+    let (headers, body) = poll_after(PORT, &sid, "big", "identity").await;
+    assert!(headers.get(CONTENT_ENCODING).is_none());
+    assert_eq!(body.len(), BIG_MSG_LEN + 1);
+}
+
+#[tokio::test]
+pub async fn decompress_requests() {
+    const PORT: u16 = 3702;
+    let mut rx = create_server(PORT).await;
+    let sid = create_polling_connection(PORT).await;
+    let params = format!("transport=polling&sid={sid}");
+
+    let body = gzip("4hello\x1e4world".as_bytes());
+    let headers = [("Content-Encoding", "gzip")];
+    let (status, _, _) = send_raw_req(PORT, params, Method::POST, &headers, body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(rx.recv().await.unwrap(), "hello");
+    assert_eq!(rx.recv().await.unwrap(), "world");
+}
+
+#[tokio::test]
+pub async fn decompression_bomb() {
+    const PORT: u16 = 3703;
+    let mut rx = create_server(PORT).await;
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+
+    // A small compressed body over the max payload once decompressed
+    let body = gzip(format!("4{}", "a".repeat(1_000_000)).as_bytes());
+    assert!(body.len() < 10_000);
+    let headers = [("Content-Encoding", "gzip")];
+    let (status, _, _) = send_raw_req(PORT, params(), Method::POST, &headers, body).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let headers = [("Content-Encoding", "zstd")];
+    let body = b"4hello".to_vec();
+    let (status, _, _) = send_raw_req(PORT, params(), Method::POST, &headers, body).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(rx.try_recv().is_err());
+}
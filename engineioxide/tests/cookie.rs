@@ -0,0 +1,153 @@
+//! Tests for the cookie set on the handshake responses
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::{EngineIoConfig, HandshakeCookie},
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::StreamExt;
+use http::{header::SET_COOKIE, HeaderMap};
+
+mod fixture;
+
+use fixture::create_server_with_config;
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+async fn create_server(port: u16, cookie: Option<HandshakeCookie>) {
+    let mut config = EngineIoConfig::builder().ping_interval(Duration::from_secs(10));
+    if let Some(cookie) = cookie {
+        config = config.cookie(cookie);
+    }
+    create_server_with_config(MyHandler, config.build(), port).await;
+}
+
+fn cookies(headers: &HeaderMap) -> Vec<&str> {
+    let cookies = headers.get_all(SET_COOKIE).iter();
+    cookies.map(|v| v.to_str().unwrap()).collect()
+}
+
+/// Opens a polling session and returns its sid with the headers of the handshake response
+#[cfg(feature = "polling")]
+async fn polling_handshake(port: u16) -> (String, HeaderMap) {
+    let params = "transport=polling".to_string();
+    let (_, headers, body) =
+        fixture::send_raw_req(port, params, http::Method::GET, &[], vec![]).await;
+    let open: serde_json::Value = serde_json::from_slice(&body[1..]).unwrap();
+    (open["sid"].as_str().unwrap().to_string(), headers)
+}
+
+/// Connects a websocket and returns the headers of the upgrade response
+#[cfg(feature = "polling")]
+async fn ws_handshake(port: u16, sid: Option<&str>) -> HeaderMap {
+    let sid = sid.map(|sid| format!("&sid={sid}")).unwrap_or_default();
+    let (_ws, res) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{port}/engine.io/?EIO=4&transport=websocket{sid}"
+    ))
+    .await
+    .unwrap();
+    res.headers().clone()
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn polling_cookie_flags() {
+    use engineioxide::config::SameSite;
+    let combinations = [
+        (
+            HandshakeCookie::default(),
+            "; Path=/; HttpOnly; SameSite=Lax",
+        ),
+        (
+            HandshakeCookie {
+                name: "lb".into(),
+                path: Some("/engine.io".into()),
+                same_site: Some(SameSite::Strict),
+                ..Default::default()
+            },
+            "; Path=/engine.io; HttpOnly; SameSite=Strict",
+        ),
+        (
+            HandshakeCookie {
+                same_site: Some(SameSite::None),
+                secure: true,
+                ..Default::default()
+            },
+            "; Path=/; HttpOnly; SameSite=None; Secure",
+        ),
+        (
+            HandshakeCookie {
+                path: None,
+                same_site: None,
+                secure: false,
+                http_only: false,
+                ..Default::default()
+            },
+            "",
+        ),
+    ];
+    for (i, (cookie, attributes)) in combinations.into_iter().enumerate() {
+        let port = 3920 + i as u16;
+        let name = cookie.name.clone();
+        create_server(port, Some(cookie)).await;
+
+        let (sid, headers) = polling_handshake(port).await;
+        assert_eq!(cookies(&headers), [format!("{name}={sid}{attributes}")]);
+    }
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn cookie_disabled_by_default() {
+    const PORT: u16 = 3924;
+    create_server(PORT, None).await;
+
+    let (_, headers) = polling_handshake(PORT).await;
+    assert!(cookies(&headers).is_empty());
+    let headers = ws_handshake(PORT, None).await;
+    assert!(cookies(&headers).is_empty());
+}
+
+#[tokio::test]
+pub async fn websocket_cookie() {
+    const PORT: u16 = 3925;
+    create_server(PORT, Some(HandshakeCookie::default())).await;
+
+    // The cookie contains the sid of the session created once the connection is upgraded
+    let (mut ws, res) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket"
+    ))
+    .await
+    .unwrap();
+    let open = ws.next().await.unwrap().unwrap().into_text().unwrap();
+    let open: serde_json::Value = serde_json::from_str(&open[1..]).unwrap();
+    let sid = open["sid"].as_str().unwrap();
+    assert_eq!(
+        cookies(res.headers()),
+        [format!("io={sid}; Path=/; HttpOnly; SameSite=Lax")]
+    );
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn upgrade_refreshes_the_cookie() {
+    const PORT: u16 = 3926;
+    create_server(PORT, Some(HandshakeCookie::default())).await;
+
+    let (sid, headers) = polling_handshake(PORT).await;
+    let expected = [format!("io={sid}; Path=/; HttpOnly; SameSite=Lax")];
+    assert_eq!(cookies(&headers), expected);
+    let headers = ws_handshake(PORT, Some(&sid)).await;
+    assert_eq!(cookies(&headers), expected);
+}
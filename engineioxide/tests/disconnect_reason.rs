@@ -4,6 +4,7 @@
 //! * Transport close
 //! * Multiple http polling
 //! * Packet parsing
+//! * Lifetime expiration
 
 use std::{sync::Arc, time::Duration};
 
@@ -11,15 +12,19 @@ use engineioxide::{
     handler::EngineIoHandler,
     socket::{DisconnectReason, Socket},
 };
-use futures::SinkExt;
+use futures::{SinkExt, StreamExt};
 use tokio::sync::mpsc;
 
 mod fixture;
 
-use fixture::{create_server, send_req};
+use fixture::create_server;
+#[cfg(feature = "polling")]
+use fixture::send_req;
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::fixture::{create_polling_connection, create_ws_connection};
+#[cfg(feature = "polling")]
+use crate::fixture::create_polling_connection;
+use crate::fixture::create_ws_connection;
 
 #[derive(Debug, Clone)]
 struct MyHandler {
@@ -49,6 +54,7 @@ impl EngineIoHandler for MyHandler {
 }
 
 #[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn polling_heartbeat_timeout() {
     let (disconnect_tx, mut rx) = mpsc::channel(10);
     create_server(MyHandler { disconnect_tx }, 1234).await;
@@ -78,6 +84,7 @@ pub async fn ws_heartbeat_timeout() {
 }
 
 #[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn polling_transport_closed() {
     let (disconnect_tx, mut rx) = mpsc::channel(10);
     create_server(MyHandler { disconnect_tx }, 1235).await;
@@ -116,6 +123,7 @@ pub async fn ws_transport_closed() {
 }
 
 #[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn multiple_http_polling() {
     let (disconnect_tx, mut rx) = mpsc::channel(10);
     create_server(MyHandler { disconnect_tx }, 1236).await;
@@ -145,6 +153,7 @@ pub async fn multiple_http_polling() {
 }
 
 #[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn polling_packet_parsing() {
     let (disconnect_tx, mut rx) = mpsc::channel(10);
     create_server(MyHandler { disconnect_tx }, 1237).await;
@@ -182,3 +191,59 @@ pub async fn ws_packet_parsing() {
 
     assert_eq!(data, DisconnectReason::PacketParsingError);
 }
+
+#[tokio::test]
+pub async fn ws_close_frame_on_packet_parsing() {
+    let (disconnect_tx, _rx) = mpsc::channel(100);
+    create_server(MyHandler { disconnect_tx }, 12352).await;
+    for _ in 0..20 {
+        let mut stream = create_ws_connection(12352).await;
+        stream
+            .send(Message::Text("aizdunazidaubdiz".into()))
+            .await
+            .unwrap();
+
+        // The client should receive the close frame before the connection is dropped
+        let close = tokio::time::timeout(Duration::from_millis(200), async {
+            while let Some(Ok(msg)) = stream.next().await {
+                if let Message::Close(_) = msg {
+                    return true;
+                }
+            }
+            false
+        })
+        .await
+        .expect("timeout waiting for the close frame");
+        assert!(close, "the close frame should be received");
+    }
+}
+
+#[tokio::test]
+pub async fn ws_lifetime_expired() {
+    use engineioxide::config::EngineIoConfig;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+    const PORT: u16 = 3930;
+    let (disconnect_tx, mut rx) = mpsc::channel(10);
+    let config = EngineIoConfig::builder()
+        .max_connection_lifetime(Duration::from_millis(100))
+        .build();
+    fixture::create_server_with_config(MyHandler { disconnect_tx }, config, PORT).await;
+    let mut stream = create_ws_connection(PORT).await;
+    stream.next().await.unwrap().unwrap(); // Open packet
+
+    let data = tokio::time::timeout(Duration::from_millis(500), rx.recv())
+        .await
+        .expect("timeout waiting for DisconnectReason::LifetimeExpired")
+        .unwrap();
+    assert_eq!(data, DisconnectReason::LifetimeExpired);
+
+    // The client is told to reconnect with the service restart close code
+    let frame = loop {
+        match stream.next().await.unwrap().unwrap() {
+            Message::Close(frame) => break frame.unwrap(),
+            _ => continue,
+        }
+    };
+    assert_eq!(frame.code, CloseCode::Restart);
+}
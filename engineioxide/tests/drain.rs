@@ -0,0 +1,102 @@
+//! Tests for the draining mode: new sessions are rejected while the existing ones keep working
+#![cfg(feature = "polling")]
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    handler::{EngineIoHandle, EngineIoHandler, ServerState},
+    sid::Sid,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+mod fixture;
+
+use fixture::{create_server, create_ws_connection, send_raw_req};
+
+#[derive(Debug, Clone)]
+struct MyHandler {
+    start_tx: mpsc::UnboundedSender<EngineIoHandle<()>>,
+    disconnect_tx: mpsc::UnboundedSender<(Sid, DisconnectReason)>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_start(&self, handle: EngineIoHandle<()>) {
+        self.start_tx.send(handle).unwrap();
+    }
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) {
+        self.disconnect_tx.send((socket.id, reason)).unwrap();
+    }
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+#[tokio::test]
+pub async fn drain_then_close_all() {
+    const PORT: u16 = 4020;
+    let (start_tx, mut start_rx) = mpsc::unbounded_channel();
+    let (disconnect_tx, mut disconnect_rx) = mpsc::unbounded_channel();
+    create_server(
+        MyHandler {
+            start_tx,
+            disconnect_tx,
+        },
+        PORT,
+    )
+    .await;
+    let handle = start_rx.recv().await.unwrap();
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+
+    assert!(!handle.is_draining());
+    handle.set_draining(true);
+    assert!(handle.is_draining());
+    assert_eq!(handle.state(), ServerState::Paused);
+
+    // New sessions are rejected
+    let params = "transport=polling".to_string();
+    let (status, _, _) = send_raw_req(PORT, params, http::Method::GET, &[], vec![]).await;
+    assert_eq!(status, http::StatusCode::SERVICE_UNAVAILABLE);
+    let url = format!("ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket");
+    match tokio_tungstenite::connect_async(&url).await {
+        Err(WsError::Http(res)) => assert_eq!(res.status(), http::StatusCode::SERVICE_UNAVAILABLE),
+        res => panic!("unexpected upgrade result: {res:?}"),
+    }
+
+    // The existing socket keeps working
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    let msg = loop {
+        match ws.next().await.unwrap().unwrap() {
+            Message::Text(msg) if msg == "2" => continue,
+            msg => break msg,
+        }
+    };
+    assert_eq!(msg, Message::Text("4hello".into()));
+    assert_eq!(handle.sockets().len(), 1);
+
+    // The drain is finished by closing the remaining sockets, new sessions are still rejected
+    assert_eq!(handle.close_all(), 1);
+    let (_, reason) = tokio::time::timeout(Duration::from_millis(200), disconnect_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(reason, DisconnectReason::ClosingServer);
+    assert!(handle.sockets().is_empty());
+    assert!(handle.is_draining());
+    let params = "transport=polling".to_string();
+    let (status, _, _) = send_raw_req(PORT, params, http::Method::GET, &[], vec![]).await;
+    assert_eq!(status, http::StatusCode::SERVICE_UNAVAILABLE);
+
+    handle.set_draining(false);
+    assert_eq!(handle.state(), ServerState::Accepting);
+    let params = "transport=polling".to_string();
+    let (status, _, _) = send_raw_req(PORT, params, http::Method::GET, &[], vec![]).await;
+    assert_eq!(status, http::StatusCode::OK);
+}
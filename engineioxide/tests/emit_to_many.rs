@@ -0,0 +1,84 @@
+//! Tests for the emits to a list of sockets
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    handler::{EmitToError, EngineIoHandle, EngineIoHandler},
+    sid::Sid,
+    socket::{DisconnectReason, Socket},
+};
+use futures::StreamExt;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_server, create_ws_connection};
+
+#[derive(Debug, Clone)]
+struct MyHandler {
+    start_tx: mpsc::UnboundedSender<EngineIoHandle<()>>,
+    connect_tx: mpsc::UnboundedSender<Sid>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_start(&self, handle: EngineIoHandle<()>) {
+        self.start_tx.send(handle).unwrap();
+    }
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        self.connect_tx.send(socket.id).unwrap();
+    }
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+    fn on_message(&self, _msg: String, _socket: Arc<Socket<()>>) {}
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+#[tokio::test]
+pub async fn emit_to_many_sids() {
+    const PORT: u16 = 3112;
+    let (start_tx, mut start_rx) = mpsc::unbounded_channel();
+    let (connect_tx, mut connect_rx) = mpsc::unbounded_channel();
+    create_server(
+        MyHandler {
+            start_tx,
+            connect_tx,
+        },
+        PORT,
+    )
+    .await;
+    let handle = start_rx.recv().await.unwrap();
+
+    let mut clients = Vec::new();
+    let mut sids = Vec::new();
+    for _ in 0..3 {
+        let mut ws = create_ws_connection(PORT).await;
+        ws.next().await.unwrap().unwrap(); // Open packet
+        sids.push(connect_rx.recv().await.unwrap());
+        clients.push(ws);
+    }
+
+    let unknown = Sid::new();
+    let targets = [sids[0], unknown, sids[2]];
+    let results = handle.emit_to_many(&targets, "hello".into());
+    assert_eq!(results.len(), 3);
+    for ((sid, res), target) in results.iter().zip(targets) {
+        assert_eq!(*sid, target);
+        if target == unknown {
+            assert!(matches!(res, Err(EmitToError::UnknownSid)));
+        } else {
+            assert!(res.is_ok());
+        }
+    }
+
+    for i in [0, 2] {
+        let msg = clients[i].next().await.unwrap().unwrap();
+        assert_eq!(msg, Message::Text("4hello".into()));
+    }
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), clients[1].next())
+            .await
+            .is_err(),
+        "the socket not targeted should not receive the message"
+    );
+}
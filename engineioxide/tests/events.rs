@@ -0,0 +1,220 @@
+//! Tests for the lifecycle event stream of the server
+use std::sync::Arc;
+
+use engineioxide::{
+    handler::EngineIoHandler,
+    service::EngineIoService,
+    socket::{DisconnectReason, Socket},
+};
+use futures::StreamExt;
+
+#[cfg(feature = "polling")]
+use engineioxide::{
+    config::EngineIoConfig,
+    events::{EventStream, ServerEvent, UpgradeFailure},
+    sid::Sid,
+};
+#[cfg(feature = "polling")]
+use futures::SinkExt;
+#[cfg(feature = "polling")]
+use std::time::Duration;
+#[cfg(feature = "polling")]
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+#[cfg(feature = "polling")]
+use fixture::{create_polling_connection, create_server_with_config, send_req};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+#[cfg(feature = "polling")]
+async fn next_event(events: &mut EventStream) -> ServerEvent {
+    tokio::time::timeout(Duration::from_millis(500), events.next())
+        .await
+        .expect("timeout waiting for an event")
+        .unwrap()
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn lifecycle_events() {
+    const PORT: u16 = 4007;
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .build();
+    let svc = create_server_with_config(MyHandler, config, PORT).await;
+    let mut events = svc.handle().events();
+
+    // Polling session upgraded to websocket, then closed by the client
+    let sid = create_polling_connection(PORT).await;
+    let sid: Sid = sid.parse().unwrap();
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::Connected { sid }
+    );
+
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={sid}"
+    ))
+    .await
+    .unwrap();
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    ws.next().await.unwrap().unwrap();
+    ws.send(Message::Text("5".into())).await.unwrap();
+    assert_eq!(next_event(&mut events).await, ServerEvent::Upgraded { sid });
+
+    ws.send(Message::Text("1".into())).await.unwrap();
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::Disconnected {
+            sid,
+            reason: DisconnectReason::TransportClose
+        }
+    );
+
+    // Invalid packet on a polling session
+    let sid = create_polling_connection(PORT).await;
+    let params = format!("transport=polling&sid={sid}");
+    let sid: Sid = sid.parse().unwrap();
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::Connected { sid }
+    );
+    send_req(PORT, params, http::Method::POST, Some("x".into())).await;
+    assert!(matches!(
+        next_event(&mut events).await,
+        ServerEvent::Error { sid: s, .. } if s == sid
+    ));
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::Disconnected {
+            sid,
+            reason: DisconnectReason::PacketParsingError
+        }
+    );
+    assert_eq!(events.lagged(), 0);
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn upgrade_failure_events() {
+    const PORT: u16 = 4009;
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .upgrade_timeout(Duration::from_millis(100))
+        .build();
+    let svc = create_server_with_config(MyHandler, config, PORT).await;
+    let mut events = svc.handle().events();
+
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+    let ws_url = format!("ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={sid}");
+    let sid: Sid = sid.parse().unwrap();
+    next_event(&mut events).await; // Connected
+
+    // The probe exchange starts, then the client goes silent on the websocket
+    let (mut ws, _) = tokio_tungstenite::connect_async(&ws_url).await.unwrap();
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    ws.next().await.unwrap().unwrap();
+    assert!(matches!(
+        next_event(&mut events).await,
+        ServerEvent::Error { .. }
+    ));
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::UpgradeFailed {
+            sid,
+            reason: UpgradeFailure::ProbeTimeout
+        }
+    );
+    send_req(PORT, params(), http::Method::POST, Some("4hello".into())).await;
+    assert_eq!(
+        send_req(PORT, params(), http::Method::GET, None).await,
+        "hello"
+    );
+
+    // The client closes the websocket after the probe
+    let (mut ws, _) = tokio_tungstenite::connect_async(&ws_url).await.unwrap();
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    ws.next().await.unwrap().unwrap();
+    ws.close(None).await.unwrap();
+    assert!(matches!(
+        next_event(&mut events).await,
+        ServerEvent::Error { .. }
+    ));
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::UpgradeFailed {
+            sid,
+            reason: UpgradeFailure::ClientAbort
+        }
+    );
+    send_req(PORT, params(), http::Method::POST, Some("4world".into())).await;
+    assert_eq!(
+        send_req(PORT, params(), http::Method::GET, None).await,
+        "world"
+    );
+
+    // The client sends an unexpected packet instead of the probe
+    let (mut ws, _) = tokio_tungstenite::connect_async(&ws_url).await.unwrap();
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    assert!(matches!(
+        next_event(&mut events).await,
+        ServerEvent::Error { .. }
+    ));
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::UpgradeFailed {
+            sid,
+            reason: UpgradeFailure::BadPacket
+        }
+    );
+    assert!(svc.handle().get_socket(sid).is_some());
+    assert_eq!(events.lagged(), 0);
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn lagging_stream() {
+    const PORT: u16 = 4008;
+    let config = EngineIoConfig::builder().event_stream_capacity(2).build();
+    let svc = create_server_with_config(MyHandler, config, PORT).await;
+    let mut events = svc.handle().events();
+
+    let mut sids = Vec::new();
+    for _ in 0..5 {
+        sids.push(create_polling_connection(PORT).await);
+    }
+    // Only the 2 last events are kept
+    let ServerEvent::Connected { sid } = next_event(&mut events).await else {
+        panic!("expected a connected event");
+    };
+    assert_eq!(sid.to_string(), sids[3]);
+    assert_eq!(events.lagged(), 3);
+    next_event(&mut events).await;
+}
+
+#[tokio::test]
+pub async fn stream_ends_with_server() {
+    let svc = EngineIoService::new(MyHandler);
+    let handle = svc.handle();
+    let mut events = handle.events();
+    drop(svc);
+    assert!(events.next().await.is_none());
+    assert!(handle.events().next().await.is_none());
+}
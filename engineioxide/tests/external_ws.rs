@@ -0,0 +1,169 @@
+//! Tests for the websocket connections upgraded outside of the engine
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    service::{
+        tungstenite::{protocol::Role, Message},
+        EngineIoService, ProtocolVersion, TransportType, WsHandshake,
+    },
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio::{io::DuplexStream, sync::mpsc};
+use tokio_tungstenite::WebSocketStream;
+
+#[derive(Debug, Clone)]
+struct MyHandler {
+    connect_tx: mpsc::UnboundedSender<Arc<Socket<()>>>,
+    disconnect_tx: mpsc::UnboundedSender<DisconnectReason>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        self.connect_tx.send(socket).unwrap();
+    }
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, reason: DisconnectReason) {
+        self.disconnect_tx.send(reason).unwrap();
+    }
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+/// Connects a client to the service through an in-memory duplex stream,
+/// the websocket upgrade being skipped on both sides
+async fn connect(svc: &EngineIoService<MyHandler>) -> WebSocketStream<DuplexStream> {
+    let (client, server) = tokio::io::duplex(1024 * 64);
+    let (parts, _) = http::Request::builder()
+        .uri("/engine.io/?EIO=4&transport=websocket")
+        .header("x-client-cert", "CN=client")
+        .body(())
+        .unwrap()
+        .into_parts();
+    let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    let svc = svc.clone();
+    tokio::spawn(async move {
+        svc.on_ws_connection(server, WsHandshake::new(ProtocolVersion::V4, parts))
+            .await;
+    });
+    WebSocketStream::from_raw_socket(client, Role::Client, None).await
+}
+
+#[tokio::test]
+pub async fn external_ws_connection() {
+    let (connect_tx, mut connect_rx) = mpsc::unbounded_channel();
+    let (disconnect_tx, mut disconnect_rx) = mpsc::unbounded_channel();
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_millis(50))
+        .ping_timeout(Duration::from_millis(50))
+        .build();
+    let svc = EngineIoService::with_config(
+        MyHandler {
+            connect_tx,
+            disconnect_tx,
+        },
+        config,
+    );
+
+    let mut ws = connect(&svc).await;
+    let open = ws.next().await.unwrap().unwrap().into_text().unwrap();
+    assert!(open.starts_with("0{\"sid\":"), "{open}");
+    assert!(open.contains("\"upgrades\":[]"), "{open}");
+
+    let socket = connect_rx.recv().await.unwrap();
+    assert_eq!(socket.transport_type(), TransportType::Websocket);
+    assert_eq!(socket.protocol, ProtocolVersion::V4);
+    assert_eq!(socket.req_parts.headers["x-client-cert"], "CN=client");
+    assert!(open.contains(&socket.id.to_string()), "{open}");
+
+    // Messages are echoed back by the handler
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4hello".into())
+    );
+    ws.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Binary(vec![1, 2, 3])
+    );
+
+    // The heartbeat is running
+    for _ in 0..3 {
+        assert_eq!(ws.next().await.unwrap().unwrap(), Message::Text("2".into()));
+        ws.send(Message::Text("3".into())).await.unwrap();
+    }
+    assert!(socket.last_rtt().is_some());
+
+    ws.close(None).await.unwrap();
+    let reason = tokio::time::timeout(Duration::from_millis(200), disconnect_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(reason, DisconnectReason::TransportClose);
+    assert!(svc.handle().get_socket(socket.id).is_none());
+}
+
+#[tokio::test]
+pub async fn external_ws_connection_rejected() {
+    let (connect_tx, mut connect_rx) = mpsc::unbounded_channel();
+    let (disconnect_tx, _disconnect_rx) = mpsc::unbounded_channel();
+    let svc = EngineIoService::new(MyHandler {
+        connect_tx,
+        disconnect_tx,
+    });
+    svc.handle().pause_accepting();
+
+    let mut ws = connect(&svc).await;
+    match ws.next().await.unwrap().unwrap() {
+        Message::Close(Some(frame)) => assert_eq!(u16::from(frame.code), 1013),
+        msg => panic!("unexpected message {msg:?}"),
+    }
+    assert!(connect_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+pub async fn send_and_flush_over_websocket() {
+    use engineioxide::socket::FlushError;
+
+    let (connect_tx, mut connect_rx) = mpsc::unbounded_channel();
+    let (disconnect_tx, mut disconnect_rx) = mpsc::unbounded_channel();
+    let svc = EngineIoService::new(MyHandler {
+        connect_tx,
+        disconnect_tx,
+    });
+
+    let mut ws = connect(&svc).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    let socket = connect_rx.recv().await.unwrap();
+
+    // It resolves once the frame is written to the connection, before the client reads it
+    tokio::time::timeout(
+        Duration::from_millis(200),
+        socket.send_and_flush("hello".into()),
+    )
+    .await
+    .expect("the message should be flushed")
+    .unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4hello".into())
+    );
+
+    ws.close(None).await.unwrap();
+    disconnect_rx.recv().await.unwrap();
+    let res = socket.send_and_flush("closed".into()).await;
+    assert!(matches!(
+        res,
+        Err(FlushError::Send(_) | FlushError::NotFlushed)
+    ));
+}
@@ -0,0 +1,95 @@
+//! Tests for the fairness between the sockets: a socket sending a huge batch of packets
+//! doesn't starve the other sockets of its worker thread
+#![cfg(feature = "polling")]
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use engineioxide::{
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use http::{Method, StatusCode};
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server, create_ws_connection};
+
+/// Each flood message takes some cpu time, the other messages are echoed
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        if msg == "flood" {
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_micros(50) {
+                std::hint::spin_loop();
+            }
+        } else {
+            socket.emit(msg).ok();
+        }
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+/// The server and the clients share the single thread of the test runtime
+#[tokio::test]
+pub async fn flooding_socket_does_not_starve_others() {
+    const PORT: u16 = 4023;
+    const FLOOD: usize = 8_000;
+    // With the default `packets_per_yield`
+    create_server(MyHandler, PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    let sid = create_polling_connection(PORT).await;
+
+    // A single polling payload with all the flood packets, processed in ~400ms
+    let flood = async {
+        let body = vec!["4flood"; FLOOD].join("\x1e");
+        let params = format!("transport=polling&sid={sid}");
+        let start = Instant::now();
+        let (status, _, _) =
+            fixture::send_raw_req(PORT, params, Method::POST, &[], body.into_bytes()).await;
+        assert_eq!(status, StatusCode::OK);
+        start.elapsed()
+    };
+
+    // Round trips of the interactive socket while the flood is processed,
+    // measured from the time they are scheduled to include the delay of a starved runtime
+    let interactive = async {
+        let scheduled = tokio::time::Instant::now() + Duration::from_millis(50);
+        let mut max_rtt = Duration::ZERO;
+        for i in 0..10 {
+            let start = scheduled + Duration::from_millis(20) * i;
+            tokio::time::sleep_until(start).await;
+            ws.send(Message::Text(format!("4ping{i}"))).await.unwrap();
+            loop {
+                match ws.next().await.unwrap().unwrap() {
+                    Message::Text(msg) if msg == format!("4ping{i}") => break,
+                    _ => continue,
+                }
+            }
+            max_rtt = max_rtt.max(start.elapsed());
+        }
+        max_rtt
+    };
+
+    let (flood_time, max_rtt) = tokio::join!(flood, interactive);
+    assert!(
+        flood_time > Duration::from_millis(300),
+        "the flood must overlap the round trips: {flood_time:?}"
+    );
+    assert!(
+        max_rtt < Duration::from_millis(100),
+        "round trip of {max_rtt:?} during a flood of {flood_time:?}"
+    );
+}
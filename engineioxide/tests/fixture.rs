@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use std::{
     collections::VecDeque,
     net::{IpAddr, Ipv4Addr, SocketAddr},
@@ -33,6 +35,17 @@ pub async fn send_req(
     params: String,
     method: http::Method,
     body: Option<String>,
+) -> String {
+    send_req_with_path(port, "/engine.io/", params, method, body).await
+}
+
+/// Same as [`send_req`] but with a custom request path
+pub async fn send_req_with_path(
+    port: u16,
+    path: &str,
+    params: String,
+    method: http::Method,
+    body: Option<String>,
 ) -> String {
     let body = match body {
         Some(b) => Either::Left(Full::new(VecDeque::from(b.into_bytes()))),
@@ -41,10 +54,7 @@ pub async fn send_req(
 
     let req = Request::builder()
         .method(method)
-        .uri(format!(
-            "http://127.0.0.1:{port}/engine.io/?EIO=4&{}",
-            params
-        ))
+        .uri(format!("http://127.0.0.1:{port}{path}?EIO=4&{}", params))
         .body(body)
         .unwrap();
     let mut res = Client::builder(TokioExecutor::new())
@@ -60,6 +70,60 @@ pub async fn send_req(
         .collect()
 }
 
+/// Same as [`send_req`] but returns the status and the headers of the response
+pub async fn send_req_headers(
+    port: u16,
+    params: String,
+    method: http::Method,
+    body: Option<String>,
+) -> (http::StatusCode, http::HeaderMap) {
+    let body = match body {
+        Some(b) => Either::Left(Full::new(VecDeque::from(b.into_bytes()))),
+        None => Either::Right(Empty::<VecDeque<u8>>::new()),
+    };
+
+    let req = Request::builder()
+        .method(method)
+        .uri(format!(
+            "http://127.0.0.1:{port}/engine.io/?EIO=4&{}",
+            params
+        ))
+        .body(body)
+        .unwrap();
+    let res = Client::builder(TokioExecutor::new())
+        .build_http()
+        .request(req)
+        .await
+        .unwrap();
+    (res.status(), res.headers().clone())
+}
+
+/// Same as [`send_req`] but with custom headers and a raw body.
+/// The status, the headers and the raw body of the response are returned
+pub async fn send_raw_req(
+    port: u16,
+    params: String,
+    method: http::Method,
+    headers: &[(&str, &str)],
+    body: Vec<u8>,
+) -> (http::StatusCode, http::HeaderMap, Vec<u8>) {
+    let mut req = Request::builder().method(method).uri(format!(
+        "http://127.0.0.1:{port}/engine.io/?EIO=4&{}",
+        params
+    ));
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    let req = req.body(Full::new(VecDeque::from(body))).unwrap();
+    let mut res = Client::builder(TokioExecutor::new())
+        .build_http()
+        .request(req)
+        .await
+        .unwrap();
+    let body = res.body_mut().collect().await.unwrap().to_bytes();
+    (res.status(), res.headers().clone(), body.to_vec())
+}
+
 pub async fn create_polling_connection(port: u16) -> String {
     let body = send_req(port, format!("transport=polling"), http::Method::GET, None).await;
     let open_packet: OpenPacket = serde_json::from_str(&body).unwrap();
@@ -80,10 +144,18 @@ pub async fn create_server<H: EngineIoHandler>(handler: H, port: u16) {
         .ping_timeout(Duration::from_millis(200))
         .max_payload(1e6 as u64)
         .build();
+    create_server_with_config(handler, config, port).await;
+}
 
+pub async fn create_server_with_config<H: EngineIoHandler>(
+    handler: H,
+    config: EngineIoConfig,
+    port: u16,
+) -> EngineIoService<H> {
     let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
 
     let svc = EngineIoService::with_config(handler, config);
+    let server_svc = svc.clone();
 
     let listener = TcpListener::bind(&addr).await.unwrap();
     tokio::spawn(async move {
@@ -94,7 +166,7 @@ pub async fn create_server<H: EngineIoHandler>(handler: H, port: u16) {
             // Use an adapter to access something implementing `tokio::io` traits as if they implement
             // `hyper::rt` IO traits.
             let io = TokioIo::new(stream);
-            let svc = svc.clone();
+            let svc = server_svc.clone();
 
             // Spawn a tokio task to serve multiple connections concurrently
             tokio::task::spawn(async move {
@@ -109,4 +181,5 @@ pub async fn create_server<H: EngineIoHandler>(handler: H, port: u16) {
             });
         }
     });
+    svc
 }
@@ -0,0 +1,117 @@
+//! Tests for the `b64` query param of the clients that can't handle binary data
+#![cfg(feature = "v3")]
+
+use std::{collections::VecDeque, sync::Arc};
+
+use engineioxide::{
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use http::Request;
+use http_body_util::{BodyExt, Full};
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::create_server;
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+/// Sends an engine.io v3 polling request and returns the body of the response
+async fn v3_req(port: u16, params: &str, method: http::Method, body: &str) -> String {
+    let req = Request::builder()
+        .method(method)
+        .uri(format!("http://127.0.0.1:{port}/engine.io/?EIO=3&{params}"))
+        .body(Full::new(VecDeque::from(body.as_bytes().to_vec())))
+        .unwrap();
+    let mut res = Client::builder(TokioExecutor::new())
+        .build_http()
+        .request(req)
+        .await
+        .unwrap();
+    let body = res.body_mut().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+pub async fn force_base64_websocket() {
+    const PORT: u16 = 4004;
+    create_server(MyHandler, PORT).await;
+
+    let url = format!("ws://127.0.0.1:{PORT}/engine.io/?EIO=3&transport=websocket&b64=1");
+    let mut ws = tokio_tungstenite::connect_async(url).await.unwrap().0;
+    let open = ws.next().await.unwrap().unwrap().into_text().unwrap();
+    assert!(open.starts_with("0{"));
+
+    // Binary data is received as a base64 text packet and echoed the same way
+    ws.send(Message::Text("b4AQID".into())).await.unwrap();
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Text("b4AQID".into()));
+
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Text("4hello".into()));
+}
+
+#[tokio::test]
+pub async fn binary_websocket_without_b64() {
+    const PORT: u16 = 4005;
+    create_server(MyHandler, PORT).await;
+
+    let url = format!("ws://127.0.0.1:{PORT}/engine.io/?EIO=3&transport=websocket");
+    let mut ws = tokio_tungstenite::connect_async(url).await.unwrap().0;
+    ws.next().await.unwrap().unwrap();
+
+    ws.send(Message::Binary(vec![4, 1, 2, 3])).await.unwrap();
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Binary(vec![4, 1, 2, 3]));
+
+    // A base64 packet from the client is still accepted, the reply is a binary frame
+    ws.send(Message::Text("b4AQID".into())).await.unwrap();
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Binary(vec![4, 1, 2, 3]));
+}
+
+#[tokio::test]
+pub async fn force_base64_kept_after_upgrade() {
+    const PORT: u16 = 4006;
+    create_server(MyHandler, PORT).await;
+
+    let open = v3_req(PORT, "transport=polling&b64=1", http::Method::GET, "").await;
+    // A string payload is sent to b64 clients: `<length>:0{...}`
+    let (_, packet) = open.split_once(':').unwrap();
+    let open: serde_json::Value = serde_json::from_str(&packet[1..]).unwrap();
+    let sid = open["sid"].as_str().unwrap();
+
+    let params = format!("transport=polling&sid={sid}");
+    v3_req(PORT, &params, http::Method::POST, "6:b4AQID").await;
+    let res = v3_req(PORT, &params, http::Method::GET, "").await;
+    assert_eq!(res, "6:b4AQID");
+
+    let url = format!("ws://127.0.0.1:{PORT}/engine.io/?EIO=3&transport=websocket&sid={sid}");
+    let mut ws = tokio_tungstenite::connect_async(url).await.unwrap().0;
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Text("3probe".into()));
+    ws.send(Message::Text("5".into())).await.unwrap();
+
+    ws.send(Message::Text("b4BAUG".into())).await.unwrap();
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Text("b4BAUG".into()));
+}
@@ -0,0 +1,93 @@
+//! Tests for [`EngineIoHandle::force_upgrade_or_close`]
+#![cfg(feature = "polling")]
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    handler::{EngineIoHandle, EngineIoHandler, UpgradeReport},
+    sid::Sid,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server, create_ws_connection, send_raw_req};
+
+#[derive(Debug, Clone)]
+struct MyHandler {
+    start_tx: mpsc::UnboundedSender<EngineIoHandle<()>>,
+    disconnect_tx: mpsc::UnboundedSender<(Sid, DisconnectReason)>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_start(&self, handle: EngineIoHandle<()>) {
+        self.start_tx.send(handle).unwrap();
+    }
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) {
+        self.disconnect_tx.send((socket.id, reason)).unwrap();
+    }
+    fn on_message(&self, _msg: String, _socket: Arc<Socket<()>>) {}
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+#[tokio::test]
+pub async fn polling_sockets_are_upgraded_or_closed() {
+    const PORT: u16 = 4019;
+    let (start_tx, mut start_rx) = mpsc::unbounded_channel();
+    let (disconnect_tx, mut disconnect_rx) = mpsc::unbounded_channel();
+    create_server(
+        MyHandler {
+            start_tx,
+            disconnect_tx,
+        },
+        PORT,
+    )
+    .await;
+    let handle = start_rx.recv().await.unwrap();
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    let upgraded = create_polling_connection(PORT).await;
+    let straggler = create_polling_connection(PORT).await;
+
+    let task = tokio::spawn(async move {
+        handle
+            .force_upgrade_or_close(Duration::from_millis(200))
+            .await
+    });
+
+    // The stragglers are nudged to poll again
+    let params = format!("transport=polling&sid={straggler}");
+    let (status, _, body) = send_raw_req(PORT, params, http::Method::GET, &[], vec![]).await;
+    assert_eq!(String::from_utf8_lossy(&body), "6", "{status}");
+
+    let (mut upgraded_ws, _) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={upgraded}"
+    ))
+    .await
+    .unwrap();
+    upgraded_ws
+        .send(Message::Text("2probe".into()))
+        .await
+        .unwrap();
+    upgraded_ws.next().await.unwrap().unwrap();
+    upgraded_ws.send(Message::Text("5".into())).await.unwrap();
+
+    let report = task.await.unwrap();
+    assert_eq!(
+        report,
+        UpgradeReport {
+            upgraded: 1,
+            closed: 1
+        }
+    );
+    let (sid, reason) = disconnect_rx.recv().await.unwrap();
+    assert_eq!(sid, straggler.parse::<Sid>().unwrap());
+    assert_eq!(reason, DisconnectReason::UpgradeRequired);
+    assert!(disconnect_rx.try_recv().is_err());
+}
@@ -0,0 +1,93 @@
+//! Tests for the hook modifying the headers of the handshake responses
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use engineioxide::{
+    config::{EngineIoConfig, Handshake},
+    handler::EngineIoHandler,
+    service::TransportType,
+    socket::{DisconnectReason, Socket},
+};
+use http::{
+    header::{CONTENT_TYPE, SET_COOKIE, UPGRADE},
+    HeaderMap, HeaderValue,
+};
+
+mod fixture;
+
+use fixture::create_server_with_config;
+#[cfg(feature = "polling")]
+use fixture::send_req_headers;
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+type Calls = Arc<Mutex<Vec<(bool, TransportType)>>>;
+
+async fn create_server(port: u16) -> Calls {
+    let calls = Calls::default();
+    let calls_clone = calls.clone();
+    let hook = move |handshake: &Handshake<'_>, headers: &mut HeaderMap| {
+        let call = (handshake.sid.is_some(), handshake.transport);
+        calls_clone.lock().unwrap().push(call);
+        headers.append(SET_COOKIE, HeaderValue::from_static("node=1"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("other=2"));
+        // Reserved headers are restored
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        headers.insert(UPGRADE, HeaderValue::from_static("h2c"));
+    };
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .handshake_response_hook(hook)
+        .build();
+    create_server_with_config(MyHandler, config, port).await;
+    calls
+}
+
+fn cookies(headers: &HeaderMap) -> Vec<&str> {
+    let cookies = headers.get_all(SET_COOKIE).iter();
+    cookies.map(|v| v.to_str().unwrap()).collect()
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn polling_handshake_hook() {
+    const PORT: u16 = 3800;
+    let calls = create_server(PORT).await;
+
+    let params = "transport=polling".to_string();
+    let (_, headers) = send_req_headers(PORT, params, http::Method::GET, None).await;
+    assert_eq!(cookies(&headers), ["node=1", "other=2"]);
+    assert_eq!(headers[CONTENT_TYPE], "text/plain; charset=UTF-8");
+    // Other headers can be set
+    assert_eq!(headers[UPGRADE], "h2c");
+    assert_eq!(*calls.lock().unwrap(), [(true, TransportType::Polling)]);
+}
+
+#[tokio::test]
+pub async fn websocket_handshake_hook() {
+    const PORT: u16 = 3801;
+    let calls = create_server(PORT).await;
+
+    let (_ws, res) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket"
+    ))
+    .await
+    .unwrap();
+    let headers = res.headers();
+    assert_eq!(cookies(headers), ["node=1", "other=2"]);
+    assert_eq!(headers[UPGRADE], "websocket");
+    // The session of a direct websocket connection is not created yet
+    assert_eq!(*calls.lock().unwrap(), [(false, TransportType::Websocket)]);
+}
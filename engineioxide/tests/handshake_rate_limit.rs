@@ -0,0 +1,145 @@
+//! Tests for the handshake rate limit per remote IP
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    rate_limit::HandshakeRateLimit,
+    socket::{DisconnectReason, Socket},
+};
+use http::{HeaderValue, StatusCode};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error as WsError};
+
+#[cfg(feature = "polling")]
+use http::Method;
+
+mod fixture;
+
+use fixture::create_server_with_config;
+#[cfg(feature = "polling")]
+use fixture::send_raw_req;
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+#[cfg(feature = "polling")]
+async fn polling_handshake(port: u16, ip: &str) -> StatusCode {
+    let headers = [("x-forwarded-for", ip)];
+    let params = "transport=polling".to_string();
+    send_raw_req(port, params, Method::GET, &headers, vec![])
+        .await
+        .0
+}
+
+async fn ws_handshake(port: u16, ip: &'static str) -> Result<(), WsError> {
+    let mut req = format!("ws://127.0.0.1:{port}/engine.io/?EIO=4&transport=websocket")
+        .into_client_request()
+        .unwrap();
+    req.headers_mut()
+        .insert("x-forwarded-for", HeaderValue::from_static(ip));
+    tokio_tungstenite::connect_async(req).await.map(|_| ())
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn handshake_rate_limit_polling() {
+    const PORT: u16 = 4002;
+    let limit = Arc::new(HandshakeRateLimit::new(2, Duration::from_secs(60)).trust_proxy(true));
+    let config = EngineIoConfig::builder()
+        .handshake_rate_limit(limit.clone())
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    assert_eq!(polling_handshake(PORT, "1.1.1.1").await, StatusCode::OK);
+    assert_eq!(polling_handshake(PORT, "1.1.1.1").await, StatusCode::OK);
+    assert_eq!(
+        polling_handshake(PORT, "1.1.1.1").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    // Other IPs are not affected
+    assert_eq!(polling_handshake(PORT, "2.2.2.2").await, StatusCode::OK);
+    assert_eq!(limit.rejected(), 1);
+    assert_eq!(limit.tracked_ips(), 2);
+
+    // Requests of existing sessions are not limited
+    let sid = fixture::create_polling_connection(PORT).await;
+    let params = format!("transport=polling&sid={sid}");
+    let headers = [("x-forwarded-for", "1.1.1.1")];
+    let (status, _, _) =
+        send_raw_req(PORT, params, Method::POST, &headers, b"4hello".to_vec()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+pub async fn handshake_rate_limit_websocket() {
+    const PORT: u16 = 4003;
+    let limit = Arc::new(HandshakeRateLimit::new(1, Duration::from_secs(60)).trust_proxy(true));
+    let config = EngineIoConfig::builder()
+        .handshake_rate_limit(limit.clone())
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    ws_handshake(PORT, "1.1.1.1").await.unwrap();
+    match ws_handshake(PORT, "1.1.1.1").await {
+        Err(WsError::Http(res)) => assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS),
+        res => panic!("unexpected upgrade result: {res:?}"),
+    }
+    ws_handshake(PORT, "2.2.2.2").await.unwrap();
+    assert_eq!(limit.rejected(), 1);
+}
+
+/// A reconnection storm of 100 handshakes per second from a single client behind a proxy
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn handshake_rate_limit_storm() {
+    const PORT: u16 = 4021;
+    let limit = HandshakeRateLimit::per_second(10, 10)
+        .forwarded_depth(1)
+        .max_concurrent_handshakes(50);
+    let limit = Arc::new(limit);
+    let config = EngineIoConfig::builder()
+        .handshake_rate_limit(limit.clone())
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    // The client spoofs the first address, the proxy appends the real one
+    let headers = [("x-forwarded-for", "6.6.6.6, 1.1.1.1")];
+    let start = tokio::time::Instant::now();
+    let mut interval = tokio::time::interval(Duration::from_millis(10));
+    let mut accepted = 0;
+    for _ in 0..100 {
+        interval.tick().await;
+        let params = "transport=polling".to_string();
+        let (status, res_headers, _) =
+            send_raw_req(PORT, params, Method::GET, &headers, vec![]).await;
+        match status {
+            StatusCode::OK => accepted += 1,
+            StatusCode::TOO_MANY_REQUESTS => assert_eq!(res_headers["retry-after"], "1"),
+            status => panic!("unexpected status: {status}"),
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    // The burst plus the tokens refilled during the storm
+    let max = 10 + (elapsed * 10.0).ceil() as u64;
+    assert!(
+        (10..=max).contains(&accepted),
+        "{accepted} accepted in {elapsed}s"
+    );
+    assert_eq!(limit.rejected(), 100 - accepted);
+    assert_eq!(limit.rejected_concurrent(), 0);
+    assert_eq!(limit.handshakes_in_progress(), 0);
+    assert_eq!(limit.tracked_ips(), 1);
+
+    // Another client is not affected
+    assert_eq!(polling_handshake(PORT, "2.2.2.2").await, StatusCode::OK);
+}
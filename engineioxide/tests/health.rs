@@ -0,0 +1,80 @@
+//! Tests for the health report of the server
+#![cfg(feature = "polling")]
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::{EngineIoHandle, EngineIoHandler, Health},
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, create_ws_connection};
+
+#[derive(Debug, Clone)]
+struct MyHandler {
+    start_tx: mpsc::UnboundedSender<EngineIoHandle<()>>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_start(&self, handle: EngineIoHandle<()>) {
+        self.start_tx.send(handle).unwrap();
+    }
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+    fn on_message(&self, _msg: String, _socket: Arc<Socket<()>>) {}
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+fn health(connections: usize, upgrading: usize, draining: bool) -> Health {
+    Health {
+        connections,
+        upgrading,
+        draining,
+        accepting: !draining,
+    }
+}
+
+#[tokio::test]
+pub async fn health_report() {
+    const PORT: u16 = 4031;
+    let (start_tx, mut start_rx) = mpsc::unbounded_channel();
+    let config = EngineIoConfig::builder()
+        .upgrade_timeout(Duration::from_millis(100))
+        .build();
+    create_server_with_config(MyHandler { start_tx }, config, PORT).await;
+    let handle = start_rx.recv().await.unwrap();
+    assert_eq!(handle.health(), health(0, 0, false));
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    let sid = create_polling_connection(PORT).await;
+    assert_eq!(handle.health(), health(2, 0, false));
+
+    // The upgrade of the polling session is stuck until the probe is sent
+    let (mut upgrade, _) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={sid}"
+    ))
+    .await
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(handle.health(), health(2, 1, false));
+    // The upgrade times out
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(handle.health(), health(2, 0, false));
+    upgrade.close(None).await.ok();
+
+    handle.set_draining(true);
+    assert_eq!(handle.health(), health(2, 0, true));
+    handle.set_draining(false);
+
+    ws.send(Message::Text("1".into())).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(handle.health(), health(1, 0, false));
+}
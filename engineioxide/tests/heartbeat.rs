@@ -0,0 +1,340 @@
+//! Tests for the websocket protocol-level ping frames, the transport liveness, the adaptive heartbeat
+//! the pings sent by the clients and the disabled heartbeat
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::{ClientPingPolicy, EngineIoConfig},
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+mod fixture;
+
+use fixture::{create_server_with_config, create_ws_connection};
+
+#[derive(Debug, Clone)]
+struct MyHandler {
+    connect_tx: mpsc::Sender<Arc<Socket<()>>>,
+    disconnect_tx: mpsc::Sender<DisconnectReason>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        println!("socket connect {}", socket.id);
+        self.connect_tx.try_send(socket).unwrap();
+    }
+    fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) {
+        println!("socket disconnect {}: {:?}", socket.id, reason);
+        self.disconnect_tx.try_send(reason).unwrap();
+    }
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+/// Spawn a server with a short heartbeat and websocket ping frames every 20ms
+async fn create_server(
+    port: u16,
+    transport_liveness: bool,
+) -> (
+    mpsc::Receiver<Arc<Socket<()>>>,
+    mpsc::Receiver<DisconnectReason>,
+) {
+    let (connect_tx, connect_rx) = mpsc::channel(10);
+    let (disconnect_tx, rx) = mpsc::channel(10);
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_millis(100))
+        .ping_timeout(Duration::from_millis(100))
+        .ws_ping_interval(Duration::from_millis(20))
+        .transport_liveness(transport_liveness)
+        .build();
+    let handler = MyHandler {
+        connect_tx,
+        disconnect_tx,
+    };
+    create_server_with_config(handler, config, port).await;
+    (connect_rx, rx)
+}
+
+/// Connect a client that answers websocket ping frames but never engine.io pings.
+/// Returns the number of ping frames received.
+async fn run_ws_only_client(port: u16, duration: Duration) -> usize {
+    let mut ws = create_ws_connection(port).await;
+    let mut pings = 0;
+    // Reading the stream automatically answers the ping frames with pong frames
+    tokio::time::timeout(duration, async {
+        while let Some(Ok(msg)) = ws.next().await {
+            if let Message::Ping(_) = msg {
+                pings += 1;
+            }
+        }
+    })
+    .await
+    .ok();
+    pings
+}
+
+#[tokio::test]
+pub async fn ws_pong_extends_heartbeat() {
+    const PORT: u16 = 3100;
+    let (mut connect_rx, mut rx) = create_server(PORT, true).await;
+
+    let pings = run_ws_only_client(PORT, Duration::from_millis(600)).await;
+    assert!(pings > 0, "the server should send websocket ping frames");
+    rx.try_recv()
+        .expect_err("the socket should be kept alive by the websocket pong frames");
+
+    // The engine.io pings are never answered
+    let socket = connect_rx.try_recv().unwrap();
+    assert!(socket.missed_pongs() > 0);
+    assert!(socket.last_ping_at().is_some());
+    assert_eq!(socket.last_pong_at(), None);
+    assert_eq!(socket.last_rtt(), None);
+}
+
+#[tokio::test]
+pub async fn ws_pong_without_transport_liveness() {
+    const PORT: u16 = 3101;
+    let (_connect_rx, mut rx) = create_server(PORT, false).await;
+
+    let pings = run_ws_only_client(PORT, Duration::from_millis(600)).await;
+    assert!(pings > 0, "the server should send websocket ping frames");
+    let reason = tokio::time::timeout(Duration::from_millis(100), rx.recv())
+        .await
+        .expect("timeout waiting for DisconnectReason::HeartbeatTimeout")
+        .unwrap();
+    assert_eq!(reason, DisconnectReason::HeartbeatTimeout);
+}
+
+#[tokio::test]
+pub async fn heartbeat_round_trip_status() {
+    const PORT: u16 = 3102;
+    let (mut connect_rx, _rx) = create_server(PORT, false).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    let socket = connect_rx.recv().await.unwrap();
+    assert_eq!(socket.last_rtt(), None);
+
+    // Answer the first engine.io ping
+    loop {
+        match ws.next().await.unwrap().unwrap() {
+            Message::Text(msg) if msg == "2" => break,
+            _ => continue,
+        }
+    }
+    ws.send(Message::Text("3".into())).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    assert!(socket.last_ping_at().is_some());
+    assert!(socket.last_pong_at().is_some());
+    assert!(socket.last_rtt().unwrap() < Duration::from_millis(100));
+    assert_eq!(socket.missed_pongs(), 0);
+}
+
+#[tokio::test]
+pub async fn adaptive_heartbeat_interval() {
+    const PORT: u16 = 3103;
+    const MIN: Duration = Duration::from_millis(20);
+    const MAX: Duration = Duration::from_millis(80);
+    let (connect_tx, mut connect_rx) = mpsc::channel(10);
+    let (disconnect_tx, _rx) = mpsc::channel(10);
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_millis(20))
+        .ping_timeout(Duration::from_millis(200))
+        .adaptive_heartbeat(MIN, MAX)
+        .build();
+    let handler = MyHandler {
+        connect_tx,
+        disconnect_tx,
+    };
+    create_server_with_config(handler, config, PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    let socket = connect_rx.recv().await.unwrap();
+    // The longest interval is advertised to the client
+    let open = ws.next().await.unwrap().unwrap().into_text().unwrap();
+    assert!(open.contains("\"pingInterval\":80"), "{open}");
+
+    // Answers the pings after `delay` until the heartbeat interval reaches `target`
+    async fn pong_until(
+        ws: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+        socket: &Socket<()>,
+        delay: Duration,
+        target: Duration,
+    ) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while socket.heartbeat_interval() != Some(target) {
+                match ws.next().await.unwrap().unwrap() {
+                    Message::Text(msg) if msg == "2" => {
+                        tokio::time::sleep(delay).await;
+                        ws.send(Message::Text("3".into())).await.unwrap();
+                    }
+                    _ => continue,
+                }
+            }
+        })
+        .await
+        .expect("the heartbeat interval should reach the target");
+    }
+
+    // Fast and stable pongs lengthen the interval
+    pong_until(&mut ws, &socket, Duration::ZERO, MAX).await;
+    // Slow pongs shorten it
+    pong_until(&mut ws, &socket, Duration::from_millis(120), MIN).await;
+    assert_eq!(socket.missed_pongs(), 0);
+}
+
+/// Connect a client to a server with the given [`ClientPingPolicy`] and send it a ping
+async fn send_client_ping(
+    port: u16,
+    policy: ClientPingPolicy,
+) -> (
+    WebSocketStream<MaybeTlsStream<TcpStream>>,
+    mpsc::Receiver<DisconnectReason>,
+) {
+    let (connect_tx, _connect_rx) = mpsc::channel(10);
+    let (disconnect_tx, rx) = mpsc::channel(10);
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .client_ping_policy(policy)
+        .build();
+    let handler = MyHandler {
+        connect_tx,
+        disconnect_tx,
+    };
+    create_server_with_config(handler, config, port).await;
+
+    let mut ws = create_ws_connection(port).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Text("2".into())).await.unwrap();
+    (ws, rx)
+}
+
+#[tokio::test]
+pub async fn client_ping_pong_policy() {
+    const PORT: u16 = 3104;
+    let (mut ws, mut rx) = send_client_ping(PORT, ClientPingPolicy::Pong).await;
+    let msg = tokio::time::timeout(Duration::from_millis(100), ws.next())
+        .await
+        .expect("the ping should be answered");
+    assert_eq!(msg.unwrap().unwrap(), Message::Text("3".into()));
+
+    // The socket is still open
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Text("4hello".into()));
+    rx.try_recv().unwrap_err();
+}
+
+#[tokio::test]
+pub async fn client_ping_ignore_policy() {
+    const PORT: u16 = 3105;
+    let (mut ws, mut rx) = send_client_ping(PORT, ClientPingPolicy::Ignore).await;
+
+    // The ping is discarded, the next message is the echo of the following one
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Text("4hello".into()));
+    rx.try_recv().unwrap_err();
+}
+
+#[tokio::test]
+pub async fn client_ping_close_policy() {
+    const PORT: u16 = 3106;
+    let (_ws, mut rx) = send_client_ping(PORT, ClientPingPolicy::Close).await;
+    let reason = tokio::time::timeout(Duration::from_millis(100), rx.recv())
+        .await
+        .expect("timeout waiting for DisconnectReason::PacketParsingError")
+        .unwrap();
+    assert_eq!(reason, DisconnectReason::PacketParsingError);
+}
+
+#[tokio::test]
+pub async fn pong_flood_does_not_stall() {
+    const PORT: u16 = 3107;
+    let (_connect_rx, mut rx) = create_server(PORT, false).await;
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+
+    // Unsolicited pongs are sent in a row, before and after the first ping
+    for _ in 0..50 {
+        ws.send(Message::Text("3".into())).await.unwrap();
+    }
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+
+    tokio::time::timeout(Duration::from_millis(50), async {
+        loop {
+            match ws.next().await.unwrap().unwrap() {
+                Message::Text(msg) if msg == "4hello" => break,
+                Message::Text(msg) => panic!("unexpected message {msg}"),
+                _ => (),
+            }
+        }
+    })
+    .await
+    .expect("the packets following the pongs should be handled right away");
+
+    // The heartbeat keeps working afterwards
+    let mut pings = 0;
+    while pings < 3 {
+        if let Message::Text(msg) = ws.next().await.unwrap().unwrap() {
+            assert_eq!(msg, "2");
+            for _ in 0..10 {
+                ws.send(Message::Text("3".into())).await.unwrap();
+            }
+            pings += 1;
+        }
+    }
+    rx.try_recv().unwrap_err();
+}
+
+#[tokio::test]
+pub async fn disabled_heartbeat() {
+    const PORT: u16 = 3108;
+    let (connect_tx, mut connect_rx) = mpsc::channel(10);
+    let (disconnect_tx, mut rx) = mpsc::channel(10);
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_millis(20))
+        .ping_timeout(Duration::from_millis(20))
+        .heartbeat(false)
+        .heartbeat_sweep_interval(Duration::from_millis(20))
+        .build();
+    let handler = MyHandler {
+        connect_tx,
+        disconnect_tx,
+    };
+    create_server_with_config(handler, config, PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    let open = ws.next().await.unwrap().unwrap().into_text().unwrap();
+    let open: serde_json::Value = serde_json::from_str(open.strip_prefix('0').unwrap()).unwrap();
+    // The longest interval a javascript client can wait for
+    assert_eq!(open["pingInterval"], i32::MAX as u64 - 20);
+    assert_eq!(open["pingTimeout"], 20);
+
+    // No ping is sent during many ping intervals and the socket is kept open
+    let res = tokio::time::timeout(Duration::from_millis(500), ws.next()).await;
+    assert!(res.is_err(), "unexpected packet {res:?}");
+    rx.try_recv().unwrap_err();
+
+    let socket = connect_rx.recv().await.unwrap();
+    assert!(!socket.is_heartbeat_enabled());
+    assert_eq!(socket.last_ping_at(), None);
+    assert_eq!(socket.heartbeat_interval(), None);
+
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4hello".into())
+    );
+}
@@ -0,0 +1,103 @@
+//! Tests for the startup and shutdown hooks of the engine.io handler
+#![cfg(feature = "polling")]
+
+use std::sync::{Arc, Mutex};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::{EngineIoHandle, EngineIoHandler},
+    layer::EngineIoLayer,
+    socket::{DisconnectReason, Socket},
+};
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, create_ws_connection};
+use futures::StreamExt;
+use tokio_tungstenite::tungstenite::Message;
+
+/// A handler with a non-clonable state recording the lifecycle events
+#[derive(Debug, Default)]
+struct MyHandler {
+    events: Mutex<Vec<String>>,
+    io: Mutex<Option<EngineIoHandle<()>>>,
+}
+
+impl MyHandler {
+    fn push(&self, event: impl Into<String>) {
+        self.events.lock().unwrap().push(event.into());
+    }
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {
+        self.push("connect");
+    }
+    fn on_disconnect(&self, _: Arc<Socket<()>>, reason: DisconnectReason) {
+        self.push(format!("disconnect {reason:?}"));
+    }
+
+    fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+
+    fn on_start(&self, io: EngineIoHandle<()>) {
+        self.push("start");
+        *self.io.lock().unwrap() = Some(io);
+    }
+
+    async fn on_shutdown(&self) {
+        tokio::task::yield_now().await;
+        // The sockets are still open and can be notified
+        let io = self.io.lock().unwrap().clone().unwrap();
+        io.broadcast("bye".into()).unwrap();
+        self.push(format!("shutdown {}", io.sockets().len()));
+    }
+}
+
+#[tokio::test]
+pub async fn shutdown_hook_runs_before_closing_sockets() {
+    let handler = Arc::new(MyHandler::default());
+    let svc = create_server_with_config(handler.clone(), EngineIoConfig::default(), 3300).await;
+
+    let sid = create_polling_connection(3300).await;
+    let ws = create_ws_connection(3300).await;
+    while svc.handle().sockets().len() < 2 {
+        tokio::task::yield_now().await;
+    }
+    assert!(svc.handle().get_socket(sid.parse().unwrap()).is_some());
+
+    svc.shutdown().await;
+
+    assert_eq!(
+        *handler.events.lock().unwrap(),
+        [
+            "start",
+            "connect",
+            "connect",
+            "shutdown 2",
+            "disconnect ClosingServer",
+            "disconnect ClosingServer"
+        ]
+    );
+    assert!(svc.handle().sockets().is_empty());
+
+    // The message broadcasted during the shutdown is still delivered before the close frame
+    let msgs: Vec<_> = ws.map(|msg| msg.unwrap()).collect().await;
+    assert_eq!(msgs.len(), 3);
+    assert_eq!(msgs[1], Message::Text("4bye".into()));
+    assert!(msgs[2].is_close());
+}
+
+#[tokio::test]
+pub async fn layer_starts_once() {
+    let handler = Arc::new(MyHandler::default());
+    let layer = EngineIoLayer::new(handler.clone());
+    let _svc1 = tower::Layer::layer(&layer, ());
+    let _svc2 = tower::Layer::layer(&layer, ());
+    assert_eq!(*handler.events.lock().unwrap(), ["start"]);
+
+    layer.shutdown().await;
+    assert_eq!(*handler.events.lock().unwrap(), ["start", "shutdown 0"]);
+}
@@ -0,0 +1,101 @@
+//! Tests for the maximum size of the received text messages
+#![cfg(feature = "polling")]
+
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{protocol::frame::coding::CloseCode, Message};
+
+mod fixture;
+
+use fixture::{
+    create_polling_connection, create_server_with_config, create_ws_connection, send_req,
+};
+
+#[derive(Debug, Clone)]
+struct MyHandler(mpsc::Sender<String>);
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, reason: DisconnectReason) {
+        self.0.try_send(format!("disconnect: {reason:?}")).unwrap();
+    }
+    fn on_message(&self, msg: String, _: Arc<Socket<()>>) {
+        self.0.try_send(msg).unwrap();
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+/// 5 two-bytes chars, exactly at the limit
+const UNDER_LIMIT: &str = "ééééé";
+/// One byte over the limit
+const OVER_LIMIT: &str = "éééééa";
+
+fn config() -> EngineIoConfig {
+    EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .max_message_size(10)
+        .build()
+}
+
+async fn recv(rx: &mut mpsc::Receiver<String>) -> String {
+    let msg = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await;
+    msg.unwrap().unwrap()
+}
+
+#[tokio::test]
+pub async fn polling_message_size() {
+    const PORT: u16 = 3400;
+    let (tx, mut rx) = mpsc::channel(10);
+    create_server_with_config(MyHandler(tx), config(), PORT).await;
+
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+    let msg = |m: &str| Some(format!("4{m}"));
+
+    send_req(PORT, params(), http::Method::POST, msg(UNDER_LIMIT)).await;
+    assert_eq!(recv(&mut rx).await, UNDER_LIMIT);
+
+    send_req(PORT, params(), http::Method::POST, msg(OVER_LIMIT)).await;
+    assert_eq!(recv(&mut rx).await, "disconnect: PacketParsingError");
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+pub async fn websocket_message_size() {
+    const PORT: u16 = 3401;
+    let (tx, mut rx) = mpsc::channel(10);
+    create_server_with_config(MyHandler(tx), config(), PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // open packet
+
+    ws.send(Message::Text(format!("4{UNDER_LIMIT}")))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut rx).await, UNDER_LIMIT);
+
+    ws.send(Message::Text(format!("4{OVER_LIMIT}")))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut rx).await, "disconnect: PacketParsingError");
+
+    let msg = tokio::time::timeout(Duration::from_millis(500), ws.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    match msg {
+        Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Policy),
+        msg => panic!("expected a close frame, got {msg:?}"),
+    }
+}
@@ -0,0 +1,76 @@
+//! Tests for the policies applied when the buffer of a socket is full
+#![cfg(feature = "polling")]
+
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::{EngineIoConfig, OverflowPolicy},
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use tokio::sync::mpsc;
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, send_req};
+
+/// Emits 5 messages when a message is received and reports the emit results and the dropped packets
+#[derive(Debug, Clone)]
+struct MyHandler(mpsc::Sender<(usize, u64)>);
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, socket: Arc<Socket<()>>) {
+        let sent = (1..=5)
+            .filter(|i| socket.emit(i.to_string()).is_ok())
+            .count();
+        self.0.try_send((sent, socket.dropped_packets())).unwrap();
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+async fn emit_and_poll(policy: OverflowPolicy, port: u16) -> ((usize, u64), String) {
+    let (tx, mut rx) = mpsc::channel(1);
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .max_buffer_size(2)
+        .overflow_policy(policy)
+        .build();
+    create_server_with_config(MyHandler(tx), config, port).await;
+
+    let sid = create_polling_connection(port).await;
+    let params = || format!("transport=polling&sid={sid}");
+    send_req(port, params(), http::Method::POST, Some("4start".into())).await;
+    let res = tokio::time::timeout(Duration::from_millis(500), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let payload = send_req(port, params(), http::Method::GET, None).await;
+    (res, payload)
+}
+
+#[tokio::test]
+pub async fn reject_policy() {
+    let (res, payload) = emit_and_poll(OverflowPolicy::Reject, 3600).await;
+    assert_eq!(res, (2, 0));
+    // The first char of the payload is stripped by `send_req`
+    assert_eq!(payload, "1\x1e42");
+}
+
+#[tokio::test]
+pub async fn drop_oldest_policy() {
+    let (res, payload) = emit_and_poll(OverflowPolicy::DropOldest, 3601).await;
+    assert_eq!(res, (5, 3));
+    assert_eq!(payload, "4\x1e45");
+}
+
+#[tokio::test]
+pub async fn drop_newest_policy() {
+    let (res, payload) = emit_and_poll(OverflowPolicy::DropNewest, 3602).await;
+    assert_eq!(res, (5, 3));
+    assert_eq!(payload, "1\x1e42");
+}
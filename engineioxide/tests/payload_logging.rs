@@ -0,0 +1,150 @@
+//! Tests for the redaction of the payloads and the correlation ids in the tracing events
+#![cfg(all(feature = "polling", feature = "tracing"))]
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use engineioxide::{
+    config::{EngineIoConfig, PayloadLogging},
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use tokio::sync::mpsc;
+use tracing_subscriber::fmt::MakeWriter;
+
+mod fixture;
+
+use fixture::{create_server_with_config, send_raw_req, send_req};
+
+/// Echoes the messages and reports the correlation id of the connected sockets
+#[derive(Debug, Clone)]
+struct EchoHandler(mpsc::UnboundedSender<String>);
+
+impl EngineIoHandler for EchoHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<Self::Data>>) {
+        self.0.send(socket.correlation_id().to_string()).unwrap();
+    }
+    fn on_disconnect(&self, _: Arc<Socket<Self::Data>>, _: DisconnectReason) {}
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<Self::Data>>) {
+        socket.emit(msg).unwrap();
+    }
+
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<Self::Data>>) {
+        socket.emit_binary(data).unwrap();
+    }
+}
+
+/// Captures the formatted tracing events
+#[derive(Debug, Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl Logs {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl io::Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Logs {
+    type Writer = Logs;
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Opens a polling session with the given request headers, exchanges a message and returns the captured logs.
+///
+/// The runtime is single-threaded so that the server tasks record their events in the test subscriber.
+async fn exchange(
+    port: u16,
+    payload_logging: PayloadLogging,
+    headers: &[(&str, &str)],
+) -> (String, String) {
+    let logs = Logs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .with_writer(logs.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .payload_logging(payload_logging)
+        .correlation_id_header(http::HeaderName::from_static("x-request-id"))
+        .build();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    create_server_with_config(EchoHandler(tx), config, port).await;
+
+    let (_, _, body) = send_raw_req(
+        port,
+        "transport=polling".into(),
+        http::Method::GET,
+        headers,
+        vec![],
+    )
+    .await;
+    let body = String::from_utf8(body).unwrap();
+    let open: serde_json::Value = serde_json::from_str(body.strip_prefix('0').unwrap()).unwrap();
+    let sid = open["sid"].as_str().unwrap().to_string();
+    let correlation_id = rx.recv().await.unwrap();
+
+    let params = format!("transport=polling&sid={sid}");
+    send_req(
+        port,
+        params.clone(),
+        http::Method::POST,
+        Some("4secret-payload\x1ebc2VjcmV0LWJpbmFyeQ==".into()),
+    )
+    .await;
+    let res = send_req(port, params, http::Method::GET, None).await;
+    assert!(res.contains("secret-payload"));
+
+    (correlation_id, logs.contents())
+}
+
+#[tokio::test(flavor = "current_thread")]
+pub async fn payload_logging_off() {
+    const PORT: u16 = 3902;
+    let (correlation_id, logs) =
+        exchange(PORT, PayloadLogging::Off, &[("x-request-id", "req-42")]).await;
+
+    assert_eq!(correlation_id, "req-42");
+    assert!(
+        logs.contains("sending packet: Message(<14 bytes>)"),
+        "{logs}"
+    );
+    assert!(logs.contains("correlation_id=req-42"), "{logs}");
+    assert!(!logs.contains("secret-payload"), "{logs}");
+    assert!(!logs.contains("c2VjcmV0LWJpbmFyeQ"), "{logs}");
+}
+
+#[tokio::test(flavor = "current_thread")]
+pub async fn payload_logging_full() {
+    const PORT: u16 = 3903;
+    let (correlation_id, logs) = exchange(PORT, PayloadLogging::Full, &[]).await;
+
+    // Without the header a random correlation id is generated
+    assert_eq!(correlation_id.len(), 16);
+    assert!(logs.contains("secret-payload"), "{logs}");
+    assert!(
+        logs.contains(&format!("correlation_id={correlation_id}")),
+        "{logs}"
+    );
+}
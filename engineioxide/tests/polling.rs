@@ -0,0 +1,384 @@
+//! Tests for the polling transport
+#![cfg(feature = "polling")]
+
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, send_req, send_req_with_path};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        println!("socket connect {}", socket.id);
+    }
+    fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) {
+        println!("socket disconnect {}: {:?}", socket.id, reason);
+    }
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+#[tokio::test]
+pub async fn polling_duration_releases_request() {
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .polling_duration(Duration::from_millis(100))
+        .build();
+    create_server_with_config(MyHandler, config, 3000).await;
+
+    let sid = create_polling_connection(3000).await;
+    let poll = send_req(
+        3000,
+        format!("transport=polling&sid={sid}"),
+        http::Method::GET,
+        None,
+    );
+    // Without the polling duration, the request would be held until the next ping (10s)
+    let body = tokio::time::timeout(Duration::from_millis(500), poll)
+        .await
+        .expect("polling request should be released after the polling duration");
+
+    // The fixture strips the packet type, a noop packet ("6") has no data
+    assert_eq!(body, "");
+}
+
+#[tokio::test]
+pub async fn upgrade_timeout_keeps_polling() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    const PORT: u16 = 3001;
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .upgrade_timeout(Duration::from_millis(100))
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let sid = create_polling_connection(PORT).await;
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={sid}"
+    ))
+    .await
+    .unwrap();
+
+    // Send the probe but never send the upgrade packet
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("3probe".into())
+    );
+
+    // The websocket attempt should be abandoned after the upgrade timeout
+    let closed = tokio::time::timeout(Duration::from_millis(500), async {
+        while let Some(Ok(_)) = ws.next().await {}
+    })
+    .await;
+    assert!(
+        closed.is_ok(),
+        "websocket should be closed after the upgrade timeout"
+    );
+
+    // The session is still usable with polling, the noop packet releasing the polling requests
+    // on probe is not queued so the next poll directly gets the data
+    let params = || format!("transport=polling&sid={sid}");
+    send_req(PORT, params(), http::Method::POST, Some("4hello".into())).await;
+    assert_eq!(
+        send_req(PORT, params(), http::Method::GET, None).await,
+        "hello"
+    );
+}
+
+#[tokio::test]
+pub async fn packets_buffered_during_upgrade() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    const PORT: u16 = 3004;
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+    let poll = tokio::spawn(send_req(PORT, params(), http::Method::GET, None));
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={sid}"
+    ))
+    .await
+    .unwrap();
+
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("3probe".into())
+    );
+    // The pending polling request is released with a noop packet
+    assert_eq!(poll.await.unwrap(), "");
+
+    // The messages emitted during the probe window are not sent to the polling transport
+    send_req(
+        PORT,
+        params(),
+        http::Method::POST,
+        Some("4a\x1e4b\x1e4c".into()),
+    )
+    .await;
+    let res = tokio::time::timeout(
+        Duration::from_millis(200),
+        send_req(PORT, params(), http::Method::GET, None),
+    )
+    .await
+    .expect("polling request should be released during the upgrade");
+    assert_eq!(res, "");
+
+    // They are flushed in order once the websocket is active
+    ws.send(Message::Text("5".into())).await.unwrap();
+    for msg in ["4a", "4b", "4c"] {
+        let res = tokio::time::timeout(Duration::from_millis(200), ws.next())
+            .await
+            .expect("timeout waiting for the buffered messages")
+            .unwrap()
+            .unwrap();
+        assert_eq!(res, Message::Text(msg.into()));
+    }
+}
+
+#[tokio::test]
+pub async fn upgrade_releases_parked_poll() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    const PORT: u16 = 3006;
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .polling_duration(Duration::from_secs(10))
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+
+    // Noop packets sent by the client are ignored
+    let res = send_req(PORT, params(), http::Method::POST, Some("6".into())).await;
+    assert_eq!(res, "k");
+
+    let poll = tokio::spawn(send_req(PORT, params(), http::Method::GET, None));
+    // Let the polling request park on the socket
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={sid}"
+    ))
+    .await
+    .unwrap();
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("3probe".into())
+    );
+
+    // The parked request is released right away rather than after the polling duration
+    let res = tokio::time::timeout(Duration::from_millis(200), poll)
+        .await
+        .expect("the polling request should be released by the upgrade");
+    assert_eq!(res.unwrap(), "");
+
+    // The noop packet is not queued, so it is not sent on the websocket after the upgrade
+    ws.send(Message::Text("5".into())).await.unwrap();
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    let msg = tokio::time::timeout(Duration::from_millis(200), ws.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(msg, Message::Text("4hello".into()));
+}
+
+#[derive(Debug, Default)]
+struct UpgradeHandler {
+    upgrades: std::sync::atomic::AtomicUsize,
+}
+
+impl EngineIoHandler for UpgradeHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+
+    fn on_upgrade(&self, _: Arc<Socket<()>>) {
+        self.upgrades
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+pub async fn concurrent_duplicate_upgrades() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    const PORT: u16 = 3005;
+    let handler = Arc::new(UpgradeHandler::default());
+    create_server_with_config(handler.clone(), EngineIoConfig::default(), PORT).await;
+    let sid = create_polling_connection(PORT).await;
+
+    // Returns true if the websocket connection was upgraded
+    let upgrade = |sid: String| async move {
+        // The upgrade of an upgraded session is rejected before the websocket handshake
+        let Ok((mut ws, _)) = tokio_tungstenite::connect_async(format!(
+            "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={sid}"
+        ))
+        .await
+        else {
+            return false;
+        };
+        ws.send(Message::Text("2probe".into())).await.unwrap();
+        match ws.next().await {
+            Some(Ok(Message::Text(msg))) if msg == "3probe" => (),
+            _ => return false,
+        }
+        ws.send(Message::Text("5".into())).await.unwrap();
+        // The second connection is closed once the first one is upgraded
+        let res = tokio::time::timeout(Duration::from_millis(200), ws.next()).await;
+        let upgraded = !matches!(res, Ok(None | Some(Err(_)) | Some(Ok(Message::Close(_)))));
+        // Keep the connection open until both attempts are done
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        upgraded
+    };
+    let (a, b) = tokio::join!(
+        tokio::spawn(upgrade(sid.clone())),
+        tokio::spawn(upgrade(sid.clone()))
+    );
+    assert!(
+        a.unwrap() ^ b.unwrap(),
+        "exactly one upgrade should succeed"
+    );
+    assert_eq!(
+        handler.upgrades.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+
+    // A later upgrade of the websocket session is rejected too
+    assert!(!upgrade(sid).await);
+    assert_eq!(
+        handler.upgrades.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+}
+
+#[tokio::test]
+pub async fn empty_message_dispatched() {
+    const PORT: u16 = 3007;
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+
+    // The handler echoes the messages, including the empty one
+    send_req(PORT, params(), http::Method::POST, Some("4\x1e4a".into())).await;
+    // The first char of the payload is stripped by `send_req`
+    let res = send_req(PORT, params(), http::Method::GET, None).await;
+    assert_eq!(res, "\x1e4a");
+}
+
+#[tokio::test]
+pub async fn custom_req_path() {
+    let config = EngineIoConfig::builder()
+        .req_path("myapp/engine.io/")
+        .build();
+    create_server_with_config(MyHandler, config, 3002).await;
+
+    let open = |path: &'static str| {
+        send_req_with_path(
+            3002,
+            path,
+            "transport=polling".into(),
+            http::Method::GET,
+            None,
+        )
+    };
+    assert!(open("/myapp/engine.io/").await.contains("\"sid\""));
+    assert!(open("/myapp/engine.io").await.contains("\"sid\""));
+
+    // Requests outside of the configured path are passed to the inner (not found) service
+    assert_eq!(open("/engine.io/").await, "");
+    assert_eq!(open("/myapp/engine.iofoo/").await, "");
+}
+
+#[tokio::test]
+pub async fn send_and_flush_resolves_with_poll_response() {
+    use engineioxide::socket::FlushError;
+    use tokio::sync::mpsc;
+
+    #[derive(Debug, Clone)]
+    struct FlushHandler(mpsc::Sender<Result<(), FlushError>>);
+    impl EngineIoHandler for FlushHandler {
+        type Data = ();
+        fn on_connect(&self, socket: Arc<Socket<()>>) {
+            let tx = self.0.clone();
+            tokio::spawn(async move {
+                let res = socket.send_and_flush("hello".into()).await;
+                tx.send(res).await.unwrap();
+                let res = socket.send_and_flush("never polled".into()).await;
+                tx.send(res).await.unwrap();
+            });
+        }
+        fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+        fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+        fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+    }
+
+    const PORT: u16 = 3003;
+    let (tx, mut rx) = mpsc::channel(10);
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .build();
+    create_server_with_config(FlushHandler(tx), config, PORT).await;
+
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+
+    // The message is only buffered until the client polls it
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(rx.try_recv().is_err());
+
+    assert_eq!(
+        send_req(PORT, params(), http::Method::GET, None).await,
+        "hello"
+    );
+    assert!(rx.recv().await.unwrap().is_ok());
+
+    // The session is closed before the second message is polled
+    send_req(PORT, params(), http::Method::POST, Some("1".into())).await;
+    let res = tokio::time::timeout(Duration::from_millis(500), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(res, Err(FlushError::NotFlushed)));
+}
@@ -0,0 +1,98 @@
+//! Tests for the coalescing of the packets sent to a parked polling request
+#![cfg(feature = "polling")]
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, send_req};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        match msg.as_str() {
+            // Packets are emitted continuously, for longer than the coalescing delay
+            "burst" => {
+                tokio::spawn(async move {
+                    for i in 0..200 {
+                        if socket.emit(i.to_string()).is_err() {
+                            break;
+                        }
+                        tokio::time::sleep(Duration::from_millis(1)).await;
+                    }
+                });
+            }
+            // The socket is closed while the emitted packet is waiting in the coalescing delay
+            "close" => {
+                socket.emit("bye".into()).unwrap();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    socket.close(DisconnectReason::TransportClose);
+                });
+            }
+            _ => (),
+        }
+    }
+
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+/// Parks a polling request, sends the message and returns the response with its duration
+/// (the first char of the response is skipped by `send_req`)
+async fn poll_after_message(port: u16, sid: &str, msg: &str) -> (String, Duration) {
+    let params = format!("transport=polling&sid={sid}");
+    let poll = tokio::spawn(send_req(port, params.clone(), http::Method::GET, None));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let start = Instant::now();
+    send_req(port, params, http::Method::POST, Some(format!("4{msg}"))).await;
+    let res = poll.await.unwrap();
+    (res, start.elapsed())
+}
+
+#[tokio::test]
+pub async fn polling_coalesce_delay() {
+    const PORT: u16 = 4024;
+    let config = EngineIoConfig::builder()
+        .polling_coalesce_delay(Duration::from_millis(50))
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+    let sid = create_polling_connection(PORT).await;
+
+    let (res, elapsed) = poll_after_message(PORT, &sid, "burst").await;
+    let packets: Vec<&str> = res.split('\x1e').collect();
+    assert!(packets.len() > 1, "packets were not coalesced: {res}");
+    assert_eq!(packets[0], "0");
+    assert_eq!(packets[1], "41");
+    // The response is not delayed beyond the bound even though the emits continue
+    assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(150), "{elapsed:?}");
+}
+
+#[tokio::test]
+pub async fn polling_coalesce_close_is_sent_right_away() {
+    const PORT: u16 = 4025;
+    let config = EngineIoConfig::builder()
+        .polling_coalesce_delay(Duration::from_secs(2))
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+    let sid = create_polling_connection(PORT).await;
+
+    let (res, elapsed) = poll_after_message(PORT, &sid, "close").await;
+    assert_eq!(res, "bye\x1e1");
+    assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+}
@@ -0,0 +1,81 @@
+//! Tests for the control packets sent ahead of a backlog of messages
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_server_with_config, create_ws_connection};
+
+const FLOOD_LEN: usize = 1000;
+
+/// Floods the socket with messages when `flood` is received, then closes it if `flood+close` is received
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        for i in 0..FLOOD_LEN {
+            socket.emit(format!("{i:0>100}")).unwrap();
+        }
+        if msg == "flood+close" {
+            socket.close(DisconnectReason::ClosingServer);
+        }
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+fn config() -> EngineIoConfig {
+    EngineIoConfig::builder()
+        .ping_interval(Duration::from_millis(50))
+        .ping_timeout(Duration::from_secs(1))
+        .max_buffer_size(FLOOD_LEN * 2)
+        .max_payload(1000)
+        .build()
+}
+
+#[tokio::test]
+pub async fn close_is_sent_before_the_backlog() {
+    const PORT: u16 = 4010;
+    create_server_with_config(MyHandler, config(), PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Text("4flood+close".into())).await.unwrap();
+
+    let msg = tokio::time::timeout(Duration::from_millis(200), ws.next())
+        .await
+        .expect("the close frame should be sent right away")
+        .unwrap()
+        .unwrap();
+    assert!(matches!(msg, Message::Close(_)), "{msg:?}");
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn ping_is_sent_before_the_backlog() {
+    const PORT: u16 = 4011;
+    create_server_with_config(MyHandler, config(), PORT).await;
+
+    let sid = fixture::create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+    let body = b"4flood".to_vec();
+    fixture::send_raw_req(PORT, params(), http::Method::POST, &[], body).await;
+
+    // The ping is buffered after the messages but it is the first packet of the next payload
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let (_, _, payload) =
+        fixture::send_raw_req(PORT, params(), http::Method::GET, &[], vec![]).await;
+    let payload = String::from_utf8(payload).unwrap();
+    assert!(payload.starts_with("2\x1e4"), "{payload:.20}");
+}
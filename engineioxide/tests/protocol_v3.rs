@@ -0,0 +1,93 @@
+//! Tests for the engine.io protocol v3: handshake, heartbeat and the `allowed_versions` opt-in
+#![cfg(all(feature = "v3", feature = "polling"))]
+use std::{collections::VecDeque, sync::Arc};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    service::ProtocolVersion,
+    socket::{DisconnectReason, Socket},
+};
+use http::Request;
+use http_body_util::{BodyExt, Full};
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+
+mod fixture;
+
+use fixture::{create_server, create_server_with_config};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+/// Sends an engine.io v3 polling request and returns the status and the body of the response
+async fn v3_req(port: u16, params: String, method: http::Method, body: &str) -> (u16, String) {
+    let req = Request::builder()
+        .method(method)
+        .uri(format!("http://127.0.0.1:{port}/engine.io/?EIO=3&{params}"))
+        .body(Full::new(VecDeque::from(body.as_bytes().to_vec())))
+        .unwrap();
+    let mut res = Client::builder(TokioExecutor::new())
+        .build_http()
+        .request(req)
+        .await
+        .unwrap();
+    let body = res.body_mut().collect().await.unwrap().to_bytes();
+    (
+        res.status().as_u16(),
+        String::from_utf8(body.to_vec()).unwrap(),
+    )
+}
+
+#[tokio::test]
+pub async fn v3_handshake_and_heartbeat() {
+    const PORT: u16 = 4033;
+    create_server(MyHandler, PORT).await;
+
+    // The v3 open packet is framed as `<length>:0{...}`, without maxPayload
+    let (status, open) = v3_req(
+        PORT,
+        "transport=polling&b64=1".into(),
+        http::Method::GET,
+        "",
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (len, packet) = open.split_once(':').unwrap();
+    assert_eq!(len.parse::<usize>().unwrap(), packet.chars().count());
+    let open: serde_json::Value = serde_json::from_str(&packet[1..]).unwrap();
+    assert!(open.get("maxPayload").is_none());
+    assert_eq!(open["pingInterval"], 300);
+    let sid = open["sid"].as_str().unwrap();
+
+    // The client pings, the server answers with a pong
+    let params = || format!("transport=polling&b64=1&sid={sid}");
+    let (status, _) = v3_req(PORT, params(), http::Method::POST, "1:2").await;
+    assert_eq!(status, 200);
+    let (_, res) = v3_req(PORT, params(), http::Method::GET, "").await;
+    assert_eq!(res, "1:3");
+}
+
+#[tokio::test]
+pub async fn v3_rejected_when_not_allowed() {
+    const PORT: u16 = 4034;
+    let config = EngineIoConfig::builder()
+        .allowed_versions([ProtocolVersion::V4])
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let (status, body) = v3_req(PORT, "transport=polling".into(), http::Method::GET, "").await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        body,
+        "{\"code\":\"5\",\"message\":\"Unsupported protocol version\"}"
+    );
+}
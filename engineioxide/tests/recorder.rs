@@ -0,0 +1,130 @@
+//! Tests for the session capture of the sockets, see [`engineioxide::recorder`]
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    packet::Packet,
+    recorder::{Direction, MemoryRecorder, SessionRecording},
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_server_with_config, create_ws_connection};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+fn config(recording: SessionRecording) -> EngineIoConfig {
+    EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .session_recording(recording)
+        .build()
+}
+
+#[tokio::test]
+pub async fn sampled_session_is_recorded() {
+    const PORT: u16 = 4015;
+    let recorder = Arc::new(MemoryRecorder::new());
+    let recording = SessionRecording::new(recorder.clone());
+    create_server_with_config(MyHandler, config(recording), PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    ws.next().await.unwrap().unwrap();
+    ws.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+    ws.next().await.unwrap().unwrap();
+
+    let records = recorder.take();
+    let sid = records[0].sid;
+    assert!(records.iter().all(|r| r.sid == sid));
+    assert!(records.windows(2).all(|w| w[0].at <= w[1].at));
+    let records: Vec<_> = records
+        .into_iter()
+        .map(|r| (r.direction, r.packet))
+        .collect();
+    assert!(matches!(records[0], (Direction::Outbound, Packet::Open(_))));
+    assert_eq!(
+        records[1..],
+        [
+            (Direction::Inbound, Packet::Message("hello".into())),
+            (Direction::Outbound, Packet::Message("hello".into())),
+            (Direction::Inbound, Packet::Binary(vec![1, 2, 3])),
+            (Direction::Outbound, Packet::Binary(vec![1, 2, 3])),
+        ]
+    );
+}
+
+#[tokio::test]
+pub async fn unsampled_session_is_not_recorded() {
+    const PORT: u16 = 4016;
+    let recorder = Arc::new(MemoryRecorder::new());
+    let recording = SessionRecording::new(recorder.clone()).sample_rate(0.0);
+    create_server_with_config(MyHandler, config(recording), PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap();
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    ws.next().await.unwrap().unwrap();
+
+    assert!(recorder.packets().is_empty());
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+pub fn runtime_recording_and_replay() {
+    use engineioxide::socket::test_socket;
+
+    let recorder = Arc::new(MemoryRecorder::new());
+    let (socket, handle) = test_socket(MyHandler);
+    assert!(!socket.is_recording());
+
+    handle.push(Packet::Message("ignored".into())).unwrap();
+    socket.start_recording(recorder.clone());
+    assert!(socket.is_recording());
+    handle.push(Packet::Message("first".into())).unwrap();
+    handle.push(Packet::Message("second".into())).unwrap();
+    socket.stop_recording();
+    handle.push(Packet::Message("ignored".into())).unwrap();
+    handle.drain();
+
+    let records = recorder.take();
+    let directions: Vec<_> = records.iter().map(|r| r.direction).collect();
+    assert_eq!(
+        directions,
+        [
+            Direction::Inbound,
+            Direction::Outbound,
+            Direction::Inbound,
+            Direction::Outbound
+        ]
+    );
+
+    // Only the inbound packets are fed to the new socket, which reproduces the outbound ones
+    let (_, handle) = test_socket(MyHandler);
+    handle.replay(records).unwrap();
+    assert_eq!(
+        handle.drain(),
+        [
+            Packet::Message("first".into()),
+            Packet::Message("second".into())
+        ]
+    );
+}
@@ -0,0 +1,102 @@
+//! Tests for the binary packets acknowledged by the client, see [`engineioxide::reliable`]
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    reliable::{decode_data, encode_ack, ReliableError},
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_server_with_config, create_ws_connection};
+
+/// Sends a reliable packet when a message is received, and reports the result with a message.
+/// The other binary packets are echoed.
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        tokio::spawn(async move {
+            let res = socket
+                .emit_binary_reliable(msg.into(), Duration::from_millis(50))
+                .await;
+            let res = match res {
+                Ok(()) => "ok",
+                Err(ReliableError::Timeout) => "timeout",
+                Err(_) => "error",
+            };
+            socket.emit(res.to_string()).ok();
+        });
+    }
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+fn config() -> EngineIoConfig {
+    EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .build()
+}
+
+#[tokio::test]
+pub async fn acknowledged_packet() {
+    const PORT: u16 = 4017;
+    create_server_with_config(MyHandler, config(), PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+
+    let msg = ws.next().await.unwrap().unwrap().into_data();
+    let (seq, data) = decode_data(&msg).unwrap();
+    assert_eq!(data, b"hello");
+    ws.send(Message::Binary(encode_ack(seq))).await.unwrap();
+
+    // The ack is consumed by the socket, it is not echoed by the handler
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4ok".into())
+    );
+    ws.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Binary(vec![1, 2, 3])
+    );
+}
+
+#[tokio::test]
+pub async fn unacknowledged_packet_is_sent_twice() {
+    const PORT: u16 = 4018;
+    create_server_with_config(MyHandler, config(), PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap();
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+
+    let first = ws.next().await.unwrap().unwrap();
+    let second = ws.next().await.unwrap().unwrap();
+    assert_eq!(first, second);
+    assert_eq!(decode_data(&first.into_data()).unwrap().1, b"hello");
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4timeout".into())
+    );
+
+    // The late ack is not pending anymore, it is passed to the handler
+    let (seq, _) = decode_data(&second.into_data()).unwrap();
+    ws.send(Message::Binary(encode_ack(seq))).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Binary(encode_ack(seq))
+    );
+}
@@ -0,0 +1,207 @@
+//! Tests for the session store shared between several servers
+#![cfg(feature = "polling")]
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    service::ProtocolVersion,
+    session::{SessionMetadata, SessionStore},
+    sid::Sid,
+    socket::{DisconnectReason, Socket},
+    TransportType,
+};
+use futures::future::BoxFuture;
+
+mod fixture;
+
+use fixture::{create_polling_connection, create_server_with_config, send_req};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        println!("socket connect {}", socket.id);
+    }
+    fn on_disconnect(&self, socket: Arc<Socket<()>>, reason: DisconnectReason) {
+        println!("socket disconnect {}: {:?}", socket.id, reason);
+    }
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+/// A minimal in-memory stand-in for a redis server, with the `SET EX`, `GET` and `DEL` commands
+#[derive(Debug, Clone, Default)]
+struct MockRedis(Arc<Mutex<HashMap<String, (String, Instant)>>>);
+
+impl MockRedis {
+    async fn set_ex(&self, key: String, value: String, ttl: Duration) {
+        let expiry = Instant::now() + ttl;
+        self.0.lock().unwrap().insert(key, (value, expiry));
+    }
+    async fn get(&self, key: String) -> Option<String> {
+        let mut map = self.0.lock().unwrap();
+        match map.get(&key) {
+            Some((_, expiry)) if *expiry < Instant::now() => {
+                map.remove(&key);
+                None
+            }
+            Some((value, _)) => Some(value.clone()),
+            None => None,
+        }
+    }
+    async fn del(&self, key: String) {
+        self.0.lock().unwrap().remove(&key);
+    }
+}
+
+/// An example of a redis backed session store.
+/// Sessions are stored under the `eio:session:<sid>` key as `<transport>:<protocol>`
+/// and expire if they are not refreshed.
+#[derive(Debug)]
+struct RedisSessionStore {
+    redis: MockRedis,
+    ttl: Duration,
+}
+
+impl RedisSessionStore {
+    fn key(sid: Sid) -> String {
+        format!("eio:session:{sid}")
+    }
+}
+
+impl SessionStore for RedisSessionStore {
+    fn exists(&self, sid: Sid) -> BoxFuture<'static, bool> {
+        let redis = self.redis.clone();
+        Box::pin(async move { redis.get(Self::key(sid)).await.is_some() })
+    }
+
+    fn touch(&self, sid: Sid, metadata: SessionMetadata) -> BoxFuture<'static, ()> {
+        let redis = self.redis.clone();
+        let transport = match metadata.transport {
+            TransportType::Polling => "polling",
+            TransportType::Websocket => "websocket",
+        };
+        let protocol = match metadata.protocol {
+            ProtocolVersion::V3 => 3,
+            ProtocolVersion::V4 => 4,
+        };
+        let value = format!("{transport}:{protocol}");
+        let ttl = self.ttl;
+        Box::pin(async move { redis.set_ex(Self::key(sid), value, ttl).await })
+    }
+
+    fn remove(&self, sid: Sid) -> BoxFuture<'static, ()> {
+        let redis = self.redis.clone();
+        Box::pin(async move { redis.del(Self::key(sid)).await })
+    }
+
+    fn metadata(&self, sid: Sid) -> BoxFuture<'static, Option<SessionMetadata>> {
+        let redis = self.redis.clone();
+        Box::pin(async move {
+            let value = redis.get(Self::key(sid)).await?;
+            let (transport, protocol) = value.split_once(':')?;
+            let transport = match transport {
+                "polling" => TransportType::Polling,
+                "websocket" => TransportType::Websocket,
+                _ => return None,
+            };
+            let protocol = match protocol {
+                "3" => ProtocolVersion::V3,
+                "4" => ProtocolVersion::V4,
+                _ => return None,
+            };
+            Some(SessionMetadata {
+                transport,
+                protocol,
+            })
+        })
+    }
+}
+
+fn config(redis: &MockRedis) -> EngineIoConfig {
+    EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(10))
+        .session_store(RedisSessionStore {
+            redis: redis.clone(),
+            ttl: Duration::from_secs(20),
+        })
+        .build()
+}
+
+// The fixture strips the first char of the response body
+const UNKNOWN_SID: &str = r#""code":"1","message":"Session ID unknown"}"#;
+
+#[tokio::test]
+pub async fn foreign_session_is_closed_cleanly() {
+    let redis = MockRedis::default();
+    create_server_with_config(MyHandler, config(&redis), 3200).await;
+    create_server_with_config(MyHandler, config(&redis), 3201).await;
+
+    let sid = create_polling_connection(3200).await;
+    let store = RedisSessionStore {
+        redis: redis.clone(),
+        ttl: Duration::from_secs(20),
+    };
+    let metadata = store.metadata(sid.parse().unwrap()).await.unwrap();
+    assert_eq!(metadata.transport, TransportType::Polling);
+    assert_eq!(metadata.protocol, ProtocolVersion::V4);
+
+    // A polling request landing on the other server gets a close packet ("1")
+    let params = format!("transport=polling&sid={sid}");
+    let body = send_req(3201, params.clone(), http::Method::GET, None).await;
+    assert_eq!(body, "");
+
+    // A post request landing on the other server gets a recoverable error
+    let body = send_req(
+        3201,
+        params.clone(),
+        http::Method::POST,
+        Some("4hello".into()),
+    )
+    .await;
+    assert_eq!(body, UNKNOWN_SID);
+
+    // The owning server still handles the session
+    send_req(
+        3200,
+        params.clone(),
+        http::Method::POST,
+        Some("4hello".into()),
+    )
+    .await;
+    let body = send_req(3200, params.clone(), http::Method::GET, None).await;
+    assert_eq!(body, "hello");
+
+    // Once closed, the session is removed from the store and is unknown everywhere
+    send_req(3200, params.clone(), http::Method::POST, Some("1".into())).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!store.exists(sid.parse().unwrap()).await);
+    let body = send_req(3201, params, http::Method::GET, None).await;
+    assert_eq!(body, UNKNOWN_SID);
+}
+
+#[tokio::test]
+pub async fn unknown_session_is_rejected() {
+    let redis = MockRedis::default();
+    create_server_with_config(MyHandler, config(&redis), 3202).await;
+
+    let params = format!("transport=polling&sid={}", Sid::new());
+    let body = send_req(3202, params, http::Method::GET, None).await;
+    assert_eq!(body, UNKNOWN_SID);
+}
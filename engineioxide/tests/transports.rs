@@ -0,0 +1,71 @@
+//! Tests for the servers with a single transport enabled
+use std::sync::Arc;
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+    TransportType,
+};
+use futures::StreamExt;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+#[cfg(feature = "polling")]
+use fixture::send_req;
+use fixture::{create_server_with_config, create_ws_connection, send_req_headers};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+    fn on_message(&self, _msg: String, _socket: Arc<Socket<()>>) {}
+    fn on_binary(&self, _data: Vec<u8>, _socket: Arc<Socket<()>>) {}
+}
+
+#[tokio::test]
+pub async fn websocket_only() {
+    const PORT: u16 = 3113;
+    let config = EngineIoConfig::builder()
+        .transports([TransportType::Websocket])
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let (status, _) =
+        send_req_headers(PORT, "transport=polling".into(), http::Method::GET, None).await;
+    assert_eq!(status, 400);
+
+    let mut ws = create_ws_connection(PORT).await;
+    let Message::Text(open) = ws.next().await.unwrap().unwrap() else {
+        panic!("expected an open packet");
+    };
+    let open: Value = serde_json::from_str(&open[1..]).unwrap();
+    assert_eq!(open["upgrades"], serde_json::json!([]));
+}
+
+#[cfg(feature = "polling")]
+#[tokio::test]
+pub async fn polling_only() {
+    const PORT: u16 = 3114;
+    let config = EngineIoConfig::builder()
+        .transports([TransportType::Polling])
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    // The websocket upgrade is not advertised
+    let body = send_req(PORT, "transport=polling".into(), http::Method::GET, None).await;
+    let open: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(open["upgrades"], serde_json::json!([]));
+
+    let ws = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket"
+    ))
+    .await;
+    assert!(ws.is_err());
+}
@@ -0,0 +1,147 @@
+//! Tests for the validation of the websocket upgrades of polling sessions
+#![cfg(feature = "polling")]
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::{EngineIoConfig, UpgradeOrigin},
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use http::HeaderName;
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error, Message};
+
+mod fixture;
+
+use fixture::{create_server_with_config, send_raw_req};
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+const CLIENT_HEADER: &str = "x-client-id";
+
+fn config() -> EngineIoConfig {
+    EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .strict_upgrade_origin(UpgradeOrigin::Header(HeaderName::from_static(
+            CLIENT_HEADER,
+        )))
+        .build()
+}
+
+/// Opens a polling session with the given client header and returns its sid
+async fn polling_handshake(port: u16, client: &str) -> String {
+    let params = "transport=polling".to_string();
+    let headers = [(CLIENT_HEADER, client)];
+    let (_, _, body) = send_raw_req(port, params, http::Method::GET, &headers, vec![]).await;
+    let open: serde_json::Value = serde_json::from_slice(&body[1..]).unwrap();
+    open["sid"].as_str().unwrap().to_string()
+}
+
+/// Sends a message on the polling session and returns the echoed payload
+async fn polling_echo(port: u16, sid: &str, msg: &str) -> String {
+    let params = || format!("transport=polling&sid={sid}");
+    let body = format!("4{msg}").into_bytes();
+    send_raw_req(port, params(), http::Method::POST, &[], body).await;
+    let (_, _, body) = send_raw_req(port, params(), http::Method::GET, &[], vec![]).await;
+    String::from_utf8(body).unwrap()
+}
+
+/// Upgrades the session with the given client header, returns the status of the rejected upgrades
+async fn upgrade(port: u16, sid: &str, client: Option<&str>) -> Result<(), http::StatusCode> {
+    let url = format!("ws://127.0.0.1:{port}/engine.io/?EIO=4&transport=websocket&sid={sid}");
+    let mut req = url.into_client_request().unwrap();
+    if let Some(client) = client {
+        req.headers_mut()
+            .insert(CLIENT_HEADER, client.parse().unwrap());
+    }
+    let mut ws = match tokio_tungstenite::connect_async(req).await {
+        Ok((ws, _)) => ws,
+        Err(Error::Http(res)) => return Err(res.status()),
+        Err(e) => panic!("unexpected error {e:?}"),
+    };
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("3probe".into())
+    );
+    ws.send(Message::Text("5".into())).await.unwrap();
+    ws.send(Message::Text("4upgraded".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4upgraded".into())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn stolen_sid_upgrade_is_rejected() {
+    const PORT: u16 = 4012;
+    create_server_with_config(MyHandler, config(), PORT).await;
+    let sid = polling_handshake(PORT, "victim").await;
+
+    // Another client with the sid but not the same origin
+    assert_eq!(
+        upgrade(PORT, &sid, Some("attacker")).await,
+        Err(http::StatusCode::BAD_REQUEST)
+    );
+    assert_eq!(
+        upgrade(PORT, &sid, None).await,
+        Err(http::StatusCode::BAD_REQUEST)
+    );
+
+    // The victim is unaffected and can still upgrade its session
+    assert_eq!(polling_echo(PORT, &sid, "hello").await, "4hello");
+    assert_eq!(upgrade(PORT, &sid, Some("victim")).await, Ok(()));
+}
+
+#[tokio::test]
+pub async fn upgraded_session_is_not_upgraded_again() {
+    const PORT: u16 = 4013;
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+    let sid = polling_handshake(PORT, "client").await;
+
+    let url = format!("ws://127.0.0.1:{PORT}/engine.io/?EIO=4&transport=websocket&sid={sid}");
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    ws.next().await.unwrap().unwrap();
+    ws.send(Message::Text("5".into())).await.unwrap();
+
+    // Without the origin check, the sid is enough for the upgrade but the session is already upgraded
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        upgrade(PORT, &sid, None).await,
+        Err(http::StatusCode::BAD_REQUEST)
+    );
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4hello".into())
+    );
+}
+
+#[tokio::test]
+pub async fn unknown_sid_upgrade_is_rejected() {
+    const PORT: u16 = 4014;
+    create_server_with_config(MyHandler, config(), PORT).await;
+
+    let sid = engineioxide::sid::Sid::new().to_string();
+    assert_eq!(
+        upgrade(PORT, &sid, Some("client")).await,
+        Err(http::StatusCode::BAD_REQUEST)
+    );
+}
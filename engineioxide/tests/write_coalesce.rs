@@ -0,0 +1,93 @@
+//! Tests for the coalescing of the websocket writes
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_server_with_config, create_ws_connection};
+
+const BURST_LEN: usize = 500;
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, _reason: DisconnectReason) {}
+
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        if msg != "burst" {
+            socket.emit(msg).ok();
+            return;
+        }
+        // Packets are emitted in small bursts, some of them across the window boundaries
+        tokio::spawn(async move {
+            for i in 0..BURST_LEN {
+                socket.emit(i.to_string()).unwrap();
+                if i % 50 == 0 {
+                    tokio::time::sleep(Duration::from_millis(3)).await;
+                }
+            }
+        });
+    }
+
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+#[tokio::test]
+pub async fn coalesced_packets_are_ordered() {
+    const PORT: u16 = 3110;
+    let config = EngineIoConfig::builder()
+        .write_coalesce_window(Duration::from_millis(2))
+        .max_buffer_size(BURST_LEN * 2)
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Text("4burst".into())).await.unwrap();
+    for i in 0..BURST_LEN {
+        let msg = tokio::time::timeout(Duration::from_secs(1), ws.next())
+            .await
+            .expect("all the packets should be received")
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg, Message::Text(format!("4{i}")));
+    }
+}
+
+#[tokio::test]
+pub async fn heartbeat_is_flushed_right_away() {
+    const PORT: u16 = 3111;
+    let config = EngineIoConfig::builder()
+        .write_coalesce_window(Duration::from_secs(2))
+        .ping_interval(Duration::from_millis(50))
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    ws.next().await.unwrap().unwrap(); // Open packet
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+
+    // The message waits in the window until the ping is emitted, then both are flushed
+    let msgs = tokio::time::timeout(Duration::from_millis(500), async {
+        let msg1 = ws.next().await.unwrap().unwrap();
+        let msg2 = ws.next().await.unwrap().unwrap();
+        (msg1, msg2)
+    })
+    .await
+    .expect("the ping should be flushed without waiting for the window");
+    assert_eq!(msgs.0, Message::Text("4hello".into()));
+    assert_eq!(msgs.1, Message::Text("2".into()));
+}
@@ -0,0 +1,150 @@
+//! Tests for the websocket connections closed by the client, cleanly with a close frame or abruptly
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    service::{tungstenite::protocol::Role, EngineIoService, ProtocolVersion, WsHandshake},
+    socket::{DisconnectReason, Socket},
+};
+use futures::StreamExt;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf},
+    sync::mpsc,
+};
+use tokio_tungstenite::WebSocketStream;
+
+#[derive(Debug, Clone)]
+struct MyHandler {
+    disconnect_tx: mpsc::UnboundedSender<DisconnectReason>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _socket: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _socket: Arc<Socket<()>>, reason: DisconnectReason) {
+        self.disconnect_tx.send(reason).unwrap();
+    }
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+    fn on_binary(&self, data: Vec<u8>, socket: Arc<Socket<()>>) {
+        socket.emit_binary(data).ok();
+    }
+}
+
+/// The server side of an in-memory stream, its reads fail with a connection reset once `reset` is set
+struct ResettableStream {
+    inner: DuplexStream,
+    reset: Arc<AtomicBool>,
+}
+
+impl AsyncRead for ResettableStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if res.is_ready() && self.reset.load(Ordering::Relaxed) {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        res
+    }
+}
+
+impl AsyncWrite for ResettableStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Connects a client through an in-memory stream.
+/// Returns the client, the flag resetting the server stream and the receiver of the disconnect reasons.
+async fn connect() -> (
+    WebSocketStream<DuplexStream>,
+    Arc<AtomicBool>,
+    mpsc::UnboundedReceiver<DisconnectReason>,
+) {
+    let (disconnect_tx, disconnect_rx) = mpsc::unbounded_channel();
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .build();
+    let svc = EngineIoService::with_config(MyHandler { disconnect_tx }, config);
+    let (client, server) = tokio::io::duplex(1024 * 64);
+    let reset = Arc::new(AtomicBool::new(false));
+    let server = ResettableStream {
+        inner: server,
+        reset: reset.clone(),
+    };
+    let (parts, _) = http::Request::new(()).into_parts();
+    let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    tokio::spawn(async move {
+        svc.on_ws_connection(server, WsHandshake::new(ProtocolVersion::V4, parts))
+            .await;
+    });
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    client.next().await.unwrap().unwrap(); // Open packet
+    (client, reset, disconnect_rx)
+}
+
+async fn disconnect_reason(rx: &mut mpsc::UnboundedReceiver<DisconnectReason>) -> DisconnectReason {
+    tokio::time::timeout(Duration::from_millis(200), rx.recv())
+        .await
+        .expect("timeout waiting for the disconnection")
+        .unwrap()
+}
+
+#[tokio::test]
+pub async fn io_error_is_transport_error() {
+    let (client, reset, mut rx) = connect().await;
+    reset.store(true, Ordering::Relaxed);
+    // Wakes up the pending read of the server
+    drop(client);
+    assert_eq!(
+        disconnect_reason(&mut rx).await,
+        DisconnectReason::TransportError
+    );
+}
+
+#[tokio::test]
+pub async fn end_without_close_frame_is_transport_error() {
+    let (client, _reset, mut rx) = connect().await;
+    drop(client);
+    assert_eq!(
+        disconnect_reason(&mut rx).await,
+        DisconnectReason::TransportError
+    );
+}
+
+#[tokio::test]
+pub async fn close_frame_is_transport_close() {
+    let (mut client, _reset, mut rx) = connect().await;
+    client.close(None).await.unwrap();
+    assert_eq!(
+        disconnect_reason(&mut rx).await,
+        DisconnectReason::TransportClose
+    );
+    // The disconnection is only reported once
+    assert!(rx.try_recv().is_err());
+}
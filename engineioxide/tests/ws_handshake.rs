@@ -0,0 +1,75 @@
+//! Tests for the sessions opened directly on websocket, without a polling handshake
+use std::{sync::Arc, time::Duration};
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+    TransportType,
+};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+mod fixture;
+
+use fixture::{create_server_with_config, create_ws_connection, send_req_headers};
+
+#[derive(Debug, Clone)]
+struct MyHandler {
+    connect_tx: mpsc::UnboundedSender<TransportType>,
+}
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, socket: Arc<Socket<()>>) {
+        self.connect_tx.send(socket.transport_type()).unwrap();
+    }
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, msg: String, socket: Arc<Socket<()>>) {
+        socket.emit(msg).ok();
+    }
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+#[tokio::test]
+pub async fn open_packet_is_the_first_websocket_frame() {
+    const PORT: u16 = 3109;
+    let (connect_tx, mut connect_rx) = mpsc::unbounded_channel();
+    let config = EngineIoConfig::builder()
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout(Duration::from_secs(5))
+        .max_payload(1000)
+        .build();
+    create_server_with_config(MyHandler { connect_tx }, config, PORT).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    let Message::Text(open) = ws.next().await.unwrap().unwrap() else {
+        panic!("expected an open packet");
+    };
+    let open: Value = serde_json::from_str(open.strip_prefix('0').unwrap()).unwrap();
+    let sid = open["sid"].as_str().unwrap().to_string();
+    assert_eq!(open["upgrades"], serde_json::json!([]));
+    assert_eq!(open["pingInterval"], 10000);
+    assert_eq!(open["pingTimeout"], 5000);
+    assert_eq!(open["maxPayload"], 1000);
+
+    // The socket is created on websocket, it is never on polling
+    assert_eq!(connect_rx.recv().await.unwrap(), TransportType::Websocket);
+    let (status, _) = send_req_headers(
+        PORT,
+        format!("transport=polling&sid={sid}"),
+        http::Method::GET,
+        None,
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    ws.send(Message::Text("4hello".into())).await.unwrap();
+    assert_eq!(
+        ws.next().await.unwrap().unwrap(),
+        Message::Text("4hello".into())
+    );
+}
@@ -0,0 +1,87 @@
+//! Tests for the validation of the `Origin` header of the websocket upgrade requests
+use std::sync::Arc;
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use http::{header::ORIGIN, HeaderValue, StatusCode};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error as WsError};
+
+mod fixture;
+
+#[cfg(feature = "polling")]
+use fixture::create_polling_connection;
+use fixture::create_server_with_config;
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+async fn connect(
+    port: u16,
+    sid: Option<&str>,
+    origin: Option<&'static str>,
+) -> Result<(), WsError> {
+    let sid = sid.map(|sid| format!("&sid={sid}")).unwrap_or_default();
+    let mut req = format!("ws://127.0.0.1:{port}/engine.io/?EIO=4&transport=websocket{sid}")
+        .into_client_request()
+        .unwrap();
+    if let Some(origin) = origin {
+        req.headers_mut()
+            .insert(ORIGIN, HeaderValue::from_static(origin));
+    }
+    tokio_tungstenite::connect_async(req).await?;
+    Ok(())
+}
+
+fn assert_rejected(res: Result<(), WsError>) {
+    match res {
+        Err(WsError::Http(res)) => assert_eq!(res.status(), StatusCode::BAD_REQUEST),
+        res => panic!("expected a 400 response, got {res:?}"),
+    }
+}
+
+#[tokio::test]
+pub async fn ws_origin_check() {
+    const PORT: u16 = 4026;
+    let config = EngineIoConfig::builder()
+        .allowed_origins(["https://example.com"])
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    connect(PORT, None, Some("https://example.com"))
+        .await
+        .unwrap();
+    assert_rejected(connect(PORT, None, Some("https://evil.com")).await);
+    // Non-browser clients don't send the header
+    connect(PORT, None, None).await.unwrap();
+
+    // Upgrades from polling are checked too
+    #[cfg(feature = "polling")]
+    {
+        let sid = create_polling_connection(PORT).await;
+        assert_rejected(connect(PORT, Some(&sid), Some("https://evil.com")).await);
+        connect(PORT, Some(&sid), Some("https://example.com"))
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+pub async fn ws_origin_all_allowed_by_default() {
+    const PORT: u16 = 4028;
+    create_server_with_config(MyHandler, EngineIoConfig::default(), PORT).await;
+
+    connect(PORT, None, None).await.unwrap();
+    connect(PORT, None, Some("https://evil.com")).await.unwrap();
+}
@@ -0,0 +1,67 @@
+//! Tests for the negotiation of the websocket subprotocols
+use std::sync::Arc;
+
+use engineioxide::{
+    config::EngineIoConfig,
+    handler::EngineIoHandler,
+    socket::{DisconnectReason, Socket},
+};
+use http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue, StatusCode};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error as WsError};
+
+mod fixture;
+
+use fixture::create_server_with_config;
+
+#[derive(Debug, Clone)]
+struct MyHandler;
+
+impl EngineIoHandler for MyHandler {
+    type Data = ();
+
+    fn on_connect(&self, _: Arc<Socket<()>>) {}
+    fn on_disconnect(&self, _: Arc<Socket<()>>, _: DisconnectReason) {}
+    fn on_message(&self, _: String, _: Arc<Socket<()>>) {}
+    fn on_binary(&self, _: Vec<u8>, _: Arc<Socket<()>>) {}
+}
+
+async fn connect(port: u16, subprotocols: &'static str) -> Result<Option<HeaderValue>, WsError> {
+    let mut req = format!("ws://127.0.0.1:{port}/engine.io/?EIO=4&transport=websocket")
+        .into_client_request()
+        .unwrap();
+    req.headers_mut().insert(
+        SEC_WEBSOCKET_PROTOCOL,
+        HeaderValue::from_static(subprotocols),
+    );
+    let (_ws, res) = tokio_tungstenite::connect_async(req).await?;
+    Ok(res.headers().get(SEC_WEBSOCKET_PROTOCOL).cloned())
+}
+
+#[tokio::test]
+pub async fn ws_subprotocol_selected() {
+    const PORT: u16 = 4000;
+    let config = EngineIoConfig::builder()
+        .ws_subprotocols(["v2.chat", "v1.chat"])
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    let subprotocol = connect(PORT, "v3.chat, v1.chat, v2.chat").await.unwrap();
+    assert_eq!(subprotocol.unwrap(), "v1.chat");
+}
+
+#[tokio::test]
+pub async fn ws_subprotocol_strict() {
+    const PORT: u16 = 4001;
+    let config = EngineIoConfig::builder()
+        .ws_subprotocols(["v1.chat"])
+        .ws_subprotocol_strict(true)
+        .build();
+    create_server_with_config(MyHandler, config, PORT).await;
+
+    match connect(PORT, "v3.chat").await {
+        Err(WsError::Http(res)) => assert_eq!(res.status(), StatusCode::BAD_REQUEST),
+        res => panic!("unexpected upgrade result: {res:?}"),
+    }
+    // Upgrades without subprotocol are still accepted
+    fixture::create_ws_connection(PORT).await;
+}
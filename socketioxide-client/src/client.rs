@@ -0,0 +1,488 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use engineioxide::packet::Packet as EIoPacket;
+use futures::{SinkExt, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use socketioxide::{
+    ack::AckResponse,
+    packet::{BinaryPacket, Packet, PacketData},
+};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{
+    config::ClientOptions,
+    errors::{ClientError, ConnectError, DisconnectReason},
+    transport::{self, Ws},
+};
+
+pub(crate) type BoxedHandler = Arc<dyn Fn(Event) + Send + Sync>;
+pub(crate) type DisconnectHandler = Box<dyn FnOnce(DisconnectReason) + Send>;
+
+type AckSender = oneshot::Sender<AckResponse<Value>>;
+
+/// A socket.io client connected to a namespace of a server, see [`connect`](crate::connect).
+///
+/// It can be cloned cheaply, all the clones share the same connection.
+/// The connection is kept until [`disconnect`](Client::disconnect) is called or the server closes it,
+/// even if all the clones are dropped.
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    ns: Cow<'static, str>,
+    sid: Mutex<Option<String>>,
+    tx: mpsc::UnboundedSender<Message>,
+    handlers: RwLock<HashMap<Cow<'static, str>, BoxedHandler>>,
+    disconnect_handler: Mutex<Option<DisconnectHandler>>,
+    acks: Mutex<HashMap<i64, AckSender>>,
+    next_ack: AtomicI64,
+    ack_timeout: Duration,
+    connected: AtomicBool,
+    /// Set when the client disconnects by itself
+    closing: AtomicBool,
+}
+
+/// An event received from the server, given to the handlers registered with [`Client::on`]
+pub struct Event {
+    /// The name of the event
+    pub name: String,
+    data: Value,
+    bin: Vec<Vec<u8>>,
+    ack: Option<i64>,
+    client: Client,
+}
+
+impl Event {
+    /// Deserializes the data of the event, a single argument is unwrapped from its array
+    /// like with the [`Data`](socketioxide::extract::Data) extractor of the server
+    pub fn data<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        let mut data = self.data.clone();
+        unwrap_array(&mut data);
+        serde_json::from_value(data)
+    }
+
+    /// Returns the binary attachments of the event
+    pub fn binary(&self) -> &[Vec<u8>] {
+        &self.bin
+    }
+
+    /// Returns true if the server expects an acknowledgement
+    pub fn expects_ack(&self) -> bool {
+        self.ack.is_some()
+    }
+
+    /// Acknowledges the event with the given data.
+    /// It does nothing if the server does not expect an acknowledgement.
+    pub fn ack<T: Serialize>(self, data: T) -> Result<(), ClientError> {
+        let Some(ack) = self.ack else {
+            return Ok(());
+        };
+        let client = &self.client;
+        let data = serde_json::to_value(data)?;
+        client.send(Packet::ack(&client.inner.ns, data, ack))
+    }
+}
+
+impl std::fmt::Debug for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Event")
+            .field("name", &self.name)
+            .field("data", &self.data)
+            .field("bin", &self.bin.len())
+            .field("ack", &self.ack)
+            .finish()
+    }
+}
+
+impl Client {
+    /// Opens the session, connects to the namespace and spawns the tasks of the connection
+    pub(crate) async fn connect(url: &str, opts: ClientOptions) -> Result<Self, ConnectError> {
+        let timeout = opts.connect_timeout;
+        let (ws, open) =
+            tokio::time::timeout(timeout, transport::open(url, &opts.path, opts.transport))
+                .await
+                .map_err(|_| ConnectError::Timeout)??;
+        let heartbeat = Duration::from_millis(open.ping_interval() + open.ping_timeout());
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let client = Client {
+            inner: Arc::new(Inner {
+                ns: opts.namespace,
+                sid: Mutex::new(None),
+                tx,
+                handlers: RwLock::new(opts.handlers),
+                disconnect_handler: Mutex::new(opts.disconnect_handler),
+                acks: Mutex::new(HashMap::new()),
+                next_ack: AtomicI64::new(0),
+                ack_timeout: opts.ack_timeout,
+                connected: AtomicBool::new(false),
+                closing: AtomicBool::new(false),
+            }),
+        };
+
+        let (connect_tx, connect_rx) = oneshot::channel();
+        let (ws_tx, ws_rx) = ws.split();
+        tokio::spawn(write_loop(ws_tx, rx));
+        tokio::spawn(client.clone().read_loop(ws_rx, heartbeat, connect_tx));
+
+        let auth = opts.auth.map(|auth| auth.to_string());
+        let packet = Packet {
+            inner: PacketData::Connect(auth),
+            ns: client.inner.ns.clone(),
+        };
+        client.send(packet).map_err(|_| ConnectError::Closed)?;
+
+        match tokio::time::timeout(timeout, connect_rx).await {
+            Ok(Ok(Ok(()))) => Ok(client),
+            Ok(Ok(Err(message))) => {
+                client.close();
+                Err(ConnectError::Rejected(message))
+            }
+            Ok(Err(_)) => Err(ConnectError::Closed),
+            Err(_) => {
+                client.close();
+                Err(ConnectError::Timeout)
+            }
+        }
+    }
+
+    /// Returns the namespace of the client
+    pub fn ns(&self) -> &str {
+        &self.inner.ns
+    }
+
+    /// Returns the socket.io sid given by the server, `None` before the connection to the namespace
+    pub fn sid(&self) -> Option<String> {
+        self.inner.sid.lock().unwrap().clone()
+    }
+
+    /// Returns true while the client is connected to the namespace
+    pub fn connected(&self) -> bool {
+        self.inner.connected.load(Ordering::SeqCst)
+    }
+
+    /// Registers or replaces the handler of an event.
+    ///
+    /// The handlers are called from the task reading the connection, so they should not block.
+    /// Use [`ClientOptions::on`] to register the handlers before the connection.
+    pub fn on<F>(&self, event: impl Into<Cow<'static, str>>, handler: F)
+    where
+        F: Fn(Event) + Send + Sync + 'static,
+    {
+        self.inner
+            .handlers
+            .write()
+            .unwrap()
+            .insert(event.into(), Arc::new(handler));
+    }
+
+    /// Emits an event to the server
+    pub fn emit<T: Serialize>(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        data: T,
+    ) -> Result<(), ClientError> {
+        let data = serde_json::to_value(data)?;
+        self.send(Packet::event(self.inner.ns.clone(), event, data))
+    }
+
+    /// Emits an event to the server with binary attachments
+    pub fn emit_with_binary<T: Serialize>(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        data: T,
+        bin: Vec<Vec<u8>>,
+    ) -> Result<(), ClientError> {
+        let data = serde_json::to_value(data)?;
+        let packet = if bin.is_empty() {
+            Packet::event(self.inner.ns.clone(), event, data)
+        } else {
+            Packet::bin_event(self.inner.ns.clone(), event, data, bin)
+        };
+        self.send(packet)
+    }
+
+    /// Emits an event to the server and waits for its acknowledgement,
+    /// at most for the [`ack_timeout`](ClientOptions::ack_timeout).
+    ///
+    /// A single argument of the acknowledgement is unwrapped from its array, like for [`Event::data`].
+    pub async fn emit_with_ack<T: Serialize, R: DeserializeOwned>(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        data: T,
+    ) -> Result<AckResponse<R>, ClientError> {
+        let data = serde_json::to_value(data)?;
+        let mut packet = Packet::event(self.inner.ns.clone(), event, data);
+        let ack = self.inner.next_ack.fetch_add(1, Ordering::Relaxed);
+        if let PacketData::Event(_, _, id) = &mut packet.inner {
+            *id = Some(ack);
+        }
+        let (tx, rx) = oneshot::channel();
+        self.inner.acks.lock().unwrap().insert(ack, tx);
+        if let Err(e) = self.send(packet) {
+            self.inner.acks.lock().unwrap().remove(&ack);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(self.inner.ack_timeout, rx).await {
+            Ok(Ok(AckResponse { mut data, binary })) => {
+                unwrap_array(&mut data);
+                Ok(AckResponse {
+                    data: serde_json::from_value(data)?,
+                    binary,
+                })
+            }
+            Ok(Err(_)) => Err(ClientError::Closed),
+            Err(_) => {
+                self.inner.acks.lock().unwrap().remove(&ack);
+                Err(ClientError::AckTimeout)
+            }
+        }
+    }
+
+    /// Disconnects the client from the namespace and closes the session.
+    /// The disconnect handler is called with [`DisconnectReason::ClientDisconnect`].
+    pub fn disconnect(&self) {
+        if self.inner.closing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.send(Packet::disconnect(&self.inner.ns)).ok();
+        self.close();
+    }
+
+    /// Closes the engine.io session
+    fn close(&self) {
+        self.inner.closing.store(true, Ordering::SeqCst);
+        self.inner
+            .tx
+            .send(Message::Text(EIoPacket::Close.encode()))
+            .ok();
+        self.inner.tx.send(Message::Close(None)).ok();
+    }
+
+    /// Sends a socket.io packet and its binary attachments
+    fn send(&self, packet: Packet<'_>) -> Result<(), ClientError> {
+        let bin = match &packet.inner {
+            PacketData::BinaryEvent(_, BinaryPacket { bin, .. }, _)
+            | PacketData::BinaryAck(BinaryPacket { bin, .. }, _) => bin.clone(),
+            _ => Vec::new(),
+        };
+        let msg = EIoPacket::Message(packet.into()).encode();
+        self.inner
+            .tx
+            .send(Message::Text(msg))
+            .map_err(|_| ClientError::Closed)?;
+        for data in bin {
+            self.inner
+                .tx
+                .send(Message::Binary(data))
+                .map_err(|_| ClientError::Closed)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the connection until it is closed, answers the pings and dispatches the packets of the namespace
+    async fn read_loop(
+        self,
+        mut rx: futures::stream::SplitStream<Ws>,
+        heartbeat: Duration,
+        connect_tx: oneshot::Sender<Result<(), String>>,
+    ) {
+        let mut connect_tx = Some(connect_tx);
+        // A binary packet waiting for its attachments
+        let mut partial: Option<Packet<'static>> = None;
+        let reason = loop {
+            let msg = match tokio::time::timeout(heartbeat, rx.next()).await {
+                Err(_) => break DisconnectReason::HeartbeatTimeout,
+                Ok(None) => break DisconnectReason::TransportClose,
+                Ok(Some(Err(_e))) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("websocket error: {_e}");
+                    break DisconnectReason::TransportError;
+                }
+                Ok(Some(Ok(msg))) => msg,
+            };
+            match msg {
+                Message::Text(msg) => match EIoPacket::try_from(msg) {
+                    Ok(EIoPacket::Ping) => {
+                        self.inner
+                            .tx
+                            .send(Message::Text(EIoPacket::Pong.encode()))
+                            .ok();
+                    }
+                    Ok(EIoPacket::Message(msg)) => {
+                        if let Some((ns, message)) = decode_connect_error(&msg) {
+                            if ns == self.inner.ns {
+                                if let Some(tx) = connect_tx.take() {
+                                    tx.send(Err(message)).ok();
+                                }
+                            }
+                            continue;
+                        }
+                        let packet = match Packet::try_from(msg) {
+                            Ok(packet) if packet.ns == self.inner.ns => packet,
+                            Ok(_) => continue,
+                            Err(_e) => {
+                                #[cfg(feature = "tracing")]
+                                tracing::debug!("invalid socket.io packet: {_e}");
+                                continue;
+                            }
+                        };
+                        match packet.inner {
+                            PacketData::Connect(data) => {
+                                let sid = data
+                                    .and_then(|data| serde_json::from_str::<Value>(&data).ok())
+                                    .and_then(|data| Some(data.get("sid")?.as_str()?.to_string()));
+                                *self.inner.sid.lock().unwrap() = sid;
+                                self.inner.connected.store(true, Ordering::SeqCst);
+                                if let Some(tx) = connect_tx.take() {
+                                    tx.send(Ok(())).ok();
+                                }
+                            }
+                            PacketData::Disconnect => break DisconnectReason::ServerDisconnect,
+                            PacketData::BinaryEvent(_, ref bin, _)
+                            | PacketData::BinaryAck(ref bin, _)
+                                if !bin.is_complete() =>
+                            {
+                                partial = Some(packet);
+                            }
+                            _ => self.dispatch(packet),
+                        }
+                    }
+                    Ok(EIoPacket::Close) => break DisconnectReason::TransportClose,
+                    Ok(_) => (),
+                    Err(_e) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("invalid engine.io packet: {_e}");
+                    }
+                },
+                Message::Binary(data) => {
+                    let Some(mut packet) = partial.take() else {
+                        continue;
+                    };
+                    let complete = match &mut packet.inner {
+                        PacketData::BinaryEvent(_, bin, _) | PacketData::BinaryAck(bin, _) => {
+                            bin.add_payload(data);
+                            bin.is_complete()
+                        }
+                        _ => true,
+                    };
+                    if complete {
+                        self.dispatch(packet);
+                    } else {
+                        partial = Some(packet);
+                    }
+                }
+                Message::Close(_) => break DisconnectReason::TransportClose,
+                _ => (),
+            }
+        };
+
+        self.inner.connected.store(false, Ordering::SeqCst);
+        // Dropping the senders resolves the pending acks with a closed error
+        self.inner.acks.lock().unwrap().clear();
+        self.inner.tx.send(Message::Close(None)).ok();
+        let reason = if self.inner.closing.load(Ordering::SeqCst) {
+            DisconnectReason::ClientDisconnect
+        } else {
+            reason
+        };
+        if let Some(handler) = self.inner.disconnect_handler.lock().unwrap().take() {
+            handler(reason);
+        }
+    }
+
+    /// Calls the handler of an event or resolves the pending ack of an acknowledgement
+    fn dispatch(&self, packet: Packet<'static>) {
+        let (name, data, bin, ack) = match packet.inner {
+            PacketData::Event(name, data, ack) => (name, data, Vec::new(), ack),
+            PacketData::BinaryEvent(name, bin, ack) => (name, bin.data, bin.bin, ack),
+            PacketData::EventAck(data, ack) => {
+                self.resolve_ack(ack, data, Vec::new());
+                return;
+            }
+            PacketData::BinaryAck(bin, ack) => {
+                self.resolve_ack(ack, bin.data, bin.bin);
+                return;
+            }
+            _ => return,
+        };
+        let handler = self.inner.handlers.read().unwrap().get(&name).cloned();
+        if let Some(handler) = handler {
+            handler(Event {
+                name: name.into_owned(),
+                data,
+                bin,
+                ack,
+                client: self.clone(),
+            });
+        }
+    }
+
+    fn resolve_ack(&self, ack: i64, data: Value, binary: Vec<Vec<u8>>) {
+        if let Some(tx) = self.inner.acks.lock().unwrap().remove(&ack) {
+            tx.send(AckResponse { data, binary }).ok();
+        }
+    }
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("ns", &self.inner.ns)
+            .field("sid", &self.sid())
+            .field("connected", &self.connected())
+            .finish()
+    }
+}
+
+/// Writes the queued messages to the connection until a close frame is queued
+async fn write_loop(
+    mut tx: futures::stream::SplitSink<Ws, Message>,
+    mut rx: mpsc::UnboundedReceiver<Message>,
+) {
+    while let Some(msg) = rx.recv().await {
+        let close = matches!(msg, Message::Close(_));
+        if tx.send(msg).await.is_err() || close {
+            break;
+        }
+    }
+    tx.close().await.ok();
+}
+
+/// Decodes a connect_error packet into its namespace and its message.
+/// The server never receives them, so they are not decoded by the [`Packet`] codec.
+fn decode_connect_error(msg: &str) -> Option<(&str, String)> {
+    let msg = msg.strip_prefix('4')?;
+    let (ns, data) = match msg.strip_prefix('/') {
+        Some(_) => msg.split_once(',')?,
+        None => ("/", msg),
+    };
+    let message = serde_json::from_str::<Value>(data)
+        .ok()
+        .and_then(|data| Some(data.get("message")?.as_str()?.to_string()))
+        .unwrap_or_else(|| data.to_string());
+    Some((ns, message))
+}
+
+/// Unwraps an array with a single element
+fn unwrap_array(v: &mut Value) {
+    match v {
+        Value::Array(vec) if vec.len() == 1 => {
+            *v = vec.pop().unwrap();
+        }
+        _ => (),
+    }
+}
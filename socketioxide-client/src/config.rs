@@ -0,0 +1,135 @@
+//! The options of a client connection, see [`ClientOptions`]
+use std::{borrow::Cow, collections::HashMap, sync::Arc, time::Duration};
+
+use serde_json::Value;
+
+use crate::{
+    client::{BoxedHandler, DisconnectHandler, Event},
+    errors::DisconnectReason,
+};
+
+/// The transport used for the engine.io handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transport {
+    /// The session is opened directly on websocket
+    #[default]
+    Websocket,
+    /// The session is opened with a polling request, then upgraded to websocket like a browser client does.
+    /// It is useful when a proxy only accepts websocket connections for known sessions.
+    Polling,
+}
+
+/// The options of a client connection, given to [`connect`](crate::connect)
+pub struct ClientOptions {
+    pub(crate) path: Cow<'static, str>,
+    pub(crate) namespace: Cow<'static, str>,
+    pub(crate) auth: Option<Value>,
+    pub(crate) transport: Transport,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) ack_timeout: Duration,
+    pub(crate) handlers: HashMap<Cow<'static, str>, BoxedHandler>,
+    pub(crate) disconnect_handler: Option<DisconnectHandler>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            path: Cow::Borrowed("/socket.io"),
+            namespace: Cow::Borrowed("/"),
+            auth: None,
+            transport: Transport::default(),
+            connect_timeout: Duration::from_secs(10),
+            ack_timeout: Duration::from_secs(5),
+            handlers: HashMap::new(),
+            disconnect_handler: None,
+        }
+    }
+}
+
+impl ClientOptions {
+    /// Creates the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The path of the socket.io server.
+    ///
+    /// Defaults to "/socket.io".
+    pub fn path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// The namespace to connect to.
+    ///
+    /// Defaults to "/".
+    pub fn namespace(mut self, namespace: impl Into<Cow<'static, str>>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// The auth payload sent with the namespace connect packet.
+    ///
+    /// Defaults to none.
+    pub fn auth(mut self, auth: Value) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// The [`Transport`] used for the engine.io handshake.
+    ///
+    /// Defaults to [`Transport::Websocket`].
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// The maximum time to open the session and to connect to the namespace.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// The maximum time to wait for the acknowledgement of [`Client::emit_with_ack`](crate::Client::emit_with_ack).
+    ///
+    /// Defaults to 5 seconds.
+    pub fn ack_timeout(mut self, timeout: Duration) -> Self {
+        self.ack_timeout = timeout;
+        self
+    }
+
+    /// Registers a handler for an event, before the connection so that no event is missed.
+    /// See [`Client::on`](crate::Client::on).
+    pub fn on<F>(mut self, event: impl Into<Cow<'static, str>>, handler: F) -> Self
+    where
+        F: Fn(Event) + Send + Sync + 'static,
+    {
+        self.handlers.insert(event.into(), Arc::new(handler));
+        self
+    }
+
+    /// Registers a handler called once when the client is disconnected, with the [`DisconnectReason`].
+    pub fn on_disconnect<F>(mut self, handler: F) -> Self
+    where
+        F: FnOnce(DisconnectReason) + Send + 'static,
+    {
+        self.disconnect_handler = Some(Box::new(handler));
+        self
+    }
+}
+
+impl std::fmt::Debug for ClientOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientOptions")
+            .field("path", &self.path)
+            .field("namespace", &self.namespace)
+            .field("auth", &self.auth)
+            .field("transport", &self.transport)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("ack_timeout", &self.ack_timeout)
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
@@ -0,0 +1,62 @@
+use tokio_tungstenite::tungstenite;
+
+/// Error returned by [`connect`](crate::connect)
+#[derive(thiserror::Error, Debug)]
+pub enum ConnectError {
+    /// The url of the server is invalid or uses an unsupported scheme (`https` or `wss`)
+    #[error("invalid url: {0}")]
+    InvalidUrl(String),
+    /// The polling handshake request failed
+    #[error("polling handshake error: {0}")]
+    Http(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The websocket connection failed
+    #[error("websocket error: {0}")]
+    Ws(#[source] Box<tungstenite::Error>),
+    /// The server did not answer the engine.io handshake as expected
+    #[error("invalid engine.io handshake: {0}")]
+    Handshake(String),
+    /// The server rejected the connection to the namespace with a connect_error packet
+    #[error("connection to the namespace rejected: {0}")]
+    Rejected(String),
+    /// The session was not opened or the namespace was not connected in time
+    #[error("connection timed out")]
+    Timeout,
+    /// The connection was closed during the handshake
+    #[error("connection closed during the handshake")]
+    Closed,
+}
+
+impl From<tungstenite::Error> for ConnectError {
+    fn from(err: tungstenite::Error) -> Self {
+        Self::Ws(Box::new(err))
+    }
+}
+
+/// Error returned by the emit methods of the [`Client`](crate::Client)
+#[derive(thiserror::Error, Debug)]
+pub enum ClientError {
+    /// The data could not be serialized or the ack could not be deserialized
+    #[error("serialization error: {0}")]
+    Serialize(#[from] serde_json::Error),
+    /// The client is disconnected
+    #[error("the client is disconnected")]
+    Closed,
+    /// The acknowledgement was not received in time
+    #[error("acknowledgement timed out")]
+    AckTimeout,
+}
+
+/// The reason of the disconnection of a [`Client`](crate::Client)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The client was disconnected with [`Client::disconnect`](crate::Client::disconnect)
+    ClientDisconnect,
+    /// The server disconnected the client from the namespace
+    ServerDisconnect,
+    /// The server closed the engine.io session or the connection was closed
+    TransportClose,
+    /// The connection failed
+    TransportError,
+    /// The server did not send any ping in time
+    HeartbeatTimeout,
+}
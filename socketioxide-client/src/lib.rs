@@ -0,0 +1,68 @@
+#![warn(
+    clippy::all,
+    clippy::todo,
+    clippy::unused_self,
+    clippy::needless_continue,
+    clippy::needless_borrow,
+    clippy::match_wildcard_for_single_variants,
+    clippy::if_let_mutex,
+    clippy::await_holding_lock,
+    clippy::inefficient_to_string,
+    rust_2018_idioms,
+    future_incompatible,
+    nonstandard_style,
+    missing_docs
+)]
+//! A minimal socket.io client, to link a socketioxide server to another socketioxide deployment
+//! or to an existing socket.io server.
+//!
+//! It reuses the engine.io and socket.io packet codecs of [`engineioxide`] and [`socketioxide`]:
+//! * The engine.io session is opened directly on websocket, or with a polling handshake upgraded to websocket,
+//!   see [`Transport`].
+//! * The pings of the server are answered, and the client is disconnected if none is received in time.
+//! * The client connects to a single namespace, with an optional auth payload.
+//! * Events are emitted with [`Client::emit`], [`Client::emit_with_binary`] and [`Client::emit_with_ack`],
+//!   and received with handlers registered with [`ClientOptions::on`] or [`Client::on`].
+//!
+//! There is no reconnection logic, a disconnection is reported to the [`ClientOptions::on_disconnect`] handler.
+//! TLS is not supported.
+//!
+//! ## Example
+//! ```no_run
+//! # use socketioxide_client::{connect, ClientOptions};
+//! # use serde_json::json;
+//! # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+//! let opts = ClientOptions::new()
+//!     .namespace("/bridge")
+//!     .auth(json!({ "token": "secret" }))
+//!     .on("message", |event| {
+//!         println!("received {:?}", event.data::<String>());
+//!         event.ack("ok").ok();
+//!     })
+//!     .on_disconnect(|reason| println!("disconnected: {reason:?}"));
+//! let client = connect("http://127.0.0.1:3000", opts).await?;
+//! client.emit("message", "hello")?;
+//! let ack = client.emit_with_ack::<_, String>("ping", ()).await?;
+//! println!("ack: {}", ack.data);
+//! # Ok(())
+//! # }
+//! ```
+mod client;
+mod config;
+mod errors;
+mod transport;
+
+pub use client::{Client, Event};
+pub use config::{ClientOptions, Transport};
+pub use errors::{ClientError, ConnectError, DisconnectReason};
+pub use socketioxide::ack::AckResponse;
+
+/// Connects to the namespace of a socket.io server, e.g. `http://127.0.0.1:3000`.
+///
+/// Only the `http://` and `ws://` urls are supported, the client has no TLS support
+/// and fails with [`ConnectError::InvalidUrl`] for the `https://` and `wss://` urls.
+///
+/// It resolves once the server accepted the connection to the namespace, or fails with a [`ConnectError`].
+pub async fn connect(url: &str, opts: ClientOptions) -> Result<Client, ConnectError> {
+    Client::connect(url, opts).await
+}
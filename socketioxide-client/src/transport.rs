@@ -0,0 +1,107 @@
+//! The engine.io handshake of the client, directly on websocket or with a polling request upgraded to websocket
+use std::collections::VecDeque;
+
+use engineioxide::packet::{OpenPacket, Packet as EIoPacket, PAYLOAD_SEPARATOR};
+use futures::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Empty};
+use hyper_util::{client::legacy::Client as HttpClient, rt::TokioExecutor};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::{config::Transport, errors::ConnectError};
+
+pub(crate) type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Opens an engine.io session with the given transport, it always ends on websocket
+pub(crate) async fn open(
+    url: &str,
+    path: &str,
+    transport: Transport,
+) -> Result<(Ws, OpenPacket), ConnectError> {
+    let url = url.trim_end_matches('/');
+    let path = path.trim_end_matches('/');
+    // There is no TLS support, so the https and wss urls are rejected
+    let ws_url = match url.split_once("://") {
+        Some(("http", rest)) => format!("ws://{rest}"),
+        Some(("ws", _)) => url.to_string(),
+        _ => return Err(ConnectError::InvalidUrl(url.to_string())),
+    };
+
+    match transport {
+        Transport::Websocket => {
+            let endpoint = format!("{ws_url}{path}/?EIO=4&transport=websocket");
+            let (mut ws, _) = tokio_tungstenite::connect_async(endpoint).await?;
+            let open = match ws.next().await {
+                Some(Ok(Message::Text(msg))) => decode_open(&msg)?,
+                Some(Ok(msg)) => return Err(ConnectError::Handshake(format!("{msg:?}"))),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(ConnectError::Closed),
+            };
+            Ok((ws, open))
+        }
+        Transport::Polling => {
+            let http_url = ws_url.replacen("ws", "http", 1);
+            let open =
+                polling_handshake(&format!("{http_url}{path}/?EIO=4&transport=polling")).await?;
+            let endpoint = format!(
+                "{ws_url}{path}/?EIO=4&transport=websocket&sid={}",
+                open.sid()
+            );
+            let (mut ws, _) = tokio_tungstenite::connect_async(endpoint).await?;
+            upgrade(&mut ws).await?;
+            Ok((ws, open))
+        }
+    }
+}
+
+/// Sends the polling handshake request and decodes the open packet of the response
+async fn polling_handshake(url: &str) -> Result<OpenPacket, ConnectError> {
+    let client = HttpClient::builder(TokioExecutor::new()).build_http::<Empty<VecDeque<u8>>>();
+    let uri = url
+        .parse()
+        .map_err(|_| ConnectError::InvalidUrl(url.to_string()))?;
+    let res = client
+        .get(uri)
+        .await
+        .map_err(|e| ConnectError::Http(e.into()))?;
+    if !res.status().is_success() {
+        return Err(ConnectError::Handshake(format!(
+            "polling handshake status: {}",
+            res.status()
+        )));
+    }
+    let body = res
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| ConnectError::Http(e.into()))?
+        .to_bytes();
+    let body = String::from_utf8_lossy(&body);
+    // The open packet is the first packet of the payload
+    let open = body.split(PAYLOAD_SEPARATOR as char).next().unwrap_or("");
+    decode_open(open)
+}
+
+/// Upgrades the session to the websocket with the probe handshake
+async fn upgrade(ws: &mut Ws) -> Result<(), ConnectError> {
+    ws.send(Message::Text(EIoPacket::PingUpgrade.encode()))
+        .await?;
+    match ws.next().await {
+        Some(Ok(Message::Text(msg))) if msg == EIoPacket::PongUpgrade.encode() => (),
+        Some(Ok(msg)) => return Err(ConnectError::Handshake(format!("{msg:?}"))),
+        Some(Err(e)) => return Err(e.into()),
+        None => return Err(ConnectError::Closed),
+    }
+    ws.send(Message::Text(EIoPacket::Upgrade.encode())).await?;
+    Ok(())
+}
+
+fn decode_open(msg: &str) -> Result<OpenPacket, ConnectError> {
+    match EIoPacket::try_from(msg) {
+        Ok(EIoPacket::Open(open)) => Ok(open),
+        Ok(packet) => Err(ConnectError::Handshake(format!(
+            "expected an open packet, got {packet:?}"
+        ))),
+        Err(e) => Err(ConnectError::Handshake(e.to_string())),
+    }
+}
@@ -0,0 +1,168 @@
+//! A socketioxide server and the client talking over localhost
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use engineioxide::service::NotFoundService;
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use serde_json::{json, Value};
+use socketioxide::{
+    adapter::LocalAdapter,
+    extract::{AckSender, Bin, Data, SocketRef},
+    service::SocketIoService,
+    SocketIo,
+};
+use socketioxide_client::{connect, ClientOptions, ConnectError, DisconnectReason, Transport};
+use tokio::{net::TcpListener, sync::mpsc};
+
+async fn spawn_server(port: u16, svc: SocketIoService<NotFoundService, LocalAdapter>) {
+    let addr = &SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+    let listener = TcpListener::bind(&addr).await.unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let svc = svc.clone();
+            tokio::spawn(async move {
+                http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), svc)
+                    .with_upgrades()
+                    .await
+                    .ok();
+            });
+        }
+    });
+}
+
+/// A server echoing the events of the client and reporting the auth payload of the connections
+async fn create_server(port: u16) -> (SocketIo, mpsc::UnboundedReceiver<Value>) {
+    let (svc, io) = SocketIo::builder()
+        .ping_interval(Duration::from_millis(300))
+        .ping_timeout(Duration::from_millis(200))
+        .build_svc();
+    let (tx, rx) = mpsc::unbounded_channel();
+    io.ns("/", move |socket: SocketRef, Data::<Value>(auth)| {
+        tx.send(auth).unwrap();
+        socket.on(
+            "echo",
+            |socket: SocketRef, Data::<Value>(data), Bin(bin)| {
+                socket.bin(bin).emit("echo", data).ok();
+            },
+        );
+        socket.on("ack", |Data::<Value>(data), ack: AckSender| {
+            ack.send(data).ok();
+        });
+    });
+    spawn_server(port, svc).await;
+    (io, rx)
+}
+
+#[tokio::test]
+pub async fn link() {
+    const PORT: u16 = 2821;
+    let (io, mut auth_rx) = create_server(PORT).await;
+    let url = format!("http://127.0.0.1:{PORT}");
+
+    for transport in [Transport::Websocket, Transport::Polling] {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let opts = ClientOptions::new()
+            .transport(transport)
+            .auth(json!({ "token": "secret" }))
+            .on("echo", move |event| {
+                tx.send((event.data::<Value>().unwrap(), event.binary().to_vec()))
+                    .unwrap();
+            })
+            .on("question", |event| {
+                let data: String = event.data().unwrap();
+                event.ack(format!("answer to {data}")).unwrap();
+            });
+        let client = connect(&url, opts).await.unwrap();
+        assert!(client.connected());
+        assert!(client.sid().is_some());
+
+        let auth = tokio::time::timeout(Duration::from_millis(200), auth_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(auth, json!({ "token": "secret" }));
+
+        // Binary event round trip
+        client
+            .emit_with_binary("echo", json!({ "a": 1 }), vec![vec![1, 2, 3], vec![4]])
+            .unwrap();
+        let (data, bin) = tokio::time::timeout(Duration::from_millis(200), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data, json!({ "a": 1 }));
+        assert_eq!(bin, vec![vec![1, 2, 3], vec![4]]);
+
+        // Ack of the server
+        let ack = client
+            .emit_with_ack::<_, String>("ack", "hello")
+            .await
+            .unwrap();
+        assert_eq!(ack.data, "hello");
+
+        // Ack of the client
+        let sockets = io.sockets().unwrap();
+        assert_eq!(sockets.len(), 1);
+        let ack = sockets[0]
+            .emit_with_ack::<_, String>("question", "life")
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(ack.data, "answer to life");
+
+        client.disconnect();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(io.sockets().unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+pub async fn link_tls_url_rejected() {
+    for url in [
+        "https://127.0.0.1:2823",
+        "wss://127.0.0.1:2823",
+        "127.0.0.1:2823",
+    ] {
+        let err = connect(url, ClientOptions::new()).await.unwrap_err();
+        assert!(matches!(err, ConnectError::InvalidUrl(u) if u == url));
+    }
+}
+
+#[tokio::test]
+pub async fn link_disconnect() {
+    const PORT: u16 = 2822;
+    let (io, _auth_rx) = create_server(PORT).await;
+    let url = format!("http://127.0.0.1:{PORT}");
+
+    let err = connect(&url, ClientOptions::new().namespace("/unknown"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ConnectError::Rejected(msg) if msg == "Invalid namespace"));
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let client = connect(
+        &url,
+        ClientOptions::new().on_disconnect(move |reason| tx.send(reason).unwrap()),
+    )
+    .await
+    .unwrap();
+    io.sockets().unwrap()[0].clone().disconnect().unwrap();
+    let reason = tokio::time::timeout(Duration::from_millis(200), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(reason, DisconnectReason::ServerDisconnect);
+    assert!(!client.connected());
+    assert!(client.emit_with_ack::<_, Value>("ack", 1).await.is_err());
+
+    // The client keeps the session alive by answering the pings
+    let client = connect(&url, ClientOptions::new()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    assert!(client.connected());
+    assert_eq!(io.sockets().unwrap().len(), 1);
+}
@@ -0,0 +1,183 @@
+#![warn(
+    clippy::all,
+    clippy::todo,
+    clippy::unused_self,
+    clippy::needless_continue,
+    clippy::needless_borrow,
+    clippy::match_wildcard_for_single_variants,
+    rust_2018_idioms,
+    future_incompatible,
+    nonstandard_style,
+    missing_docs
+)]
+//! Derive macros for [socketioxide](https://docs.rs/socketioxide).
+//!
+//! They are re-exported by socketioxide with the `derive` feature, they should not be used directly.
+use std::collections::HashMap;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derives the `SocketIoEvent` trait for an enum of unit variants, mapping each variant to an event name.
+///
+/// The name of a variant is used as is, unless it is renamed with `#[socketio(rename = "...")]`.
+/// The names of all the variants can be converted with `#[socketio(rename_all = "...")]` on the enum,
+/// with one of `lowercase`, `UPPERCASE`, `snake_case`, `SCREAMING_SNAKE_CASE`, `kebab-case` or `camelCase`.
+///
+/// ```ignore
+/// #[derive(SocketIoEvent)]
+/// #[socketio(rename_all = "snake_case")]
+/// enum OrderEvent {
+///     CreateOrder, // "create_order"
+///     #[socketio(rename = "order:created")]
+///     OrderCreated,
+/// }
+/// ```
+#[proc_macro_derive(SocketIoEvent, attributes(socketio))]
+pub fn derive_socketio_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "SocketIoEvent can only be derived for enums",
+        ));
+    };
+    let rename_all = match parse_attr(&input.attrs, "rename_all")? {
+        Some(lit) => Some(
+            RenameRule::parse(&lit.value())
+                .ok_or_else(|| syn::Error::new(lit.span(), "unknown rename_all rule"))?,
+        ),
+        None => None,
+    };
+
+    let mut names = HashMap::new();
+    let mut variants = Vec::with_capacity(data.variants.len());
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "SocketIoEvent variants must not have fields",
+            ));
+        }
+        let ident = &variant.ident;
+        let name = match parse_attr(&variant.attrs, "rename")? {
+            Some(lit) => lit.value(),
+            None => match rename_all {
+                Some(rule) => rule.apply(&ident.to_string()),
+                None => ident.to_string(),
+            },
+        };
+        if let Some(other) = names.insert(name.clone(), ident) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                format!("duplicate event name \"{name}\", already used by {other}"),
+            ));
+        }
+        variants.push((ident, name));
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let to_name = variants
+        .iter()
+        .map(|(variant, name)| quote!(Self::#variant => #name,));
+    let from_name = variants
+        .iter()
+        .map(|(variant, name)| quote!(#name => ::core::option::Option::Some(Self::#variant),));
+
+    Ok(quote! {
+        impl #impl_generics ::socketioxide::SocketIoEvent for #ident #ty_generics #where_clause {
+            fn name(&self) -> &'static str {
+                match self {
+                    #(#to_name)*
+                }
+            }
+
+            fn from_name(name: &str) -> ::core::option::Option<Self> {
+                match name {
+                    #(#from_name)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+    })
+}
+
+/// Parses the `#[socketio(key = "value")]` attribute with the given key
+fn parse_attr(attrs: &[syn::Attribute], key: &str) -> syn::Result<Option<LitStr>> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("socketio")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) {
+                value = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown socketio attribute"))
+            }
+        })?;
+    }
+    Ok(value)
+}
+
+/// The case conversions of the `rename_all` attribute
+#[derive(Debug, Clone, Copy)]
+enum RenameRule {
+    Lower,
+    Upper,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    Camel,
+}
+
+impl RenameRule {
+    fn parse(rule: &str) -> Option<Self> {
+        match rule {
+            "lowercase" => Some(Self::Lower),
+            "UPPERCASE" => Some(Self::Upper),
+            "snake_case" => Some(Self::Snake),
+            "SCREAMING_SNAKE_CASE" => Some(Self::ScreamingSnake),
+            "kebab-case" => Some(Self::Kebab),
+            "camelCase" => Some(Self::Camel),
+            _ => None,
+        }
+    }
+
+    /// Converts a PascalCase variant name
+    fn apply(self, variant: &str) -> String {
+        match self {
+            Self::Lower => variant.to_ascii_lowercase(),
+            Self::Upper => variant.to_ascii_uppercase(),
+            Self::Snake => split_words(variant, '_'),
+            Self::ScreamingSnake => split_words(variant, '_').to_ascii_uppercase(),
+            Self::Kebab => split_words(variant, '-'),
+            Self::Camel => {
+                let mut chars = variant.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_lowercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// Lowercases a PascalCase name, separating its words with `sep`
+fn split_words(variant: &str, sep: char) -> String {
+    let mut name = String::with_capacity(variant.len() + 4);
+    for (i, c) in variant.char_indices() {
+        if i > 0 && c.is_uppercase() {
+            name.push(sep);
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
@@ -1,7 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use engineioxide::sid::Sid;
 use socketioxide::{
-    packet::{Packet, PacketData},
+    packet::{Packet, PacketData, RawJson},
     ProtocolVersion,
 };
 fn criterion_benchmark(c: &mut Criterion) {
@@ -143,6 +143,24 @@ fn criterion_benchmark(c: &mut Criterion) {
             let _: String = packet.clone().try_into().unwrap();
         })
     });
+
+    // A ~50kb pre-serialized document, e.g. read from a cache
+    let doc: Vec<_> = (0..1000)
+        .map(|i| serde_json::json!({ "id": i, "name": format!("item {i}"), "tags": ["a", "b"] }))
+        .collect();
+    let doc = serde_json::to_string(&doc).unwrap();
+    c.bench_function("Encode packet event with a 50kb document parsed", |b| {
+        b.iter(|| {
+            let data: serde_json::Value = serde_json::from_str(black_box(&doc)).unwrap();
+            let _: String = Packet::event("/", "event", data).into();
+        })
+    });
+    c.bench_function("Encode packet event with a 50kb raw document", |b| {
+        let raw = RawJson::new(doc.clone()).unwrap();
+        b.iter(|| {
+            let _: String = Packet::raw_event("/", "event", black_box(raw.clone())).into();
+        })
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
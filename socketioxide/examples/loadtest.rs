@@ -0,0 +1,113 @@
+//! A load testing client measuring the round-trip time of the [echo probe](socketioxide::SocketIoBuilder::echo_probe).
+//!
+//! It opens `connections` websocket connections to the server and sends `probes` echo probes on each of them,
+//! one at a time, then reports the round-trip time percentiles.
+//! If no address is given, a local server with the echo probe enabled is started.
+//!
+//! ```text
+//! cargo run --release -p socketioxide --example loadtest -- [connections] [probes] [address]
+//! ```
+use std::time::{Duration, Instant};
+
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, SocketIo};
+use tokio_tungstenite::tungstenite::Message;
+
+const DEFAULT_CONNECTIONS: usize = 100;
+const DEFAULT_PROBES: usize = 100;
+
+async fn spawn_local_server() -> Result<String, Box<dyn std::error::Error>> {
+    let (layer, io) = SocketIo::builder().echo_probe(true).build_layer();
+    io.ns("/", |_: SocketRef| {});
+
+    let app = axum::Router::new().layer(layer);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(addr.to_string())
+}
+
+/// Connects to the default namespace and measures the round-trip time of each probe
+async fn run_connection(
+    addr: String,
+    probes: usize,
+) -> Result<Vec<Duration>, Box<dyn std::error::Error + Send + Sync>> {
+    let url = format!("ws://{addr}/socket.io/?EIO=4&transport=websocket");
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+    ws.send(Message::Text("40".into())).await?;
+
+    let mut rtts = Vec::with_capacity(probes);
+    let mut connected = false;
+    let mut sent_at = Instant::now();
+    while let Some(msg) = ws.next().await {
+        let Message::Text(msg) = msg? else { continue };
+        if msg == "2" {
+            ws.send(Message::Text("3".into())).await?;
+            continue;
+        }
+        if msg.starts_with("40") {
+            connected = true;
+        } else if msg.starts_with("43") {
+            rtts.push(sent_at.elapsed());
+        } else {
+            continue;
+        }
+        if connected && rtts.len() == probes {
+            break;
+        }
+        // The ack id is used to send each probe as an acknowledged event
+        sent_at = Instant::now();
+        let probe = format!(r#"42{}["__sioxide_echo","ping"]"#, rtts.len());
+        ws.send(Message::Text(probe)).await?;
+    }
+    ws.close(None).await.ok();
+    Ok(rtts)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let i = ((sorted.len() as f64 * p).ceil() as usize).saturating_sub(1);
+    sorted[i.min(sorted.len() - 1)]
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let connections = args.next().map_or(Ok(DEFAULT_CONNECTIONS), |v| v.parse())?;
+    let probes = args.next().map_or(Ok(DEFAULT_PROBES), |v| v.parse())?;
+    let addr = match args.next() {
+        Some(addr) => addr,
+        None => spawn_local_server().await?,
+    };
+
+    println!("sending {probes} probes on {connections} connections to {addr}");
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..connections)
+        .map(|_| tokio::spawn(run_connection(addr.clone(), probes)))
+        .collect();
+    let mut rtts = Vec::with_capacity(connections * probes);
+    for task in tasks {
+        match task.await? {
+            Ok(res) => rtts.extend(res),
+            Err(e) => eprintln!("connection failed: {e}"),
+        }
+    }
+    let elapsed = start.elapsed();
+    if rtts.is_empty() {
+        return Err("no probe was answered, is the echo probe enabled on the server?".into());
+    }
+
+    rtts.sort_unstable();
+    println!(
+        "{} probes in {:.2?} ({:.0} probes/s)",
+        rtts.len(),
+        elapsed,
+        rtts.len() as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "p50: {:.2?}, p99: {:.2?}, max: {:.2?}",
+        percentile(&rtts, 0.5),
+        percentile(&rtts, 0.99),
+        rtts[rtts.len() - 1]
+    );
+    Ok(())
+}
@@ -7,21 +7,32 @@
 use std::{
     fmt::Debug,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
     time::Duration,
 };
 
 use engineioxide::sid::Sid;
 use futures::{
-    future::FusedFuture,
-    stream::{FusedStream, FuturesUnordered},
+    future::{BoxFuture, Either, FusedFuture},
+    stream::{FusedStream, FuturesUnordered, SelectAll},
     Future, Stream,
 };
-use serde::de::DeserializeOwned;
+use serde::de::{DeserializeOwned, Visitor};
 use serde_json::Value;
-use tokio::{sync::oneshot::Receiver, time::Timeout};
+use tokio::{
+    sync::oneshot::{error::RecvError, Receiver},
+    time::{error::Elapsed, Timeout},
+};
 
-use crate::{adapter::Adapter, errors::AckError, extract::SocketRef, packet::Packet, SocketError};
+use crate::{
+    adapter::Adapter,
+    errors::AckError,
+    extract::SocketRef,
+    ns::Namespace,
+    packet::{EncodedEvent, Packet},
+    SocketError,
+};
 
 /// An acknowledgement sent by the client.
 /// It contains the data sent by the client and the binary payloads if there are any.
@@ -36,13 +47,51 @@ pub struct AckResponse<T> {
 
 pub(crate) type AckResult<T = Value> = Result<AckResponse<T>, AckError<()>>;
 
+/// The retry policy of the acknowledgements of a namespace,
+/// set with [`SocketIo::set_ack_retry`](crate::SocketIo::set_ack_retry).
+///
+/// When the acknowledgement of an event times out, the event is emitted again to the socket with a fresh ack id,
+/// up to `attempts` times, before an [`AckError::Timeout`] is returned. The packet is sent again as is,
+/// the data is not serialized again. A late acknowledgement of a previous attempt is ignored.
+///
+/// The retries stop as soon as the socket is disconnected. The client may receive the event several times,
+/// so it should be idempotent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckRetry {
+    /// The maximum number of times the event is emitted again
+    pub attempts: u32,
+    /// The delay before each new attempt
+    pub backoff: Backoff,
+}
+
+/// The delay before emitting again an event whose acknowledgement timed out, see [`AckRetry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// The same delay before each attempt
+    Fixed(Duration),
+    /// The given delay before the first attempt, doubled for each following attempt
+    Exponential(Duration),
+}
+
+impl Backoff {
+    /// Returns the delay before the attempt `n`, starting at 0
+    fn delay(&self, n: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential(delay) => delay.saturating_mul(2u32.saturating_pow(n)),
+        }
+    }
+}
+
+type TimeoutResult<T> = Result<Result<AckResult<T>, RecvError>, Elapsed>;
+
 pin_project_lite::pin_project! {
     /// A [`Future`] of [`AckResponse`] received from the client with its corresponding [`Sid`].
     /// It is used internally by [`AckStream`] and **should not** be used directly.
     pub struct AckResultWithId<T> {
         id: Sid,
         #[pin]
-        result: Timeout<Receiver<AckResult<T>>>,
+        result: Either<Timeout<Receiver<AckResult<T>>>, BoxFuture<'static, AckResult<T>>>,
     }
 }
 
@@ -51,17 +100,73 @@ impl<T> Future for AckResultWithId<T> {
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let project = self.project();
-        match project.result.poll(cx) {
-            Poll::Ready(v) => {
-                let v = match v {
-                    Ok(Ok(Ok(v))) => Ok(v),
-                    Ok(Ok(Err(e))) => Err(e),
-                    Ok(Err(_)) => Err(AckError::Socket(SocketError::Closed(()))),
-                    Err(_) => Err(AckError::Timeout),
+        let v = match project.result.as_pin_mut() {
+            Either::Left(result) => futures::ready!(result.poll(cx)),
+            Either::Right(retry) => return retry.poll(cx).map(|v| (*project.id, v)),
+        };
+        Poll::Ready((*project.id, map_timeout_result(v)))
+    }
+}
+
+fn map_timeout_result<T>(v: TimeoutResult<T>) -> AckResult<T> {
+    match v {
+        Ok(Ok(Ok(v))) => Ok(v),
+        Ok(Ok(Err(e))) => Err(e),
+        Ok(Err(_)) => Err(AckError::Socket(SocketError::Closed(()))),
+        Err(_) => Err(AckError::Timeout),
+    }
+}
+
+impl<T> AckResultWithId<T> {
+    fn new(id: Sid, rx: Receiver<AckResult<T>>, duration: Duration) -> Self {
+        Self {
+            id,
+            result: Either::Left(tokio::time::timeout(duration, rx)),
+        }
+    }
+}
+
+impl AckResultWithId<Value> {
+    /// Waits for the acknowledgement `rx` of the `event` sent to the socket `id` with the `ack` id.
+    /// On timeouts, the event is emitted again with a fresh ack id according to the `retry` policy.
+    fn with_retry<A: Adapter>(
+        ns: Arc<Namespace<A>>,
+        id: Sid,
+        event: Arc<EncodedEvent>,
+        (ack, rx): (i64, Receiver<AckResult<Value>>),
+        duration: Duration,
+        retry: AckRetry,
+    ) -> Self {
+        let fut = async move {
+            let (mut ack, mut rx) = (ack, rx);
+            let closed = || Err(AckError::Socket(SocketError::Closed(())));
+            let mut attempt = 0;
+            loop {
+                let res = tokio::time::timeout(duration, rx).await;
+                // The acknowledgement, or the closed socket error, is returned right away
+                if res.is_ok() {
+                    return map_timeout_result(res);
+                }
+                let Ok(socket) = ns.get_socket(id) else {
+                    return closed();
                 };
-                Poll::Ready((*project.id, v))
+                socket.forget_ack(ack);
+                if attempt == retry.attempts {
+                    return Err(AckError::Timeout);
+                }
+                tokio::time::sleep(retry.backoff.delay(attempt)).await;
+                if !socket.connected() {
+                    return closed();
+                }
+                attempt += 1;
+                #[cfg(feature = "tracing")]
+                tracing::debug!("[sid={id}] ack timeout, emitting again (attempt {attempt})");
+                (ack, rx) = socket.send_encoded_with_ack(&event, None);
             }
-            Poll::Pending => Poll::Pending,
+        };
+        Self {
+            id,
+            result: Either::Right(Box::pin(fut)),
         }
     }
 }
@@ -76,12 +181,21 @@ pin_project_lite::pin_project! {
     /// * As a [`Future`]: It will yield the first [`AckResponse`] received from the client.
     /// Useful when expecting only one acknowledgement.
     ///
+    /// If the packet was not sent to any socket (e.g. an empty room), the stream ends right away
+    /// and the future resolves with an [`AckError::NoTarget`], without waiting for the timeout.
+    ///
     /// If the packet encoding failed an [`serde_json::Error`] is **immediately** returned.
     ///
     /// If the client didn't respond before the timeout, the [`AckStream`] will yield
     /// an [`AckError::Timeout`]. If the data sent by the client is not deserializable as `T`,
     /// an [`AckError::Serde`] will be yielded.
     ///
+    /// The arguments of the client callback are decoded like in javascript:
+    /// * With several arguments, `T` can be a tuple with one element per argument, e.g. `(String, u32)`
+    ///   for `callback("ok", 1)`. If the number of arguments doesn't match, an [`AckError::Decode`] is yielded.
+    /// * With a single argument, `T` can be its type directly, e.g. `String` for `callback("ok")`.
+    /// * Without argument, `T` can be `()`.
+    ///
     /// An [`AckStream`] can be created from:
     /// * The [`SocketRef::emit_with_ack`] method, in this case there will be only one [`AckResponse`].
     /// * The [`Operator::emit_with_ack`] method, in this case there will be as many [`AckResponse`]
@@ -135,6 +249,11 @@ pin_project_lite::pin_project! {
             rx: AckResultWithId<Value>,
             polled: bool,
         },
+
+        Merged {
+            #[pin]
+            streams: SelectAll<Pin<Box<AckInnerStream>>>,
+        },
     }
 }
 
@@ -158,26 +277,61 @@ impl AckInnerStream {
             return AckInnerStream::Stream { rxs };
         }
 
-        let duration = duration.unwrap_or_else(|| sockets.first().unwrap().config.ack_timeout);
+        let first = sockets.first().unwrap();
+        let duration = duration.unwrap_or_else(|| first.ack_timeout());
+        // With a retry policy, the packet is encoded once for all the sockets and attempts
+        let retry = first
+            .ns
+            .ack_retry()
+            .map(|retry| (retry, Arc::new(EncodedEvent::new(packet.clone()))));
         for socket in sockets {
-            let rx = socket.send_with_ack(packet.clone());
-            rxs.push(AckResultWithId {
-                result: tokio::time::timeout(duration, rx),
-                id: socket.id,
-            });
+            let rx = match &retry {
+                Some((retry, event)) => AckResultWithId::with_retry(
+                    socket.ns.clone(),
+                    socket.id,
+                    event.clone(),
+                    socket.send_encoded_with_ack(event, None),
+                    duration,
+                    *retry,
+                ),
+                None => {
+                    let (_, rx) = socket.send_with_ack(packet.clone());
+                    AckResultWithId::new(socket.id, rx, duration)
+                }
+            };
+            rxs.push(rx);
         }
         AckInnerStream::Stream { rxs }
     }
 
+    /// Merges several [`AckInnerStream`] into a single one yielding the acknowledgements of all of them.
+    pub(crate) fn merge(streams: impl IntoIterator<Item = AckInnerStream>) -> Self {
+        AckInnerStream::Merged {
+            streams: futures::stream::select_all(streams.into_iter().map(Box::pin)),
+        }
+    }
+
     /// Creates a new [`AckInnerStream`] from a [`oneshot::Receiver`](tokio) corresponding to the acknowledgement
     /// of a single socket.
     pub fn send(rx: Receiver<AckResult<Value>>, duration: Duration, id: Sid) -> Self {
         AckInnerStream::Fut {
             polled: false,
-            rx: AckResultWithId {
-                id,
-                result: tokio::time::timeout(duration, rx),
-            },
+            rx: AckResultWithId::new(id, rx, duration),
+        }
+    }
+
+    /// Same as [`AckInnerStream::send`] but the `event` is emitted again on timeouts, according to the `retry` policy.
+    pub(crate) fn send_with_retry<A: Adapter>(
+        ns: Arc<Namespace<A>>,
+        id: Sid,
+        event: Arc<EncodedEvent>,
+        ack: (i64, Receiver<AckResult<Value>>),
+        duration: Duration,
+        retry: AckRetry,
+    ) -> Self {
+        AckInnerStream::Fut {
+            polled: false,
+            rx: AckResultWithId::with_retry(ns, id, event, ack, duration, retry),
         }
     }
 }
@@ -191,6 +345,7 @@ impl Stream for AckInnerStream {
         match self.project() {
             Fut { polled, .. } if *polled => Poll::Ready(None),
             Stream { rxs } => rxs.poll_next(cx),
+            Merged { streams } => streams.poll_next(cx),
             Fut { rx, polled } => match rx.poll(cx) {
                 Poll::Ready(val) => {
                     *polled = true;
@@ -205,6 +360,7 @@ impl Stream for AckInnerStream {
         use AckInnerStream::*;
         match self {
             Stream { rxs, .. } => rxs.size_hint(),
+            Merged { streams } => streams.size_hint(),
             Fut { .. } => (1, Some(1)),
         }
     }
@@ -215,6 +371,7 @@ impl FusedStream for AckInnerStream {
         use AckInnerStream::*;
         match self {
             Stream { rxs, .. } => rxs.is_terminated(),
+            Merged { streams } => streams.is_terminated(),
             Fut { polled, .. } => *polled,
         }
     }
@@ -227,9 +384,8 @@ impl Future for AckInnerStream {
         match self.as_mut().poll_next(cx) {
             Poll::Ready(Some(v)) => Poll::Ready(v.1),
             Poll::Pending => Poll::Pending,
-            Poll::Ready(None) => {
-                unreachable!("stream should at least yield 1 value")
-            }
+            // The packet was not sent to any socket
+            Poll::Ready(None) => Poll::Ready(Err(AckError::NoTarget)),
         }
     }
 }
@@ -239,6 +395,7 @@ impl FusedFuture for AckInnerStream {
         use AckInnerStream::*;
         match self {
             Stream { rxs, .. } => rxs.is_terminated(),
+            Merged { streams } => streams.is_terminated(),
             Fut { polled, .. } => *polled,
         }
     }
@@ -297,21 +454,92 @@ impl<T> From<AckInnerStream> for AckStream<T> {
 
 fn map_ack_response<T: DeserializeOwned>(ack: AckResult<Value>) -> AckResult<T> {
     ack.and_then(|v| {
-        serde_json::from_value(v.data)
-            .map(|data| AckResponse {
-                data,
-                binary: v.binary,
-            })
-            .map_err(|e| e.into())
+        decode_ack_data(v.data).map(|data| AckResponse {
+            data,
+            binary: v.binary,
+        })
     })
 }
 
+/// Decodes the arguments of an ack response like the javascript callbacks receive them:
+/// * The array of arguments is decoded as is, e.g. into a tuple with one element per argument.
+/// * A single argument can also be decoded directly, without a one-element tuple or array.
+/// * A response without argument can also be decoded as `()`.
+///
+/// If `T` is a tuple or an array that doesn't have as many elements as the response has arguments,
+/// an [`AckError::Decode`] is returned.
+pub(crate) fn decode_ack_data<T: DeserializeOwned>(data: Value) -> Result<T, AckError<()>> {
+    let err = match T::deserialize(&data) {
+        Ok(data) => return Ok(data),
+        Err(err) => err,
+    };
+    let Value::Array(args) = data else {
+        return Err(err.into());
+    };
+    match (expected_arity::<T>(), args.len()) {
+        (Some(expected), received) if expected != received => {
+            Err(AckError::Decode { expected, received })
+        }
+        (None, 1) => Ok(T::deserialize(&args[0])?),
+        (None, 0) => T::deserialize(&Value::Null).map_err(|_| err.into()),
+        _ => Err(err.into()),
+    }
+}
+
+/// Returns the number of elements of `T` if it is decoded from a tuple (tuples, tuple structs and arrays)
+fn expected_arity<T: DeserializeOwned>() -> Option<usize> {
+    T::deserialize(ArityProbe).err()?.0
+}
+
+/// A deserializer that fails with the number of elements of the tuple requested by the type, if any
+struct ArityProbe;
+
+#[derive(Debug)]
+struct Arity(Option<usize>);
+
+impl std::fmt::Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tuple arity: {:?}", self.0)
+    }
+}
+impl std::error::Error for Arity {}
+impl serde::de::Error for Arity {
+    fn custom<M: std::fmt::Display>(_: M) -> Self {
+        Arity(None)
+    }
+}
+
+impl<'de> serde::Deserializer<'de> for ArityProbe {
+    type Error = Arity;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Arity> {
+        Err(Arity(None))
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, _: V) -> Result<V::Value, Arity> {
+        Err(Arity(Some(len)))
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        len: usize,
+        _: V,
+    ) -> Result<V::Value, Arity> {
+        Err(Arity(Some(len)))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq map struct enum identifier ignored_any
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::sync::Arc;
 
     use engineioxide::sid::Sid;
     use futures::StreamExt;
+    use serde_json::json;
 
     use crate::{adapter::LocalAdapter, ns::Namespace, socket::Socket};
 
@@ -344,6 +572,139 @@ mod test {
         assert!(stream.next().await.is_none());
     }
 
+    fn create_retry_socket(attempts: u32) -> Arc<Socket<LocalAdapter>> {
+        let sid = Sid::new();
+        let ns = Namespace::<LocalAdapter>::new_dummy([sid]);
+        ns.set_ack_timeout(Some(Duration::from_millis(50)));
+        ns.set_ack_retry(Some(AckRetry {
+            attempts,
+            backoff: Backoff::Fixed(Duration::from_millis(10)),
+        }));
+        ns.get_socket(sid).unwrap()
+    }
+
+    #[test]
+    fn decode_ack_arguments() {
+        let args = json!(["ok", 1, true]);
+        let data: (String, u32, bool) = decode_ack_data(args.clone()).unwrap();
+        assert_eq!(data, ("ok".into(), 1, true));
+        let data: Value = decode_ack_data(args.clone()).unwrap();
+        assert_eq!(data, args);
+        assert!(matches!(
+            decode_ack_data::<(String, u32)>(args.clone()),
+            Err(AckError::Decode {
+                expected: 2,
+                received: 3
+            })
+        ));
+        assert!(matches!(
+            decode_ack_data::<[String; 1]>(json!([])),
+            Err(AckError::Decode {
+                expected: 1,
+                received: 0
+            })
+        ));
+        // The arity is right but not the types
+        assert!(matches!(
+            decode_ack_data::<(String, String, bool)>(args),
+            Err(AckError::Serde(_))
+        ));
+
+        // A single argument is unwrapped
+        assert_eq!(decode_ack_data::<String>(json!(["ok"])).unwrap(), "ok");
+        assert_eq!(
+            decode_ack_data::<[String; 1]>(json!(["ok"])).unwrap(),
+            ["ok"]
+        );
+        assert_eq!(
+            decode_ack_data::<Option<String>>(json!(["ok"])).unwrap(),
+            Some("ok".into())
+        );
+        assert_eq!(
+            decode_ack_data::<Vec<u32>>(json!([[1, 2]])).unwrap(),
+            [1, 2]
+        );
+        assert!(matches!(
+            decode_ack_data::<String>(json!(["ok", "ok"])),
+            Err(AckError::Serde(_))
+        ));
+
+        // No argument
+        decode_ack_data::<()>(json!([])).unwrap();
+        assert!(decode_ack_data::<Vec<u32>>(json!([])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn backoff_delay() {
+        let fixed = Backoff::Fixed(Duration::from_millis(10));
+        assert_eq!(fixed.delay(0), Duration::from_millis(10));
+        assert_eq!(fixed.delay(3), Duration::from_millis(10));
+        let exp = Backoff::Exponential(Duration::from_millis(10));
+        assert_eq!(exp.delay(0), Duration::from_millis(10));
+        assert_eq!(exp.delay(3), Duration::from_millis(80));
+        assert!(exp.delay(64) > Duration::from_secs(3600));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ack_retry_until_acked() {
+        let socket = create_retry_socket(2);
+        let stream = socket.emit_with_ack::<_, String>("test", "data").unwrap();
+        let res = tokio::spawn(stream);
+
+        // The first attempt times out after 50ms, the event is emitted again after 10ms with a new ack id
+        tokio::time::sleep(Duration::from_millis(70)).await;
+        assert!(!res.is_finished());
+        socket
+            .clone()
+            .recv(Packet::ack("/", "late".into(), 1).inner)
+            .unwrap();
+        socket
+            .clone()
+            .recv(Packet::ack("/", "test".into(), 2).inner)
+            .unwrap();
+        assert_eq!(res.await.unwrap().unwrap().data, "test");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ack_retry_exhausted() {
+        let socket = create_retry_socket(2);
+        let start = tokio::time::Instant::now();
+        let res = socket
+            .emit_with_ack::<_, String>("test", "data")
+            .unwrap()
+            .await;
+        assert!(matches!(res, Err(AckError::Timeout)));
+        // 3 attempts of 50ms with 2 backoffs of 10ms
+        assert_eq!(start.elapsed(), Duration::from_millis(170));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ack_retry_with_operator_timeout() {
+        let socket = create_retry_socket(1);
+        let start = tokio::time::Instant::now();
+        let res = SocketRef::from(socket)
+            .timeout(Duration::from_millis(20))
+            .emit_with_ack::<_, String>("test", "data")
+            .unwrap()
+            .await;
+        assert!(matches!(res, Err(AckError::Timeout)));
+        assert_eq!(start.elapsed(), Duration::from_millis(50));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ack_retry_stops_on_disconnect() {
+        let socket = create_retry_socket(5);
+        let stream = socket.emit_with_ack::<_, String>("test", "data").unwrap();
+        let res = tokio::spawn(stream);
+        tokio::time::sleep(Duration::from_millis(55)).await;
+        socket.set_connected(false);
+        let res = res.await.unwrap();
+        assert!(matches!(
+            res,
+            Err(AckError::Socket(SocketError::Closed(())))
+        ));
+    }
+
     #[tokio::test]
     async fn ack_stream() {
         let (tx, rx) = tokio::sync::oneshot::channel();
@@ -7,17 +7,22 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     convert::Infallible,
-    sync::{RwLock, Weak},
+    sync::{Arc, Mutex, RwLock, Weak},
+    task::Poll,
     time::Duration,
 };
 
-use engineioxide::sid::Sid;
+use engineioxide::{sid::Sid, TransportType};
+use rand::{rngs::StdRng, seq::index, Rng, SeedableRng};
+use tokio::{runtime::Handle, task::AbortHandle};
+use tokio_util::time::{delay_queue, DelayQueue};
 
 use crate::{
     ack::AckInnerStream,
     errors::{AdapterError, BroadcastError},
+    event_stream::ServerEvent,
     extract::SocketRef,
-    ns::Namespace,
+    ns::{DeliveryFilter, Namespace},
     operators::RoomParam,
     packet::Packet,
     DisconnectError,
@@ -35,6 +40,15 @@ pub enum BroadcastFlags {
     Broadcast,
 }
 
+/// The rooms joined and left by a socket when its rooms are replaced with [`Adapter::set_rooms`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoomsDiff {
+    /// The rooms the socket joined
+    pub joined: Vec<Room>,
+    /// The rooms the socket left
+    pub left: Vec<Room>,
+}
+
 /// Options that can be used to modify the behavior of the broadcast methods.
 #[derive(Clone, Debug, Default)]
 pub struct BroadcastOptions {
@@ -46,6 +60,70 @@ pub struct BroadcastOptions {
     pub except: HashSet<Room>,
     /// The socket id of the sender.
     pub sid: Option<Sid>,
+    /// Only select a random sample of the targeted sockets.
+    pub sample: Option<Sample>,
+    /// Only select the sockets currently connected with this transport.
+    pub transport: Option<TransportType>,
+    /// Skip the sockets not seen alive for more than this duration, see [`Socket::is_stale`](crate::socket::Socket::is_stale).
+    pub max_staleness: Option<Duration>,
+}
+
+/// The outcome of a broadcast on the current server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BroadcastResult {
+    /// The number of sockets the packet was sent to.
+    pub sent: usize,
+    /// The number of sockets skipped by the [`DeliveryFilter`](crate::DeliveryFilter) of the namespace.
+    pub skipped: usize,
+}
+
+impl BroadcastResult {
+    /// Creates a new result, for the adapters reporting the outcome of their broadcasts.
+    pub fn new(sent: usize, skipped: usize) -> Self {
+        Self { sent, skipped }
+    }
+
+    /// Adds the counts of another broadcast
+    pub(crate) fn merge(&mut self, other: BroadcastResult) {
+        self.sent += other.sent;
+        self.skipped += other.skipped;
+    }
+}
+
+/// A random selection of `count` sockets among the sockets targeted by a broadcast.
+///
+/// It should be applied by the adapter once the targeted sockets are resolved,
+/// i.e. after the rooms and the `except` rooms are applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sample {
+    /// The number of sockets to select. If there are fewer targeted sockets, all of them are selected.
+    pub count: usize,
+    /// A seed for the random generator. The same seed and the same targets in the same order
+    /// always give the same selection. If it is not set, the thread-local random generator is used.
+    pub seed: Option<u64>,
+}
+
+impl Sample {
+    /// Keep `count` items chosen uniformly at random, in their original order.
+    pub fn apply<T>(&self, mut items: Vec<T>) -> Vec<T> {
+        fn select<T, R: Rng>(items: &mut Vec<T>, count: usize, rng: &mut R) {
+            let mut keep = vec![false; items.len()];
+            for i in index::sample(rng, items.len(), count) {
+                keep[i] = true;
+            }
+            let mut keep = keep.into_iter();
+            items.retain(|_| keep.next().unwrap());
+        }
+
+        if self.count < items.len() {
+            match self.seed {
+                Some(seed) => select(&mut items, self.count, &mut StdRng::seed_from_u64(seed)),
+                None => select(&mut items, self.count, &mut rand::thread_rng()),
+            }
+        }
+        items
+    }
 }
 //TODO: Make an AsyncAdapter trait
 /// An adapter is responsible for managing the state of the server.
@@ -70,13 +148,68 @@ pub trait Adapter: std::fmt::Debug + Send + Sync + 'static {
 
     /// Adds the socket to all the rooms.
     fn add_all(&self, sid: Sid, rooms: impl RoomParam) -> Result<(), Self::Error>;
+    /// Adds the socket to all the rooms, with an optional time to live for the memberships.
+    ///
+    /// A membership with a TTL is removed when it expires, as if the socket left the room,
+    /// unless it is refreshed by adding the socket to the room again before.
+    /// Without a TTL, the membership is permanent, even if it had a TTL before.
+    ///
+    /// The default implementation ignores the TTL and adds the socket to the rooms permanently,
+    /// adapters supporting expiring memberships should override it.
+    fn add_all_with_ttl(
+        &self,
+        sid: Sid,
+        rooms: impl RoomParam,
+        ttl: Option<Duration>,
+    ) -> Result<(), Self::Error> {
+        let _ = ttl;
+        self.add_all(sid, rooms)
+    }
     /// Removes the socket from the rooms.
     fn del(&self, sid: Sid, rooms: impl RoomParam) -> Result<(), Self::Error>;
     /// Removes the socket from all the rooms.
     fn del_all(&self, sid: Sid) -> Result<(), Self::Error>;
+    /// Moves the socket from the `from` room to the `to` room.
+    /// Returns false if the socket was not in the `from` room, it still joins the `to` room.
+    ///
+    /// The default implementation leaves then joins the rooms, adapters should override it
+    /// to move the socket atomically, so that a broadcast never sees it in neither or both rooms.
+    fn move_room(&self, sid: Sid, from: &str, to: &str) -> Result<bool, Self::Error> {
+        let present = self.socket_rooms(sid)?.iter().any(|room| room == from);
+        self.del(sid, Room::Owned(from.to_string()))?;
+        self.add_all(sid, Room::Owned(to.to_string()))?;
+        Ok(present)
+    }
+
+    /// Replaces the rooms of the socket with the given ones, and returns the rooms joined and left.
+    /// The memberships kept are not modified, e.g. they keep their TTL.
+    ///
+    /// The default implementation leaves then joins the rooms, adapters should override it
+    /// to update the rooms atomically, so that a broadcast sees either the old or the new rooms.
+    fn set_rooms(&self, sid: Sid, rooms: impl RoomParam) -> Result<RoomsDiff, Self::Error> {
+        let rooms: HashSet<Room> = rooms.into_room_iter().collect();
+        let current: HashSet<Room> = self.socket_rooms(sid)?.into_iter().collect();
+        let diff = RoomsDiff {
+            joined: rooms.difference(&current).cloned().collect(),
+            left: current.difference(&rooms).cloned().collect(),
+        };
+        self.del(sid, diff.left.clone())?;
+        self.add_all(sid, diff.joined.clone())?;
+        Ok(diff)
+    }
 
     /// Broadcasts the packet to the sockets that match the [`BroadcastOptions`].
-    fn broadcast(&self, packet: Packet<'_>, opts: BroadcastOptions) -> Result<(), BroadcastError>;
+    ///
+    /// The [`DeliveryFilter`](crate::DeliveryFilter) of the namespace, if any, should be applied
+    /// to each socket just before sending the packet.
+    ///
+    /// The packet must be queued to the local sockets before returning, so that the packets sent to a socket are
+    /// received in the order in which the emit calls completed, see [emit ordering](crate#emit-ordering).
+    fn broadcast(
+        &self,
+        packet: Packet<'_>,
+        opts: BroadcastOptions,
+    ) -> Result<BroadcastResult, BroadcastError>;
 
     /// Broadcasts the packet to the sockets that match the [`BroadcastOptions`] and return a stream of ack responses.
     fn broadcast_with_ack(
@@ -110,19 +243,94 @@ pub trait Adapter: std::fmt::Debug + Send + Sync + 'static {
     /// Returns all the rooms for this adapter.
     fn rooms(&self) -> Result<Vec<Room>, Self::Error>;
 
+    /// Returns the number of sockets in the given room.
+    ///
+    /// The default implementation fetches the sockets of the room, adapters should override it
+    /// to count them without fetching them.
+    fn room_size(&self, room: &str) -> Result<usize, Self::Error> {
+        Ok(self.sockets(Room::Owned(room.to_string()))?.len())
+    }
+
+    /// Returns all the rooms for this adapter with the number of sockets in each of them.
+    ///
+    /// The rooms named after the sid of one of their sockets (e.g. joined with `socket.join(socket.id)`)
+    /// are excluded, unless `include_sid_rooms` is true.
+    ///
+    /// The default implementation queries the rooms one by one, adapters should override it
+    /// to take a snapshot of all the rooms at once.
+    fn room_sizes(&self, include_sid_rooms: bool) -> Result<Vec<(Room, usize)>, Self::Error> {
+        let mut sizes = Vec::new();
+        for room in self.rooms()? {
+            let sids = self.sockets(room.clone())?;
+            if include_sid_rooms || !is_sid_room(&room, |sid| sids.contains(sid)) {
+                sizes.push((room, sids.len()));
+            }
+        }
+        Ok(sizes)
+    }
+
     //TODO: implement
     // fn server_side_emit(&self, packet: Packet, opts: BroadcastOptions) -> Result<u64, Error>;
     // fn persist_session(&self, sid: i64);
     // fn restore_session(&self, sid: i64) -> Session;
 }
 
+/// Returns true if the room is named after the sid of one of its sockets
+fn is_sid_room(room: &str, contains: impl FnOnce(&Sid) -> bool) -> bool {
+    room.parse::<Sid>().is_ok_and(|sid| contains(&sid))
+}
+
+type RoomsMap = RwLock<HashMap<Room, HashSet<Sid>>>;
+
 /// The default adapter. Store the state in memory.
 #[derive(Debug)]
 pub struct LocalAdapter {
-    rooms: RwLock<HashMap<Room, HashSet<Sid>>>,
+    rooms: Arc<RoomsMap>,
+    expiries: Arc<Mutex<Expiries>>,
     ns: Weak<Namespace<Self>>,
 }
 
+/// The room memberships with a TTL, expired by a single sweep task per adapter.
+///
+/// It is always locked before the rooms map so that a membership can't be refreshed while it expires.
+#[derive(Debug, Default)]
+struct Expiries {
+    queue: DelayQueue<(Sid, Room)>,
+    keys: HashMap<(Sid, Room), delay_queue::Key>,
+    sweeper: Option<AbortHandle>,
+}
+
+impl Expiries {
+    /// Sets, refreshes or removes the TTL of a membership
+    fn set(&mut self, sid: Sid, room: Room, ttl: Option<Duration>) {
+        match (self.keys.get(&(sid, room.clone())), ttl) {
+            (Some(key), Some(ttl)) => self.queue.reset(key, ttl),
+            (Some(_), None) => self.remove(sid, &room),
+            (None, Some(ttl)) => {
+                let key = self.queue.insert((sid, room.clone()), ttl);
+                self.keys.insert((sid, room), key);
+            }
+            (None, None) => (),
+        }
+    }
+
+    fn remove(&mut self, sid: Sid, room: &Room) {
+        if let Some(key) = self.keys.remove(&(sid, room.clone())) {
+            self.queue.remove(&key);
+        }
+    }
+
+    fn remove_all(&mut self, sid: Sid) {
+        let Self { queue, keys, .. } = self;
+        keys.retain(|(s, _), key| {
+            if *s == sid {
+                queue.remove(key);
+            }
+            *s != sid
+        });
+    }
+}
+
 impl From<Infallible> for AdapterError {
     fn from(_: Infallible) -> AdapterError {
         unreachable!()
@@ -134,7 +342,8 @@ impl Adapter for LocalAdapter {
 
     fn new(ns: Weak<Namespace<Self>>) -> Self {
         Self {
-            rooms: HashMap::new().into(),
+            rooms: Arc::new(HashMap::new().into()),
+            expiries: Default::default(),
             ns,
         }
     }
@@ -146,6 +355,11 @@ impl Adapter for LocalAdapter {
     fn close(&self) -> Result<(), Infallible> {
         #[cfg(feature = "tracing")]
         tracing::debug!("closing local adapter: {}", self.ns.upgrade().unwrap().path);
+        let mut expiries = self.expiries.lock().unwrap();
+        if let Some(sweeper) = expiries.sweeper.take() {
+            sweeper.abort();
+        }
+        *expiries = Expiries::default();
         let mut rooms = self.rooms.write().unwrap();
         rooms.clear();
         rooms.shrink_to_fit();
@@ -157,42 +371,168 @@ impl Adapter for LocalAdapter {
     }
 
     fn add_all(&self, sid: Sid, rooms: impl RoomParam) -> Result<(), Infallible> {
+        self.add_all_with_ttl(sid, rooms, None)
+    }
+
+    fn add_all_with_ttl(
+        &self,
+        sid: Sid,
+        rooms: impl RoomParam,
+        ttl: Option<Duration>,
+    ) -> Result<(), Infallible> {
+        // The TTLs are registered on the timer of the runtime configured for the server
+        let runtime = ttl.and_then(|_| self.runtime());
+        let _ctx = runtime.as_ref().map(Handle::enter);
+        let mut joined = Vec::new();
+        let mut expiries = self.expiries.lock().unwrap();
         let mut rooms_map = self.rooms.write().unwrap();
         for room in rooms.into_room_iter() {
-            rooms_map.entry(room).or_default().insert(sid);
+            if rooms_map.entry(room.clone()).or_default().insert(sid) {
+                joined.push(room.clone());
+            }
+            expiries.set(sid, room, ttl);
         }
+        drop(rooms_map);
+        if let Some(runtime) = &runtime {
+            self.spawn_sweeper(&mut expiries, runtime);
+        }
+        drop(expiries);
+        self.send_events(joined, |room| ServerEvent::RoomJoin { sid, room });
         Ok(())
     }
 
     fn del(&self, sid: Sid, rooms: impl RoomParam) -> Result<(), Infallible> {
+        let mut left = Vec::new();
+        let mut expiries = self.expiries.lock().unwrap();
         let mut rooms_map = self.rooms.write().unwrap();
         for room in rooms.into_room_iter() {
-            if let Some(room) = rooms_map.get_mut(&room) {
-                room.remove(&sid);
+            expiries.remove(sid, &room);
+            if rooms_map
+                .get_mut(&room)
+                .is_some_and(|sids| sids.remove(&sid))
+            {
+                left.push(room);
             }
         }
+        drop(rooms_map);
+        drop(expiries);
+        self.send_events(left, |room| ServerEvent::RoomLeave { sid, room });
         Ok(())
     }
 
     fn del_all(&self, sid: Sid) -> Result<(), Infallible> {
+        let mut left = Vec::new();
+        let mut expiries = self.expiries.lock().unwrap();
+        expiries.remove_all(sid);
         let mut rooms_map = self.rooms.write().unwrap();
-        for room in rooms_map.values_mut() {
-            room.remove(&sid);
+        for (room, sids) in rooms_map.iter_mut() {
+            if sids.remove(&sid) {
+                left.push(room.clone());
+            }
         }
+        drop(rooms_map);
+        drop(expiries);
+        self.send_events(left, |room| ServerEvent::RoomLeave { sid, room });
         Ok(())
     }
 
-    fn broadcast(&self, packet: Packet<'_>, opts: BroadcastOptions) -> Result<(), BroadcastError> {
+    fn move_room(&self, sid: Sid, from: &str, to: &str) -> Result<bool, Infallible> {
+        let mut events = Vec::with_capacity(2);
+        // Both rooms are updated under the same locks, so that a broadcast sees the socket in exactly one of them
+        let mut expiries = self.expiries.lock().unwrap();
+        let mut rooms_map = self.rooms.write().unwrap();
+        let present = rooms_map.get(from).is_some_and(|sids| sids.contains(&sid));
+        if from != to && present {
+            let from = Room::Owned(from.to_string());
+            expiries.remove(sid, &from);
+            rooms_map.get_mut(&from).unwrap().remove(&sid);
+            events.push(ServerEvent::RoomLeave { sid, room: from });
+        }
+        let to = Room::Owned(to.to_string());
+        if rooms_map.entry(to.clone()).or_default().insert(sid) {
+            events.push(ServerEvent::RoomJoin {
+                sid,
+                room: to.clone(),
+            });
+        }
+        expiries.set(sid, to, None);
+        drop(rooms_map);
+        drop(expiries);
+
+        if let Some(ns) = self.ns.upgrade().filter(|_| !events.is_empty()) {
+            ns.events.send(|| events);
+        }
+        Ok(present)
+    }
+
+    fn set_rooms(&self, sid: Sid, rooms: impl RoomParam) -> Result<RoomsDiff, Infallible> {
+        let rooms: HashSet<Room> = rooms.into_room_iter().collect();
+        let mut diff = RoomsDiff::default();
+        // All the rooms are updated under the same locks, so that a broadcast sees either the old or the new rooms
+        let mut expiries = self.expiries.lock().unwrap();
+        let mut rooms_map = self.rooms.write().unwrap();
+        for (room, sids) in rooms_map.iter_mut() {
+            if !rooms.contains(room) && sids.remove(&sid) {
+                expiries.remove(sid, room);
+                diff.left.push(room.clone());
+            }
+        }
+        for room in rooms {
+            if rooms_map.entry(room.clone()).or_default().insert(sid) {
+                diff.joined.push(room);
+            }
+        }
+        drop(rooms_map);
+        drop(expiries);
+
+        let events = diff
+            .left
+            .iter()
+            .map(|room| ServerEvent::RoomLeave {
+                sid,
+                room: room.clone(),
+            })
+            .chain(diff.joined.iter().map(|room| ServerEvent::RoomJoin {
+                sid,
+                room: room.clone(),
+            }))
+            .collect::<Vec<_>>();
+        if let Some(ns) = self.ns.upgrade().filter(|_| !events.is_empty()) {
+            ns.events.send(|| events);
+        }
+        Ok(diff)
+    }
+
+    fn broadcast(
+        &self,
+        packet: Packet<'_>,
+        opts: BroadcastOptions,
+    ) -> Result<BroadcastResult, BroadcastError> {
         let sockets = self.apply_opts(opts);
+        let filter = self.delivery_filter();
 
         #[cfg(feature = "tracing")]
         tracing::debug!("broadcasting packet to {} sockets", sockets.len());
-        let errors: Vec<_> = sockets
-            .into_iter()
-            .filter_map(|socket| socket.send(packet.clone()).err())
-            .collect();
+        let mut res = BroadcastResult::default();
+        let mut errors = Vec::new();
+        for socket in sockets {
+            if !socket.connected() {
+                // The socket is being disconnected, e.g. because its namespace is deleted
+                continue;
+            }
+            if filter
+                .as_ref()
+                .is_some_and(|f| !f.should_deliver(&socket, &packet.inner))
+            {
+                res.skipped += 1;
+            } else if let Err(e) = socket.send(packet.clone()) {
+                errors.push(e);
+            } else {
+                res.sent += 1;
+            }
+        }
         if errors.is_empty() {
-            Ok(())
+            Ok(res)
         } else {
             Err(errors.into())
         }
@@ -204,7 +544,10 @@ impl Adapter for LocalAdapter {
         opts: BroadcastOptions,
         timeout: Option<Duration>,
     ) -> AckInnerStream {
-        let sockets = self.apply_opts(opts);
+        let mut sockets = self.apply_opts(opts);
+        if let Some(filter) = self.delivery_filter() {
+            sockets.retain(|socket| filter.should_deliver(socket, &packet.inner));
+        }
         #[cfg(feature = "tracing")]
         tracing::debug!(
             "broadcasting packet to {} sockets: {:?}",
@@ -273,36 +616,143 @@ impl Adapter for LocalAdapter {
     fn rooms(&self) -> Result<Vec<Room>, Self::Error> {
         Ok(self.rooms.read().unwrap().keys().cloned().collect())
     }
+
+    fn room_size(&self, room: &str) -> Result<usize, Self::Error> {
+        Ok(self.rooms.read().unwrap().get(room).map_or(0, HashSet::len))
+    }
+
+    fn room_sizes(&self, include_sid_rooms: bool) -> Result<Vec<(Room, usize)>, Self::Error> {
+        let rooms = self.rooms.read().unwrap();
+        let sizes = rooms
+            .iter()
+            .filter(|(room, sids)| {
+                include_sid_rooms || !is_sid_room(room, |sid| sids.contains(sid))
+            })
+            .map(|(room, sids)| (room.clone(), sids.len()))
+            .collect();
+        Ok(sizes)
+    }
+}
+
+impl Drop for LocalAdapter {
+    fn drop(&mut self) {
+        if let Some(sweeper) = self.expiries.lock().unwrap().sweeper.take() {
+            sweeper.abort();
+        }
+    }
 }
 
 impl LocalAdapter {
+    fn delivery_filter(&self) -> Option<DeliveryFilter<Self>> {
+        self.ns.upgrade()?.delivery_filter()
+    }
+
+    /// Returns the runtime configured for the server, or the runtime of the current context
+    fn runtime(&self) -> Option<Handle> {
+        self.ns
+            .upgrade()?
+            .config
+            .engine_config
+            .runtime_handle()
+            .ok()
+    }
+
+    /// Spawns the task removing the expired memberships if it is not running yet.
+    ///
+    /// It waits for the next expiry of the queue, and for a new membership when the queue is empty.
+    fn spawn_sweeper(&self, expiries: &mut Expiries, runtime: &Handle) {
+        if expiries.sweeper.is_some() {
+            return;
+        }
+        let state = self.expiries.clone();
+        let rooms = self.rooms.clone();
+        let ns = self.ns.clone();
+        let sweeper = runtime.spawn(async move {
+            loop {
+                let (sid, room, removed) = futures::future::poll_fn(|cx| {
+                    let mut expiries = state.lock().unwrap();
+                    // When the queue is empty, the task is woken by the next insertion
+                    let Poll::Ready(Some(expired)) = expiries.queue.poll_expired(cx) else {
+                        return Poll::Pending;
+                    };
+                    let (sid, room) = expired.into_inner();
+                    expiries.keys.remove(&(sid, room.clone()));
+                    let removed = rooms
+                        .write()
+                        .unwrap()
+                        .get_mut(&room)
+                        .is_some_and(|sids| sids.remove(&sid));
+                    Poll::Ready((sid, room, removed))
+                })
+                .await;
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!("[sid={sid}] room membership expired: {room}");
+                match ns.upgrade() {
+                    Some(ns) if removed => {
+                        ns.events.send(|| [ServerEvent::RoomLeave { sid, room }]);
+                    }
+                    Some(_) => (),
+                    None => break,
+                }
+            }
+        });
+        expiries.sweeper = Some(sweeper.abort_handle());
+    }
+
+    /// Sends a room event for each of the given rooms to the subscribers of the event stream.
+    fn send_events(&self, rooms: Vec<Room>, event: impl Fn(Room) -> ServerEvent) {
+        if let Some(ns) = self.ns.upgrade().filter(|_| !rooms.is_empty()) {
+            ns.events.send(|| rooms.into_iter().map(event));
+        }
+    }
+
     /// Applies the given `opts` and return the sockets that match.
     fn apply_opts(&self, opts: BroadcastOptions) -> Vec<SocketRef<Self>> {
-        let rooms = opts.rooms;
+        let sample = opts.sample;
+        let transport = opts.transport;
+        let max_staleness = opts.max_staleness;
+        let mut sockets = self.resolve_opts(opts);
+        if let Some(transport) = transport {
+            sockets.retain(|s| s.transport_type() == transport);
+        }
+        if let Some(max_staleness) = max_staleness {
+            sockets.retain(|s| !s.is_stale(max_staleness));
+        }
+        if let Some(sample) = sample {
+            // Sockets are deduplicated and sorted so that a seeded sample is deterministic
+            sockets.sort_by_key(|s| s.id);
+            sockets.dedup_by_key(|s| s.id);
+            sockets = sample.apply(sockets);
+        }
+        sockets
+    }
 
-        let except = self.get_except_sids(&opts.except);
+    fn resolve_opts(&self, opts: BroadcastOptions) -> Vec<SocketRef<Self>> {
         let ns = self.ns.upgrade().unwrap();
-        if !rooms.is_empty() {
-            let rooms_map = self.rooms.read().unwrap();
-            rooms
+        let rooms_map = self.rooms.read().unwrap();
+        // The excluded rooms' sets are checked in place rather than merged into a new set
+        let is_excluded = |sid: &Sid| {
+            opts.except
+                .iter()
+                .any(|room| rooms_map.get(room).is_some_and(|sids| sids.contains(sid)))
+        };
+        let is_sender = |sid: &Sid| opts.sid == Some(*sid);
+        let broadcast = opts.flags.contains(&BroadcastFlags::Broadcast);
+
+        if !opts.rooms.is_empty() {
+            opts.rooms
                 .iter()
                 .filter_map(|room| rooms_map.get(room))
                 .flatten()
-                .filter(|sid| {
-                    !except.contains(*sid)
-                        && (!opts.flags.contains(&BroadcastFlags::Broadcast)
-                            || opts.sid.map(|s| s != **sid).unwrap_or(true))
-                })
+                .filter(|sid| !(is_excluded(sid) || broadcast && is_sender(sid)))
                 .filter_map(|sid| ns.get_socket(*sid).ok())
                 .map(SocketRef::from)
                 .collect()
-        } else if opts.flags.contains(&BroadcastFlags::Broadcast) {
-            let sockets = ns.get_sockets();
-            sockets
+        } else if broadcast {
+            ns.get_sockets()
                 .into_iter()
-                .filter(|socket| {
-                    !except.contains(&socket.id) && opts.sid.map(|s| s != socket.id).unwrap_or(true)
-                })
+                .filter(|socket| !is_excluded(&socket.id) && !is_sender(&socket.id))
                 .map(SocketRef::from)
                 .collect()
         } else if let Some(sock) = opts.sid.and_then(|sid| ns.get_socket(sid).ok()) {
@@ -311,22 +761,12 @@ impl LocalAdapter {
             vec![]
         }
     }
-
-    fn get_except_sids(&self, except: &HashSet<Room>) -> HashSet<Sid> {
-        let mut except_sids = HashSet::new();
-        let rooms_map = self.rooms.read().unwrap();
-        for room in except {
-            if let Some(sockets) = rooms_map.get(room) {
-                except_sids.extend(sockets);
-            }
-        }
-        except_sids
-    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{event_stream::EventSender, SocketIoConfig};
     use std::sync::Arc;
 
     macro_rules! hash_set {
@@ -380,6 +820,335 @@ mod test {
         assert_eq!(rooms_map.get("room2").unwrap().len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_move_room() {
+        let socket = Sid::new();
+        let ns = Namespace::new_dummy([socket]);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        adapter.add_all(socket, ["room1", "room2"]).unwrap();
+
+        assert!(adapter.move_room(socket, "room1", "room3").unwrap());
+        assert_eq!(adapter.room_size("room1").unwrap(), 0);
+        assert_eq!(adapter.room_size("room3").unwrap(), 1);
+
+        // The source room is missing, the socket still joins the target room
+        assert!(!adapter.move_room(socket, "room1", "room4").unwrap());
+        assert_eq!(adapter.room_size("room4").unwrap(), 1);
+        // Moving to the same room or to a room already joined does nothing
+        assert!(adapter.move_room(socket, "room4", "room4").unwrap());
+        assert!(adapter.move_room(socket, "room4", "room2").unwrap());
+        assert_eq!(adapter.room_size("room2").unwrap(), 1);
+        assert_eq!(adapter.room_size("room4").unwrap(), 0);
+        let mut rooms = adapter.socket_rooms(socket).unwrap();
+        rooms.sort();
+        assert_eq!(rooms, ["room2", "room3"]);
+    }
+
+    #[tokio::test]
+    async fn test_set_rooms() {
+        let socket = Sid::new();
+        let ns = Namespace::new_dummy([socket]);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        adapter.add_all(socket, ["room1", "room2"]).unwrap();
+
+        let mut diff = adapter
+            .set_rooms(socket, ["room2", "room3", "room4"])
+            .unwrap();
+        diff.joined.sort();
+        assert_eq!(diff.joined, ["room3", "room4"]);
+        assert_eq!(diff.left, ["room1"]);
+        let mut rooms = adapter.socket_rooms(socket).unwrap();
+        rooms.sort();
+        assert_eq!(rooms, ["room2", "room3", "room4"]);
+
+        // Setting the same rooms again does nothing
+        let diff = adapter
+            .set_rooms(socket, ["room2", "room3", "room4"])
+            .unwrap();
+        assert_eq!(diff, RoomsDiff::default());
+
+        let diff = adapter.set_rooms(socket, Vec::<Room>::new()).unwrap();
+        assert_eq!(diff.left.len(), 3);
+        assert!(adapter.socket_rooms(socket).unwrap().is_empty());
+    }
+
+    /// Replaces the rooms of a socket in a loop while resolving broadcasts from another thread.
+    /// The broadcasts must only see the old or the new rooms, never a partial update.
+    #[test]
+    fn test_set_rooms_concurrent_broadcasts() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let socket = Sid::new();
+        let ns = Namespace::new_dummy([socket]);
+        let adapter = Arc::new(LocalAdapter::new(Arc::downgrade(&ns)));
+        adapter.set_rooms(socket, ["a", "b"]).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let adapter = adapter.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                for i in 0..5000 {
+                    let rooms = if i % 2 == 0 { ["c", "d"] } else { ["a", "b"] };
+                    adapter.set_rooms(socket, rooms).unwrap();
+                }
+                done.store(true, Ordering::SeqCst);
+            })
+        };
+
+        // The socket is either in {a, b} or in {c, d}:
+        // it is always in `a` or `c`, and never in `b` or `c` without being in `a` or `d`
+        let opts = |rooms: [&'static str; 2], except: &[&'static str]| BroadcastOptions {
+            rooms: rooms.into_iter().map(Room::Borrowed).collect(),
+            except: except.iter().copied().map(Room::Borrowed).collect(),
+            ..Default::default()
+        };
+        let mut broadcasts = 0;
+        while !done.load(Ordering::SeqCst) {
+            assert_eq!(adapter.apply_opts(opts(["a", "c"], &[])).len(), 1);
+            assert!(adapter.apply_opts(opts(["b", "c"], &["a", "d"])).is_empty());
+            broadcasts += 1;
+        }
+        writer.join().unwrap();
+        assert!(broadcasts > 0);
+        ns.clean_dummy_sockets();
+    }
+
+    /// Joins, leaves, moves and disconnects sockets from several threads with random interleavings,
+    /// then compares the room sizes with the memberships recomputed from the operations of each thread.
+    #[test]
+    fn test_room_sizes_concurrent() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        use std::{
+            collections::BTreeMap,
+            sync::atomic::{AtomicBool, Ordering},
+        };
+
+        const ROOMS: [&str; 4] = ["room1", "room2", "room3", "room4"];
+        let ns = Namespace::new_dummy([]);
+        let adapter = Arc::new(LocalAdapter::new(Arc::downgrade(&ns)));
+        let seed: u64 = rand::random();
+        let done = Arc::new(AtomicBool::new(false));
+
+        // Each thread owns its sockets and records their memberships
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let adapter = adapter.clone();
+                std::thread::spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(seed + i);
+                    let sids: Vec<Sid> = (0..4).map(|_| Sid::new()).collect();
+                    let mut joined: HashMap<Sid, HashSet<&str>> = HashMap::new();
+                    for _ in 0..500 {
+                        let sid = sids[rng.gen_range(0..sids.len())];
+                        let room = ROOMS[rng.gen_range(0..ROOMS.len())];
+                        let rooms = joined.entry(sid).or_default();
+                        match rng.gen_range(0..10) {
+                            0..=3 => {
+                                adapter.add_all(sid, room).unwrap();
+                                rooms.insert(room);
+                            }
+                            4..=6 => {
+                                adapter.del(sid, room).unwrap();
+                                rooms.remove(room);
+                            }
+                            7..=8 => {
+                                let from = ROOMS[rng.gen_range(0..ROOMS.len())];
+                                adapter.move_room(sid, from, room).unwrap();
+                                rooms.remove(from);
+                                rooms.insert(room);
+                            }
+                            _ => {
+                                adapter.del_all(sid).unwrap();
+                                rooms.clear();
+                            }
+                        }
+                    }
+                    joined
+                })
+            })
+            .collect();
+        let reader = {
+            let (adapter, done) = (adapter.clone(), done.clone());
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    for (room, size) in adapter.room_sizes(true).unwrap() {
+                        assert!(size <= 32, "{room} has {size} sockets");
+                    }
+                }
+            })
+        };
+
+        let mut expected: BTreeMap<&str, usize> = ROOMS.iter().map(|r| (*r, 0)).collect();
+        for writer in writers {
+            for rooms in writer.join().unwrap().into_values() {
+                for room in rooms {
+                    *expected.get_mut(room).unwrap() += 1;
+                }
+            }
+        }
+        done.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+
+        for (room, size) in &expected {
+            assert_eq!(adapter.room_size(room).unwrap(), *size, "seed {seed}");
+        }
+        let sizes: BTreeMap<Room, usize> = adapter.room_sizes(true).unwrap().into_iter().collect();
+        let expected: BTreeMap<Room, usize> = expected
+            .into_iter()
+            .map(|(room, size)| (Room::Borrowed(room), size))
+            .collect();
+        assert_eq!(sizes, expected, "seed {seed}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_add_all_with_ttl_expiry() {
+        let socket = Sid::new();
+        let ns = Namespace::new_dummy([socket]);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        let mut events = ns.events.subscribe();
+        let ttl = Some(Duration::from_secs(5));
+        adapter.add_all_with_ttl(socket, ["typing"], ttl).unwrap();
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServerEvent::RoomJoin { .. }
+        ));
+        adapter.add_all(socket, ["room1"]).unwrap();
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServerEvent::RoomJoin { .. }
+        ));
+
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(adapter.room_size("typing").unwrap(), 1);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(adapter.room_size("typing").unwrap(), 0);
+        assert_eq!(adapter.room_size("room1").unwrap(), 1);
+        let event = events.recv().await.unwrap();
+        assert!(
+            matches!(event, ServerEvent::RoomLeave { sid, ref room } if sid == socket && room == "typing")
+        );
+        assert!(adapter.expiries.lock().unwrap().keys.is_empty());
+    }
+
+    #[test]
+    fn test_add_all_with_ttl_configured_runtime() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let mut config = SocketIoConfig::default();
+        config.engine_config.runtime = Some(rt.handle().clone());
+        let ns: Arc<Namespace<LocalAdapter>> =
+            Namespace::new("/".into(), || {}, EventSender::new(1), Arc::new(config));
+
+        // Called outside of a tokio context, the sweeper runs on the configured runtime
+        let socket = Sid::new();
+        let ttl = Some(Duration::from_millis(10));
+        ns.adapter
+            .add_all_with_ttl(socket, ["typing"], ttl)
+            .unwrap();
+        assert_eq!(ns.adapter.room_size("typing").unwrap(), 1);
+        rt.block_on(async { tokio::time::sleep(Duration::from_millis(50)).await });
+        assert_eq!(ns.adapter.room_size("typing").unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_add_all_with_ttl_refresh() {
+        let socket = Sid::new();
+        let ns = Namespace::new_dummy([socket]);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        let ttl = Some(Duration::from_secs(5));
+        adapter
+            .add_all_with_ttl(socket, ["room1", "room2"], ttl)
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        adapter.add_all_with_ttl(socket, "room1", ttl).unwrap();
+        // Joining without a TTL makes the membership permanent
+        adapter.add_all(socket, "room2").unwrap();
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(adapter.room_size("room1").unwrap(), 1);
+        assert_eq!(adapter.room_size("room2").unwrap(), 1);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(adapter.room_size("room1").unwrap(), 0);
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(adapter.room_size("room2").unwrap(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_add_all_with_ttl_leave_before_expiry() {
+        let socket = Sid::new();
+        let ns = Namespace::new_dummy([socket]);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        let ttl = Some(Duration::from_secs(5));
+        adapter
+            .add_all_with_ttl(socket, ["room1", "room2"], ttl)
+            .unwrap();
+        adapter.del(socket, "room1").unwrap();
+        adapter.del_all(socket).unwrap();
+        assert!(adapter.expiries.lock().unwrap().keys.is_empty());
+
+        // A socket joining again after leaving is not removed by the previous TTL
+        let mut events = ns.events.subscribe();
+        adapter.add_all(socket, "room1").unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(adapter.room_size("room1").unwrap(), 1);
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServerEvent::RoomJoin { .. }
+        ));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_room_size() {
+        let sid1 = Sid::new();
+        let sid2 = Sid::new();
+        let ns = Namespace::new_dummy([sid1, sid2]);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        adapter.add_all(sid1, ["room1", "room2"]).unwrap();
+        adapter.add_all(sid2, ["room1"]).unwrap();
+        assert_eq!(adapter.room_size("room1").unwrap(), 2);
+        assert_eq!(adapter.room_size("room2").unwrap(), 1);
+        assert_eq!(adapter.room_size("room3").unwrap(), 0);
+
+        adapter.del_all(sid1).unwrap();
+        assert_eq!(adapter.room_size("room1").unwrap(), 1);
+        assert_eq!(adapter.room_size("room2").unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_room_sizes() {
+        let sid1 = Sid::new();
+        let sid2 = Sid::new();
+        let ns = Namespace::new_dummy([sid1, sid2]);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        adapter.add_all(sid1, ["room1", "room2"]).unwrap();
+        adapter.add_all(sid2, ["room1"]).unwrap();
+        adapter.add_all(sid1, sid1).unwrap();
+        // A room named after a sid that is not one of its members is a regular room
+        adapter.add_all(sid2, sid1).unwrap();
+        adapter.del(sid1, sid1).unwrap();
+
+        let sorted = |mut sizes: Vec<(Room, usize)>| {
+            sizes.sort();
+            sizes
+        };
+        let mut expected: Vec<(Room, usize)> = vec![
+            ("room1".into(), 2),
+            ("room2".into(), 1),
+            (sid1.to_string().into(), 1),
+        ];
+        expected.sort();
+        assert_eq!(sorted(adapter.room_sizes(false).unwrap()), expected);
+
+        adapter.add_all(sid2, sid2).unwrap();
+        assert_eq!(sorted(adapter.room_sizes(false).unwrap()), expected);
+        expected.push((sid2.to_string().into(), 1));
+        assert_eq!(sorted(adapter.room_sizes(true).unwrap()), sorted(expected));
+    }
+
     #[tokio::test]
     async fn test_socket_room() {
         let sid1 = Sid::new();
@@ -516,6 +1285,39 @@ mod test {
         assert!(sockets.contains(&socket2));
         assert!(sockets.contains(&socket0));
     }
+    #[tokio::test]
+    async fn test_sample() {
+        let sids: [Sid; 10] = std::array::from_fn(|_| Sid::new());
+        let ns = Namespace::new_dummy(sids);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        for sid in &sids[..8] {
+            adapter.add_all(*sid, ["workers"]).unwrap();
+        }
+        adapter.add_all(sids[0], ["busy"]).unwrap();
+
+        let opts = |count, seed| BroadcastOptions {
+            rooms: hash_set!["workers".into()],
+            except: hash_set!["busy".into()],
+            flags: hash_set![BroadcastFlags::Broadcast],
+            sample: Some(Sample { count, seed }),
+            ..Default::default()
+        };
+        let sample = adapter.fetch_sockets(opts(3, Some(42))).unwrap();
+        assert_eq!(sample.len(), 3);
+        assert!(sample.iter().all(|s| sids[1..8].contains(&s.id)));
+
+        // The same seed gives the same selection
+        let ids = |sockets: Vec<SocketRef<LocalAdapter>>| -> Vec<Sid> {
+            sockets.into_iter().map(|s| s.id).collect()
+        };
+        let again = adapter.fetch_sockets(opts(3, Some(42))).unwrap();
+        assert_eq!(ids(sample), ids(again));
+
+        // Asking for more sockets than targeted selects all of them
+        let all = adapter.fetch_sockets(opts(20, None)).unwrap();
+        assert_eq!(all.len(), 7);
+    }
+
     #[tokio::test]
     async fn test_apply_opts() {
         let socket0 = Sid::new();
@@ -578,4 +1380,57 @@ mod test {
         let sockets = adapter.fetch_sockets(opts).unwrap();
         assert_eq!(sockets.len(), 0);
     }
+
+    #[tokio::test]
+    async fn test_except_room() {
+        let sids: [Sid; 4] = std::array::from_fn(|_| Sid::new());
+        let ns = Namespace::new_dummy(sids);
+        let adapter = LocalAdapter::new(Arc::downgrade(&ns));
+        adapter.add_all(sids[0], ["dnd", "room1"]).unwrap();
+        adapter.add_all(sids[1], ["dnd"]).unwrap();
+        adapter.add_all(sids[2], ["room1"]).unwrap();
+
+        let except = |rooms: HashSet<Room>| {
+            let opts = BroadcastOptions {
+                except: rooms,
+                flags: hash_set![BroadcastFlags::Broadcast],
+                ..Default::default()
+            };
+            let mut ids: Vec<Sid> = adapter
+                .fetch_sockets(opts)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        let sorted = |mut ids: Vec<Sid>| {
+            ids.sort();
+            ids
+        };
+
+        // Every socket of the namespace except the ones in the room
+        assert_eq!(
+            except(hash_set!["dnd".into()]),
+            sorted(vec![sids[2], sids[3]])
+        );
+        // Overlapping excluded rooms
+        assert_eq!(
+            except(hash_set!["dnd".into(), "room1".into()]),
+            vec![sids[3]]
+        );
+        // A targeted room disjoint from the excluded room
+        let opts = BroadcastOptions {
+            rooms: hash_set!["room1".into()],
+            except: hash_set!["unrelated".into()],
+            flags: hash_set![BroadcastFlags::Broadcast],
+            ..Default::default()
+        };
+        adapter.add_all(sids[3], ["unrelated"]).unwrap();
+        assert_eq!(adapter.fetch_sockets(opts).unwrap().len(), 2);
+        adapter.del(sids[3], ["unrelated"]).unwrap();
+        // An empty or unknown room excludes nobody
+        assert_eq!(except(hash_set!["unknown".into()]), sorted(sids.to_vec()));
+    }
 }
@@ -1,28 +1,91 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
 
-use engineioxide::handler::EngineIoHandler;
+#[cfg(feature = "polling")]
+use engineioxide::handler::UpgradeReport;
+use engineioxide::handler::{EngineIoHandle, EngineIoHandler, Health, ServerState};
 use engineioxide::socket::{DisconnectReason as EIoDisconnectReason, Socket as EIoSocket};
-use futures::TryFutureExt;
 
 use engineioxide::sid::Sid;
+use futures::future::{select, Either};
+use serde_json::Value;
 use tokio::sync::oneshot;
 
 use crate::adapter::Adapter;
-use crate::handler::ConnectHandler;
+use crate::event_stream::EventSender;
+use crate::extract::SocketRef;
+use crate::handler::{ConnectHandler, NamespaceHandler};
 use crate::ProtocolVersion;
 use crate::{
     errors::Error,
     ns::Namespace,
-    packet::{Packet, PacketData},
+    packet::{self, MalformedEvent, MalformedEventPolicy, Packet, PacketData, PacketError},
+    snapshot::ServerSnapshot,
+    socket::{CloseWarning, CloseWarningReason},
     SocketIoConfig,
 };
 
+type BoxedGlobalConnectHandler<A> = Arc<dyn Fn(SocketRef<A>) + Send + Sync>;
+
+/// A callback called for every socket successfully connected to any namespace
+pub(crate) struct GlobalConnectHandler<A: Adapter>(BoxedGlobalConnectHandler<A>);
+
+impl<A: Adapter> GlobalConnectHandler<A> {
+    pub(crate) fn call(&self, socket: SocketRef<A>) {
+        (self.0)(socket)
+    }
+}
+
+impl<A: Adapter> Clone for GlobalConnectHandler<A> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<A: Adapter> std::fmt::Debug for GlobalConnectHandler<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GlobalConnectHandler").finish()
+    }
+}
+
+/// The engine.io socket of a connection, see [`SocketIo::on_raw_binary`](crate::SocketIo::on_raw_binary)
+pub type EngineSocket = EIoSocket<SocketData>;
+
+/// The handler of the binary messages matching the raw binary filter
+pub(crate) struct RawBinaryHandler(Box<dyn Fn(Vec<u8>, Arc<EngineSocket>) + Send + Sync>);
+
+impl std::fmt::Debug for RawBinaryHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawBinaryHandler").finish()
+    }
+}
+
+type BoxedNamespaceFactory<A> = Box<dyn Fn(&str) -> Option<NamespaceHandler<A>> + Send + Sync>;
+
+/// A factory creating the namespaces that are not registered when a client connects to them
+pub(crate) struct NamespaceFactory<A: Adapter>(BoxedNamespaceFactory<A>);
+
+impl<A: Adapter> std::fmt::Debug for NamespaceFactory<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NamespaceFactory").finish()
+    }
+}
+
 #[derive(Debug)]
 pub struct Client<A: Adapter> {
     pub(crate) config: Arc<SocketIoConfig>,
     ns: RwLock<HashMap<Cow<'static, str>, Arc<Namespace<A>>>>,
+    dyn_ns: RwLock<Vec<NamespaceFactory<A>>>,
+    global_connect: RwLock<Option<GlobalConnectHandler<A>>>,
+    raw_binary: RwLock<Option<RawBinaryHandler>>,
+    limit_violations: AtomicU64,
+    echo_probes: AtomicU64,
+    pub(crate) events: EventSender,
+    /// Set when the engine.io server is started
+    engine: OnceLock<EngineIoHandle<SocketData>>,
 }
 
 impl<A: Adapter> Client<A> {
@@ -31,8 +94,62 @@ impl<A: Adapter> Client<A> {
         crate::state::freeze_state();
 
         Self {
+            events: EventSender::new(config.event_stream_capacity),
             config,
             ns: RwLock::new(HashMap::new()),
+            dyn_ns: RwLock::new(Vec::new()),
+            global_connect: RwLock::new(None),
+            raw_binary: RwLock::new(None),
+            limit_violations: AtomicU64::new(0),
+            echo_probes: AtomicU64::new(0),
+            engine: OnceLock::new(),
+        }
+    }
+
+    /// Returns a handle to the engine.io server, if it is started
+    pub(crate) fn engine(&self) -> Option<&EngineIoHandle<SocketData>> {
+        self.engine.get()
+    }
+
+    /// Returns the [`ServerState`] of the engine.io server.
+    /// A client without server yet is considered as accepting.
+    pub(crate) fn server_state(&self) -> ServerState {
+        self.engine
+            .get()
+            .map_or(ServerState::Accepting, |engine| engine.state())
+    }
+
+    /// Returns the [`Health`] of the engine.io server.
+    /// A client without server yet is considered as accepting, without connections.
+    pub(crate) fn health(&self) -> Health {
+        self.engine.get().map_or(
+            Health {
+                connections: 0,
+                upgrading: 0,
+                draining: false,
+                accepting: true,
+            },
+            |engine| engine.health(),
+        )
+    }
+
+    pub(crate) fn pause_accepting(&self) {
+        if let Some(engine) = self.engine.get() {
+            engine.pause_accepting();
+        }
+    }
+
+    pub(crate) fn resume_accepting(&self) {
+        if let Some(engine) = self.engine.get() {
+            engine.resume_accepting();
+        }
+    }
+
+    #[cfg(feature = "polling")]
+    pub(crate) async fn force_upgrade_or_close(&self, grace: Duration) -> UpgradeReport {
+        match self.engine.get() {
+            Some(engine) => engine.force_upgrade_or_close(grace).await,
+            None => UpgradeReport::default(),
         }
     }
 
@@ -44,17 +161,35 @@ impl<A: Adapter> Client<A> {
         esocket: &Arc<engineioxide::Socket<SocketData>>,
     ) -> Result<(), Error> {
         #[cfg(feature = "tracing")]
-        tracing::debug!("auth: {:?}", auth);
+        tracing::debug!(
+            "auth: {}",
+            self.config.engine_config.payload_logging.format(&auth)
+        );
+
+        if self.too_many_namespaces(ns_path, esocket) {
+            let packet = Packet::connect_error(ns_path, "Too many namespaces").into();
+            esocket.emit(packet).ok();
+            self.on_limit_exceeded(esocket, Error::LimitExceeded("max_namespaces_per_socket"));
+            return Ok(());
+        }
 
-        if let Some(ns) = self.get_ns(ns_path) {
+        if let Some(ns) = self.get_or_create_ns(ns_path) {
             let esocket = esocket.clone();
             let config = self.config.clone();
-            tokio::spawn(async move {
-                if ns
-                    .connect(esocket.id, esocket.clone(), auth, config)
-                    .await
-                    .is_ok()
-                {
+            let global_connect = self.global_connect.read().unwrap().clone();
+            esocket
+                .data
+                .pending_connects
+                .fetch_add(1, Ordering::Relaxed);
+            self.config.engine_config.spawn(async move {
+                let res = ns
+                    .connect(esocket.id, esocket.clone(), auth, config, global_connect)
+                    .await;
+                esocket
+                    .data
+                    .pending_connects
+                    .fetch_sub(1, Ordering::Relaxed);
+                if res.is_ok() {
                     // cancel the connect timeout task for v5
                     if let Some(tx) = esocket.data.connect_recv_tx.lock().unwrap().take() {
                         tx.send(()).ok();
@@ -79,9 +214,24 @@ impl<A: Adapter> Client<A> {
         }
     }
 
+    /// Returns true if connecting the socket to a new namespace would exceed
+    /// the [`SocketIoConfig::max_namespaces_per_socket`] limit.
+    /// Connecting again to a namespace the socket is already connected to is always allowed.
+    fn too_many_namespaces(&self, ns_path: &str, esocket: &EIoSocket<SocketData>) -> bool {
+        let Some(max) = self.config.max_namespaces_per_socket else {
+            return false;
+        };
+        let namespaces = esocket.data.namespaces.lock().unwrap();
+        let pending = esocket.data.pending_connects.load(Ordering::Relaxed);
+        !namespaces.iter().any(|ns| ns == ns_path) && namespaces.len() + pending >= max
+    }
+
     /// Propagate a packet to a its target namespace
     fn sock_propagate_packet(&self, packet: Packet<'_>, sid: Sid) -> Result<(), Error> {
         if let Some(ns) = self.get_ns(&packet.ns) {
+            if self.is_echo_probe(&packet) {
+                return self.answer_echo_probe(&ns, packet, sid);
+            }
             ns.recv(sid, packet.inner)
         } else {
             #[cfg(feature = "tracing")]
@@ -90,6 +240,125 @@ impl<A: Adapter> Client<A> {
         }
     }
 
+    fn is_echo_probe(&self, packet: &Packet<'_>) -> bool {
+        self.config.echo_probe
+            && matches!(&packet.inner, PacketData::Event(e, _, _) | PacketData::BinaryEvent(e, _, _)
+                if *e == self.config.echo_probe_event)
+    }
+
+    /// Answers an echo probe with its arguments and attachments followed by a server timestamp,
+    /// as an acknowledgement if one was requested or as the same event otherwise
+    fn answer_echo_probe(
+        &self,
+        ns: &Namespace<A>,
+        packet: Packet<'_>,
+        sid: Sid,
+    ) -> Result<(), Error> {
+        let socket = ns.get_socket(sid)?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let (e, data, bin, ack) = match packet.inner {
+            PacketData::Event(e, data, ack) => (e, data, vec![], ack),
+            PacketData::BinaryEvent(e, bin, ack) => (e, bin.data, bin.bin, ack),
+            _ => unreachable!("only events are echo probes"),
+        };
+        let mut args = match data {
+            Value::Array(args) => args,
+            data => vec![data],
+        };
+        args.push(timestamp.into());
+        let data = Value::Array(args);
+        let reply = match ack {
+            Some(ack) if bin.is_empty() => Packet::ack(&packet.ns, data, ack),
+            Some(ack) => Packet::bin_ack(&packet.ns, data, bin, ack),
+            None if bin.is_empty() => Packet::event(packet.ns.as_ref(), e, data),
+            None => Packet::bin_event(packet.ns.as_ref(), e, data, bin),
+        };
+        self.echo_probes.fetch_add(1, Ordering::Relaxed);
+        // A probe that can't be answered because the buffer is full is dropped like any other packet
+        socket.send(reply).ok();
+        Ok(())
+    }
+
+    /// Returns the total number of answered echo probes
+    pub(crate) fn echo_probes(&self) -> u64 {
+        self.echo_probes.load(Ordering::Relaxed)
+    }
+
+    /// Check that an incoming packet doesn't exceed the limits of the [`SocketIoConfig`]
+    fn check_limits(&self, packet: &Packet<'_>) -> Result<(), Error> {
+        let config = &self.config;
+        let (event, args, attachments) = match &packet.inner {
+            PacketData::Connect(Some(auth)) if auth.len() > config.max_connect_payload_size => {
+                return Err(Error::LimitExceeded("max_connect_payload_size"))
+            }
+            PacketData::Event(e, data, _) => (Some(e), Some(data), 0),
+            PacketData::EventAck(data, _) => (None, Some(data), 0),
+            PacketData::BinaryEvent(e, bin, _) => (Some(e), Some(&bin.data), bin.payload_count()),
+            PacketData::BinaryAck(bin, _) => (None, Some(&bin.data), bin.payload_count()),
+            _ => (None, None, 0),
+        };
+        if event.is_some_and(|e| e.len() > config.max_event_name_len) {
+            return Err(Error::LimitExceeded("max_event_name_len"));
+        }
+        let args_count = args.map_or(0, |v| v.as_array().map_or(1, Vec::len)) + attachments;
+        if args_count > config.max_args_count {
+            return Err(Error::LimitExceeded("max_args_count"));
+        }
+        if attachments > config.max_attachments {
+            return Err(Error::LimitExceeded("max_attachments"));
+        }
+        Ok(())
+    }
+
+    /// Record a rejected packet for the given socket and close it
+    /// if it has exceeded the [`SocketIoConfig::max_violations`] threshold
+    fn on_limit_exceeded(&self, socket: &EIoSocket<SocketData>, _err: Error) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={}] rejecting packet: {}", socket.id, _err);
+        self.limit_violations.fetch_add(1, Ordering::Relaxed);
+        let violations = socket.data.limit_violations.fetch_add(1, Ordering::Relaxed) + 1;
+        if self
+            .config
+            .max_violations
+            .is_some_and(|max| violations >= max)
+        {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={}] too many limit violations, closing", socket.id);
+            socket.close(EIoDisconnectReason::PacketParsingError);
+        }
+    }
+
+    /// Apply the [`SocketIoConfig::malformed_event_policy`] to a packet that can't be decoded
+    fn on_malformed_packet(&self, socket: &EIoSocket<SocketData>, msg: &str, err: PacketError) {
+        match self.config.malformed_event_policy {
+            MalformedEventPolicy::Drop => (),
+            MalformedEventPolicy::SendError => {
+                let (ns, event) = packet::malformed_packet_info(msg);
+                let data = MalformedEvent {
+                    event,
+                    reason: err.to_string(),
+                };
+                let data = serde_json::to_value(data).unwrap();
+                let packet = Packet::event(ns, "error", data);
+                if let Err(_e) = socket.emit(packet.into()) {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        "[sid={}] error while sending malformed event error: {_e}",
+                        socket.id
+                    );
+                }
+            }
+            MalformedEventPolicy::Close => socket.close(EIoDisconnectReason::PacketParsingError),
+        }
+    }
+
+    /// Returns the total number of rejected packets
+    pub(crate) fn limit_violations(&self) -> u64 {
+        self.limit_violations.load(Ordering::Relaxed)
+    }
+
     /// Spawn a task that will close the socket if it is not connected to a namespace
     /// after the [`SocketIoConfig::connect_timeout`] duration
     fn spawn_connect_timeout_task(&self, socket: Arc<EIoSocket<SocketData>>) {
@@ -98,13 +367,19 @@ impl<A: Adapter> Client<A> {
         let (tx, rx) = oneshot::channel();
         socket.data.connect_recv_tx.lock().unwrap().replace(tx);
 
-        tokio::spawn(
-            tokio::time::timeout(self.config.connect_timeout, rx).map_err(move |_| {
+        // The timeout is created in the spawned task so that it uses the timer of the configured runtime
+        let connect_timeout = self.config.connect_timeout;
+        // The task also exits as soon as the engine.io socket is closed, e.g. when the server is dropped
+        self.config.engine_config.spawn(async move {
+            let timeout = tokio::time::timeout(connect_timeout, rx);
+            let closed = socket.closed();
+            futures::pin_mut!(timeout, closed);
+            if let Either::Left((Err(_), _)) = select(timeout, closed).await {
                 #[cfg(feature = "tracing")]
                 tracing::debug!("connect timeout for socket {}", socket.id);
                 socket.close(EIoDisconnectReason::TransportClose);
-            }),
-        );
+            }
+        });
     }
 
     /// Adds a new namespace handler
@@ -115,27 +390,115 @@ impl<A: Adapter> Client<A> {
     {
         #[cfg(feature = "tracing")]
         tracing::debug!("adding namespace {}", path);
-        let ns = Namespace::new(path.clone(), callback);
+        let config = self.config.clone();
+        let ns = Namespace::new(path.clone(), callback, self.events.clone(), config);
         self.ns.write().unwrap().insert(path, ns);
     }
 
-    /// Deletes a namespace handler
+    /// Deletes a namespace and disconnects its sockets
     pub fn delete_ns(&self, path: &str) {
         #[cfg(feature = "tracing")]
         tracing::debug!("deleting namespace {}", path);
-        self.ns.write().unwrap().remove(path);
+        let ns = self.ns.write().unwrap().remove(path);
+        if let Some(ns) = ns {
+            ns.delete();
+        }
     }
 
     pub fn get_ns(&self, path: &str) -> Option<Arc<Namespace<A>>> {
         self.ns.read().unwrap().get(path).cloned()
     }
 
-    /// Closes all engine.io connections and all clients
+    /// Adds a factory for dynamic namespaces, called in the order they were added
+    /// when a client connects to a namespace that is not registered
+    pub fn add_dyn_ns<F>(&self, factory: F)
+    where
+        F: Fn(&str) -> Option<NamespaceHandler<A>> + Send + Sync + 'static,
+    {
+        self.dyn_ns
+            .write()
+            .unwrap()
+            .push(NamespaceFactory(Box::new(factory)));
+    }
+
+    /// Sets the callback called for every socket connected to any namespace
+    pub fn set_global_connect_handler<F>(&self, callback: F)
+    where
+        F: Fn(SocketRef<A>) + Send + Sync + 'static,
+    {
+        self.global_connect
+            .write()
+            .unwrap()
+            .replace(GlobalConnectHandler(Arc::new(callback)));
+    }
+
+    /// Sets the handler of the binary messages matching the raw binary filter
+    pub fn set_raw_binary_handler<F>(&self, handler: F)
+    where
+        F: Fn(Vec<u8>, Arc<EngineSocket>) + Send + Sync + 'static,
+    {
+        self.raw_binary
+            .write()
+            .unwrap()
+            .replace(RawBinaryHandler(Box::new(handler)));
+    }
+
+    /// Gets a namespace or creates it with the first dynamic namespace factory matching its path.
+    /// A created namespace is registered like the other ones until it is deleted.
+    fn get_or_create_ns(&self, path: &str) -> Option<Arc<Namespace<A>>> {
+        if let Some(ns) = self.get_ns(path) {
+            return Some(ns);
+        }
+        let handler = self
+            .dyn_ns
+            .read()
+            .unwrap()
+            .iter()
+            .find_map(|factory| (factory.0)(path))?;
+
+        // Another connection may have created the namespace in the meantime
+        let mut nsps = self.ns.write().unwrap();
+        let ns = nsps.entry(Cow::Owned(path.to_string())).or_insert_with(|| {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("creating dynamic namespace {}", path);
+            let path = Cow::Owned(path.to_string());
+            let config = self.config.clone();
+            Namespace::new_boxed(path, handler.0, self.events.clone(), config)
+        });
+        Some(ns.clone())
+    }
+
+    /// Creates a [`ServerSnapshot`] of all the namespaces.
+    /// The namespaces are copied out of the map so that it is not locked while they are snapshotted.
+    pub(crate) fn snapshot(&self) -> Result<ServerSnapshot, A::Error> {
+        let mut ns: Vec<_> = self.ns.read().unwrap().values().cloned().collect();
+        ns.sort_by(|a, b| a.path.cmp(&b.path));
+        let namespaces = ns
+            .iter()
+            .map(|ns| ns.snapshot())
+            .collect::<Result<_, _>>()?;
+        Ok(ServerSnapshot { namespaces })
+    }
+
+    /// Closes all engine.io connections and all clients,
+    /// after the grace period of the [`CloseWarning`] if it is emitted
     #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
-    pub(crate) async fn close(&self) {
+    pub(crate) async fn close(&self, warning: Option<CloseWarning>) {
+        let ns = self.ns.read().unwrap().clone();
+        if let Some(warning) = warning {
+            let grace = ns
+                .values()
+                .flat_map(|ns| ns.get_sockets())
+                .filter_map(|socket| socket.send_close_warning(&warning))
+                .max();
+            if let Some(grace) = grace {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("closing all namespaces in {grace:?}");
+                tokio::time::sleep(grace).await;
+            }
+        }
         #[cfg(feature = "tracing")]
         tracing::debug!("closing all namespaces");
-        let ns = self.ns.read().unwrap().clone();
         futures::future::join_all(ns.values().map(|ns| ns.close())).await;
         #[cfg(feature = "tracing")]
         tracing::debug!("all namespaces closed");
@@ -146,15 +509,62 @@ impl<A: Adapter> Client<A> {
 pub struct SocketData {
     /// Partial binary packet that is being received
     /// Stored here until all the binary payloads are received
+    ///
+    /// It is bound to the engine.io session rather than to a transport so that
+    /// the attachments can still be applied if the session is upgraded in the meantime
     pub partial_bin_packet: Mutex<Option<Packet<'static>>>,
 
     /// Channel used to notify the socket that it has been connected to a namespace for v5
     pub connect_recv_tx: Mutex<Option<oneshot::Sender<()>>>,
+
+    /// The number of packets of this socket rejected because they exceeded one of the limits
+    pub limit_violations: AtomicUsize,
+
+    /// The paths of the namespaces the connection is connected to, in connection order
+    pub namespaces: Mutex<Vec<Cow<'static, str>>>,
+
+    /// The number of namespace connections of this socket that are not completed yet
+    pub pending_connects: AtomicUsize,
+}
+
+impl<A: Adapter> Drop for Client<A> {
+    /// The client is dropped after the engine.io server is torn down,
+    /// the remaining sockets are discarded so that the namespaces can be dropped
+    fn drop(&mut self) {
+        for ns in self.ns.get_mut().unwrap().values() {
+            ns.discard_sockets();
+        }
+    }
 }
 
 impl<A: Adapter> EngineIoHandler for Client<A> {
     type Data = SocketData;
 
+    fn on_start(&self, io: EngineIoHandle<SocketData>) {
+        self.engine.set(io).ok();
+    }
+
+    /// The idle connections are warned with a [`CloseWarning`] on each of their namespaces
+    fn on_close_warning(
+        &self,
+        socket: Arc<EIoSocket<SocketData>>,
+        reason: &EIoDisconnectReason,
+    ) -> Duration {
+        let reason = match reason {
+            EIoDisconnectReason::IdleTimeout => CloseWarningReason::IdleTimeout,
+            _ => return Duration::ZERO,
+        };
+        let warning = CloseWarning::new(reason);
+        self.ns
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|ns| ns.get_socket(socket.id).ok())
+            .filter_map(|socket| socket.send_close_warning(&warning))
+            .max()
+            .unwrap_or_default()
+    }
+
     #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, socket), fields(sid = socket.id.to_string())))]
     fn on_connect(&self, socket: Arc<EIoSocket<SocketData>>) {
         #[cfg(feature = "tracing")]
@@ -202,18 +612,49 @@ impl<A: Adapter> EngineIoHandler for Client<A> {
 
     fn on_message(&self, msg: String, socket: Arc<EIoSocket<SocketData>>) {
         #[cfg(feature = "tracing")]
-        tracing::debug!("Received message: {:?}", msg);
-        let packet = match Packet::try_from(msg) {
+        let _span = socket.span().clone().entered();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            "Received message: {}",
+            self.config.engine_config.payload_logging.format(&msg)
+        );
+
+        // An empty engine.io message carries no socket.io packet, it is ignored rather than
+        // being rejected as an invalid packet, so it can't be mistaken for a CONNECT packet of type 0
+        if msg.is_empty() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("ignoring empty engine.io message");
+            return;
+        }
+
+        // The declared attachment count is checked before deserializing the packet
+        // so that we never wait for an unbounded number of binary payloads
+        if let Some((count, _)) = packet::declared_attachments(&msg) {
+            if count > self.config.max_attachments {
+                self.on_limit_exceeded(&socket, Error::LimitExceeded("max_attachments"));
+                return;
+            }
+        }
+
+        let packet = match Packet::try_from(msg.as_str()) {
             Ok(packet) => packet,
-            Err(_e) => {
+            Err(err) => {
                 #[cfg(feature = "tracing")]
-                tracing::debug!("socket serialization error: {}", _e);
-                socket.close(EIoDisconnectReason::PacketParsingError);
+                tracing::debug!("socket serialization error: {}", err);
+                self.on_malformed_packet(&socket, &msg, err);
                 return;
             }
         };
         #[cfg(feature = "tracing")]
-        tracing::debug!("Packet: {:?}", packet);
+        tracing::debug!(
+            "Packet: {}",
+            self.config.engine_config.payload_logging.format(&packet)
+        );
+
+        if let Err(err) = self.check_limits(&packet) {
+            self.on_limit_exceeded(&socket, err);
+            return;
+        }
 
         let res: Result<(), Error> = match packet.inner {
             PacketData::Connect(auth) => self
@@ -248,6 +689,21 @@ impl<A: Adapter> EngineIoHandler for Client<A> {
     ///
     /// If the packet is complete, it is propagated to the namespace
     fn on_binary(&self, data: Vec<u8>, socket: Arc<EIoSocket<SocketData>>) {
+        let raw = self
+            .config
+            .raw_binary_filter
+            .is_some_and(|filter| filter(&data))
+            && socket.data.partial_bin_packet.lock().unwrap().is_none();
+        if raw {
+            match &*self.raw_binary.read().unwrap() {
+                Some(handler) => (handler.0)(data, socket),
+                None => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("[sid={}] no handler for raw binary message", socket.id);
+                }
+            }
+            return;
+        }
         if apply_payload_on_packet(data, &socket) {
             if let Some(packet) = socket.data.partial_bin_packet.lock().unwrap().take() {
                 if let Err(ref err) = self.sock_propagate_packet(packet, socket.id) {
@@ -331,4 +787,148 @@ mod test {
             .await
             .unwrap_err();
     }
+
+    #[tokio::test]
+    async fn connect_invalid_namespace() {
+        use engineioxide::{socket::test_socket, Packet as EIoPacket};
+        let (_sock, handle) = test_socket(create_client());
+        handle.push(EIoPacket::Message("0/admin,".into())).unwrap();
+        let res: String = Packet::connect_error("/admin", "Invalid namespace").into();
+        assert_eq!(handle.drain(), vec![EIoPacket::Message(res)]);
+    }
+
+    /// Creates a client with close warnings and a socket connected to the `/` namespace
+    async fn create_warned_socket() -> (
+        Arc<Client<LocalAdapter>>,
+        Arc<crate::socket::Socket<LocalAdapter>>,
+        engineioxide::socket::TestSocketHandle<Arc<Client<LocalAdapter>>>,
+    ) {
+        use engineioxide::{socket::test_socket, Packet as EIoPacket};
+        let config = crate::SocketIoConfig {
+            close_warning_grace: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let client = Arc::new(Client::<LocalAdapter>::new(Arc::new(config)));
+        client.add_ns("/".into(), || {});
+        let (sock, handle) = test_socket(client.clone());
+        handle.push(EIoPacket::Message("0".into())).unwrap();
+        let ns = client.get_ns("/").unwrap();
+        let socket = loop {
+            match ns.get_socket(sock.id) {
+                Ok(socket) => break socket,
+                Err(_) => tokio::task::yield_now().await,
+            }
+        };
+        handle.drain();
+        (client, socket, handle)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_warning_before_disconnect() {
+        use engineioxide::Packet as EIoPacket;
+        let (_client, socket, handle) = create_warned_socket().await;
+
+        socket.clone().disconnect().unwrap();
+        let warning = r#"2["server_close_warning",{"reason":"disconnect"}]"#;
+        assert_eq!(handle.drain(), [EIoPacket::Message(warning.into())]);
+
+        tokio::time::sleep(Duration::from_secs(9)).await;
+        assert!(handle.drain().is_empty());
+        assert!(socket.connected());
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(handle.drain(), [EIoPacket::Message("1".into())]);
+        assert!(!socket.connected());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_warning_suppressed() {
+        use engineioxide::Packet as EIoPacket;
+        let (_client, socket, handle) = create_warned_socket().await;
+
+        socket.clone().disconnect_with_warning(None).unwrap();
+        assert_eq!(handle.drain(), [EIoPacket::Message("1".into())]);
+        assert!(!socket.connected());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_warning_before_shutdown() {
+        use engineioxide::Packet as EIoPacket;
+        let (client, socket, handle) = create_warned_socket().await;
+
+        let warning = CloseWarning::new(CloseWarningReason::ServerShutdown)
+            .retry_after(Duration::from_secs(30));
+        let start = tokio::time::Instant::now();
+        let close = tokio::spawn(async move { client.close(Some(warning)).await });
+        tokio::task::yield_now().await;
+        let warning =
+            r#"2["server_close_warning",{"reason":"server_shutdown","retry_after_ms":30000}]"#;
+        assert_eq!(handle.drain(), [EIoPacket::Message(warning.into())]);
+        assert!(socket.connected());
+
+        close.await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+        assert!(!socket.connected());
+    }
+
+    fn create_limited_client(max_violations: Option<usize>) -> super::Client<LocalAdapter> {
+        let config = crate::SocketIoConfig {
+            max_event_name_len: 8,
+            max_args_count: 2,
+            max_attachments: 2,
+            max_connect_payload_size: 16,
+            max_violations,
+            ..Default::default()
+        };
+        let client = Client::<LocalAdapter>::new(std::sync::Arc::new(config));
+        client.add_ns("/".into(), || {});
+        client
+    }
+
+    #[tokio::test]
+    async fn limits_reject_packets() {
+        let client = create_limited_client(None);
+        let (tx, mut rx) = mpsc::channel(1);
+        let close_fn = Box::new(move |_, _| tx.try_send(()).unwrap());
+        let sock = Arc::new(EIoSocket::new_dummy(Sid::new(), close_fn));
+        let packets = [
+            "54294967295-[\"event\",{\"_placeholder\":true,\"num\":0}]",
+            "5999999999999999999999999-[\"event\"]",
+            "63-1[{\"_placeholder\":true,\"num\":0}]",
+            "2[\"very_long_event\"]",
+            "2[\"event\",1,2,3]",
+            "31[1,2,3]",
+            "52-[\"event\",1,{\"_placeholder\":true,\"num\":0},{\"_placeholder\":true,\"num\":1}]",
+            "0{\"token\":\"a_very_long_token\"}",
+        ];
+        for packet in packets {
+            client.on_message(packet.into(), sock.clone());
+        }
+        assert_eq!(client.limit_violations(), packets.len() as u64);
+        assert_eq!(
+            sock.data.limit_violations.load(Ordering::Relaxed),
+            packets.len()
+        );
+        assert!(sock.data.partial_bin_packet.lock().unwrap().is_none());
+        rx.try_recv().unwrap_err();
+
+        // Packets within the limits are not rejected
+        client.on_message("2[\"event\",1,2]".into(), sock.clone());
+        client.on_message(
+            "51-[\"event\",{\"_placeholder\":true,\"num\":0}]".into(),
+            sock,
+        );
+        assert_eq!(client.limit_violations(), packets.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn limits_max_violations() {
+        let client = create_limited_client(Some(2));
+        let (tx, mut rx) = mpsc::channel(1);
+        let close_fn = Box::new(move |_, _| tx.try_send(()).unwrap());
+        let sock = Arc::new(EIoSocket::new_dummy(Sid::new(), close_fn));
+        client.on_message("2[\"very_long_event\"]".into(), sock.clone());
+        rx.try_recv().unwrap_err();
+        client.on_message("2[\"very_long_event\"]".into(), sock.clone());
+        rx.try_recv().unwrap();
+    }
 }
@@ -22,6 +22,9 @@ pub enum Error {
 
     #[error("adapter error: {0}")]
     Adapter(#[from] AdapterError),
+
+    #[error("packet exceeds the configured {0} limit")]
+    LimitExceeded(&'static str),
 }
 
 pub(crate) struct ConnectFail;
@@ -33,10 +36,23 @@ pub enum AckError<T> {
     #[error("cannot deserialize json packet from ack response: {0:?}")]
     Serde(#[from] serde_json::Error),
 
+    /// The ack response doesn't have as many arguments as the elements of the requested tuple
+    #[error("ack response has {received} arguments, expected {expected}")]
+    Decode {
+        /// The number of elements of the requested tuple
+        expected: usize,
+        /// The number of arguments of the ack response
+        received: usize,
+    },
+
     /// The ack response timed out
     #[error("ack timeout error")]
     Timeout,
 
+    /// The packet was not sent to any socket, so no ack response can be received
+    #[error("no socket to acknowledge the packet")]
+    NoTarget,
+
     /// An error happened while broadcasting to other socket.io nodes
     #[error("adapter error: {0}")]
     Adapter(#[from] AdapterError),
@@ -60,7 +76,68 @@ pub enum BroadcastError {
     /// An error occured while broadcasting to other nodes.
     #[error("Adapter error: {0}")]
     Adapter(#[from] AdapterError),
+
+    /// The namespace was deleted with [`SocketIo::delete_ns`](crate::SocketIo::delete_ns).
+    #[error("the namespace is closed")]
+    NamespaceClosed,
+}
+/// Error type for the operators targeting a single socket by its id,
+/// see [`BroadcastOperators::to_socket`](crate::operators::BroadcastOperators::to_socket).
+#[derive(thiserror::Error, Debug)]
+pub enum EmitError {
+    /// No socket with this id is connected to the namespace.
+    #[error("socket {0} not found")]
+    SocketNotFound(Sid),
+
+    /// An error occurred while serializing the JSON packet.
+    #[error("Error serializing JSON packet: {0:?}")]
+    Serialize(#[from] serde_json::Error),
+
+    /// An error occurred while sending the packet to the socket.
+    #[error("Error sending data through the engine.io socket: {0:?}")]
+    Socket(#[from] SocketError<()>),
+
+    /// An error occured while resolving the socket through the adapter.
+    #[error("Adapter error: {0}")]
+    Adapter(#[from] AdapterError),
+
+    /// The client was not seen alive within the allowed staleness,
+    /// see [`Socket::emit_if_alive`](crate::socket::Socket::emit_if_alive).
+    #[error("socket is stale")]
+    Stale,
+
+    /// The namespace was deleted with [`SocketIo::delete_ns`](crate::SocketIo::delete_ns).
+    #[error("the namespace is closed")]
+    NamespaceClosed,
 }
+
+impl<T> From<SendError<T>> for EmitError {
+    fn from(err: SendError<T>) -> Self {
+        match err {
+            SendError::Serialize(err) => EmitError::Serialize(err),
+            SendError::Socket(SocketError::InternalChannelFull(_)) => {
+                EmitError::Socket(SocketError::InternalChannelFull(()))
+            }
+            SendError::Socket(SocketError::Closed(_)) => EmitError::Socket(SocketError::Closed(())),
+        }
+    }
+}
+
+impl From<BroadcastError> for EmitError {
+    fn from(err: BroadcastError) -> Self {
+        match err {
+            // There is at most one error as there is only one targeted socket
+            BroadcastError::Socket(mut errors) => match errors.pop() {
+                Some(err) => EmitError::Socket(err),
+                None => EmitError::Socket(SocketError::Closed(())),
+            },
+            BroadcastError::Serialize(err) => EmitError::Serialize(err),
+            BroadcastError::Adapter(err) => EmitError::Adapter(err),
+            BroadcastError::NamespaceClosed => EmitError::NamespaceClosed,
+        }
+    }
+}
+
 /// Error type for sending operations.
 #[derive(thiserror::Error, Debug)]
 pub enum SendError<T> {
@@ -170,7 +247,37 @@ impl From<&Error> for Option<EIoDisconnectReason> {
             Error::Serialize(_) | Error::InvalidPacketType | Error::InvalidEventName => {
                 Some(PacketParsingError)
             }
-            Error::Adapter(_) | Error::InvalidNamespace => None,
+            Error::Adapter(_) | Error::InvalidNamespace | Error::LimitExceeded(_) => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn source_chaining() {
+        let io_err = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        let err = BroadcastError::from(AdapterError::from(Box::new(io_err) as Box<_>));
+        let source = err
+            .source()
+            .unwrap()
+            .downcast_ref::<AdapterError>()
+            .unwrap();
+        let source = source.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(
+            source.unwrap().kind(),
+            std::io::ErrorKind::ConnectionRefused
+        );
+
+        let err = EmitError::from(serde_json::from_str::<u32>("{").unwrap_err());
+        assert!(err.source().unwrap().is::<serde_json::Error>());
+
+        let err = AckError::<()>::from(SocketError::Closed(()));
+        let source = err.source().unwrap().downcast_ref::<SocketError<()>>();
+        assert!(matches!(source, Some(SocketError::Closed(()))));
+    }
+}
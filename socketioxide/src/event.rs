@@ -0,0 +1,47 @@
+//! Typed event names, to check the event names at compile time instead of using string literals.
+//!
+//! The [`SocketIoEvent`] trait maps the values of a type, usually an enum, to event names.
+//! With the `derive` feature, it can be derived for enums of unit variants:
+//! * The name of a variant is used as is, unless it is renamed with `#[socketio(rename = "...")]`.
+//! * All the names can be converted with `#[socketio(rename_all = "...")]` on the enum, with one of
+//!   `lowercase`, `UPPERCASE`, `snake_case`, `SCREAMING_SNAKE_CASE`, `kebab-case` or `camelCase`.
+//!
+//! Typed events are registered with [`Socket::on_typed`](crate::socket::Socket::on_typed) and emitted with
+//! [`Socket::emit_typed`](crate::socket::Socket::emit_typed). They share the handlers of the string API:
+//! a typed and a string registration for the same name replace each other.
+//!
+//! #### Example
+//! ```
+//! # #[cfg(feature = "derive")]
+//! # {
+//! # use socketioxide::{SocketIo, SocketIoEvent, extract::*};
+//! # use serde_json::Value;
+//! #[derive(Debug, PartialEq, SocketIoEvent)]
+//! #[socketio(rename_all = "snake_case")]
+//! enum OrderEvent {
+//!     CreateOrder,
+//!     #[socketio(rename = "order:created")]
+//!     OrderCreated,
+//! }
+//! assert_eq!(OrderEvent::CreateOrder.name(), "create_order");
+//! assert_eq!(OrderEvent::from_name("order:created"), Some(OrderEvent::OrderCreated));
+//!
+//! let (_, io) = SocketIo::new_svc();
+//! io.ns("/", |socket: SocketRef| {
+//!     socket.on_typed(OrderEvent::CreateOrder, |socket: SocketRef, Data::<Value>(order)| {
+//!         socket.emit_typed(OrderEvent::OrderCreated, order).ok();
+//!     });
+//! });
+//! # }
+//! ```
+
+/// A typed event name. See the [module level documentation](self) for more details.
+pub trait SocketIoEvent: Sized {
+    /// The name of the event
+    fn name(&self) -> &'static str;
+
+    /// Returns the event with the given name, if there is one.
+    ///
+    /// It can be used to match the events received by a catch-all handler.
+    fn from_name(name: &str) -> Option<Self>;
+}
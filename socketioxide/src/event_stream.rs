@@ -0,0 +1,71 @@
+use std::borrow::Cow;
+
+use engineioxide::sid::Sid;
+use tokio::sync::broadcast;
+
+use crate::{adapter::Room, socket::DisconnectReason};
+
+/// A server-side event, received through the stream returned by [`SocketIo::event_stream`](crate::SocketIo::event_stream).
+///
+/// Each event is sent once the corresponding state change is visible,
+/// e.g. a socket is already listed by `fetch_sockets` when its [`Connect`](ServerEvent::Connect) event is received.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    /// A socket connected to a namespace
+    Connect {
+        /// The id of the socket
+        sid: Sid,
+        /// The path of the namespace
+        ns: Cow<'static, str>,
+    },
+    /// A socket disconnected from a namespace
+    Disconnect {
+        /// The id of the socket
+        sid: Sid,
+        /// The path of the namespace
+        ns: Cow<'static, str>,
+        /// The reason of the disconnection
+        reason: DisconnectReason,
+    },
+    /// A socket joined a room it was not in.
+    /// This event is only sent by the default [`LocalAdapter`](crate::adapter::LocalAdapter).
+    RoomJoin {
+        /// The id of the socket
+        sid: Sid,
+        /// The room joined
+        room: Room,
+    },
+    /// A socket left a room, either explicitly or because it disconnected.
+    /// This event is only sent by the default [`LocalAdapter`](crate::adapter::LocalAdapter).
+    RoomLeave {
+        /// The id of the socket
+        sid: Sid,
+        /// The room left
+        room: Room,
+    },
+}
+
+/// The sending half of the server event stream, shared by the client and all its namespaces.
+#[derive(Debug, Clone)]
+pub(crate) struct EventSender(broadcast::Sender<ServerEvent>);
+
+impl EventSender {
+    pub fn new(capacity: usize) -> Self {
+        Self(broadcast::channel(capacity).0)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.0.subscribe()
+    }
+
+    /// Sends the events built by `events` if there is at least one subscriber.
+    /// Nothing is built otherwise.
+    pub fn send<I: IntoIterator<Item = ServerEvent>>(&self, events: impl FnOnce() -> I) {
+        if self.0.receiver_count() > 0 {
+            for event in events() {
+                // The only error is when every subscriber was dropped in the meantime
+                self.0.send(event).ok();
+            }
+        }
+    }
+}
@@ -117,7 +117,10 @@ use std::sync::Arc;
 
 use futures::Future;
 
-use crate::{adapter::Adapter, socket::Socket};
+use crate::{
+    adapter::{Adapter, LocalAdapter},
+    socket::Socket,
+};
 
 use super::MakeErasedHandler;
 
@@ -263,6 +266,27 @@ struct ConnectMiddlewareLayer<M, N, T, T1> {
     phantom: std::marker::PhantomData<(T, T1)>,
 }
 
+/// A type erased [`ConnectHandler`] returned by a dynamic namespace factory,
+/// see [`SocketIo::dyn_ns`](crate::SocketIo::dyn_ns).
+pub struct NamespaceHandler<A: Adapter = LocalAdapter>(pub(crate) BoxedConnectHandler<A>);
+
+impl<A: Adapter> NamespaceHandler<A> {
+    /// Wraps a [`ConnectHandler`], with its middlewares if any
+    pub fn new<C, T>(handler: C) -> Self
+    where
+        C: ConnectHandler<A, T>,
+        T: Send + Sync + 'static,
+    {
+        Self(MakeErasedHandler::new_ns_boxed(handler))
+    }
+}
+
+impl<A: Adapter> std::fmt::Debug for NamespaceHandler<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NamespaceHandler").finish()
+    }
+}
+
 impl<A: Adapter, T, H> MakeErasedHandler<H, A, T>
 where
     H: ConnectHandler<A, T> + Send + Sync + 'static,
@@ -386,7 +410,7 @@ macro_rules! impl_handler_async {
                 )*
 
                 let fut = (self.clone())($($ty,)*);
-                tokio::spawn(fut);
+                s.config.engine_config.spawn(fut);
 
             }
         }
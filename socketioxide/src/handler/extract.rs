@@ -19,6 +19,8 @@
 //! * [`TransportType`](crate::TransportType): extracts the transport type
 //! * [`DisconnectReason`]: extracts the reason of the disconnection
 //! * [`State`]: extracts a reference to a state previously set with [`SocketIoBuilder::with_state`](crate::io::SocketIoBuilder).
+//! * [`Extension`]: extracts a clone of a value previously inserted in the [`extensions`](crate::socket::Socket::extensions)
+//!   of the socket, for instance by a middleware.
 //!
 //! ### You can also implement your own Extractor with the [`FromConnectParts`], [`FromMessageParts`] and [`FromDisconnectParts`] traits
 //! When implementing these traits, if you clone the [`Arc<Socket>`] make sure that it is dropped at least when the socket is disconnected.
@@ -87,16 +89,17 @@
 //! io.ns("/", handler);
 //! // Use the service with your favorite http server
 use std::convert::Infallible;
+use std::future::Future;
 use std::sync::Arc;
 
 use super::message::FromMessageParts;
 use super::FromDisconnectParts;
 use super::{connect::FromConnectParts, message::FromMessage};
-use crate::errors::{DisconnectError, SendError};
+use crate::errors::{DisconnectError, SendError, SocketError};
 use crate::socket::DisconnectReason;
 use crate::{
     adapter::{Adapter, LocalAdapter},
-    packet::Packet,
+    packet::RawJson,
     socket::Socket,
 };
 use serde::{de::DeserializeOwned, Serialize};
@@ -106,6 +109,10 @@ use serde_json::Value;
 #[cfg_attr(docsrs, doc(cfg(feature = "state")))]
 pub use state_extract::*;
 
+#[cfg(feature = "extensions")]
+#[cfg_attr(docsrs, doc(cfg(feature = "extensions")))]
+pub use extension_extract::*;
+
 /// Utility function to unwrap an array with a single element
 fn upwrap_array(v: &mut Value) {
     match v {
@@ -120,6 +127,14 @@ fn upwrap_array(v: &mut Value) {
 /// If a deserialization error occurs, the [`ConnectHandler`](super::ConnectHandler) won't be called
 /// and an error log will be print if the `tracing` feature is enabled.
 pub struct Data<T: DeserializeOwned>(pub T);
+impl<T: DeserializeOwned> Data<T> {
+    /// Deserializes the data of an event as the extractor does,
+    /// to be called explicitly from a [`BoxedHandler`](crate::handler::BoxedHandler).
+    pub fn from_value(mut v: Value) -> Result<Self, serde_json::Error> {
+        upwrap_array(&mut v);
+        serde_json::from_value(v).map(Data)
+    }
+}
 impl<T, A> FromConnectParts<A> for Data<T>
 where
     T: DeserializeOwned,
@@ -152,6 +167,14 @@ where
 
 /// An Extractor that returns the deserialized data related to the event.
 pub struct TryData<T: DeserializeOwned>(pub Result<T, serde_json::Error>);
+impl<T: DeserializeOwned> TryData<T> {
+    /// Deserializes the data of an event as the extractor does,
+    /// to be called explicitly from a [`BoxedHandler`](crate::handler::BoxedHandler).
+    pub fn from_value(mut v: Value) -> Self {
+        upwrap_array(&mut v);
+        TryData(serde_json::from_value(v))
+    }
+}
 
 impl<T, A> FromConnectParts<A> for TryData<T>
 where
@@ -184,6 +207,32 @@ where
     }
 }
 /// An Extractor that returns a reference to a [`Socket`].
+///
+/// It is an owned and cheaply cloneable handle that can be stored or moved to another task
+/// (e.g. to emit to a specific client from a timer). Sockets can also be retrieved later
+/// with [`SocketIo::get_socket`](crate::SocketIo::get_socket).
+///
+/// Holding a [`SocketRef`] does not prevent the socket from being disconnected.
+/// Once it is disconnected any emit will return a [`SocketError::Closed`](crate::SocketError::Closed) error.
+///
+/// [`SocketRef::spawn`] moves a clone of the reference to a new task,
+/// so that the task never borrows the socket of the handler.
+/// ```
+/// # use socketioxide::{SocketIo, extract::SocketRef};
+/// # use std::time::Duration;
+/// let (_, io) = SocketIo::new_svc();
+/// io.ns("/", |socket: SocketRef| {
+///     socket.spawn(|socket| async move {
+///         loop {
+///             tokio::time::sleep(Duration::from_secs(1)).await;
+///             // Stop the timer once the socket is disconnected
+///             if socket.emit("tick", ()).is_err() {
+///                 break;
+///             }
+///         }
+///     });
+/// });
+/// ```
 #[derive(Debug)]
 pub struct SocketRef<A: Adapter = LocalAdapter>(Arc<Socket<A>>);
 
@@ -238,6 +287,23 @@ impl<A: Adapter> Clone for SocketRef<A> {
 }
 
 impl<A: Adapter> SocketRef<A> {
+    /// Spawns a task with its own [`SocketRef`] to this socket, on the runtime of the server
+    /// (see [`SocketIoBuilder::runtime`](crate::SocketIoBuilder::runtime)).
+    ///
+    /// The task is not aborted when the socket is disconnected:
+    /// its emits then fail with a [`SocketError::Closed`] error, which can be used to stop it.
+    ///
+    /// # Panics
+    /// If there is no configured runtime and it is called outside of a runtime context.
+    pub fn spawn<F, Fut>(&self, task: F) -> tokio::task::JoinHandle<Fut::Output>
+    where
+        F: FnOnce(SocketRef<A>) -> Fut,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        self.0.config.engine_config.spawn(task(self.clone()))
+    }
+
     /// Disconnect the socket from the current namespace,
     ///
     /// It will also call the disconnect handler if it is set.
@@ -245,6 +311,18 @@ impl<A: Adapter> SocketRef<A> {
     pub fn disconnect(self) -> Result<(), DisconnectError> {
         self.0.disconnect()
     }
+
+    /// Asks the client to reconnect to another server, then disconnects the socket,
+    /// see [`Socket::migrate`].
+    #[inline(always)]
+    pub fn migrate(self, url: &str) -> Result<(), SocketError<()>> {
+        self.0.migrate(url)
+    }
+
+    #[inline(always)]
+    pub(crate) fn migrate_at(self, url: &str, jitter_ratio: f64) -> Result<(), SocketError<()>> {
+        self.0.migrate_at(url, jitter_ratio)
+    }
 }
 
 /// An Extractor that returns the binary data of the message.
@@ -264,6 +342,14 @@ impl<A: Adapter> FromMessage<A> for Bin {
 
 /// An Extractor to send an ack response corresponding to the current event.
 /// If the client sent a normal message without expecting an ack, the ack callback will do nothing.
+/// Use [`AckSender::expects_ack`] to know if an ack response will be sent.
+///
+/// The ack response is sent with the id of the event packet, i.e. `3<id>[data]` (or a binary ack).
+/// [`AckSender::send`] consumes the sender, so an event can't be acknowledged twice with the same sender.
+///
+/// The sender is `Send + 'static`, so the ack can be deferred: it can be moved to a spawned task
+/// or stored in a shared state and sent once the work is done.
+/// If the socket is disconnected in the meantime, [`AckSender::send`] returns an error.
 #[derive(Debug)]
 pub struct AckSender<A: Adapter = LocalAdapter> {
     binary: Vec<Vec<u8>>,
@@ -290,6 +376,22 @@ impl<A: Adapter> AckSender<A> {
         }
     }
 
+    /// Returns true if the client expects an ack response for this event.
+    pub fn expects_ack(&self) -> bool {
+        self.ack_id.is_some()
+    }
+
+    /// Returns the id of the event to acknowledge, if the client expects an ack response.
+    /// It can be used later with [`Socket::ack_raw`].
+    pub fn ack_id(&self) -> Option<i64> {
+        self.ack_id
+    }
+
+    /// Returns true if the socket is disconnected, an ack response can't be sent anymore.
+    pub fn is_closed(&self) -> bool {
+        !self.socket.connected()
+    }
+
     /// Add binary data to the ack response.
     pub fn bin(mut self, bin: Vec<Vec<u8>>) -> Self {
         self.binary = bin;
@@ -297,27 +399,39 @@ impl<A: Adapter> AckSender<A> {
     }
 
     /// Send the ack response to the client.
+    ///
+    /// If the client didn't expect an ack for this event, nothing is sent and `Ok(())` is returned.
+    ///
+    /// Like for the events, a tuple or a sequence is sent as several arguments to the client callback:
+    /// `ack.send(("ok", 1))` calls `callback("ok", 1)`. To send a single array argument, wrap it in a one-element tuple.
+    ///
+    /// # Errors
+    /// If the socket is disconnected or if its buffer is full, a [`SendError::Socket`](crate::SendError::Socket)
+    /// error is returned with the value to send.
     pub fn send<T: Serialize>(self, data: T) -> Result<(), SendError<T>> {
-        use crate::socket::PermitExt;
         if let Some(ack_id) = self.ack_id {
-            let permit = match self.socket.reserve() {
-                Ok(permit) => permit,
-                Err(e) => {
-                    #[cfg(feature = "tracing")]
-                    tracing::debug!("sending error during emit message: {e:?}");
-                    return Err(e.with_value(data).into());
-                }
-            };
-            let ns = self.socket.ns();
-            let data = serde_json::to_value(data)?;
-            let packet = if self.binary.is_empty() {
-                Packet::ack(ns, data, ack_id)
-            } else {
-                Packet::bin_ack(ns, data, self.binary, ack_id)
-            };
-            permit.send(packet);
+            self.socket.send_ack(ack_id, data, self.binary)
+        } else {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("ack sent for an event without ack id, ignoring it");
             Ok(())
+        }
+    }
+
+    /// Send the ack response to the client with pre-serialized JSON data, without parsing it again.
+    ///
+    /// Like with [`AckSender::send`], if the client didn't expect an ack for this event,
+    /// nothing is sent and `Ok(())` is returned.
+    ///
+    /// # Errors
+    /// If the socket is disconnected or if its buffer is full, a [`SendError::Socket`](crate::SendError::Socket)
+    /// error is returned with the value to send.
+    pub fn send_raw_json(self, raw: impl Into<RawJson>) -> Result<(), SendError<RawJson>> {
+        if let Some(ack_id) = self.ack_id {
+            self.socket.send_raw_ack(ack_id, raw.into(), self.binary)
         } else {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("ack sent for an event without ack id, ignoring it");
             Ok(())
         }
     }
@@ -460,3 +574,88 @@ mod state_extract {
         }
     }
 }
+
+#[cfg(feature = "extensions")]
+mod extension_extract {
+    use super::*;
+
+    /// An Extractor that contains a clone of a value previously inserted in the
+    /// [`extensions`](crate::socket::Socket::extensions) of the socket,
+    /// for instance by a [`ConnectMiddleware`](super::super::ConnectMiddleware) that authenticated the user.
+    ///
+    /// The value is cloned without any serialization, wrap it in an [`Arc`] if it is expensive to clone.
+    ///
+    /// If the value is not found, the handler won't be called. For a [`MessageHandler`](super::super::MessageHandler),
+    /// the failure is reported according to the [`ErrorPolicy`](super::super::ErrorPolicy) of the namespace.
+    ///
+    /// ### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::{SocketRef, Extension}, handler::ConnectHandler};
+    /// #[derive(Clone)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    /// fn auth(socket: SocketRef) -> Result<(), &'static str> {
+    ///     socket.extensions.insert(User { name: "alice".into() });
+    ///     Ok(())
+    /// }
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", (|socket: SocketRef| {
+    ///     socket.on("hello", |Extension(user): Extension<User>| {
+    ///         println!("hello from {}", user.name);
+    ///     });
+    /// }).with(auth));
+    /// ```
+    pub struct Extension<T>(pub T);
+
+    /// The value was not found in the extensions of the socket and therefore the handler won't be called.
+    #[derive(Debug, thiserror::Error)]
+    #[error("missing state {0}")]
+    pub struct ExtensionNotFound(pub &'static str);
+
+    impl<T> std::ops::Deref for Extension<T> {
+        type Target = T;
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    fn extract<A: Adapter, T: Clone + Send + Sync + 'static>(
+        s: &Arc<Socket<A>>,
+    ) -> Result<Extension<T>, ExtensionNotFound> {
+        s.extensions
+            .get::<T>()
+            .map(|v| Extension(v.clone()))
+            .ok_or(ExtensionNotFound(std::any::type_name::<T>()))
+    }
+
+    impl<A: Adapter, T: Clone + Send + Sync + 'static> FromConnectParts<A> for Extension<T> {
+        type Error = ExtensionNotFound;
+        fn from_connect_parts(
+            s: &Arc<Socket<A>>,
+            _: &Option<String>,
+        ) -> Result<Self, ExtensionNotFound> {
+            extract(s)
+        }
+    }
+    impl<A: Adapter, T: Clone + Send + Sync + 'static> FromDisconnectParts<A> for Extension<T> {
+        type Error = ExtensionNotFound;
+        fn from_disconnect_parts(
+            s: &Arc<Socket<A>>,
+            _: DisconnectReason,
+        ) -> Result<Self, ExtensionNotFound> {
+            extract(s)
+        }
+    }
+    impl<A: Adapter, T: Clone + Send + Sync + 'static> FromMessageParts<A> for Extension<T> {
+        type Error = ExtensionNotFound;
+        fn from_message_parts(
+            s: &Arc<Socket<A>>,
+            _: &mut serde_json::Value,
+            _: &mut Vec<Vec<u8>>,
+            _: &Option<i64>,
+        ) -> Result<Self, ExtensionNotFound> {
+            extract(s)
+        }
+    }
+}
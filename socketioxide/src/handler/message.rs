@@ -9,6 +9,9 @@
 //!
 //! Handlers can be _optionally_ async.
 //!
+//! Handlers can return a `Result<(), E>` where `E` converts into a [`HandlerError`]. Their errors, as well as the
+//! failures of their extractors, are reported to the client according to the [`ErrorPolicy`] of the namespace.
+//!
 //! ## Example with sync closures
 //! ```rust
 //! # use socketioxide::SocketIo;
@@ -32,7 +35,7 @@
 //!     // `Bin` extractor must be the last argument because it consumes the rest of the packet
 //!     s.on("binary_event", |s: SocketRef, TryData::<String>(data), Bin(bin)| {
 //!       println!("Socket received event with data: {:?} and binary data: {:?}", data, bin);
-//!     })
+//!     });
 //! });
 //! ```
 //!
@@ -50,7 +53,7 @@
 //!     // `Bin` extractor must be the last argument because it consumes the rest of the packet
 //!     s.on("/binary_event", move |s: SocketRef, TryData::<String>(data), Bin(bin)| async move {
 //!       println!("Socket received event with data: {:?} and binary data: {:?}", data, bin);
-//!     })
+//!     });
 //! });
 //! ```
 //!
@@ -71,12 +74,20 @@
 //!     s.on("event_2", on_event);
 //! });
 //! ```
-use std::sync::Arc;
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
-use futures::Future;
+use futures::{future::BoxFuture, Future};
 use serde_json::Value;
+use tokio::sync::Semaphore;
 
-use crate::adapter::Adapter;
+use crate::adapter::{Adapter, LocalAdapter};
+use crate::extract::{AckSender, SocketRef};
 use crate::socket::Socket;
 
 use super::MakeErasedHandler;
@@ -85,7 +96,15 @@ use super::MakeErasedHandler;
 pub(crate) type BoxedMessageHandler<A> = Box<dyn ErasedMessageHandler<A>>;
 
 pub(crate) trait ErasedMessageHandler<A: Adapter>: Send + Sync + 'static {
-    fn call(&self, s: Arc<Socket<A>>, v: Value, p: Vec<Vec<u8>>, ack_id: Option<i64>);
+    fn call(&self, s: Arc<Socket<A>>, e: &str, v: Value, p: Vec<Vec<u8>>, ack_id: Option<i64>);
+    fn call_fut(
+        &self,
+        s: Arc<Socket<A>>,
+        e: &str,
+        v: Value,
+        p: Vec<Vec<u8>>,
+        ack_id: Option<i64>,
+    ) -> Option<BoxFuture<'static, ()>>;
 }
 
 /// Define a handler for the connect event.
@@ -100,8 +119,23 @@ pub(crate) trait ErasedMessageHandler<A: Adapter>: Send + Sync + 'static {
     )
 )]
 pub trait MessageHandler<A: Adapter, T>: Send + Sync + 'static {
-    /// Call the handler with the given arguments
-    fn call(&self, s: Arc<Socket<A>>, v: Value, p: Vec<Vec<u8>>, ack_id: Option<i64>);
+    /// Call the handler of the event `e` with the given arguments.
+    /// A failure of the handler is reported according to the [`ErrorPolicy`] of the namespace.
+    fn call(&self, s: Arc<Socket<A>>, e: &str, v: Value, p: Vec<Vec<u8>>, ack_id: Option<i64>);
+
+    /// Call the handler with the given arguments, and return the future of async handlers instead of spawning it.
+    #[doc(hidden)]
+    fn call_fut(
+        &self,
+        s: Arc<Socket<A>>,
+        e: &str,
+        v: Value,
+        p: Vec<Vec<u8>>,
+        ack_id: Option<i64>,
+    ) -> Option<BoxFuture<'static, ()>> {
+        self.call(s, e, v, p, ack_id);
+        None
+    }
 
     #[doc(hidden)]
     fn phantom(&self) -> std::marker::PhantomData<T> {
@@ -127,8 +161,266 @@ where
     A: Adapter,
 {
     #[inline(always)]
-    fn call(&self, s: Arc<Socket<A>>, v: Value, p: Vec<Vec<u8>>, ack_id: Option<i64>) {
-        self.handler.call(s, v, p, ack_id);
+    fn call(&self, s: Arc<Socket<A>>, e: &str, v: Value, p: Vec<Vec<u8>>, ack_id: Option<i64>) {
+        self.handler.call(s, e, v, p, ack_id);
+    }
+
+    #[inline(always)]
+    fn call_fut(
+        &self,
+        s: Arc<Socket<A>>,
+        e: &str,
+        v: Value,
+        p: Vec<Vec<u8>>,
+        ack_id: Option<i64>,
+    ) -> Option<BoxFuture<'static, ()>> {
+        self.handler.call_fut(s, e, v, p, ack_id)
+    }
+}
+
+/// A message handler stored as a trait object, registered with [`Socket::on_boxed`].
+///
+/// Unlike the [`MessageHandler`]s, its type does not depend on its arguments so handlers can be built at runtime,
+/// e.g. to register handlers in a loop over event names or from a routing table loaded from plugins.
+///
+/// It is called with the socket, the data of the event, as an array if the client sent multiple arguments,
+/// and an [`AckSender`] if the client expects an ack. The binary attachments of the event are not passed.
+/// The extractors can still be used explicitly with [`Data::from_value`](crate::extract::Data::from_value)
+/// and [`TryData::from_value`](crate::extract::TryData::from_value).
+///
+/// #### Example
+/// ```
+/// # use socketioxide::{SocketIo, extract::*, handler::message::BoxedHandler};
+/// # use futures::FutureExt;
+/// let (_, io) = SocketIo::new_svc();
+/// io.ns("/", |socket: SocketRef| {
+///     for event in ["a", "b", "c"] {
+///         let handler: BoxedHandler = Box::new(move |socket, data, ack| {
+///             async move {
+///                 let Data::<String>(data) = Data::from_value(data).unwrap();
+///                 if let Some(ack) = ack {
+///                     ack.send(format!("{event}: {data}")).ok();
+///                 }
+///             }
+///             .boxed()
+///         });
+///         socket.on_boxed(event, handler);
+///     }
+/// });
+/// ```
+pub type BoxedHandler<A = LocalAdapter> =
+    Box<dyn Fn(SocketRef<A>, Value, Option<AckSender<A>>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+impl<A: Adapter> ErasedMessageHandler<A> for BoxedHandler<A> {
+    fn call(&self, s: Arc<Socket<A>>, e: &str, v: Value, p: Vec<Vec<u8>>, ack_id: Option<i64>) {
+        let config = s.config.clone();
+        if let Some(fut) = self.call_fut(s, e, v, p, ack_id) {
+            config.engine_config.spawn(fut);
+        }
+    }
+
+    fn call_fut(
+        &self,
+        s: Arc<Socket<A>>,
+        _: &str,
+        v: Value,
+        _: Vec<Vec<u8>>,
+        ack_id: Option<i64>,
+    ) -> Option<BoxFuture<'static, ()>> {
+        let ack = ack_id.map(|id| AckSender::new(s.clone(), Some(id)));
+        Some(self(SocketRef::from(s), v, ack))
+    }
+}
+
+/// What happens to the invocations of a message handler exceeding its
+/// [`max_concurrent`](crate::socket::MessageHandlerConfig::max_concurrent) limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// The invocation waits for a running one to complete.
+    /// At most the given number of invocations wait, the following ones are rejected.
+    Queue(usize),
+    /// The invocation is rejected. If the client requested an acknowledgement,
+    /// it is answered with a `{ "error": "too many concurrent events" }` object.
+    #[default]
+    Reject,
+    /// The invocation is silently dropped.
+    Drop,
+}
+
+/// The data sent in the acknowledgement of a rejected invocation
+const REJECTED_ACK: &str = "too many concurrent events";
+
+/// The concurrency limit of the async message handler of an event, for a single socket
+#[derive(Debug)]
+pub(crate) struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    policy: QueuePolicy,
+    queued: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_concurrent: usize, policy: QueuePolicy) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            policy,
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// Call the handler, applying the [`QueuePolicy`] if too many invocations are running
+    pub fn call<A: Adapter>(
+        self: &Arc<Self>,
+        handler: &dyn ErasedMessageHandler<A>,
+        s: Arc<Socket<A>>,
+        e: &str,
+        v: Value,
+        p: Vec<Vec<u8>>,
+        ack_id: Option<i64>,
+    ) {
+        let config = s.config.clone();
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            if let Some(fut) = handler.call_fut(s, e, v, p, ack_id) {
+                config.engine_config.spawn(async move {
+                    fut.await;
+                    drop(permit);
+                });
+            }
+            return;
+        }
+
+        match self.policy {
+            QueuePolicy::Queue(max) if self.try_enqueue(max) => {
+                let Some(fut) = handler.call_fut(s, e, v, p, ack_id) else {
+                    self.queued.fetch_sub(1, Ordering::Relaxed);
+                    return;
+                };
+                let limit = self.clone();
+                config.engine_config.spawn(async move {
+                    let permit = limit.semaphore.clone().acquire_owned().await;
+                    limit.queued.fetch_sub(1, Ordering::Relaxed);
+                    fut.await;
+                    drop(permit);
+                });
+            }
+            QueuePolicy::Drop => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("[sid={}] dropping event, too many concurrent events", s.id);
+            }
+            QueuePolicy::Queue(_) | QueuePolicy::Reject => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("[sid={}] rejecting event, too many concurrent events", s.id);
+                if let Some(ack_id) = ack_id {
+                    s.send_ack(ack_id, serde_json::json!({ "error": REJECTED_ACK }), vec![])
+                        .ok();
+                }
+            }
+        }
+    }
+
+    /// Reserves a place in the queue if there are less than `max` waiting invocations
+    fn try_enqueue(&self, max: usize) -> bool {
+        self.queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+                (queued < max).then_some(queued + 1)
+            })
+            .is_ok()
+    }
+}
+
+/// How the failures of the message handlers of a namespace are reported to the client,
+/// set with [`SocketIo::set_error_policy`](crate::SocketIo::set_error_policy).
+///
+/// A handler fails when one of its extractors rejects the event, or when it returns a [`HandlerError`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// The failure is not reported, the client learns nothing.
+    #[default]
+    Silent,
+    /// An event with the given name is emitted to the client with a `{ "event", "message", "code" }` object.
+    EmitEvent(Cow<'static, str>),
+    /// If the client requested an acknowledgement, it is answered with a `{ "error": message, "code": code }` object,
+    /// instead of leaving its callback waiting until the timeout. Otherwise the failure is not reported.
+    AckError,
+}
+
+/// The failure of a message handler, reported to the client according to the [`ErrorPolicy`] of the namespace.
+///
+/// Handlers can return a `Result<(), E>` where `E` converts into a [`HandlerError`].
+/// All the [`std::error::Error`]s convert into a [`HandlerError`] with the `handler_error` code.
+///
+/// #### Example
+/// ```
+/// # use socketioxide::{SocketIo, extract::*, handler::message::{ErrorPolicy, HandlerError}};
+/// let (_, io) = SocketIo::new_svc();
+/// io.ns("/", |socket: SocketRef| {
+///     socket.on("parse", |Data::<String>(data)| -> Result<(), std::num::ParseIntError> {
+///         let _n: u32 = data.parse()?;
+///         Ok(())
+///     });
+///     socket.on("admin", |_: SocketRef| async move {
+///         Err::<(), _>(HandlerError::new("forbidden").with_code("forbidden"))
+///     });
+/// });
+/// io.set_error_policy("/", ErrorPolicy::AckError);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerError {
+    /// A description of the failure
+    pub message: String,
+    /// A machine readable code for the failure
+    pub code: Cow<'static, str>,
+}
+
+impl HandlerError {
+    /// Creates a handler error with the given message and the `handler_error` code
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            code: Cow::Borrowed("handler_error"),
+        }
+    }
+
+    /// Sets the code of the error
+    pub fn with_code(mut self, code: impl Into<Cow<'static, str>>) -> Self {
+        self.code = code.into();
+        self
+    }
+
+    /// The error reported when an extractor rejects the event, with the `invalid_data` code
+    pub(crate) fn extract(err: &dyn std::error::Error) -> Self {
+        Self::new(err.to_string()).with_code("invalid_data")
+    }
+}
+
+impl std::fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl<E: std::error::Error> From<E> for HandlerError {
+    fn from(err: E) -> Self {
+        Self::new(err.to_string())
+    }
+}
+
+/// The return type of the message handlers: `()`, or a `Result<(), E>` where `E` converts into a [`HandlerError`].
+pub trait IntoHandlerResult {
+    /// Converts the return value of the handler into a result
+    fn into_handler_result(self) -> Result<(), HandlerError>;
+}
+
+impl IntoHandlerResult for () {
+    #[inline(always)]
+    fn into_handler_result(self) -> Result<(), HandlerError> {
+        Ok(())
+    }
+}
+
+impl<E: Into<HandlerError>> IntoHandlerResult for Result<(), E> {
+    #[inline(always)]
+    fn into_handler_result(self) -> Result<(), HandlerError> {
+        self.map_err(Into::into)
     }
 }
 
@@ -213,26 +505,64 @@ where
 }
 
 /// Empty Async handler
-impl<A, F, Fut> MessageHandler<A, (private::Async,)> for F
+impl<A, F, Fut, R> MessageHandler<A, (private::Async,)> for F
 where
     F: FnOnce() -> Fut + Send + Sync + Clone + 'static,
-    Fut: Future<Output = ()> + Send + 'static,
+    Fut: Future<Output = R> + Send + 'static,
+    R: IntoHandlerResult,
     A: Adapter,
 {
-    fn call(&self, _: Arc<Socket<A>>, _: Value, _: Vec<Vec<u8>>, _: Option<i64>) {
-        let fut = (self.clone())();
-        tokio::spawn(fut);
+    fn call(&self, s: Arc<Socket<A>>, e: &str, _: Value, _: Vec<Vec<u8>>, ack_id: Option<i64>) {
+        let config = s.config.clone();
+        config
+            .engine_config
+            .spawn(report_async_error(s, e, ack_id, (self.clone())()));
+    }
+
+    fn call_fut(
+        &self,
+        s: Arc<Socket<A>>,
+        e: &str,
+        _: Value,
+        _: Vec<Vec<u8>>,
+        ack_id: Option<i64>,
+    ) -> Option<BoxFuture<'static, ()>> {
+        Some(Box::pin(report_async_error(s, e, ack_id, (self.clone())())))
     }
 }
 
 /// Empty Sync handler
-impl<A, F> MessageHandler<A, (private::Sync,)> for F
+impl<A, F, R> MessageHandler<A, (private::Sync,)> for F
 where
-    F: FnOnce() + Send + Sync + Clone + 'static,
+    F: FnOnce() -> R + Send + Sync + Clone + 'static,
+    R: IntoHandlerResult,
     A: Adapter,
 {
-    fn call(&self, _: Arc<Socket<A>>, _: Value, _: Vec<Vec<u8>>, _: Option<i64>) {
-        (self.clone())();
+    fn call(&self, s: Arc<Socket<A>>, e: &str, _: Value, _: Vec<Vec<u8>>, ack_id: Option<i64>) {
+        if let Err(err) = (self.clone())().into_handler_result() {
+            s.report_handler_error(e, ack_id, err);
+        }
+    }
+}
+
+/// Wraps the future of an async handler so that its error is reported once it completes.
+/// The event name is only kept if the [`ErrorPolicy`] of the namespace reports the failures.
+fn report_async_error<A: Adapter, R: IntoHandlerResult>(
+    s: Arc<Socket<A>>,
+    e: &str,
+    ack_id: Option<i64>,
+    fut: impl Future<Output = R> + Send + 'static,
+) -> impl Future<Output = ()> + Send + 'static {
+    let e = s.ns.reports_errors().then(|| e.to_string());
+    async move {
+        match (fut.await.into_handler_result(), e) {
+            (Err(err), Some(e)) => s.report_handler_error(&e, ack_id, err),
+            (Err(_err), None) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("[sid={}] async handler failed: {_err}", s.id);
+            }
+            (Ok(()), _) => (),
+        }
     }
 }
 
@@ -241,36 +571,52 @@ macro_rules! impl_async_handler {
         [$($ty:ident),*], $last:ident
     ) => {
         #[allow(non_snake_case, unused)]
-        impl<A, F, M, $($ty,)* $last, Fut> MessageHandler<A, (private::Async, M, $($ty,)* $last,)> for F
+        impl<A, F, M, $($ty,)* $last, Fut, R> MessageHandler<A, (private::Async, M, $($ty,)* $last,)> for F
         where
             F: FnOnce($($ty,)* $last,) -> Fut + Send + Sync + Clone + 'static,
-            Fut: Future<Output = ()> + Send + 'static,
+            Fut: Future<Output = R> + Send + 'static,
+            R: IntoHandlerResult,
             A: Adapter,
             $( $ty: FromMessageParts<A> + Send, )*
             $last: FromMessage<A, M> + Send,
         {
-            fn call(&self, s: Arc<Socket<A>>, mut v: Value, mut p: Vec<Vec<u8>>, ack_id: Option<i64>) {
+            fn call(&self, s: Arc<Socket<A>>, e: &str, v: Value, p: Vec<Vec<u8>>, ack_id: Option<i64>) {
+                let config = s.config.clone();
+                if let Some(fut) = self.call_fut(s, e, v, p, ack_id) {
+                    config.engine_config.spawn(fut);
+                }
+            }
+
+            fn call_fut(
+                &self,
+                s: Arc<Socket<A>>,
+                e: &str,
+                mut v: Value,
+                mut p: Vec<Vec<u8>>,
+                ack_id: Option<i64>,
+            ) -> Option<BoxFuture<'static, ()>> {
                 $(
                     let $ty = match $ty::from_message_parts(&s, &mut v, &mut p, &ack_id) {
                         Ok(v) => v,
-                        Err(_e) => {
+                        Err(err) => {
                             #[cfg(feature = "tracing")]
-                            tracing::error!("Error while extracting data: {}", _e);
-                            return;
+                            tracing::error!("Error while extracting data: {}", err);
+                            s.report_handler_error(e, ack_id, HandlerError::extract(&err));
+                            return None;
                         },
                     };
                 )*
-                let last = match $last::from_message(s, v, p, ack_id) {
+                let last = match $last::from_message(s.clone(), v, p, ack_id) {
                     Ok(v) => v,
-                    Err(_e) => {
+                    Err(err) => {
                         #[cfg(feature = "tracing")]
-                        tracing::error!("Error while extracting data: {}", _e);
-                        return;
+                        tracing::error!("Error while extracting data: {}", err);
+                        s.report_handler_error(e, ack_id, HandlerError::extract(&err));
+                        return None;
                     },
                 };
 
-                let fut = (self.clone())($($ty,)* last);
-                tokio::spawn(fut);
+                Some(Box::pin(report_async_error(s, e, ack_id, (self.clone())($($ty,)* last))))
             }
         }
     };
@@ -280,26 +626,29 @@ macro_rules! impl_handler {
         [$($ty:ident),*], $last:ident
     ) => {
         #[allow(non_snake_case, unused)]
-        impl<A, F, M, $($ty,)* $last> MessageHandler<A, (private::Sync, M, $($ty,)* $last,)> for F
+        impl<A, F, M, $($ty,)* $last, R> MessageHandler<A, (private::Sync, M, $($ty,)* $last,)> for F
         where
-            F: FnOnce($($ty,)* $last,) + Send + Sync + Clone + 'static,
+            F: FnOnce($($ty,)* $last,) -> R + Send + Sync + Clone + 'static,
+            R: IntoHandlerResult,
             A: Adapter,
             $( $ty: FromMessageParts<A> + Send, )*
             $last: FromMessage<A, M> + Send,
         {
-            fn call(&self, s: Arc<Socket<A>>, mut v: Value, mut p: Vec<Vec<u8>>, ack_id: Option<i64>) {
+            fn call(&self, s: Arc<Socket<A>>, e: &str, mut v: Value, mut p: Vec<Vec<u8>>, ack_id: Option<i64>) {
                 $(
                     let $ty = match $ty::from_message_parts(&s, &mut v, &mut p, &ack_id) {
                         Ok(v) => v,
-                        Err(_) => return,
+                        Err(err) => return s.report_handler_error(e, ack_id, HandlerError::extract(&err)),
                     };
                 )*
-                let last = match $last::from_message(s, v, p, ack_id) {
+                let last = match $last::from_message(s.clone(), v, p, ack_id) {
                     Ok(v) => v,
-                    Err(_) => return,
+                    Err(err) => return s.report_handler_error(e, ack_id, HandlerError::extract(&err)),
                 };
 
-                (self.clone())($($ty,)* last);
+                if let Err(err) = (self.clone())($($ty,)* last).into_handler_result() {
+                    s.report_handler_error(e, ack_id, err);
+                }
             }
         }
     };
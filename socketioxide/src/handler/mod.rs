@@ -7,11 +7,14 @@ pub mod extract;
 pub mod message;
 
 pub(crate) use connect::BoxedConnectHandler;
-pub use connect::{ConnectHandler, ConnectMiddleware, FromConnectParts};
+pub use connect::{ConnectHandler, ConnectMiddleware, FromConnectParts, NamespaceHandler};
 pub(crate) use disconnect::BoxedDisconnectHandler;
 pub use disconnect::{DisconnectHandler, FromDisconnectParts};
-pub(crate) use message::BoxedMessageHandler;
-pub use message::{FromMessage, FromMessageParts, MessageHandler};
+pub use message::{
+    BoxedHandler, ErrorPolicy, FromMessage, FromMessageParts, HandlerError, IntoHandlerResult,
+    MessageHandler, QueuePolicy,
+};
+pub(crate) use message::{BoxedMessageHandler, ConcurrencyLimit};
 /// A struct used to erase the type of a [`ConnectHandler`] or [`MessageHandler`] so it can be stored in a map
 pub(crate) struct MakeErasedHandler<H, A, T> {
     handler: H,
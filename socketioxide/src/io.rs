@@ -1,22 +1,40 @@
 use std::{borrow::Cow, sync::Arc, time::Duration};
 
+#[cfg(feature = "polling")]
+use engineioxide::handler::UpgradeReport;
 use engineioxide::{
-    config::{EngineIoConfig, EngineIoConfigBuilder},
+    channel::PacketChannel,
+    config::{
+        ClientPingPolicy, EngineIoConfig, EngineIoConfigBuilder, Handshake, HandshakeCookie,
+        OverflowPolicy, PayloadLogging, UpgradeOrigin, Utf8Validation,
+    },
+    handler::{Health, ServerState},
+    rate_limit::HandshakeRateLimit,
+    recorder::SessionRecording,
     service::NotFoundService,
+    session::SessionStore,
     sid::Sid,
+    socket::PacketBuf,
     TransportType,
 };
+use serde_json::Value;
+use tokio::sync::broadcast;
 
 use crate::{
-    ack::AckStream,
-    adapter::{Adapter, LocalAdapter, Room},
-    client::Client,
+    ack::{AckRetry, AckStream},
+    adapter::{Adapter, BroadcastResult, LocalAdapter, Room},
+    client::{Client, EngineSocket},
     extract::SocketRef,
-    handler::ConnectHandler,
+    handler::{ConnectHandler, ErrorPolicy, MakeErasedHandler, MessageHandler, NamespaceHandler},
     layer::SocketIoLayer,
-    operators::{BroadcastOperators, RoomParam},
+    operators::{BroadcastOperators, RoomParam, SocketOperators},
+    packet::{MalformedEventPolicy, RawJson},
+    presence::Presence,
     service::SocketIoService,
-    BroadcastError, DisconnectError,
+    snapshot::ServerSnapshot,
+    socket::{CloseWarning, CloseWarningReason},
+    validation::EventValidator,
+    BroadcastError, DeliveryFilter, DisconnectError, ProtocolVersion, ServerEvent,
 };
 
 /// Configuration for Socket.IO & Engine.IO
@@ -34,6 +52,116 @@ pub struct SocketIoConfig {
     ///
     /// Defaults to 45 seconds.
     pub connect_timeout: Duration,
+
+    /// The maximum length in bytes of an incoming event name.
+    ///
+    /// Defaults to 256 bytes.
+    pub max_event_name_len: usize,
+
+    /// The maximum number of arguments of an incoming event or acknowledgement.
+    ///
+    /// Defaults to 256 arguments.
+    pub max_args_count: usize,
+
+    /// The maximum number of binary attachments of an incoming packet.
+    /// The declared count is checked before any attachment is buffered.
+    ///
+    /// Defaults to 256 attachments.
+    pub max_attachments: usize,
+
+    /// The maximum size in bytes of the payload of an incoming connect packet (the auth data).
+    ///
+    /// Defaults to 10 kb.
+    pub max_connect_payload_size: usize,
+
+    /// The maximum number of namespaces a single connection can be connected to, connecting ones included.
+    /// A connect packet to a new namespace beyond it is rejected with a connect error
+    /// and counts as a violation of the limits (see [`max_violations`](Self::max_violations)),
+    /// so that a client can't allocate the state of thousands of namespaces.
+    ///
+    /// Defaults to `None` (no limit).
+    pub max_namespaces_per_socket: Option<usize>,
+
+    /// The number of rejected packets after which the client is disconnected.
+    /// A packet is rejected when it exceeds one of the limits above.
+    /// If it is `None`, offending packets are only dropped.
+    ///
+    /// Defaults to `None`.
+    pub max_violations: Option<usize>,
+
+    /// What the server does when an incoming packet can't be decoded.
+    ///
+    /// Defaults to [`MalformedEventPolicy::Close`].
+    pub malformed_event_policy: MalformedEventPolicy,
+
+    /// The number of events buffered for each subscriber of the [`SocketIo::event_stream`].
+    /// When a subscriber lags behind, the oldest events are dropped for it.
+    ///
+    /// Defaults to 1024 events.
+    pub event_stream_capacity: usize,
+
+    /// If the server answers the [`echo_probe_event`](Self::echo_probe_event) itself, without calling any handler.
+    /// It is meant to measure the latency of the server for load testing.
+    ///
+    /// Defaults to `false`.
+    pub echo_probe: bool,
+
+    /// The name of the event answered by the echo probe.
+    ///
+    /// Defaults to `__sioxide_echo`.
+    pub echo_probe_event: Cow<'static, str>,
+
+    /// If the auth payload of the sockets is kept and included in the [snapshots](SocketIo::snapshot).
+    /// It may contain user data such as credentials.
+    ///
+    /// Defaults to `false`.
+    pub snapshot_auth: bool,
+
+    /// The name of the event emitted to the sockets asked to reconnect to another server with [`Socket::migrate`](crate::socket::Socket::migrate).
+    ///
+    /// Defaults to `migrate`.
+    pub migration_event: Cow<'static, str>,
+
+    /// The minimum amount of time between the migration event and the disconnection of a migrated socket.
+    ///
+    /// Defaults to 5 seconds.
+    pub migration_delay: Duration,
+
+    /// The maximum random amount of time added to the [`migration_delay`](Self::migration_delay),
+    /// to spread the reconnections of the migrated sockets.
+    ///
+    /// Defaults to 5 seconds.
+    pub migration_jitter: Duration,
+
+    /// The name of the event emitted with a [`CloseWarning`](crate::socket::CloseWarning)
+    /// before the server closes a connection, see [`close_warning_grace`](Self::close_warning_grace).
+    ///
+    /// Defaults to `server_close_warning`.
+    pub close_warning_event: Cow<'static, str>,
+
+    /// The amount of time between the [`close_warning_event`](Self::close_warning_event)
+    /// and the server-initiated close of a connection.
+    /// If it is `None`, no warning is emitted and the connections are closed right away.
+    ///
+    /// Defaults to `None`.
+    pub close_warning_grace: Option<Duration>,
+
+    /// If true, an emit failing because the connection of a socket is closed also disconnects the socket
+    /// right away, with the [`DisconnectReason::TransportError`](crate::socket::DisconnectReason::TransportError) reason.
+    /// It is removed from its namespace and its rooms, so that the following emits and broadcasts fail fast
+    /// instead of targeting a dead socket until the closed connection is handled.
+    ///
+    /// Defaults to `false`.
+    pub strict_emit: bool,
+
+    /// A filter selecting the incoming binary messages of a custom sub-protocol. The matched messages
+    /// bypass the socket.io parsing and are given to the handler registered with [`SocketIo::on_raw_binary`].
+    ///
+    /// While the attachments of a socket.io binary packet are expected, the binary messages are
+    /// always used as attachments, so that the reassembly of the binary packets is unaffected.
+    ///
+    /// Defaults to `None`.
+    pub raw_binary_filter: Option<fn(&[u8]) -> bool>,
 }
 
 impl Default for SocketIoConfig {
@@ -45,103 +173,778 @@ impl Default for SocketIoConfig {
             },
             ack_timeout: Duration::from_secs(5),
             connect_timeout: Duration::from_secs(45),
+            max_event_name_len: 256,
+            max_args_count: 256,
+            max_attachments: 256,
+            max_connect_payload_size: 1e4 as usize, // 10kb
+            max_namespaces_per_socket: None,
+            max_violations: None,
+            malformed_event_policy: MalformedEventPolicy::Close,
+            event_stream_capacity: 1024,
+            echo_probe: false,
+            echo_probe_event: Cow::Borrowed("__sioxide_echo"),
+            snapshot_auth: false,
+            migration_event: Cow::Borrowed("migrate"),
+            migration_delay: Duration::from_secs(5),
+            migration_jitter: Duration::from_secs(5),
+            close_warning_event: Cow::Borrowed("server_close_warning"),
+            close_warning_grace: None,
+            strict_emit: false,
+            raw_binary_filter: None,
+        }
+    }
+}
+
+/// A builder to create a [`SocketIo`] instance.
+/// It contains everything to configure the socket.io server with a [`SocketIoConfig`].
+/// It can be used to build either a Tower [`Layer`](tower::layer::Layer) or a [`Service`](tower::Service).
+pub struct SocketIoBuilder<A: Adapter = LocalAdapter> {
+    config: SocketIoConfig,
+    engine_config_builder: EngineIoConfigBuilder,
+    adapter: std::marker::PhantomData<A>,
+}
+
+impl<A: Adapter> SocketIoBuilder<A> {
+    /// Creates a new [`SocketIoBuilder`] with default config
+    pub fn new() -> Self {
+        Self {
+            config: SocketIoConfig::default(),
+            engine_config_builder: EngineIoConfigBuilder::new().req_path("/socket.io".to_string()),
+            adapter: std::marker::PhantomData,
         }
     }
-}
 
-/// A builder to create a [`SocketIo`] instance.
-/// It contains everything to configure the socket.io server with a [`SocketIoConfig`].
-/// It can be used to build either a Tower [`Layer`](tower::layer::Layer) or a [`Service`](tower::Service).
-pub struct SocketIoBuilder<A: Adapter = LocalAdapter> {
-    config: SocketIoConfig,
-    engine_config_builder: EngineIoConfigBuilder,
-    adapter: std::marker::PhantomData<A>,
-}
+    /// The path to listen for socket.io requests on, for example "/myapp/socket.io".
+    /// It is normalized with a leading slash and without trailing slash.
+    /// Requests that don't match this path are passed to the inner service.
+    ///
+    /// Defaults to "/socket.io".
+    #[inline]
+    pub fn req_path(mut self, req_path: impl Into<Cow<'static, str>>) -> Self {
+        self.engine_config_builder = self.engine_config_builder.req_path(req_path);
+        self
+    }
+
+    /// The interval at which the server will send a ping packet to the client.
+    ///
+    /// Defaults to 25 seconds.
+    #[inline]
+    pub fn ping_interval(mut self, ping_interval: Duration) -> Self {
+        self.engine_config_builder = self.engine_config_builder.ping_interval(ping_interval);
+        self
+    }
+
+    /// The amount of time the server will wait for a ping response from the client before closing the connection.
+    ///
+    /// Defaults to 20 seconds.
+    #[inline]
+    pub fn ping_timeout(mut self, ping_timeout: Duration) -> Self {
+        self.engine_config_builder = self.engine_config_builder.ping_timeout(ping_timeout);
+        self
+    }
+
+    /// If false, the server never sends pings and only relies on the transport to detect closed connections
+    /// (e.g. with TCP keepalive). It is meant for internal links over a trusted network,
+    /// where the heartbeat traffic is pure overhead.
+    ///
+    /// Defaults to true.
+    #[inline]
+    pub fn heartbeat(mut self, heartbeat: bool) -> Self {
+        self.engine_config_builder = self.engine_config_builder.heartbeat(heartbeat);
+        self
+    }
+
+    /// The maximum number of packets that can be buffered per connection before being emitted to the client.
+    /// If the buffer if full the `emit()` method will return an error
+    ///
+    /// Defaults to 128 packets.
+    #[inline]
+    pub fn max_buffer_size(mut self, max_buffer_size: usize) -> Self {
+        self.engine_config_builder = self.engine_config_builder.max_buffer_size(max_buffer_size);
+        self
+    }
+
+    /// What to do when a packet is emitted while the buffer of a socket is full.
+    /// With a dropping policy, a broadcast is not failing for the sockets that are too slow,
+    /// the discarded packets are counted by [`Socket::dropped_packets`](crate::socket::Socket::dropped_packets).
+    ///
+    /// Defaults to [`OverflowPolicy::Reject`].
+    #[inline]
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.engine_config_builder = self.engine_config_builder.overflow_policy(overflow_policy);
+        self
+    }
+
+    /// Creates the queue buffering the packets emitted to each client, from the
+    /// [`max_buffer_size`](Self::max_buffer_size) of the clients.
+    /// See [`PacketChannel`] and the [`RingBuffer`](engineioxide::channel::RingBuffer) alternative.
+    ///
+    /// Defaults to a [`Mutex`](std::sync::Mutex)-guarded [`VecDeque`](std::collections::VecDeque).
+    #[inline]
+    pub fn packet_channel<Q: PacketChannel<PacketBuf> + 'static>(
+        mut self,
+        factory: impl Fn(usize) -> Q + Send + Sync + 'static,
+    ) -> Self {
+        self.engine_config_builder = self.engine_config_builder.packet_channel(factory);
+        self
+    }
+
+    /// How the payloads of the packets are written in the tracing events.
+    /// With [`PayloadLogging::Off`], the application data never appears in the logs,
+    /// only the packet types and sizes.
+    ///
+    /// Defaults to [`PayloadLogging::Full`] in debug builds and to [`PayloadLogging::Off`] in release builds.
+    #[inline]
+    pub fn payload_logging(mut self, payload_logging: PayloadLogging) -> Self {
+        self.engine_config_builder = self.engine_config_builder.payload_logging(payload_logging);
+        self
+    }
+
+    /// The request header from which the correlation id of a connection is captured at handshake,
+    /// e.g. `x-request-id`. See [`Socket::correlation_id`](crate::socket::Socket::correlation_id).
+    ///
+    /// Defaults to `None`, the correlation ids are always random.
+    #[inline]
+    pub fn correlation_id_header(mut self, header: http::HeaderName) -> Self {
+        self.engine_config_builder = self.engine_config_builder.correlation_id_header(header);
+        self
+    }
+
+    /// The maximum size of a payload in bytes.
+    /// If a payload is bigger than this value the `emit()` method will return an error.
+    ///
+    /// Defaults to 100 kb.
+    #[inline]
+    pub fn max_payload(mut self, max_payload: u64) -> Self {
+        self.engine_config_builder = self.engine_config_builder.max_payload(max_payload);
+        self
+    }
+
+    /// The number of inbound packets dispatched before yielding to the runtime, so that a client
+    /// sending many packets at once doesn't starve the other clients of its worker thread.
+    /// 0 disables the yields.
+    ///
+    /// Defaults to 64 packets.
+    #[inline]
+    pub fn packets_per_yield(mut self, packets_per_yield: usize) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .packets_per_yield(packets_per_yield);
+        self
+    }
+
+    /// The maximum size in bytes of a message received from the client, measured on the decoded UTF-8 string.
+    /// If a bigger message is received, the client is disconnected.
+    ///
+    /// Defaults to no limit.
+    #[inline]
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .max_message_size(max_message_size);
+        self
+    }
+
+    /// The minimum size in bytes of a polling response payload to be compressed with gzip or brotli,
+    /// if the client accepts it. Compressed polling requests are always decompressed.
+    ///
+    /// Defaults to 1kb.
+    #[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+    #[cfg(feature = "compression")]
+    #[inline]
+    pub fn compression_threshold(mut self, compression_threshold: usize) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .compression_threshold(compression_threshold);
+        self
+    }
+
+    /// How invalid UTF-8 in the text packets of polling requests is handled.
+    /// With [`Utf8Validation::Lossy`], invalid sequences are replaced rather than closing the session.
+    ///
+    /// Defaults to [`Utf8Validation::Strict`].
+    #[inline]
+    pub fn utf8_validation(mut self, utf8_validation: Utf8Validation) -> Self {
+        self.engine_config_builder = self.engine_config_builder.utf8_validation(utf8_validation);
+        self
+    }
+
+    /// The maximum amount of time a polling request is held open while waiting for data.
+    /// After this duration the request is released with a noop packet so that the client can re-poll.
+    ///
+    /// Defaults to 25 seconds.
+    #[inline]
+    pub fn polling_duration(mut self, polling_duration: Duration) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .polling_duration(polling_duration);
+        self
+    }
+
+    /// The amount of time the server will wait for the upgrade probe exchange to complete
+    /// when a polling session is upgraded to websocket.
+    /// If it times out, the websocket attempt is abandoned and the session stays on polling.
+    ///
+    /// Defaults to 10 seconds.
+    #[inline]
+    pub fn upgrade_timeout(mut self, upgrade_timeout: Duration) -> Self {
+        self.engine_config_builder = self.engine_config_builder.upgrade_timeout(upgrade_timeout);
+        self
+    }
+
+    /// Only accepts the upgrades of a polling session from a request
+    /// with the same [`UpgradeOrigin`] as the handshake request of the session.
+    /// The upgrades failing the check are rejected with a `400 Bad Request` response.
+    ///
+    /// By default only the sid is checked.
+    #[inline]
+    pub fn strict_upgrade_origin(mut self, origin: UpgradeOrigin) -> Self {
+        self.engine_config_builder = self.engine_config_builder.strict_upgrade_origin(origin);
+        self
+    }
+
+    /// The interval at which the server will send websocket protocol-level ping frames to the client.
+    /// It is independent of the engine.io heartbeat and can be used to keep idle connections
+    /// open through proxies.
+    ///
+    /// Defaults to `None` (no ping frames are sent).
+    #[inline]
+    pub fn ws_ping_interval(mut self, ws_ping_interval: Duration) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .ws_ping_interval(ws_ping_interval);
+        self
+    }
+
+    /// If true, transport activity (websocket pong frames) received since the last engine.io ping
+    /// extends the engine.io heartbeat deadline when the client does not answer in time.
+    ///
+    /// Defaults to false, to keep the behavior of the engine.io protocol.
+    #[inline]
+    pub fn transport_liveness(mut self, transport_liveness: bool) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .transport_liveness(transport_liveness);
+        self
+    }
+
+    /// Adapts the interval between the heartbeat pings of each socket to its round-trip times,
+    /// within `min_interval` and `max_interval`.
+    /// See [`AdaptiveHeartbeat`](engineioxide::config::AdaptiveHeartbeat) for more details.
+    ///
+    /// Defaults to `None` (pings are sent every [`ping_interval`](Self::ping_interval)).
+    ///
+    /// # Panics
+    /// If `min_interval` is zero or greater than `max_interval`.
+    #[inline]
+    pub fn adaptive_heartbeat(mut self, min_interval: Duration, max_interval: Duration) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .adaptive_heartbeat(min_interval, max_interval);
+        self
+    }
+
+    /// Waits for this amount of time after a packet before flushing the websocket,
+    /// so that the packets emitted in bursts are written with fewer flushes and syscalls.
+    /// Heartbeat and close packets are always flushed right away.
+    ///
+    /// Defaults to `None` (the packets are flushed as soon as the emission buffer is drained).
+    #[inline]
+    pub fn write_coalesce_window(mut self, write_coalesce_window: Duration) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .write_coalesce_window(write_coalesce_window);
+        self
+    }
+
+    /// Waits for this amount of time after the first packet before responding to a parked polling request,
+    /// so that the packets emitted in bursts are sent in a single payload.
+    /// Heartbeat and close packets and full payloads are always sent right away.
+    ///
+    /// Defaults to `None` (the packets are sent as soon as they are emitted).
+    #[inline]
+    pub fn polling_coalesce_delay(mut self, polling_coalesce_delay: Duration) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .polling_coalesce_delay(polling_coalesce_delay);
+        self
+    }
+
+    /// What to do when a client sends an engine.io ping with the v4 protocol, where only the server should send pings.
+    /// See [`ClientPingPolicy`] for the available policies.
+    ///
+    /// Defaults to [`ClientPingPolicy::Pong`].
+    #[inline]
+    pub fn client_ping_policy(mut self, client_ping_policy: ClientPingPolicy) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .client_ping_policy(client_ping_policy);
+        self
+    }
+
+    /// The amount of time a client can stay connected without sending any message.
+    /// Heartbeats are not taken into account, so it closes connections that are alive but idle at the application level.
+    /// Idle sockets are disconnected with the [`DisconnectReason::IdleTimeout`](crate::socket::DisconnectReason::IdleTimeout) reason.
+    ///
+    /// Defaults to `None` (idle connections are kept).
+    #[inline]
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.engine_config_builder = self.engine_config_builder.idle_timeout(idle_timeout);
+        self
+    }
+
+    /// The maximum amount of time a client can stay connected before it is disconnected,
+    /// so that it reconnects and gets rebalanced by the load balancer, e.g. after a scaling event.
+    /// Expired sockets are disconnected with the [`DisconnectReason::LifetimeExpired`](crate::socket::DisconnectReason::LifetimeExpired) reason.
+    ///
+    /// Defaults to `None` (connections are never recycled).
+    #[inline]
+    pub fn max_connection_lifetime(mut self, max_connection_lifetime: Duration) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .max_connection_lifetime(max_connection_lifetime);
+        self
+    }
+
+    /// The interval at which a background task disconnects the clients that outlived their heartbeat deadline,
+    /// as a safety net against stuck heartbeat tasks.
+    /// Dead clients are disconnected with the [`DisconnectReason::HeartbeatTimeout`](crate::socket::DisconnectReason::HeartbeatTimeout) reason.
+    ///
+    /// Defaults to `None` (no sweep).
+    #[inline]
+    pub fn heartbeat_sweep_interval(mut self, heartbeat_sweep_interval: Duration) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .heartbeat_sweep_interval(heartbeat_sweep_interval);
+        self
+    }
+
+    /// The runtime on which the tasks of the server are spawned (heartbeats, websocket connections, async handlers, ...).
+    /// It is useful when the server is embedded in an application managing its own runtimes.
+    /// Its time driver must be enabled.
+    ///
+    /// Defaults to the runtime of the current context.
+    #[inline]
+    pub fn runtime(mut self, runtime: tokio::runtime::Handle) -> Self {
+        self.engine_config_builder = self.engine_config_builder.runtime(runtime);
+        self
+    }
+
+    /// A hook called with the headers of the engine.io handshake responses, just before they are sent:
+    /// the polling handshake response and the `101 Switching Protocols` response of websocket connections.
+    /// It can be used to add a sticky session cookie or security headers.
+    ///
+    /// Some headers are reserved and can't be modified,
+    /// see [`EngineIoConfig::handshake_response_hook`](engineioxide::config::EngineIoConfig::handshake_response_hook).
+    #[inline]
+    pub fn handshake_response_hook(
+        mut self,
+        hook: impl Fn(&Handshake<'_>, &mut http::HeaderMap) + Send + Sync + 'static,
+    ) -> Self {
+        self.engine_config_builder = self.engine_config_builder.handshake_response_hook(hook);
+        self
+    }
+
+    /// Sets a cookie containing the engine.io sid on the handshake responses, for cookie-based sticky sessions.
+    /// See [`HandshakeCookie`] for the attributes of the cookie.
+    ///
+    /// Defaults to `None` (no cookie is set).
+    #[inline]
+    pub fn cookie(mut self, cookie: HandshakeCookie) -> Self {
+        self.engine_config_builder = self.engine_config_builder.cookie(cookie);
+        self
+    }
+
+    /// If false, polling responses have a `X-Accel-Buffering: no` header
+    /// so that reverse proxies like nginx don't buffer the long-polling responses.
+    ///
+    /// Defaults to true.
+    #[inline]
+    pub fn proxy_buffering(mut self, proxy_buffering: bool) -> Self {
+        self.engine_config_builder = self.engine_config_builder.proxy_buffering(proxy_buffering);
+        self
+    }
+
+    /// The amount of time given to the websocket writer to flush the final packets
+    /// and the close frame when a connection is closed, before it is forcibly dropped.
+    ///
+    /// Defaults to 1 second.
+    #[inline]
+    pub fn close_grace(mut self, close_grace: Duration) -> Self {
+        self.engine_config_builder = self.engine_config_builder.close_grace(close_grace);
+        self
+    }
+
+    /// The number of times a websocket write failing with a transient error (e.g. `WouldBlock`)
+    /// is retried before the connection is closed. Fatal errors are never retried.
+    ///
+    /// Defaults to 3.
+    #[inline]
+    pub fn ws_write_retries(mut self, ws_write_retries: u32) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .ws_write_retries(ws_write_retries);
+        self
+    }
+
+    /// The delay before the first retry of a failed websocket write, doubled for each following retry.
+    ///
+    /// Defaults to 10ms.
+    #[inline]
+    pub fn ws_write_retry_backoff(mut self, ws_write_retry_backoff: Duration) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .ws_write_retry_backoff(ws_write_retry_backoff);
+        self
+    }
+
+    /// If true, a socket upgraded from polling to websocket is downgraded back to polling
+    /// when its websocket writes still fail after the write retries, instead of being closed.
+    ///
+    /// It is a best effort that only works with clients still able to poll the session, see
+    /// [`EngineIoConfig::ws_polling_fallback`](engineioxide::config::EngineIoConfig::ws_polling_fallback).
+    ///
+    /// Defaults to false.
+    #[inline]
+    pub fn ws_polling_fallback(mut self, ws_polling_fallback: bool) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .ws_polling_fallback(ws_polling_fallback);
+        self
+    }
+
+    /// The websocket subprotocols accepted by the server.
+    /// The first subprotocol offered by the client that is accepted is echoed in the upgrade response.
+    ///
+    /// Defaults to an empty list.
+    #[inline]
+    pub fn ws_subprotocols<I>(mut self, subprotocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'static, str>>,
+    {
+        self.engine_config_builder = self.engine_config_builder.ws_subprotocols(subprotocols);
+        self
+    }
+
+    /// If true, a websocket upgrade offering only subprotocols that are not accepted
+    /// is rejected with a `400 Bad Request` response.
+    ///
+    /// Defaults to false, the upgrade is accepted without subprotocol.
+    #[inline]
+    pub fn ws_subprotocol_strict(mut self, ws_subprotocol_strict: bool) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .ws_subprotocol_strict(ws_subprotocol_strict);
+        self
+    }
+
+    /// The origins allowed to connect, e.g. `https://example.com`.
+    /// Websocket upgrade requests with a disallowed `Origin` header are rejected
+    /// with a `400 Bad Request` response, like the [`strict_upgrade_origin`](Self::strict_upgrade_origin) check.
+    /// The same list should be given to the CORS configuration of the polling transport.
+    ///
+    /// Defaults to an empty list (all origins are allowed).
+    #[inline]
+    pub fn allowed_origins<I>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'static, str>>,
+    {
+        self.engine_config_builder = self.engine_config_builder.allowed_origins(origins);
+        self
+    }
+
+    /// The engine.io [`SessionStore`] used to validate session ids across processes,
+    /// for example when running behind a load balancer without sticky sessions.
+    ///
+    /// Defaults to a [`MemorySessionStore`](engineioxide::session::MemorySessionStore).
+    #[inline]
+    pub fn session_store(mut self, session_store: impl SessionStore) -> Self {
+        self.engine_config_builder = self.engine_config_builder.session_store(session_store);
+        self
+    }
+
+    /// Limits the engine.io handshakes per remote IP with a [`HandshakeRateLimit`].
+    /// Over-limit handshakes are rejected with a `429 Too Many Requests` response, before any socket is created.
+    ///
+    /// Defaults to `None`, the handshakes are not limited.
+    #[inline]
+    pub fn handshake_rate_limit(mut self, limit: Arc<HandshakeRateLimit>) -> Self {
+        self.engine_config_builder = self.engine_config_builder.handshake_rate_limit(limit);
+        self
+    }
+
+    /// Records the engine.io packets of a random sample of the new sessions with a [`SessionRecording`].
+    /// See the [`recorder`](engineioxide::recorder) module for more details.
+    ///
+    /// Defaults to `None`.
+    #[inline]
+    pub fn session_recording(mut self, recording: SessionRecording) -> Self {
+        self.engine_config_builder = self.engine_config_builder.session_recording(recording);
+        self
+    }
+
+    /// The delay advertised in the `Retry-After` header of the `503 Service Unavailable` responses
+    /// sent to new connections while the server is paused, see [`SocketIo::pause_accepting`].
+    ///
+    /// Defaults to 5 seconds.
+    #[inline]
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.engine_config_builder = self.engine_config_builder.retry_after(retry_after);
+        self
+    }
+
+    /// Allowed transports on this server
+    ///
+    /// The `transports` array should have a size of 1 or 2
+    ///
+    /// Defaults to :
+    /// `[TransportType::Polling, TransportType::Websocket]`
+    #[inline]
+    pub fn transports<const N: usize>(mut self, transports: [TransportType; N]) -> Self {
+        self.engine_config_builder = self.engine_config_builder.transports(transports);
+        self
+    }
+
+    /// Allowed socket.io protocol versions on this server, e.g. `[ProtocolVersion::V5]` to reject the v4 clients
+    /// of a server built with the `v4` feature.
+    ///
+    /// Defaults to all the versions supported by the enabled features:
+    /// `[ProtocolVersion::V4, ProtocolVersion::V5]` with the `v4` feature, `[ProtocolVersion::V5]` otherwise.
+    #[inline]
+    pub fn allowed_versions<const N: usize>(mut self, versions: [ProtocolVersion; N]) -> Self {
+        self.engine_config_builder = self
+            .engine_config_builder
+            .allowed_versions(versions.map(Into::into));
+        self
+    }
+
+    /// The amount of time the server will wait for an acknowledgement from the client before closing the connection.
+    ///
+    /// Defaults to 5 seconds.
+    #[inline]
+    pub fn ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.config.ack_timeout = ack_timeout;
+        self
+    }
+
+    /// The amount of time before disconnecting a client that has not successfully joined a namespace.
+    ///
+    /// Defaults to 45 seconds.
+    #[inline]
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.config.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// The maximum length in bytes of an incoming event name.
+    ///
+    /// Defaults to 256 bytes.
+    #[inline]
+    pub fn max_event_name_len(mut self, max_event_name_len: usize) -> Self {
+        self.config.max_event_name_len = max_event_name_len;
+        self
+    }
+
+    /// The maximum number of arguments of an incoming event or acknowledgement.
+    ///
+    /// Defaults to 256 arguments.
+    #[inline]
+    pub fn max_args_count(mut self, max_args_count: usize) -> Self {
+        self.config.max_args_count = max_args_count;
+        self
+    }
+
+    /// The maximum number of binary attachments of an incoming packet.
+    /// The declared count is checked before any attachment is buffered.
+    ///
+    /// Defaults to 256 attachments.
+    #[inline]
+    pub fn max_attachments(mut self, max_attachments: usize) -> Self {
+        self.config.max_attachments = max_attachments;
+        self
+    }
+
+    /// The maximum size in bytes of the payload of an incoming connect packet (the auth data).
+    ///
+    /// Defaults to 10 kb.
+    #[inline]
+    pub fn max_connect_payload_size(mut self, max_connect_payload_size: usize) -> Self {
+        self.config.max_connect_payload_size = max_connect_payload_size;
+        self
+    }
+
+    /// The maximum number of namespaces a single connection can be connected to, connecting ones included.
+    /// A connect packet to a new namespace beyond it is rejected with a connect error
+    /// and counts as a violation of the limits.
+    ///
+    /// By default there is no limit.
+    #[inline]
+    pub fn max_namespaces_per_socket(mut self, max_namespaces_per_socket: usize) -> Self {
+        self.config.max_namespaces_per_socket = Some(max_namespaces_per_socket);
+        self
+    }
+
+    /// Disconnects a client after it sent `max_violations` packets exceeding one of the limits.
+    ///
+    /// By default offending packets are only dropped.
+    #[inline]
+    pub fn max_violations(mut self, max_violations: usize) -> Self {
+        self.config.max_violations = Some(max_violations);
+        self
+    }
+
+    /// What the server does when an incoming packet can't be decoded,
+    /// e.g. because its data is not valid json or an event has no name.
+    /// See [`MalformedEventPolicy`] for the available policies.
+    ///
+    /// Defaults to [`MalformedEventPolicy::Close`].
+    #[inline]
+    pub fn malformed_event_policy(mut self, policy: MalformedEventPolicy) -> Self {
+        self.config.malformed_event_policy = policy;
+        self
+    }
+
+    /// The number of shards of the socket maps of the engine and of each namespace,
+    /// see [`ShardedMap`](engineioxide::shard::ShardedMap).
+    /// More shards lower the lock contention between the connections, disconnections and broadcasts.
+    ///
+    /// Defaults to four times the number of available CPUs.
+    ///
+    /// # Panics
+    /// If the number of shards is 0.
+    #[inline]
+    pub fn socket_shards(mut self, socket_shards: usize) -> Self {
+        self.engine_config_builder = self.engine_config_builder.socket_shards(socket_shards);
+        self
+    }
+
+    /// The number of events buffered for each subscriber of the [`SocketIo::event_stream`].
+    /// When a subscriber lags behind, the oldest events are dropped for it.
+    ///
+    /// Defaults to 1024 events.
+    ///
+    /// # Panics
+    /// If the capacity is 0.
+    #[inline]
+    pub fn event_stream_capacity(mut self, event_stream_capacity: usize) -> Self {
+        assert!(
+            event_stream_capacity > 0,
+            "event_stream_capacity must be > 0"
+        );
+        self.config.event_stream_capacity = event_stream_capacity;
+        self
+    }
+
+    /// Enables the echo probe: the [`echo_probe_event`](Self::echo_probe_event) is answered by the server itself
+    /// with the same arguments and binary attachments followed by a server timestamp in milliseconds since the unix epoch.
+    /// If the client requested an acknowledgement, the answer is sent as the acknowledgement, otherwise the event is sent back.
+    ///
+    /// No handler is called for this event, so load testing tools can measure
+    /// the round-trip time of the server without any application logic.
+    /// The number of answered probes is available with [`SocketIo::echo_probes`].
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn echo_probe(mut self, enabled: bool) -> Self {
+        self.config.echo_probe = enabled;
+        self
+    }
 
-impl<A: Adapter> SocketIoBuilder<A> {
-    /// Creates a new [`SocketIoBuilder`] with default config
-    pub fn new() -> Self {
-        Self {
-            config: SocketIoConfig::default(),
-            engine_config_builder: EngineIoConfigBuilder::new().req_path("/socket.io".to_string()),
-            adapter: std::marker::PhantomData,
-        }
+    /// The name of the event answered by the [`echo_probe`](Self::echo_probe).
+    ///
+    /// Defaults to `__sioxide_echo`.
+    #[inline]
+    pub fn echo_probe_event(mut self, event: impl Into<Cow<'static, str>>) -> Self {
+        self.config.echo_probe_event = event.into();
+        self
     }
 
-    /// The path to listen for socket.io requests on.
+    /// Keeps the auth payload sent by the clients when connecting, and includes it in the [snapshots](SocketIo::snapshot).
+    /// It is disabled by default because the auth payload may contain user data such as credentials.
     ///
-    /// Defaults to "/socket.io".
+    /// Defaults to `false`.
     #[inline]
-    pub fn req_path(mut self, req_path: impl Into<Cow<'static, str>>) -> Self {
-        self.engine_config_builder = self.engine_config_builder.req_path(req_path);
+    pub fn snapshot_auth(mut self, snapshot_auth: bool) -> Self {
+        self.config.snapshot_auth = snapshot_auth;
         self
     }
 
-    /// The interval at which the server will send a ping packet to the client.
+    /// The name of the event emitted to the sockets asked to reconnect to another server
+    /// with [`Socket::migrate`](crate::socket::Socket::migrate). Its payload is `{ "url": url }`.
     ///
-    /// Defaults to 25 seconds.
+    /// Defaults to `migrate`.
     #[inline]
-    pub fn ping_interval(mut self, ping_interval: Duration) -> Self {
-        self.engine_config_builder = self.engine_config_builder.ping_interval(ping_interval);
+    pub fn migration_event(mut self, event: impl Into<Cow<'static, str>>) -> Self {
+        self.config.migration_event = event.into();
         self
     }
 
-    /// The amount of time the server will wait for a ping response from the client before closing the connection.
+    /// The minimum amount of time between the migration event and the disconnection of a migrated socket.
     ///
-    /// Defaults to 20 seconds.
+    /// Defaults to 5 seconds.
     #[inline]
-    pub fn ping_timeout(mut self, ping_timeout: Duration) -> Self {
-        self.engine_config_builder = self.engine_config_builder.ping_timeout(ping_timeout);
+    pub fn migration_delay(mut self, delay: Duration) -> Self {
+        self.config.migration_delay = delay;
         self
     }
 
-    /// The maximum number of packets that can be buffered per connection before being emitted to the client.
-    /// If the buffer if full the `emit()` method will return an error
+    /// The maximum random amount of time added to the [`migration_delay`](Self::migration_delay).
+    /// When many sockets are migrated at once, their disconnections are spread over it
+    /// so that they don't all reconnect to the target server at the same time.
     ///
-    /// Defaults to 128 packets.
+    /// Defaults to 5 seconds.
     #[inline]
-    pub fn max_buffer_size(mut self, max_buffer_size: usize) -> Self {
-        self.engine_config_builder = self.engine_config_builder.max_buffer_size(max_buffer_size);
+    pub fn migration_jitter(mut self, jitter: Duration) -> Self {
+        self.config.migration_jitter = jitter;
         self
     }
 
-    /// The maximum size of a payload in bytes.
-    /// If a payload is bigger than this value the `emit()` method will return an error.
+    /// The name of the event emitted with a [`CloseWarning`](crate::socket::CloseWarning) before the server closes a connection.
     ///
-    /// Defaults to 100 kb.
+    /// Defaults to `server_close_warning`.
     #[inline]
-    pub fn max_payload(mut self, max_payload: u64) -> Self {
-        self.engine_config_builder = self.engine_config_builder.max_payload(max_payload);
+    pub fn close_warning_event(mut self, event: impl Into<Cow<'static, str>>) -> Self {
+        self.config.close_warning_event = event.into();
         self
     }
 
-    /// Allowed transports on this server
+    /// Warns the clients before the server closes their connection, when:
+    /// * The server is closed with [`SocketIo::close`]
+    /// * A socket is disconnected with [`Socket::disconnect`](crate::socket::Socket::disconnect)
+    /// * A connection is idle for the [`idle_timeout`](Self::idle_timeout)
     ///
-    /// The `transports` array should have a size of 1 or 2
+    /// The [`close_warning_event`](Self::close_warning_event) is emitted with a [`CloseWarning`](crate::socket::CloseWarning)
+    /// and the connection is closed once the `grace` period elapses.
+    /// With a zero `grace`, the warning is emitted right before the connection is closed.
     ///
-    /// Defaults to :
-    /// `[TransportType::Polling, TransportType::Websocket]`
+    /// Defaults to `None` (no warning).
     #[inline]
-    pub fn transports<const N: usize>(mut self, transports: [TransportType; N]) -> Self {
-        self.engine_config_builder = self.engine_config_builder.transports(transports);
+    pub fn close_warning_grace(mut self, grace: Duration) -> Self {
+        self.config.close_warning_grace = Some(grace);
         self
     }
 
-    /// The amount of time the server will wait for an acknowledgement from the client before closing the connection.
+    /// If true, an emit failing because the connection of a socket is closed also disconnects the socket
+    /// right away, so that the following emits and broadcasts fail fast instead of targeting a dead socket.
+    /// See [`SocketIoConfig::strict_emit`].
     ///
-    /// Defaults to 5 seconds.
+    /// Defaults to `false`.
     #[inline]
-    pub fn ack_timeout(mut self, ack_timeout: Duration) -> Self {
-        self.config.ack_timeout = ack_timeout;
+    pub fn strict_emit(mut self, strict_emit: bool) -> Self {
+        self.config.strict_emit = strict_emit;
         self
     }
 
-    /// The amount of time before disconnecting a client that has not successfully joined a namespace.
+    /// Routes the incoming binary messages matching the filter to the handler registered with
+    /// [`SocketIo::on_raw_binary`] instead of the socket.io parser, to run a custom binary sub-protocol
+    /// on the same connections. See [`SocketIoConfig::raw_binary_filter`].
     ///
-    /// Defaults to 45 seconds.
+    /// Defaults to `None`.
     #[inline]
-    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
-        self.config.connect_timeout = connect_timeout;
+    pub fn raw_binary_filter(mut self, filter: fn(&[u8]) -> bool) -> Self {
+        self.config.raw_binary_filter = Some(filter);
         self
     }
 
@@ -253,6 +1056,197 @@ impl<A: Adapter> SocketIo<A> {
         &self.0.config
     }
 
+    /// Returns the total number of incoming packets that were rejected because they exceeded
+    /// one of the limits of the [`SocketIoConfig`] (e.g. [`SocketIoConfig::max_attachments`])
+    #[inline]
+    pub fn limit_violations(&self) -> u64 {
+        self.0.limit_violations()
+    }
+
+    /// Returns the total number of packets answered by the [echo probe](SocketIoBuilder::echo_probe)
+    #[inline]
+    pub fn echo_probes(&self) -> u64 {
+        self.0.echo_probes()
+    }
+
+    /// Creates a serializable [`ServerSnapshot`] of the namespaces, their sockets and their rooms,
+    /// for example to expose it on an internal debugging endpoint.
+    ///
+    /// The locks are only held while copying each namespace and socket, never during the whole snapshot,
+    /// so it is not an atomic view of the server. Only the sockets connected to this node are included.
+    ///
+    /// The auth payload of the sockets is only included if [`snapshot_auth`](SocketIoBuilder::snapshot_auth) is enabled.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.join("room1").ok();
+    /// });
+    /// let snapshot = io.snapshot().unwrap();
+    /// let json = serde_json::to_value(&snapshot).unwrap();
+    /// assert_eq!(json["namespaces"][0]["name"], "/");
+    /// ```
+    pub fn snapshot(&self) -> Result<ServerSnapshot, A::Error> {
+        self.0.snapshot()
+    }
+
+    /// Returns a [`Presence`] helper to query the last time each client was seen
+    /// and to be notified of the clients that became silent.
+    /// See the [`presence`](crate::presence) module for more details.
+    #[inline]
+    pub fn presence(&self) -> Presence<A> {
+        Presence::new(self.0.clone())
+    }
+
+    /// Stops accepting new connections, e.g. to drain this node before a deployment.
+    /// The engine.io handshakes of new clients are rejected with a `503 Service Unavailable` response
+    /// and a [`Retry-After`](SocketIoBuilder::retry_after) header, for polling and websocket alike.
+    ///
+    /// The connected sockets keep working: they can still exchange events, join namespaces
+    /// and upgrade their transport. It has no effect once the server is shutting down.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, ServerState};
+    /// let (_svc, io) = SocketIo::new_svc();
+    /// io.pause_accepting();
+    /// assert_eq!(io.server_state(), ServerState::Paused);
+    /// io.resume_accepting();
+    /// assert_eq!(io.server_state(), ServerState::Accepting);
+    /// ```
+    #[inline]
+    pub fn pause_accepting(&self) {
+        self.0.pause_accepting();
+    }
+
+    /// Accepts new connections again after a call to [`pause_accepting`](Self::pause_accepting).
+    #[inline]
+    pub fn resume_accepting(&self) {
+        self.0.resume_accepting();
+    }
+
+    /// Puts the server in draining mode, e.g. to remove this node from a load balancer without downtime.
+    /// It is the same as [`pause_accepting`](Self::pause_accepting): new connections are rejected
+    /// so that the load balancer routes them elsewhere, while the connected sockets keep working until
+    /// they disconnect. The remaining sockets can then be disconnected with [`close`](Self::close) to finish the drain.
+    ///
+    /// `set_draining(false)` accepts new connections again.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::SocketIo;
+    /// # use std::time::Duration;
+    /// # async fn doc() {
+    /// let (_svc, io) = SocketIo::new_svc();
+    /// io.set_draining(true);
+    /// assert!(io.is_draining());
+    /// // Let the clients disconnect by themselves for a while
+    /// tokio::time::sleep(Duration::from_secs(30)).await;
+    /// io.close().await;
+    /// # }
+    /// ```
+    #[inline]
+    pub fn set_draining(&self, draining: bool) {
+        if draining {
+            self.0.pause_accepting();
+        } else {
+            self.0.resume_accepting();
+        }
+    }
+
+    /// Returns true if the server is draining, see [`set_draining`](Self::set_draining).
+    #[inline]
+    pub fn is_draining(&self) -> bool {
+        self.server_state() == ServerState::Paused
+    }
+
+    /// Pushes the clients still on the polling transport to upgrade to websocket, e.g. to migrate
+    /// them off polling during an incident.
+    ///
+    /// The polling clients are asked to poll again right away, so that they get a chance to upgrade.
+    /// The ones still on polling after the `grace` are disconnected with the
+    /// [`DisconnectReason::UpgradeRequired`](crate::socket::DisconnectReason::UpgradeRequired) reason,
+    /// and reconnect like after a lost connection.
+    ///
+    /// Returns the number of clients upgraded during the grace and the number of closed ones.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::SocketIo;
+    /// # use std::time::Duration;
+    /// # async fn doc(io: SocketIo) {
+    /// let report = io.force_upgrade_or_close(Duration::from_secs(10)).await;
+    /// println!("{} upgraded, {} closed", report.upgraded, report.closed);
+    /// # }
+    /// ```
+    #[cfg(feature = "polling")]
+    #[inline]
+    pub async fn force_upgrade_or_close(&self, grace: Duration) -> UpgradeReport {
+        self.0.force_upgrade_or_close(grace).await
+    }
+
+    /// Returns whether the server accepts new connections, see [`ServerState`].
+    #[inline]
+    pub fn server_state(&self) -> ServerState {
+        self.0.server_state()
+    }
+
+    /// Returns the [`Health`] of the server: its number of engine.io connections and upgrades in progress,
+    /// and whether it accepts new connections. It only reads atomic counters,
+    /// so it can back a frequently polled health route.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::SocketIo;
+    /// let (_svc, io) = SocketIo::new_svc();
+    /// // e.g. in the handler of a `/healthz` route
+    /// let health = io.health();
+    /// let status = if health.accepting { 200 } else { 503 };
+    /// println!("{status}: {} connections", health.connections);
+    /// ```
+    #[inline]
+    pub fn health(&self) -> Health {
+        self.0.health()
+    }
+
+    /// Subscribes to the [`ServerEvent`]s of all the namespaces: connections, disconnections,
+    /// and room joins and leaves.
+    ///
+    /// An event is sent once its state change is visible, e.g. with [`fetch_sockets`](SocketIo::fetch_sockets)
+    /// or [`rooms`](SocketIo::rooms). Only the events happening after the subscription are received.
+    ///
+    /// The stream buffers up to [`SocketIoConfig::event_stream_capacity`] events for each subscriber.
+    /// A subscriber that lags behind misses the oldest events, and its next call to `recv` returns
+    /// [`RecvError::Lagged`](tokio::sync::broadcast::error::RecvError::Lagged) with the number of dropped events.
+    /// Slow subscribers never slow down the server.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, ServerEvent};
+    /// # use tokio::sync::broadcast::error::RecvError;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let (_, io) = SocketIo::new_svc();
+    /// let mut events = io.event_stream();
+    /// tokio::spawn(async move {
+    ///     loop {
+    ///         match events.recv().await {
+    ///             Ok(ServerEvent::Connect { sid, ns }) => println!("{sid} connected to {ns}"),
+    ///             Ok(event) => println!("{event:?}"),
+    ///             Err(RecvError::Lagged(n)) => println!("{n} events dropped"),
+    ///             Err(RecvError::Closed) => break,
+    ///         }
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    #[inline]
+    pub fn event_stream(&self) -> broadcast::Receiver<ServerEvent> {
+        self.0.events.subscribe()
+    }
+
     /// ### Registers a [`ConnectHandler`] for the given namespace.
     ///
     /// * See the [`connect`](crate::handler::connect) module doc for more details on connect handler.
@@ -269,79 +1263,404 @@ impl<A: Adapter> SocketIo<A> {
     ///
     /// let (_, io) = SocketIo::new_svc();
     /// io.ns("/", |socket: SocketRef| {
-    ///     // Register a handler for the "test" event and extract the data as a `MyData` struct
-    ///     // With the Data extractor, the handler is called only if the data can be deserialized as a `MyData` struct
-    ///     // If you want to manage errors yourself you can use the TryData extractor
-    ///     socket.on("test", |socket: SocketRef, Data::<MyData>(data)| {
-    ///         println!("Received a test message {:?}", data);
-    ///         socket.emit("test-test", MyData { name: "Test".to_string(), age: 8 }).ok(); // Emit a message to the client
-    ///     });
+    ///     // Register a handler for the "test" event and extract the data as a `MyData` struct
+    ///     // With the Data extractor, the handler is called only if the data can be deserialized as a `MyData` struct
+    ///     // If you want to manage errors yourself you can use the TryData extractor
+    ///     socket.on("test", |socket: SocketRef, Data::<MyData>(data)| {
+    ///         println!("Received a test message {:?}", data);
+    ///         socket.emit("test-test", MyData { name: "Test".to_string(), age: 8 }).ok(); // Emit a message to the client
+    ///     });
+    /// });
+    ///
+    /// ```
+    ///
+    /// #### Example with a closure and an acknowledgement + binary data:
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use serde_json::Value;
+    /// # use serde::{Serialize, Deserialize};
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// struct MyData {
+    ///     name: String,
+    ///     age: u8,
+    /// }
+    ///
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     // Register an async handler for the "test" event and extract the data as a `MyData` struct
+    ///     // Extract the binary payload as a `Vec<Vec<u8>>` with the Bin extractor.
+    ///     // It should be the last extractor because it consumes the request
+    ///     socket.on("test", |socket: SocketRef, Data::<MyData>(data), ack: AckSender, Bin(bin)| async move {
+    ///         println!("Received a test message {:?}", data);
+    ///         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    ///         ack.bin(bin).send(data).ok(); // The data received is sent back to the client through the ack
+    ///         socket.emit("test-test", MyData { name: "Test".to_string(), age: 8 }).ok(); // Emit a message to the client
+    ///     });
+    /// });
+    /// ```
+    /// #### Simple example with a closure:
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::{SocketRef, Data}};
+    /// # use serde::{Serialize, Deserialize};
+    /// #[derive(Debug, Deserialize)]
+    /// struct MyAuthData {
+    ///     token: String,
+    /// }
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// struct MyData {
+    ///     name: String,
+    ///     age: u8,
+    /// }
+    ///
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef, Data(auth): Data<MyAuthData>| {
+    ///     if auth.token.is_empty() {
+    ///         println!("Invalid token, disconnecting");
+    ///         socket.disconnect().ok();
+    ///         return;
+    ///     }
+    ///     socket.on("test", |socket: SocketRef, Data::<MyData>(data)| async move {
+    ///         println!("Received a test message {:?}", data);
+    ///         socket.emit("test-test", MyData { name: "Test".to_string(), age: 8 }).ok(); // Emit a message to the client
+    ///     });
+    /// });
+    ///
+    /// ```
+    #[inline]
+    pub fn ns<C, T>(&self, path: impl Into<Cow<'static, str>>, callback: C)
+    where
+        C: ConnectHandler<A, T>,
+        T: Send + Sync + 'static,
+    {
+        self.0.add_ns(path.into(), callback);
+    }
+
+    /// Registers a factory creating namespaces on demand, similar to the dynamic namespaces of socket.io.
+    ///
+    /// When a client connects to a namespace that is not registered, the factories are called in the order
+    /// they were added with the requested path. The first one returning a [`NamespaceHandler`] creates the
+    /// namespace, which is then kept like the ones registered with [`ns`](Self::ns) until it is
+    /// [deleted](Self::delete_ns). If no factory matches the path, the connection to the namespace is
+    /// rejected with a `connect_error` packet.
+    ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, handler::NamespaceHandler, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.dyn_ns(|path| {
+    ///     let id = path.strip_prefix("/room-")?;
+    ///     if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+    ///         return None;
+    ///     }
+    ///     Some(NamespaceHandler::new(|socket: SocketRef| {
+    ///         println!("socket {} connected to {}", socket.id, socket.ns());
+    ///     }))
+    /// });
+    /// ```
+    #[inline]
+    pub fn dyn_ns<F>(&self, factory: F)
+    where
+        F: Fn(&str) -> Option<NamespaceHandler<A>> + Send + Sync + 'static,
+    {
+        self.0.add_dyn_ns(factory);
+    }
+
+    /// Registers a callback called for every socket connected to any namespace, to share the setup
+    /// of the sockets between the namespaces, like attaching a correlation id or default extensions.
+    ///
+    /// For each connection, the middlewares of the namespace are called first. If they accept the connection,
+    /// the connect packet is sent to the client, then this callback is called
+    /// and finally the connect handler of the namespace. It is not called for rejected connections.
+    ///
+    /// Registering a new callback replaces the previous one, for the next connections.
+    ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.on_connect_global(|socket: SocketRef| {
+    ///     println!("socket {} connected to {}", socket.id, socket.ns());
+    ///     socket.on("ping", |socket: SocketRef| {
+    ///         socket.emit("pong", ()).ok();
+    ///     });
+    /// });
+    /// io.ns("/", |socket: SocketRef| {
+    ///     println!("socket connected to the root namespace");
+    /// });
+    /// ```
+    #[inline]
+    pub fn on_connect_global<F>(&self, callback: F)
+    where
+        F: Fn(SocketRef<A>) + Send + Sync + 'static,
+    {
+        self.0.set_global_connect_handler(callback);
+    }
+
+    /// Registers the handler of the binary messages matching the [`raw_binary_filter`](SocketIoBuilder::raw_binary_filter),
+    /// called with the message and the [`EngineSocket`] of the connection, which can
+    /// [`emit_binary`](engineioxide::Socket::emit_binary) replies directly.
+    ///
+    /// The matched messages are dropped if no handler is registered.
+    /// Registering a new handler replaces the previous one.
+    ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::SocketIo;
+    /// let (_, io) = SocketIo::builder()
+    ///     .raw_binary_filter(|data| data.first() == Some(&0xAB))
+    ///     .build_svc();
+    /// io.on_raw_binary(|data, socket| {
+    ///     // Echo the message of the custom protocol
+    ///     socket.emit_binary(data).ok();
+    /// });
+    /// ```
+    #[inline]
+    pub fn on_raw_binary<F>(&self, handler: F)
+    where
+        F: Fn(Vec<u8>, Arc<EngineSocket>) + Send + Sync + 'static,
+    {
+        self.0.set_raw_binary_handler(handler);
+    }
+
+    /// Sets the [`DeliveryFilter`] of the namespace with the given path, or removes it with `None`.
+    ///
+    /// The filter is evaluated for each socket in the broadcast fan-out, to suppress some events
+    /// for some sockets without computing custom rooms for each emit. The number of skipped sockets
+    /// is reported in the [`BroadcastResult`] of the broadcast.
+    ///
+    /// Returns false if the namespace is not registered.
+    ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, DeliveryFilter, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.join("feed").ok();
     /// });
+    /// // Only the sockets with the beta feature flag receive the "beta" events
+    /// io.set_delivery_filter(
+    ///     "/",
+    ///     Some(DeliveryFilter::new(|socket, event| {
+    ///         event != "beta" || socket.req_parts().headers.contains_key("x-beta")
+    ///     })),
+    /// );
+    /// io.to("feed").emit("beta", "hello").ok();
+    /// ```
+    pub fn set_delivery_filter<'a>(
+        &self,
+        path: impl Into<&'a str>,
+        filter: Option<DeliveryFilter<A>>,
+    ) -> bool {
+        match self.0.get_ns(path.into()) {
+            Some(ns) => {
+                ns.set_delivery_filter(filter);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the [`EventValidator`] of the namespace with the given path, or removes it with `None`.
+    ///
+    /// The validator is called for each event received on the namespace, before it is dispatched to the handlers.
+    /// See the [`validation`](crate::validation) module for more details.
+    ///
+    /// Returns false if the namespace is not registered.
     ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef, validation::{EventValidator, ValidationError}};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {});
+    /// io.set_event_validator(
+    ///     "/",
+    ///     Some(EventValidator::new(|event, _| match event {
+    ///         "admin" => Err(ValidationError::new("unknown event")),
+    ///         _ => Ok(()),
+    ///     })),
+    /// );
     /// ```
+    pub fn set_event_validator<'a>(
+        &self,
+        path: impl Into<&'a str>,
+        validator: Option<EventValidator>,
+    ) -> bool {
+        match self.0.get_ns(path.into()) {
+            Some(ns) => {
+                ns.set_event_validator(validator);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// ### Registers a [`MessageHandler`] for the given event on all the sockets of the namespace with the given path,
+    /// including the sockets already connected.
     ///
-    /// #### Example with a closure and an acknowledgement + binary data:
+    /// The events are routed by name: a handler registered on a socket with [`Socket::on`](crate::socket::Socket::on)
+    /// takes precedence over the namespace handler of the same event, and the events without any handler
+    /// are passed to the fallback handler of the socket registered with [`Socket::on_raw`](crate::socket::Socket::on_raw).
+    /// Registering another handler for the same event replaces the previous one.
+    ///
+    /// Returns false if the namespace is not registered.
+    ///
+    /// # Example
     /// ```
     /// # use socketioxide::{SocketIo, extract::*};
     /// # use serde_json::Value;
-    /// # use serde::{Serialize, Deserialize};
-    /// #[derive(Debug, Serialize, Deserialize)]
-    /// struct MyData {
-    ///     name: String,
-    ///     age: u8,
-    /// }
-    ///
     /// let (_, io) = SocketIo::new_svc();
     /// io.ns("/", |socket: SocketRef| {
-    ///     // Register an async handler for the "test" event and extract the data as a `MyData` struct
-    ///     // Extract the binary payload as a `Vec<Vec<u8>>` with the Bin extractor.
-    ///     // It should be the last extractor because it consumes the request
-    ///     socket.on("test", |socket: SocketRef, Data::<MyData>(data), ack: AckSender, Bin(bin)| async move {
-    ///         println!("Received a test message {:?}", data);
-    ///         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-    ///         ack.bin(bin).send(data).ok(); // The data received is sent back to the client through the ack
-    ///         socket.emit("test-test", MyData { name: "Test".to_string(), age: 8 }).ok(); // Emit a message to the client
-    ///     });
+    ///     socket.on_raw(|_, event, _| println!("unhandled event {}", event.event));
+    /// });
+    /// io.ns_on("/", "chat", |socket: SocketRef, Data::<Value>(msg)| {
+    ///     socket.broadcast().emit("chat", msg).ok();
+    /// });
+    /// io.ns_on("/", "ping", |ack: AckSender| {
+    ///     ack.send("pong").ok();
     /// });
     /// ```
-    /// #### Simple example with a closure:
+    pub fn ns_on<'a, H, T>(
+        &self,
+        path: impl Into<&'a str>,
+        event: impl Into<Cow<'static, str>>,
+        handler: H,
+    ) -> bool
+    where
+        H: MessageHandler<A, T>,
+        T: Send + Sync + 'static,
+    {
+        match self.0.get_ns(path.into()) {
+            Some(ns) => {
+                ns.set_message_handler(event.into(), MakeErasedHandler::new_message_boxed(handler));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the [`ErrorPolicy`] of the namespace with the given path,
+    /// applied when a message handler fails because of an extractor or returns an error.
+    ///
+    /// Defaults to [`ErrorPolicy::Silent`].
+    ///
+    /// Returns false if the namespace is not registered.
+    ///
+    /// # Example
     /// ```
-    /// # use socketioxide::{SocketIo, extract::{SocketRef, Data}};
-    /// # use serde::{Serialize, Deserialize};
-    /// #[derive(Debug, Deserialize)]
-    /// struct MyAuthData {
-    ///     token: String,
-    /// }
-    /// #[derive(Debug, Serialize, Deserialize)]
-    /// struct MyData {
-    ///     name: String,
-    ///     age: u8,
-    /// }
+    /// # use socketioxide::{SocketIo, extract::SocketRef, handler::ErrorPolicy};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {});
+    /// io.set_error_policy("/", ErrorPolicy::EmitEvent("error".into()));
+    /// ```
+    pub fn set_error_policy<'a>(&self, path: impl Into<&'a str>, policy: ErrorPolicy) -> bool {
+        match self.0.get_ns(path.into()) {
+            Some(ns) => {
+                ns.set_error_policy(policy);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the maximum number of sockets connected to the namespace with the given path, or removes it with `None`.
+    ///
+    /// Once the limit is reached, the new connections to the namespace are rejected with a
+    /// `connect_error` packet, until some sockets leave it. The connections to the other namespaces are not affected.
+    /// The sockets already connected are kept if the limit is lowered below their number.
+    ///
+    /// Returns false if the namespace is not registered.
     ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
     /// let (_, io) = SocketIo::new_svc();
-    /// io.ns("/", |socket: SocketRef, Data(auth): Data<MyAuthData>| {
-    ///     if auth.token.is_empty() {
-    ///         println!("Invalid token, disconnecting");
-    ///         socket.disconnect().ok();
-    ///         return;
-    ///     }
-    ///     socket.on("test", |socket: SocketRef, Data::<MyData>(data)| async move {
-    ///         println!("Received a test message {:?}", data);
-    ///         socket.emit("test-test", MyData { name: "Test".to_string(), age: 8 }).ok(); // Emit a message to the client
-    ///     });
-    /// });
+    /// io.ns("/video", |socket: SocketRef| {});
+    /// io.set_max_connections("/video", Some(1000));
+    /// ```
+    pub fn set_max_connections<'a>(&self, path: impl Into<&'a str>, max: Option<usize>) -> bool {
+        match self.0.get_ns(path.into()) {
+            Some(ns) => {
+                ns.set_max_connections(max);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the default ack timeout of the namespace with the given path, or removes it with `None`.
+    ///
+    /// It overrides the [`ack_timeout`](SocketIoBuilder::ack_timeout) of the config for the acknowledgements
+    /// awaited on this namespace, a timeout set with `timeout()` on the operators still takes precedence.
+    ///
+    /// Returns false if the namespace is not registered.
     ///
+    /// # Example
     /// ```
-    #[inline]
-    pub fn ns<C, T>(&self, path: impl Into<Cow<'static, str>>, callback: C)
-    where
-        C: ConnectHandler<A, T>,
-        T: Send + Sync + 'static,
-    {
-        self.0.add_ns(path.into(), callback);
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// # use std::time::Duration;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/slow", |socket: SocketRef| {});
+    /// io.set_ack_timeout("/slow", Some(Duration::from_secs(30)));
+    /// ```
+    pub fn set_ack_timeout<'a>(&self, path: impl Into<&'a str>, timeout: Option<Duration>) -> bool {
+        match self.0.get_ns(path.into()) {
+            Some(ns) => {
+                ns.set_ack_timeout(timeout);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the [`AckRetry`] policy of the namespace with the given path, or removes it with `None`.
+    ///
+    /// When an acknowledgement times out, the packet is emitted again with a new ack id
+    /// until the client answers or the attempts are exhausted. Each attempt waits for the full ack timeout.
+    ///
+    /// Returns false if the namespace is not registered.
+    ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef, ack::{AckRetry, Backoff}};
+    /// # use std::time::Duration;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {});
+    /// io.set_ack_retry(
+    ///     "/",
+    ///     Some(AckRetry {
+    ///         attempts: 3,
+    ///         backoff: Backoff::Exponential(Duration::from_millis(100)),
+    ///     }),
+    /// );
+    /// ```
+    pub fn set_ack_retry<'a>(&self, path: impl Into<&'a str>, retry: Option<AckRetry>) -> bool {
+        match self.0.get_ns(path.into()) {
+            Some(ns) => {
+                ns.set_ack_retry(retry);
+                true
+            }
+            None => false,
+        }
     }
 
-    /// Deletes the namespace with the given path
+    /// Deletes the namespace with the given path:
+    /// * Its sockets are disconnected with the [`DisconnectReason::ServerNSDisconnect`](crate::socket::DisconnectReason::ServerNSDisconnect)
+    ///   reason, their `on_disconnect` handlers are called. The underlying connections stay open for the other namespaces.
+    /// * Its rooms are dropped with its adapter and its handlers are unregistered.
+    /// * The following connections to the namespace are rejected with an invalid namespace error,
+    ///   unless a [dynamic namespace](Self::dyn_ns) factory creates it again.
+    ///
+    /// The broadcasts started before the deletion complete, the ones started afterwards with an operator
+    /// kept from the namespace fail with a [`BroadcastError::NamespaceClosed`](crate::BroadcastError::NamespaceClosed) error.
+    ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/event-2024", |socket: SocketRef| {});
+    /// io.delete_ns("/event-2024");
+    /// assert!(io.of("/event-2024").is_none());
+    /// ```
     #[inline]
     pub fn delete_ns<'a>(&self, path: impl Into<&'a str>) {
         self.0.delete_ns(path.into());
@@ -350,15 +1669,46 @@ impl<A: Adapter> SocketIo<A> {
     /// Gracefully closes all the connections and drops every sockets
     ///
     /// Any `on_disconnect` handler will called with [`DisconnectReason::ClosingServer`](crate::socket::DisconnectReason::ClosingServer)
+    ///
+    /// If the close warnings are enabled with [`SocketIoBuilder::close_warning_grace`],
+    /// the clients are warned first and the connections are closed once the grace period elapses.
     #[inline]
     pub async fn close(&self) {
-        self.0.close().await;
+        let warning = CloseWarning::new(CloseWarningReason::ServerShutdown);
+        self.0.close(Some(warning)).await;
+    }
+
+    /// Same as [`SocketIo::close`] with a custom [`CloseWarning`], e.g. to tell the clients when to reconnect.
+    /// If it is `None`, the clients are not warned and the connections are closed right away.
+    ///
+    /// The warning is only emitted if the close warnings are enabled with [`SocketIoBuilder::close_warning_grace`].
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, socket::{CloseWarning, CloseWarningReason}};
+    /// # use std::time::Duration;
+    /// # async fn doc() {
+    /// let (_, io) = SocketIo::builder()
+    ///     .close_warning_grace(Duration::from_secs(2))
+    ///     .build_svc();
+    /// let warning = CloseWarning::new(CloseWarningReason::ServerShutdown)
+    ///     .retry_after(Duration::from_secs(30));
+    /// io.close_with_warning(Some(warning)).await;
+    /// # }
+    /// ```
+    #[inline]
+    pub async fn close_with_warning(&self, warning: Option<CloseWarning>) {
+        self.0.close(warning).await;
     }
 
     // Chaining operators fns
 
     /// Selects a specific namespace to perform operations on
     ///
+    /// Returns `None` if the namespace is not registered: namespaces are never created by this method.
+    /// A namespace matching a [dynamic namespace](Self::dyn_ns) factory is only returned
+    /// once a client has connected to it.
+    ///
     /// ## Example
     /// ```
     /// # use socketioxide::{SocketIo, extract::SocketRef};
@@ -404,6 +1754,27 @@ impl<A: Adapter> SocketIo<A> {
         self.get_default_op().to(rooms)
     }
 
+    /// Selects the socket with the given id on the root namespace.
+    ///
+    /// Alias for `io.of("/").unwrap().to_socket(sid)`,
+    /// see [`BroadcastOperators::to_socket`] for more details.
+    ///
+    /// ## Panics
+    /// If the **default namespace "/" is not found** this fn will panic!
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// let io2 = io.clone();
+    /// io.ns("/", move |socket: SocketRef| {
+    ///     io2.to_socket(socket.id).emit("welcome", ()).unwrap();
+    /// });
+    #[inline]
+    pub fn to_socket(&self, sid: Sid) -> SocketOperators<A> {
+        self.get_default_op().to_socket(sid)
+    }
+
     /// Selects all sockets in the given rooms on the root namespace.
     ///
     /// Alias for :
@@ -563,6 +1934,89 @@ impl<A: Adapter> SocketIo<A> {
         self.get_default_op().bin(binary)
     }
 
+    /// Transforms the payload once for each selected room before it is sent.
+    ///
+    /// Alias for `io.of("/").unwrap().map_payload(mapper)`
+    ///
+    /// See [`BroadcastOperators::map_payload`] for more details.
+    ///
+    /// ## Panics
+    /// If the **default namespace "/" is not found** this fn will panic!
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// # use serde_json::json;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     println!("Socket connected on / namespace with id: {}", socket.id);
+    /// });
+    ///
+    /// // Later in your code you can send a legacy payload to the sockets in the v1-clients room
+    /// io.within(["v1-clients", "v2-clients"])
+    ///   .map_payload(|room, value| match room {
+    ///       Some("v1-clients") => json!({ "user_name": value["user"]["name"] }),
+    ///       _ => value,
+    ///   })
+    ///   .emit("user", json!({ "user": { "name": "foo" } }));
+    #[inline]
+    pub fn map_payload(
+        &self,
+        mapper: impl Fn(Option<&str>, Value) -> Value + Send + Sync + 'static,
+    ) -> BroadcastOperators<A> {
+        self.get_default_op().map_payload(mapper)
+    }
+
+    /// Selects `count` sockets uniformly at random among the sockets of the default namespace.
+    ///
+    /// Alias for `io.of("/").unwrap().sample(count)`
+    ///
+    /// See [`BroadcastOperators::sample`] for more details.
+    ///
+    /// ## Panics
+    /// If the **default namespace "/" is not found** this fn will panic!
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     println!("Socket connected on / namespace with id: {}", socket.id);
+    /// });
+    ///
+    /// // Later in your code you can ping 3 random sockets of the workers room
+    /// io.to("workers").sample(3).emit("ping", ());
+    /// // Or 3 random sockets of the whole namespace
+    /// io.sample(3).emit("ping", ());
+    #[inline]
+    pub fn sample(&self, count: usize) -> BroadcastOperators<A> {
+        self.get_default_op().sample(count)
+    }
+
+    /// Selects the sockets of the default namespace currently connected with the given transport.
+    ///
+    /// Alias for `io.of("/").unwrap().on_transport(transport)`
+    ///
+    /// See [`BroadcastOperators::on_transport`] for more details.
+    ///
+    /// ## Panics
+    /// If the **default namespace "/" is not found** this fn will panic!
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, TransportType, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     println!("Socket connected on / namespace with id: {}", socket.id);
+    /// });
+    ///
+    /// // Later in your code you can send a large payload to the websocket clients only
+    /// io.on_transport(TransportType::Websocket).emit("news", "rich content");
+    #[inline]
+    pub fn on_transport(&self, transport: TransportType) -> BroadcastOperators<A> {
+        self.get_default_op().on_transport(transport)
+    }
+
     /// Emits a message to all sockets selected with the previous operators.
     ///
     /// Alias for `io.of("/").unwrap().emit(event, data)`
@@ -584,16 +2038,43 @@ impl<A: Adapter> SocketIo<A> {
     /// io.to("room1")
     ///   .to("room3")
     ///   .except("room2")
-    ///   .emit("Hello World!", ());
+    ///   .emit("Hello World!", ()).ok();
     #[inline]
+    #[must_use = "the message is not sent to some clients if the broadcast fails"]
     pub fn emit<T: serde::Serialize>(
         &self,
         event: impl Into<Cow<'static, str>>,
         data: T,
-    ) -> Result<(), BroadcastError> {
+    ) -> Result<BroadcastResult, BroadcastError> {
         self.get_default_op().emit(event, data)
     }
 
+    /// Emits a message with pre-serialized JSON data to all sockets selected with the previous operators.
+    ///
+    /// Alias for `io.of("/").unwrap().emit_raw_json(event, raw)`
+    ///
+    /// ## Panics
+    /// If the **default namespace "/" is not found** this fn will panic!
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef, packet::RawJson};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {});
+    ///
+    /// let doc = RawJson::new(r#"{"title":"hello"}"#).unwrap();
+    /// io.to("room1").emit_raw_json("doc", doc);
+    /// ```
+    #[inline]
+    #[must_use = "the message is not sent to some clients if the broadcast fails"]
+    pub fn emit_raw_json(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        raw: impl Into<RawJson>,
+    ) -> Result<BroadcastResult, BroadcastError> {
+        self.get_default_op().emit_raw_json(event, raw)
+    }
+
     /// Emits a message to all sockets selected with the previous operators and
     /// waits for the acknowledgement(s).
     ///
@@ -614,6 +2095,8 @@ impl<A: Adapter> SocketIo<A> {
     /// an [`AckError::Timeout`]. If the data sent by the client is not deserializable as `V`,
     /// an [`AckError::Serde`] will be yielded.
     ///
+    /// If no socket is selected, the [`AckStream`] ends right away without waiting for the timeout.
+    ///
     /// [`timeout()`]: #method.timeout
     /// [`Stream`]: futures::stream::Stream
     /// [`Future`]: futures::future::Future
@@ -755,6 +2238,62 @@ impl<A: Adapter> SocketIo<A> {
         self.get_default_op().rooms()
     }
 
+    /// Gets the number of sockets in the given room on the current namespace, without fetching the sockets.
+    ///
+    /// Alias for `io.of("/").unwrap().room_size(room)`
+    ///
+    /// ## Panics
+    /// If the **default namespace "/" is not found** this fn will panic!
+    ///
+    /// ### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// let io2 = io.clone();
+    /// io.ns("/", move |socket: SocketRef| async move {
+    ///     socket.join("lobby").unwrap();
+    ///     println!("{} people in the lobby", io2.room_size("lobby").unwrap());
+    /// });
+    #[inline]
+    pub fn room_size(&self, room: &str) -> Result<usize, A::Error> {
+        self.get_default_op().room_size(room)
+    }
+
+    /// Gets all the rooms of the current namespace with the number of sockets in each of them.
+    /// The rooms named after the sid of one of their sockets are excluded, unless `include_sid_rooms` is true.
+    ///
+    /// Alias for `io.of("/").unwrap().room_sizes(include_sid_rooms)`
+    ///
+    /// ## Panics
+    /// If the **default namespace "/" is not found** this fn will panic!
+    ///
+    /// ### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// let (_, io) = SocketIo::new_svc();
+    /// let io2 = io.clone();
+    /// io.ns("/", move |socket: SocketRef| async move {
+    ///     socket.join(socket.id).unwrap();
+    ///     socket.join("lobby").unwrap();
+    ///     // Only the lobby is listed
+    ///     println!("{:?}", io2.room_sizes(false).unwrap());
+    /// });
+    #[inline]
+    pub fn room_sizes(&self, include_sid_rooms: bool) -> Result<Vec<(Room, usize)>, A::Error> {
+        self.get_default_op().room_sizes(include_sid_rooms)
+    }
+
+    /// Gets the number of sockets connected to the current namespace on this server.
+    ///
+    /// Alias for `io.of("/").unwrap().socket_count()`
+    ///
+    /// ## Panics
+    /// If the **default namespace "/" is not found** this fn will panic!
+    #[inline]
+    pub fn socket_count(&self) -> usize {
+        self.get_default_op().socket_count()
+    }
+
     /// Makes all sockets selected with the previous operators leave the given room(s).
     ///
     /// Alias for `io.of("/").unwrap().join(rooms)`
@@ -845,7 +2384,7 @@ mod tests {
         let config = SocketIoConfig::default().into();
         io.0.get_ns("/")
             .unwrap()
-            .connect(sid, socket, None, config)
+            .connect(sid, socket, None, config, None)
             .await
             .ok();
 
@@ -159,6 +159,7 @@
 //! * [`TransportType`]: extracts the transport type of the socket
 //! * [`DisconnectReason`](crate::socket::DisconnectReason): extracts the reason of the disconnection
 //! * [`State`](extract::State): extracts a reference to a state previously set with [`SocketIoBuilder::with_state`](crate::io::SocketIoBuilder).
+//! * [`Extension`](extract::Extension): extracts a clone of a value previously inserted in the [`extensions`](socket::Socket::extensions) of the socket.
 //! ### Extractor order
 //! Extractors are run in the order of their declaration in the handler signature. If an extractor returns an error, the handler won't be called and a `tracing::error!` call will be emitted if the `tracing` feature is enabled.
 //!
@@ -169,11 +170,15 @@
 //! ## Events
 //! There are three types of events:
 //! * The connect event is emitted when a new connection is established. It can be handled with the [`ConnectHandler`](handler::ConnectHandler) and the `io.ns` method.
-//! * The message event is emitted when a new message is received. It can be handled with the [`MessageHandler`](handler::MessageHandler) and the `socket.on` method.
+//! * The message event is emitted when a new message is received. It can be handled with the [`MessageHandler`](handler::MessageHandler) and the `socket.on` method,
+//!   or for all the sockets of a namespace with the [`SocketIo::ns_on`] method.
 //! * The disconnect event is emitted when a socket is closed. It can be handled with the [`DisconnectHandler`](handler::DisconnectHandler) and the `socket.on_disconnect` method.
 //!
 //! Only one handler can exist for an event so registering a new handler for an event will replace the previous one.
 //!
+//! The event names can be checked at compile time with a type implementing [`SocketIoEvent`],
+//! derivable with the `derive` feature. See the [`event`] module for more details.
+//!
 //! ## Middlewares
 //! When providing a [`ConnectHandler`](handler::ConnectHandler) for a namespace you can add any number of
 //! [`ConnectMiddleware`](handler::ConnectMiddleware) in front of it. It is useful to add authentication or logging middlewares.
@@ -211,6 +216,13 @@
 //! a [`SendError`] will be returned and the provided data will be given back.
 //! Moreover, a tracing log will be emitted if the `tracing` feature is enabled.
 //!
+//! #### Emit ordering
+//! The packets are queued in the channel of each targeted socket before the `emit` call returns,
+//! the broadcasts included: the adapter does not defer the fan-out to another task.
+//! Therefore all the packets sent to a socket are received in the order in which their `emit` calls completed,
+//! whether they were emitted directly to the socket or broadcasted to one of its rooms.
+//! There is no ordering between emits running concurrently on different tasks.
+//!
 //! #### Emitting with operators
 //! To configure the emit, you can chain [`Operators`](operators) methods to the emit call. With that you can easily configure the following options:
 //! * rooms: emit, join, leave to specific rooms
@@ -249,6 +261,7 @@
 //! the state of each socket. It is backed by a [`dashmap`] so you can safely access it from multiple threads.
 //! Beware that deadlocks can easily occur if you hold a value ref and try to remove it at the same time.
 //! See the [`extensions`] module doc for more details.
+//! The values inserted by a middleware can then be extracted in the handlers with the [`Extension`](extract::Extension) extractor.
 //!
 //! #### Global state
 //! You can enable the `state` feature and use [`SocketIoBuilder::with_state`](SocketIoBuilder) method to set
@@ -267,10 +280,13 @@
 //! Currently there is no other adapters available but more will be added in the future.
 //!
 //! ## [Feature flags](#feature-flags)
-//! * `v4`: enable support for the socket.io protocol v4
+//! * `polling` (enabled by default): enable the http long-polling transport, if disabled only websocket connections are accepted
+//! * `v4`: enable support for the socket.io protocol v4 (it enables the `polling` feature)
 //! * `tracing`: enable logging with [`tracing`] calls
+//! * `compression`: enable gzip and brotli compression of the http long-polling payloads
 //! * `extensions`: enable per-socket state with the [`extensions`] module
 //! * `state`: enable global state management
+//! * `derive`: enable the derive macro of the [`SocketIoEvent`] trait
 //!
 pub mod adapter;
 
@@ -281,20 +297,40 @@ pub mod extensions;
 mod state;
 
 pub mod ack;
+pub mod event;
 pub mod handler;
 pub mod layer;
 pub mod operators;
 pub mod packet;
+pub mod presence;
 pub mod service;
+pub mod snapshot;
 pub mod socket;
+pub mod validation;
 
-pub use engineioxide::TransportType;
-pub use errors::{AckError, AdapterError, BroadcastError, DisconnectError, SendError, SocketError};
+pub use client::EngineSocket;
+#[cfg(feature = "polling")]
+pub use engineioxide::handler::UpgradeReport;
+pub use engineioxide::{
+    config::{ClientPingPolicy, OverflowPolicy, PayloadLogging, Utf8Validation},
+    handler::{Health, ServerState},
+    TransportType,
+};
+pub use errors::{
+    AckError, AdapterError, BroadcastError, DisconnectError, EmitError, SendError, SocketError,
+};
+pub use event::SocketIoEvent;
+pub use event_stream::ServerEvent;
 pub use handler::extract;
 pub use io::{SocketIo, SocketIoBuilder, SocketIoConfig};
+pub use ns::DeliveryFilter;
+#[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
+#[cfg(feature = "derive")]
+pub use socketioxide_derive::SocketIoEvent;
 
 mod client;
 mod errors;
+mod event_stream;
 mod io;
 mod ns;
 
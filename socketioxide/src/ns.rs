@@ -1,57 +1,203 @@
 use std::{
     borrow::Cow,
     collections::HashMap,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
 };
 
 use crate::{
-    adapter::Adapter,
+    ack::AckRetry,
+    adapter::{Adapter, LocalAdapter},
     errors::{ConnectFail, Error},
-    handler::{BoxedConnectHandler, ConnectHandler, MakeErasedHandler},
+    event_stream::{EventSender, ServerEvent},
+    handler::{
+        BoxedConnectHandler, BoxedMessageHandler, ConnectHandler, ErrorPolicy, MakeErasedHandler,
+    },
     packet::{Packet, PacketData},
+    snapshot::NamespaceSnapshot,
     socket::Socket,
+    validation::EventValidator,
     SocketIoConfig,
 };
-use crate::{client::SocketData, errors::AdapterError};
-use engineioxide::sid::Sid;
+use crate::{
+    client::{GlobalConnectHandler, SocketData},
+    errors::AdapterError,
+    extract::SocketRef,
+};
+use engineioxide::{shard::ShardedMap, sid::Sid};
 
 pub struct Namespace<A: Adapter> {
     pub path: Cow<'static, str>,
     pub(crate) adapter: A,
+    /// The configuration of the server, e.g. to spawn the tasks of the adapter on the configured runtime
+    pub(crate) config: Arc<SocketIoConfig>,
     handler: BoxedConnectHandler<A>,
-    sockets: RwLock<HashMap<Sid, Arc<Socket<A>>>>,
+    sockets: ShardedMap<Arc<Socket<A>>>,
+    pub(crate) events: EventSender,
+    delivery_filter: RwLock<Option<DeliveryFilter<A>>>,
+    event_validator: RwLock<Option<EventValidator>>,
+    ack_timeout: RwLock<Option<Duration>>,
+    ack_retry: RwLock<Option<AckRetry>>,
+    error_policy: RwLock<ErrorPolicy>,
+    max_connections: RwLock<Option<usize>>,
+    /// The message handlers shared by all the sockets, registered with [`SocketIo::ns_on`](crate::SocketIo::ns_on)
+    message_handlers: RwLock<HashMap<Cow<'static, str>, Arc<BoxedMessageHandler<A>>>>,
+    /// The number of sockets in the namespace, or reserved by a connection in progress
+    connections: AtomicUsize,
+    /// Set once the namespace is deleted
+    closed: AtomicBool,
+}
+
+type ShouldDeliver<A> = dyn Fn(&Socket<A>, &str) -> bool + Send + Sync;
+
+/// A predicate selecting, for each socket of a namespace, the events it receives,
+/// set with [`SocketIo::set_delivery_filter`](crate::SocketIo::set_delivery_filter).
+///
+/// It is called with the socket and the event name in the broadcast fan-out, just before the packet
+/// is pushed into the buffer of each socket, so it should be cheap and must not block.
+/// The payload is not serialized again, the same packet is shared by all the delivered sockets.
+/// The other packets, like acknowledgements or disconnections, are always delivered.
+///
+/// By default it is only applied to broadcasts, see [`DeliveryFilter::direct_emits`].
+pub struct DeliveryFilter<A: Adapter = LocalAdapter> {
+    should_deliver: Arc<ShouldDeliver<A>>,
+    direct_emits: bool,
+}
+
+impl<A: Adapter> DeliveryFilter<A> {
+    /// Creates a filter delivering the events for which `should_deliver` returns true
+    pub fn new<F>(should_deliver: F) -> Self
+    where
+        F: Fn(&Socket<A>, &str) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            should_deliver: Arc::new(should_deliver),
+            direct_emits: false,
+        }
+    }
+
+    /// Also applies the filter to the events emitted directly to a socket with
+    /// [`Socket::emit`], [`Socket::emit_raw_json`], [`Socket::emit_with_binary`] and the
+    /// [`ConfOperators::emit`](crate::operators::ConfOperators::emit). A suppressed direct emit succeeds without sending anything.
+    ///
+    /// Defaults to false.
+    pub fn direct_emits(mut self, direct_emits: bool) -> Self {
+        self.direct_emits = direct_emits;
+        self
+    }
+
+    /// Returns true if an event emitted directly to the socket should be delivered
+    pub(crate) fn should_deliver_direct(&self, socket: &Socket<A>, event: &str) -> bool {
+        !self.direct_emits || (self.should_deliver)(socket, event)
+    }
+
+    /// Returns true if the packet should be delivered to the socket
+    pub(crate) fn should_deliver(&self, socket: &Socket<A>, packet: &PacketData<'_>) -> bool {
+        packet
+            .event_name()
+            .map_or(true, |event| (self.should_deliver)(socket, event))
+    }
+}
+
+impl<A: Adapter> Clone for DeliveryFilter<A> {
+    fn clone(&self) -> Self {
+        Self {
+            should_deliver: self.should_deliver.clone(),
+            direct_emits: self.direct_emits,
+        }
+    }
+}
+
+impl<A: Adapter> std::fmt::Debug for DeliveryFilter<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeliveryFilter")
+            .field("direct_emits", &self.direct_emits)
+            .finish()
+    }
 }
 
 impl<A: Adapter> Namespace<A> {
-    pub fn new<C, T>(path: Cow<'static, str>, handler: C) -> Arc<Self>
+    /// Creates a namespace whose socket map has the given number of shards
+    pub(crate) fn new<C, T>(
+        path: Cow<'static, str>,
+        handler: C,
+        events: EventSender,
+        config: Arc<SocketIoConfig>,
+    ) -> Arc<Self>
     where
         C: ConnectHandler<A, T> + Send + Sync + 'static,
         T: Send + Sync + 'static,
     {
+        Self::new_boxed(
+            path,
+            MakeErasedHandler::new_ns_boxed(handler),
+            events,
+            config,
+        )
+    }
+
+    /// Creates a namespace with an already type erased connect handler
+    pub(crate) fn new_boxed(
+        path: Cow<'static, str>,
+        handler: BoxedConnectHandler<A>,
+        events: EventSender,
+        config: Arc<SocketIoConfig>,
+    ) -> Arc<Self> {
         Arc::new_cyclic(|ns| Self {
             path,
-            handler: MakeErasedHandler::new_ns_boxed(handler),
-            sockets: HashMap::new().into(),
+            handler,
+            sockets: ShardedMap::new(config.engine_config.socket_shards),
             adapter: A::new(ns.clone()),
+            config,
+            events,
+            delivery_filter: RwLock::new(None),
+            event_validator: RwLock::new(None),
+            ack_timeout: RwLock::new(None),
+            ack_retry: RwLock::new(None),
+            error_policy: RwLock::new(ErrorPolicy::Silent),
+            max_connections: RwLock::new(None),
+            message_handlers: RwLock::new(HashMap::new()),
+            connections: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
         })
     }
 
     /// Connects a socket to a namespace.
     ///
-    /// Middlewares are first called to check if the connection is allowed.
+    /// If the namespace is full, a connect_error packet is sent to the client.
+    ///
+    /// Middlewares are then called to check if the connection is allowed.
     /// * If the handler returns an error, a connect_error packet is sent to the client.
-    /// * If the handler returns Ok, a connect packet is sent to the client
-    /// and the handler is called.
+    /// * If the handler returns Ok, a connect packet is sent to the client,
+    /// the global connect handler is called and then the handler of the namespace.
     pub(crate) async fn connect(
         self: Arc<Self>,
         sid: Sid,
         esocket: Arc<engineioxide::Socket<SocketData>>,
         auth: Option<String>,
         config: Arc<SocketIoConfig>,
+        global_connect: Option<GlobalConnectHandler<A>>,
     ) -> Result<(), ConnectFail> {
-        let socket: Arc<Socket<A>> = Socket::new(sid, self.clone(), esocket.clone(), config).into();
+        let socket: Arc<Socket<A>> = Socket::new(sid, self.clone(), esocket.clone(), config)
+            .with_auth(&auth)
+            .into();
+
+        if !self.reserve_connection() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(ns = self.path.as_ref(), ?socket.id, "namespace is full, emitting connect_error packet");
+            if let Err(_e) = socket.send(Packet::connect_error(&self.path, "Namespace is full")) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("error sending connect_error packet: {:?}, closing conn", _e);
+                esocket.close(engineioxide::DisconnectReason::PacketParsingError);
+            }
+            return Err(ConnectFail);
+        }
 
         if let Err(e) = self.handler.call_middleware(socket.clone(), &auth).await {
+            self.release_connection();
             #[cfg(feature = "tracing")]
             tracing::trace!(ns = self.path.as_ref(), ?socket.id, "emitting connect_error packet");
 
@@ -64,7 +210,10 @@ impl<A: Adapter> Namespace<A> {
             return Err(ConnectFail);
         }
 
-        self.sockets.write().unwrap().insert(sid, socket.clone());
+        if self.sockets.insert(sid, socket.clone()).is_some() {
+            // The socket connected again to the namespace, it already holds a slot
+            self.release_connection();
+        }
         let protocol = esocket.protocol.into();
 
         if let Err(_e) = socket.send(Packet::connect(&self.path, socket.id, protocol)) {
@@ -75,6 +224,21 @@ impl<A: Adapter> Namespace<A> {
         }
 
         socket.set_connected(true);
+        esocket
+            .data
+            .namespaces
+            .lock()
+            .unwrap()
+            .push(self.path.clone());
+        self.events.send(|| {
+            Some(ServerEvent::Connect {
+                sid,
+                ns: self.path.clone(),
+            })
+        });
+        if let Some(global_connect) = global_connect {
+            global_connect.call(SocketRef::from(socket.clone()));
+        }
         self.handler.call(socket, auth);
 
         Ok(())
@@ -82,14 +246,16 @@ impl<A: Adapter> Namespace<A> {
 
     /// Removes a socket from a namespace and propagate the event to the adapter
     pub fn remove_socket(&self, sid: Sid) -> Result<(), AdapterError> {
-        self.sockets.write().unwrap().remove(&sid);
+        if self.sockets.remove(&sid).is_some() {
+            self.release_connection();
+        }
         self.adapter
             .del_all(sid)
             .map_err(|err| AdapterError(Box::new(err)))
     }
 
     pub fn has(&self, sid: Sid) -> bool {
-        self.sockets.read().unwrap().values().any(|s| s.id == sid)
+        self.sockets.contains_key(&sid)
     }
 
     pub fn recv(&self, sid: Sid, packet: PacketData<'_>) -> Result<(), Error> {
@@ -100,17 +266,110 @@ impl<A: Adapter> Namespace<A> {
         }
     }
 
-    pub fn get_socket(&self, sid: Sid) -> Result<Arc<Socket<A>>, Error> {
-        self.sockets
-            .read()
+    /// Sets or removes the delivery filter of the namespace
+    pub(crate) fn set_delivery_filter(&self, filter: Option<DeliveryFilter<A>>) {
+        *self.delivery_filter.write().unwrap() = filter;
+    }
+
+    /// Returns the delivery filter of the namespace, if there is one
+    pub(crate) fn delivery_filter(&self) -> Option<DeliveryFilter<A>> {
+        self.delivery_filter.read().unwrap().clone()
+    }
+
+    /// Sets or removes the event validator of the namespace
+    pub(crate) fn set_event_validator(&self, validator: Option<EventValidator>) {
+        *self.event_validator.write().unwrap() = validator;
+    }
+
+    /// Returns the event validator of the namespace, if there is one
+    pub(crate) fn event_validator(&self) -> Option<EventValidator> {
+        self.event_validator.read().unwrap().clone()
+    }
+
+    /// Sets or removes the default ack timeout of the namespace
+    pub(crate) fn set_ack_timeout(&self, timeout: Option<Duration>) {
+        *self.ack_timeout.write().unwrap() = timeout;
+    }
+
+    /// Returns the default ack timeout of the namespace, if there is one
+    pub(crate) fn ack_timeout(&self) -> Option<Duration> {
+        *self.ack_timeout.read().unwrap()
+    }
+
+    /// Sets or removes the ack retry policy of the namespace
+    pub(crate) fn set_ack_retry(&self, retry: Option<AckRetry>) {
+        *self.ack_retry.write().unwrap() = retry;
+    }
+
+    /// Returns the ack retry policy of the namespace, if there is one
+    pub(crate) fn ack_retry(&self) -> Option<AckRetry> {
+        *self.ack_retry.read().unwrap()
+    }
+
+    /// Sets the error policy of the namespace
+    pub(crate) fn set_error_policy(&self, policy: ErrorPolicy) {
+        *self.error_policy.write().unwrap() = policy;
+    }
+
+    /// Returns the error policy of the namespace
+    pub(crate) fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy.read().unwrap().clone()
+    }
+
+    /// Returns true if the error policy of the namespace reports the failures to the clients
+    pub(crate) fn reports_errors(&self) -> bool {
+        *self.error_policy.read().unwrap() != ErrorPolicy::Silent
+    }
+
+    /// Sets or removes the maximum number of sockets connected to the namespace
+    pub(crate) fn set_message_handler(
+        &self,
+        event: Cow<'static, str>,
+        handler: BoxedMessageHandler<A>,
+    ) {
+        self.message_handlers
+            .write()
             .unwrap()
-            .get(&sid)
-            .cloned()
-            .ok_or(Error::SocketGone(sid))
+            .insert(event, Arc::new(handler));
+    }
+
+    /// Returns the message handler of the namespace for the given event, if there is one
+    pub(crate) fn message_handler(&self, event: &str) -> Option<Arc<BoxedMessageHandler<A>>> {
+        self.message_handlers.read().unwrap().get(event).cloned()
+    }
+
+    pub(crate) fn set_max_connections(&self, max: Option<usize>) {
+        *self.max_connections.write().unwrap() = max;
+    }
+
+    /// Reserves a slot for a new socket, returns false if the namespace is full.
+    ///
+    /// The slot is taken atomically so that concurrent connections can't exceed the limit.
+    fn reserve_connection(&self) -> bool {
+        let max = self.max_connections.read().unwrap().unwrap_or(usize::MAX);
+        self.connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < max).then_some(count + 1)
+            })
+            .is_ok()
+    }
+
+    /// Releases the slot of a socket leaving the namespace
+    fn release_connection(&self) {
+        self.connections.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    pub fn get_socket(&self, sid: Sid) -> Result<Arc<Socket<A>>, Error> {
+        self.sockets.get(&sid).ok_or(Error::SocketGone(sid))
     }
 
     pub fn get_sockets(&self) -> Vec<Arc<Socket<A>>> {
-        self.sockets.read().unwrap().values().cloned().collect()
+        self.sockets.values()
+    }
+
+    /// Returns the number of sockets connected to this namespace, summed across the shards of the socket map
+    pub fn socket_count(&self) -> usize {
+        self.sockets.len()
     }
 
     /// Closes the entire namespace :
@@ -121,29 +380,73 @@ impl<A: Adapter> Namespace<A> {
         self.adapter.close().ok();
         #[cfg(feature = "tracing")]
         tracing::debug!("closing all sockets in namespace {}", self.path);
-        let sockets = self.sockets.read().unwrap().clone();
-        futures::future::join_all(sockets.values().map(|s| s.close_underlying_transport())).await;
-        self.sockets.write().unwrap().shrink_to_fit();
+        let sockets = self.sockets.values();
+        futures::future::join_all(sockets.iter().map(|s| s.close_underlying_transport())).await;
+        self.sockets.shrink_to_fit();
         #[cfg(feature = "tracing")]
         tracing::debug!("all sockets in namespace {} closed", self.path);
     }
+
+    /// Deletes the namespace at runtime, once it is removed from the namespace map:
+    /// * The following broadcasts fail with a [`BroadcastError::NamespaceClosed`](crate::BroadcastError::NamespaceClosed) error
+    /// * All the sockets are disconnected with the [`DisconnectReason::ServerNSDisconnect`](crate::socket::DisconnectReason::ServerNSDisconnect) reason,
+    ///   the underlying connections are kept
+    /// * The adapter is closed, dropping the rooms
+    pub(crate) fn delete(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        for socket in self.sockets.values() {
+            if let Err(_e) = socket.disconnect() {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("error disconnecting socket from deleted namespace: {_e}");
+            }
+        }
+        self.sockets.clear();
+        self.connections.store(0, Ordering::Release);
+        self.adapter.close().ok();
+    }
+
+    /// Returns true if the namespace was deleted
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Creates a [`NamespaceSnapshot`] of the namespace and of its sockets.
+    ///
+    /// The sockets are copied out of the namespace before being snapshotted,
+    /// so the namespace is not locked while the adapter is queried.
+    pub(crate) fn snapshot(&self) -> Result<NamespaceSnapshot, A::Error> {
+        let sockets = self
+            .get_sockets()
+            .iter()
+            .map(|socket| socket.snapshot())
+            .collect::<Result<_, _>>()?;
+        Ok(NamespaceSnapshot::new(self.path.to_string(), sockets))
+    }
+
+    /// Removes all the sockets from the namespace without notifying them.
+    ///
+    /// It breaks the reference cycles between the namespace and its sockets
+    /// once the engine.io server is torn down.
+    pub(crate) fn discard_sockets(&self) {
+        self.sockets.clear();
+        self.connections.store(0, Ordering::Release);
+    }
 }
 
 #[cfg(test)]
 impl<A: Adapter> Namespace<A> {
     pub fn new_dummy<const S: usize>(sockets: [Sid; S]) -> Arc<Self> {
-        let ns = Namespace::new(Cow::Borrowed("/"), || {});
+        let config = Arc::new(SocketIoConfig::default());
+        let ns = Namespace::new(Cow::Borrowed("/"), || {}, EventSender::new(1), config);
         for sid in sockets {
             ns.sockets
-                .write()
-                .unwrap()
                 .insert(sid, Socket::new_dummy(sid, ns.clone()).into());
         }
         ns
     }
 
     pub fn clean_dummy_sockets(&self) {
-        self.sockets.write().unwrap().clear();
+        self.sockets.clear();
     }
 }
 
@@ -6,23 +6,28 @@
 //! There is two types of operators:
 //! * [`ConfOperators`]: Chainable operators to configure the message to be sent.
 //! * [`BroadcastOperators`]: Chainable operators to select sockets to send a message to and to configure the message to be sent.
+//! * [`SocketOperators`]: Chainable operators to configure a message sent to a single socket selected by its id.
 use std::borrow::Cow;
 use std::{sync::Arc, time::Duration};
 
-use engineioxide::sid::Sid;
+use engineioxide::{sid::Sid, TransportType};
+use serde_json::Value;
 
 use crate::ack::{AckInnerStream, AckStream};
-use crate::adapter::LocalAdapter;
-use crate::errors::{BroadcastError, DisconnectError};
+use crate::adapter::{BroadcastResult, LocalAdapter};
+use crate::errors::{AdapterError, BroadcastError, DisconnectError, EmitError};
 use crate::extract::SocketRef;
 use crate::socket::Socket;
 use crate::SendError;
 use crate::{
-    adapter::{Adapter, BroadcastFlags, BroadcastOptions, Room},
+    adapter::{Adapter, BroadcastFlags, BroadcastOptions, Room, Sample},
     ns::Namespace,
-    packet::Packet,
+    packet::{BinaryPacket, Packet, PacketData, RawJson},
 };
 
+/// A payload transformer registered with [`BroadcastOperators::map_payload`].
+type PayloadMapper = Box<dyn Fn(Option<&str>, Value) -> Value + Send + Sync>;
+
 /// A trait for types that can be used as a room parameter.
 ///
 /// [`String`], [`Vec<String>`], [`Vec<&str>`], [`&'static str`](str) and const arrays are implemented by default.
@@ -113,6 +118,13 @@ pub struct BroadcastOperators<A: Adapter = LocalAdapter> {
     timeout: Option<Duration>,
     ns: Arc<Namespace<A>>,
     opts: BroadcastOptions,
+    mapper: Option<PayloadMapper>,
+}
+/// Chainable operators to configure a message sent to a single socket, selected with
+/// [`BroadcastOperators::to_socket`] or [`SocketIo::to_socket`](crate::SocketIo::to_socket).
+pub struct SocketOperators<A: Adapter = LocalAdapter> {
+    inner: BroadcastOperators<A>,
+    sid: Sid,
 }
 
 impl<A: Adapter> From<ConfOperators<'_, A>> for BroadcastOperators<A> {
@@ -126,6 +138,7 @@ impl<A: Adapter> From<ConfOperators<'_, A>> for BroadcastOperators<A> {
             timeout: conf.timeout,
             ns: conf.socket.ns.clone(),
             opts,
+            mapper: None,
         }
     }
 }
@@ -189,6 +202,7 @@ impl<'a, A: Adapter> ConfOperators<'a, A> {
     }
 
     /// Filters out all sockets selected with the previous operators which are in the given rooms.
+    /// If no room was selected before, every socket of the namespace that is not in the given rooms is selected.
     /// #### Example
     /// ```
     /// # use socketioxide::{SocketIo, extract::*};
@@ -340,6 +354,7 @@ impl<A: Adapter> ConfOperators<'_, A> {
     ///         socket.bin(bin).emit("test", [arr]).ok();
     ///     });
     /// });
+    #[must_use = "the message is lost if the emit fails"]
     pub fn emit<T: serde::Serialize>(
         mut self,
         event: impl Into<Cow<'static, str>>,
@@ -350,6 +365,10 @@ impl<A: Adapter> ConfOperators<'_, A> {
         if !self.socket.connected() {
             return Err(SendError::Socket(SocketError::Closed(data)));
         }
+        let event = event.into();
+        if !self.socket.should_deliver(&event) {
+            return Ok(());
+        }
         let permit = match self.socket.reserve() {
             Ok(permit) => permit,
             Err(e) => {
@@ -367,7 +386,9 @@ impl<A: Adapter> ConfOperators<'_, A> {
     /// Emits a message to the client and wait for acknowledgement.
     ///
     /// The acknowledgement has a timeout specified in the config (5s by default)
-    /// (see [`SocketIoBuilder::ack_timeout`]) or with the [`timeout()`] operator.
+    /// (see [`SocketIoBuilder::ack_timeout`]), in the namespace (see [`SocketIo::set_ack_timeout`])
+    /// or with the [`timeout()`] operator. If the namespace has an [`AckRetry`] policy
+    /// (see [`SocketIo::set_ack_retry`]), the message is emitted again when the acknowledgement times out.
     ///
     /// To get acknowledgements, an [`AckStream`] is returned.
     /// It can be used in two ways:
@@ -388,6 +409,9 @@ impl<A: Adapter> ConfOperators<'_, A> {
     ///
     /// [`timeout()`]: crate::operators::ConfOperators#method.timeout
     /// [`SocketIoBuilder::ack_timeout`]: crate::SocketIoBuilder#method.ack_timeout
+    /// [`SocketIo::set_ack_timeout`]: crate::SocketIo::set_ack_timeout
+    /// [`SocketIo::set_ack_retry`]: crate::SocketIo::set_ack_retry
+    /// [`AckRetry`]: crate::ack::AckRetry
     /// [`Stream`]: futures::stream::Stream
     /// [`Future`]: futures::future::Future
     /// [`AckResponse`]: crate::ack::AckResponse
@@ -432,10 +456,9 @@ impl<A: Adapter> ConfOperators<'_, A> {
                 return Err(e.with_value(data).into());
             }
         };
-        let timeout = self.timeout.unwrap_or(self.socket.config.ack_timeout);
+        let timeout = self.timeout.unwrap_or_else(|| self.socket.ack_timeout());
         let packet = self.get_packet(event, data)?;
-        let rx = self.socket.send_with_ack_permit(packet, permit);
-        let stream = AckInnerStream::send(rx, timeout, self.socket.id);
+        let stream = self.socket.send_with_ack_stream(packet, permit, timeout);
         Ok(AckStream::<V>::from(stream))
     }
 
@@ -501,6 +524,7 @@ impl<A: Adapter> BroadcastOperators<A> {
             timeout: None,
             ns,
             opts: BroadcastOptions::default(),
+            mapper: None,
         }
     }
     pub(crate) fn from_sock(ns: Arc<Namespace<A>>, sid: Sid) -> Self {
@@ -512,6 +536,7 @@ impl<A: Adapter> BroadcastOperators<A> {
                 sid: Some(sid),
                 ..Default::default()
             },
+            mapper: None,
         }
     }
 
@@ -566,6 +591,7 @@ impl<A: Adapter> BroadcastOperators<A> {
     }
 
     /// Filters out all sockets selected with the previous operators which are in the given rooms.
+    /// If no room was selected before, every socket of the namespace that is not in the given rooms is selected.
     /// #### Example
     /// ```
     /// # use socketioxide::{SocketIo, extract::*};
@@ -680,6 +706,171 @@ impl<A: Adapter> BroadcastOperators<A> {
         self.binary = binary;
         self
     }
+
+    /// Transforms the payload for each selected room before it is sent.
+    ///
+    /// The transformer is called **once per room** selected with [`to()`] or [`within()`], with the room name
+    /// and the serialized payload, and not once per socket: the transformed packet is then sent to every
+    /// socket of the room. If no room is selected, it is called once with `None` for the whole namespace.
+    ///
+    /// A socket that is in several selected rooms only receives one message, transformed for
+    /// one of its rooms. Rooms are visited in lexicographic order.
+    ///
+    /// If a binary payload is attached with [`bin()`], the transformer sees the payload in its placeholder form:
+    /// an array of arguments ending with one `{ "_placeholder": true, "num": n }` object per binary payload,
+    /// which must be kept for the client to reconstruct the message.
+    ///
+    /// [`to()`]: #method.to
+    /// [`within()`]: #method.within
+    /// [`bin()`]: #method.bin
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use serde_json::{json, Value};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on("test", |socket: SocketRef| async move {
+    ///         // Legacy clients get the old field layout, other clients get the new one
+    ///         socket.within(["v1-clients", "v2-clients"])
+    ///             .map_payload(|room, value| match room {
+    ///                 Some("v1-clients") => json!({ "user_name": value["user"]["name"] }),
+    ///                 _ => value,
+    ///             })
+    ///             .emit("user", json!({ "user": { "name": "foo" } }))
+    ///             .ok();
+    ///     });
+    /// });
+    pub fn map_payload(
+        mut self,
+        mapper: impl Fn(Option<&str>, Value) -> Value + Send + Sync + 'static,
+    ) -> Self {
+        self.mapper = Some(Box::new(mapper));
+        self
+    }
+
+    /// Selects `count` sockets uniformly at random among the sockets selected with the previous operators.
+    ///
+    /// The selection is made by the adapter once the targeted sockets are resolved, after the [`except()`] filtering.
+    /// If fewer sockets are targeted, all of them are selected.
+    ///
+    /// When used with [`map_payload()`], a sample is selected for each room.
+    ///
+    /// [`except()`]: #method.except
+    /// [`map_payload()`]: #method.map_payload
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use futures::StreamExt;
+    /// # use std::time::Duration;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on("job", |socket: SocketRef| async move {
+    ///         // Ask any 3 workers to handle the job
+    ///         let acks = socket.to("workers")
+    ///             .sample(3)
+    ///             .timeout(Duration::from_secs(5))
+    ///             .emit_with_ack::<String>("job", "data")
+    ///             .unwrap();
+    ///         acks.for_each(|(sid, ack)| async move {
+    ///             println!("worker {sid} answered: {ack:?}");
+    ///         }).await;
+    ///     });
+    /// });
+    pub fn sample(self, count: usize) -> Self {
+        self.sample_inner(count, None)
+    }
+
+    /// Same as [`sample()`] but with a seeded random generator, to get a deterministic selection.
+    ///
+    /// [`sample()`]: #method.sample
+    pub fn sample_with_seed(self, count: usize, seed: u64) -> Self {
+        self.sample_inner(count, Some(seed))
+    }
+
+    fn sample_inner(mut self, count: usize, seed: Option<u64>) -> Self {
+        self.opts.sample = Some(Sample { count, seed });
+        self
+    }
+
+    /// Only selects the sockets currently connected with the given transport.
+    ///
+    /// The transport of a socket is the one in use when the message is broadcast:
+    /// a socket upgrading from polling to websocket is selected as a polling socket until the upgrade is complete.
+    /// The filter is applied before the [`sample()`] selection.
+    ///
+    /// [`sample()`]: #method.sample
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, TransportType, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on("test", |socket: SocketRef| async move {
+    ///         // Websocket clients get the rich content, polling clients get a lighter fallback
+    ///         let image = vec![0u8; 1024 * 1024];
+    ///         socket.to("room").on_transport(TransportType::Websocket).bin(vec![image]).emit("image", ()).ok();
+    ///         socket.to("room").on_transport(TransportType::Polling).emit("image_url", "https://...").ok();
+    ///     });
+    /// });
+    pub fn on_transport(mut self, transport: TransportType) -> Self {
+        self.opts.transport = Some(transport);
+        self
+    }
+
+    /// Skips the sockets not seen alive for more than `max_staleness`, instead of queueing messages
+    /// in the buffers of clients that are probably gone. See [`Socket::emit_if_alive`] for the staleness definition.
+    ///
+    /// Like [`on_transport()`], the filter is applied before the [`sample()`] selection.
+    ///
+    /// [`on_transport()`]: #method.on_transport
+    /// [`sample()`]: #method.sample
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use std::time::Duration;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on("price", |socket: SocketRef| async move {
+    ///         socket.to("market").skip_stale(Duration::from_secs(30)).emit("price", 42).ok();
+    ///     });
+    /// });
+    pub fn skip_stale(mut self, max_staleness: Duration) -> Self {
+        self.opts.max_staleness = Some(max_staleness);
+        self
+    }
+
+    /// Selects the socket with the given id in this namespace, for example to send a direct message.
+    ///
+    /// The socket is resolved through the adapter when the message is emitted. If it is not connected anymore,
+    /// a [`EmitError::SocketNotFound`] is returned instead of silently sending the message to nobody.
+    ///
+    /// The rooms and filters selected with the previous operators are ignored,
+    /// the binary payload and the timeout are kept.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use serde_json::Value;
+    /// let (_, io) = SocketIo::new_svc();
+    /// let io2 = io.clone();
+    /// io.ns("/chat", move |socket: SocketRef| {
+    ///     let io = io2.clone();
+    ///     socket.on("dm", move |Data::<(String, Value)>((to, data))| async move {
+    ///         let Ok(sid) = to.parse() else { return };
+    ///         if let Err(err) = io.of("/chat").unwrap().to_socket(sid).emit("dm", data) {
+    ///             println!("dm not delivered: {err}");
+    ///         }
+    ///     });
+    /// });
+    pub fn to_socket(self, sid: Sid) -> SocketOperators<A> {
+        let mut inner = BroadcastOperators::from_sock(self.ns, sid);
+        inner.binary = self.binary;
+        inner.timeout = self.timeout;
+        SocketOperators { inner, sid }
+    }
 }
 
 // ==== impl BroadcastOperators consume fns ====
@@ -690,6 +881,9 @@ impl<A: Adapter> BroadcastOperators<A> {
     /// Therefore if you want to send an array as the _first_ argument of the payload,
     /// you need to wrap it in an array or a tuple.
     ///
+    /// On success, the [`BroadcastResult`] gives the number of sockets the message was sent to on this server,
+    /// and the number of sockets skipped by the [`DeliveryFilter`](crate::DeliveryFilter) of the namespace.
+    ///
     /// ## Errors
     /// * When encoding the data into JSON a [`BroadcastError::Serialize`] may be returned.
     /// * If the underlying engine.io connection is closed for a given socket a [`BroadcastError::Socket(SocketError::Closed)`]
@@ -697,6 +891,7 @@ impl<A: Adapter> BroadcastOperators<A> {
     /// * If the packet buffer is full for a given socket, a [`BroadcastError::Socket(SocketError::InternalChannelFull)`]
     /// will be retured.
     /// See [`SocketIoBuilder::max_buffer_size`] option for more infos on internal buffer config
+    /// * If the namespace was deleted, a [`BroadcastError::NamespaceClosed`] is returned.
     ///
     /// > **Note**: If a error is returned because of a specific socket, the message will still be sent to all other sockets.
     ///
@@ -722,18 +917,86 @@ impl<A: Adapter> BroadcastOperators<A> {
     ///         socket.to("room2").emit("test", [arr]).ok();
     ///     });
     /// });
+    #[must_use = "the message is not sent to some clients if the broadcast fails"]
     pub fn emit<T: serde::Serialize>(
         mut self,
         event: impl Into<Cow<'static, str>>,
         data: T,
-    ) -> Result<(), BroadcastError> {
+    ) -> Result<BroadcastResult, BroadcastError> {
         let packet = self.get_packet(event, data)?;
-        if let Err(e) = self.ns.adapter.broadcast(packet, self.opts) {
+        self.send_packet(packet)
+    }
+
+    /// Emits a message with pre-serialized JSON data to all sockets selected with the previous operators,
+    /// without parsing it again.
+    ///
+    /// The packet is only encoded once for all the sockets. If binary attachments or a payload mapper
+    /// are set with the previous operators, the data has to be parsed to be modified.
+    ///
+    /// ## Errors
+    /// The same errors as [`BroadcastOperators::emit`] are returned, except for the serialization ones.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*, packet::RawJson};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     let doc = RawJson::new(r#"{"title":"hello","items":[1,2,3]}"#).unwrap();
+    ///     socket.to("room1").emit_raw_json("doc", doc).ok();
+    /// });
+    /// ```
+    #[must_use = "the message is not sent to some clients if the broadcast fails"]
+    pub fn emit_raw_json(
+        mut self,
+        event: impl Into<Cow<'static, str>>,
+        raw: impl Into<RawJson>,
+    ) -> Result<BroadcastResult, BroadcastError> {
+        let ns = self.ns.path.clone();
+        let raw = raw.into();
+        let packet = if self.binary.is_empty() {
+            Packet::raw_event(ns, event.into(), raw)
+        } else {
+            let binary = std::mem::take(&mut self.binary);
+            Packet::bin_event(ns, event.into(), raw.to_value(), binary)
+        };
+        self.send_packet(packet)
+    }
+
+    /// Broadcasts the packet, applying the payload mapper for each room if there is one
+    fn send_packet(mut self, packet: Packet<'static>) -> Result<BroadcastResult, BroadcastError> {
+        if self.ns.is_closed() {
+            return Err(BroadcastError::NamespaceClosed);
+        }
+        let Some(mapper) = self.mapper.take() else {
+            let res = self.ns.adapter.broadcast(packet, self.opts);
             #[cfg(feature = "tracing")]
-            tracing::debug!("broadcast error: {e:?}");
-            return Err(e);
+            if let Err(e) = &res {
+                tracing::debug!("broadcast error: {e:?}");
+            }
+            return res;
+        };
+
+        let mut res = BroadcastResult::default();
+        let mut errors = Vec::new();
+        for (room, opts) in self.segments() {
+            let packet = map_packet(&packet, room.as_deref(), &mapper);
+            match self.ns.adapter.broadcast(packet, opts) {
+                Ok(segment) => res.merge(segment),
+                Err(BroadcastError::Socket(e)) => errors.extend(e),
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("broadcast error: {e:?}");
+                    return Err(e);
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(res)
+        } else {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("broadcast error: {errors:?}");
+            Err(BroadcastError::Socket(errors))
         }
-        Ok(())
     }
 
     /// Emits a message to all sockets selected with the previous operators and
@@ -759,6 +1022,8 @@ impl<A: Adapter> BroadcastOperators<A> {
     /// an [`AckError::Timeout`]. If the data sent by the client is not deserializable as `V`,
     /// an [`AckError::Serde`] will be yielded.
     ///
+    /// If no socket is selected, the [`AckStream`] ends right away without waiting for the timeout.
+    ///
     /// [`timeout()`]: #method.timeout
     /// [`Stream`]: futures::stream::Stream
     /// [`Future`]: futures::future::Future
@@ -800,12 +1065,26 @@ impl<A: Adapter> BroadcastOperators<A> {
         data: impl serde::Serialize,
     ) -> Result<AckStream<V>, serde_json::Error> {
         let packet = self.get_packet(event, data)?;
-        let stream = self
-            .ns
-            .adapter
-            .broadcast_with_ack(packet, self.opts, self.timeout)
-            .into();
-        Ok(stream)
+        let Some(mapper) = self.mapper.take() else {
+            let stream = self
+                .ns
+                .adapter
+                .broadcast_with_ack(packet, self.opts, self.timeout)
+                .into();
+            return Ok(stream);
+        };
+
+        let streams: Vec<_> = self
+            .segments()
+            .into_iter()
+            .map(|(room, opts)| {
+                let packet = map_packet(&packet, room.as_deref(), &mapper);
+                self.ns
+                    .adapter
+                    .broadcast_with_ack(packet, opts, self.timeout)
+            })
+            .collect();
+        Ok(AckInnerStream::merge(streams).into())
     }
 
     /// Gets all sockets selected with the previous operators.
@@ -845,6 +1124,46 @@ impl<A: Adapter> BroadcastOperators<A> {
         self.ns.adapter.disconnect_socket(self.opts)
     }
 
+    /// Asks all sockets selected with the previous operators to reconnect to another server,
+    /// then disconnects them, see [`Socket::migrate`].
+    ///
+    /// The disconnections are spread evenly over the [`migration_jitter`](crate::SocketIoBuilder::migration_jitter),
+    /// after the [`migration_delay`](crate::SocketIoBuilder::migration_delay), so that the sockets
+    /// don't all reconnect to the target server at the same time.
+    ///
+    /// ### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///   socket.on("drain", |socket: SocketRef| async move {
+    ///     // Move all the sockets of the room1 to another server
+    ///     socket.within("room1").migrate("wss://node-2.example.com").unwrap();
+    ///   });
+    /// });
+    pub fn migrate(self, url: &str) -> Result<(), BroadcastError> {
+        let sockets = self
+            .ns
+            .adapter
+            .fetch_sockets(self.opts)
+            .map_err(Into::<AdapterError>::into)?;
+        // Each socket is disconnected at a random time of its own slot of the jitter
+        let count = sockets.len() as f64;
+        let errors: Vec<_> = sockets
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, socket)| {
+                let ratio = (i as f64 + rand::random::<f64>()) / count;
+                socket.migrate_at(url, ratio).err()
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.into())
+        }
+    }
+
     /// Makes all sockets selected with the previous operators join the given room(s).
     ///
     /// ### Example
@@ -882,6 +1201,53 @@ impl<A: Adapter> BroadcastOperators<A> {
         self.ns.adapter.rooms()
     }
 
+    /// Gets the number of sockets in the given room of the namespace, without fetching the sockets.
+    ///
+    /// ### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///   socket.on("join", |socket: SocketRef| async move {
+    ///     socket.join("lobby").unwrap();
+    ///     let online = socket.broadcast().room_size("lobby").unwrap();
+    ///     socket.emit("online", online).ok();
+    ///   });
+    /// });
+    pub fn room_size(&self, room: &str) -> Result<usize, A::Error> {
+        self.ns.adapter.room_size(room)
+    }
+
+    /// Gets all the rooms of the namespace with the number of sockets in each of them.
+    ///
+    /// The rooms named after the sid of one of their sockets (e.g. joined with `socket.join(socket.id)`)
+    /// are excluded, unless `include_sid_rooms` is true.
+    ///
+    /// ### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///   socket.on("channels", |socket: SocketRef| async move {
+    ///     let channels = socket.broadcast().room_sizes(false).unwrap();
+    ///     for (room, size) in channels {
+    ///         println!("{room}: {size} members");
+    ///     }
+    ///   });
+    /// });
+    pub fn room_sizes(&self, include_sid_rooms: bool) -> Result<Vec<(Room, usize)>, A::Error> {
+        self.ns.adapter.room_sizes(include_sid_rooms)
+    }
+
+    /// Gets the number of sockets connected to the namespace on this server.
+    ///
+    /// Unlike [`room_size()`], it doesn't depend on the rooms joined by the sockets.
+    ///
+    /// [`room_size()`]: #method.room_size
+    pub fn socket_count(&self) -> usize {
+        self.ns.socket_count()
+    }
+
     /// Gets a [`SocketRef`] by the specified [`Sid`].
     pub fn get_socket(&self, sid: Sid) -> Option<SocketRef<A>> {
         self.ns.get_socket(sid).map(SocketRef::from).ok()
@@ -903,4 +1269,131 @@ impl<A: Adapter> BroadcastOperators<A> {
         };
         Ok(packet)
     }
+
+    /// Splits the selection into one [`BroadcastOptions`] per selected room, so that the payload mapper
+    /// is applied once per room. Rooms already visited are excluded from the next ones so that
+    /// a socket in several selected rooms only receives one message.
+    fn segments(&self) -> Vec<(Option<Room>, BroadcastOptions)> {
+        if self.opts.rooms.is_empty() {
+            return vec![(None, self.opts.clone())];
+        }
+        let mut rooms: Vec<&Room> = self.opts.rooms.iter().collect();
+        rooms.sort();
+
+        let mut except = self.opts.except.clone();
+        let mut segments = Vec::with_capacity(rooms.len());
+        for room in rooms {
+            let opts = BroadcastOptions {
+                flags: self.opts.flags.clone(),
+                rooms: [room.clone()].into(),
+                except: except.clone(),
+                sid: self.opts.sid,
+                sample: self.opts.sample,
+                transport: self.opts.transport,
+                max_staleness: self.opts.max_staleness,
+            };
+            except.insert(room.clone());
+            segments.push((Some(room.clone()), opts));
+        }
+        segments
+    }
+}
+
+// ==== impl SocketOperators operations ====
+impl<A: Adapter> SocketOperators<A> {
+    /// Sets a custom timeout when sending a message with an acknowledgement.
+    ///
+    /// See [`SocketIoBuilder::ack_timeout`](crate::SocketIoBuilder) for the default timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.inner.timeout = Some(timeout);
+        self
+    }
+
+    /// Adds a binary payload to the message.
+    pub fn bin(mut self, binary: Vec<Vec<u8>>) -> Self {
+        self.inner.binary = binary;
+        self
+    }
+
+    /// Emits a message to the selected socket.
+    ///
+    /// ## Errors
+    /// * If the socket is not connected to the namespace, a [`EmitError::SocketNotFound`] is returned.
+    /// * When encoding the data into JSON a [`EmitError::Serialize`] may be returned.
+    /// * If the underlying engine.io connection is closed or its buffer is full, a [`EmitError::Socket`] is returned.
+    ///
+    /// A message skipped by the [`DeliveryFilter`](crate::DeliveryFilter) of the namespace is not an error.
+    #[must_use = "the message is lost if the emit fails"]
+    pub fn emit<T: serde::Serialize>(
+        self,
+        event: impl Into<Cow<'static, str>>,
+        data: T,
+    ) -> Result<(), EmitError> {
+        let sid = self.sid;
+        let res = self.inner.emit(event, data)?;
+        if res.sent + res.skipped == 0 {
+            return Err(EmitError::SocketNotFound(sid));
+        }
+        Ok(())
+    }
+
+    /// Emits a message to the selected socket and waits for its acknowledgement.
+    ///
+    /// See [`BroadcastOperators::emit_with_ack`] for more details on the returned [`AckStream`],
+    /// which yields at most one acknowledgement.
+    ///
+    /// ## Errors
+    /// * If the socket is not connected to the namespace, a [`EmitError::SocketNotFound`] is **immediately** returned.
+    /// * When encoding the data into JSON a [`EmitError::Serialize`] is **immediately** returned.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// let io2 = io.clone();
+    /// io.ns("/", move |socket: SocketRef| {
+    ///     let io = io2.clone();
+    ///     async move {
+    ///         let ack = io.to_socket(socket.id).emit_with_ack::<String>("ping", ()).unwrap().await;
+    ///         println!("{ack:?}");
+    ///     }
+    /// });
+    pub fn emit_with_ack<V>(
+        self,
+        event: impl Into<Cow<'static, str>>,
+        data: impl serde::Serialize,
+    ) -> Result<AckStream<V>, EmitError> {
+        let sockets = self
+            .inner
+            .ns
+            .adapter
+            .fetch_sockets(self.inner.opts.clone())
+            .map_err(Into::into)?;
+        if sockets.is_empty() {
+            return Err(EmitError::SocketNotFound(self.sid));
+        }
+        Ok(self.inner.emit_with_ack(event, data)?)
+    }
+}
+
+/// Applies a payload mapper to a copy of an event packet.
+fn map_packet(
+    packet: &Packet<'static>,
+    room: Option<&str>,
+    mapper: &PayloadMapper,
+) -> Packet<'static> {
+    let mut packet = packet.clone();
+    match &mut packet.inner {
+        PacketData::Event(_, data, _)
+        | PacketData::BinaryEvent(_, BinaryPacket { data, .. }, _) => {
+            *data = mapper(room, data.take())
+        }
+        PacketData::RawEvent(e, raw, ack) => {
+            // The raw data must be parsed to be mapped
+            let data = mapper(room, raw.to_value());
+            packet.inner = PacketData::Event(std::mem::take(e), data, *ack);
+        }
+        _ => unreachable!("only event packets are broadcasted"),
+    }
+    packet
 }
@@ -1,15 +1,72 @@
 //! Socket.io packet implementation.
 //! The [`Packet`] is the base unit of data that is sent over the engine.io socket.
-//! It should not be used directly except when implementing the [`Adapter`](crate::adapter::Adapter) trait.
-use std::borrow::Cow;
+//! It should not be used directly except when implementing the [`Adapter`](crate::adapter::Adapter) trait
+//! or a custom transport.
+//!
+//! A packet is encoded to its text representation with `String::from(packet)`
+//! and decoded with `Packet::try_from(string)`. The binary attachments of the binary packets
+//! are not part of the text representation, they are sent separately as engine.io binary packets
+//! and added to a decoded packet with [`BinaryPacket::add_payload`].
+use std::{borrow::Cow, sync::Arc};
 
 use crate::ProtocolVersion;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::{json, value::RawValue, Value};
 
 use crate::errors::Error;
 use engineioxide::sid::Sid;
 
+/// Error returned when decoding a [`Packet`]
+#[derive(thiserror::Error, Debug)]
+pub enum PacketError {
+    /// The packet type is unknown or the packet is malformed
+    #[error("invalid packet type")]
+    InvalidPacketType,
+    /// The event name of an event packet is missing or is not a string
+    #[error("invalid event name")]
+    InvalidEventName,
+    /// The data of the packet is not valid json
+    #[error("error deserializing json packet: {0:?}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// What the server does when an incoming packet can't be decoded,
+/// e.g. because its data is not valid json or an event has no name.
+///
+/// It is set with [`SocketIoBuilder::malformed_event_policy`](crate::SocketIoBuilder::malformed_event_policy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MalformedEventPolicy {
+    /// The packet is dropped and the connection is kept open.
+    Drop,
+    /// The packet is dropped and an `error` event is emitted to the client,
+    /// on the namespace of the packet, with a [`MalformedEvent`].
+    SendError,
+    /// The connection is closed.
+    #[default]
+    Close,
+}
+
+/// The data of the `error` event emitted with the [`MalformedEventPolicy::SendError`] policy.
+///
+/// It is serialized as `{ "event": event, "reason": reason }`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MalformedEvent {
+    /// The name of the malformed event, if it could be read from the packet
+    pub event: Option<String>,
+    /// Why the packet can't be decoded
+    pub reason: String,
+}
+
+impl From<PacketError> for Error {
+    fn from(err: PacketError) -> Self {
+        match err {
+            PacketError::InvalidPacketType => Error::InvalidPacketType,
+            PacketError::InvalidEventName => Error::InvalidEventName,
+            PacketError::Json(e) => Error::Serialize(e),
+        }
+    }
+}
+
 /// The socket.io packet type.
 /// Each packet has a type and a namespace
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -111,6 +168,26 @@ impl<'a> Packet<'a> {
         }
     }
 
+    /// Create an event packet with pre-serialized arguments for the given namespace
+    pub fn raw_event(
+        ns: impl Into<Cow<'a, str>>,
+        e: impl Into<Cow<'a, str>>,
+        raw: RawJson,
+    ) -> Self {
+        Self {
+            inner: PacketData::RawEvent(e.into(), raw, None),
+            ns: ns.into(),
+        }
+    }
+
+    /// Create an ack packet with pre-serialized data for the given namespace
+    pub fn raw_ack(ns: &'a str, raw: RawJson, ack: i64) -> Self {
+        Self {
+            inner: PacketData::RawAck(raw, ack),
+            ns: Cow::Borrowed(ns),
+        }
+    }
+
     /// Create a binary ack packet for the given namespace
     pub fn bin_ack(ns: &'a str, data: Value, bin: Vec<Vec<u8>>, ack: i64) -> Self {
         debug_assert!(!bin.is_empty());
@@ -136,10 +213,10 @@ impl<'a> Packet<'a> {
             Connect(Some(data)) => data.len(),
             Connect(None) => 0,
             Disconnect => 0,
-            Event(_, _, Some(ack)) => {
+            Event(_, _, Some(ack)) | RawEvent(_, _, Some(ack)) => {
                 ack.checked_ilog10().unwrap_or(0) as usize + ACK_PUNCTUATION_SIZE
             }
-            Event(_, _, None) => 0,
+            Event(_, _, None) | RawEvent(_, _, None) => 0,
             BinaryEvent(_, bin, None) => {
                 bin.payload_count.checked_ilog10().unwrap_or(0) as usize + BINARY_PUNCTUATION_SIZE
             }
@@ -149,7 +226,9 @@ impl<'a> Packet<'a> {
                     + ACK_PUNCTUATION_SIZE
                     + BINARY_PUNCTUATION_SIZE
             }
-            EventAck(_, ack) => ack.checked_ilog10().unwrap_or(0) as usize + ACK_PUNCTUATION_SIZE,
+            EventAck(_, ack) | RawAck(_, ack) => {
+                ack.checked_ilog10().unwrap_or(0) as usize + ACK_PUNCTUATION_SIZE
+            }
             BinaryAck(bin, ack) => {
                 ack.checked_ilog10().unwrap_or(0) as usize
                     + bin.payload_count.checked_ilog10().unwrap_or(0) as usize
@@ -195,6 +274,99 @@ pub enum PacketData<'a> {
     BinaryEvent(Cow<'a, str>, BinaryPacket, Option<i64>),
     /// Binary ack packet, to acknowledge an event with binary data
     BinaryAck(BinaryPacket, i64),
+    /// Event packet with pre-serialized arguments, only used to send packets
+    RawEvent(Cow<'a, str>, RawJson, Option<i64>),
+    /// Event ack packet with pre-serialized data, only used to send packets
+    RawAck(RawJson, i64),
+}
+
+/// A pre-serialized JSON value that is spliced as-is into the packets, without being parsed again.
+///
+/// Like any other data, if it is a JSON array, its elements are sent as multiple arguments.
+/// The JSON is sent as-is: the encoded packet is byte-identical to the one of the parsed [`Value`]
+/// if it was produced by [`serde_json::to_string`] from this value, otherwise its whitespaces are kept.
+///
+/// It is cheap to clone so that it is not copied for each socket of a broadcast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawJson(Arc<str>);
+
+impl RawJson {
+    /// Creates a [`RawJson`] from a string, checking that it is valid JSON.
+    pub fn new(json: impl Into<Arc<str>>) -> Result<Self, serde_json::Error> {
+        let json = json.into();
+        serde_json::from_str::<&RawValue>(&json)?;
+        Ok(Self(json))
+    }
+
+    /// Creates a [`RawJson`] from a string that is trusted to be valid JSON.
+    ///
+    /// The JSON is only validated in debug builds, where this function panics if it is invalid.
+    pub fn new_unchecked(json: impl Into<Arc<str>>) -> Self {
+        let json = json.into();
+        debug_assert!(
+            serde_json::from_str::<&RawValue>(&json).is_ok(),
+            "invalid raw JSON: {json}"
+        );
+        Self(json)
+    }
+
+    /// Returns the JSON string
+    pub fn get(&self) -> &str {
+        &self.0
+    }
+
+    /// Parses the JSON into a [`Value`], used when the data must be modified
+    pub(crate) fn to_value(&self) -> Value {
+        serde_json::from_str(&self.0).unwrap_or(Value::Null)
+    }
+
+    /// Serializes the data of an event packet: `["<event name>", ...data]`
+    fn event_data(&self, e: &str) -> String {
+        let json = self.0.trim();
+        let e = serde_json::to_string(e).unwrap();
+        let mut res = String::with_capacity(json.len() + e.len() + 3);
+        res.push('[');
+        res.push_str(&e);
+        match json.strip_prefix('[').and_then(|j| j.strip_suffix(']')) {
+            // An empty array is sent as a single argument, like with a parsed value
+            Some(args) if args.trim().is_empty() => res.push_str(",[]"),
+            Some(args) => {
+                res.push(',');
+                res.push_str(args);
+            }
+            None => {
+                res.push(',');
+                res.push_str(json);
+            }
+        }
+        res.push(']');
+        res
+    }
+
+    /// Serializes the data of an ack packet, which is always an array
+    fn ack_data(&self) -> String {
+        let json = self.0.trim();
+        if json.starts_with('[') {
+            json.to_string()
+        } else if json == "null" {
+            "[]".to_string()
+        } else {
+            format!("[{json}]")
+        }
+    }
+}
+
+impl From<&RawValue> for RawJson {
+    fn from(value: &RawValue) -> Self {
+        Self(value.get().into())
+    }
+}
+
+impl From<Box<RawValue>> for RawJson {
+    fn from(value: Box<RawValue>) -> Self {
+        let value: Box<str> = value.into();
+        Self(value.into())
+    }
 }
 
 /// Binary packet used when sending binary data
@@ -218,6 +390,8 @@ impl<'a> PacketData<'a> {
             PacketData::ConnectError(_) => '4',
             PacketData::BinaryEvent(_, _, _) => '5',
             PacketData::BinaryAck(_, _) => '6',
+            PacketData::RawEvent(_, _, _) => '2',
+            PacketData::RawAck(_, _) => '3',
         }
     }
 
@@ -225,13 +399,23 @@ impl<'a> PacketData<'a> {
     /// It will only set the ack id for the packets that support it
     pub(crate) fn set_ack_id(&mut self, ack_id: i64) {
         match self {
-            PacketData::Event(_, _, ack) | PacketData::BinaryEvent(_, _, ack) => {
-                *ack = Some(ack_id)
-            }
+            PacketData::Event(_, _, ack)
+            | PacketData::BinaryEvent(_, _, ack)
+            | PacketData::RawEvent(_, _, ack) => *ack = Some(ack_id),
             _ => {}
         };
     }
 
+    /// Returns the event name of the packet if it is an event
+    pub(crate) fn event_name(&self) -> Option<&str> {
+        match self {
+            PacketData::Event(e, _, _)
+            | PacketData::BinaryEvent(e, _, _)
+            | PacketData::RawEvent(e, _, _) => Some(e),
+            _ => None,
+        }
+    }
+
     /// Check if the packet is a binary packet (either binary event or binary ack)
     pub(crate) fn is_binary(&self) -> bool {
         matches!(
@@ -300,11 +484,67 @@ impl BinaryPacket {
     pub fn is_complete(&self) -> bool {
         self.payload_count == self.bin.len()
     }
+
+    /// The number of expected payloads
+    pub(crate) fn payload_count(&self) -> usize {
+        self.payload_count
+    }
 }
 
 impl<'a> From<Packet<'a>> for String {
-    fn from(mut packet: Packet<'a>) -> String {
+    fn from(packet: Packet<'a>) -> String {
+        packet.encode().0
+    }
+}
+
+/// An event packet encoded once without ack id, to be sent several times with a different one.
+/// It is used by the [`AckRetry`](crate::ack::AckRetry) policy to emit an event again without serializing it.
+#[derive(Debug)]
+pub(crate) struct EncodedEvent {
+    encoded: String,
+    /// The position of the ack id in the encoded packet
+    ack_pos: usize,
+    bin: Vec<Vec<u8>>,
+}
+
+impl EncodedEvent {
+    pub fn new(mut packet: Packet<'_>) -> Self {
+        let bin = match packet.inner {
+            PacketData::BinaryEvent(_, ref mut bin, ref mut ack) => {
+                *ack = None;
+                std::mem::take(&mut bin.bin)
+            }
+            PacketData::Event(_, _, ref mut ack) | PacketData::RawEvent(_, _, ref mut ack) => {
+                *ack = None;
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        let (encoded, ack_pos) = packet.encode();
+        Self {
+            encoded,
+            ack_pos,
+            bin,
+        }
+    }
+
+    /// Returns the encoded packet with the given ack id, and its binary payloads
+    pub fn with_ack_id(&self, ack: i64) -> (String, Vec<Vec<u8>>) {
+        let mut itoa_buf = itoa::Buffer::new();
+        let ack = itoa_buf.format(ack);
+        let mut res = String::with_capacity(self.encoded.len() + ack.len());
+        res.push_str(&self.encoded[..self.ack_pos]);
+        res.push_str(ack);
+        res.push_str(&self.encoded[self.ack_pos..]);
+        (res, self.bin.clone())
+    }
+}
+
+impl Packet<'_> {
+    /// Encodes the packet, the position where the ack id of an event is or would be is returned with it
+    fn encode(self) -> (String, usize) {
         use PacketData::*;
+        let mut packet = self;
 
         // Serialize the data if there is any
         // pre-serializing allows to preallocate the buffer
@@ -332,6 +572,8 @@ impl<'a> From<Packet<'a>> for String {
                 .unwrap();
                 Some(packet)
             }
+            RawEvent(e, raw, _) => Some(raw.event_data(e)),
+            RawAck(raw, _) => Some(raw.ack_data()),
             _ => None,
         };
 
@@ -356,18 +598,20 @@ impl<'a> From<Packet<'a>> for String {
         }
 
         let mut itoa_buf = itoa::Buffer::new();
+        let mut ack_pos = 0;
 
         match packet.inner {
             PacketData::Connect(Some(data)) => res.push_str(&data),
             PacketData::Disconnect | PacketData::Connect(None) => (),
-            PacketData::Event(_, _, ack) => {
+            PacketData::Event(_, _, ack) | PacketData::RawEvent(_, _, ack) => {
+                ack_pos = res.len();
                 if let Some(ack) = ack {
                     res.push_str(itoa_buf.format(ack));
                 }
 
                 res.push_str(&data.unwrap())
             }
-            PacketData::EventAck(_, ack) => {
+            PacketData::EventAck(_, ack) | PacketData::RawAck(_, ack) => {
                 res.push_str(itoa_buf.format(ack));
                 res.push_str(&data.unwrap())
             }
@@ -378,6 +622,7 @@ impl<'a> From<Packet<'a>> for String {
 
                 push_nsp(&mut res);
 
+                ack_pos = res.len();
                 if let Some(ack) = ack {
                     res.push_str(itoa_buf.format(ack));
                 }
@@ -395,7 +640,7 @@ impl<'a> From<Packet<'a>> for String {
             }
         };
 
-        res
+        (res, ack_pos)
     }
 }
 
@@ -403,19 +648,19 @@ impl<'a> From<Packet<'a>> for String {
 /// ```text
 /// ["<event name>", ...<JSON-stringified payload without binary>]
 /// ```
-fn deserialize_event_packet(data: &str) -> Result<(String, Value), Error> {
+fn deserialize_event_packet(data: &str) -> Result<(String, Value), PacketError> {
     #[cfg(feature = "tracing")]
-    tracing::debug!("Deserializing event packet: {:?}", data);
+    tracing::debug!("Deserializing event packet of {} bytes", data.len());
     let packet = match serde_json::from_str::<Value>(data)? {
         Value::Array(packet) => packet,
-        _ => return Err(Error::InvalidEventName),
+        _ => return Err(PacketError::InvalidEventName),
     };
 
     let event = packet
         .first()
-        .ok_or(Error::InvalidEventName)?
+        .ok_or(PacketError::InvalidEventName)?
         .as_str()
-        .ok_or(Error::InvalidEventName)?
+        .ok_or(PacketError::InvalidEventName)?
         .to_string();
     let payload = Value::from_iter(packet.into_iter().skip(1));
     Ok((event, payload))
@@ -423,7 +668,7 @@ fn deserialize_event_packet(data: &str) -> Result<(String, Value), Error> {
 
 fn deserialize_packet<T: DeserializeOwned>(data: &str) -> Result<Option<T>, serde_json::Error> {
     #[cfg(feature = "tracing")]
-    tracing::debug!("Deserializing packet: {:?}", data);
+    tracing::debug!("Deserializing packet of {} bytes", data.len());
     let packet = if data.is_empty() {
         None
     } else {
@@ -432,64 +677,130 @@ fn deserialize_packet<T: DeserializeOwned>(data: &str) -> Result<Option<T>, serd
     Ok(packet)
 }
 
-/// Deserialize a packet from a string
-/// The string should be in the format of:
+/// Read the declared number of binary attachments from the header of a raw binary packet
+/// without deserializing the rest of the packet.
+///
+/// Returns the count and the length of the header part (`<# of binary attachments>-`),
+/// or `None` if the header is malformed or the packet is not a binary packet.
+pub(crate) fn declared_attachments(value: &str) -> Option<(usize, usize)> {
+    let chars = value.as_bytes();
+    if !matches!(chars.first(), Some(b'5' | b'6')) {
+        return None;
+    }
+    let digits = chars[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 || chars.get(digits + 1) != Some(&b'-') {
+        return None;
+    }
+    // An overflowing count is reported as the biggest possible count
+    let count = value[1..digits + 1].parse().unwrap_or(usize::MAX);
+    Some((count, digits + 1))
+}
+
+/// The header of a raw packet, read without deserializing its data
+struct RawHeader<'a> {
+    index: u8,
+    ns: Option<&'a str>,
+    ack: Option<i64>,
+    data: &'a str,
+}
+
+/// Read the header of a raw packet:
 /// ```text
-/// <packet type>[<# of binary attachments>-][<namespace>,][<acknowledgment id>][JSON-stringified payload without binary]
-/// + binary attachments extracted
+/// <packet type>[<# of binary attachments>-][<namespace>,][<acknowledgment id>]
 /// ```
-impl<'a> TryFrom<String> for Packet<'a> {
-    type Error = Error;
+fn read_header(value: &str) -> Result<RawHeader<'_>, PacketError> {
+    // It is possible to parse the packet from a byte slice because separators are only ASCII
+    let chars = value.as_bytes();
+    let mut i = 1;
+    let index = chars
+        .first()
+        .copied()
+        .filter(|c| (b'0'..=b'6').contains(c))
+        .ok_or(PacketError::InvalidPacketType)?;
+
+    // Move the cursor to skip the payload count if it is a binary packet
+    if index == b'5' || index == b'6' {
+        i += declared_attachments(value)
+            .ok_or(PacketError::InvalidPacketType)?
+            .1;
+    }
 
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        // It is possible to parse the packet from a byte slice because separators are only ASCII
-        let chars = value.as_bytes();
-        let mut i = 1;
-        let index = (b'0'..=b'6')
-            .contains(&chars[0])
-            .then_some(chars[0])
-            .ok_or(Error::InvalidPacketType)?;
-
-        // Move the cursor to skip the payload count if it is a binary packet
-        if index == b'5' || index == b'6' {
-            while chars.get(i) != Some(&b'-') {
-                i += 1;
+    let start_index = i;
+    // Custom nsps will start with a slash
+    let ns = if chars.get(i) == Some(&b'/') {
+        loop {
+            match chars.get(i) {
+                Some(b',') => {
+                    i += 1;
+                    break Some(&value[start_index..i - 1]);
+                }
+                // It maybe possible depending on clients that ns does not end with a comma
+                // if it is the end of the packet
+                // e.g `1/custom`
+                None => {
+                    break Some(&value[start_index..i]);
+                }
+                Some(_) => i += 1,
             }
-            i += 1;
         }
+    } else {
+        None
+    };
 
-        let start_index = i;
-        // Custom nsps will start with a slash
-        let ns = if chars.get(i) == Some(&b'/') {
-            loop {
-                match chars.get(i) {
-                    Some(b',') => {
-                        i += 1;
-                        break Cow::Owned(value[start_index..i - 1].to_string());
-                    }
-                    // It maybe possible depending on clients that ns does not end with a comma
-                    // if it is the end of the packet
-                    // e.g `1/custom`
-                    None => {
-                        break Cow::Owned(value[start_index..i].to_string());
-                    }
-                    Some(_) => i += 1,
-                }
-            }
-        } else {
-            Cow::Borrowed("/")
-        };
+    let start_index = i;
+    let ack: Option<i64> = loop {
+        match chars.get(i) {
+            Some(c) if c.is_ascii_digit() => i += 1,
+            Some(b'[' | b'{') if i > start_index => break value[start_index..i].parse().ok(),
+            _ => break None,
+        }
+    };
 
-        let start_index = i;
-        let ack: Option<i64> = loop {
-            match chars.get(i) {
-                Some(c) if c.is_ascii_digit() => i += 1,
-                Some(b'[' | b'{') if i > start_index => break value[start_index..i].parse().ok(),
-                _ => break None,
-            }
-        };
+    Ok(RawHeader {
+        index,
+        ns,
+        ack,
+        data: &value[i..],
+    })
+}
+
+/// Best-effort read of the namespace and the event name of a packet that can't be decoded.
+///
+/// The namespace falls back to the main namespace if the header is malformed,
+/// the event name is `None` if it is not the first string of the data.
+pub(crate) fn malformed_packet_info(value: &str) -> (&str, Option<String>) {
+    let Ok(header) = read_header(value) else {
+        return ("/", None);
+    };
+    let ns = header.ns.unwrap_or("/");
+    if !matches!(header.index, b'2' | b'5') {
+        return (ns, None);
+    }
+    // Only the first value of the array is deserialized, the rest may be invalid
+    let event = header.data.trim_start().strip_prefix('[').and_then(|data| {
+        let mut values = serde_json::Deserializer::from_str(data).into_iter::<String>();
+        values.next()?.ok()
+    });
+    (ns, event)
+}
 
-        let data = &value[i..];
+/// Deserialize a packet from a string
+/// The string should be in the format of:
+/// ```text
+/// <packet type>[<# of binary attachments>-][<namespace>,][<acknowledgment id>][JSON-stringified payload without binary]
+/// + binary attachments extracted
+/// ```
+impl<'a> TryFrom<&str> for Packet<'a> {
+    type Error = PacketError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let RawHeader {
+            index,
+            ns,
+            ack,
+            data,
+        } = read_header(value)?;
+        let ns = ns.map_or(Cow::Borrowed("/"), |ns| Cow::Owned(ns.to_string()));
         let inner = match index {
             b'0' => PacketData::Connect((!data.is_empty()).then(|| data.to_string())),
             b'1' => PacketData::Disconnect,
@@ -498,27 +809,36 @@ impl<'a> TryFrom<String> for Packet<'a> {
                 PacketData::Event(event.into(), payload, ack)
             }
             b'3' => {
-                let packet = deserialize_packet(data)?.ok_or(Error::InvalidPacketType)?;
-                PacketData::EventAck(packet, ack.ok_or(Error::InvalidPacketType)?)
+                let packet = deserialize_packet(data)?.ok_or(PacketError::InvalidPacketType)?;
+                PacketData::EventAck(packet, ack.ok_or(PacketError::InvalidPacketType)?)
             }
             b'5' => {
                 let (event, payload) = deserialize_event_packet(data)?;
                 PacketData::BinaryEvent(event.into(), BinaryPacket::incoming(payload), ack)
             }
             b'6' => {
-                let packet = deserialize_packet(data)?.ok_or(Error::InvalidPacketType)?;
+                let packet = deserialize_packet(data)?.ok_or(PacketError::InvalidPacketType)?;
                 PacketData::BinaryAck(
                     BinaryPacket::incoming(packet),
-                    ack.ok_or(Error::InvalidPacketType)?,
+                    ack.ok_or(PacketError::InvalidPacketType)?,
                 )
             }
-            _ => return Err(Error::InvalidPacketType),
+            _ => return Err(PacketError::InvalidPacketType),
         };
 
         Ok(Self { inner, ns })
     }
 }
 
+/// Deserialize a packet from a string, same as the `TryFrom<&str>` implementation
+impl<'a> TryFrom<String> for Packet<'a> {
+    type Error = PacketError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::try_from(value.as_str())
+    }
+}
+
 /// Connect packet sent by the client
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectPacket {
@@ -808,6 +1128,27 @@ mod test {
         assert_eq!(packet, comparison_packet(Some(254), "/admin™"));
     }
 
+    #[test]
+    fn encoded_event_with_ack_id() {
+        let packets = [
+            Packet::event("/", "event", json!({ "data": "value™" })),
+            Packet::event("/admin™", "event", json!([1, 2])),
+            Packet::bin_event("/admin™", "event", json!({ "data": 1 }), vec![vec![1]]),
+        ];
+        for packet in packets {
+            let event = EncodedEvent::new(packet.clone());
+            for ack in [1, 254] {
+                let mut packet = packet.clone();
+                packet.inner.set_ack_id(ack);
+                let bin = match &packet.inner {
+                    PacketData::BinaryEvent(_, bin, _) => bin.bin.clone(),
+                    _ => vec![],
+                };
+                assert_eq!(event.with_ack_id(ack), (String::from(packet), bin));
+            }
+        }
+    }
+
     // BinaryAck(BinaryPacket, i64),
     #[test]
     fn packet_encode_binary_ack() {
@@ -910,4 +1251,255 @@ mod test {
         let packet = Packet::bin_ack("/", json!("data"), vec![vec![1]], 54);
         assert_eq!(packet.get_size_hint(), 5);
     }
+
+    #[test]
+    fn packet_declared_attachments() {
+        assert_eq!(declared_attachments("51-[\"event\"]"), Some((1, 2)));
+        assert_eq!(declared_attachments("612-/admin,1[]"), Some((12, 3)));
+        assert_eq!(
+            declared_attachments("54294967295-[]"),
+            Some((4294967295, 11))
+        );
+        assert_eq!(
+            declared_attachments("599999999999999999999999999-[]"),
+            Some((usize::MAX, 27))
+        );
+        assert_eq!(declared_attachments("2[\"event\"]"), None);
+        assert_eq!(declared_attachments("5-[\"event\"]"), None);
+        assert_eq!(declared_attachments("51[\"event\"]"), None);
+        assert_eq!(declared_attachments("5"), None);
+        assert_eq!(declared_attachments("51"), None);
+        assert_eq!(declared_attachments(""), None);
+    }
+
+    #[test]
+    fn packet_decode_adversarial_headers() {
+        let packets = [
+            "",
+            "5",
+            "6",
+            "5-",
+            "51",
+            "5111111",
+            "5abc-[]",
+            "54294967295",
+            "54294967295-",
+            "54294967295-/",
+            "6-1[]",
+            "0/",
+            "1/admin",
+            "2/admin,",
+            "29999999999999999999999[]",
+            "3[]",
+            "3a[]",
+            "7[]",
+            "é",
+            "5é-[]",
+            "2/é,[\"é\"]",
+        ];
+        for packet in packets {
+            // Invalid packets should return an error without panicking or looping forever
+            let _ = Packet::try_from(packet.to_string());
+        }
+    }
+
+    #[test]
+    fn packet_malformed_info() {
+        let info = malformed_packet_info;
+        assert_eq!(info(r#"2["event",{]"#), ("/", Some("event".into())));
+        assert_eq!(
+            info(r#"51-/admin,12[ "event", "#),
+            ("/admin", Some("event".into()))
+        );
+        assert_eq!(info(r#"2/admin,[1,"event"]"#), ("/admin", None));
+        assert_eq!(info("2[]"), ("/", None));
+        assert_eq!(info(r#"3/admin,1["event"]"#), ("/admin", None));
+        assert_eq!(info(r#"9["event"]"#), ("/", None));
+    }
+
+    #[test]
+    fn packet_decode_fuzz() {
+        const ALPHABET: &[&str] = &[
+            "0",
+            "1",
+            "2",
+            "3",
+            "5",
+            "6",
+            "9",
+            "-",
+            "/",
+            ",",
+            "[",
+            "]",
+            "{",
+            "}",
+            "\"",
+            ":",
+            "é",
+            "_placeholder",
+            "true",
+            "num",
+        ];
+        // Simple xorshift generator so that the test is deterministic
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..10_000 {
+            let len = next() % 24;
+            let packet: String = (0..len)
+                .map(|_| ALPHABET[(next() % ALPHABET.len() as u64) as usize])
+                .collect();
+            let _ = declared_attachments(&packet);
+            let _ = Packet::try_from(packet);
+        }
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        const STRINGS: &[&str] = &[
+            "",
+            "event",
+            "é™",
+            "with \"quotes\"",
+            "😀 中文",
+            "[1,2]",
+            "/,",
+        ];
+        const NAMESPACES: &[&str] = &["/", "/admin™", "/a/b"];
+        // Simple xorshift generator so that the test is deterministic
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2_000 {
+            let ns = NAMESPACES[(next() % NAMESPACES.len() as u64) as usize];
+            let event = STRINGS[(next() % STRINGS.len() as u64) as usize];
+            let ack = (next() % 10_000) as i64;
+            // Empty data is not kept as is: an event without arguments is encoded with an empty array argument
+            let args: Vec<Value> = (0..1 + next() % 4)
+                .map(|_| match next() % 4 {
+                    0 => json!(STRINGS[(next() % STRINGS.len() as u64) as usize]),
+                    1 => json!(next() as i64 % 1000),
+                    2 => json!({ "key": STRINGS[(next() % STRINGS.len() as u64) as usize], "n": null }),
+                    _ => json!([true, 1.5]),
+                })
+                .collect();
+            let data = Value::Array(args);
+
+            let mut event_with_ack = Packet::event(ns, event, data.clone());
+            event_with_ack.inner.set_ack_id(ack);
+            let packets = [
+                Packet::connect(ns, Sid::new(), ProtocolVersion::V5),
+                Packet::disconnect(ns),
+                Packet::event(ns, event, data.clone()),
+                event_with_ack,
+                Packet::ack(ns, data.clone(), ack),
+            ];
+            for packet in packets {
+                let decoded = Packet::try_from(String::from(packet.clone())).unwrap();
+                assert_eq!(decoded, packet);
+            }
+
+            // The binary attachments are sent separately and added to the decoded packet
+            let bin: Vec<Vec<u8>> = (0..1 + next() % 3).map(|i| vec![i as u8; 3]).collect();
+            let packet = Packet::bin_event(ns, event, data.clone(), bin.clone());
+            let mut decoded = Packet::try_from(String::from(packet)).unwrap();
+            let PacketData::BinaryEvent(e, ref mut packet, None) = decoded.inner else {
+                panic!("expected a binary event");
+            };
+            assert_eq!(e, event);
+            for payload in bin.clone() {
+                packet.add_payload(payload);
+            }
+            assert!(packet.is_complete());
+            assert_eq!(packet.data, data);
+            assert_eq!(packet.bin, bin);
+        }
+    }
+
+    #[test]
+    fn packet_encode_raw_event() {
+        let values = [
+            json!([]),
+            json!([1, "two", { "three": 3 }]),
+            json!([[1, 2]]),
+            json!({ "data": "value", "nested": { "a": [1, null] } }),
+            json!("str™"),
+            json!(null),
+            json!(1.5),
+        ];
+        for value in values {
+            let raw = RawJson::new(serde_json::to_string(&value).unwrap()).unwrap();
+            for ns in ["/", "/admin™"] {
+                let parsed: String = Packet::event(ns, "event", value.clone()).into();
+                let packet: String = Packet::raw_event(ns, "event", raw.clone()).into();
+                assert_eq!(packet, parsed);
+
+                let mut parsed = Packet::event(ns, "event", value.clone());
+                parsed.inner.set_ack_id(254);
+                let mut packet = Packet::raw_event(ns, "event", raw.clone());
+                packet.inner.set_ack_id(254);
+                assert_eq!(String::from(packet), String::from(parsed));
+            }
+        }
+    }
+
+    #[test]
+    fn packet_encode_raw_ack() {
+        let values = [
+            json!([]),
+            json!([1, "two"]),
+            json!({ "data": "value" }),
+            json!("str"),
+            json!(null),
+        ];
+        for value in values {
+            let raw = RawJson::new(serde_json::to_string(&value).unwrap()).unwrap();
+            for ns in ["/", "/admin™"] {
+                let parsed: String = Packet::ack(ns, value.clone(), 54).into();
+                let packet: String = Packet::raw_ack(ns, raw.clone(), 54).into();
+                assert_eq!(packet, parsed);
+            }
+        }
+    }
+
+    #[test]
+    fn raw_json_whitespaces() {
+        let raw = RawJson::new(" [ 1 , 2 ] ").unwrap();
+        let packet: String = Packet::raw_event("/", "event", raw.clone()).into();
+        assert_eq!(packet, r#"2["event", 1 , 2 ]"#);
+        let packet: String = Packet::raw_ack("/", raw, 1).into();
+        assert_eq!(packet, "31[ 1 , 2 ]");
+
+        let raw = RawJson::new("[ ]").unwrap();
+        let packet: String = Packet::raw_event("/", "event", raw).into();
+        assert_eq!(packet, r#"2["event",[]]"#);
+    }
+
+    #[test]
+    fn raw_json_validation() {
+        assert!(RawJson::new(r#"{"a":1}"#).is_ok());
+        assert!(RawJson::new(r#"{"a":1"#).is_err());
+        assert!(RawJson::new("[1] [2]").is_err());
+        assert!(RawJson::new("").is_err());
+        assert_eq!(RawJson::new_unchecked("[1]").get(), "[1]");
+
+        let value: Box<RawValue> = serde_json::from_str(r#"{"a":[1,2]}"#).unwrap();
+        assert_eq!(RawJson::from(value).get(), r#"{"a":[1,2]}"#);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn raw_json_unchecked_validation() {
+        RawJson::new_unchecked("{");
+    }
 }
@@ -0,0 +1,137 @@
+//! Presence of the connected clients, created with [`SocketIo::presence`](crate::SocketIo::presence).
+//!
+//! The last time a client was seen is updated for every packet received from it, heartbeats included.
+//! It allows to detect the clients whose transport is dead before the heartbeat closes their connection,
+//! which can take up to `ping_interval + ping_timeout`.
+//!
+//! The ids are the engine.io session ids, which are also the ids of the sockets
+//! connected to the namespaces (see [`Socket::id`](crate::socket::Socket::id)).
+//!
+//! #### Example
+//! ```
+//! # use std::time::Duration;
+//! # use socketioxide::{SocketIo, extract::SocketRef};
+//! # #[tokio::main]
+//! # async fn main() {
+//! let (_, io) = SocketIo::new_svc();
+//! let presence = io.presence();
+//! io.ns("/", move |socket: SocketRef| {
+//!     assert!(presence.is_active(socket.id, Duration::from_secs(10)));
+//! });
+//!
+//! // Called once for each socket silent for more than 10 seconds
+//! io.presence().on_stale(Duration::from_secs(10), |sid| {
+//!     println!("{sid} looks offline");
+//! });
+//! # }
+//! ```
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use engineioxide::sid::Sid;
+use tokio::time::Instant;
+
+use crate::{
+    adapter::{Adapter, LocalAdapter},
+    client::Client,
+};
+
+/// A helper to query the activity of the connected clients.
+/// See the [module level documentation](self) for more details.
+pub struct Presence<A: Adapter = LocalAdapter> {
+    client: Arc<Client<A>>,
+}
+
+impl<A: Adapter> Presence<A> {
+    pub(crate) fn new(client: Arc<Client<A>>) -> Self {
+        Self { client }
+    }
+
+    /// Returns the last time each connected client was seen
+    pub fn last_seen(&self) -> HashMap<Sid, Instant> {
+        self.client
+            .engine()
+            .map(|engine| {
+                engine
+                    .sockets()
+                    .into_iter()
+                    .map(|socket| (socket.id, socket.last_seen()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns true if the client with this id is connected and was seen in the last `within` duration
+    pub fn is_active(&self, sid: Sid, within: Duration) -> bool {
+        self.client
+            .engine()
+            .and_then(|engine| engine.get_socket(sid))
+            .is_some_and(|socket| socket.last_seen().elapsed() <= within)
+    }
+
+    /// Registers a `callback` called with the id of each client that was not seen for more than `threshold`.
+    ///
+    /// It is called once when a client becomes stale, and again if the client is seen then becomes stale again.
+    /// The clients are checked every quarter of the `threshold`, on a background task stopped with the server.
+    /// The `threshold` should be lower than `ping_interval + ping_timeout`
+    /// to be notified before the heartbeat closes the connection.
+    pub fn on_stale<F>(&self, threshold: Duration, callback: F)
+    where
+        F: Fn(Sid) + Send + Sync + 'static,
+    {
+        let client = Arc::downgrade(&self.client);
+        let period = (threshold / 4).max(Duration::from_millis(1));
+        self.client
+            .config
+            .engine_config
+            .spawn(watch_stale(client, threshold, period, callback));
+    }
+}
+
+async fn watch_stale<A: Adapter>(
+    client: Weak<Client<A>>,
+    threshold: Duration,
+    period: Duration,
+    callback: impl Fn(Sid),
+) {
+    let mut interval = tokio::time::interval(period);
+    let mut stale = HashSet::new();
+    loop {
+        interval.tick().await;
+        let Some(client) = client.upgrade() else {
+            break;
+        };
+        let Some(engine) = client.engine() else {
+            continue;
+        };
+        let now = Instant::now();
+        // The sockets that are not stale anymore or disconnected are forgotten
+        let mut still_stale = HashSet::with_capacity(stale.len());
+        for socket in engine.sockets() {
+            if now.saturating_duration_since(socket.last_seen()) > threshold {
+                if !stale.contains(&socket.id) {
+                    callback(socket.id);
+                }
+                still_stale.insert(socket.id);
+            }
+        }
+        stale = still_stale;
+    }
+}
+
+impl<A: Adapter> Clone for Presence<A> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+        }
+    }
+}
+
+impl<A: Adapter> std::fmt::Debug for Presence<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Presence").finish()
+    }
+}
@@ -43,7 +43,11 @@
 //! }
 //! ```
 
-use engineioxide::service::{EngineIoService, MakeEngineIoService};
+use engineioxide::service::{
+    tungstenite::{Error as WsError, Message},
+    EngineIoService, MakeEngineIoService, WsHandshake,
+};
+use futures::{Sink, Stream};
 use http::{Request, Response};
 use http_body::Body;
 use hyper::service::Service as HyperSvc;
@@ -116,6 +120,20 @@ impl<A: Adapter, S: Clone> SocketIoService<S, A> {
         self.engine_svc.into_make_service()
     }
 
+    /// Drives the socket.io protocol over a websocket connection that was already upgraded,
+    /// see [`EngineIoService::on_ws_connection`].
+    #[inline(always)]
+    pub async fn on_ws_connection<W>(&self, ws: W, handshake: WsHandshake)
+    where
+        W: Stream<Item = Result<Message, WsError>>
+            + Sink<Message, Error = WsError>
+            + Unpin
+            + Send
+            + 'static,
+    {
+        self.engine_svc.on_ws_connection(ws, handshake).await
+    }
+
     /// Creates a new [`EngineIoService`] with a custom inner service and a custom config.
     pub(crate) fn with_config_inner(
         inner: S,
@@ -0,0 +1,77 @@
+//! Serializable snapshots of the state of the server, created with [`SocketIo::snapshot`](crate::SocketIo::snapshot).
+//!
+//! They are meant to be exposed on an internal debugging endpoint, for example as JSON:
+//! ```
+//! # use socketioxide::SocketIo;
+//! let (_, io) = SocketIo::new_svc();
+//! let snapshot = io.snapshot().unwrap();
+//! let json = serde_json::to_string(&snapshot).unwrap();
+//! assert_eq!(json, r#"{"namespaces":[]}"#);
+//! ```
+use std::collections::BTreeMap;
+
+use engineioxide::sid::Sid;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::adapter::Room;
+
+/// A snapshot of all the namespaces of the server
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerSnapshot {
+    /// The namespaces, sorted by name
+    pub namespaces: Vec<NamespaceSnapshot>,
+}
+
+/// A snapshot of a namespace and of its local sockets
+#[derive(Debug, Clone, Serialize)]
+pub struct NamespaceSnapshot {
+    /// The path of the namespace
+    pub name: String,
+    /// The sockets connected to the namespace, sorted by id
+    pub sockets: Vec<SocketSnapshot>,
+    /// The rooms of the namespace, with the ids of the sockets in each room
+    pub rooms: BTreeMap<Room, Vec<Sid>>,
+}
+
+/// A snapshot of a socket connected to a namespace
+#[derive(Debug, Clone, Serialize)]
+pub struct SocketSnapshot {
+    /// The socket.io id of the socket
+    pub id: Sid,
+    /// The engine.io session id of the underlying connection
+    pub sid: Sid,
+    /// The current transport of the connection: `polling` or `websocket`
+    pub transport: &'static str,
+    /// The address of the client, taken from a [`SocketAddr`](std::net::SocketAddr)
+    /// in the request extensions, or else from the `x-forwarded-for` header
+    pub remote_addr: Option<String>,
+    /// The rooms joined by the socket
+    pub rooms: Vec<Room>,
+    /// The time at which the socket connected to the namespace, in milliseconds since the unix epoch
+    pub connected_at: u64,
+    /// The number of acknowledgements awaited from the client
+    pub pending_acks: usize,
+    /// The auth payload sent by the client when connecting.
+    /// It is only captured if [`snapshot_auth`](crate::SocketIoBuilder::snapshot_auth) is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Value>,
+}
+
+impl NamespaceSnapshot {
+    /// Creates a namespace snapshot from the snapshots of its sockets
+    pub(crate) fn new(name: String, mut sockets: Vec<SocketSnapshot>) -> Self {
+        sockets.sort_by_key(|s| s.id);
+        let mut rooms: BTreeMap<Room, Vec<Sid>> = BTreeMap::new();
+        for socket in &sockets {
+            for room in &socket.rooms {
+                rooms.entry(room.clone()).or_default().push(socket.id);
+            }
+        }
+        Self {
+            name,
+            sockets,
+            rooms,
+        }
+    }
+}
@@ -9,28 +9,36 @@ use std::{
         atomic::{AtomicBool, AtomicI64, Ordering},
         Arc, RwLock,
     },
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use engineioxide::socket::{DisconnectReason as EIoDisconnectReason, Permit};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
-use tokio::sync::oneshot::{self, Receiver};
+use tokio::sync::{
+    mpsc::error::TrySendError,
+    oneshot::{self, Receiver},
+};
 
 #[cfg(feature = "extensions")]
 use crate::extensions::Extensions;
 
 use crate::{
-    ack::{AckInnerStream, AckResponse, AckResult, AckStream},
-    adapter::{Adapter, LocalAdapter, Room},
-    errors::{DisconnectError, Error, SendError},
+    ack::{decode_ack_data, AckInnerStream, AckResponse, AckResult, AckStream},
+    adapter::{Adapter, LocalAdapter, Room, RoomsDiff},
+    errors::{DisconnectError, EmitError, Error, SendError},
+    event::SocketIoEvent,
+    event_stream::ServerEvent,
+    extract::{AckSender, SocketRef},
     handler::{
-        BoxedDisconnectHandler, BoxedMessageHandler, DisconnectHandler, MakeErasedHandler,
-        MessageHandler,
+        BoxedDisconnectHandler, BoxedHandler, BoxedMessageHandler, ConcurrencyLimit,
+        DisconnectHandler, ErrorPolicy, HandlerError, MakeErasedHandler, MessageHandler,
+        QueuePolicy,
     },
     ns::Namespace,
     operators::{BroadcastOperators, ConfOperators, RoomParam},
-    packet::{BinaryPacket, Packet, PacketData},
+    packet::{BinaryPacket, EncodedEvent, Packet, PacketData, RawJson},
+    snapshot::SocketSnapshot,
     AckError, SocketIoConfig,
 };
 use crate::{
@@ -60,14 +68,28 @@ pub enum DisconnectReason {
     /// The client did not send a PONG packet in the `ping timeout` delay
     HeartbeatTimeout,
 
+    /// The client did not send any message for the `idle_timeout` duration
+    IdleTimeout,
+
+    /// The socket was connected for the `max_connection_lifetime` duration
+    LifetimeExpired,
+
     /// The client has manually disconnected the socket using [`socket.disconnect()`](https://socket.io/fr/docs/v4/client-api/#socketdisconnect)
     ClientNSDisconnect,
 
     /// The socket was forcefully disconnected from the namespace with [`Socket::disconnect`]
     ServerNSDisconnect,
 
+    /// The client was still on the polling transport after the grace of
+    /// [`SocketIo::force_upgrade_or_close`](crate::SocketIo::force_upgrade_or_close)
+    UpgradeRequired,
+
     /// The server is being closed
     ClosingServer,
+
+    /// The socket was asked to reconnect to another server with [`Socket::migrate`]
+    /// or [`BroadcastOperators::migrate`], and was disconnected after the migration delay
+    ServerShuttingDown,
 }
 
 impl std::fmt::Display for DisconnectReason {
@@ -79,9 +101,13 @@ impl std::fmt::Display for DisconnectReason {
             PacketParsingError => "client sent a bad request / the packet could not be parsed",
             TransportError => "The connection was abruptly closed",
             HeartbeatTimeout => "client did not send a PONG packet in time",
+            IdleTimeout => "client did not send any message in time",
+            LifetimeExpired => "socket exceeded its maximum connection lifetime",
             ClientNSDisconnect => "client has manually disconnected the socket from the namespace",
             ServerNSDisconnect => "socket was forcefully disconnected from the namespace",
+            UpgradeRequired => "client did not upgrade from polling in time",
             ClosingServer => "server is being closed",
+            ServerShuttingDown => "socket was migrated to another server",
         };
         f.write_str(str)
     }
@@ -94,15 +120,62 @@ impl From<EIoDisconnectReason> for DisconnectReason {
             EIoDisconnectReason::TransportClose => TransportClose,
             EIoDisconnectReason::TransportError => TransportError,
             EIoDisconnectReason::HeartbeatTimeout => HeartbeatTimeout,
+            EIoDisconnectReason::IdleTimeout => IdleTimeout,
+            EIoDisconnectReason::LifetimeExpired => LifetimeExpired,
             EIoDisconnectReason::MultipleHttpPollingError => MultipleHttpPollingError,
             EIoDisconnectReason::PacketParsingError => PacketParsingError,
+            EIoDisconnectReason::UpgradeRequired => UpgradeRequired,
             EIoDisconnectReason::ClosingServer => ClosingServer,
         }
     }
 }
 
+/// The reason of a [`CloseWarning`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum CloseWarningReason {
+    /// The server is being closed with [`SocketIo::close`](crate::SocketIo::close)
+    ServerShutdown,
+    /// The client did not send any message for the `idle_timeout` duration
+    IdleTimeout,
+    /// The socket is disconnected from the namespace with [`Socket::disconnect`]
+    Disconnect,
+}
+
+/// A warning emitted to the client before the server closes its connection,
+/// if it is enabled with [`SocketIoBuilder::close_warning_grace`](crate::SocketIoBuilder::close_warning_grace).
+///
+/// It is emitted with the [`close_warning_event`](crate::SocketIoConfig::close_warning_event)
+/// as `{ "reason": "server_shutdown", "retry_after_ms": 30000 }`, `retry_after_ms` being omitted if it is not set.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct CloseWarning {
+    /// Why the connection is closed
+    pub reason: CloseWarningReason,
+    /// How long the client should wait before reconnecting, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl CloseWarning {
+    /// Creates a [`CloseWarning`] without any retry delay
+    pub fn new(reason: CloseWarningReason) -> Self {
+        Self {
+            reason,
+            retry_after_ms: None,
+        }
+    }
+
+    /// Sets how long the client should wait before reconnecting
+    pub fn retry_after(mut self, delay: Duration) -> Self {
+        self.retry_after_ms = Some(delay.as_millis() as u64);
+        self
+    }
+}
+
 pub(crate) trait PermitExt<'a> {
     fn send(self, packet: Packet<'_>);
+    fn send_encoded(self, msg: String, bin_payloads: Vec<Vec<u8>>);
 }
 impl<'a> PermitExt<'a> for Permit<'a> {
     fn send(self, mut packet: Packet<'_>) {
@@ -121,6 +194,103 @@ impl<'a> PermitExt<'a> for Permit<'a> {
             self.emit(msg);
         }
     }
+
+    fn send_encoded(self, msg: String, bin_payloads: Vec<Vec<u8>>) {
+        if bin_payloads.is_empty() {
+            self.emit(msg);
+        } else {
+            self.emit_many(msg, bin_payloads);
+        }
+    }
+}
+
+/// A catch-all handler registered with [`Socket::on_any`].
+type AnyHandler<A> = Box<dyn Fn(&str, &[Value], &Socket<A>) + Send + Sync>;
+
+/// A fallback handler registered with [`Socket::on_raw`].
+type RawHandler<A> = Box<dyn Fn(SocketRef<A>, RawEvent, AckSender<A>) + Send + Sync>;
+
+/// An incoming event that is not parsed by any extractor, received with [`Socket::on_raw`].
+///
+/// It can be re-emitted as is to another socket, room or namespace
+/// with the [`bin`](BroadcastOperators::bin) and [`emit`](BroadcastOperators::emit) operators.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawEvent {
+    /// The namespace of the event
+    pub ns: String,
+    /// The name of the event
+    pub event: String,
+    /// The arguments array of the event. Binary placeholders are not included.
+    pub data: Value,
+    /// The binary attachments of the event
+    pub bin: Vec<Vec<u8>>,
+    /// The ack id of the event, if the client expects an ack
+    pub ack_id: Option<i64>,
+}
+
+impl RawEvent {
+    /// The number of binary attachments of the event
+    pub fn attachments(&self) -> usize {
+        self.bin.len()
+    }
+}
+
+/// Configures the concurrency of a message handler registered with [`Socket::on`].
+///
+/// By default, the invocations of a handler are not limited.
+/// The limit only applies to async handlers, sync handlers are always called inline.
+/// It is applied per socket and per event.
+///
+/// ## Example
+/// ```
+/// # use socketioxide::{SocketIo, extract::*, handler::QueuePolicy};
+/// let (_, io) = SocketIo::new_svc();
+/// io.ns("/", |socket: SocketRef| {
+///     // At most 2 searches run at the same time, the following ones are rejected
+///     socket
+///         .on("search", |ack: AckSender| async move {
+///             ack.send("result").ok();
+///         })
+///         .max_concurrent(2)
+///         .queue(QueuePolicy::Reject);
+/// });
+/// ```
+pub struct MessageHandlerConfig<'a, A: Adapter = LocalAdapter> {
+    socket: &'a Socket<A>,
+    event: Cow<'static, str>,
+    max_concurrent: Option<usize>,
+    policy: QueuePolicy,
+}
+
+impl<'a, A: Adapter> MessageHandlerConfig<'a, A> {
+    /// Sets the maximum number of invocations of the handler running at the same time.
+    ///
+    /// The invocations exceeding this limit are handled according to the [`QueuePolicy`],
+    /// which defaults to [`QueuePolicy::Reject`].
+    pub fn max_concurrent(mut self, max: usize) -> Self {
+        self.max_concurrent = Some(max);
+        self.update();
+        self
+    }
+
+    /// Sets the [`QueuePolicy`] applied to the invocations exceeding the
+    /// [`max_concurrent`](Self::max_concurrent) limit.
+    pub fn queue(mut self, policy: QueuePolicy) -> Self {
+        self.policy = policy;
+        self.update();
+        self
+    }
+
+    fn update(&self) {
+        if let Some(max) = self.max_concurrent {
+            let limit = Arc::new(ConcurrencyLimit::new(max, self.policy));
+            self.socket
+                .handler_limits
+                .write()
+                .unwrap()
+                .insert(self.event.clone(), limit);
+        }
+    }
 }
 
 /// A Socket represents a client connected to a namespace.
@@ -130,10 +300,15 @@ pub struct Socket<A: Adapter = LocalAdapter> {
     pub(crate) config: Arc<SocketIoConfig>,
     pub(crate) ns: Arc<Namespace<A>>,
     message_handlers: RwLock<HashMap<Cow<'static, str>, BoxedMessageHandler<A>>>,
+    handler_limits: RwLock<HashMap<Cow<'static, str>, Arc<ConcurrencyLimit>>>,
+    any_handlers: RwLock<Vec<AnyHandler<A>>>,
+    raw_handler: RwLock<Option<RawHandler<A>>>,
     disconnect_handler: Mutex<Option<BoxedDisconnectHandler<A>>>,
     ack_message: Mutex<HashMap<i64, oneshot::Sender<AckResult<Value>>>>,
     ack_counter: AtomicI64,
     connected: AtomicBool,
+    connected_at: SystemTime,
+    auth: Option<String>,
     /// The socket id
     pub id: Sid,
 
@@ -158,10 +333,15 @@ impl<A: Adapter> Socket<A> {
         Self {
             ns,
             message_handlers: RwLock::new(HashMap::new()),
+            handler_limits: RwLock::new(HashMap::new()),
+            any_handlers: RwLock::new(Vec::new()),
+            raw_handler: RwLock::new(None),
             disconnect_handler: Mutex::new(None),
             ack_message: Mutex::new(HashMap::new()),
             ack_counter: AtomicI64::new(0),
             connected: AtomicBool::new(false),
+            connected_at: SystemTime::now(),
+            auth: None,
             id: sid,
             #[cfg(feature = "extensions")]
             extensions: Extensions::new(),
@@ -222,15 +402,159 @@ impl<A: Adapter> Socket<A> {
     ///     });
     /// });
     /// ```
-    pub fn on<H, T>(&self, event: impl Into<Cow<'static, str>>, handler: H)
+    ///
+    /// The returned [`MessageHandlerConfig`] can be used to limit the concurrent invocations of the handler.
+    pub fn on<H, T>(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        handler: H,
+    ) -> MessageHandlerConfig<'_, A>
+    where
+        H: MessageHandler<A, T>,
+        T: Send + Sync + 'static,
+    {
+        self.insert_message_handler(event.into(), MakeErasedHandler::new_message_boxed(handler))
+    }
+
+    /// ### Registers a [`MessageHandler`] for the given typed event.
+    ///
+    /// It is the same as [`Socket::on`] with the [`name`](SocketIoEvent::name) of the event,
+    /// so a typed and a string registration for the same name replace each other.
+    /// See the [`event`](crate::event) module for more details.
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, SocketIoEvent, extract::*};
+    /// # use serde_json::Value;
+    /// struct Ping;
+    /// impl SocketIoEvent for Ping {
+    ///     fn name(&self) -> &'static str {
+    ///         "ping"
+    ///     }
+    ///     fn from_name(name: &str) -> Option<Self> {
+    ///         (name == "ping").then_some(Ping)
+    ///     }
+    /// }
+    ///
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on_typed(Ping, |socket: SocketRef, Data::<Value>(data)| {
+    ///         socket.emit_typed(Ping, data).ok();
+    ///     });
+    /// });
+    /// ```
+    pub fn on_typed<E, H, T>(&self, event: E, handler: H) -> MessageHandlerConfig<'_, A>
     where
+        E: SocketIoEvent,
         H: MessageHandler<A, T>,
         T: Send + Sync + 'static,
     {
+        self.on(event.name(), handler)
+    }
+
+    /// ### Registers a [`BoxedHandler`] for the given event.
+    ///
+    /// It behaves like [`on()`], but the handler is a trait object instead of a generic [`MessageHandler`],
+    /// so that handlers can be registered dynamically, e.g. in a loop over a list of event names.
+    /// See [`BoxedHandler`] for an example.
+    ///
+    /// [`on()`]: #method.on
+    pub fn on_boxed(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        handler: BoxedHandler<A>,
+    ) -> MessageHandlerConfig<'_, A> {
+        self.insert_message_handler(event.into(), Box::new(handler))
+    }
+
+    fn insert_message_handler(
+        &self,
+        event: Cow<'static, str>,
+        handler: BoxedMessageHandler<A>,
+    ) -> MessageHandlerConfig<'_, A> {
+        self.handler_limits.write().unwrap().remove(&event);
         self.message_handlers
             .write()
             .unwrap()
-            .insert(event.into(), MakeErasedHandler::new_message_boxed(handler));
+            .insert(event.clone(), handler);
+        MessageHandlerConfig {
+            socket: self,
+            event,
+            max_concurrent: None,
+            policy: QueuePolicy::default(),
+        }
+    }
+
+    /// ### Registers a catch-all handler called for every event received from the client.
+    ///
+    /// The handler is called synchronously with the event name and the arguments of the event,
+    /// **before** the handler registered with [`on()`] for this event. It is also called for events
+    /// without any registered handler.
+    ///
+    /// It only observes the events: it can't prevent the specific handler from being called.
+    /// Multiple catch-all handlers can be registered, they are called in their registration order.
+    ///
+    /// For binary events, the binary payloads are not part of the arguments.
+    /// Use [`on_raw()`](#method.on_raw) to get them.
+    ///
+    /// [`on()`]: #method.on
+    ///
+    /// #### Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on_any(|event, args, socket| {
+    ///         println!("[{}] received {event} with {} args", socket.id, args.len());
+    ///     });
+    /// });
+    /// ```
+    pub fn on_any(&self, handler: impl Fn(&str, &[Value], &Socket<A>) + Send + Sync + 'static) {
+        self.any_handlers.write().unwrap().push(Box::new(handler));
+    }
+
+    /// ### Registers a fallback handler called for the events without any handler registered with [`on()`].
+    ///
+    /// The handler receives the [`RawEvent`] untouched, with its binary attachments,
+    /// and an [`AckSender`] to answer the client if it expects an ack.
+    /// Only one fallback handler can be registered, registering another one replaces it.
+    ///
+    /// [`on()`]: #method.on
+    ///
+    /// #### Example
+    /// A relay forwarding all the events from the `/a` namespace to the `/b` namespace, with their acks:
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use serde_json::Value;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/b", || {});
+    /// let io2 = io.clone();
+    /// io.ns("/a", move |socket: SocketRef| {
+    ///     let io = io2.clone();
+    ///     socket.on_raw(move |_, event, ack| {
+    ///         let b = io.of("/b").unwrap().bin(event.bin);
+    ///         if event.ack_id.is_none() {
+    ///             b.emit(event.event, event.data).ok();
+    ///             return;
+    ///         }
+    ///         let Ok(stream) = b.emit_with_ack::<Value>(event.event, event.data) else {
+    ///             return;
+    ///         };
+    ///         tokio::spawn(async move {
+    ///             use futures::StreamExt;
+    ///             // Forward the first ack received
+    ///             if let Some((_, Ok(res))) = stream.boxed().next().await {
+    ///                 ack.bin(res.binary).send(res.data).ok();
+    ///             }
+    ///         });
+    ///     });
+    /// });
+    /// ```
+    pub fn on_raw(
+        &self,
+        handler: impl Fn(SocketRef<A>, RawEvent, AckSender<A>) + Send + Sync + 'static,
+    ) {
+        self.raw_handler.write().unwrap().replace(Box::new(handler));
     }
 
     /// ## Registers a disconnect handler.
@@ -303,6 +627,7 @@ impl<A: Adapter> Socket<A> {
     ///     });
     /// });
     /// ```
+    #[must_use = "the message is lost if the emit fails, use `emit_or_log` to log the failures"]
     pub fn emit<T: Serialize>(
         &self,
         event: impl Into<Cow<'static, str>>,
@@ -311,6 +636,201 @@ impl<A: Adapter> Socket<A> {
         if !self.connected() {
             return Err(SendError::Socket(SocketError::Closed(data)));
         }
+        let event = event.into();
+        if !self.should_deliver(&event) {
+            return Ok(());
+        }
+
+        let permit = match self.reserve() {
+            Ok(permit) => permit,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("sending error during emit message: {e:?}");
+                return Err(e.with_value(data).into());
+            }
+        };
+
+        let ns = self.ns();
+        let data = serde_json::to_value(data)?;
+        permit.send(Packet::event(ns, event, data));
+        Ok(())
+    }
+
+    /// Emits a message to the client with a typed event, see [`Socket::on_typed`] for an example.
+    ///
+    /// It is the same as [`Socket::emit`] with the [`name`](SocketIoEvent::name) of the event.
+    #[must_use = "the message is lost if the emit fails, use `emit_or_log` to log the failures"]
+    pub fn emit_typed<E: SocketIoEvent, T: Serialize>(
+        &self,
+        event: E,
+        data: T,
+    ) -> Result<(), SendError<T>> {
+        self.emit(event.name(), data)
+    }
+
+    /// Emits a message to the client like [`Socket::emit`], but a failure is logged as a warning
+    /// with the sid of the socket and the event, instead of being returned.
+    /// The warnings are only logged if the `tracing` feature is enabled.
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.emit_or_log("welcome", "hello");
+    /// });
+    /// ```
+    pub fn emit_or_log<T: Serialize>(&self, event: impl Into<Cow<'static, str>>, data: T) {
+        let event = event.into();
+        #[cfg(feature = "tracing")]
+        let res = self.emit(event.clone(), data);
+        #[cfg(not(feature = "tracing"))]
+        let res = self.emit(event, data);
+        if let Err(_e) = res {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(sid = %self.id, event = %event, "emit failed: {_e}");
+        }
+    }
+
+    /// Emits a message to the client, unless the client is stale: it has not been seen alive for more than `max_staleness`.
+    ///
+    /// The client is seen alive when it answers a ping, or when the transport is active
+    /// (see [`SocketIoBuilder::ws_ping_interval`]). A stale client is probably gone, but it is only disconnected
+    /// once the heartbeat times out. Meanwhile the emitted messages would pointlessly fill its buffer.
+    ///
+    /// ## Errors
+    /// * If the client is stale, an [`EmitError::Stale`] is returned and nothing is sent.
+    /// * Otherwise the same errors as [`Socket::emit`] are returned, without the data.
+    ///
+    /// [`SocketIoBuilder::ws_ping_interval`]: crate::SocketIoBuilder#method.ws_ping_interval
+    /// [`EmitError::Stale`]: crate::EmitError::Stale
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use std::time::Duration;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on("subscribe", |socket: SocketRef| async move {
+    ///         loop {
+    ///             tokio::time::sleep(Duration::from_millis(100)).await;
+    ///             // Stop streaming the quotes once the client is stale or disconnected
+    ///             if socket.emit_if_alive("quote", 42, Duration::from_secs(30)).is_err() {
+    ///                 break;
+    ///             }
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    #[must_use = "the message is lost if the emit fails"]
+    pub fn emit_if_alive<T: Serialize>(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        data: T,
+        max_staleness: Duration,
+    ) -> Result<(), EmitError> {
+        if self.is_stale(max_staleness) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={}] skipping emit to stale socket", self.id);
+            return Err(EmitError::Stale);
+        }
+        Ok(self.emit(event, data)?)
+    }
+
+    /// Returns true if the client has not been seen alive for more than `max_staleness`,
+    /// that is, it did not answer any ping and the transport was not active.
+    ///
+    /// See [`Socket::emit_if_alive`] and [`BroadcastOperators::skip_stale`].
+    pub fn is_stale(&self, max_staleness: Duration) -> bool {
+        self.esocket.last_alive_at().elapsed() > max_staleness
+    }
+
+    /// Emits a message to the client with pre-serialized JSON data, without parsing it again.
+    ///
+    /// Like with [`Socket::emit`], if the JSON is an array, its elements are sent as multiple arguments.
+    /// The encoded packet is the same as the one sent by [`Socket::emit`] with the parsed value.
+    ///
+    /// ## Errors
+    /// * If the socket is disconnected a [`SendError::Socket(SocketError::Closed)`] is returned.
+    /// * If the packet buffer is full, a [`SendError::Socket(SocketError::InternalChannelFull)`] is returned.
+    ///
+    /// [`SendError::Socket(SocketError::Closed)`]: crate::SocketError::Closed
+    /// [`SendError::Socket(SocketError::InternalChannelFull)`]: crate::SocketError::InternalChannelFull
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*, packet::RawJson};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     // e.g. a document cached as a JSON string
+    ///     let doc = RawJson::new(r#"{"title":"hello","items":[1,2,3]}"#).unwrap();
+    ///     socket.emit_raw_json("doc", doc).ok();
+    /// });
+    /// ```
+    #[must_use = "the message is lost if the emit fails"]
+    pub fn emit_raw_json(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        raw: impl Into<RawJson>,
+    ) -> Result<(), SendError<RawJson>> {
+        let raw = raw.into();
+        if !self.connected() {
+            return Err(SendError::Socket(SocketError::Closed(raw)));
+        }
+        let event = event.into();
+        if !self.should_deliver(&event) {
+            return Ok(());
+        }
+
+        let permit = match self.reserve() {
+            Ok(permit) => permit,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("sending error during emit message: {e:?}");
+                return Err(e.with_value(raw).into());
+            }
+        };
+
+        permit.send(Packet::raw_event(self.ns(), event, raw));
+        Ok(())
+    }
+
+    /// Emits a message with binary attachments to the client, in one logical message.
+    ///
+    /// The data is sent as a binary event packet whose arguments end with a placeholder for each attachment,
+    /// followed by the attachments as binary frames. The client reassembles them into the original arguments,
+    /// e.g. `emit_with_binary("file", json!({ "name": "a.png" }), vec![buffer])` is received as `("file", { name }, buffer)`.
+    ///
+    /// It is equivalent to [`socket.bin(attachments).emit(event, data)`](Self::bin).
+    /// If there is no attachment, a normal event packet is sent.
+    ///
+    /// ## Errors
+    /// The same errors as [`Socket::emit`] are returned.
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use serde_json::json;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     let file = vec![1, 2, 3, 4];
+    ///     socket.emit_with_binary("file", json!({ "name": "a.bin" }), vec![file]).ok();
+    /// });
+    /// ```
+    #[must_use = "the message is lost if the emit fails"]
+    pub fn emit_with_binary<T: Serialize>(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        data: T,
+        attachments: Vec<Vec<u8>>,
+    ) -> Result<(), SendError<T>> {
+        if !self.connected() {
+            return Err(SendError::Socket(SocketError::Closed(data)));
+        }
+        let event = event.into();
+        if !self.should_deliver(&event) {
+            return Ok(());
+        }
 
         let permit = match self.reserve() {
             Ok(permit) => permit,
@@ -323,14 +843,21 @@ impl<A: Adapter> Socket<A> {
 
         let ns = self.ns();
         let data = serde_json::to_value(data)?;
-        permit.send(Packet::event(ns, event.into(), data));
+        let packet = if attachments.is_empty() {
+            Packet::event(ns, event, data)
+        } else {
+            Packet::bin_event(ns, event, data, attachments)
+        };
+        permit.send(packet);
         Ok(())
     }
 
     /// Emits a message to the client and wait for acknowledgement.
     ///
     /// The acknowledgement has a timeout specified in the config (5s by default)
-    /// (see [`SocketIoBuilder::ack_timeout`]) or with the [`timeout()`] operator.
+    /// (see [`SocketIoBuilder::ack_timeout`]), in the namespace (see [`SocketIo::set_ack_timeout`])
+    /// or with the [`timeout()`] operator. If the namespace has an [`AckRetry`] policy
+    /// (see [`SocketIo::set_ack_retry`]), the message is emitted again when the acknowledgement times out.
     ///
     /// To get acknowledgements, an [`AckStream`] is returned.
     /// It can be used in two ways:
@@ -353,6 +880,9 @@ impl<A: Adapter> Socket<A> {
     ///
     /// [`timeout()`]: crate::operators::ConfOperators#method.timeout
     /// [`SocketIoBuilder::ack_timeout`]: crate::SocketIoBuilder#method.ack_timeout
+    /// [`SocketIo::set_ack_timeout`]: crate::SocketIo::set_ack_timeout
+    /// [`SocketIo::set_ack_retry`]: crate::SocketIo::set_ack_retry
+    /// [`AckRetry`]: crate::ack::AckRetry
     /// [`Stream`]: futures::stream::Stream
     /// [`Future`]: futures::future::Future
     /// [`AckError`]: crate::AckError
@@ -362,27 +892,149 @@ impl<A: Adapter> Socket<A> {
     /// [`AckError::Socket(SocketError::Closed)`]: crate::SocketError::Closed
     /// [`io::get_socket()`]: crate::SocketIo#method.get_socket
     ///
-    /// # Basic example
+    /// # Basic example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use serde_json::Value;
+    /// # use std::sync::Arc;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on("test", |socket: SocketRef, Data::<Value>(data)| async move {
+    ///         // Emit a test message and wait for an acknowledgement with the timeout specified in the config
+    ///         match socket.emit_with_ack::<_, Value>("test", data).unwrap().await {
+    ///             Ok(ack) => println!("Ack received {:?}", ack),
+    ///             Err(err) => println!("Ack error {:?}", err),
+    ///         }
+    ///    });
+    /// });
+    /// ```
+    pub fn emit_with_ack<T: Serialize, V: DeserializeOwned>(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        data: T,
+    ) -> Result<AckStream<V>, SendError<T>> {
+        if !self.connected() {
+            return Err(SendError::Socket(SocketError::Closed(data)));
+        }
+        let permit = match self.reserve() {
+            Ok(permit) => permit,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("sending error during emit message: {e:?}");
+                return Err(e.with_value(data).into());
+            }
+        };
+        let data = serde_json::to_value(data)?;
+        let packet = Packet::event(self.ns.path.clone(), event.into(), data);
+        let stream = self.send_with_ack_stream(packet, permit, self.ack_timeout());
+        Ok(AckStream::<V>::from(stream))
+    }
+
+    /// Emits a message to the client and waits for its acknowledgement, deserialized as `V`.
+    ///
+    /// It is a shorthand for a single [`emit_with_ack`] awaited with a custom timeout,
+    /// where only the data of the acknowledgement is returned.
+    /// The binary payloads of the acknowledgement, if any, are ignored.
+    ///
+    /// # Errors
+    /// * If the data cannot be serialized or if the acknowledgement cannot be deserialized as `V`,
+    ///   an [`AckError::Serde`] is returned.
+    /// * If the client didn't respond before the `timeout`, an [`AckError::Timeout`] is returned.
+    /// * If the socket is full or if it has been closed before receiving the acknowledgement,
+    ///   an [`AckError::Socket`] is returned.
+    ///
+    /// In every case, the acknowledgement is not expected anymore and a late response is ignored.
+    ///
+    /// [`emit_with_ack`]: Socket::emit_with_ack
+    /// [`AckError::Serde`]: crate::AckError::Serde
+    /// [`AckError::Timeout`]: crate::AckError::Timeout
+    /// [`AckError::Socket`]: crate::AckError::Socket
+    ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use serde::Deserialize;
+    /// # use std::time::Duration;
+    /// #[derive(Debug, Deserialize)]
+    /// struct Confirmation {
+    ///     accepted: bool,
+    /// }
+    ///
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| async move {
+    ///     let res = socket
+    ///         .emit_with_ack_typed::<_, Confirmation>("invite", "room", Duration::from_secs(10))
+    ///         .await;
+    ///     match res {
+    ///         Ok(confirmation) => println!("invitation accepted: {}", confirmation.accepted),
+    ///         Err(err) => println!("ack error {:?}", err),
+    ///     }
+    /// });
+    /// ```
+    pub async fn emit_with_ack_typed<T: Serialize, V: DeserializeOwned>(
+        &self,
+        event: impl Into<Cow<'static, str>>,
+        data: T,
+        timeout: Duration,
+    ) -> Result<V, AckError<()>> {
+        if !self.connected() {
+            return Err(AckError::Socket(SocketError::Closed(())));
+        }
+        let permit = match self.reserve() {
+            Ok(permit) => permit,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("sending error during emit message: {e:?}");
+                return Err(e.into());
+            }
+        };
+        let data = serde_json::to_value(data)?;
+        let packet = Packet::event(self.ns(), event.into(), data);
+        let (ack, rx) = self.send_with_ack_permit(packet, permit);
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(res)) => decode_ack_data(res?.data),
+            Ok(Err(_)) => Err(AckError::Socket(SocketError::Closed(()))),
+            Err(_) => {
+                self.forget_ack(ack);
+                Err(AckError::Timeout)
+            }
+        }
+    }
+
+    /// Sends an ack response to the client for the event with the given `ack_id`.
+    ///
+    /// It is useful when the ack id was transported out-of-band, e.g. with work queued to another service.
+    /// Otherwise, prefer the [`AckSender`] extractor which can also be moved to another task.
+    /// The id is not checked, the client ignores acks that it doesn't expect.
+    ///
+    /// # Errors
+    /// If the socket is disconnected or if its buffer is full, a [`SendError::Socket`] error is returned
+    /// with the value to send.
+    ///
+    /// # Example
     /// ```
     /// # use socketioxide::{SocketIo, extract::*};
-    /// # use serde_json::Value;
-    /// # use std::sync::Arc;
     /// let (_, io) = SocketIo::new_svc();
     /// io.ns("/", |socket: SocketRef| {
-    ///     socket.on("test", |socket: SocketRef, Data::<Value>(data)| async move {
-    ///         // Emit a test message and wait for an acknowledgement with the timeout specified in the config
-    ///         match socket.emit_with_ack::<_, Value>("test", data).unwrap().await {
-    ///             Ok(ack) => println!("Ack received {:?}", ack),
-    ///             Err(err) => println!("Ack error {:?}", err),
+    ///     socket.on("work", |socket: SocketRef, ack: AckSender| async move {
+    ///         if let Some(ack_id) = ack.ack_id() {
+    ///             // The id can be stored and the ack sent later
+    ///             socket.ack_raw(ack_id, "done").ok();
     ///         }
-    ///    });
+    ///     });
     /// });
     /// ```
-    pub fn emit_with_ack<T: Serialize, V: DeserializeOwned>(
+    pub fn ack_raw<T: Serialize>(&self, ack_id: i64, data: T) -> Result<(), SendError<T>> {
+        self.send_ack(ack_id, data, Vec::new())
+    }
+
+    /// Sends an ack response with optional binary attachments
+    pub(crate) fn send_ack<T: Serialize>(
         &self,
-        event: impl Into<Cow<'static, str>>,
+        ack_id: i64,
         data: T,
-    ) -> Result<AckStream<V>, SendError<T>> {
+        bin: Vec<Vec<u8>>,
+    ) -> Result<(), SendError<T>> {
         if !self.connected() {
             return Err(SendError::Socket(SocketError::Closed(data)));
         }
@@ -390,15 +1042,48 @@ impl<A: Adapter> Socket<A> {
             Ok(permit) => permit,
             Err(e) => {
                 #[cfg(feature = "tracing")]
-                tracing::debug!("sending error during emit message: {e:?}");
+                tracing::debug!("sending error during ack message: {e:?}");
                 return Err(e.with_value(data).into());
             }
         };
+        let ns = self.ns();
         let data = serde_json::to_value(data)?;
-        let packet = Packet::event(self.ns(), event.into(), data);
-        let rx = self.send_with_ack_permit(packet, permit);
-        let stream = AckInnerStream::send(rx, self.config.ack_timeout, self.id);
-        Ok(AckStream::<V>::from(stream))
+        let packet = if bin.is_empty() {
+            Packet::ack(ns, data, ack_id)
+        } else {
+            Packet::bin_ack(ns, data, bin, ack_id)
+        };
+        permit.send(packet);
+        Ok(())
+    }
+
+    /// Sends an ack response with pre-serialized data and optional binary attachments
+    pub(crate) fn send_raw_ack(
+        &self,
+        ack_id: i64,
+        raw: RawJson,
+        bin: Vec<Vec<u8>>,
+    ) -> Result<(), SendError<RawJson>> {
+        if !self.connected() {
+            return Err(SendError::Socket(SocketError::Closed(raw)));
+        }
+        let permit = match self.reserve() {
+            Ok(permit) => permit,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("sending error during ack message: {e:?}");
+                return Err(e.with_value(raw).into());
+            }
+        };
+        let ns = self.ns();
+        let packet = if bin.is_empty() {
+            Packet::raw_ack(ns, raw, ack_id)
+        } else {
+            // The placeholders of the attachments must be added to the data
+            Packet::bin_ack(ns, raw.to_value(), bin, ack_id)
+        };
+        permit.send(packet);
+        Ok(())
     }
 
     // Room actions
@@ -414,6 +1099,86 @@ impl<A: Adapter> Socket<A> {
         self.ns.adapter.add_all(self.id, rooms)
     }
 
+    /// Joins the given rooms for the given time to live.
+    ///
+    /// Once the TTL is elapsed, the socket automatically leaves the rooms, and a
+    /// [`ServerEvent::RoomLeave`](crate::ServerEvent::RoomLeave) event is sent.
+    /// Joining a room again refreshes its TTL, with [`join_with_ttl`](Self::join_with_ttl),
+    /// or removes it, with [`join`](Self::join).
+    /// The adapters that don't support expiring memberships ignore the TTL,
+    /// see [`Adapter::add_all_with_ttl`].
+    ///
+    /// ## Errors
+    /// When using a distributed adapter, it can return an [`Adapter::Error`] which is mostly related to network errors.
+    /// For the default [`LocalAdapter`] it is always an [`Infallible`](std::convert::Infallible) error
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::SocketRef};
+    /// # use std::time::Duration;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on("typing", |socket: SocketRef| {
+    ///         socket.join_with_ttl("typing", Duration::from_secs(5)).ok();
+    ///     });
+    /// });
+    /// ```
+    pub fn join_with_ttl(&self, rooms: impl RoomParam, ttl: Duration) -> Result<(), A::Error> {
+        self.ns.adapter.add_all_with_ttl(self.id, rooms, Some(ttl))
+    }
+
+    /// Joins all the rooms of the iterator, with a single call to the adapter.
+    ///
+    /// ## Errors
+    /// When using a distributed adapter, it can return an [`Adapter::Error`] which is mostly related to network errors.
+    /// For the default [`LocalAdapter`] it is always an [`Infallible`](std::convert::Infallible) error
+    pub fn join_many<R: Into<Room>>(
+        &self,
+        rooms: impl IntoIterator<Item = R>,
+    ) -> Result<(), A::Error> {
+        let rooms: Vec<Room> = rooms.into_iter().map(Into::into).collect();
+        self.ns.adapter.add_all(self.id, rooms)
+    }
+
+    /// Leaves all the rooms of the iterator, with a single call to the adapter.
+    ///
+    /// ## Errors
+    /// When using a distributed adapter, it can return an [`Adapter::Error`] which is mostly related to network errors.
+    /// For the default [`LocalAdapter`] it is always an [`Infallible`](std::convert::Infallible) error
+    pub fn leave_many<R: Into<Room>>(
+        &self,
+        rooms: impl IntoIterator<Item = R>,
+    ) -> Result<(), A::Error> {
+        let rooms: Vec<Room> = rooms.into_iter().map(Into::into).collect();
+        self.ns.adapter.del(self.id, rooms)
+    }
+
+    /// Replaces the rooms of the socket with the given ones, e.g. to reconcile them with
+    /// the permissions of the user. It joins the missing rooms and leaves the other ones,
+    /// and returns the [`RoomsDiff`] that was applied. It is idempotent.
+    ///
+    /// With the default [`LocalAdapter`], the rooms are updated atomically: a broadcast
+    /// sees either the old or the new rooms of the socket, never a part of the changes.
+    ///
+    /// ## Errors
+    /// When using a distributed adapter, it can return an [`Adapter::Error`] which is mostly related to network errors.
+    /// For the default [`LocalAdapter`] it is always an [`Infallible`](std::convert::Infallible) error
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.join(["a", "z"]).ok();
+    ///     let diff = socket.set_rooms(["a", "b", "c"]).unwrap();
+    ///     assert_eq!(diff.left, ["z"]);
+    ///     assert_eq!(diff.joined.len(), 2);
+    /// });
+    /// ```
+    pub fn set_rooms(&self, rooms: impl RoomParam) -> Result<RoomsDiff, A::Error> {
+        self.ns.adapter.set_rooms(self.id, rooms)
+    }
+
     /// Leaves the given rooms.
     ///
     /// If the room does not exist, it will do nothing
@@ -424,6 +1189,34 @@ impl<A: Adapter> Socket<A> {
         self.ns.adapter.del(self.id, rooms)
     }
 
+    /// Moves the socket from the `from` room to the `to` room, e.g. to change channel.
+    ///
+    /// Unlike [`leave()`](Self::leave) followed by [`join()`](Self::join), the default [`LocalAdapter`]
+    /// updates both rooms atomically: a broadcast never sees the socket in neither or both rooms.
+    ///
+    /// Returns false if the socket was not in the `from` room, it still joins the `to` room.
+    ///
+    /// ## Errors
+    /// When using a distributed adapter, it can return an [`Adapter::Error`] which is mostly related to network errors.
+    /// For the default [`LocalAdapter`] it is always an [`Infallible`](std::convert::Infallible) error
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.join("general").ok();
+    ///     socket.on("switch", |socket: SocketRef, Data::<(String, String)>((from, to))| {
+    ///         if !socket.move_room(&from, &to).unwrap() {
+    ///             println!("socket was not in {from}");
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    pub fn move_room(&self, from: &str, to: &str) -> Result<bool, A::Error> {
+        self.ns.adapter.move_room(self.id, from, to)
+    }
+
     /// Leaves all rooms where the socket is connected.
     /// ## Errors
     /// When using a distributed adapter, it can return an [`Adapter::Error`] which is mostly related to network errors.
@@ -474,6 +1267,26 @@ impl<A: Adapter> Socket<A> {
         BroadcastOperators::from_sock(self.ns.clone(), self.id).to(rooms)
     }
 
+    /// Selects the other sessions of the same user, which are all the clients in the given per-user room
+    /// except the current socket. It is equivalent to [`to()`](Self::to) and can be chained with
+    /// the other operators in the same way.
+    ///
+    /// It is useful when a user has one socket per device, all joining a room identifying the user.
+    /// # Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// # use serde_json::Value;
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef, Data::<String>(user_id)| {
+    ///     let room = format!("user:{user_id}");
+    ///     socket.join(room.clone()).ok();
+    ///     // Notify the other devices of the user, but not the one logging in
+    ///     socket.to_other_sessions(room).emit("logged_in_elsewhere", ()).ok();
+    /// });
+    pub fn to_other_sessions(&self, user_room: impl RoomParam) -> BroadcastOperators<A> {
+        self.to(user_room)
+    }
+
     /// Selects all clients in the given rooms.
     ///
     /// It does include the current socket contrary to the `to()` operator.
@@ -616,7 +1429,41 @@ impl<A: Adapter> Socket<A> {
     /// Disconnects the socket from the current namespace,
     ///
     /// It will also call the disconnect handler if it is set.
+    ///
+    /// If the close warnings are enabled with [`SocketIoBuilder::close_warning_grace`](crate::SocketIoBuilder::close_warning_grace),
+    /// a [`CloseWarning`] is emitted first and the socket is disconnected once the grace period elapses.
     pub fn disconnect(self: Arc<Self>) -> Result<(), DisconnectError> {
+        self.disconnect_with_warning(Some(CloseWarning::new(CloseWarningReason::Disconnect)))
+    }
+
+    /// Same as [`Socket::disconnect`] with a custom [`CloseWarning`].
+    /// If it is `None`, the client is not warned and the socket is disconnected right away.
+    ///
+    /// The warning is only emitted if the close warnings are enabled with
+    /// [`SocketIoBuilder::close_warning_grace`](crate::SocketIoBuilder::close_warning_grace).
+    pub fn disconnect_with_warning(
+        self: Arc<Self>,
+        warning: Option<CloseWarning>,
+    ) -> Result<(), DisconnectError> {
+        let grace = warning.and_then(|warning| self.send_close_warning(&warning));
+        let Some(grace) = grace.filter(|grace| !grace.is_zero()) else {
+            return self.disconnect_now();
+        };
+        let socket = self.clone();
+        self.config.engine_config.spawn(async move {
+            tokio::time::sleep(grace).await;
+            if !socket.connected() {
+                return;
+            }
+            if let Err(_e) = socket.disconnect_now() {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("error while disconnecting warned socket: {_e}");
+            }
+        });
+        Ok(())
+    }
+
+    fn disconnect_now(self: Arc<Self>) -> Result<(), DisconnectError> {
         let res = self.send(Packet::disconnect(&self.ns.path));
         if let Err(SocketError::InternalChannelFull(_)) = res {
             return Err(DisconnectError::InternalChannelFull);
@@ -626,6 +1473,80 @@ impl<A: Adapter> Socket<A> {
         Ok(())
     }
 
+    /// Asks the client to reconnect to another server, e.g. before a rolling restart.
+    ///
+    /// The [`migration_event`](crate::SocketIoBuilder::migration_event) is emitted with the target `url`
+    /// as `{ "url": url }`, then the socket is disconnected from the namespace with the
+    /// [`DisconnectReason::ServerShuttingDown`] reason after the [`migration_delay`](crate::SocketIoBuilder::migration_delay)
+    /// plus a random part of the [`migration_jitter`](crate::SocketIoBuilder::migration_jitter),
+    /// giving the client time to reconnect elsewhere.
+    ///
+    /// To migrate many sockets with their disconnections spread over the jitter, use [`BroadcastOperators::migrate`].
+    ///
+    /// ## Errors
+    /// If the migration event can't be sent, a [`SocketError`] is returned and the socket is not disconnected.
+    ///
+    /// ## Example
+    /// ```
+    /// # use socketioxide::{SocketIo, extract::*};
+    /// let (_, io) = SocketIo::new_svc();
+    /// io.ns("/", |socket: SocketRef| {
+    ///     socket.on("drain", |socket: SocketRef| {
+    ///         socket.migrate("wss://node-2.example.com").ok();
+    ///     });
+    /// });
+    /// ```
+    pub fn migrate(self: Arc<Self>, url: &str) -> Result<(), SocketError<()>> {
+        self.migrate_at(url, rand::random())
+    }
+
+    /// Emits the [`CloseWarning`] if the close warnings are enabled,
+    /// returning the grace period to wait before closing the socket
+    pub(crate) fn send_close_warning(&self, warning: &CloseWarning) -> Option<Duration> {
+        let grace = self.config.close_warning_grace?;
+        let data = serde_json::to_value(warning).unwrap();
+        let event = self.config.close_warning_event.as_ref();
+        if let Err(_e) = self.send(Packet::event(self.ns(), event, data)) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("[sid={}] error while sending close warning: {_e}", self.id);
+            return None;
+        }
+        Some(grace)
+    }
+
+    /// Emits the migration event then disconnects the socket after the migration delay
+    /// and the given fraction of the migration jitter
+    pub(crate) fn migrate_at(
+        self: Arc<Self>,
+        url: &str,
+        jitter_ratio: f64,
+    ) -> Result<(), SocketError<()>> {
+        let delay =
+            self.config.migration_delay + self.config.migration_jitter.mul_f64(jitter_ratio);
+        let data = serde_json::json!({ "url": url });
+        self.send(Packet::event(
+            self.ns(),
+            self.config.migration_event.as_ref(),
+            data,
+        ))?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={}] migrating to {url} in {delay:?}", self.id);
+
+        let socket = self.clone();
+        self.config.engine_config.spawn(async move {
+            tokio::time::sleep(delay).await;
+            if !socket.connected() {
+                return;
+            }
+            socket.send(Packet::disconnect(&socket.ns.path)).ok();
+            if let Err(_e) = socket.close(DisconnectReason::ServerShuttingDown) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("error while disconnecting migrated socket: {_e}");
+            }
+        });
+        Ok(())
+    }
+
     /// Closes the engine.io connection if it is not already closed.
     /// Return a future that resolves when the underlying transport is closed.
     pub(crate) async fn close_underlying_transport(&self) {
@@ -641,14 +1562,91 @@ impl<A: Adapter> Socket<A> {
         self.connected.store(connected, Ordering::SeqCst);
     }
 
+    /// Keeps the auth payload of the socket for the [snapshots](crate::SocketIo::snapshot),
+    /// if it is enabled with [`snapshot_auth`](crate::SocketIoBuilder::snapshot_auth)
+    pub(crate) fn with_auth(mut self, auth: &Option<String>) -> Self {
+        if self.config.snapshot_auth {
+            self.auth = auth.clone();
+        }
+        self
+    }
+
+    /// Creates a [`SocketSnapshot`] of the current state of the socket
+    pub(crate) fn snapshot(&self) -> Result<SocketSnapshot, A::Error> {
+        let req_parts = self.req_parts();
+        let remote_addr = req_parts
+            .extensions
+            .get::<std::net::SocketAddr>()
+            .map(|addr| addr.to_string())
+            .or_else(|| {
+                let forwarded = req_parts.headers.get("x-forwarded-for")?.to_str().ok()?;
+                Some(forwarded.split(',').next()?.trim().to_string())
+            });
+        let connected_at = self
+            .connected_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Ok(SocketSnapshot {
+            id: self.id,
+            sid: self.esocket.id,
+            transport: self.transport_type().into(),
+            remote_addr,
+            rooms: self.rooms()?,
+            connected_at,
+            pending_acks: self.ack_message.lock().unwrap().len(),
+            auth: self
+                .auth
+                .as_deref()
+                .and_then(|auth| serde_json::from_str(auth).ok()),
+        })
+    }
+
     /// Gets the current namespace path.
     #[inline]
     pub fn ns(&self) -> &str {
         &self.ns.path
     }
 
+    /// Gets the paths of all the namespaces the underlying connection is connected to, in connection order.
+    ///
+    /// A single connection can be connected to multiple namespaces at the same time.
+    /// There is one [`Socket`] per namespace, each with its own rooms, handlers and extensions,
+    /// and emits and room operations are always scoped to the namespace of the socket.
+    pub fn namespaces(&self) -> Vec<String> {
+        self.esocket
+            .data
+            .namespaces
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|ns| ns.to_string())
+            .collect()
+    }
+
     pub(crate) fn reserve(&self) -> Result<Permit<'_>, SocketError<()>> {
-        Ok(self.esocket.reserve()?)
+        let res = self.esocket.reserve();
+        if let Err(TrySendError::Closed(())) = res {
+            if self.config.strict_emit && self.connected() {
+                self.close_dead();
+            }
+        }
+        Ok(res?)
+    }
+
+    /// Disconnects the socket right away because its connection is closed, see [`SocketIoConfig::strict_emit`]
+    fn close_dead(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            "[sid={}] emit to a closed connection, disconnecting",
+            self.id
+        );
+        if let Ok(socket) = self.ns.get_socket(self.id) {
+            if let Err(_e) = socket.close(DisconnectReason::TransportError) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("error while disconnecting dead socket: {_e}");
+            }
+        }
     }
 
     pub(crate) fn send(&self, packet: Packet<'_>) -> Result<(), SocketError<()>> {
@@ -657,21 +1655,27 @@ impl<A: Adapter> Socket<A> {
         Ok(())
     }
 
+    /// Sends the packet with a new ack id, returned with the receiver of the acknowledgement
     pub(crate) fn send_with_ack_permit(
         &self,
         mut packet: Packet<'_>,
         permit: Permit<'_>,
-    ) -> Receiver<AckResult<Value>> {
+    ) -> (i64, Receiver<AckResult<Value>>) {
         let (tx, rx) = oneshot::channel();
 
         let ack = self.ack_counter.fetch_add(1, Ordering::SeqCst) + 1;
         packet.inner.set_ack_id(ack);
         permit.send(packet);
         self.ack_message.lock().unwrap().insert(ack, tx);
-        rx
+        (ack, rx)
     }
 
-    pub(crate) fn send_with_ack(&self, mut packet: Packet<'_>) -> Receiver<AckResult<Value>> {
+    /// Sends the packet with a new ack id, returned with the receiver of the acknowledgement.
+    /// If the packet can't be sent, the receiver resolves with the error.
+    pub(crate) fn send_with_ack(
+        &self,
+        mut packet: Packet<'_>,
+    ) -> (i64, Receiver<AckResult<Value>>) {
         let (tx, rx) = oneshot::channel();
 
         let ack = self.ack_counter.fetch_add(1, Ordering::SeqCst) + 1;
@@ -684,7 +1688,69 @@ impl<A: Adapter> Socket<A> {
                 tx.send(Err(AckError::Socket(e))).ok();
             }
         }
-        rx
+        (ack, rx)
+    }
+
+    /// Sends the encoded event with a new ack id, returned with the receiver of the acknowledgement.
+    /// If the packet can't be sent, the receiver resolves with the error.
+    pub(crate) fn send_encoded_with_ack(
+        &self,
+        event: &EncodedEvent,
+        permit: Option<Permit<'_>>,
+    ) -> (i64, Receiver<AckResult<Value>>) {
+        let (tx, rx) = oneshot::channel();
+
+        let ack = self.ack_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        match permit.map_or_else(|| self.reserve(), Ok) {
+            Ok(permit) => {
+                let (msg, bin_payloads) = event.with_ack_id(ack);
+                permit.send_encoded(msg, bin_payloads);
+                self.ack_message.lock().unwrap().insert(ack, tx);
+            }
+            Err(e) => {
+                tx.send(Err(AckError::Socket(e))).ok();
+            }
+        }
+        (ack, rx)
+    }
+
+    /// Sends the packet with a new ack id through the permit and returns the stream of its acknowledgement.
+    /// The packet is emitted again on timeouts according to the [`AckRetry`](crate::ack::AckRetry) policy of the namespace.
+    pub(crate) fn send_with_ack_stream(
+        &self,
+        packet: Packet<'static>,
+        permit: Permit<'_>,
+        timeout: Duration,
+    ) -> AckInnerStream {
+        match self.ns.ack_retry() {
+            Some(retry) => {
+                // The packet is encoded once for all the attempts
+                let event = Arc::new(EncodedEvent::new(packet));
+                let ack = self.send_encoded_with_ack(&event, Some(permit));
+                AckInnerStream::send_with_retry(
+                    self.ns.clone(),
+                    self.id,
+                    event,
+                    ack,
+                    timeout,
+                    retry,
+                )
+            }
+            None => {
+                let (_, rx) = self.send_with_ack_permit(packet, permit);
+                AckInnerStream::send(rx, timeout, self.id)
+            }
+        }
+    }
+
+    /// Stops waiting for the acknowledgement with the given id, a late response is ignored
+    pub(crate) fn forget_ack(&self, ack: i64) {
+        self.ack_message.lock().unwrap().remove(&ack);
+    }
+
+    /// Returns the default ack timeout: the one of the namespace if it is set, otherwise the one of the config
+    pub(crate) fn ack_timeout(&self) -> Duration {
+        self.ns.ack_timeout().unwrap_or(self.config.ack_timeout)
     }
 
     /// Called when the socket is gracefully disconnected from the server or the client
@@ -692,12 +1758,28 @@ impl<A: Adapter> Socket<A> {
     /// It maybe also close when the underlying transport is closed or failed.
     pub(crate) fn close(self: Arc<Self>, reason: DisconnectReason) -> Result<(), AdapterError> {
         self.set_connected(false);
+        // Pending acknowledgements will never be received,
+        // dropping their senders resolves them with a closed socket error
+        self.ack_message.lock().unwrap().clear();
 
         if let Some(handler) = self.disconnect_handler.lock().unwrap().take() {
             handler.call(self.clone(), reason);
         }
 
+        self.esocket
+            .data
+            .namespaces
+            .lock()
+            .unwrap()
+            .retain(|ns| ns != &self.ns.path);
         self.ns.remove_socket(self.id)?;
+        self.ns.events.send(|| {
+            Some(ServerEvent::Disconnect {
+                sid: self.id,
+                ns: self.ns.path.clone(),
+                reason,
+            })
+        });
         Ok(())
     }
 
@@ -736,6 +1818,31 @@ impl<A: Adapter> Socket<A> {
         self.esocket.transport_type()
     }
 
+    /// Returns false if a direct emit of the event is suppressed by the
+    /// [`DeliveryFilter`](crate::DeliveryFilter) of the namespace
+    pub(crate) fn should_deliver(&self, event: &str) -> bool {
+        self.ns
+            .delivery_filter()
+            .map_or(true, |filter| filter.should_deliver_direct(self, event))
+    }
+
+    /// Returns the correlation id of the underlying connection.
+    ///
+    /// It is captured at handshake from the [`correlation_id_header`](crate::SocketIoBuilder::correlation_id_header)
+    /// request header if there is one, otherwise it is random. It is shared by all the namespaces of the connection
+    /// and included in its tracing events.
+    pub fn correlation_id(&self) -> &str {
+        self.esocket.correlation_id()
+    }
+
+    /// Returns the number of packets discarded because the buffer of the underlying connection was full,
+    /// according to the configured [`OverflowPolicy`](crate::OverflowPolicy).
+    ///
+    /// The buffer is shared by all the namespaces of the connection.
+    pub fn dropped_packets(&self) -> u64 {
+        self.esocket.dropped_packets()
+    }
+
     /// Gets the socket.io [`ProtocolVersion`](crate::ProtocolVersion) used by the client to connect with this [`Socket`]
     ///
     /// It can also be accessed as an extractor:
@@ -752,9 +1859,11 @@ impl<A: Adapter> Socket<A> {
     }
 
     fn recv_event(self: Arc<Self>, e: &str, data: Value, ack: Option<i64>) -> Result<(), Error> {
-        if let Some(handler) = self.message_handlers.read().unwrap().get(e) {
-            handler.call(self.clone(), data, vec![], ack);
+        if !self.validate_event(e, &data, ack) {
+            return Ok(());
         }
+        self.call_any_handlers(e, &data);
+        self.dispatch_event(e, data, vec![], ack);
         Ok(())
     }
 
@@ -764,12 +1873,128 @@ impl<A: Adapter> Socket<A> {
         packet: BinaryPacket,
         ack: Option<i64>,
     ) -> Result<(), Error> {
-        if let Some(handler) = self.message_handlers.read().unwrap().get(e) {
-            handler.call(self.clone(), packet.data, packet.bin, ack);
+        if !self.validate_event(e, &packet.data, ack) {
+            return Ok(());
         }
+        self.call_any_handlers(e, &packet.data);
+        self.dispatch_event(e, packet.data, packet.bin, ack);
         Ok(())
     }
 
+    /// Dispatches an event to the handler registered on the socket with [`on()`](Self::on),
+    /// then to the handler registered on the namespace with [`SocketIo::ns_on`](crate::SocketIo::ns_on),
+    /// and finally to the fallback handler registered with [`on_raw()`](Self::on_raw).
+    fn dispatch_event(self: &Arc<Self>, e: &str, data: Value, bin: Vec<Vec<u8>>, ack: Option<i64>) {
+        if let Some(handler) = self.message_handlers.read().unwrap().get(e) {
+            self.call_message_handler(handler, e, data, bin, ack);
+        } else if let Some(handler) = self.ns.message_handler(e) {
+            self.call_message_handler(&handler, e, data, bin, ack);
+        } else {
+            self.call_raw_handler(e, data, bin, ack);
+        }
+    }
+
+    /// Validates a received event with the [`EventValidator`](crate::validation::EventValidator) of the namespace.
+    /// A rejected event is answered with the validation error if the client expects an ack.
+    ///
+    /// Returns false if the event is rejected and should not be dispatched.
+    fn validate_event(&self, e: &str, data: &Value, ack: Option<i64>) -> bool {
+        let Some(validator) = self.ns.event_validator() else {
+            return true;
+        };
+        let Err(err) = validator.validate(e, data) else {
+            return true;
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={}] event {e} rejected: {err}", self.id);
+        if let Some(ack_id) = ack {
+            if let Err(_e) = self.send_ack(ack_id, err, vec![]) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("error sending validation error ack: {_e:?}");
+            }
+        }
+        false
+    }
+
+    /// Calls the message handler, through its concurrency limit if there is one
+    fn call_message_handler(
+        self: &Arc<Self>,
+        handler: &BoxedMessageHandler<A>,
+        e: &str,
+        data: Value,
+        bin: Vec<Vec<u8>>,
+        ack_id: Option<i64>,
+    ) {
+        let limit = self.handler_limits.read().unwrap().get(e).cloned();
+        match limit {
+            Some(limit) => limit.call(handler.as_ref(), self.clone(), e, data, bin, ack_id),
+            None => handler.call(self.clone(), e, data, bin, ack_id),
+        }
+    }
+
+    /// Reports the failure of the message handler of the event `e` to the client,
+    /// according to the [`ErrorPolicy`] of the namespace
+    pub(crate) fn report_handler_error(&self, e: &str, ack_id: Option<i64>, err: HandlerError) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("[sid={}] handler of event {e} failed: {err}", self.id);
+        let res = match self.ns.error_policy() {
+            ErrorPolicy::Silent => return,
+            ErrorPolicy::EmitEvent(event) => self.emit(
+                event,
+                serde_json::json!({ "event": e, "message": err.message, "code": err.code }),
+            ),
+            ErrorPolicy::AckError => match ack_id {
+                Some(ack_id) => self.send_ack(
+                    ack_id,
+                    serde_json::json!({ "error": err.message, "code": err.code }),
+                    vec![],
+                ),
+                None => return,
+            },
+        };
+        if let Err(_e) = res {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("error reporting handler failure: {_e:?}");
+        }
+    }
+
+    fn call_raw_handler(
+        self: &Arc<Self>,
+        e: &str,
+        data: Value,
+        bin: Vec<Vec<u8>>,
+        ack_id: Option<i64>,
+    ) {
+        if let Some(handler) = self.raw_handler.read().unwrap().as_ref() {
+            let event = RawEvent {
+                ns: self.ns().to_string(),
+                event: e.to_string(),
+                data,
+                bin,
+                ack_id,
+            };
+            handler(
+                SocketRef::from(self.clone()),
+                event,
+                AckSender::new(self.clone(), ack_id),
+            );
+        }
+    }
+
+    fn call_any_handlers(&self, e: &str, data: &Value) {
+        let handlers = self.any_handlers.read().unwrap();
+        if handlers.is_empty() {
+            return;
+        }
+        let args = match data {
+            Value::Array(args) => args.as_slice(),
+            data => std::slice::from_ref(data),
+        };
+        for handler in handlers.iter() {
+            handler(e, args, self);
+        }
+    }
+
     fn recv_ack(self: Arc<Self>, data: Value, ack: i64) -> Result<(), Error> {
         if let Some(tx) = self.ack_message.lock().unwrap().remove(&ack) {
             let res = AckResponse {
@@ -846,4 +2071,52 @@ mod test {
             Err(SendError::Socket(SocketError::InternalChannelFull(_)))
         ));
     }
+
+    #[tokio::test]
+    async fn emit_with_ack_typed_cleanup() {
+        let sid = Sid::new();
+        let ns = Namespace::<LocalAdapter>::new_dummy([sid]);
+        let socket: Arc<Socket> = Socket::new_dummy(sid, ns).into();
+
+        let res = socket
+            .emit_with_ack_typed::<_, String>("test", "foo", Duration::from_millis(10))
+            .await;
+        assert!(matches!(res, Err(AckError::Timeout)));
+        assert!(socket.ack_message.lock().unwrap().is_empty());
+
+        let ack = socket.emit_with_ack_typed::<_, String>("test", "foo", Duration::from_secs(5));
+        let recv = async {
+            tokio::task::yield_now().await;
+            let ack_id = *socket.ack_message.lock().unwrap().keys().next().unwrap();
+            socket.clone().recv_ack(Value::from(42), ack_id).unwrap();
+        };
+        let (res, _) = futures::join!(ack, recv);
+        assert!(matches!(res, Err(AckError::Serde(_))));
+        assert!(socket.ack_message.lock().unwrap().is_empty());
+
+        let ack = socket.emit_with_ack_typed::<_, String>("test", "foo", Duration::from_secs(5));
+        let recv = async {
+            tokio::task::yield_now().await;
+            let ack_id = *socket.ack_message.lock().unwrap().keys().next().unwrap();
+            socket.clone().recv_ack("bar".into(), ack_id).unwrap();
+        };
+        let (res, _) = futures::join!(ack, recv);
+        assert_eq!(res.unwrap(), "bar");
+    }
+
+    #[tokio::test]
+    async fn emit_on_closed_socket() {
+        use crate::extract::SocketRef;
+        let sid = Sid::new();
+        let ns = Namespace::<LocalAdapter>::new_dummy([sid]).into();
+        let socket: SocketRef = Arc::new(Socket::new_dummy(sid, ns)).into();
+        let handle = socket.clone();
+        socket.disconnect().unwrap();
+
+        assert!(!handle.connected());
+        assert!(matches!(
+            handle.emit("test", Value::Null),
+            Err(SendError::Socket(SocketError::Closed(_)))
+        ));
+    }
 }
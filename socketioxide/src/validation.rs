@@ -0,0 +1,168 @@
+//! Validation of the events received on a namespace, before they are dispatched to the handlers.
+//!
+//! An [`EventValidator`] is set on a namespace with [`SocketIo::set_event_validator`](crate::SocketIo::set_event_validator).
+//! It is called with the event name and its arguments once the packet is parsed, before any handler
+//! (including the [`on_any`](crate::socket::Socket::on_any) handlers) is called. When an event is rejected:
+//! * If the client requested an acknowledgement, it is answered with the [`ValidationError`].
+//! * Otherwise the event is dropped.
+//!
+//! In both cases the event is counted by [`EventValidator::rejected`].
+//!
+//! With the `jsonschema` feature, [`EventValidator::json_schemas`] validates the arguments of each event against a JSON schema.
+//!
+//! #### Example
+//! ```
+//! # use socketioxide::{SocketIo, extract::*, validation::{EventValidator, ValidationError}};
+//! # use serde_json::Value;
+//! let (_, io) = SocketIo::new_svc();
+//! io.ns("/", |socket: SocketRef| {
+//!     // The message is always a string here
+//!     socket.on("message", |Data::<String>(msg)| async move {});
+//! });
+//!
+//! let validator = EventValidator::new(|event, args| match event {
+//!     "message" if !args[0].is_string() => Err(ValidationError::new("message should be a string")),
+//!     _ => Ok(()),
+//! });
+//! io.set_event_validator("/", Some(validator.clone()));
+//! println!("{} invalid events", validator.rejected());
+//! ```
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+type ValidateFn = dyn Fn(&str, &Value) -> Result<(), ValidationError> + Send + Sync;
+
+/// The reason why an event was rejected by an [`EventValidator`].
+///
+/// It is sent as the acknowledgement of the rejected event, serialized as `{ "error": message, "details": details }`.
+#[derive(Debug, Clone, PartialEq, Serialize, thiserror::Error)]
+#[error("{message}")]
+pub struct ValidationError {
+    /// A description of the error
+    #[serde(rename = "error")]
+    pub message: String,
+    /// Optional details about the error, e.g. the list of the invalid fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl ValidationError {
+    /// Creates a validation error with the given message and without details
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Adds details to the validation error
+    pub fn with_details(mut self, details: impl Into<Value>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+}
+
+/// A hook validating the events received on a namespace, see the [module level documentation](self).
+///
+/// It is called with the event name and the array of its arguments (without the binary attachments),
+/// for each event received, so it should be cheap and must not block.
+///
+/// The clones of a validator share the same [`rejected`](Self::rejected) counter.
+#[derive(Clone)]
+pub struct EventValidator {
+    validate: Arc<ValidateFn>,
+    rejected: Arc<AtomicU64>,
+}
+
+impl EventValidator {
+    /// Creates a validator accepting the events for which `validate` returns `Ok(())`
+    pub fn new<F>(validate: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Result<(), ValidationError> + Send + Sync + 'static,
+    {
+        Self {
+            validate: Arc::new(validate),
+            rejected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Returns the number of events rejected by this validator
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Validates an event, counting it if it is rejected
+    pub(crate) fn validate(&self, event: &str, args: &Value) -> Result<(), ValidationError> {
+        let res = (self.validate)(event, args);
+        if res.is_err() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+}
+
+#[cfg(feature = "jsonschema")]
+impl EventValidator {
+    /// Creates a validator checking the array of the arguments of each event against the JSON schema registered for its name.
+    /// The events without a schema are accepted.
+    ///
+    /// A rejected event is answered with the `"invalid arguments"` error message
+    /// and the list of the schema violations as details.
+    ///
+    /// # Errors
+    /// If a schema is invalid, the name of its event and the error are returned.
+    ///
+    /// # Example
+    /// ```
+    /// # use socketioxide::validation::EventValidator;
+    /// # use serde_json::json;
+    /// let validator = EventValidator::json_schemas([(
+    ///     "message",
+    ///     json!({ "type": "array", "items": [{ "type": "string" }], "minItems": 1 }),
+    /// )])
+    /// .unwrap();
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "jsonschema")))]
+    pub fn json_schemas<I, E>(schemas: I) -> Result<Self, (String, String)>
+    where
+        I: IntoIterator<Item = (E, Value)>,
+        E: Into<String>,
+    {
+        let schemas = schemas
+            .into_iter()
+            .map(|(event, schema)| {
+                let event = event.into();
+                match jsonschema::JSONSchema::compile(&schema) {
+                    Ok(schema) => Ok((event, schema)),
+                    Err(err) => {
+                        let err = err.to_string();
+                        Err((event, err))
+                    }
+                }
+            })
+            .collect::<Result<std::collections::HashMap<_, _>, _>>()?;
+
+        Ok(Self::new(move |event, args| {
+            let Some(schema) = schemas.get(event) else {
+                return Ok(());
+            };
+            schema.validate(args).map_err(|errors| {
+                let details: Vec<Value> = errors.map(|err| err.to_string().into()).collect();
+                ValidationError::new("invalid arguments").with_details(details)
+            })
+        }))
+    }
+}
+
+impl std::fmt::Debug for EventValidator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventValidator")
+            .field("rejected", &self.rejected())
+            .finish()
+    }
+}
@@ -6,8 +6,9 @@ mod utils;
 
 use fixture::{create_server, create_ws_connection};
 use futures::{SinkExt, StreamExt};
-use socketioxide::extract::SocketRef;
+use socketioxide::extract::{AckSender, Data, SocketRef};
 use socketioxide::packet::{Packet, PacketData};
+use socketioxide::{AckError, SendError, SocketError};
 use tokio::sync::mpsc;
 use tokio::time::Duration;
 use tokio_tungstenite::tungstenite::Message;
@@ -130,3 +131,226 @@ pub async fn broadcast_with_ack() {
         }
     }
 }
+
+#[tokio::test]
+pub async fn room_ack_with_disconnect() {
+    const PORT: u16 = 2102;
+    use Message::*;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<SocketRef>(2);
+
+    io.ns("/", move |socket: SocketRef| {
+        socket.join("room").unwrap();
+        tx.try_send(socket).unwrap();
+    });
+
+    let (mut stx1, mut srx1) = create_ws_connection(PORT).await.split();
+    let (mut stx2, mut srx2) = create_ws_connection(PORT).await.split();
+    for srx in [&mut srx1, &mut srx2] {
+        assert_ok!(srx.next().await.unwrap());
+        assert_ok!(srx.next().await.unwrap());
+    }
+    // Keep a reference to the sockets so that they are not dropped when they disconnect
+    let _sockets = [rx.recv().await.unwrap(), rx.recv().await.unwrap()];
+
+    let ack_stream = assert_ok!(io
+        .to("room")
+        .timeout(Duration::from_secs(5))
+        .emit_with_ack::<[String; 1]>("test", "foo"));
+    let acks = tokio::spawn(ack_stream.collect::<Vec<_>>());
+
+    // The first client acknowledges the event
+    let msg = assert_ok!(srx1.next().await.unwrap());
+    assert_eq!(msg, Text("421[\"test\",\"foo\"]".to_string()));
+    assert_ok!(stx1.send(Text("431[\"oof\"]".to_string())).await);
+
+    // The second client disconnects without acknowledging it
+    assert_ok!(srx2.next().await.unwrap());
+    assert_ok!(stx2.send(Text("1".to_string())).await);
+
+    // The gather should complete well before the timeout
+    let acks = tokio::time::timeout(Duration::from_millis(500), acks)
+        .await
+        .expect("ack gathering should not wait for the timeout")
+        .unwrap();
+    assert_eq!(acks.len(), 2);
+    let ok = acks.iter().filter(|(_, ack)| ack.is_ok()).count();
+    let closed = acks
+        .iter()
+        .filter(|(_, ack)| matches!(ack, Err(AckError::Socket(SocketError::Closed(())))))
+        .count();
+    assert_eq!((ok, closed), (1, 1));
+}
+
+#[tokio::test]
+pub async fn ack_client_event() {
+    const PORT: u16 = 2103;
+    use Message::*;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<bool>(4);
+
+    io.ns("/", move |s: SocketRef| {
+        s.on("test", move |Data::<String>(data), ack: AckSender| {
+            assert_ok!(tx.try_send(ack.expects_ack()));
+            assert_ok!(ack.send(data + "-ack"));
+        });
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+
+    // An event without ack id is not acknowledged
+    assert_ok!(stx.send(Text("42[\"test\",\"foo\"]".to_string())).await);
+    assert!(!rx.recv().await.unwrap());
+
+    assert_ok!(stx.send(Text("427[\"test\",\"bar\"]".to_string())).await);
+    assert!(rx.recv().await.unwrap());
+    let msg = assert_ok!(srx.next().await.unwrap());
+    assert_eq!(msg, Text("437[\"bar-ack\"]".to_string()));
+
+    assert_ok!(stx.close().await);
+}
+
+#[tokio::test]
+pub async fn deferred_ack() {
+    const PORT: u16 = 2104;
+    use std::{collections::HashMap, sync::Arc};
+    use Message::*;
+    let io = create_server(PORT).await;
+    let pending = Arc::new(std::sync::Mutex::new(HashMap::<i64, AckSender>::new()));
+    let (tx, mut rx) = mpsc::channel::<bool>(4);
+
+    io.ns("/", move |s: SocketRef| {
+        let pending = pending.clone();
+        // The ack sender is stashed and answered later from another task
+        s.on("deferred", move |ack: AckSender| {
+            let id = ack.ack_id().unwrap();
+            pending.lock().unwrap().insert(id, ack);
+            let pending = pending.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                let ack = pending.lock().unwrap().remove(&id).unwrap();
+                assert!(!ack.is_closed());
+                assert_ok!(ack.send("later"));
+            });
+        });
+        // The ack id is transported out-of-band
+        s.on("raw", |s: SocketRef, ack: AckSender| {
+            let id = ack.ack_id().unwrap();
+            drop(ack);
+            assert_ok!(s.ack_raw(id, "raw"));
+        });
+        s.on("closed", move |s: SocketRef, ack: AckSender| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                s.disconnect().unwrap();
+                assert!(ack.is_closed());
+                let res = ack.send("too late");
+                tx.try_send(matches!(
+                    res,
+                    Err(SendError::Socket(SocketError::Closed(_)))
+                ))
+                .unwrap();
+            });
+        });
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+
+    assert_ok!(stx.send(Text("421[\"deferred\"]".to_string())).await);
+    assert_ok!(stx.send(Text("422[\"raw\"]".to_string())).await);
+    let msg = assert_ok!(srx.next().await.unwrap());
+    assert_eq!(msg, Text("432[\"raw\"]".to_string()));
+    let msg = tokio::time::timeout(Duration::from_millis(700), async {
+        loop {
+            // Answer the heartbeats while waiting for the deferred ack
+            match assert_ok!(srx.next().await.unwrap()) {
+                Text(msg) if msg == "2" => assert_ok!(stx.send(Text("3".to_string())).await),
+                msg => break msg,
+            }
+        }
+    })
+    .await
+    .expect("deferred ack not received");
+    assert_eq!(msg, Text("431[\"later\"]".to_string()));
+
+    assert_ok!(stx.send(Text("423[\"closed\"]".to_string())).await);
+    assert!(rx.recv().await.unwrap());
+}
+
+#[tokio::test]
+pub async fn multi_arg_ack() {
+    const PORT: u16 = 2105;
+    use Message::*;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<String>(4);
+
+    io.ns("/", move |s: SocketRef| {
+        // The arguments of the ack are the elements of the tuple
+        s.on("multi", |ack: AckSender| {
+            assert_ok!(ack.send(("ok", 1, true)));
+        });
+        // A single array argument is wrapped in a one-element tuple
+        s.on("array", |ack: AckSender| {
+            assert_ok!(ack.send((vec![1, 2],)));
+        });
+        tokio::spawn(async move {
+            let res = assert_ok!(s.emit_with_ack::<_, (String, u32)>("ask", ())).await;
+            let ack = assert_ok!(res);
+            assert_ok!(tx.try_send(format!("{:?}", ack.data)));
+
+            let res = assert_ok!(s.emit_with_ack::<_, String>("ask", ())).await;
+            let ack = assert_ok!(res);
+            assert_ok!(tx.try_send(ack.data));
+
+            let res = assert_ok!(s.emit_with_ack::<_, ()>("ask", ())).await;
+            assert_ok!(res);
+            assert_ok!(tx.try_send("unit".into()));
+
+            let res = assert_ok!(s.emit_with_ack::<_, (String, u32)>("ask", ())).await;
+            assert_ok!(tx.try_send(res.unwrap_err().to_string()));
+        });
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+
+    // socket.emit("multi", (a, b, c) => {})
+    assert_ok!(stx.send(Text("421[\"multi\"]".to_string())).await);
+    // socket.emit("array", (arr) => {})
+    assert_ok!(stx.send(Text("422[\"array\"]".to_string())).await);
+
+    // Packets produced by socket.io-client for callback("ok", 1), callback("ok"), callback() and callback(1)
+    let answers = ["[\"ok\",1]", "[\"ok\"]", "[]", "[1]"];
+    let mut answered = 0;
+    let mut acks = Vec::new();
+    while answered < answers.len() || acks.len() < 2 {
+        match assert_ok!(srx.next().await.unwrap()) {
+            Text(msg) if msg.starts_with("42") => {
+                let id = &msg[2..msg.find('[').unwrap()];
+                assert!(msg[2 + id.len()..].starts_with("[\"ask\""), "{msg}");
+                let answer = answers[answered];
+                assert_ok!(stx.send(Text(format!("43{id}{answer}"))).await);
+                answered += 1;
+            }
+            Text(msg) if msg.starts_with("43") => acks.push(msg),
+            msg => panic!("unexpected message {msg:?}"),
+        }
+    }
+    acks.sort();
+    assert_eq!(acks, ["431[\"ok\",1,true]", "432[[1,2]]"]);
+
+    assert_eq!(rx.recv().await.unwrap(), "(\"ok\", 1)");
+    assert_eq!(rx.recv().await.unwrap(), "ok");
+    assert_eq!(rx.recv().await.unwrap(), "unit");
+    assert_eq!(
+        rx.recv().await.unwrap(),
+        "ack response has 1 arguments, expected 2"
+    );
+
+    assert_ok!(stx.close().await);
+}
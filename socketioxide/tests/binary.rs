@@ -0,0 +1,105 @@
+//! Tests for events carrying binary attachments
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use socketioxide::{
+    extract::{Bin, Data, SocketRef},
+    SocketIo,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+pub async fn emit_with_binary() {
+    const PORT: u16 = 2740;
+    use Message::*;
+    let io = create_server(PORT).await;
+    io.ns("/", |s: SocketRef| {
+        s.emit_with_binary("file", json!({ "name": "a.bin" }), vec![vec![1, 2, 3, 4]])
+            .unwrap();
+        s.emit_with_binary("no-file", "foo", vec![]).unwrap();
+        s.on("echo", |s: SocketRef, Data::<Value>(data), Bin(bin)| {
+            s.emit_with_binary("echo", data, bin).unwrap();
+        });
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap()); // engine.io open packet
+    assert_ok!(srx.next().await.unwrap()); // socket.io connect packet
+
+    // The header packet has a placeholder for each attachment, sent right after it
+    let header = r#"451-["file",{"name":"a.bin"},{"_placeholder":true,"num":0}]"#;
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Text(header.into()));
+    assert_eq!(
+        assert_ok!(srx.next().await.unwrap()),
+        Binary(vec![1, 2, 3, 4])
+    );
+    // Without attachment, a normal event is sent
+    let msg = assert_ok!(srx.next().await.unwrap());
+    assert_eq!(msg, Text(r#"42["no-file","foo"]"#.into()));
+
+    // The attachments received from the client are reassembled and sent back in the same order
+    let header =
+        r#"452-["echo",{"name":"b"},{"_placeholder":true,"num":0},{"_placeholder":true,"num":1}]"#;
+    assert_ok!(stx.send(Text(header.into())).await);
+    assert_ok!(stx.send(Binary(vec![1])).await);
+    assert_ok!(stx.send(Binary(vec![2, 3])).await);
+
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Text(header.into()));
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Binary(vec![1]));
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Binary(vec![2, 3]));
+}
+
+#[tokio::test]
+pub async fn raw_binary_sub_protocol() {
+    const PORT: u16 = 2826;
+    use Message::*;
+    let (svc, io) = SocketIo::builder()
+        .raw_binary_filter(|data| data.first() == Some(&0xAB))
+        .build_svc();
+    spawn_server(PORT, svc).await;
+    io.on_raw_binary(|mut data, socket| {
+        data.push(0xFF);
+        socket.emit_binary(data).unwrap();
+    });
+    io.ns("/", |s: SocketRef| {
+        s.on("echo", |s: SocketRef, Data::<Value>(data), Bin(bin)| {
+            s.emit_with_binary("echo", data, bin).unwrap();
+        });
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap()); // engine.io open packet
+    assert_ok!(srx.next().await.unwrap()); // socket.io connect packet
+
+    // The messages of the sub-protocol bypass socket.io
+    assert_ok!(stx.send(Binary(vec![0xAB, 1])).await);
+    assert_eq!(
+        assert_ok!(srx.next().await.unwrap()),
+        Binary(vec![0xAB, 1, 0xFF])
+    );
+
+    // The attachments are reassembled even if they match the filter
+    let header = r#"451-["echo","a",{"_placeholder":true,"num":0}]"#;
+    assert_ok!(stx.send(Text(header.into())).await);
+    assert_ok!(stx.send(Binary(vec![0xAB, 2])).await);
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Text(header.into()));
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Binary(vec![0xAB, 2]));
+
+    // Other binary messages are still handled by socket.io, and ignored without a pending packet
+    assert_ok!(stx.send(Binary(vec![3])).await);
+    let header = r#"452-["echo","b",{"_placeholder":true,"num":0},{"_placeholder":true,"num":1}]"#;
+    assert_ok!(stx.send(Text(header.into())).await);
+    assert_ok!(stx.send(Binary(vec![4])).await);
+    assert_ok!(stx.send(Binary(vec![0xAB, 5])).await);
+    assert_ok!(stx.send(Binary(vec![0xAB, 6])).await);
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Text(header.into()));
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Binary(vec![4]));
+    assert_eq!(assert_ok!(srx.next().await.unwrap()), Binary(vec![0xAB, 5]));
+    assert_eq!(
+        assert_ok!(srx.next().await.unwrap()),
+        Binary(vec![0xAB, 6, 0xFF])
+    );
+}
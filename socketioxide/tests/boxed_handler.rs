@@ -0,0 +1,77 @@
+//! Tests for the handlers registered as trait objects
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection};
+use futures::{FutureExt, SinkExt, StreamExt};
+use socketioxide::{
+    extract::{Data, SocketRef},
+    handler::BoxedHandler,
+};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+const HANDLERS: usize = 50;
+
+/// Builds a routing table of handlers acknowledging the events with their name and data
+fn routing_table(tx: mpsc::UnboundedSender<String>) -> Vec<(String, BoxedHandler)> {
+    (0..HANDLERS)
+        .map(|i| {
+            let event = format!("event-{i}");
+            let name = event.clone();
+            let tx = tx.clone();
+            let handler: BoxedHandler = Box::new(move |_, data, ack| {
+                let name = name.clone();
+                let tx = tx.clone();
+                async move {
+                    let Data::<String>(data) = Data::from_value(data).unwrap();
+                    match ack {
+                        Some(ack) => ack.send(format!("{name}:{data}")).unwrap(),
+                        None => tx.send(format!("{name}:{data}")).unwrap(),
+                    }
+                }
+                .boxed()
+            });
+            (event, handler)
+        })
+        .collect()
+}
+
+#[tokio::test]
+pub async fn dispatch_to_boxed_handlers() {
+    const PORT: u16 = 2803;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    io.ns("/", move |socket: SocketRef| {
+        for (event, handler) in routing_table(tx.clone()) {
+            socket.on_boxed(event, handler);
+        }
+        // Statically typed handlers live alongside the boxed ones
+        socket.on("static", |socket: SocketRef| {
+            socket.emit("static", "ok").unwrap();
+        });
+    });
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet
+
+    for i in 0..HANDLERS {
+        let msg = format!("42{i}[\"event-{i}\",\"data-{i}\"]");
+        assert_ok!(ws.send(Message::Text(msg)).await);
+        let ack = assert_ok!(ws.next().await.unwrap());
+        assert_eq!(ack, Message::Text(format!("43{i}[\"event-{i}:data-{i}\"]")));
+    }
+
+    // Without an ack, the handler is called without an AckSender
+    assert_ok!(
+        ws.send(Message::Text("42[\"event-7\",\"foo\"]".into()))
+            .await
+    );
+    assert_eq!(rx.recv().await.unwrap(), "event-7:foo");
+
+    assert_ok!(ws.send(Message::Text("42[\"static\"]".into())).await);
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert_eq!(msg, Message::Text("42[\"static\",\"ok\"]".into()));
+}
@@ -0,0 +1,455 @@
+//! Tests for the top-level broadcast conveniences:
+//! * `io.emit()` on the default namespace
+//! * `io.of(ns).emit()` on a given namespace
+//! * `socket.broadcast()` which excludes the sender
+//! * `socket.to_other_sessions()` which excludes the sender from its per-user room
+//! * `map_payload()` which transforms the payload once per room
+//! * `sample()` which selects random sockets among the targets
+//! * Emitting to an empty target set or to an unknown namespace
+//! * The order of the direct emits and the broadcasts received by a socket
+mod fixture;
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use socketioxide::extract::{Data, SocketRef};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Wait for the next text message, skipping the heartbeat pings
+async fn next_msg(ws: &mut Ws) -> Option<String> {
+    loop {
+        let msg = tokio::time::timeout(Duration::from_millis(100), ws.next())
+            .await
+            .ok()??
+            .unwrap();
+        match msg {
+            Message::Text(msg) if msg == "2" => continue,
+            Message::Text(msg) => return Some(msg),
+            _ => continue,
+        }
+    }
+}
+
+/// Create a websocket connection and wait for the open and connect packets
+async fn connect(port: u16) -> Ws {
+    let mut ws = create_ws_connection(port).await;
+    assert!(next_msg(&mut ws).await.unwrap().starts_with('0'));
+    assert!(next_msg(&mut ws).await.unwrap().starts_with("40"));
+    ws
+}
+
+#[tokio::test]
+pub async fn io_emit() {
+    const PORT: u16 = 2600;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<()>(10);
+    let tx1 = tx.clone();
+    io.ns("/", move || tx1.try_send(()).unwrap());
+    io.ns("/chat", move || tx.try_send(()).unwrap());
+
+    let mut ws1 = connect(PORT).await;
+    let mut ws2 = connect(PORT).await;
+
+    let mut chat = create_ws_connection(PORT).await;
+    assert!(next_msg(&mut chat).await.unwrap().starts_with('0'));
+    next_msg(&mut chat).await.unwrap(); // connect packet to the default namespace
+    chat.send(Message::Text("40/chat,".into())).await.unwrap();
+    assert!(next_msg(&mut chat).await.unwrap().starts_with("40/chat,"));
+
+    for _ in 0..4 {
+        rx.recv().await.unwrap();
+    }
+
+    io.emit("test", "hello").unwrap();
+    assert_eq!(next_msg(&mut ws1).await.unwrap(), r#"42["test","hello"]"#);
+    assert_eq!(next_msg(&mut ws2).await.unwrap(), r#"42["test","hello"]"#);
+    // The default namespace message is received once by the chat socket
+    assert_eq!(next_msg(&mut chat).await.unwrap(), r#"42["test","hello"]"#);
+    assert_eq!(next_msg(&mut chat).await, None);
+
+    io.of("/chat").unwrap().emit("test", "hello").unwrap();
+    assert_eq!(
+        next_msg(&mut chat).await.unwrap(),
+        r#"42/chat,["test","hello"]"#
+    );
+    assert_eq!(next_msg(&mut ws1).await, None);
+    assert_eq!(next_msg(&mut ws2).await, None);
+
+    assert!(io.of("/unknown").is_none());
+}
+
+#[tokio::test]
+pub async fn socket_broadcast_excludes_sender() {
+    const PORT: u16 = 2601;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<()>(10);
+    io.ns("/", move |socket: SocketRef| {
+        socket.on("msg", |socket: SocketRef, Data::<String>(data)| {
+            socket.broadcast().emit("msg", data).unwrap();
+        });
+        tx.try_send(()).unwrap();
+    });
+
+    let mut ws1 = connect(PORT).await;
+    let mut ws2 = connect(PORT).await;
+    let mut ws3 = connect(PORT).await;
+    for _ in 0..3 {
+        rx.recv().await.unwrap();
+    }
+
+    ws1.send(Message::Text(r#"42["msg","hi"]"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(next_msg(&mut ws2).await.unwrap(), r#"42["msg","hi"]"#);
+    assert_eq!(next_msg(&mut ws3).await.unwrap(), r#"42["msg","hi"]"#);
+    assert_eq!(next_msg(&mut ws1).await, None);
+}
+
+#[tokio::test]
+pub async fn to_other_sessions() {
+    const PORT: u16 = 2827;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<()>(10);
+    io.ns("/", move |socket: SocketRef| {
+        socket.on("join", |socket: SocketRef, Data::<String>(room)| {
+            socket.join(room).unwrap();
+        });
+        socket.on("login", |socket: SocketRef, Data::<String>(room)| {
+            socket
+                .to_other_sessions(room)
+                .emit("logged_in_elsewhere", ())
+                .unwrap();
+        });
+        tx.try_send(()).unwrap();
+    });
+
+    let mut devices = Vec::new();
+    for room in ["user:1", "user:1", "user:1", "user:2"] {
+        let mut ws = connect(PORT).await;
+        rx.recv().await.unwrap();
+        ws.send(Message::Text(format!(r#"42["join","{room}"]"#)))
+            .await
+            .unwrap();
+        devices.push(ws);
+    }
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    devices[0]
+        .send(Message::Text(r#"42["login","user:1"]"#.into()))
+        .await
+        .unwrap();
+    for ws in &mut devices[1..3] {
+        assert_eq!(
+            next_msg(ws).await.unwrap(),
+            r#"42["logged_in_elsewhere",null]"#
+        );
+    }
+    // Neither the originator nor the sessions of other users receive it
+    assert_eq!(next_msg(&mut devices[0]).await, None);
+    assert_eq!(next_msg(&mut devices[3]).await, None);
+}
+
+/// Create a connection and make it join the given rooms
+async fn connect_in(port: u16, rooms: &str, rx: &mut mpsc::Receiver<()>) -> Ws {
+    let mut ws = connect(port).await;
+    ws.send(Message::Text(format!(r#"42["join",{rooms}]"#)))
+        .await
+        .unwrap();
+    rx.recv().await.unwrap();
+    ws
+}
+
+#[tokio::test]
+pub async fn map_payload_per_room() {
+    const PORT: u16 = 2602;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<()>(10);
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on(
+            "join",
+            move |socket: SocketRef, Data::<Vec<String>>(rooms)| {
+                socket.join(rooms).unwrap();
+                tx.try_send(()).unwrap();
+            },
+        );
+    });
+
+    let mut v1 = connect_in(PORT, r#"["v1-clients"]"#, &mut rx).await;
+    let mut v2 = connect_in(PORT, r#"["v2-clients"]"#, &mut rx).await;
+    let mut both = connect_in(PORT, r#"["v1-clients","v2-clients"]"#, &mut rx).await;
+    let mut other = connect_in(PORT, r#"["other"]"#, &mut rx).await;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls1 = calls.clone();
+    io.within(["v1-clients", "v2-clients"])
+        .map_payload(move |room, value| {
+            calls1.fetch_add(1, Ordering::SeqCst);
+            match room {
+                Some("v1-clients") => json!({ "user_name": value["user"]["name"] }),
+                _ => value,
+            }
+        })
+        .emit("user", json!({ "user": { "name": "foo" } }))
+        .unwrap();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    let legacy = r#"42["user",{"user_name":"foo"}]"#;
+    assert_eq!(next_msg(&mut v1).await.unwrap(), legacy);
+    assert_eq!(
+        next_msg(&mut v2).await.unwrap(),
+        r#"42["user",{"user":{"name":"foo"}}]"#
+    );
+    // A socket in both rooms only receives the message once
+    assert_eq!(next_msg(&mut both).await.unwrap(), legacy);
+    assert_eq!(next_msg(&mut both).await, None);
+    assert_eq!(next_msg(&mut other).await, None);
+
+    // Without any room the mapper is called once for the whole namespace
+    io.map_payload(|room, _| json!(room.is_none()))
+        .emit("all", ())
+        .unwrap();
+    for ws in [&mut v1, &mut v2, &mut both, &mut other] {
+        assert_eq!(next_msg(ws).await.unwrap(), r#"42["all",true]"#);
+    }
+}
+
+#[tokio::test]
+pub async fn map_payload_binary_placeholders() {
+    const PORT: u16 = 2603;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<()>(10);
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on(
+            "join",
+            move |socket: SocketRef, Data::<Vec<String>>(rooms)| {
+                socket.join(rooms).unwrap();
+                tx.try_send(()).unwrap();
+            },
+        );
+    });
+
+    let mut ws1 = connect_in(PORT, r#"["room1"]"#, &mut rx).await;
+    let mut ws2 = connect_in(PORT, r#"["room2"]"#, &mut rx).await;
+
+    io.to(["room1", "room2"])
+        .bin(vec![vec![1, 2, 3]])
+        .map_payload(|room, mut value: Value| {
+            let args = value.as_array_mut().unwrap();
+            assert_eq!(
+                args.last().unwrap(),
+                &json!({ "_placeholder": true, "num": 0 })
+            );
+            args.insert(0, json!(room));
+            value
+        })
+        .emit("bin", "data")
+        .unwrap();
+
+    assert_eq!(
+        next_msg(&mut ws1).await.unwrap(),
+        r#"451-["bin","room1","data",{"_placeholder":true,"num":0}]"#
+    );
+    assert_eq!(
+        next_msg(&mut ws2).await.unwrap(),
+        r#"451-["bin","room2","data",{"_placeholder":true,"num":0}]"#
+    );
+}
+
+#[tokio::test]
+pub async fn map_payload_with_ack() {
+    const PORT: u16 = 2604;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<()>(10);
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on(
+            "join",
+            move |socket: SocketRef, Data::<Vec<String>>(rooms)| {
+                socket.join(rooms).unwrap();
+                tx.try_send(()).unwrap();
+            },
+        );
+    });
+
+    let mut ws1 = connect_in(PORT, r#"["room1"]"#, &mut rx).await;
+    let mut ws2 = connect_in(PORT, r#"["room2"]"#, &mut rx).await;
+
+    let acks = io
+        .within(["room1", "room2"])
+        .map_payload(|room, _| json!(room))
+        .emit_with_ack::<[String; 1]>("ack", ())
+        .unwrap();
+
+    for ws in [&mut ws1, &mut ws2] {
+        let msg = next_msg(ws).await.unwrap();
+        let (ack_id, data) = msg.strip_prefix("42").unwrap().split_once('[').unwrap();
+        let room: (String, String) = serde_json::from_str(&format!("[{data}")).unwrap();
+        ws.send(Message::Text(format!(r#"43{ack_id}["{}"]"#, room.1)))
+            .await
+            .unwrap();
+    }
+
+    let mut rooms: Vec<String> = acks
+        .map(|(_, ack)| ack.unwrap().data[0].clone())
+        .collect()
+        .await;
+    rooms.sort();
+    assert_eq!(rooms, ["room1", "room2"]);
+}
+
+#[tokio::test]
+pub async fn sample_with_ack() {
+    const PORT: u16 = 2605;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<()>(10);
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on(
+            "join",
+            move |socket: SocketRef, Data::<Vec<String>>(rooms)| {
+                socket.join(rooms).unwrap();
+                tx.try_send(()).unwrap();
+            },
+        );
+    });
+
+    let mut workers = Vec::new();
+    for _ in 0..4 {
+        workers.push(connect_in(PORT, r#"["workers"]"#, &mut rx).await);
+    }
+    let mut busy = connect_in(PORT, r#"["workers","busy"]"#, &mut rx).await;
+
+    let acks = io
+        .to("workers")
+        .except("busy")
+        .sample_with_seed(2, 7)
+        .emit_with_ack::<[String; 1]>("job", ())
+        .unwrap();
+
+    let mut selected = 0;
+    for ws in &mut workers {
+        let Some(msg) = next_msg(ws).await else {
+            continue;
+        };
+        selected += 1;
+        let ack_id = msg.strip_prefix("42").unwrap().split_once('[').unwrap().0;
+        ws.send(Message::Text(format!(r#"43{ack_id}["done"]"#)))
+            .await
+            .unwrap();
+    }
+    assert_eq!(selected, 2);
+    assert_eq!(next_msg(&mut busy).await, None);
+
+    let acks: Vec<_> = acks.map(|(_, ack)| ack.unwrap().data).collect().await;
+    assert_eq!(acks, [["done"], ["done"]]);
+}
+
+#[tokio::test]
+pub async fn empty_targets() {
+    use socketioxide::{handler::NamespaceHandler, AckError};
+    const PORT: u16 = 2606;
+    let io = create_server(PORT).await;
+    io.ns("/", || {});
+    io.dyn_ns(|path| {
+        path.starts_with("/dyn-")
+            .then(|| NamespaceHandler::new(|| {}))
+    });
+    let mut ws = connect(PORT).await;
+
+    // Emitting to an empty room is a no-op
+    let res = io.to("nonexistent-room").emit("msg", "hello").unwrap();
+    assert_eq!(res.sent, 0);
+    assert_eq!(res.skipped, 0);
+    assert_eq!(next_msg(&mut ws).await, None);
+
+    // Unknown namespaces are never created, even if they match a dynamic namespace
+    assert!(io.of("/typo").is_none());
+    assert!(io.of("/dyn-1").is_none());
+
+    // The acknowledgements of an empty target set are not awaited until the timeout
+    let res = tokio::time::timeout(Duration::from_millis(100), async {
+        let stream = io
+            .to("nonexistent-room")
+            .timeout(Duration::from_secs(10))
+            .emit_with_ack::<Value>("msg", "hello")
+            .unwrap();
+        stream.collect::<Vec<_>>().await
+    })
+    .await
+    .expect("the ack stream should end right away");
+    assert!(res.is_empty());
+
+    let res = tokio::time::timeout(Duration::from_millis(100), async {
+        io.to("nonexistent-room")
+            .emit_with_ack::<Value>("msg", "hello")
+            .unwrap()
+            .await
+    })
+    .await
+    .expect("the ack future should resolve right away");
+    assert!(matches!(res, Err(AckError::NoTarget)));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+pub async fn emit_and_broadcast_ordering() {
+    const PORT: u16 = 2825;
+    const COUNT: u64 = 500;
+    let io = create_server(PORT).await;
+    io.ns("/", |socket: SocketRef| socket.join("room").unwrap());
+
+    let mut clients = Vec::new();
+    for _ in 0..4 {
+        clients.push(connect(PORT).await);
+    }
+    let sockets = io.sockets().unwrap();
+    assert_eq!(sockets.len(), 4);
+
+    // Each task interleaves direct emits and broadcasts to the room,
+    // every socket must receive the packets of a task in the order of the emit calls
+    let tasks = ["a", "b"].map(|event| {
+        let io = io.clone();
+        let sockets = sockets.clone();
+        tokio::spawn(async move {
+            for i in 0..COUNT {
+                if i % 2 == 0 {
+                    for socket in &sockets {
+                        socket.emit(event, i).unwrap();
+                    }
+                } else {
+                    io.to("room").emit(event, i).unwrap();
+                }
+                if i % 50 == 0 {
+                    tokio::task::yield_now().await;
+                }
+            }
+        })
+    });
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    for ws in &mut clients {
+        let mut next = [0u64; 2];
+        while next != [COUNT; 2] {
+            let msg = next_msg(ws).await.unwrap();
+            let msg: Value = serde_json::from_str(msg.strip_prefix("42").unwrap()).unwrap();
+            let task = if msg[0] == "a" { 0 } else { 1 };
+            assert_eq!(msg[1], next[task]);
+            next[task] += 1;
+        }
+        assert_eq!(next_msg(ws).await, None);
+    }
+}
@@ -0,0 +1,54 @@
+//! Tests for the warning emitted to the clients before a server-initiated close
+mod fixture;
+mod utils;
+
+use std::time::{Duration, Instant};
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::StreamExt;
+use socketioxide::{extract::SocketRef, SocketIo};
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+pub async fn idle_connection_is_warned() {
+    const PORT: u16 = 2805;
+    const GRACE: Duration = Duration::from_millis(100);
+    let (svc, io) = SocketIo::builder()
+        .idle_timeout(Duration::from_millis(100))
+        .close_warning_event("closing")
+        .close_warning_grace(GRACE)
+        .build_svc();
+    spawn_server(PORT, svc).await;
+    io.ns("/", |_: SocketRef| {});
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet
+
+    let msg = assert_ok!(ws.next().await.unwrap());
+    let warned_at = Instant::now();
+    assert_eq!(
+        msg,
+        Message::Text(r#"42["closing",{"reason":"idle_timeout"}]"#.into())
+    );
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert!(msg.is_close(), "{msg:?}");
+    assert!(warned_at.elapsed() >= GRACE - Duration::from_millis(10));
+}
+
+#[tokio::test]
+pub async fn no_warning_by_default() {
+    const PORT: u16 = 2806;
+    let (svc, io) = SocketIo::builder()
+        .idle_timeout(Duration::from_millis(100))
+        .build_svc();
+    spawn_server(PORT, svc).await;
+    io.ns("/", |_: SocketRef| {});
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert!(msg.is_close(), "{msg:?}");
+}
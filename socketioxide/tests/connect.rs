@@ -105,3 +105,85 @@ pub async fn connect_middleware_error() {
     rx.recv().await.unwrap();
     rx.try_recv().unwrap_err();
 }
+
+#[tokio::test]
+pub async fn multiple_namespaces() {
+    use futures::SinkExt;
+    const PORT: u16 = 2422;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<Vec<String>>(100);
+
+    let tx_root = tx.clone();
+    io.ns("/", move |s: SocketRef| {
+        s.on("list", move |s: SocketRef| {
+            tx_root.try_send(s.namespaces()).unwrap();
+        });
+    });
+    io.ns("/custom", move |s: SocketRef| {
+        tx.try_send(s.namespaces()).unwrap();
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+
+    stx.send(Text("40/custom,".into())).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap(), ["/", "/custom"]);
+
+    stx.send(Text("41/custom,".into())).await.unwrap();
+    stx.send(Text(r#"42["list"]"#.into())).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap(), ["/"]);
+}
+
+#[tokio::test]
+pub async fn global_connect_order() {
+    use futures::SinkExt;
+    const PORT: u16 = 2823;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<String>(100);
+
+    let tx1 = tx.clone();
+    io.on_connect_global(move |s: SocketRef| {
+        tx1.try_send(format!("global {}", s.ns())).unwrap();
+    });
+    let handler = |name: &'static str| {
+        let tx = tx.clone();
+        move |s: SocketRef| {
+            tx.try_send(format!("{name} {}", s.ns())).unwrap();
+        }
+    };
+    let middleware = |accept: bool| {
+        let tx = tx.clone();
+        move |s: SocketRef| {
+            tx.try_send(format!("middleware {}", s.ns())).unwrap();
+            if accept {
+                Ok(())
+            } else {
+                Err("rejected")
+            }
+        }
+    };
+    io.ns("/", handler("handler").with(middleware(true)));
+    io.ns("/custom", handler("handler"));
+    io.ns("/private", handler("handler").with(middleware(false)));
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+    assert_eq!(rx.recv().await.unwrap(), "middleware /");
+    assert_eq!(rx.recv().await.unwrap(), "global /");
+    assert_eq!(rx.recv().await.unwrap(), "handler /");
+
+    stx.send(Text("40/custom,".into())).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap(), "global /custom");
+    assert_eq!(rx.recv().await.unwrap(), "handler /custom");
+
+    // Rejected connections do not call the global handler
+    stx.send(Text("40/private,".into())).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap(), "middleware /private");
+    let p = assert_ok!(srx.next().await.unwrap());
+    assert!(matches!(p, Text(s) if s.starts_with("40/custom,")));
+    let p = assert_ok!(srx.next().await.unwrap());
+    assert_eq!(p, Text("44/private,{\"message\":\"rejected\"}".to_string()));
+    rx.try_recv().unwrap_err();
+}
@@ -0,0 +1,70 @@
+//! Tests for the removal of a namespace at runtime with [`SocketIo::delete_ns`]
+mod fixture;
+mod utils;
+
+use std::time::Duration;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, socket::DisconnectReason, BroadcastError, SocketIo};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+pub async fn delete_ns_disconnects_its_sockets() {
+    const PORT: u16 = 2815;
+    let (svc, io) = SocketIo::new_svc();
+    spawn_server(PORT, svc).await;
+    let (tx, mut rx) = mpsc::channel::<DisconnectReason>(10);
+    io.ns("/", || {});
+    io.ns("/event-2024", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on_disconnect(move |reason: DisconnectReason| {
+            tx.try_send(reason).unwrap();
+        });
+    });
+
+    let mut clients = Vec::with_capacity(10);
+    for _ in 0..10 {
+        let mut ws = create_ws_connection(PORT).await;
+        assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+        assert_ok!(ws.next().await.unwrap()); // socket.io connect packet of the main namespace
+        assert_ok!(ws.send(Message::Text("40/event-2024,".into())).await);
+        let msg = assert_ok!(ws.next().await.unwrap()).into_text().unwrap();
+        assert!(msg.starts_with("40/event-2024,{\"sid\":"), "{msg}");
+        clients.push(ws);
+    }
+    let sockets = io.of("/event-2024").unwrap().sockets().unwrap();
+    assert_eq!(sockets.len(), 10);
+    let operators = io.of("/event-2024").unwrap();
+
+    io.delete_ns("/event-2024");
+    assert!(io.of("/event-2024").is_none());
+
+    for _ in 0..10 {
+        let reason = tokio::time::timeout(Duration::from_millis(500), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reason, DisconnectReason::ServerNSDisconnect);
+    }
+    for ws in &mut clients {
+        let msg = assert_ok!(ws.next().await.unwrap());
+        assert_eq!(msg, Message::Text("41/event-2024,".into()));
+    }
+
+    // The operators kept before the deletion cannot emit anymore
+    let res = operators.emit("hello", "world");
+    assert!(
+        matches!(res, Err(BroadcastError::NamespaceClosed)),
+        "{res:?}"
+    );
+
+    // A new connection to the deleted namespace is rejected
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.send(Message::Text("40/event-2024,".into())).await);
+    let msg = assert_ok!(ws.next().await.unwrap()).into_text().unwrap();
+    assert_eq!(msg, r#"44/event-2024,{"message":"Invalid namespace"}"#);
+}
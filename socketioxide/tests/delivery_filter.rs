@@ -0,0 +1,109 @@
+//! Tests for the per-socket delivery filter of the namespaces
+mod fixture;
+mod utils;
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use fixture::{create_server, create_ws_connection};
+use futures::StreamExt;
+use socketioxide::{adapter::BroadcastResult, extract::SocketRef, DeliveryFilter};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+pub async fn delivery_filter_broadcast() {
+    const PORT: u16 = 2780;
+    let io = create_server(PORT).await;
+    let blocked = Arc::new(Mutex::new(HashSet::new()));
+    let (tx, mut rx) = mpsc::channel::<SocketRef>(4);
+    let count = Arc::new(Mutex::new(0));
+    let blocked_ = blocked.clone();
+    io.ns("/", move |socket: SocketRef| {
+        socket.join("room").unwrap();
+        let mut count = count.lock().unwrap();
+        // Every other socket is blocked
+        if *count % 2 == 1 {
+            blocked_.lock().unwrap().insert(socket.id);
+        }
+        *count += 1;
+        tx.try_send(socket).unwrap();
+    });
+    let blocked_ = blocked.clone();
+    assert!(io.set_delivery_filter(
+        "/",
+        Some(DeliveryFilter::new(move |socket, event| {
+            event != "news" || !blocked_.lock().unwrap().contains(&socket.id)
+        }))
+    ));
+    assert!(!io.set_delivery_filter("/unknown", None));
+
+    let mut clients = Vec::new();
+    let mut sockets = Vec::new();
+    for _ in 0..4 {
+        let mut ws = create_ws_connection(PORT).await;
+        assert_ok!(ws.next().await.unwrap());
+        assert_ok!(ws.next().await.unwrap());
+        sockets.push(rx.recv().await.unwrap());
+        clients.push(ws);
+    }
+
+    let res = io.to("room").emit("news", "hello").unwrap();
+    assert_eq!(res, BroadcastResult::new(2, 2));
+    // Only the events matching the predicate are suppressed
+    let res = io.to("room").emit("other", "hello").unwrap();
+    assert_eq!(res, BroadcastResult::new(4, 0));
+    // Direct emits are not filtered unless opted in
+    for socket in &sockets {
+        socket.emit("news", "direct").unwrap();
+    }
+
+    for (i, ws) in clients.iter_mut().enumerate() {
+        let mut expected = vec![r#"42["other","hello"]"#, r#"42["news","direct"]"#];
+        if i % 2 == 0 {
+            expected.insert(0, r#"42["news","hello"]"#);
+        }
+        for expected in expected {
+            let msg = assert_ok!(ws.next().await.unwrap()).into_text().unwrap();
+            assert_eq!(msg, expected);
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn delivery_filter_direct_emits() {
+    const PORT: u16 = 2781;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<SocketRef>(1);
+    io.ns("/", move |socket: SocketRef| {
+        tx.try_send(socket).unwrap();
+    });
+    io.set_delivery_filter(
+        "/",
+        Some(DeliveryFilter::new(|_, event| event != "secret").direct_emits(true)),
+    );
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+    let socket = rx.recv().await.unwrap();
+
+    socket.emit("secret", "data").unwrap();
+    socket.emit("public", "data").unwrap();
+    let msg = assert_ok!(ws.next().await.unwrap()).into_text().unwrap();
+    assert_eq!(msg, r#"42["public","data"]"#);
+
+    // Without a filter every event is delivered
+    io.set_delivery_filter("/", None);
+    socket.emit("secret", "data").unwrap();
+    let msg = tokio::time::timeout(Duration::from_millis(200), ws.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        assert_ok!(msg).into_text().unwrap(),
+        r#"42["secret","data"]"#
+    );
+}
@@ -1,7 +1,7 @@
 //! Tests for disconnect reasons
 //! Test are made on polling and websocket transports for engine.io errors and only websocket for socket.io errors:
 //! * Heartbeat timeout
-//! * Transport close
+//! * Transport close (for every namespace of the connection)
 //! * Multiple http polling
 //! * Packet parsing
 //!
@@ -37,6 +37,7 @@ fn attach_handler(io: &SocketIo, chan_size: usize) -> mpsc::Receiver<DisconnectR
 // Engine IO Disconnect Reason Tests
 
 #[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn polling_heartbeat_timeout() {
     let io = create_server(1234).await;
     let mut rx = attach_handler(&io, 1);
@@ -65,6 +66,7 @@ pub async fn ws_heartbeat_timeout() {
 }
 
 #[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn polling_transport_closed() {
     let io = create_server(1235).await;
     let mut rx = attach_handler(&io, 1);
@@ -103,6 +105,62 @@ pub async fn ws_transport_closed() {
 }
 
 #[tokio::test]
+pub async fn ws_transport_closed_all_namespaces() {
+    let io = create_server(12353).await;
+    let (tx, mut rx) = mpsc::channel::<String>(10);
+    for ns in ["/", "/custom"] {
+        let tx = tx.clone();
+        io.ns(ns, move |socket: SocketRef| {
+            socket.join("room").unwrap();
+            // The client never answers, the ack is resolved when the transport is closed
+            let ack = socket.emit_with_ack::<_, ()>("test", ()).unwrap();
+            let tx_ack = tx.clone();
+            tokio::spawn(async move {
+                let res = ack.await;
+                tx_ack.try_send(format!("ack: {}", res.is_err())).unwrap();
+            });
+            let tx = tx.clone();
+            socket.on_disconnect(move |s: SocketRef, reason: DisconnectReason| {
+                tx.try_send(format!("{}: {reason:?}", s.ns())).unwrap();
+            });
+        });
+    }
+    let mut stream = create_ws_connection(12353).await;
+    stream
+        .send(Message::Text("40/custom,".into()))
+        .await
+        .unwrap();
+    // open, connect and event packets for both namespaces
+    for _ in 0..5 {
+        stream.next().await.unwrap().unwrap();
+    }
+
+    // A close at the engine.io level disconnects every namespace of the connection
+    stream.send(Message::Text("1".into())).await.unwrap();
+    let mut events = Vec::new();
+    for _ in 0..4 {
+        let event = tokio::time::timeout(Duration::from_millis(20), rx.recv())
+            .await
+            .expect("timeout waiting for the namespaces to be disconnected")
+            .unwrap();
+        events.push(event);
+    }
+    events.sort();
+    assert_eq!(
+        events,
+        [
+            "/: TransportClose",
+            "/custom: TransportClose",
+            "ack: true",
+            "ack: true"
+        ]
+    );
+    assert!(io.within("room").sockets().unwrap().is_empty());
+    assert!(io.of("/custom").unwrap().sockets().unwrap().is_empty());
+}
+
+#[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn multiple_http_polling() {
     let io = create_server(1236).await;
     let mut rx = attach_handler(&io, 1);
@@ -141,6 +199,7 @@ pub async fn multiple_http_polling() {
 }
 
 #[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn polling_packet_parsing() {
     let io = create_server(1237).await;
     let mut rx = attach_handler(&io, 1);
@@ -179,6 +238,23 @@ pub async fn ws_packet_parsing() {
     assert_eq!(data, DisconnectReason::PacketParsingError);
 }
 
+#[tokio::test]
+pub async fn ws_empty_message_ignored() {
+    let io = create_server(12354).await;
+    let mut rx = attach_handler(&io, 1);
+    let mut stream = create_ws_connection(12354).await;
+    // An empty engine.io message is not a socket.io packet, the socket stays connected
+    stream.send(Message::Text("4".into())).await.unwrap();
+    stream.send(Message::Text("41".into())).await.unwrap();
+
+    let data = tokio::time::timeout(Duration::from_millis(20), rx.recv())
+        .await
+        .expect("timeout waiting for DisconnectReason::ClientNSDisconnect")
+        .unwrap();
+
+    assert_eq!(data, DisconnectReason::ClientNSDisconnect);
+}
+
 // Socket IO Disconnect Reason Tests
 
 #[tokio::test]
@@ -255,6 +331,7 @@ pub async fn server_ws_closing() {
 }
 
 #[tokio::test]
+#[cfg(feature = "polling")]
 pub async fn server_http_closing() {
     let io = create_server(12351).await;
     let _rx = attach_handler(&io, 100);
@@ -0,0 +1,73 @@
+//! Tests for the dynamic namespaces created on demand
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, handler::NamespaceHandler};
+use tokio_tungstenite::tungstenite::Message;
+
+fn room_ns(path: &str) -> Option<NamespaceHandler> {
+    let id = path.strip_prefix("/room-")?;
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(NamespaceHandler::new(|socket: SocketRef| {
+        let ns = socket.ns().to_string();
+        socket.emit("welcome", ns).unwrap();
+    }))
+}
+
+#[tokio::test]
+pub async fn dyn_ns_created_on_demand() {
+    const PORT: u16 = 2770;
+    let io = create_server(PORT).await;
+    io.ns("/", || {});
+    io.dyn_ns(room_ns);
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+    assert!(io.of("/room-12").is_none());
+
+    stx.send(Message::Text("40/room-12,".into())).await.unwrap();
+    let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+    assert!(msg.starts_with("40/room-12,{\"sid\":"), "{msg}");
+    let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+    assert_eq!(msg, r#"42/room-12,["welcome","/room-12"]"#);
+
+    // The created namespace is registered and reused by the following connections
+    let sockets = || io.of("/room-12").unwrap().sockets().unwrap().len();
+    assert_eq!(sockets(), 1);
+    let (mut stx2, mut srx2) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx2.next().await.unwrap());
+    assert_ok!(srx2.next().await.unwrap());
+    stx2.send(Message::Text("40/room-12,".into()))
+        .await
+        .unwrap();
+    assert_ok!(srx2.next().await.unwrap());
+    assert_ok!(srx2.next().await.unwrap());
+    assert_eq!(sockets(), 2);
+}
+
+#[tokio::test]
+pub async fn dyn_ns_rejects_unmatched_path() {
+    const PORT: u16 = 2771;
+    let io = create_server(PORT).await;
+    io.ns("/", || {});
+    io.dyn_ns(room_ns);
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+
+    for path in ["/room-abc", "/unknown"] {
+        stx.send(Message::Text(format!("40{path},"))).await.unwrap();
+        let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+        assert_eq!(
+            msg,
+            format!(r#"44{path},{{"message":"Invalid namespace"}}"#)
+        );
+        assert!(io.of(path).is_none());
+    }
+}
@@ -0,0 +1,119 @@
+//! Tests for the echo probe answered by the server without calling any handler
+mod fixture;
+mod utils;
+
+use fixture::{create_polling_connection, create_ws_connection, send_req, spawn_server};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use socketioxide::{extract::SocketRef, SocketIo};
+use tokio_tungstenite::tungstenite::Message;
+
+async fn create_echo_server(port: u16) -> SocketIo {
+    let (svc, io) = SocketIo::builder().echo_probe(true).build_svc();
+    spawn_server(port, svc).await;
+    // The echo probe bypasses the handlers, the client would be disconnected otherwise
+    io.ns("/", |socket: SocketRef| {
+        socket.on("__sioxide_echo", |socket: SocketRef| {
+            socket.disconnect().ok();
+        });
+    });
+    io
+}
+
+/// Splits the arguments of an answer from the server timestamp added at the end
+fn split_timestamp(data: &str) -> (Vec<Value>, u64) {
+    let mut args: Vec<Value> = serde_json::from_str(data).unwrap();
+    let timestamp = args.pop().unwrap().as_u64().unwrap();
+    (args, timestamp)
+}
+
+#[tokio::test]
+pub async fn echo_probe_ws() {
+    const PORT: u16 = 2750;
+    let io = create_echo_server(PORT).await;
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+
+    // With an ack id, the probe is answered with an acknowledgement
+    stx.send(Message::Text(r#"421["__sioxide_echo",{"a":1},"b"]"#.into()))
+        .await
+        .unwrap();
+    let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+    let (args, timestamp) = split_timestamp(msg.strip_prefix("431").unwrap());
+    assert_eq!(args, [serde_json::json!({ "a": 1 }), "b".into()]);
+    assert!(timestamp > 0);
+
+    // Without an ack id, the event is sent back
+    stx.send(Message::Text(r#"42["__sioxide_echo","hi"]"#.into()))
+        .await
+        .unwrap();
+    let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+    let msg = msg.strip_prefix(r#"42["__sioxide_echo","#).unwrap();
+    let (args, _) = split_timestamp(&format!("[{msg}"));
+    assert_eq!(args, ["hi"]);
+
+    // The binary attachments are sent back
+    stx.send(Message::Text(
+        r#"451-2["__sioxide_echo",{"_placeholder":true,"num":0}]"#.into(),
+    ))
+    .await
+    .unwrap();
+    stx.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+    let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+    let (args, _) = split_timestamp(
+        &msg.strip_prefix("461-2")
+            .unwrap()
+            .replace(r#",{"_placeholder":true,"num":0}"#, ""),
+    );
+    assert!(args.is_empty());
+    assert_eq!(
+        assert_ok!(srx.next().await.unwrap()),
+        Message::Binary(vec![1, 2, 3])
+    );
+
+    assert_eq!(io.echo_probes(), 3);
+}
+
+#[tokio::test]
+pub async fn echo_probe_polling() {
+    const PORT: u16 = 2751;
+    let io = create_echo_server(PORT).await;
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+    // The first char of the payload is stripped by `send_req`
+    let connect = send_req(PORT, params(), http::Method::GET, None).await;
+    assert!(connect.starts_with("0{"));
+
+    send_req(
+        PORT,
+        params(),
+        http::Method::POST,
+        Some(r#"421["__sioxide_echo",1]"#.into()),
+    )
+    .await;
+    let msg = send_req(PORT, params(), http::Method::GET, None).await;
+    let (args, _) = split_timestamp(msg.strip_prefix("31").unwrap());
+    assert_eq!(args, [1]);
+    assert_eq!(io.echo_probes(), 1);
+}
+
+#[tokio::test]
+pub async fn echo_probe_disabled_by_default() {
+    const PORT: u16 = 2752;
+    let (svc, io) = SocketIo::new_svc();
+    spawn_server(PORT, svc).await;
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(1);
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on("__sioxide_echo", move || tx.try_send(()).unwrap());
+    });
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+    stx.send(Message::Text(r#"42["__sioxide_echo"]"#.into()))
+        .await
+        .unwrap();
+    rx.recv().await.unwrap();
+    assert_eq!(io.echo_probes(), 0);
+}
@@ -0,0 +1,119 @@
+//! Tests for the [`ErrorPolicy`] applied when a message handler fails
+mod fixture;
+mod utils;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{
+    extract::{Data, SocketRef},
+    handler::{ErrorPolicy, HandlerError},
+    SocketIo,
+};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn connect(port: u16, policy: ErrorPolicy) -> Ws {
+    let (svc, io) = SocketIo::new_svc();
+    spawn_server(port, svc).await;
+    io.ns("/", |s: SocketRef| {
+        // Fails to extract anything but a number
+        s.on("num", |Data::<u32>(_)| {});
+        s.on("fail", |_: SocketRef| async move {
+            Err::<(), _>(HandlerError::new("boom").with_code("exploded"))
+        });
+        s.on(
+            "parse",
+            |Data::<String>(data)| -> Result<(), std::num::ParseIntError> {
+                data.parse::<u32>()?;
+                Ok(())
+            },
+        );
+        s.on("ping", |s: SocketRef| {
+            s.emit("pong", "ok").ok();
+        });
+    });
+    io.set_error_policy("/", policy);
+
+    let mut ws = create_ws_connection(port).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet
+    ws
+}
+
+async fn send(ws: &mut Ws, msg: &str) {
+    assert_ok!(ws.send(Message::Text(msg.to_string())).await);
+}
+
+async fn recv(ws: &mut Ws) -> String {
+    assert_ok!(ws.next().await.unwrap()).into_text().unwrap()
+}
+
+/// Checks that nothing was sent before the answer to a ping
+async fn assert_nothing_sent(ws: &mut Ws) {
+    send(ws, r#"42["ping"]"#).await;
+    assert_eq!(recv(ws).await, r#"42["pong","ok"]"#);
+}
+
+#[tokio::test]
+pub async fn silent_policy() {
+    const PORT: u16 = 2812;
+    let mut ws = connect(PORT, ErrorPolicy::Silent).await;
+
+    send(&mut ws, r#"42["num","abc"]"#).await;
+    send(&mut ws, r#"421["num","abc"]"#).await;
+    send(&mut ws, r#"422["fail"]"#).await;
+    assert_nothing_sent(&mut ws).await;
+}
+
+#[tokio::test]
+pub async fn emit_event_policy() {
+    const PORT: u16 = 2813;
+    let mut ws = connect(PORT, ErrorPolicy::EmitEvent("error".into())).await;
+
+    send(&mut ws, r#"42["num","abc"]"#).await;
+    let msg = recv(&mut ws).await;
+    let msg: serde_json::Value = serde_json::from_str(msg.strip_prefix("42").unwrap()).unwrap();
+    assert_eq!(msg[0], "error");
+    assert_eq!(msg[1]["event"], "num");
+    assert_eq!(msg[1]["code"], "invalid_data");
+    assert!(msg[1]["message"].is_string());
+
+    // The event is emitted even if the client expects an ack
+    send(&mut ws, r#"421["fail"]"#).await;
+    assert_eq!(
+        recv(&mut ws).await,
+        r#"42["error",{"code":"exploded","event":"fail","message":"boom"}]"#
+    );
+
+    send(&mut ws, r#"42["parse","abc"]"#).await;
+    assert_eq!(
+        recv(&mut ws).await,
+        r#"42["error",{"code":"handler_error","event":"parse","message":"invalid digit found in string"}]"#
+    );
+
+    send(&mut ws, r#"42["parse","12"]"#).await;
+    assert_nothing_sent(&mut ws).await;
+}
+
+#[tokio::test]
+pub async fn ack_error_policy() {
+    const PORT: u16 = 2814;
+    let mut ws = connect(PORT, ErrorPolicy::AckError).await;
+
+    send(&mut ws, r#"421["fail"]"#).await;
+    assert_eq!(
+        recv(&mut ws).await,
+        r#"431[{"code":"exploded","error":"boom"}]"#
+    );
+
+    send(&mut ws, r#"422["num","abc"]"#).await;
+    let msg = recv(&mut ws).await;
+    let msg: serde_json::Value = serde_json::from_str(msg.strip_prefix("432").unwrap()).unwrap();
+    assert_eq!(msg[0]["code"], "invalid_data");
+
+    // Without an ack id the failure is not reported
+    send(&mut ws, r#"42["fail"]"#).await;
+    send(&mut ws, r#"42["num","abc"]"#).await;
+    assert_nothing_sent(&mut ws).await;
+}
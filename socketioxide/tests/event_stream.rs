@@ -0,0 +1,137 @@
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, socket::DisconnectReason, ServerEvent, SocketIo};
+use std::time::Duration;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::Message;
+
+async fn recv(rx: &mut broadcast::Receiver<ServerEvent>) -> ServerEvent {
+    let event = tokio::time::timeout(Duration::from_millis(100), rx.recv()).await;
+    event.unwrap().unwrap()
+}
+
+#[tokio::test]
+pub async fn connection_and_room_events() {
+    const PORT: u16 = 2720;
+    let io = create_server(PORT).await;
+    let mut events = io.event_stream();
+
+    io.ns("/", |socket: SocketRef| {
+        socket.join("room1").unwrap();
+        socket.on("move", |socket: SocketRef| {
+            socket.leave("room1").unwrap();
+            socket.join(["room2", "room2"]).unwrap();
+        });
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap()); // engine.io open packet
+    assert_ok!(srx.next().await.unwrap()); // socket.io connect packet
+
+    let sid = match recv(&mut events).await {
+        ServerEvent::Connect { sid, ns } => {
+            assert_eq!(ns, "/");
+            sid
+        }
+        event => panic!("unexpected event {event:?}"),
+    };
+    assert_eq!(
+        recv(&mut events).await,
+        ServerEvent::RoomJoin {
+            sid,
+            room: "room1".into()
+        }
+    );
+
+    stx.send(Message::Text(r#"42["move"]"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(
+        recv(&mut events).await,
+        ServerEvent::RoomLeave {
+            sid,
+            room: "room1".into()
+        }
+    );
+    // Joining a room twice only sends one event
+    assert_eq!(
+        recv(&mut events).await,
+        ServerEvent::RoomJoin {
+            sid,
+            room: "room2".into()
+        }
+    );
+    assert_eq!(io.within("room2").sockets().unwrap()[0].id, sid);
+
+    stx.send(Message::Close(None)).await.unwrap();
+    assert_eq!(
+        recv(&mut events).await,
+        ServerEvent::RoomLeave {
+            sid,
+            room: "room2".into()
+        }
+    );
+    assert_eq!(
+        recv(&mut events).await,
+        ServerEvent::Disconnect {
+            sid,
+            ns: "/".into(),
+            reason: DisconnectReason::TransportClose
+        }
+    );
+    assert!(io.sockets().unwrap().is_empty());
+    assert_eq!(
+        events.try_recv(),
+        Err(broadcast::error::TryRecvError::Empty)
+    );
+}
+
+#[tokio::test]
+pub async fn connect_event_after_socket_is_visible() {
+    const PORT: u16 = 2721;
+    let io = create_server(PORT).await;
+    io.ns("/", || {});
+    let mut events = io.event_stream();
+
+    let io_clone = io.clone();
+    let visible = tokio::spawn(async move {
+        match events.recv().await.unwrap() {
+            ServerEvent::Connect { sid, .. } => io_clone.sockets().unwrap()[0].id == sid,
+            event => panic!("unexpected event {event:?}"),
+        }
+    });
+
+    let _ws = create_ws_connection(PORT).await;
+    let visible = tokio::time::timeout(Duration::from_millis(200), visible).await;
+    assert!(visible.unwrap().unwrap());
+}
+
+#[tokio::test]
+pub async fn lagging_subscriber_drops_oldest_events() {
+    const PORT: u16 = 2722;
+    let (svc, io) = SocketIo::builder().event_stream_capacity(2).build_svc();
+    spawn_server(PORT, svc).await;
+    io.ns("/", || {});
+    let mut events = io.event_stream();
+
+    let mut ws = Vec::new();
+    for _ in 0..4 {
+        let mut conn = create_ws_connection(PORT).await;
+        assert_ok!(conn.next().await.unwrap()); // engine.io open packet
+        assert_ok!(conn.next().await.unwrap()); // socket.io connect packet
+        ws.push(conn);
+    }
+    assert!(matches!(events.recv().await, Err(RecvError::Lagged(2))));
+    assert!(matches!(
+        recv(&mut events).await,
+        ServerEvent::Connect { .. }
+    ));
+    assert!(matches!(
+        recv(&mut events).await,
+        ServerEvent::Connect { .. }
+    ));
+}
@@ -151,7 +151,7 @@ pub async fn socketio_client(
         .await
 }
 
-async fn spawn_server(port: u16, svc: SocketIoService<NotFoundService, LocalAdapter>) {
+pub async fn spawn_server(port: u16, svc: SocketIoService<NotFoundService, LocalAdapter>) {
     let addr = &SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
     let listener = TcpListener::bind(&addr).await.unwrap();
     tokio::spawn(async move {
@@ -0,0 +1,101 @@
+//! Tests for the concurrency limits of the message handlers
+mod fixture;
+mod utils;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{
+    extract::{AckSender, SocketRef},
+    handler::QueuePolicy,
+};
+use tokio::{sync::watch, time::Duration};
+use tokio_tungstenite::tungstenite::Message;
+
+const REJECTED: &str = r#"[{"error":"too many concurrent events"}]"#;
+
+/// Starts a server whose "search" handler waits for the gate to open, limited to 2 concurrent invocations.
+/// Fires 10 "search" events with an ack and returns the acks received before and after opening the gate,
+/// along with the maximum number of handlers that ran at the same time.
+async fn fire_searches(port: u16, policy: QueuePolicy) -> (Vec<String>, Vec<String>, usize) {
+    let io = create_server(port).await;
+    let (gate_tx, gate_rx) = watch::channel(false);
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let max_running_ = max_running.clone();
+    io.ns("/", move |socket: SocketRef| {
+        let gate_rx = gate_rx.clone();
+        let running = running.clone();
+        let max_running = max_running_.clone();
+        socket
+            .on("search", move |ack: AckSender| {
+                let mut gate_rx = gate_rx.clone();
+                let running = running.clone();
+                let max_running = max_running.clone();
+                async move {
+                    let count = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(count, Ordering::SeqCst);
+                    gate_rx.wait_for(|open| *open).await.unwrap();
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    ack.send("done").ok();
+                }
+            })
+            .max_concurrent(2)
+            .queue(policy);
+    });
+
+    let mut ws = create_ws_connection(port).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+    for i in 0..10 {
+        let msg = format!(r#"42{i}["search"]"#);
+        assert_ok!(ws.send(Message::Text(msg)).await);
+    }
+
+    let mut before = Vec::new();
+    while let Ok(msg) = tokio::time::timeout(Duration::from_millis(100), ws.next()).await {
+        before.push(assert_ok!(msg.unwrap()).into_text().unwrap().to_string());
+    }
+    gate_tx.send(true).unwrap();
+    let mut after = Vec::new();
+    while let Ok(msg) = tokio::time::timeout(Duration::from_millis(100), ws.next()).await {
+        after.push(assert_ok!(msg.unwrap()).into_text().unwrap().to_string());
+    }
+    after.sort();
+    (before, after, max_running.load(Ordering::SeqCst))
+}
+
+#[tokio::test]
+pub async fn max_concurrent_reject() {
+    const PORT: u16 = 2790;
+    let (before, after, max_running) = fire_searches(PORT, QueuePolicy::Reject).await;
+    assert_eq!(max_running, 2);
+    let rejected: Vec<_> = (2..10).map(|i| format!("43{i}{REJECTED}")).collect();
+    assert_eq!(before, rejected);
+    assert_eq!(after, [r#"430["done"]"#, r#"431["done"]"#]);
+}
+
+#[tokio::test]
+pub async fn max_concurrent_queue() {
+    const PORT: u16 = 2791;
+    let (before, after, max_running) = fire_searches(PORT, QueuePolicy::Queue(5)).await;
+    assert_eq!(max_running, 2);
+    // 2 invocations run, 5 are queued and the 3 last ones are rejected
+    let rejected: Vec<_> = (7..10).map(|i| format!("43{i}{REJECTED}")).collect();
+    assert_eq!(before, rejected);
+    let done: Vec<_> = (0..7).map(|i| format!(r#"43{i}["done"]"#)).collect();
+    assert_eq!(after, done);
+}
+
+#[tokio::test]
+pub async fn max_concurrent_drop() {
+    const PORT: u16 = 2792;
+    let (before, after, max_running) = fire_searches(PORT, QueuePolicy::Drop).await;
+    assert_eq!(max_running, 2);
+    assert!(before.is_empty());
+    assert_eq!(after, [r#"430["done"]"#, r#"431["done"]"#]);
+}
@@ -0,0 +1,83 @@
+//! Tests for the [`MalformedEventPolicy`] applied to the packets that can't be decoded
+mod fixture;
+mod utils;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, packet::MalformedEventPolicy, SocketIo};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn connect(port: u16, policy: MalformedEventPolicy) -> Ws {
+    let (svc, io) = SocketIo::builder()
+        .malformed_event_policy(policy)
+        .build_svc();
+    spawn_server(port, svc).await;
+    io.ns("/", |s: SocketRef| {
+        s.on("ping", |s: SocketRef| {
+            s.emit("pong", "ok").ok();
+        });
+    });
+
+    let mut ws = create_ws_connection(port).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet
+    ws
+}
+
+/// Checks that the connection is still usable
+async fn assert_alive(ws: &mut Ws) {
+    assert_ok!(ws.send(Message::Text(r#"42["ping"]"#.into())).await);
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert_eq!(msg, Message::Text(r#"42["pong","ok"]"#.into()));
+}
+
+#[tokio::test]
+pub async fn drop_policy() {
+    const PORT: u16 = 2807;
+    let mut ws = connect(PORT, MalformedEventPolicy::Drop).await;
+
+    assert_ok!(ws.send(Message::Text(r#"42["ping",{]"#.into())).await);
+    assert_ok!(ws.send(Message::Text("42[]".into())).await);
+    assert_alive(&mut ws).await;
+}
+
+#[tokio::test]
+pub async fn send_error_policy() {
+    const PORT: u16 = 2808;
+    let mut ws = connect(PORT, MalformedEventPolicy::SendError).await;
+
+    let parse_error = |msg: Message| -> serde_json::Value {
+        let msg = msg.into_text().unwrap();
+        let data = msg.strip_prefix("42").expect(&msg);
+        serde_json::from_str(data).unwrap()
+    };
+
+    // Invalid json after the event name
+    assert_ok!(ws.send(Message::Text(r#"42["ping",{]"#.into())).await);
+    let msg = parse_error(assert_ok!(ws.next().await.unwrap()));
+    assert_eq!(msg[0], "error");
+    assert_eq!(msg[1]["event"], "ping");
+    assert!(msg[1]["reason"].as_str().unwrap().contains("json"), "{msg}");
+
+    // Missing event name
+    assert_ok!(ws.send(Message::Text("42[]".into())).await);
+    let msg = parse_error(assert_ok!(ws.next().await.unwrap()));
+    assert_eq!(
+        msg,
+        serde_json::json!(["error", { "event": null, "reason": "invalid event name" }])
+    );
+
+    assert_alive(&mut ws).await;
+}
+
+#[tokio::test]
+pub async fn close_policy() {
+    const PORT: u16 = 2809;
+    let mut ws = connect(PORT, MalformedEventPolicy::Close).await;
+
+    assert_ok!(ws.send(Message::Text(r#"42["ping",{]"#.into())).await);
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert!(msg.is_close(), "{msg:?}");
+}
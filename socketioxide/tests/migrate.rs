@@ -0,0 +1,121 @@
+//! Tests for the migration of the sockets to another server
+mod fixture;
+mod utils;
+
+use std::time::{Duration, Instant};
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, socket::DisconnectReason, SocketIo};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+const URL: &str = "wss://node-2.example.com";
+
+async fn create_migration_server(
+    port: u16,
+    delay: Duration,
+    jitter: Duration,
+) -> (SocketIo, mpsc::UnboundedReceiver<DisconnectReason>) {
+    let (svc, io) = SocketIo::builder()
+        .migration_event("reconnect_to")
+        .migration_delay(delay)
+        .migration_jitter(jitter)
+        .build_svc();
+    spawn_server(port, svc).await;
+    let (tx, rx) = mpsc::unbounded_channel();
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on_disconnect(move |reason: DisconnectReason| tx.send(reason).unwrap());
+        socket.on("migrate", |socket: SocketRef| {
+            socket.migrate(URL).unwrap();
+        });
+        socket.on("join", |socket: SocketRef| socket.join("drain").unwrap());
+    });
+    (io, rx)
+}
+
+/// Waits for the migration event and then for the disconnect packet,
+/// returning the time at which the socket was disconnected
+async fn expect_migration(mut ws: WebSocketStream<MaybeTlsStream<TcpStream>>) -> Instant {
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert_eq!(
+        msg,
+        Message::Text(format!("42[\"reconnect_to\",{{\"url\":\"{URL}\"}}]"))
+    );
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert_eq!(msg, Message::Text("41".into()));
+    Instant::now()
+}
+
+#[tokio::test]
+pub async fn migrate_socket() {
+    const PORT: u16 = 2801;
+    const DELAY: Duration = Duration::from_millis(50);
+    let (_io, mut rx) = create_migration_server(PORT, DELAY, Duration::ZERO).await;
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+
+    let start = Instant::now();
+    assert_ok!(ws.send(Message::Text("42[\"migrate\"]".into())).await);
+    let disconnected_at = expect_migration(ws).await;
+    assert!(disconnected_at - start >= DELAY);
+    assert_eq!(
+        rx.recv().await.unwrap(),
+        DisconnectReason::ServerShuttingDown
+    );
+}
+
+#[tokio::test]
+pub async fn migrate_operator_spreads_disconnections() {
+    const PORT: u16 = 2802;
+    const DELAY: Duration = Duration::from_millis(50);
+    const JITTER: Duration = Duration::from_millis(400);
+    const COUNT: usize = 4;
+    let (io, mut rx) = create_migration_server(PORT, DELAY, JITTER).await;
+
+    let mut clients = Vec::with_capacity(COUNT);
+    for _ in 0..COUNT {
+        let mut ws = create_ws_connection(PORT).await;
+        assert_ok!(ws.next().await.unwrap());
+        assert_ok!(ws.next().await.unwrap());
+        assert_ok!(ws.send(Message::Text("42[\"join\"]".into())).await);
+        clients.push(ws);
+    }
+    // A socket outside of the room is not migrated
+    let mut other = create_ws_connection(PORT).await;
+    assert_ok!(other.next().await.unwrap());
+    assert_ok!(other.next().await.unwrap());
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let start = Instant::now();
+    assert_ok!(io.within("drain").migrate(URL));
+    let times = futures::future::join_all(clients.into_iter().map(expect_migration)).await;
+
+    for _ in 0..COUNT {
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            DisconnectReason::ServerShuttingDown
+        );
+    }
+    let first = *times.iter().min().unwrap();
+    let last = *times.iter().max().unwrap();
+    assert!(first - start >= DELAY);
+    assert!(last - start <= DELAY + JITTER + Duration::from_millis(100));
+    // Each socket is disconnected in its own slot of the jitter
+    assert!(
+        last - first >= JITTER / 2,
+        "disconnections should be spread over the jitter: {:?}",
+        last - first
+    );
+
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), other.next())
+            .await
+            .is_err(),
+        "the socket outside of the room should not be migrated"
+    );
+    assert!(rx.try_recv().is_err());
+}
@@ -0,0 +1,72 @@
+//! Tests for the limit of namespaces per connection set with `max_namespaces_per_socket`
+mod fixture;
+mod utils;
+
+use std::time::Duration;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{handler::NamespaceHandler, SocketIo};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn create_server(port: u16) -> SocketIo {
+    let (svc, io) = SocketIo::builder()
+        .max_namespaces_per_socket(3)
+        .max_violations(3)
+        .build_svc();
+    spawn_server(port, svc).await;
+    io.ns("/", || {});
+    // Any `/ns-*` namespace is created on demand
+    io.dyn_ns(|path| {
+        path.starts_with("/ns-")
+            .then(|| NamespaceHandler::new(|| {}))
+    });
+    io
+}
+
+/// Connects the client to the namespace and returns the answer of the server
+async fn connect_ns(ws: &mut Ws, ns: &str) -> String {
+    assert_ok!(ws.send(Message::Text(format!("40{ns},"))).await);
+    assert_ok!(ws.next().await.unwrap()).into_text().unwrap()
+}
+
+#[tokio::test]
+pub async fn max_namespaces_per_socket() {
+    const PORT: u16 = 2830;
+    let io = create_server(PORT).await;
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet of the main namespace
+
+    // The main namespace is the first of the 3 allowed namespaces
+    for ns in ["/ns-1", "/ns-2"] {
+        let msg = connect_ns(&mut ws, ns).await;
+        assert!(msg.starts_with(&format!("40{ns},{{\"sid\":")), "{msg}");
+    }
+    let msg = connect_ns(&mut ws, "/ns-3").await;
+    assert_eq!(msg, r#"44/ns-3,{"message":"Too many namespaces"}"#);
+    // The rejected namespace is never created
+    assert!(io.of("/ns-3").is_none());
+
+    // Once the client leaves a namespace, it can join another one
+    assert_ok!(ws.send(Message::Text("41/ns-1,".into())).await);
+    let msg = connect_ns(&mut ws, "/ns-3").await;
+    assert!(msg.starts_with("40/ns-3,{\"sid\":"), "{msg}");
+
+    // Repeated attempts beyond the limit close the connection
+    let msg = connect_ns(&mut ws, "/ns-4").await;
+    assert_eq!(msg, r#"44/ns-4,{"message":"Too many namespaces"}"#);
+    assert_ok!(ws.send(Message::Text("40/ns-5,".into())).await);
+    let closed = tokio::time::timeout(Duration::from_millis(200), async {
+        loop {
+            match ws.next().await {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => continue,
+            }
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "the connection should be closed");
+}
@@ -0,0 +1,90 @@
+//! Tests for the connection limit of a namespace set with [`SocketIo::set_max_connections`]
+mod fixture;
+mod utils;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, SocketIo};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn create_server(port: u16, max: usize) -> SocketIo {
+    let (svc, io) = SocketIo::new_svc();
+    spawn_server(port, svc).await;
+    io.ns("/", || {});
+    io.ns("/video", |_: SocketRef| {});
+    assert!(io.set_max_connections("/video", Some(max)));
+    io
+}
+
+/// Connects a client to the main namespace
+async fn connect(port: u16) -> Ws {
+    let mut ws = create_ws_connection(port).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet of the main namespace
+    ws
+}
+
+/// Connects the client to the limited namespace and returns the answer of the server
+async fn connect_video(ws: &mut Ws) -> String {
+    assert_ok!(ws.send(Message::Text("40/video,".into())).await);
+    assert_ok!(ws.next().await.unwrap()).into_text().unwrap()
+}
+
+const FULL: &str = r#"44/video,{"message":"Namespace is full"}"#;
+
+#[tokio::test]
+pub async fn limit_is_hit_and_recovered() {
+    const PORT: u16 = 2819;
+    let io = create_server(PORT, 2).await;
+
+    let mut clients = Vec::new();
+    for _ in 0..2 {
+        let mut ws = connect(PORT).await;
+        let msg = connect_video(&mut ws).await;
+        assert!(msg.starts_with("40/video,{\"sid\":"), "{msg}");
+        clients.push(ws);
+    }
+
+    let mut ws = connect(PORT).await;
+    assert_eq!(connect_video(&mut ws).await, FULL);
+    assert_eq!(io.of("/video").unwrap().sockets().unwrap().len(), 2);
+    // The other namespaces are still open
+    assert_eq!(io.sockets().unwrap().len(), 3);
+
+    // Once a socket leaves the namespace, a new one can join it
+    assert_ok!(clients[0].send(Message::Text("41/video,".into())).await);
+    let msg = connect_video(&mut ws).await;
+    assert!(msg.starts_with("40/video,{\"sid\":"), "{msg}");
+    assert_eq!(connect_video(&mut clients[0]).await, FULL);
+
+    // Without a limit, every socket can join
+    io.set_max_connections("/video", None);
+    let msg = connect_video(&mut clients[0]).await;
+    assert!(msg.starts_with("40/video,{\"sid\":"), "{msg}");
+}
+
+#[tokio::test]
+pub async fn concurrent_connections_do_not_exceed_the_limit() {
+    const PORT: u16 = 2820;
+    let io = create_server(PORT, 5).await;
+
+    let mut clients = Vec::new();
+    for _ in 0..20 {
+        clients.push(connect(PORT).await);
+    }
+    for ws in &mut clients {
+        assert_ok!(ws.send(Message::Text("40/video,".into())).await);
+    }
+    let mut connected = 0;
+    for ws in &mut clients {
+        let msg = assert_ok!(ws.next().await.unwrap()).into_text().unwrap();
+        if msg != FULL {
+            assert!(msg.starts_with("40/video,{\"sid\":"), "{msg}");
+            connected += 1;
+        }
+    }
+    assert_eq!(connected, 5);
+    assert_eq!(io.of("/video").unwrap().sockets().unwrap().len(), 5);
+}
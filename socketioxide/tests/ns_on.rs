@@ -0,0 +1,74 @@
+//! Tests for the message handlers registered on a namespace with `SocketIo::ns_on`
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use socketioxide::extract::{Data, SocketRef};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+async fn recv(rx: &mut mpsc::Receiver<String>) -> String {
+    let msg = tokio::time::timeout(Duration::from_millis(100), rx.recv()).await;
+    msg.unwrap().unwrap()
+}
+
+#[tokio::test]
+pub async fn ns_on_routing() {
+    const PORT: u16 = 2829;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<String>(100);
+
+    let tx1 = tx.clone();
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx1.clone();
+        socket.on("override", move |Data::<String>(data)| {
+            tx.try_send(format!("socket:override:{data}")).unwrap();
+        });
+        let tx = tx1.clone();
+        socket.on_raw(move |_, event, _| {
+            tx.try_send(format!("fallback:{}", event.event)).unwrap();
+        });
+    });
+    assert!(!io.ns_on("/unknown", "chat", || {}));
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap()); // engine.io open packet
+    assert_ok!(srx.next().await.unwrap()); // socket.io connect packet
+
+    // Registered after the socket is connected
+    let tx1 = tx.clone();
+    assert!(io.ns_on("/", "chat", move |Data::<String>(data)| {
+        tx1.try_send(format!("ns:chat:{data}")).unwrap();
+    }));
+    let tx1 = tx.clone();
+    assert!(io.ns_on("/", "override", move |Data::<String>(data)| {
+        tx1.try_send(format!("ns:override:{data}")).unwrap();
+    }));
+
+    for msg in [
+        r#"42["chat","foo"]"#,
+        r#"42["override","bar"]"#,
+        r#"42["unknown",1]"#,
+    ] {
+        stx.send(Message::Text(msg.into())).await.unwrap();
+    }
+    assert_eq!(recv(&mut rx).await, "ns:chat:foo");
+    // The handler of the socket takes precedence
+    assert_eq!(recv(&mut rx).await, "socket:override:bar");
+    assert_eq!(recv(&mut rx).await, "fallback:unknown");
+
+    // A new handler replaces the previous one
+    let tx1 = tx.clone();
+    io.ns_on("/", "chat", move |Data::<String>(data)| {
+        tx1.try_send(format!("ns:chat2:{data}")).unwrap();
+    });
+    stx.send(Message::Text(r#"42["chat","baz"]"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut rx).await, "ns:chat2:baz");
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    rx.try_recv().unwrap_err();
+}
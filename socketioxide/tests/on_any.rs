@@ -0,0 +1,51 @@
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use socketioxide::extract::{Data, SocketRef};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+async fn recv(rx: &mut mpsc::Receiver<String>) -> String {
+    let msg = tokio::time::timeout(Duration::from_millis(100), rx.recv()).await;
+    msg.unwrap().unwrap()
+}
+
+#[tokio::test]
+pub async fn on_any_called_before_specific_handler() {
+    const PORT: u16 = 2700;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<String>(100);
+
+    io.ns("/", move |socket: SocketRef| {
+        let tx1 = tx.clone();
+        socket.on_any(move |event, args, _| {
+            tx1.try_send(format!("any:{event}:{}", serde_json::json!(args)))
+                .unwrap();
+        });
+        let tx1 = tx.clone();
+        socket.on("test", move |Data::<String>(data)| {
+            tx1.try_send(format!("test:{data}")).unwrap();
+        });
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap()); // engine.io open packet
+    assert_ok!(srx.next().await.unwrap()); // socket.io connect packet
+
+    stx.send(Message::Text(r#"42["test","foo"]"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut rx).await, r#"any:test:["foo"]"#);
+    assert_eq!(recv(&mut rx).await, "test:foo");
+
+    // Events without a specific handler are also received
+    stx.send(Message::Text(r#"42["other",1,2]"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut rx).await, "any:other:[1,2]");
+    rx.try_recv().unwrap_err();
+}
@@ -0,0 +1,79 @@
+//! Tests for pausing the acceptance of new connections
+mod fixture;
+mod utils;
+
+use std::{collections::VecDeque, time::Duration};
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use http::{Request, StatusCode};
+use http_body_util::Empty;
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use socketioxide::{
+    extract::{Data, SocketRef},
+    ServerState, SocketIo,
+};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+async fn polling_handshake(port: u16) -> http::Response<hyper::body::Incoming> {
+    let req = Request::get(format!(
+        "http://127.0.0.1:{port}/socket.io/?EIO=4&transport=polling"
+    ))
+    .body(Empty::<VecDeque<u8>>::new())
+    .unwrap();
+    Client::builder(TokioExecutor::new())
+        .build_http()
+        .request(req)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+pub async fn pause_accepting() {
+    const PORT: u16 = 2796;
+    let (svc, io) = SocketIo::builder()
+        .ping_interval(Duration::from_millis(300))
+        .ping_timeout(Duration::from_millis(200))
+        .retry_after(Duration::from_secs(7))
+        .build_svc();
+    spawn_server(PORT, svc).await;
+    io.ns("/", |socket: SocketRef| {
+        socket.on("echo", |socket: SocketRef, Data::<String>(data)| {
+            socket.emit("echo", data).unwrap();
+        });
+    });
+    assert_eq!(io.server_state(), ServerState::Accepting);
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+
+    io.pause_accepting();
+    assert_eq!(io.server_state(), ServerState::Paused);
+
+    let res = polling_handshake(PORT).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()["Retry-After"], "7");
+    let url = format!("ws://127.0.0.1:{PORT}/socket.io/?EIO=4&transport=websocket");
+    match tokio_tungstenite::connect_async(&url).await {
+        Err(WsError::Http(res)) => {
+            assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(res.headers()["Retry-After"], "7");
+        }
+        res => panic!("unexpected upgrade result: {res:?}"),
+    }
+
+    // The existing socket keeps exchanging events
+    ws.send(Message::Text(r#"42["echo","still here"]"#.to_string()))
+        .await
+        .unwrap();
+    let msg = assert_ok!(ws.next().await.unwrap()).into_text().unwrap();
+    assert_eq!(msg, r#"42["echo","still here"]"#);
+
+    io.resume_accepting();
+    assert_eq!(io.server_state(), ServerState::Accepting);
+    assert_eq!(polling_handshake(PORT).await.status(), StatusCode::OK);
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+}
@@ -0,0 +1,62 @@
+//! Tests for the presence of the clients
+mod fixture;
+mod utils;
+
+use std::time::Duration;
+
+use engineioxide::sid::Sid;
+use fixture::spawn_server;
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, SocketIo};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+pub async fn stale_and_late_pong() {
+    const PORT: u16 = 2797;
+    const THRESHOLD: Duration = Duration::from_secs(5);
+    let (svc, io) = SocketIo::builder()
+        .ping_interval(Duration::from_secs(3600))
+        .ping_timeout(Duration::from_secs(3600))
+        .build_svc();
+    spawn_server(PORT, svc).await;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    io.ns("/", move |socket: SocketRef| tx.send(socket.id).unwrap());
+
+    let mut ws = fixture::create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+    let sid = rx.recv().await.unwrap();
+
+    tokio::time::pause();
+    let presence = io.presence();
+    let (stale_tx, mut stale_rx) = mpsc::unbounded_channel();
+    presence.on_stale(THRESHOLD, move |sid| stale_tx.send(sid).unwrap());
+    assert!(presence.is_active(sid, THRESHOLD));
+    assert_eq!(presence.last_seen().len(), 1);
+
+    tokio::time::advance(Duration::from_secs(3)).await;
+    assert!(stale_rx.try_recv().is_err());
+
+    // The client stays silent until the stale threshold is exceeded
+    assert_eq!(stale_rx.recv().await.unwrap(), sid);
+    assert!(!presence.is_active(sid, THRESHOLD));
+    assert!(presence.last_seen()[&sid].elapsed() > THRESHOLD);
+
+    // A late pong from the client makes it active again.
+    // Yielding doesn't advance the paused clock, unlike sleeping or waiting for the network
+    ws.send(Message::Text("3".into())).await.unwrap();
+    let start = std::time::Instant::now();
+    while !presence.is_active(sid, THRESHOLD) {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "pong not received"
+        );
+        tokio::task::yield_now().await;
+    }
+    assert!(stale_rx.try_recv().is_err());
+
+    // It becomes stale again if it stays silent
+    assert_eq!(stale_rx.recv().await.unwrap(), sid);
+    assert!(!presence.is_active(Sid::new(), THRESHOLD));
+}
@@ -0,0 +1,100 @@
+mod fixture;
+mod utils;
+
+use std::time::Duration;
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use socketioxide::extract::SocketRef;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn next_msg(ws: &mut Ws) -> Message {
+    let msg = tokio::time::timeout(Duration::from_millis(200), ws.next()).await;
+    msg.unwrap().unwrap().unwrap()
+}
+
+async fn connect_ns(port: u16, ns: &str) -> Ws {
+    let mut ws = create_ws_connection(port).await;
+    next_msg(&mut ws).await; // engine.io open packet
+    next_msg(&mut ws).await; // default namespace connect packet
+    ws.send(Message::Text(format!("40{ns},"))).await.unwrap();
+    let msg = next_msg(&mut ws).await;
+    assert!(matches!(msg, Message::Text(s) if s.starts_with(&format!("40{ns},"))));
+    ws
+}
+
+#[tokio::test]
+pub async fn relay_raw_events() {
+    const PORT: u16 = 2710;
+    let io = create_server(PORT).await;
+    io.ns("/", || {});
+    io.ns("/b", || {});
+    let io2 = io.clone();
+    io.ns("/a", move |socket: SocketRef| {
+        let io = io2.clone();
+        socket.on("handled", || {});
+        socket.on_raw(move |_, event, ack| {
+            assert_eq!(event.ns, "/a");
+            let b = io.of("/b").unwrap().bin(event.bin);
+            if event.ack_id.is_none() {
+                b.emit(event.event, event.data).unwrap();
+                return;
+            }
+            let stream = b.emit_with_ack::<Value>(event.event, event.data).unwrap();
+            tokio::spawn(async move {
+                let (_, res) = stream.boxed().next().await.unwrap();
+                let res = res.unwrap();
+                ack.bin(res.binary).send(res.data).unwrap();
+            });
+        });
+    });
+
+    let mut a = connect_ns(PORT, "/a").await;
+    let mut b = connect_ns(PORT, "/b").await;
+
+    // Events with a specific handler are not relayed
+    a.send(Message::Text(r#"42/a,["handled",1]"#.into()))
+        .await
+        .unwrap();
+    a.send(Message::Text(r#"42/a,["text",1,"two"]"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(
+        next_msg(&mut b).await,
+        Message::Text(r#"42/b,["text",1,"two"]"#.into())
+    );
+
+    // Binary event with an ack
+    a.send(Message::Text(
+        r#"451-/a,7["bin","data",{"_placeholder":true,"num":0}]"#.into(),
+    ))
+    .await
+    .unwrap();
+    a.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+
+    let msg = next_msg(&mut b).await.into_text().unwrap();
+    let (ack_id, data) = msg
+        .strip_prefix("451-/b,")
+        .unwrap()
+        .split_once('[')
+        .unwrap();
+    assert_eq!(data, r#""bin","data",{"_placeholder":true,"num":0}]"#);
+    assert_eq!(next_msg(&mut b).await, Message::Binary(vec![1, 2, 3]));
+
+    b.send(Message::Text(format!(
+        r#"461-/b,{ack_id}["ok",{{"_placeholder":true,"num":0}}]"#
+    )))
+    .await
+    .unwrap();
+    b.send(Message::Binary(vec![4, 5])).await.unwrap();
+
+    assert_eq!(
+        next_msg(&mut a).await,
+        Message::Text(r#"461-/a,7["ok",{"_placeholder":true,"num":0}]"#.into())
+    );
+    assert_eq!(next_msg(&mut a).await, Message::Binary(vec![4, 5]));
+}
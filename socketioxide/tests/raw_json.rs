@@ -0,0 +1,56 @@
+//! Tests for the emission of pre-serialized JSON data
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use socketioxide::{
+    extract::{AckSender, SocketRef},
+    packet::RawJson,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+const DOC: &str = r#"{"items":[1,2,3],"title":"hello"}"#;
+
+#[tokio::test]
+pub async fn emit_raw_json() {
+    const PORT: u16 = 2760;
+    let io = create_server(PORT).await;
+    io.ns("/", |socket: SocketRef| {
+        socket.join("room").unwrap();
+        let raw = RawJson::new(DOC).unwrap();
+        socket.emit_raw_json("doc", raw.clone()).unwrap();
+        socket.to("room").emit_raw_json("doc", raw.clone()).unwrap();
+        socket
+            .within("room")
+            .map_payload(|_, data| json!([data, "mapped"]))
+            .emit_raw_json("doc", raw)
+            .unwrap();
+        socket.on("ack", |ack: AckSender| {
+            ack.send_raw_json(RawJson::new(DOC).unwrap()).unwrap();
+        });
+    });
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap());
+    assert_ok!(srx.next().await.unwrap());
+
+    // The same packet as with the parsed document is sent
+    let expected = format!(r#"42["doc",{DOC}]"#);
+    let value: Value = serde_json::from_str(DOC).unwrap();
+    let parsed: String = socketioxide::packet::Packet::event("/", "doc", value).into();
+    assert_eq!(format!("4{parsed}"), expected);
+
+    let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+    assert_eq!(msg, expected);
+
+    // The socket is excluded from the `to` broadcast but not from the `within` one
+    let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+    assert_eq!(msg, format!(r#"42["doc",{DOC},"mapped"]"#));
+
+    stx.send(Message::Text(r#"421["ack"]"#.into()))
+        .await
+        .unwrap();
+    let msg = assert_ok!(srx.next().await.unwrap()).into_text().unwrap();
+    assert_eq!(msg, format!("431[{DOC}]"));
+}
@@ -0,0 +1,73 @@
+//! Tests for a server spawning its tasks on an explicitly provided runtime
+mod fixture;
+mod utils;
+
+use std::time::Duration;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, SocketIo};
+use tokio_tungstenite::tungstenite::Message;
+
+#[test]
+pub fn server_built_outside_runtime() {
+    const PORT: u16 = 2730;
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+    let handle = rt.handle().clone();
+
+    // The service is built on a plain thread, outside of any runtime context
+    let (svc, io) = std::thread::spawn(move || {
+        SocketIo::builder()
+            .runtime(handle)
+            .ping_interval(Duration::from_millis(50))
+            .build_svc()
+    })
+    .join()
+    .unwrap();
+    io.ns("/", |socket: SocketRef| {
+        socket.on("ping", |socket: SocketRef| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            socket.emit("pong", "ok").ok();
+        });
+    });
+
+    // The http connections are served by a runtime without timers,
+    // the server tasks would panic if they were spawned on it
+    let (bound_tx, bound_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let server_rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        server_rt.block_on(async move {
+            spawn_server(PORT, svc).await;
+            bound_tx.send(()).unwrap();
+            std::future::pending::<()>().await
+        });
+    });
+    bound_rx.recv().unwrap();
+
+    rt.block_on(async move {
+        let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+        assert_ok!(srx.next().await.unwrap()); // engine.io open packet
+        assert_ok!(srx.next().await.unwrap()); // socket.io connect packet
+
+        stx.send(Message::Text(r#"42["ping"]"#.into()))
+            .await
+            .unwrap();
+        let mut msgs = Vec::new();
+        tokio::time::timeout(Duration::from_millis(500), async {
+            while msgs.len() < 2 {
+                msgs.push(srx.next().await.unwrap().unwrap());
+            }
+        })
+        .await
+        .expect("timeout waiting for the pong event and the heartbeat");
+        assert!(msgs.contains(&Message::Text(r#"42["pong","ok"]"#.into())));
+        assert!(msgs.contains(&Message::Text("2".into())));
+    });
+}
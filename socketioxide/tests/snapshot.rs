@@ -0,0 +1,121 @@
+//! Tests for the snapshots of the server state
+mod fixture;
+mod utils;
+
+use std::time::Duration;
+
+use fixture::spawn_server;
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use socketioxide::{extract::SocketRef, SocketIo};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+async fn connect(port: u16, ns: &str, auth: &str) -> WebSocketStream<MaybeTlsStream<TcpStream>> {
+    let mut ws = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{port}/socket.io/?EIO=4&transport=websocket"
+    ))
+    .await
+    .unwrap()
+    .0;
+    let ns = if ns == "/" {
+        String::new()
+    } else {
+        format!("{ns},")
+    };
+    ws.send(Message::Text(format!("40{ns}{auth}")))
+        .await
+        .unwrap();
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+    ws
+}
+
+async fn create_server(port: u16, snapshot_auth: bool) -> (SocketIo, mpsc::Receiver<SocketRef>) {
+    let (svc, io) = SocketIo::builder()
+        .ping_interval(Duration::from_millis(300))
+        .ping_timeout(Duration::from_millis(200))
+        .snapshot_auth(snapshot_auth)
+        .build_svc();
+    spawn_server(port, svc).await;
+
+    let (tx, rx) = mpsc::channel(4);
+    let tx_ = tx.clone();
+    io.ns("/", move |socket: SocketRef| {
+        socket.join("lobby").unwrap();
+        tx_.try_send(socket).unwrap();
+    });
+    io.ns("/admin", move |socket: SocketRef| {
+        tx.try_send(socket).unwrap();
+    });
+    (io, rx)
+}
+
+#[tokio::test]
+pub async fn snapshot_structure() {
+    const PORT: u16 = 2793;
+    let (io, mut rx) = create_server(PORT, false).await;
+
+    let _ws1 = connect(PORT, "/", r#"{"token":"secret"}"#).await;
+    let s1 = rx.recv().await.unwrap();
+    let _ws2 = connect(PORT, "/", "").await;
+    let s2 = rx.recv().await.unwrap();
+    let _ws3 = connect(PORT, "/admin", "").await;
+    let s3 = rx.recv().await.unwrap();
+    s2.join("game").unwrap();
+
+    let snapshot = serde_json::to_value(io.snapshot().unwrap()).unwrap();
+    let namespaces = snapshot["namespaces"].as_array().unwrap();
+    assert_eq!(namespaces.len(), 2);
+    assert_eq!(namespaces[0]["name"], "/");
+    assert_eq!(namespaces[1]["name"], "/admin");
+
+    let mut ids = [s1.id, s2.id];
+    ids.sort();
+    let [first, second] = ids.map(|id| id.to_string());
+    assert_eq!(
+        namespaces[0]["rooms"],
+        json!({ "game": [s2.id.to_string()], "lobby": [first, second] })
+    );
+    assert_eq!(namespaces[1]["rooms"], json!({}));
+
+    let sockets = namespaces[0]["sockets"].as_array().unwrap();
+    assert_eq!(sockets.len(), 2);
+    let socket = sockets
+        .iter()
+        .find(|s| s["id"] == s2.id.to_string())
+        .unwrap();
+    let mut rooms = socket["rooms"].as_array().unwrap().clone();
+    rooms.sort_by_key(|r| r.to_string());
+    assert_eq!(rooms, [json!("game"), json!("lobby")]);
+    assert_eq!(socket["transport"], "websocket");
+    assert_eq!(socket["pending_acks"], 0);
+    assert!(socket["sid"].is_string());
+    assert!(socket["remote_addr"].is_null());
+    assert!(socket["connected_at"].as_u64().unwrap() > 0);
+    // The auth payload is not captured by default
+    assert!(sockets.iter().all(|s| s.get("auth").is_none()));
+
+    let sockets = namespaces[1]["sockets"].as_array().unwrap();
+    assert_eq!(sockets.len(), 1);
+    assert_eq!(sockets[0]["id"], s3.id.to_string());
+    assert_eq!(sockets[0]["rooms"], json!([]));
+
+    // Pending acks are counted
+    let _ack = s3.emit_with_ack::<_, ()>("ask", ()).unwrap();
+    let snapshot = serde_json::to_value(io.snapshot().unwrap()).unwrap();
+    assert_eq!(snapshot["namespaces"][1]["sockets"][0]["pending_acks"], 1);
+}
+
+#[tokio::test]
+pub async fn snapshot_auth() {
+    const PORT: u16 = 2794;
+    let (io, mut rx) = create_server(PORT, true).await;
+
+    let _ws = connect(PORT, "/", r#"{"token":"secret"}"#).await;
+    rx.recv().await.unwrap();
+
+    let snapshot = serde_json::to_value(io.snapshot().unwrap()).unwrap();
+    let socket = &snapshot["namespaces"][0]["sockets"][0];
+    assert_eq!(socket["auth"], json!({ "token": "secret" }));
+}
@@ -0,0 +1,62 @@
+//! Tests for the tasks spawned with [`SocketRef::spawn`]
+mod fixture;
+mod utils;
+
+use std::time::Duration;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, SendError, SocketError, SocketIo};
+use tokio::sync::{mpsc, Notify};
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+pub async fn spawned_task_emits_to_its_socket() {
+    const PORT: u16 = 2810;
+    let (svc, io) = SocketIo::new_svc();
+    spawn_server(PORT, svc).await;
+    io.ns("/", |socket: SocketRef| {
+        socket.spawn(|socket| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            socket.emit("hello", "world").unwrap();
+        });
+    });
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert_eq!(msg, Message::Text(r#"42["hello","world"]"#.into()));
+}
+
+#[tokio::test]
+pub async fn spawned_task_emits_fail_after_disconnect() {
+    const PORT: u16 = 2811;
+    let (svc, io) = SocketIo::new_svc();
+    spawn_server(PORT, svc).await;
+    let (tx, mut rx) = mpsc::channel(1);
+    io.ns("/", move |socket: SocketRef| {
+        let disconnected = std::sync::Arc::new(Notify::new());
+        let notify = disconnected.clone();
+        socket.on_disconnect(move || notify.notify_one());
+        let tx = tx.clone();
+        socket.spawn(|socket| async move {
+            disconnected.notified().await;
+            tx.send(socket.emit("hello", "world")).await.unwrap();
+        });
+    });
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.send(Message::Text("41".into())).await); // socket.io disconnect packet
+
+    let res = tokio::time::timeout(Duration::from_millis(500), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        matches!(res, Err(SendError::Socket(SocketError::Closed("world")))),
+        "{res:?}"
+    );
+}
@@ -0,0 +1,75 @@
+//! Tests for the emits skipping the stale sockets
+mod fixture;
+mod utils;
+
+use std::time::Duration;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, EmitError, SocketIo};
+use tokio::{net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+const MAX_STALENESS: Duration = Duration::from_millis(150);
+
+/// Returns the next text message which is not a ping
+async fn next_msg(ws: &mut WebSocketStream<MaybeTlsStream<TcpStream>>) -> String {
+    loop {
+        match assert_ok!(ws.next().await.unwrap()) {
+            Message::Text(msg) if msg == "2" => continue,
+            Message::Text(msg) => return msg,
+            msg => panic!("unexpected message {msg:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn stale_socket_is_skipped() {
+    const PORT: u16 = 2804;
+    let (svc, io) = SocketIo::builder()
+        .ping_interval(Duration::from_millis(100))
+        .ping_timeout(Duration::from_secs(2))
+        .build_svc();
+    spawn_server(PORT, svc).await;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    io.ns("/", move |socket: SocketRef| {
+        socket.join("room").unwrap();
+        tx.send(socket).unwrap();
+    });
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet
+    let socket = rx.recv().await.unwrap();
+
+    // The first ping is answered
+    assert_eq!(
+        assert_ok!(ws.next().await.unwrap()),
+        Message::Text("2".into())
+    );
+    assert_ok!(ws.send(Message::Text("3".into())).await);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(!socket.is_stale(MAX_STALENESS));
+    assert_ok!(socket.emit_if_alive("direct", 1, MAX_STALENESS));
+    assert_eq!(next_msg(&mut ws).await, r#"42["direct",1]"#);
+
+    // The following pings are not answered
+    tokio::time::sleep(MAX_STALENESS + Duration::from_millis(50)).await;
+    assert!(socket.is_stale(MAX_STALENESS));
+    assert!(matches!(
+        socket.emit_if_alive("direct", 2, MAX_STALENESS),
+        Err(EmitError::Stale)
+    ));
+    assert_ok!(io.to("room").skip_stale(MAX_STALENESS).emit("room", 2));
+    assert_ok!(io.to("room").emit("room", "not skipped"));
+    assert_eq!(next_msg(&mut ws).await, r#"42["room","not skipped"]"#);
+
+    // A fresh pong makes the socket alive again
+    assert_ok!(ws.send(Message::Text("3".into())).await);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(!socket.is_stale(MAX_STALENESS));
+    assert_ok!(socket.emit_if_alive("direct", 3, MAX_STALENESS));
+    assert_ok!(io.to("room").skip_stale(MAX_STALENESS).emit("room", 3));
+    assert_eq!(next_msg(&mut ws).await, r#"42["direct",3]"#);
+    assert_eq!(next_msg(&mut ws).await, r#"42["room",3]"#);
+}
@@ -0,0 +1,83 @@
+//! Tests for the global state and the socket state injected by a middleware in the handlers
+mod fixture;
+mod utils;
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use socketioxide::{
+    extract::{AckSender, Extension, SocketRef, State},
+    handler::{ConnectHandler, ErrorPolicy},
+    SocketIo,
+};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+struct AppState {
+    name: &'static str,
+}
+
+#[derive(Clone)]
+struct User(String);
+
+#[derive(Clone)]
+struct Missing;
+
+fn auth(socket: SocketRef) -> Result<(), &'static str> {
+    socket.extensions.insert(User("alice".into()));
+    Ok(())
+}
+
+async fn recv(ws: &mut Ws) -> String {
+    assert_ok!(ws.next().await.unwrap()).into_text().unwrap()
+}
+
+/// Sends an event and returns the first argument of its ack
+async fn ack(ws: &mut Ws, msg: &str) -> Value {
+    assert_ok!(ws.send(Message::Text(msg.to_string())).await);
+    let msg = recv(ws).await;
+    let args = &msg[msg.find('[').unwrap()..];
+    serde_json::from_str::<Value>(args).unwrap()[0].clone()
+}
+
+#[tokio::test]
+pub async fn state_injection() {
+    const PORT: u16 = 2824;
+    // The global state is static so all the cases share the same server
+    let (svc, io) = SocketIo::builder()
+        .with_state(AppState { name: "app" })
+        .build_svc();
+    spawn_server(PORT, svc).await;
+    io.ns(
+        "/",
+        (|s: SocketRef, Extension(user): Extension<User>| {
+            s.emit("welcome", user.0).ok();
+            s.on(
+                "both",
+                |State(app): State<AppState>, Extension(user): Extension<User>, ack: AckSender| {
+                    ack.send(format!("{} {}", app.name, user.0)).ok();
+                },
+            );
+            s.on("missing_global", |_: State<Missing>| {});
+            s.on("missing_socket", |_: Extension<Missing>| {});
+        })
+        .with(auth),
+    );
+    io.set_error_policy("/", ErrorPolicy::AckError);
+
+    let mut ws = create_ws_connection(PORT).await;
+    recv(&mut ws).await; // engine.io open packet
+    recv(&mut ws).await; // socket.io connect packet
+    assert_eq!(recv(&mut ws).await, r#"42["welcome","alice"]"#);
+
+    assert_eq!(ack(&mut ws, r#"421["both"]"#).await, "app alice");
+
+    let err = ack(&mut ws, r#"422["missing_global"]"#).await;
+    assert_eq!(err["code"], "invalid_data");
+    assert_eq!(err["error"], "State not found");
+
+    let err = ack(&mut ws, r#"423["missing_socket"]"#).await;
+    assert_eq!(err["code"], "invalid_data");
+    assert_eq!(err["error"], "missing state state_injection::Missing");
+}
@@ -0,0 +1,104 @@
+//! Tests for the emits to closed sockets and the [`SocketIoBuilder::strict_emit`] option
+mod fixture;
+mod utils;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use fixture::{create_ws_connection, spawn_server};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, SendError, SocketError, SocketIo};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+pub async fn emit_fails_right_after_disconnect() {
+    const PORT: u16 = 2816;
+    let (svc, io) = SocketIo::new_svc();
+    spawn_server(PORT, svc).await;
+    let (tx, mut rx) = mpsc::channel(1);
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on_disconnect(move |socket: SocketRef| tx.try_send(socket).unwrap());
+    });
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet
+    assert_ok!(ws.close(None).await);
+
+    let socket = tokio::time::timeout(Duration::from_millis(500), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let res = socket.emit("hello", "world");
+    assert!(
+        matches!(res, Err(SendError::Socket(SocketError::Closed("world")))),
+        "{res:?}"
+    );
+}
+
+/// Connects a client to the "/" and "/other" namespaces and closes its connection.
+/// The first disconnect handler emits to the socket of the other namespace, which is still registered
+/// while its connection is already closed.
+///
+/// Returns if this emit failed with a closed socket error and if the other socket is still connected after it.
+async fn emit_to_closed_socket(port: u16, strict_emit: bool) -> (bool, bool) {
+    let (svc, io) = SocketIo::builder().strict_emit(strict_emit).build_svc();
+    spawn_server(port, svc).await;
+    let (tx, mut rx) = mpsc::channel(2);
+    let main: Arc<Mutex<Option<SocketRef>>> = Default::default();
+    let other: Arc<Mutex<Option<SocketRef>>> = Default::default();
+    let register = |slot: Arc<Mutex<Option<SocketRef>>>,
+                    peer: Arc<Mutex<Option<SocketRef>>>,
+                    tx: mpsc::Sender<(bool, bool)>| {
+        move |socket: SocketRef| {
+            slot.lock().unwrap().replace(socket.clone());
+            let peer = peer.clone();
+            let tx = tx.clone();
+            socket.on_disconnect(move || {
+                let Some(peer) = peer.lock().unwrap().take() else {
+                    return;
+                };
+                if peer.connected() {
+                    let res = peer.emit("bye", ());
+                    let closed = matches!(res, Err(SendError::Socket(SocketError::Closed(()))));
+                    tx.try_send((closed, peer.connected())).unwrap();
+                }
+            });
+        }
+    };
+    io.ns("/", register(main.clone(), other.clone(), tx.clone()));
+    io.ns("/other", register(other.clone(), main.clone(), tx));
+
+    let mut ws = create_ws_connection(port).await;
+    assert_ok!(ws.next().await.unwrap()); // engine.io open packet
+    assert_ok!(ws.next().await.unwrap()); // socket.io connect packet
+    assert_ok!(ws.send(Message::Text("40/other,".into())).await);
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.close(None).await);
+
+    let res = tokio::time::timeout(Duration::from_millis(500), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    // Only the first disconnect handler finds a connected peer
+    rx.try_recv().unwrap_err();
+    res
+}
+
+#[tokio::test]
+pub async fn emit_to_closed_socket_without_strict_emit() {
+    let (closed, still_connected) = emit_to_closed_socket(2817, false).await;
+    assert!(closed);
+    assert!(still_connected);
+}
+
+#[tokio::test]
+pub async fn emit_to_closed_socket_with_strict_emit() {
+    let (closed, still_connected) = emit_to_closed_socket(2818, true).await;
+    assert!(closed);
+    assert!(!still_connected);
+}
@@ -0,0 +1,66 @@
+//! Tests for the teardown of the server when its service is dropped
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use engineioxide::service::NotFoundService;
+use http::{Method, Request};
+use http_body_util::{BodyExt, Full};
+use socketioxide::{extract::SocketRef, service::SocketIoService, SocketIo};
+use tower::Service;
+
+/// Sends a polling request to the service and returns the body of the response
+async fn send_req(
+    svc: &mut SocketIoService<NotFoundService>,
+    method: Method,
+    params: &str,
+    body: &str,
+) -> String {
+    let req = Request::builder()
+        .method(method)
+        .uri(format!("/socket.io/?EIO=4&transport=polling{params}"))
+        .body(Full::new(VecDeque::from(body.as_bytes().to_vec())))
+        .unwrap();
+    let res = svc.call(req).await.unwrap();
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+pub async fn drop_service_without_leaking_tasks() {
+    let metrics = tokio::runtime::Handle::current().metrics();
+    for _ in 0..100 {
+        let (mut svc, io) = SocketIo::builder()
+            .ping_interval(Duration::from_millis(10))
+            .ping_timeout(Duration::from_millis(10))
+            .build_svc();
+        let token = Arc::new(());
+        let token_ = token.clone();
+        io.ns("/", move |_: SocketRef| {
+            let _ = &token_;
+        });
+
+        let mut sids = Vec::new();
+        for _ in 0..2 {
+            let open = send_req(&mut svc, Method::GET, "", "").await;
+            let open: serde_json::Value = serde_json::from_str(&open[1..]).unwrap();
+            sids.push(open["sid"].as_str().unwrap().to_string());
+        }
+        // Only the first socket connects to the namespace, the other one waits for its connect timeout
+        let params = format!("&sid={}", sids[0]);
+        assert_eq!(send_req(&mut svc, Method::POST, &params, "40").await, "ok");
+        while io.sockets().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        drop(svc);
+        drop(io);
+
+        // The heartbeat and connect timeout tasks stop, and the namespaces are dropped
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while metrics.num_alive_tasks() > 0 || Arc::strong_count(&token) > 1 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("the server tasks should stop once the service is dropped");
+    }
+}
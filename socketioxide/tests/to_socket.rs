@@ -0,0 +1,65 @@
+//! Tests for the operators targeting a single socket by its id
+mod fixture;
+mod utils;
+
+use engineioxide::sid::Sid;
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{extract::SocketRef, EmitError};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+pub async fn to_socket() {
+    const PORT: u16 = 2798;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (disconnect_tx, mut disconnect_rx) = mpsc::unbounded_channel();
+    io.ns("/", move |socket: SocketRef| {
+        let disconnect_tx = disconnect_tx.clone();
+        socket.on_disconnect(move |socket: SocketRef| disconnect_tx.send(socket.id).unwrap());
+        tx.send(socket.id).unwrap();
+    });
+
+    let mut ws1 = create_ws_connection(PORT).await;
+    assert_ok!(ws1.next().await.unwrap());
+    assert_ok!(ws1.next().await.unwrap());
+    let sid1 = rx.recv().await.unwrap();
+    let mut ws2 = create_ws_connection(PORT).await;
+    assert_ok!(ws2.next().await.unwrap());
+    assert_ok!(ws2.next().await.unwrap());
+    let sid2 = rx.recv().await.unwrap();
+
+    // Only the selected socket receives the message
+    assert_ok!(io.to_socket(sid1).emit("dm", "hello"));
+    assert_ok!(io.of("/").unwrap().to_socket(sid2).emit("dm", "world"));
+    let msg = assert_ok!(ws1.next().await.unwrap());
+    assert_eq!(msg, Message::Text("42[\"dm\",\"hello\"]".into()));
+    let msg = assert_ok!(ws2.next().await.unwrap());
+    assert_eq!(msg, Message::Text("42[\"dm\",\"world\"]".into()));
+
+    // With an acknowledgement
+    let ack = assert_ok!(io
+        .to_socket(sid2)
+        .emit_with_ack::<[String; 1]>("ping", "foo"));
+    let msg = assert_ok!(ws2.next().await.unwrap());
+    assert_eq!(msg, Message::Text("421[\"ping\",\"foo\"]".into()));
+    assert_ok!(ws2.send(Message::Text("431[\"pong\"]".into())).await);
+    let ack = assert_ok!(ack.await);
+    assert_eq!(ack.data, ["pong"]);
+
+    // Disconnected and unknown sockets
+    assert_ok!(ws1.close(None).await);
+    assert_eq!(disconnect_rx.recv().await.unwrap(), sid1);
+    let res = io.to_socket(sid1).emit("dm", "hello");
+    assert!(matches!(res, Err(EmitError::SocketNotFound(sid)) if sid == sid1));
+    let res = io.to_socket(sid1).emit_with_ack::<String>("ping", "foo");
+    assert!(matches!(res, Err(EmitError::SocketNotFound(sid)) if sid == sid1));
+    let res = io.to_socket(Sid::new()).emit("dm", "hello");
+    assert!(matches!(res, Err(EmitError::SocketNotFound(_))));
+
+    // The other socket didn't receive any of them
+    assert_ok!(io.to_socket(sid2).emit("dm", "bye"));
+    let msg = assert_ok!(ws2.next().await.unwrap());
+    assert_eq!(msg, Message::Text("42[\"dm\",\"bye\"]".into()));
+}
@@ -0,0 +1,59 @@
+//! Tests for the broadcasts filtered by transport
+mod fixture;
+mod utils;
+
+use fixture::{create_polling_connection, create_server, create_ws_connection, send_req};
+use futures::StreamExt;
+use socketioxide::{adapter::BroadcastResult, extract::SocketRef, TransportType};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+pub async fn broadcast_on_transport() {
+    const PORT: u16 = 2795;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<SocketRef>(4);
+    io.ns("/", move |socket: SocketRef| {
+        socket.join("room").unwrap();
+        tx.try_send(socket).unwrap();
+    });
+
+    let mut clients = Vec::new();
+    for _ in 0..2 {
+        let mut ws = create_ws_connection(PORT).await;
+        assert_ok!(ws.next().await.unwrap());
+        assert_ok!(ws.next().await.unwrap());
+        rx.recv().await.unwrap();
+        clients.push(ws);
+    }
+    let sid = create_polling_connection(PORT).await;
+    let params = || format!("transport=polling&sid={sid}");
+    // The first char of the payload is stripped by `send_req`
+    let connect = send_req(PORT, params(), http::Method::GET, None).await;
+    assert!(connect.starts_with("0{"));
+    let polling_socket = rx.recv().await.unwrap();
+    assert_eq!(polling_socket.transport_type(), TransportType::Polling);
+
+    let res = io
+        .to("room")
+        .on_transport(TransportType::Websocket)
+        .emit("rich", "content")
+        .unwrap();
+    assert_eq!(res, BroadcastResult::new(2, 0));
+    let res = io
+        .to("room")
+        .on_transport(TransportType::Polling)
+        .emit("light", "fallback")
+        .unwrap();
+    assert_eq!(res, BroadcastResult::new(1, 0));
+    // Without filter, every socket of the room is selected
+    assert_eq!(io.to("room").sockets().unwrap().len(), 3);
+    let res = io.on_transport(TransportType::Websocket).sockets().unwrap();
+    assert_eq!(res.len(), 2);
+
+    for ws in &mut clients {
+        let msg = assert_ok!(ws.next().await.unwrap()).into_text().unwrap();
+        assert_eq!(msg, r#"42["rich","content"]"#);
+    }
+    let msg = send_req(PORT, params(), http::Method::GET, None).await;
+    assert_eq!(msg, r#"2["light","fallback"]"#);
+}
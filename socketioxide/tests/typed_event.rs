@@ -0,0 +1,122 @@
+//! Tests for the typed events derived with `#[derive(SocketIoEvent)]`
+#![cfg(feature = "derive")]
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{
+    extract::{Data, SocketRef},
+    SocketIoEvent,
+};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, SocketIoEvent)]
+enum Event {
+    Ping,
+    #[socketio(rename = "order:created")]
+    OrderCreated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, SocketIoEvent)]
+#[socketio(rename_all = "snake_case")]
+enum SnakeEvent {
+    CreateOrder,
+    #[socketio(rename = "cancel")]
+    CancelOrder,
+}
+
+#[derive(Debug, PartialEq, SocketIoEvent)]
+#[socketio(rename_all = "kebab-case")]
+enum KebabEvent {
+    UserJoinedRoom,
+}
+
+#[derive(Debug, PartialEq, SocketIoEvent)]
+#[socketio(rename_all = "camelCase")]
+enum CamelEvent {
+    UserLeft,
+}
+
+#[test]
+fn derive_names() {
+    assert_eq!(Event::Ping.name(), "Ping");
+    assert_eq!(Event::OrderCreated.name(), "order:created");
+    assert_eq!(SnakeEvent::CreateOrder.name(), "create_order");
+    // A variant rename takes precedence over the rename rule
+    assert_eq!(SnakeEvent::CancelOrder.name(), "cancel");
+    assert_eq!(KebabEvent::UserJoinedRoom.name(), "user-joined-room");
+    assert_eq!(CamelEvent::UserLeft.name(), "userLeft");
+}
+
+#[test]
+fn derive_from_name() {
+    for event in [Event::Ping, Event::OrderCreated] {
+        assert_eq!(Event::from_name(event.name()), Some(event));
+    }
+    for event in [SnakeEvent::CreateOrder, SnakeEvent::CancelOrder] {
+        assert_eq!(SnakeEvent::from_name(event.name()), Some(event));
+    }
+    // The original variant names are not accepted once renamed
+    assert_eq!(Event::from_name("OrderCreated"), None);
+    assert_eq!(SnakeEvent::from_name("CreateOrder"), None);
+    assert_eq!(SnakeEvent::from_name("cancel_order"), None);
+    assert_eq!(Event::from_name(""), None);
+}
+
+async fn recv(rx: &mut mpsc::Receiver<String>) -> String {
+    let msg = tokio::time::timeout(Duration::from_millis(100), rx.recv()).await;
+    msg.unwrap().unwrap()
+}
+
+#[tokio::test]
+pub async fn typed_and_string_handlers() {
+    const PORT: u16 = 2828;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<String>(100);
+
+    io.ns("/", move |socket: SocketRef| {
+        let tx1 = tx.clone();
+        socket.on_any(move |event, _, _| {
+            let event = SnakeEvent::from_name(event);
+            tx1.try_send(format!("any:{event:?}")).unwrap();
+        });
+        let tx1 = tx.clone();
+        socket.on("create_order", move |Data::<String>(data)| {
+            tx1.try_send(format!("string:{data}")).unwrap();
+        });
+        // Replaces the string registration of the same event
+        let tx1 = tx.clone();
+        socket.on_typed(
+            SnakeEvent::CreateOrder,
+            move |socket: SocketRef, Data::<String>(data)| {
+                tx1.try_send(format!("typed:{data}")).unwrap();
+                socket.emit_typed(Event::OrderCreated, data).unwrap();
+            },
+        );
+    });
+
+    let (mut stx, mut srx) = create_ws_connection(PORT).await.split();
+    assert_ok!(srx.next().await.unwrap()); // engine.io open packet
+    assert_ok!(srx.next().await.unwrap()); // socket.io connect packet
+
+    stx.send(Message::Text(r#"42["create_order","foo"]"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut rx).await, "any:Some(CreateOrder)");
+    assert_eq!(recv(&mut rx).await, "typed:foo");
+    let msg = assert_ok!(srx.next().await.unwrap());
+    assert_eq!(msg, Message::Text(r#"42["order:created","foo"]"#.into()));
+
+    stx.send(Message::Text(r#"42["unknown",1]"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut rx).await, "any:None");
+
+    // The event is dispatched once
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    rx.try_recv().unwrap_err();
+}
@@ -0,0 +1,53 @@
+mod fixture;
+mod utils;
+
+use fixture::{create_polling_connection, create_server, send_req};
+use futures::{SinkExt, StreamExt};
+use socketioxide::extract::{Bin, SocketRef};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+#[cfg(feature = "polling")]
+pub async fn binary_attachments_after_upgrade() {
+    const PORT: u16 = 2500;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::channel::<Vec<Vec<u8>>>(1);
+    io.ns("/", move |socket: SocketRef| {
+        socket.on("bin", move |Bin(bin)| async move {
+            tx.try_send(bin).unwrap();
+        });
+    });
+
+    let sid = create_polling_connection(PORT).await;
+
+    // The binary event header is sent over polling
+    send_req(
+        PORT,
+        format!("transport=polling&sid={sid}"),
+        http::Method::POST,
+        Some(r#"451-["bin",{"_placeholder":true,"num":0}]"#.to_string()),
+    )
+    .await;
+
+    // The session is upgraded before the attachment is sent
+    let mut ws = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{PORT}/socket.io/?EIO=4&transport=websocket&sid={sid}"
+    ))
+    .await
+    .unwrap()
+    .0;
+    ws.send(Message::Text("2probe".into())).await.unwrap();
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert_eq!(msg, Message::Text("3probe".into()));
+    ws.send(Message::Text("5".into())).await.unwrap();
+
+    // The attachment is sent over websocket
+    ws.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+
+    let bin = tokio::time::timeout(std::time::Duration::from_millis(200), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(bin, vec![vec![1, 2, 3]]);
+}
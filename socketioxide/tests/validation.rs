@@ -0,0 +1,106 @@
+//! Tests for the validation of the received events
+mod fixture;
+mod utils;
+
+use fixture::{create_server, create_ws_connection};
+use futures::{SinkExt, StreamExt};
+use socketioxide::{
+    extract::{Data, SocketRef},
+    validation::{EventValidator, ValidationError},
+};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+pub async fn event_validator() {
+    const PORT: u16 = 2799;
+    let io = create_server(PORT).await;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    io.ns("/", move |socket: SocketRef| {
+        let tx = tx.clone();
+        socket.on("message", move |Data::<String>(msg)| {
+            tx.send(msg).unwrap();
+        });
+    });
+    let validator = EventValidator::new(|event, args| match event {
+        "message" if !args[0].is_string() => {
+            Err(ValidationError::new("message should be a string").with_details("args[0]"))
+        }
+        _ => Ok(()),
+    });
+    assert!(io.set_event_validator("/", Some(validator.clone())));
+    assert!(!io.set_event_validator("/unknown", Some(validator.clone())));
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+
+    // A valid event is dispatched
+    assert_ok!(
+        ws.send(Message::Text("42[\"message\",\"hello\"]".into()))
+            .await
+    );
+    assert_eq!(rx.recv().await.unwrap(), "hello");
+
+    // An invalid event with an ack is answered with the error
+    assert_ok!(ws.send(Message::Text("421[\"message\",1]".into())).await);
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert_eq!(
+        msg,
+        Message::Text(
+            "431[{\"details\":\"args[0]\",\"error\":\"message should be a string\"}]".into()
+        )
+    );
+
+    // An invalid event without ack is dropped
+    assert_ok!(ws.send(Message::Text("42[\"message\",2]".into())).await);
+    assert_ok!(
+        ws.send(Message::Text("42[\"message\",\"world\"]".into()))
+            .await
+    );
+    assert_eq!(rx.recv().await.unwrap(), "world");
+    assert!(rx.try_recv().is_err());
+    assert_eq!(validator.rejected(), 2);
+}
+
+#[cfg(feature = "jsonschema")]
+#[tokio::test]
+pub async fn json_schema_validator() {
+    use serde_json::json;
+    const PORT: u16 = 2800;
+    let validator = EventValidator::json_schemas([(
+        "message",
+        json!({ "type": "array", "items": [{ "type": "string" }], "minItems": 1 }),
+    )])
+    .unwrap();
+    let io = create_server(PORT).await;
+    io.ns("/", |socket: SocketRef| {
+        socket.on("message", |socket: SocketRef, Data::<String>(msg)| {
+            socket.emit("message", msg).ok();
+        });
+    });
+    io.set_event_validator("/", Some(validator.clone()));
+
+    let mut ws = create_ws_connection(PORT).await;
+    assert_ok!(ws.next().await.unwrap());
+    assert_ok!(ws.next().await.unwrap());
+
+    assert_ok!(ws.send(Message::Text("421[\"message\"]".into())).await);
+    let msg = assert_ok!(ws.next().await.unwrap()).into_text().unwrap();
+    assert_eq!(
+        msg,
+        "431[{\"details\":[\"[] has less than 1 item\"],\"error\":\"invalid arguments\"}]"
+    );
+    // Events without schema are accepted
+    assert_ok!(ws.send(Message::Text("42[\"other\",1]".into())).await);
+    assert_ok!(
+        ws.send(Message::Text("42[\"message\",\"hello\"]".into()))
+            .await
+    );
+    let msg = assert_ok!(ws.next().await.unwrap());
+    assert_eq!(msg, Message::Text("42[\"message\",\"hello\"]".into()));
+    assert_eq!(validator.rejected(), 1);
+
+    let err = EventValidator::json_schemas([("message", json!({ "type": 1 }))]).unwrap_err();
+    assert_eq!(err.0, "message");
+}